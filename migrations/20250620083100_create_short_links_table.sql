@@ -0,0 +1,17 @@
+CREATE TABLE short_links (
+   code TEXT NOT NULL,
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   destination_url TEXT NOT NULL,
+   created_at timestamptz NOT NULL,
+   expires_at timestamptz NULL,
+   PRIMARY KEY (code)
+);
+
+CREATE TABLE short_link_clicks (
+   id BIGSERIAL PRIMARY KEY,
+   code TEXT NOT NULL
+      REFERENCES short_links (code),
+   clicked_at timestamptz NOT NULL
+);
+CREATE INDEX short_link_clicks_code_idx ON short_link_clicks (code);
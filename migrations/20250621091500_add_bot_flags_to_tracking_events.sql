@@ -0,0 +1,17 @@
+ALTER TABLE short_link_clicks
+   ADD COLUMN subscriber_id uuid NULL,
+   ADD COLUMN user_agent TEXT NULL,
+   ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT false,
+   ADD COLUMN bot_reason TEXT NULL;
+
+CREATE TABLE email_opens (
+   id BIGSERIAL PRIMARY KEY,
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   subscriber_id uuid NULL,
+   user_agent TEXT NULL,
+   is_bot BOOLEAN NOT NULL DEFAULT false,
+   bot_reason TEXT NULL,
+   opened_at timestamptz NOT NULL
+);
+CREATE INDEX email_opens_newsletter_issue_id_idx ON email_opens (newsletter_issue_id);
@@ -0,0 +1,8 @@
+ALTER TABLE newsletter_issues
+   ADD COLUMN tracking_mode TEXT NOT NULL DEFAULT 'detailed',
+   ADD COLUMN aggregate_opens BIGINT NOT NULL DEFAULT 0,
+   ADD COLUMN aggregate_flagged_opens BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE short_links
+   ADD COLUMN aggregate_clicks BIGINT NOT NULL DEFAULT 0,
+   ADD COLUMN aggregate_flagged_clicks BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+CREATE TABLE newsletter_deliveries (
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   delivered_at timestamptz NOT NULL,
+   PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX newsletter_deliveries_subscriber_id_idx ON newsletter_deliveries (subscriber_id);
+CREATE INDEX email_opens_subscriber_id_idx ON email_opens (subscriber_id);
+CREATE INDEX short_link_clicks_subscriber_id_idx ON short_link_clicks (subscriber_id);
@@ -0,0 +1,54 @@
+-- Engagement over the last 90 days, per subscriber.
+-- Only issues tracked in `detailed` mode can tell us whether a given
+-- subscriber engaged, so `aggregate` issues are ignored altogether.
+CREATE VIEW subscriber_engagement AS
+WITH engagement_events AS (
+   SELECT o.subscriber_id, o.newsletter_issue_id, o.opened_at AS occurred_at
+   FROM email_opens o
+   WHERE NOT o.is_bot AND o.subscriber_id IS NOT NULL
+   UNION ALL
+   SELECT c.subscriber_id, l.newsletter_issue_id, c.clicked_at AS occurred_at
+   FROM short_link_clicks c
+   JOIN short_links l ON l.code = c.code
+   WHERE NOT c.is_bot AND c.subscriber_id IS NOT NULL
+),
+per_subscriber AS (
+   SELECT
+      s.id AS subscriber_id,
+      s.subscribed_at,
+      (
+         SELECT MAX(e.occurred_at)
+         FROM engagement_events e
+         WHERE e.subscriber_id = s.id
+      ) AS last_engaged_at,
+      (
+         SELECT COUNT(*)
+         FROM newsletter_deliveries d
+         JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+         WHERE d.subscriber_id = s.id
+            AND i.tracking_mode = 'detailed'
+            AND d.delivered_at > now() - interval '90 days'
+      ) AS deliveries_90d,
+      (
+         SELECT COUNT(DISTINCT e.newsletter_issue_id)
+         FROM engagement_events e
+         WHERE e.subscriber_id = s.id
+            AND e.occurred_at > now() - interval '90 days'
+      ) AS engaged_issues_90d
+   FROM subscriptions s
+)
+SELECT
+   subscriber_id,
+   last_engaged_at,
+   deliveries_90d,
+   engaged_issues_90d,
+   CASE
+      WHEN deliveries_90d = 0 THEN NULL
+      ELSE LEAST(100, (100 * engaged_issues_90d) / deliveries_90d)
+   END AS score,
+   (
+      subscribed_at < now() - interval '90 days'
+      AND deliveries_90d > 0
+      AND (last_engaged_at IS NULL OR last_engaged_at < now() - interval '90 days')
+   ) AS inactive_90d
+FROM per_subscriber;
@@ -0,0 +1,20 @@
+CREATE TABLE reengagement_campaigns (
+   campaign_id uuid NOT NULL,
+   started_by uuid NOT NULL
+      REFERENCES users (user_id),
+   started_at timestamptz NOT NULL,
+   grace_period_ends_at timestamptz NOT NULL,
+   completed_at timestamptz NULL,
+   PRIMARY KEY (campaign_id)
+);
+
+CREATE TABLE reengagement_recipients (
+   campaign_id uuid NOT NULL
+      REFERENCES reengagement_campaigns (campaign_id),
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   reengagement_token TEXT NOT NULL UNIQUE,
+   emailed_at timestamptz NULL,
+   responded_at timestamptz NULL,
+   PRIMARY KEY (campaign_id, subscriber_id)
+);
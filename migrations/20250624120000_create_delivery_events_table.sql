@@ -0,0 +1,41 @@
+-- Bounces and spam complaints reported by the email provider, attached to
+-- the newsletter delivery they are about.
+CREATE TABLE delivery_events (
+   delivery_event_id uuid NOT NULL,
+   PRIMARY KEY (delivery_event_id),
+   newsletter_issue_id uuid NOT NULL,
+   subscriber_id uuid NOT NULL,
+   FOREIGN KEY (newsletter_issue_id, subscriber_id)
+      REFERENCES newsletter_deliveries (newsletter_issue_id, subscriber_id)
+      ON DELETE CASCADE,
+   event_type TEXT NOT NULL,
+   provider_event_id TEXT UNIQUE,
+   occurred_at timestamptz NOT NULL,
+   received_at timestamptz NOT NULL
+);
+CREATE INDEX delivery_events_delivery_idx
+   ON delivery_events (newsletter_issue_id, subscriber_id);
+
+-- One row per delivery with everything the deliverability dashboard needs.
+CREATE VIEW delivery_outcomes AS
+SELECT
+   d.newsletter_issue_id,
+   d.subscriber_id,
+   lower(split_part(s.email, '@', 2)) AS recipient_domain,
+   d.delivered_at,
+   EXTRACT(EPOCH FROM d.delivered_at - i.published_at)::float8 AS latency_seconds,
+   EXISTS (
+      SELECT 1 FROM delivery_events e
+      WHERE e.newsletter_issue_id = d.newsletter_issue_id
+         AND e.subscriber_id = d.subscriber_id
+         AND e.event_type IN ('hard_bounce', 'soft_bounce')
+   ) AS bounced,
+   EXISTS (
+      SELECT 1 FROM delivery_events e
+      WHERE e.newsletter_issue_id = d.newsletter_issue_id
+         AND e.subscriber_id = d.subscriber_id
+         AND e.event_type = 'complaint'
+   ) AS complained
+FROM newsletter_deliveries d
+JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+JOIN subscriptions s ON s.id = d.subscriber_id;
@@ -0,0 +1,4 @@
+-- The id the email provider assigned to each message, which its webhooks
+-- refer to.
+ALTER TABLE newsletter_deliveries ADD COLUMN provider_message_id TEXT UNIQUE;
+ALTER TABLE reengagement_recipients ADD COLUMN provider_message_id TEXT UNIQUE;
@@ -0,0 +1,2 @@
+-- Data-residency region of the subscriber, NULL meaning "no requirement".
+ALTER TABLE subscriptions ADD COLUMN region TEXT;
@@ -0,0 +1,25 @@
+CREATE TABLE newsletter_drafts (
+   newsletter_draft_id uuid NOT NULL,
+   title TEXT NOT NULL,
+   text_content TEXT NOT NULL,
+   html_content TEXT NOT NULL,
+   created_at timestamptz NOT NULL,
+   newsletter_issue_id uuid NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   PRIMARY KEY (newsletter_draft_id)
+);
+
+CREATE TABLE feed_entries (
+   feed_url TEXT NOT NULL,
+   entry_id TEXT NOT NULL,
+   seen_at timestamptz NOT NULL,
+   newsletter_draft_id uuid NULL
+      REFERENCES newsletter_drafts (newsletter_draft_id),
+   PRIMARY KEY (feed_url, entry_id)
+);
+
+CREATE TABLE watched_feeds (
+   feed_url TEXT NOT NULL,
+   first_polled_at timestamptz NOT NULL,
+   PRIMARY KEY (feed_url)
+);
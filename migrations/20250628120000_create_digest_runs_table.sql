@@ -0,0 +1,8 @@
+CREATE TABLE digest_runs (
+   digest_name TEXT NOT NULL,
+   scheduled_for timestamptz NOT NULL,
+   ran_at timestamptz NOT NULL,
+   newsletter_issue_id uuid NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   PRIMARY KEY (digest_name, scheduled_for)
+);
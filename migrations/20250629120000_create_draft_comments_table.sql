@@ -0,0 +1,18 @@
+CREATE TABLE draft_comments (
+   comment_id uuid NOT NULL,
+   newsletter_draft_id uuid NOT NULL
+      REFERENCES newsletter_drafts (newsletter_draft_id) ON DELETE CASCADE,
+   author_id uuid NOT NULL
+      REFERENCES users (user_id),
+   body TEXT NOT NULL,
+   anchor_field TEXT NOT NULL,
+   anchor_start INTEGER NOT NULL,
+   anchor_end INTEGER NOT NULL,
+   created_at timestamptz NOT NULL,
+   resolved_at timestamptz NULL,
+   resolved_by uuid NULL
+      REFERENCES users (user_id),
+   PRIMARY KEY (comment_id),
+   CHECK (0 <= anchor_start AND anchor_start <= anchor_end)
+);
+CREATE INDEX draft_comments_draft_idx ON draft_comments (newsletter_draft_id);
@@ -0,0 +1,6 @@
+CREATE TABLE subscriber_tags (
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   tag TEXT NOT NULL,
+   PRIMARY KEY (subscriber_id, tag)
+);
@@ -0,0 +1,7 @@
+-- Network the subscription came from (an IPv4 /24 or an IPv6 /48), NULL
+-- when unknown.
+ALTER TABLE subscriptions ADD COLUMN signup_network TEXT;
+-- Why a subscription is held in the `quarantined` status.
+ALTER TABLE subscriptions ADD COLUMN quarantine_reason TEXT;
+CREATE INDEX subscriptions_signup_network_idx ON subscriptions (signup_network, subscribed_at);
+CREATE INDEX subscriptions_email_domain_idx ON subscriptions (split_part(email, '@', 2), subscribed_at);
@@ -0,0 +1,7 @@
+-- Confirmation emails sent per recipient, to cap how many an address can be
+-- sent regardless of who asks for them.
+CREATE TABLE confirmation_emails(
+   recipient TEXT NOT NULL,
+   sent_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX confirmation_emails_recipient_idx ON confirmation_emails (recipient, sent_at);
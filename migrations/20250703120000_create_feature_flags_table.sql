@@ -0,0 +1,7 @@
+-- Feature flags set at runtime, overriding the ones from the configuration.
+CREATE TABLE feature_flags(
+   name TEXT NOT NULL PRIMARY KEY,
+   enabled BOOLEAN NOT NULL,
+   rollout_percentage SMALLINT CHECK (rollout_percentage BETWEEN 0 AND 100),
+   updated_at timestamptz NOT NULL DEFAULT now()
+);
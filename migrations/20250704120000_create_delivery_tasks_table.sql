@@ -0,0 +1,13 @@
+-- Deliveries of an issue still to be sent, claimed by one worker at a time.
+CREATE TABLE delivery_tasks (
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   enqueued_at timestamptz NOT NULL,
+   attempts INT NOT NULL DEFAULT 0,
+   claimed_by uuid NULL,
+   lease_expires_at timestamptz NULL,
+   PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX delivery_tasks_claimed_by_idx ON delivery_tasks (claimed_by);
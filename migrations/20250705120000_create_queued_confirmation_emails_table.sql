@@ -0,0 +1,7 @@
+-- Confirmation emails held back while deliveries are paused.
+CREATE TABLE queued_confirmation_emails (
+   subscriber_id uuid NOT NULL PRIMARY KEY
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   subscription_token TEXT NOT NULL,
+   enqueued_at timestamptz NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Confirmation emails that failed to send are queued for the delivery
+-- worker to retry.
+ALTER TABLE queued_confirmation_emails
+   ADD COLUMN attempts INT NOT NULL DEFAULT 0,
+   ADD COLUMN last_error TEXT NULL,
+   ADD COLUMN next_attempt_at timestamptz NOT NULL DEFAULT now();
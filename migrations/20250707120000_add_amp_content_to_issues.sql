@@ -0,0 +1,4 @@
+-- Optional AMP for Email body of issues and drafts, sent alongside the HTML
+-- and text ones.
+ALTER TABLE newsletter_issues ADD COLUMN amp_content TEXT NULL;
+ALTER TABLE newsletter_drafts ADD COLUMN amp_content TEXT NULL;
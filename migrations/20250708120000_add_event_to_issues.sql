@@ -0,0 +1,4 @@
+-- Event announced by an issue, as JSON, turned into an .ics attachment and
+-- "Add to calendar" links.
+ALTER TABLE newsletter_issues ADD COLUMN event TEXT NULL;
+ALTER TABLE newsletter_drafts ADD COLUMN event TEXT NULL;
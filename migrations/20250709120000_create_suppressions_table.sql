@@ -0,0 +1,22 @@
+-- Addresses no newsletter nor confirmation email is sent to anymore,
+-- whatever their subscription status, e.g. after they reported one as spam.
+CREATE TABLE suppressions (
+   email TEXT NOT NULL,
+   PRIMARY KEY (email),
+   reason TEXT NOT NULL,
+   -- The issue that was reported, if any.
+   newsletter_issue_id uuid NULL
+      REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE SET NULL,
+   suppressed_at timestamptz NOT NULL
+);
+
+-- Issues whose complaint rate crossed the alert threshold, admins are only
+-- notified once per issue.
+CREATE TABLE complaint_alerts (
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+   PRIMARY KEY (newsletter_issue_id),
+   deliveries BIGINT NOT NULL,
+   complaints BIGINT NOT NULL,
+   raised_at timestamptz NOT NULL
+);
@@ -0,0 +1,29 @@
+-- DMARC aggregate reports about the sending domains, one row per report.
+CREATE TABLE dmarc_reports (
+   org_name TEXT NOT NULL,
+   report_id TEXT NOT NULL,
+   PRIMARY KEY (org_name, report_id),
+   domain TEXT NOT NULL,
+   begins_at timestamptz NOT NULL,
+   ends_at timestamptz NOT NULL,
+   messages BIGINT NOT NULL,
+   -- Messages passing DMARC, i.e. with an aligned DKIM or SPF pass.
+   passed BIGINT NOT NULL,
+   quarantined BIGINT NOT NULL,
+   rejected BIGINT NOT NULL,
+   received_at timestamptz NOT NULL
+);
+CREATE INDEX dmarc_reports_domain_idx ON dmarc_reports (domain, begins_at);
+
+-- Daily Google Postmaster Tools statistics of the sending domains.
+CREATE TABLE domain_reputation (
+   domain TEXT NOT NULL,
+   day DATE NOT NULL,
+   PRIMARY KEY (domain, day),
+   reputation TEXT NULL,
+   user_reported_spam_ratio float8 NULL,
+   spf_success_ratio float8 NULL,
+   dkim_success_ratio float8 NULL,
+   dmarc_success_ratio float8 NULL,
+   fetched_at timestamptz NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE template_fragments (
+    name TEXT PRIMARY KEY,
+    position TEXT NOT NULL CHECK (position IN ('header', 'footer')),
+    html TEXT NOT NULL,
+    text TEXT NOT NULL,
+    updated_at timestamptz NOT NULL
+);
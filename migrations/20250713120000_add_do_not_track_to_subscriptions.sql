@@ -0,0 +1,3 @@
+-- Subscribers who opted out of tracking from their subscription status page:
+-- their emails carry neither the open pixel nor short links.
+ALTER TABLE subscriptions ADD COLUMN do_not_track BOOLEAN NOT NULL DEFAULT false;
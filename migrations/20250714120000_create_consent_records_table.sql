@@ -0,0 +1,14 @@
+-- Evidence of each double opt-in, exported when a subscriber disputes having
+-- consented. Subscribers who signed up before it existed have no row.
+CREATE TABLE consent_records (
+   subscriber_id uuid NOT NULL PRIMARY KEY
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   signup_channel TEXT NULL,
+   signup_ip TEXT NULL,
+   signup_user_agent TEXT NULL,
+   confirmation_message_id TEXT NULL,
+   confirmation_sent_at timestamptz NULL,
+   confirmed_at timestamptz NULL,
+   confirmation_ip TEXT NULL,
+   confirmation_user_agent TEXT NULL
+);
@@ -0,0 +1,4 @@
+-- Tokens issued before expiry existed get the default lifetime from now on.
+-- The default also lets backups taken before this migration be restored.
+ALTER TABLE subscription_tokens
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '7 days';
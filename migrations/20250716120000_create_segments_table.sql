@@ -0,0 +1,22 @@
+CREATE TABLE segments (
+   segment_id uuid NOT NULL,
+   name TEXT NOT NULL UNIQUE,
+   -- `SubscriberFilter`, serialized as JSON.
+   filter TEXT NOT NULL,
+   created_at timestamptz NOT NULL,
+   updated_at timestamptz NOT NULL,
+   PRIMARY KEY (segment_id)
+);
+
+-- Every change made to a segment, kept once the segment is deleted.
+CREATE TABLE segment_changes (
+   id BIGSERIAL PRIMARY KEY,
+   segment_id uuid NOT NULL,
+   change TEXT NOT NULL CHECK (change IN ('created', 'updated', 'deleted')),
+   name TEXT NOT NULL,
+   filter TEXT NOT NULL,
+   changed_by uuid NULL
+      REFERENCES users (user_id) ON DELETE SET NULL,
+   changed_at timestamptz NOT NULL
+);
+CREATE INDEX segment_changes_segment_idx ON segment_changes (segment_id);
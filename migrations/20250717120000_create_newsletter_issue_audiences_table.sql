@@ -0,0 +1,20 @@
+-- Who an issue was sent to, resolved when its deliveries were queued, so
+-- that its reports do not change as subscribers are retagged or leave.
+CREATE TABLE newsletter_issue_audiences (
+   newsletter_issue_id uuid PRIMARY KEY
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   -- Saved segment the issue was sent to, kept once the segment is deleted.
+   segment_id uuid NULL,
+   filter TEXT NULL,
+   engagement TEXT NULL,
+   evaluated_at timestamptz NOT NULL
+);
+CREATE TABLE newsletter_issue_recipients (
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id),
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX newsletter_issue_recipients_subscriber_id_idx
+   ON newsletter_issue_recipients (subscriber_id);
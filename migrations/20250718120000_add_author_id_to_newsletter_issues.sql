@@ -0,0 +1,4 @@
+-- Admin who published the issue. Digests, and the issues published before
+-- authors were recorded, have none.
+ALTER TABLE newsletter_issues
+   ADD COLUMN author_id uuid NULL REFERENCES users (user_id) ON DELETE SET NULL;
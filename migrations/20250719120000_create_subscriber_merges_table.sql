@@ -0,0 +1,12 @@
+-- Duplicate subscribers merged into another record, for an admin to trace
+-- where an address went.
+CREATE TABLE subscriber_merges (
+   id BIGSERIAL PRIMARY KEY,
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   merged_subscriber_id uuid NOT NULL,
+   merged_email TEXT NOT NULL,
+   merged_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+   merged_at timestamptz NOT NULL
+);
+CREATE INDEX subscriber_merges_subscriber_id_idx ON subscriber_merges (subscriber_id);
@@ -0,0 +1,37 @@
+-- Lists of subscribers uploaded as CSV, imported in the background by the
+-- `imports` job.
+CREATE TABLE subscriber_imports (
+   import_id uuid NOT NULL,
+   PRIMARY KEY (import_id),
+   status TEXT NOT NULL,
+   total_rows INT NOT NULL,
+   processed_rows INT NOT NULL DEFAULT 0,
+   imported INT NOT NULL DEFAULT 0,
+   -- Rows whose address was already subscribed or suppressed.
+   skipped INT NOT NULL DEFAULT 0,
+   rejected INT NOT NULL DEFAULT 0,
+   created_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+   created_at timestamptz NOT NULL,
+   completed_at timestamptz NULL
+);
+
+-- Valid rows of an import waiting for the job, deleted once processed.
+-- Names are encrypted like those of subscribers.
+CREATE TABLE subscriber_import_rows (
+   import_id uuid NOT NULL
+      REFERENCES subscriber_imports (import_id) ON DELETE CASCADE,
+   line INT NOT NULL,
+   PRIMARY KEY (import_id, line),
+   email TEXT NOT NULL,
+   name TEXT NOT NULL,
+   tags TEXT[] NOT NULL
+);
+
+-- Rows of an import that could not be imported, and why.
+CREATE TABLE subscriber_import_errors (
+   import_id uuid NOT NULL
+      REFERENCES subscriber_imports (import_id) ON DELETE CASCADE,
+   line INT NOT NULL,
+   PRIMARY KEY (import_id, line),
+   error TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Why the last attempt at a delivery failed, reported to admins until a
+-- later attempt succeeds.
+ALTER TABLE delivery_tasks ADD COLUMN last_error TEXT NULL;
@@ -0,0 +1,10 @@
+-- Previews of the pages linked to by `{{card url="..."}}` shortcodes, reused
+-- for a day by the issues linking to the same page.
+CREATE TABLE link_previews (
+   url TEXT NOT NULL,
+   PRIMARY KEY (url),
+   title TEXT NULL,
+   description TEXT NULL,
+   image_url TEXT NULL,
+   fetched_at timestamptz NOT NULL
+);
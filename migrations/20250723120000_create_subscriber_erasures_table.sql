@@ -0,0 +1,9 @@
+-- Subscribers deleted at their request or an admin's, for an audit to show
+-- when the erasure happened without keeping anything about them.
+CREATE TABLE subscriber_erasures (
+   id BIGSERIAL PRIMARY KEY,
+   subscriber_id uuid NOT NULL,
+   -- NULL when the subscriber asked for it themselves.
+   erased_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+   erased_at timestamptz NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Addresses, or whole domains, newsletter issues may be sent from once
+-- someone receiving email there proved it with the token emailed to them.
+CREATE TABLE verified_senders (
+   address TEXT PRIMARY KEY,
+   verification_token TEXT NULL UNIQUE,
+   verified_at timestamptz NULL,
+   added_at timestamptz NOT NULL
+);
+
+ALTER TABLE newsletter_issues
+   ADD COLUMN sender_email TEXT NULL,
+   ADD COLUMN sender_name TEXT NULL;
+
+ALTER TABLE newsletter_drafts
+   ADD COLUMN sender_email TEXT NULL,
+   ADD COLUMN sender_name TEXT NULL;
@@ -0,0 +1,13 @@
+-- Copies of published issues for internal recipients, e.g. the marketing
+-- team or an archive mailbox, who are not subscribers. `sent_at` is set when
+-- a worker picks the copy up, copies are sent at most once.
+CREATE TABLE internal_copies (
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+   email TEXT NOT NULL,
+   sent_at timestamptz NULL,
+   PRIMARY KEY (newsletter_issue_id, email)
+);
+
+ALTER TABLE newsletter_drafts
+   ADD COLUMN internal_copies TEXT[] NOT NULL DEFAULT '{}';
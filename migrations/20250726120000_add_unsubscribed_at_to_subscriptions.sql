@@ -0,0 +1,5 @@
+-- When subscribers last unsubscribed themselves, through their link or by
+-- reporting an issue as spam, to attribute unsubscribes to the issue that
+-- prompted them. Unknown for the ones who unsubscribed before this
+-- migration.
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
@@ -0,0 +1,14 @@
+-- Keys scripts publish newsletter issues with, on behalf of the admin who
+-- generated them. Only their hash is kept: the key is shown once.
+CREATE TABLE api_keys (
+   api_key_id uuid PRIMARY KEY,
+   user_id uuid NOT NULL
+      REFERENCES users (user_id) ON DELETE CASCADE,
+   name TEXT NOT NULL,
+   -- The first characters of the key, to tell keys apart.
+   prefix TEXT NOT NULL,
+   key_hash TEXT NOT NULL UNIQUE,
+   created_at timestamptz NOT NULL,
+   last_used_at timestamptz NULL,
+   revoked_at timestamptz NULL
+);
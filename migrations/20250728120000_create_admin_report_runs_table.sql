@@ -0,0 +1,8 @@
+-- Runs of the stats summaries emailed to admins, so that each scheduled
+-- run is only sent by one instance, once.
+CREATE TABLE admin_report_runs (
+   report_name TEXT NOT NULL,
+   scheduled_for timestamptz NOT NULL,
+   ran_at timestamptz NOT NULL,
+   PRIMARY KEY (report_name, scheduled_for)
+);
@@ -0,0 +1,10 @@
+-- Last notification of each alert rule, so that instances checking the same
+-- rule notify operators once per cool-down.
+CREATE TABLE alert_notifications (
+   rule TEXT NOT NULL,
+   PRIMARY KEY (rule),
+   notified_at timestamptz NOT NULL
+);
+
+-- When the last attempt at a delivery failed, for the failure rate alert.
+ALTER TABLE delivery_tasks ADD COLUMN failed_at timestamptz NULL;
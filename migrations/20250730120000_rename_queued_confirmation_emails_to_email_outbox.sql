@@ -0,0 +1,8 @@
+-- Confirmation emails are written to the outbox in the transaction storing
+-- the subscription, and relayed by the delivery worker unless sent straight
+-- away, so that a crash after the commit does not lose them.
+ALTER TABLE queued_confirmation_emails RENAME TO email_outbox;
+-- Instances of the previous release keep queueing through the old name
+-- while both run side by side. The view is dropped by a later release,
+-- once none of them is left.
+CREATE VIEW queued_confirmation_emails AS SELECT * FROM email_outbox;
@@ -0,0 +1,3 @@
+-- Drafts published by the draft scheduler once due, e.g. scheduled from
+-- Slack.
+ALTER TABLE newsletter_drafts ADD COLUMN scheduled_at timestamptz NULL;
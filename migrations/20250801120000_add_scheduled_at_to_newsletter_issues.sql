@@ -0,0 +1,9 @@
+-- Issues published with a `scheduled_at` are stored straight away, with it
+-- as their `published_at`, and queued for delivery by the issue scheduler
+-- once it is reached. Until then they have no audience, and the segment they
+-- are to be sent to is kept alongside.
+ALTER TABLE newsletter_issues
+   ADD COLUMN scheduled_at timestamptz NULL,
+   ADD COLUMN scheduled_segment_id uuid NULL,
+   ADD COLUMN scheduled_filter TEXT NULL,
+   ADD COLUMN scheduled_engagement TEXT NULL;
@@ -0,0 +1,5 @@
+-- Issues can be sent to the subscribers having any of a list of tags, on top
+-- of a saved segment and of their engagement.
+ALTER TABLE newsletter_issue_audiences
+   ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_tags TEXT[] NULL;
@@ -0,0 +1,18 @@
+-- Invite codes of invitation-only newsletters, generated in batches. Codes
+-- are random, only their hash is kept: they are shown once.
+CREATE TABLE invitation_batches (
+   batch_id uuid PRIMARY KEY,
+   -- How many subscriptions each code of the batch is good for.
+   max_uses INTEGER NOT NULL CHECK (max_uses > 0),
+   created_by uuid NULL
+      REFERENCES users (user_id) ON DELETE SET NULL,
+   created_at timestamptz NOT NULL
+);
+
+CREATE TABLE invitations (
+   code_hash TEXT PRIMARY KEY,
+   batch_id uuid NOT NULL
+      REFERENCES invitation_batches (batch_id) ON DELETE CASCADE,
+   uses INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX invitations_batch_id_idx ON invitations (batch_id);
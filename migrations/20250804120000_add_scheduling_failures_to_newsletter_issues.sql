@@ -0,0 +1,12 @@
+-- A scheduled issue that cannot be queued, e.g. its filter no longer parses,
+-- is set aside with the reason rather than retried on every poll, and no
+-- longer holds up the issues due after it.
+ALTER TABLE newsletter_issues ADD COLUMN schedule_error TEXT NULL;
+-- The segment is only kept for reference, its filter being copied in
+-- `scheduled_filter`: deleting it leaves the audience of the issue as is.
+UPDATE newsletter_issues i SET scheduled_segment_id = NULL
+WHERE scheduled_segment_id IS NOT NULL
+    AND NOT EXISTS (SELECT 1 FROM segments s WHERE s.segment_id = i.scheduled_segment_id);
+ALTER TABLE newsletter_issues
+   ADD CONSTRAINT newsletter_issues_scheduled_segment_id_fkey
+   FOREIGN KEY (scheduled_segment_id) REFERENCES segments (segment_id) ON DELETE SET NULL;
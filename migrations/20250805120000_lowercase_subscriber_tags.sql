@@ -0,0 +1,15 @@
+-- Tags are now lowercased when parsed. Merge the tags differing only by
+-- their case, then lowercase the rest.
+DELETE FROM subscriber_tags t
+WHERE t.tag <> lower(t.tag)
+    AND EXISTS (
+        SELECT 1 FROM subscriber_tags u
+        WHERE u.subscriber_id = t.subscriber_id
+            AND lower(u.tag) = lower(t.tag)
+            AND (u.tag = lower(u.tag) OR u.tag < t.tag)
+    );
+UPDATE subscriber_tags SET tag = lower(tag) WHERE tag <> lower(tag);
+UPDATE subscriber_import_rows SET tags = (
+    SELECT COALESCE(array_agg(DISTINCT lower(tag)), '{}') FROM UNNEST(tags) AS tag
+)
+WHERE EXISTS (SELECT 1 FROM UNNEST(tags) AS tag WHERE tag <> lower(tag));
@@ -0,0 +1,4 @@
+-- Uploaded files list the addresses and names of subscribers: they are
+-- deleted some time after their import completes, their progress and
+-- rejected rows being kept.
+ALTER TABLE subscriber_imports ADD COLUMN file_deleted_at timestamptz NULL;
@@ -0,0 +1,5 @@
+-- A scheduled draft failing to publish is retried later and later, then
+-- unscheduled with the reason once it failed too many times.
+ALTER TABLE newsletter_drafts
+   ADD COLUMN publish_attempts INT NOT NULL DEFAULT 0,
+   ADD COLUMN publish_error TEXT NULL;
@@ -0,0 +1,2 @@
+-- Bumped on every password change, ending the sessions started before it.
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
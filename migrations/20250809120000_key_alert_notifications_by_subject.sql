@@ -0,0 +1,4 @@
+-- Rules of one kind with different parameters notify independently.
+ALTER TABLE alert_notifications ADD COLUMN subject TEXT NOT NULL DEFAULT '';
+ALTER TABLE alert_notifications DROP CONSTRAINT alert_notifications_pkey;
+ALTER TABLE alert_notifications ADD PRIMARY KEY (rule, subject);
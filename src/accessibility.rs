@@ -0,0 +1,293 @@
+/// Contrast ratio WCAG asks of body text.
+const MIN_CONTRAST_RATIO: f64 = 4.5;
+
+/// Give an HTML email the basics screen readers rely on: a language and an
+/// `article` landmark around the content.
+///
+/// Fragments are wrapped in a `<div>` carrying both, full documents get a
+/// `lang` on their `<html>` tag unless they already have one. Content made
+/// accessible before is returned as is.
+pub fn make_accessible(html: &str, lang: &str) -> String {
+    if let Some(start) = find_tag(html, "html") {
+        let end = start + html[start..].find('>').unwrap_or(html.len() - start);
+        if attribute(&html[start..end], "lang").is_some() {
+            return html.to_owned();
+        }
+        let name_end = start + "<html".len();
+        return format!(
+            r#"{} lang="{}"{}"#,
+            &html[..name_end],
+            lang,
+            &html[name_end..]
+        );
+    }
+    if html.starts_with(ARTICLE_PREFIX) {
+        return html.to_owned();
+    }
+    format!(
+        r#"{} lang="{}" aria-roledescription="email">{}</div>"#,
+        ARTICLE_PREFIX, lang, html
+    )
+}
+
+const ARTICLE_PREFIX: &str = r#"<div role="article""#;
+
+/// The content of an email wrapped by [`make_accessible`], to append more of
+/// it before wrapping it again.
+pub fn strip_article(html: &str) -> &str {
+    html.strip_prefix(ARTICLE_PREFIX)
+        .and_then(|rest| rest.split_once('>'))
+        .and_then(|(_, content)| content.strip_suffix("</div>"))
+        .unwrap_or(html)
+}
+
+/// An accessibility issue found before sending an email.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AccessibilityWarning {
+    /// Headings jump levels, e.g. from `<h1>` to `<h3>`, which breaks the
+    /// outline screen readers navigate by.
+    SkippedHeadingLevel { from: u8, to: u8 },
+    /// An image is announced by its file name.
+    MissingAltText { src: String },
+    /// Inline colors too close to be read by subscribers with low vision.
+    LowContrast {
+        color: String,
+        background: String,
+        ratio: f64,
+    },
+}
+
+impl std::fmt::Display for AccessibilityWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessibilityWarning::SkippedHeadingLevel { from, to } => {
+                write!(f, "heading levels skip from h{} to h{}", from, to)
+            }
+            AccessibilityWarning::MissingAltText { src } => {
+                write!(f, "image {} has no alt text", src)
+            }
+            AccessibilityWarning::LowContrast {
+                color,
+                background,
+                ratio,
+            } => write!(
+                f,
+                "{} on {} has a contrast ratio of {:.2}, below {}",
+                color, background, ratio, MIN_CONTRAST_RATIO
+            ),
+        }
+    }
+}
+
+/// Look for the accessibility issues that can be told from the markup alone.
+pub fn check_accessibility(html: &str) -> Vec<AccessibilityWarning> {
+    let mut warnings = Vec::new();
+    let mut last_heading = None;
+    for tag in tags(html) {
+        let name = tag_name(tag);
+        if let Some(level) = heading_level(&name) {
+            if let Some(last) = last_heading
+                && level > last + 1
+            {
+                warnings.push(AccessibilityWarning::SkippedHeadingLevel {
+                    from: last,
+                    to: level,
+                });
+            }
+            last_heading = Some(level);
+        }
+        if name == "img" && attribute(tag, "alt").is_none() {
+            warnings.push(AccessibilityWarning::MissingAltText {
+                src: attribute(tag, "src").unwrap_or_default().to_owned(),
+            });
+        }
+        if let Some(style) = attribute(tag, "style")
+            && let Some(color) = style_property(style, "color")
+        {
+            let background = style_property(style, "background-color")
+                .or_else(|| style_property(style, "background"))
+                .unwrap_or("#ffffff");
+            if let (Some(fg), Some(bg)) = (parse_color(color), parse_color(background)) {
+                let ratio = contrast_ratio(fg, bg);
+                if ratio < MIN_CONTRAST_RATIO {
+                    warnings.push(AccessibilityWarning::LowContrast {
+                        color: color.to_owned(),
+                        background: background.to_owned(),
+                        ratio: (ratio * 100.0).round() / 100.0,
+                    });
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// Opening tags of `html`, without their angle brackets.
+fn tags(html: &str) -> impl Iterator<Item = &str> {
+    html.split('<')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('>').map(|(tag, _)| tag))
+        .filter(|tag| !tag.starts_with('/') && !tag.starts_with('!'))
+}
+
+/// The lowercase name of `tag`, given without its angle brackets.
+pub fn tag_name(tag: &str) -> String {
+    tag.split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn find_tag(html: &str, name: &str) -> Option<usize> {
+    html.match_indices('<')
+        .map(|(i, _)| i)
+        .find(|&i| tag_name(&html[i + 1..]) == name)
+}
+
+fn heading_level(name: &str) -> Option<u8> {
+    match name.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some(level - b'0'),
+        _ => None,
+    }
+}
+
+/// The value of a quoted attribute of `tag`.
+pub fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lowercase = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(i) = lowercase[from..].find(name) {
+        let start = from + i;
+        from = start + name.len();
+        let preceded_by_space = lowercase[..start].ends_with(char::is_whitespace);
+        let rest = lowercase[from..].trim_start();
+        if !preceded_by_space || !rest.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return value.split(char::is_whitespace).next();
+        }
+        return value[1..].split(quote).next();
+    }
+    None
+}
+
+fn style_property<'a>(style: &'a str, name: &str) -> Option<&'a str> {
+    style.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        property
+            .trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim())
+    })
+}
+
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    match value.to_ascii_lowercase().as_str() {
+        "black" => return Some([0, 0, 0]),
+        "white" => return Some([255, 255, 255]),
+        _ => {}
+    }
+    let hex = value.strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let mut rgb = [0; 3];
+            for (i, c) in hex.chars().enumerate() {
+                rgb[i] = channel(&c.to_string())? * 17;
+            }
+            Some(rgb)
+        }
+        6 => Some([
+            channel(hex.get(0..2)?)?,
+            channel(hex.get(2..4)?)?,
+            channel(hex.get(4..6)?)?,
+        ]),
+        _ => None,
+    }
+}
+
+/// The WCAG contrast ratio, from 1 (same colors) to 21 (black on white).
+fn contrast_ratio(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let luminance = |rgb: [u8; 3]| {
+        let [r, g, b] = rgb.map(|c| {
+            let c = f64::from(c) / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    };
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessibilityWarning, check_accessibility, make_accessible, strip_article};
+
+    #[test]
+    fn fragments_are_wrapped_in_an_article() {
+        assert_eq!(
+            make_accessible("<p>Hello</p>", "en"),
+            r#"<div role="article" lang="en" aria-roledescription="email"><p>Hello</p></div>"#
+        );
+    }
+
+    #[test]
+    fn accessible_content_is_left_as_is() {
+        let accessible = make_accessible("<p>Hello</p>", "en");
+        assert_eq!(make_accessible(&accessible, "fr"), accessible);
+        assert_eq!(strip_article(&accessible), "<p>Hello</p>");
+    }
+
+    #[test]
+    fn documents_get_a_language() {
+        assert_eq!(
+            make_accessible("<!DOCTYPE html><html><body>Hi</body></html>", "fr"),
+            r#"<!DOCTYPE html><html lang="fr"><body>Hi</body></html>"#
+        );
+        let with_lang = r#"<html lang="de"><body>Hallo</body></html>"#;
+        assert_eq!(make_accessible(with_lang, "fr"), with_lang);
+    }
+
+    #[test]
+    fn skipped_heading_levels_are_reported() {
+        assert_eq!(
+            check_accessibility("<h1>Title</h1><h3>Section</h3><h2>Other</h2>"),
+            [AccessibilityWarning::SkippedHeadingLevel { from: 1, to: 3 }]
+        );
+        assert!(check_accessibility("<h1>Title</h1><h2>Section</h2><h1>Next</h1>").is_empty());
+    }
+
+    #[test]
+    fn images_without_alt_text_are_reported() {
+        assert_eq!(
+            check_accessibility(r#"<img src="a.png"><img src="b.png" alt="">"#),
+            [AccessibilityWarning::MissingAltText {
+                src: "a.png".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn low_contrast_colors_are_reported() {
+        assert_eq!(
+            check_accessibility(r#"<p style="color: #aaa">Faint</p>"#),
+            [AccessibilityWarning::LowContrast {
+                color: "#aaa".into(),
+                background: "#ffffff".into(),
+                ratio: 2.32,
+            }]
+        );
+        assert!(
+            check_accessibility(r#"<p style="color: #fff; background-color: #000">Bold</p>"#)
+                .is_empty()
+        );
+    }
+}
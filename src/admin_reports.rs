@@ -0,0 +1,474 @@
+use crate::EmailClient;
+use crate::configuration::{AdminReportSettings, EmailTemplate, Settings};
+use crate::jobs::Job;
+use crate::leader_election::LeaderElection;
+use crate::publishing::escape_html;
+use crate::templates::EmailTemplates;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Cron expressions cannot be more precise than this.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How many issues a report highlights.
+const TOP_ISSUES: i64 = 5;
+
+/// Emails the configured stats summaries to admins when their schedule is
+/// due, e.g. weekly or monthly.
+///
+/// A report covers the period since its previous run. The first time a
+/// report is seen it is only registered, the first email going out at the
+/// next scheduled time.
+pub struct AdminReportScheduler {
+    reports: Vec<AdminReportSettings>,
+    templates: EmailTemplates,
+    pg_pool: PgPool,
+    email_client: Arc<EmailClient>,
+    leader_election: Arc<LeaderElection>,
+    job: Arc<Job>,
+}
+
+/// A scheduled report that was due and emailed.
+#[derive(Debug)]
+pub struct AdminReportRun {
+    pub name: String,
+    pub scheduled_for: DateTime<Utc>,
+}
+
+/// What happened over the period of a report.
+#[derive(Debug)]
+pub struct ReportStats {
+    pub new_subscribers: i64,
+    pub unsubscribes: i64,
+    /// Confirmed subscribers at the end of the period.
+    pub confirmed_subscribers: i64,
+    /// The issues published over the period most opened, best first.
+    pub top_issues: Vec<TopIssue>,
+    pub deliveries: i64,
+    pub bounces: i64,
+    pub complaints: i64,
+}
+
+#[derive(Debug)]
+pub struct TopIssue {
+    pub title: String,
+    pub deliveries: i64,
+    pub unique_opens: i64,
+}
+
+/// A report rendered from the `admin_report` template.
+pub struct RenderedReport {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl AdminReportScheduler {
+    /// `None` when no report is configured.
+    pub fn build(
+        configuration: &Settings,
+        pg_pool: PgPool,
+        email_client: Arc<EmailClient>,
+    ) -> Option<Self> {
+        if configuration.admin_reports.is_empty() {
+            return None;
+        }
+        Some(Self {
+            reports: configuration.admin_reports.clone(),
+            templates: EmailTemplates::new(configuration.email_templates.clone()),
+            leader_election: Arc::new(LeaderElection::new("admin_reports", pg_pool.clone())),
+            pg_pool,
+            email_client,
+            job: Job::new("admin_reports"),
+        })
+    }
+
+    /// Only the leader among the running instances sends the reports.
+    pub fn leader_election(&self) -> Arc<LeaderElection> {
+        self.leader_election.clone()
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(
+                CHECK_INTERVAL,
+                Some(&self.leader_election),
+                &shutdown,
+                || async { self.run_due_reports(Utc::now()).await.map(|_| ()) },
+            )
+            .await
+    }
+
+    /// Email every report whose schedule fired by `now`.
+    ///
+    /// Missed runs are not caught up one by one, the latest one covers them.
+    /// While deliveries are paused, runs are left due for after the pause.
+    #[tracing::instrument(name = "Run due admin reports", skip(self))]
+    pub async fn run_due_reports(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<AdminReportRun>, anyhow::Error> {
+        let mut runs = Vec::new();
+        if self
+            .email_client
+            .is_paused()
+            .await
+            .context("Failed to check whether deliveries are paused")?
+        {
+            return Ok(runs);
+        }
+        for report in &self.reports {
+            let Some(last_run) = get_last_run(&self.pg_pool, &report.name)
+                .await
+                .context("Failed to retrieve the last admin report run")?
+            else {
+                register_report(&self.pg_pool, &report.name, now)
+                    .await
+                    .context("Failed to register an admin report")?;
+                continue;
+            };
+            let Some(scheduled_for) = report
+                .schedule
+                .after(&last_run)
+                .take_while(|t| *t <= now)
+                .last()
+            else {
+                continue;
+            };
+            if let Some(run) = self.run_report(report, last_run, scheduled_for).await? {
+                runs.push(run);
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Returns `None` if another instance claimed the run first.
+    ///
+    /// Reports go through the transactional path, one email per recipient.
+    /// Failing to email one of them is logged, the report is not sent again.
+    #[tracing::instrument(
+        name = "Run admin report",
+        skip(self, report),
+        fields(report_name = %report.name)
+    )]
+    async fn run_report(
+        &self,
+        report: &AdminReportSettings,
+        since: DateTime<Utc>,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<Option<AdminReportRun>, anyhow::Error> {
+        let mut transaction = self
+            .pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        if !claim_run(&mut transaction, &report.name, scheduled_for)
+            .await
+            .context("Failed to claim an admin report run")?
+        {
+            return Ok(None);
+        }
+        let stats = get_report_stats(&mut transaction, since, scheduled_for)
+            .await
+            .context("Failed to compute the stats of an admin report")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to claim an admin report run")?;
+
+        let rendered = render_admin_report(
+            &self.templates.current().await.admin_report,
+            &report.name,
+            since,
+            scheduled_for,
+            &stats,
+        );
+        for recipient in &report.recipients {
+            if let Err(e) = self
+                .email_client
+                .send_email(recipient, &rendered.subject, &rendered.html, &rendered.text)
+                .await
+            {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    recipient = %recipient,
+                    "Failed to email an admin report",
+                );
+            }
+        }
+        Ok(Some(AdminReportRun {
+            name: report.name.clone(),
+            scheduled_for,
+        }))
+    }
+}
+
+/// Fill the `admin_report` template with the stats of the period from
+/// `since` to `until`.
+pub fn render_admin_report(
+    template: &EmailTemplate,
+    name: &str,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    stats: &ReportStats,
+) -> RenderedReport {
+    let rate = |count: i64| {
+        if stats.deliveries == 0 {
+            "0.0%".to_owned()
+        } else {
+            format!("{:.1}%", count as f64 * 100.0 / stats.deliveries as f64)
+        }
+    };
+    let period = format!(
+        "{} to {}",
+        since.format("%Y-%m-%d"),
+        until.format("%Y-%m-%d")
+    );
+    let new_subscribers = stats.new_subscribers.to_string();
+    let unsubscribes = stats.unsubscribes.to_string();
+    let confirmed_subscribers = stats.confirmed_subscribers.to_string();
+    let deliveries = stats.deliveries.to_string();
+    let bounce_rate = rate(stats.bounces);
+    let complaint_rate = rate(stats.complaints);
+
+    let (mut top_html, mut top_text) = (String::new(), String::new());
+    if stats.top_issues.is_empty() {
+        top_html.push_str("<p>No issue was published.</p>");
+        top_text.push_str("No issue was published.");
+    } else {
+        top_html.push_str("<ol>");
+        for issue in &stats.top_issues {
+            let opens = format!(
+                "{} opened by {} of {} recipients",
+                issue.title, issue.unique_opens, issue.deliveries
+            );
+            top_html.push_str(&format!("<li>{}</li>", escape_html(&opens)));
+            top_text.push_str(&format!("- {}\n", opens));
+        }
+        top_html.push_str("</ol>");
+    }
+
+    let escaped_name = escape_html(name);
+    let variables = |name: &'_ str, top_issues: &'_ str| -> Vec<(&'static str, String)> {
+        vec![
+            ("name", name.to_owned()),
+            ("period", period.clone()),
+            ("new_subscribers", new_subscribers.clone()),
+            ("unsubscribes", unsubscribes.clone()),
+            ("confirmed_subscribers", confirmed_subscribers.clone()),
+            ("top_issues", top_issues.to_owned()),
+            ("deliveries", deliveries.clone()),
+            ("bounce_rate", bounce_rate.clone()),
+            ("complaint_rate", complaint_rate.clone()),
+        ]
+    };
+    let html_variables = variables(&escaped_name, &top_html);
+    let text_variables = variables(name, top_text.trim_end());
+    RenderedReport {
+        subject: template.render_subject(&borrow(&text_variables)),
+        html: template.render_html(&borrow(&html_variables)),
+        text: template.render_text(&borrow(&text_variables)),
+    }
+}
+
+fn borrow<'a>(variables: &'a [(&'static str, String)]) -> Vec<(&'static str, &'a str)> {
+    variables.iter().map(|(k, v)| (*k, v.as_str())).collect()
+}
+
+#[tracing::instrument(name = "Get last admin report run", skip(pg_pool))]
+async fn get_last_run(
+    pg_pool: &PgPool,
+    report_name: &str,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let last_run = sqlx::query!(
+        r#"SELECT max(scheduled_for) AS last_run FROM admin_report_runs WHERE report_name = $1"#,
+        report_name,
+    )
+    .fetch_one(pg_pool)
+    .await?
+    .last_run;
+    Ok(last_run)
+}
+
+/// Record an empty run, so that the first report covers what happens from
+/// now on.
+#[tracing::instrument(name = "Register admin report", skip(pg_pool))]
+async fn register_report(
+    pg_pool: &PgPool,
+    report_name: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_report_runs (report_name, scheduled_for, ran_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        report_name,
+        now,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+async fn claim_run(
+    transaction: &mut PgConnection,
+    report_name: &str,
+    scheduled_for: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO admin_report_runs (report_name, scheduled_for, ran_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        report_name,
+        scheduled_for,
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+/// Growth, top issues and deliverability from `since` to `until`.
+async fn get_report_stats(
+    transaction: &mut PgConnection,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<ReportStats, sqlx::Error> {
+    let growth = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (
+                WHERE subscribed_at > $1 AND subscribed_at <= $2
+            ) AS "new_subscribers!",
+            COUNT(*) FILTER (
+                WHERE unsubscribed_at > $1 AND unsubscribed_at <= $2
+            ) AS "unsubscribes!",
+            COUNT(*) FILTER (WHERE status = 'confirmed') AS "confirmed_subscribers!"
+        FROM subscriptions
+        "#,
+        since,
+        until,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let deliverability = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "deliveries!",
+            COUNT(*) FILTER (WHERE bounced) AS "bounces!",
+            COUNT(*) FILTER (WHERE complained) AS "complaints!"
+        FROM delivery_outcomes
+        WHERE delivered_at > $1 AND delivered_at <= $2
+        "#,
+        since,
+        until,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let top_issues = sqlx::query_as!(
+        TopIssue,
+        r#"
+        SELECT
+            i.title,
+            (SELECT COUNT(*) FROM newsletter_deliveries d
+             WHERE d.newsletter_issue_id = i.newsletter_issue_id) AS "deliveries!",
+            (SELECT COUNT(DISTINCT o.subscriber_id) FROM email_opens o
+             WHERE o.newsletter_issue_id = i.newsletter_issue_id
+                AND NOT o.is_bot) AS "unique_opens!"
+        FROM newsletter_issues i
+        WHERE i.published_at > $1 AND i.published_at <= $2
+        ORDER BY 3 DESC, i.published_at DESC
+        LIMIT $3
+        "#,
+        since,
+        until,
+        TOP_ISSUES,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    Ok(ReportStats {
+        new_subscribers: growth.new_subscribers,
+        unsubscribes: growth.unsubscribes,
+        confirmed_subscribers: growth.confirmed_subscribers,
+        top_issues,
+        deliveries: deliverability.deliveries,
+        bounces: deliverability.bounces,
+        complaints: deliverability.complaints,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReportStats, TopIssue, render_admin_report};
+    use crate::get_configuration;
+    use chrono::{TimeZone, Utc};
+
+    fn stats() -> ReportStats {
+        ReportStats {
+            new_subscribers: 12,
+            unsubscribes: 2,
+            confirmed_subscribers: 340,
+            top_issues: vec![TopIssue {
+                title: "Tips & tricks".into(),
+                deliveries: 300,
+                unique_opens: 150,
+            }],
+            deliveries: 400,
+            bounces: 4,
+            complaints: 0,
+        }
+    }
+
+    #[test]
+    fn reports_fill_every_placeholder() {
+        let template = get_configuration().unwrap().email_templates.admin_report;
+        let since = Utc.with_ymd_and_hms(2025, 7, 1, 8, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2025, 8, 1, 8, 0, 0).unwrap();
+
+        let report = render_admin_report(&template, "Monthly report", since, until, &stats());
+
+        assert_eq!(report.subject, "Monthly report: 2025-07-01 to 2025-08-01");
+        for content in [&report.html, &report.text] {
+            assert!(!content.contains('{'), "{}", content);
+            assert!(content.contains("340"));
+            assert!(content.contains("1.0%"));
+        }
+        assert!(
+            report
+                .html
+                .contains("Tips &amp; tricks opened by 150 of 300 recipients")
+        );
+        assert!(
+            report
+                .text
+                .contains("- Tips & tricks opened by 150 of 300 recipients")
+        );
+    }
+
+    #[test]
+    fn reports_without_deliveries_have_no_rates() {
+        let template = get_configuration().unwrap().email_templates.admin_report;
+        let stats = ReportStats {
+            top_issues: Vec::new(),
+            deliveries: 0,
+            ..stats()
+        };
+
+        let report =
+            render_admin_report(&template, "Weekly report", Utc::now(), Utc::now(), &stats);
+
+        assert!(report.text.contains("0.0% bounced"));
+        assert!(report.text.contains("No issue was published."));
+    }
+}
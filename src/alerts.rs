@@ -0,0 +1,298 @@
+use crate::EmailClient;
+use crate::configuration::{AlertRule, AlertSettings, Settings};
+use crate::jobs::Job;
+use crate::publishing::escape_html;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Notification sent to the operators when a rule holds.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct OperationalAlert {
+    /// See [`AlertRule::name`].
+    pub rule: String,
+    /// See [`AlertRule::subject`].
+    pub subject: String,
+    pub message: String,
+    /// What the rule measured, compared against `threshold`.
+    pub value: f64,
+    pub threshold: f64,
+    pub raised_at: DateTime<Utc>,
+}
+
+/// Checks the alert rules periodically, so that operators hear about
+/// failing deliveries before subscribers do.
+///
+/// Every instance runs one, the provider being judged by what each instance
+/// sees. Alerts are deduplicated in the database: a rule notifies once per
+/// cool-down, however many instances see it hold. A notification failing on
+/// every channel is not counted, the next check tries again.
+pub struct OperationalAlerts {
+    settings: AlertSettings,
+    pg_pool: PgPool,
+    email_client: Arc<EmailClient>,
+    http_client: reqwest::Client,
+    job: Arc<Job>,
+}
+
+impl OperationalAlerts {
+    /// `None` when alerts are not configured.
+    pub fn build(
+        configuration: &Settings,
+        pg_pool: PgPool,
+        email_client: Arc<EmailClient>,
+    ) -> Option<Self> {
+        let settings = configuration.alerts.clone()?;
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.webhook_timeout)
+            .build()
+            .expect("Failed to build the alert webhook HTTP client");
+        Some(Self {
+            settings,
+            pg_pool,
+            email_client,
+            http_client,
+            job: Job::new("alerts"),
+        })
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(self.settings.check_interval, None, &shutdown, || async {
+                self.check(Utc::now()).await.map(|_| ())
+            })
+            .await
+    }
+
+    /// Evaluate every rule, returning the alerts raised.
+    ///
+    /// Failing to notify a channel is logged rather than returned, the other
+    /// channels still being notified.
+    #[tracing::instrument(name = "Check alert rules", skip(self))]
+    pub async fn check(&self, now: DateTime<Utc>) -> Result<Vec<OperationalAlert>, anyhow::Error> {
+        let mut alerts = Vec::new();
+        for rule in &self.settings.rules {
+            let Some(alert) = self.evaluate(rule, now).await? else {
+                continue;
+            };
+            // The claim is only committed once notified, other instances
+            // waiting on it in the meantime.
+            let mut transaction = self
+                .pg_pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?;
+            if !claim_notification(
+                &mut transaction,
+                &alert.rule,
+                &alert.subject,
+                now,
+                self.settings.cool_down,
+            )
+            .await
+            .context("Failed to claim an alert notification")?
+            {
+                continue;
+            }
+            tracing::warn!(rule = rule.name(), alert = %alert.message, "Raising an operational alert");
+            if !self.notify(&alert).await {
+                tracing::error!(
+                    rule = rule.name(),
+                    "Every alert channel failed, the alert will be raised again"
+                );
+                continue;
+            }
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to claim an alert notification")?;
+            alerts.push(alert);
+        }
+        Ok(alerts)
+    }
+
+    /// The alert `rule` raises at `now`, if it holds.
+    async fn evaluate(
+        &self,
+        rule: &AlertRule,
+        now: DateTime<Utc>,
+    ) -> Result<Option<OperationalAlert>, anyhow::Error> {
+        let (value, threshold, message) = match rule {
+            AlertRule::DeliveryFailureRate {
+                above,
+                min_attempts,
+                window,
+            } => {
+                let since = now - chrono::Duration::from_std(*window)?;
+                let (failed, delivered) = get_delivery_attempts(&self.pg_pool, since)
+                    .await
+                    .context("Failed to count the recent delivery attempts")?;
+                let attempts = failed + delivered;
+                if attempts == 0 || attempts < i64::from(*min_attempts) {
+                    return Ok(None);
+                }
+                let rate = failed as f64 / attempts as f64;
+                if rate <= *above {
+                    return Ok(None);
+                }
+                let message = format!(
+                    "{:.1}% of the deliveries attempted over the last {} minutes failed ({} of {})",
+                    rate * 100.0,
+                    window.as_secs() / 60,
+                    failed,
+                    attempts
+                );
+                (rate, *above, message)
+            }
+            AlertRule::QueueDepth { above } => {
+                let depth = get_queue_depth(&self.pg_pool)
+                    .await
+                    .context("Failed to measure the delivery queue")?;
+                if depth <= i64::from(*above) {
+                    return Ok(None);
+                }
+                let message = format!("{} deliveries are waiting to be sent", depth);
+                (depth as f64, f64::from(*above), message)
+            }
+            AlertRule::ProviderUnavailable {
+                consecutive_failures,
+            } => {
+                let failures = self
+                    .email_client
+                    .endpoint_stats()
+                    .into_iter()
+                    .filter(|e| e.routes.iter().any(|r| r == "default"))
+                    .map(|e| e.consecutive_failures)
+                    .min()
+                    .unwrap_or(0);
+                if failures < u64::from(*consecutive_failures) {
+                    return Ok(None);
+                }
+                let message = format!(
+                    "The email provider failed the last {} sends in a row",
+                    failures
+                );
+                (failures as f64, f64::from(*consecutive_failures), message)
+            }
+        };
+        Ok(Some(OperationalAlert {
+            rule: rule.name().into(),
+            subject: rule.subject(),
+            message,
+            value,
+            threshold,
+            raised_at: now,
+        }))
+    }
+
+    /// Whether any channel was notified, having no channel counting as
+    /// notified.
+    async fn notify(&self, alert: &OperationalAlert) -> bool {
+        let mut attempted = 0;
+        let mut failed = 0;
+        let subject = format!("[Alert] {}", alert.message);
+        let html = format!("<p>{}</p>", escape_html(&alert.message));
+        for recipient in &self.settings.email_recipients {
+            attempted += 1;
+            if let Err(e) = self
+                .email_client
+                .send_email(recipient, &subject, &html, &alert.message)
+                .await
+            {
+                failed += 1;
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    recipient = %recipient,
+                    "Failed to email an operational alert",
+                );
+            }
+        }
+        if let Some(url) = &self.settings.webhook_url {
+            attempted += 1;
+            if let Err(e) = self.post(url, alert).await {
+                failed += 1;
+                tracing::error!(error.cause_chain = ?e, "Failed to notify the alert webhook");
+            }
+        }
+        if let Some(url) = &self.settings.slack_webhook_url {
+            attempted += 1;
+            let message =
+                serde_json::json!({ "text": format!(":rotating_light: {}", alert.message) });
+            if let Err(e) = self.post(url, &message).await {
+                failed += 1;
+                tracing::error!(error.cause_chain = ?e, "Failed to notify Slack of an alert");
+            }
+        }
+        attempted == 0 || failed < attempted
+    }
+
+    async fn post(&self, url: &str, body: &impl serde::Serialize) -> Result<(), reqwest::Error> {
+        self.http_client
+            .post(url)
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Record that `rule` notified about `subject` at `now`, unless it already
+/// did within the cool-down.
+async fn claim_notification(
+    transaction: &mut PgConnection,
+    rule: &str,
+    subject: &str,
+    now: DateTime<Utc>,
+    cool_down: std::time::Duration,
+) -> Result<bool, sqlx::Error> {
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO alert_notifications (rule, subject, notified_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (rule, subject) DO UPDATE SET notified_at = EXCLUDED.notified_at
+        WHERE alert_notifications.notified_at <= $3 - make_interval(secs => $4)
+        "#,
+        rule,
+        subject,
+        now,
+        cool_down.as_secs_f64(),
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(claimed == 1)
+}
+
+/// Deliveries whose last attempt failed since `since`, and deliveries sent
+/// since then.
+async fn get_delivery_attempts(
+    pg_pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<(i64, i64), sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM delivery_tasks WHERE failed_at > $1) AS "failed!",
+            (SELECT COUNT(*) FROM newsletter_deliveries WHERE delivered_at > $1) AS "delivered!"
+        "#,
+        since,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    Ok((r.failed, r.delivered))
+}
+
+async fn get_queue_depth(pg_pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let depth = sqlx::query!(r#"SELECT COUNT(*) AS "depth!" FROM delivery_tasks"#)
+        .fetch_one(pg_pool)
+        .await?
+        .depth;
+    Ok(depth)
+}
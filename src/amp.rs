@@ -0,0 +1,151 @@
+/// Mailbox providers drop AMP bodies larger than this.
+const MAX_AMP_SIZE: usize = 200 * 1024;
+const AMP_RUNTIME: &str = r#"<script async src="https://cdn.ampproject.org/v0.js"></script>"#;
+const AMP_BOILERPLATE: &str = "<style amp4email-boilerplate>body{visibility:hidden}</style>";
+const AMP_COMPONENTS: &str = "https://cdn.ampproject.org/";
+
+/// Structural requirements of an AMP for Email document a provider would
+/// otherwise reject, or a mailbox silently ignore.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum AmpValidationError {
+    #[error("The AMP body must start with <!doctype html>.")]
+    MissingDoctype,
+    #[error("The AMP body must have an <html ⚡4email> or <html amp4email> tag.")]
+    MissingAmpAttribute,
+    #[error("The AMP body must have a <head> and a <body>.")]
+    MissingHeadOrBody,
+    #[error("The AMP body must declare <meta charset=\"utf-8\">.")]
+    MissingCharset,
+    #[error("The AMP body must load the AMP runtime: {AMP_RUNTIME}")]
+    MissingRuntime,
+    #[error("The AMP body must include the boilerplate: {AMP_BOILERPLATE}")]
+    MissingBoilerplate,
+    #[error("The AMP body can only load scripts from {AMP_COMPONENTS}.")]
+    ForbiddenScript,
+    #[error("The AMP body is {0} bytes long, above the limit of {MAX_AMP_SIZE}.")]
+    TooLarge(usize),
+}
+
+/// Check that `amp` is a well-formed AMP for Email document.
+///
+/// This is not the full AMP validator, only the structure that makes
+/// providers accept the `text/x-amp-html` part.
+pub fn validate_amp_email(amp: &str) -> Result<(), AmpValidationError> {
+    if amp.len() > MAX_AMP_SIZE {
+        return Err(AmpValidationError::TooLarge(amp.len()));
+    }
+    let lowercase = amp.to_lowercase();
+    if !lowercase.trim_start().starts_with("<!doctype html>") {
+        return Err(AmpValidationError::MissingDoctype);
+    }
+    let html_tag = lowercase
+        .find("<html")
+        .and_then(|start| lowercase[start..].split_once('>'))
+        .map(|(tag, _)| tag)
+        .ok_or(AmpValidationError::MissingAmpAttribute)?;
+    if !html_tag
+        .split_whitespace()
+        .any(|attribute| attribute == "⚡4email" || attribute == "amp4email")
+    {
+        return Err(AmpValidationError::MissingAmpAttribute);
+    }
+    if !lowercase.contains("<head>") || !lowercase.contains("<body") {
+        return Err(AmpValidationError::MissingHeadOrBody);
+    }
+    if !lowercase.contains(r#"<meta charset="utf-8">"#) {
+        return Err(AmpValidationError::MissingCharset);
+    }
+    if !amp.contains(AMP_RUNTIME) {
+        return Err(AmpValidationError::MissingRuntime);
+    }
+    if !amp.contains(AMP_BOILERPLATE) {
+        return Err(AmpValidationError::MissingBoilerplate);
+    }
+    let loads_other_scripts = lowercase
+        .split("<script")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('>').map(|(attributes, _)| attributes))
+        .any(|attributes| !attributes.contains(&format!(r#"src="{}"#, AMP_COMPONENTS)));
+    if loads_other_scripts {
+        return Err(AmpValidationError::ForbiddenScript);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AmpValidationError, validate_amp_email};
+    use claims::assert_ok;
+
+    fn amp_email(body: &str) -> String {
+        format!(
+            r#"<!doctype html>
+<html ⚡4email data-css-strict>
+<head>
+<meta charset="utf-8">
+<script async src="https://cdn.ampproject.org/v0.js"></script>
+<script async custom-element="amp-form" src="https://cdn.ampproject.org/v0/amp-form-0.1.js"></script>
+<style amp4email-boilerplate>body{{visibility:hidden}}</style>
+</head>
+<body>{}</body>
+</html>"#,
+            body
+        )
+    }
+
+    #[test]
+    fn well_formed_amp_emails_are_accepted() {
+        assert_ok!(validate_amp_email(&amp_email("<p>Hello</p>")));
+        assert_ok!(validate_amp_email(
+            &amp_email("<p>Hello</p>").replace("⚡4email", "amp4email")
+        ));
+    }
+
+    #[test]
+    fn regular_html_is_rejected() {
+        assert_eq!(
+            validate_amp_email("<p>Hello</p>"),
+            Err(AmpValidationError::MissingDoctype)
+        );
+        assert_eq!(
+            validate_amp_email(&amp_email("").replace("⚡4email", "")),
+            Err(AmpValidationError::MissingAmpAttribute)
+        );
+    }
+
+    #[test]
+    fn the_runtime_and_boilerplate_are_required() {
+        let runtime = r#"<script async src="https://cdn.ampproject.org/v0.js"></script>"#;
+        assert_eq!(
+            validate_amp_email(&amp_email("").replace(runtime, "")),
+            Err(AmpValidationError::MissingRuntime)
+        );
+        assert_eq!(
+            validate_amp_email(&amp_email("").replace("amp4email-boilerplate", "")),
+            Err(AmpValidationError::MissingBoilerplate)
+        );
+    }
+
+    #[test]
+    fn other_scripts_are_rejected() {
+        assert_eq!(
+            validate_amp_email(&amp_email("<script>alert(1)</script>")),
+            Err(AmpValidationError::ForbiddenScript)
+        );
+        assert_eq!(
+            validate_amp_email(&amp_email(
+                r#"<script src="https://evil.example.com/x.js"></script>"#
+            )),
+            Err(AmpValidationError::ForbiddenScript)
+        );
+    }
+
+    #[test]
+    fn large_amp_emails_are_rejected() {
+        let body = "a".repeat(200 * 1024);
+        assert!(matches!(
+            validate_amp_email(&amp_email(&body)),
+            Err(AmpValidationError::TooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+//! Keys scripts publish newsletter issues with, sent in an `X-Api-Key`
+//! header.
+//!
+//! A key acts on behalf of the admin who generated it. Keys are random, so
+//! a SHA-256 hash is enough to store them: the key itself is only shown
+//! once, when it is generated.
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{Rng, thread_rng};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Marks API keys, e.g. for secret scanners.
+const KEY_PREFIX: &str = "zp_";
+const KEY_RANDOM_LENGTH: usize = 40;
+/// How much of a key is kept in clear to tell keys apart.
+const DISPLAYED_PREFIX_LENGTH: usize = KEY_PREFIX.len() + 6;
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(serde::Serialize, Debug)]
+pub struct ApiKey {
+    pub api_key_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    /// The admin the key acts on behalf of.
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Fail unless `name` can tell a key apart, e.g. `CI pipeline`.
+pub fn validate_key_name(name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("API keys must be given a name.".into());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "API key names are at most {} characters long.",
+            MAX_NAME_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn generate_key() -> SecretString {
+    let random: String = std::iter::repeat_with(|| thread_rng().sample(Alphanumeric))
+        .map(char::from)
+        .take(KEY_RANDOM_LENGTH)
+        .collect();
+    SecretString::from(format!("{}{}", KEY_PREFIX, random))
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Generate a key acting on behalf of `user_id`, returned along with its
+/// description: it cannot be retrieved later.
+#[tracing::instrument(name = "Create an API key", skip(pg_pool))]
+pub async fn create_api_key(
+    pg_pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+) -> Result<(SecretString, ApiKey), sqlx::Error> {
+    let key = generate_key();
+    let api_key = sqlx::query_as!(
+        ApiKey,
+        r#"
+        WITH created AS (
+            INSERT INTO api_keys (api_key_id, user_id, name, prefix, key_hash, created_at)
+            VALUES ($1, $2, $3, $4, $5, now())
+            RETURNING *
+        )
+        SELECT
+            k.api_key_id AS "api_key_id!",
+            k.name AS "name!",
+            k.prefix AS "prefix!",
+            u.username,
+            k.created_at AS "created_at!",
+            k.last_used_at,
+            k.revoked_at
+        FROM created k
+        JOIN users u ON u.user_id = k.user_id
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name.trim(),
+        &key.expose_secret()[..DISPLAYED_PREFIX_LENGTH],
+        hash_key(key.expose_secret()),
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    Ok((key, api_key))
+}
+
+/// Every key, the revoked ones included, newest first.
+pub async fn list_api_keys(pg_pool: &PgPool) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT
+            k.api_key_id, k.name, k.prefix, u.username,
+            k.created_at, k.last_used_at, k.revoked_at
+        FROM api_keys k
+        JOIN users u ON u.user_id = k.user_id
+        ORDER BY k.created_at DESC
+        "#
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+/// Stop accepting a key. Return whether there is such a key.
+#[tracing::instrument(name = "Revoke an API key", skip(pg_pool))]
+pub async fn revoke_api_key(pg_pool: &PgPool, api_key_id: Uuid) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = COALESCE(revoked_at, now())
+        WHERE api_key_id = $1
+        "#,
+        api_key_id,
+    )
+    .execute(pg_pool)
+    .await?
+    .rows_affected();
+    Ok(updated > 0)
+}
+
+/// The admin `key` acts on behalf of, `None` if it is unknown or revoked.
+#[tracing::instrument(name = "Look up an API key", skip(pg_pool, key))]
+pub async fn find_api_key_owner(
+    pg_pool: &PgPool,
+    key: &SecretString,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE api_keys
+        SET last_used_at = now()
+        WHERE key_hash = $1 AND revoked_at IS NULL
+        RETURNING user_id
+        "#,
+        hash_key(key.expose_secret()),
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KEY_PREFIX, generate_key, hash_key, validate_key_name};
+    use claims::{assert_err, assert_ok};
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn keys_are_prefixed_and_unique() {
+        let (first, second) = (generate_key(), generate_key());
+
+        assert!(first.expose_secret().starts_with(KEY_PREFIX));
+        assert_ne!(first.expose_secret(), second.expose_secret());
+        assert_ne!(
+            hash_key(first.expose_secret()),
+            hash_key(second.expose_secret())
+        );
+    }
+
+    #[test]
+    fn keys_need_a_reasonable_name() {
+        assert_ok!(validate_key_name("CI pipeline"));
+        assert_err!(validate_key_name("  "));
+        assert_err!(validate_key_name(&"a".repeat(101)));
+    }
+}
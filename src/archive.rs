@@ -0,0 +1,319 @@
+//! Static copy of the published issues, to be hosted on a CDN so that the
+//! archive stays up when the application is not.
+use crate::blob_store::BlobStore;
+use crate::branding::{Branding, PageMetadata};
+use crate::configuration::{FooterTemplate, Settings};
+use crate::encryption::FieldCipher;
+use crate::link_shortener::{get_link_destinations, restore_links};
+use crate::publishing::escape_html;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Remembers the hash of every page written, to rewrite only the ones that
+/// changed since the last export.
+const MANIFEST_FILE: &str = "manifest.json";
+/// Where the archive is exported to in the storage, unless a directory is
+/// given.
+pub const ARCHIVE_PREFIX: &str = "archive";
+const INDEX_FILE: &str = "index.html";
+const SITEMAP_FILE: &str = "sitemap.xml";
+const ISSUES_DIRECTORY: &str = "issues";
+/// Length of the descriptions shown when a link to an issue unfurls, in
+/// characters.
+const DESCRIPTION_LENGTH: usize = 200;
+
+/// Keys of the files written by an export, and how many pages were left as
+/// they were.
+#[derive(Debug)]
+pub struct ArchiveExport {
+    pub written: Vec<String>,
+    pub unchanged: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct Manifest {
+    /// SHA-256 of each page, by path relative to the archive directory.
+    pages: BTreeMap<String, String>,
+}
+
+struct ArchivedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+/// Renders the archive with the look of the hosted pages.
+///
+/// Short links are replaced by their destination and footer placeholders
+/// filled without a recipient, leaving no link that needs the application
+/// besides the ones to manage a subscription. Once the archive is given a
+/// base URL, a sitemap lists its pages for search engines.
+pub struct Archive {
+    branding: Branding,
+    product_name: String,
+    footer: FooterTemplate,
+    base_url: String,
+    link_base_url: String,
+    archive_base_url: Option<String>,
+}
+
+impl Archive {
+    pub fn new(configuration: &Settings) -> Self {
+        Self {
+            branding: Branding::new(configuration.branding.clone()),
+            product_name: configuration.branding.product_name.clone(),
+            footer: configuration.email_templates.footer.clone(),
+            base_url: configuration.application.base_url.clone(),
+            link_base_url: configuration.application.link_base_url().to_owned(),
+            archive_base_url: configuration
+                .archive
+                .base_url
+                .as_deref()
+                .map(|base_url| base_url.trim_end_matches('/').to_owned()),
+        }
+    }
+
+    /// Write a page for every published issue and an index of them into
+    /// `store`, under `prefix` unless it is empty.
+    #[tracing::instrument(name = "Export the archive", skip(self, pg_pool, cipher, store))]
+    pub async fn export(
+        &self,
+        pg_pool: &PgPool,
+        cipher: &FieldCipher,
+        store: &dyn BlobStore,
+        prefix: &str,
+    ) -> Result<ArchiveExport, anyhow::Error> {
+        let key = |relative_path: &str| match prefix.trim_end_matches('/') {
+            "" => relative_path.to_owned(),
+            prefix => format!("{}/{}", prefix, relative_path),
+        };
+        let manifest_key = key(MANIFEST_FILE);
+        let mut manifest: Manifest = match store
+            .get(&manifest_key)
+            .await
+            .context("Failed to read the archive manifest")?
+        {
+            Some(manifest) => {
+                serde_json::from_slice(&manifest).context("Failed to parse the archive manifest")?
+            }
+            None => Manifest::default(),
+        };
+
+        let issues = get_archived_issues(pg_pool, cipher)
+            .await
+            .context("Failed to retrieve the published issues")?;
+        let mut pages = Vec::with_capacity(issues.len() + 1);
+        for issue in &issues {
+            let link_destinations = get_link_destinations(pg_pool, issue.newsletter_issue_id)
+                .await
+                .context("Failed to retrieve the short links of an issue")?;
+            let content =
+                restore_links(&self.link_base_url, &issue.html_content, &link_destinations);
+            let description = summarize(&restore_links(
+                &self.link_base_url,
+                &issue.text_content,
+                &link_destinations,
+            ));
+            pages.push((
+                issue_path(issue.newsletter_issue_id),
+                self.issue_page(issue, &content, &description),
+            ));
+        }
+        pages.push((INDEX_FILE.to_owned(), self.index_page(&issues)));
+        match &self.archive_base_url {
+            Some(archive_base_url) => {
+                pages.push((SITEMAP_FILE.to_owned(), sitemap(archive_base_url, &issues)))
+            }
+            None => tracing::warn!("The archive has no base URL, leaving out the sitemap"),
+        }
+
+        let mut export = ArchiveExport {
+            written: Vec::new(),
+            unchanged: 0,
+        };
+        for (relative_path, page) in pages {
+            let hash = hex::encode(Sha256::digest(page.as_bytes()));
+            let key = key(&relative_path);
+            if manifest.pages.get(&relative_path) == Some(&hash) && store.exists(&key).await? {
+                export.unchanged += 1;
+                continue;
+            }
+            store.put(&key, page.into_bytes()).await?;
+            manifest.pages.insert(relative_path, hash);
+            export.written.push(key);
+        }
+        store
+            .put(&manifest_key, serde_json::to_vec_pretty(&manifest)?)
+            .await
+            .context("Failed to write the archive manifest")?;
+        Ok(export)
+    }
+
+    fn issue_page(&self, issue: &ArchivedIssue, content: &str, description: &str) -> String {
+        let manage_link = format!(
+            "{}/subscriptions/login",
+            self.base_url.trim_end_matches('/')
+        );
+        self.branding.public_page(
+            &issue.title,
+            &format!(
+                r#"<p><time datetime="{}">{}</time></p>
+{}
+<p><a href="../{}">All issues</a></p>"#,
+                issue.published_at.to_rfc3339(),
+                issue.published_at.format("%B %-d, %Y"),
+                self.footer.render_without_recipient(content, &manage_link),
+                INDEX_FILE,
+            ),
+            &PageMetadata {
+                description,
+                url: self
+                    .page_url(&issue_path(issue.newsletter_issue_id))
+                    .as_deref(),
+                published_at: Some(issue.published_at),
+            },
+        )
+    }
+
+    fn index_page(&self, issues: &[ArchivedIssue]) -> String {
+        let entries: String = issues
+            .iter()
+            .map(|issue| {
+                format!(
+                    r#"<li><a href="{}">{}</a> <time datetime="{}">{}</time></li>
+"#,
+                    issue_path(issue.newsletter_issue_id),
+                    escape_html(&issue.title),
+                    issue.published_at.to_rfc3339(),
+                    issue.published_at.format("%B %-d, %Y"),
+                )
+            })
+            .collect();
+        self.branding.public_page(
+            "Archive",
+            &format!("<ul>\n{}</ul>", entries),
+            &PageMetadata {
+                description: &format!("Every issue of {}, newest first.", self.product_name),
+                url: self.page_url(INDEX_FILE).as_deref(),
+                published_at: None,
+            },
+        )
+    }
+
+    fn page_url(&self, relative_path: &str) -> Option<String> {
+        self.archive_base_url
+            .as_ref()
+            .map(|base_url| format!("{}/{}", base_url, relative_path))
+    }
+}
+
+fn issue_path(newsletter_issue_id: Uuid) -> String {
+    format!("{}/{}.html", ISSUES_DIRECTORY, newsletter_issue_id)
+}
+
+/// The first words of `text`, on a single line.
+fn summarize(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= DESCRIPTION_LENGTH {
+        return text;
+    }
+    let cut: String = text.chars().take(DESCRIPTION_LENGTH).collect();
+    let cut = match cut.rfind(' ') {
+        Some(end) => &cut[..end],
+        None => &cut,
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// The index and every issue, see <https://www.sitemaps.org/protocol.html>.
+fn sitemap(archive_base_url: &str, issues: &[ArchivedIssue]) -> String {
+    let url = |relative_path: &str, last_modified: Option<DateTime<Utc>>| {
+        let last_modified = last_modified
+            .map(|at| format!("<lastmod>{}</lastmod>", at.format("%Y-%m-%d")))
+            .unwrap_or_default();
+        format!(
+            "<url><loc>{}/{}</loc>{}</url>\n",
+            escape_html(archive_base_url),
+            relative_path,
+            last_modified
+        )
+    };
+    let mut sitemap = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#,
+    );
+    sitemap.push_str(&url(
+        INDEX_FILE,
+        issues.first().map(|issue| issue.published_at),
+    ));
+    for issue in issues {
+        sitemap.push_str(&url(
+            &issue_path(issue.newsletter_issue_id),
+            Some(issue.published_at),
+        ));
+    }
+    sitemap.push_str("</urlset>\n");
+    sitemap
+}
+
+/// Every published issue, newest first, leaving out those scheduled for
+/// later.
+#[tracing::instrument(name = "Get the issues to archive", skip_all)]
+async fn get_archived_issues(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, published_at
+        FROM newsletter_issues
+        WHERE published_at <= now()
+        ORDER BY published_at DESC, newsletter_issue_id
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(ArchivedIssue {
+                newsletter_issue_id: r.newsletter_issue_id,
+                title: r.title,
+                text_content: cipher.decrypt(r.text_content)?,
+                html_content: cipher.decrypt(r.html_content)?,
+                published_at: r.published_at,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DESCRIPTION_LENGTH, summarize};
+
+    #[test]
+    fn short_texts_are_kept_on_a_single_line() {
+        assert_eq!(summarize("Hello,\n\n  world!"), "Hello, world!");
+    }
+
+    #[test]
+    fn long_texts_are_cut_between_words() {
+        let text = "Lorem ipsum, dolor sit amet. ".repeat(20);
+
+        let summary = summarize(&text);
+
+        assert!(summary.chars().count() <= DESCRIPTION_LENGTH + 1);
+        assert!(summary.ends_with("…"));
+        assert!(text.starts_with(summary.trim_end_matches('…')));
+        assert!(!summary.contains(",…") && !summary.contains(".…"));
+    }
+}
@@ -0,0 +1,432 @@
+use crate::api_keys::find_api_key_owner;
+use crate::domain::Password;
+use crate::routes::error_chain_fmt;
+use crate::session_state::TypedSession;
+use crate::telemetry::{record_in_request_spans, spawn_blocking_with_tracing};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderValue;
+use actix_web::http::{StatusCode, header};
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError, web};
+use anyhow::Context;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use std::future::{Ready, ready};
+
+#[derive(thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AuthError::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            AuthError::InvalidCredentials(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_static(r#"Basic realm="publish""#);
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+        }
+    }
+}
+
+/// Username and password extracted from a `Basic` `Authorization` header.
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+impl FromRequest for Credentials {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(basic_authentication(req).map_err(AuthError::InvalidCredentials))
+    }
+}
+
+fn basic_authentication(req: &HttpRequest) -> Result<Credentials, anyhow::Error> {
+    let auth_str = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string")?;
+    let base64encoded_segment = auth_str
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'")?;
+    let decoded_bytes = BASE64_STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8")?;
+
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .context("A username must be provided in 'Basic' auth")?
+        .to_string();
+    let password = credentials
+        .next()
+        .context("A password must be provided in 'Basic' auth")?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: SecretString::from(password),
+    })
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, pg_pool))]
+async fn get_stored_credentials(
+    username: &str,
+    pg_pool: &PgPool,
+) -> Result<Option<(uuid::Uuid, SecretString)>, anyhow::Error> {
+    let row: Option<_> = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1
+        "#,
+        username,
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to perform a query to validate auth credentials")?
+    .map(|r| (r.user_id, SecretString::from(r.password_hash)));
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pg_pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pg_pool: &PgPool,
+) -> Result<uuid::Uuid, AuthError> {
+    let mut user_id = None;
+    let mut expected_password_hash = SecretString::from(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+    );
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pg_pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task.")??;
+
+    let user_id = user_id
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username.")))?;
+    record_in_request_spans("user_id", &tracing::field::display(&user_id));
+    Ok(user_id)
+}
+
+/// Header carrying the key of a script acting on behalf of an admin.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Whatever an admin proves who they are with: an API key, `Basic`
+/// credentials or the session they started by logging in, looked for in
+/// this order.
+pub enum AdminCredentials {
+    ApiKey(SecretString),
+    Basic(Credentials),
+    Session(TypedSession),
+}
+
+impl FromRequest for AdminCredentials {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if let Some(key) = req.headers().get(API_KEY_HEADER) {
+            return ready(
+                key.to_str()
+                    .map(|key| AdminCredentials::ApiKey(SecretString::from(key)))
+                    .context("The API key was not a valid UTF8 string")
+                    .map_err(AuthError::InvalidCredentials),
+            );
+        }
+        if let Ok(credentials) = basic_authentication(req) {
+            return ready(Ok(AdminCredentials::Basic(credentials)));
+        }
+        ready(
+            TypedSession::from_request(req, payload)
+                .into_inner()
+                .map(AdminCredentials::Session)
+                .map_err(|e| anyhow::anyhow!("Failed to read the session: {}", e).into()),
+        )
+    }
+}
+
+/// Identify the admin behind `credentials`.
+pub async fn authenticate(
+    credentials: AdminCredentials,
+    pg_pool: &PgPool,
+) -> Result<uuid::Uuid, AuthError> {
+    let user_id = match credentials {
+        AdminCredentials::ApiKey(key) => find_api_key_owner(pg_pool, &key)
+            .await
+            .context("Failed to look up an API key")?
+            .ok_or_else(|| {
+                AuthError::InvalidCredentials(anyhow::anyhow!("Unknown or revoked API key"))
+            }),
+        AdminCredentials::Basic(credentials) => validate_credentials(credentials, pg_pool).await,
+        AdminCredentials::Session(session) => {
+            session_user(session, pg_pool).await?.ok_or_else(|| {
+                AuthError::InvalidCredentials(anyhow::anyhow!(
+                    "Neither credentials nor a session were provided"
+                ))
+            })
+        }
+    }?;
+    record_in_request_spans("user_id", &tracing::field::display(&user_id));
+    Ok(user_id)
+}
+
+/// Tie `session` to `user_id`, until they log out or change their password.
+pub async fn start_session(
+    session: &TypedSession,
+    user_id: uuid::Uuid,
+    pg_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let session_version = sqlx::query_scalar!(
+        "SELECT session_version FROM users WHERE user_id = $1",
+        user_id,
+    )
+    .fetch_one(pg_pool)
+    .await
+    .context("Failed to retrieve the session version of an admin")?;
+    session.renew();
+    session
+        .insert_user(user_id, session_version)
+        .context("Failed to store the user id in the session")
+}
+
+/// The admin `session` was started for, or `None` if it was not, or their
+/// password changed since, in which case the session is ended.
+pub async fn session_user(
+    session: TypedSession,
+    pg_pool: &PgPool,
+) -> Result<Option<uuid::Uuid>, anyhow::Error> {
+    let Some((user_id, session_version)) =
+        session.get_user().context("Failed to read the session")?
+    else {
+        return Ok(None);
+    };
+    let current_version = sqlx::query_scalar!(
+        "SELECT session_version FROM users WHERE user_id = $1",
+        user_id,
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to retrieve the session version of an admin")?;
+    if current_version != Some(session_version) {
+        session.log_out();
+        return Ok(None);
+    }
+    Ok(Some(user_id))
+}
+
+/// The admin logged in, made available to the handlers behind
+/// [`reject_anonymous_users`] as `web::ReqData<UserId>`.
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(uuid::Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::ops::Deref for UserId {
+    type Target = uuid::Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Send visitors without a session to the login form.
+///
+/// Paths no route matches are let through to a 404, rather than offering
+/// the login form for any of them.
+pub async fn reject_anonymous_users(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if !request.resource_map().has_resource(request.path()) {
+        return Ok(next.call(request).await?.map_into_left_body());
+    }
+    let session = {
+        let (http_request, payload) = request.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let pg_pool = request
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("No database pool"))?;
+    let user_id = session_user(session, &pg_pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match user_id {
+        Some(user_id) => {
+            record_in_request_spans("user_id", &tracing::field::display(&user_id));
+            request.extensions_mut().insert(UserId(user_id));
+            Ok(next.call(request).await?.map_into_left_body())
+        }
+        None => {
+            let response = HttpResponse::SeeOther()
+                .insert_header((header::LOCATION, "/login"))
+                .finish();
+            Ok(request.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get username", skip(pg_pool))]
+pub async fn get_username(user_id: uuid::Uuid, pg_pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pg_pool)
+    .await
+    .context("Failed to perform a query to retrieve a username")?;
+    Ok(row.username)
+}
+
+/// Replace the password of an admin, hashed with a fresh salt, and end
+/// their sessions. Returns the session version sessions must carry from now
+/// on.
+#[tracing::instrument(name = "Change password", skip(password, pg_pool))]
+pub async fn change_password(
+    user_id: uuid::Uuid,
+    password: Password,
+    pg_pool: &PgPool,
+) -> Result<i32, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+    sqlx::query_scalar!(
+        r#"
+        UPDATE users
+        SET password_hash = $1, session_version = session_version + 1
+        WHERE user_id = $2
+        RETURNING session_version
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+    )
+    .fetch_one(pg_pool)
+    .await
+    .context("Failed to change user's password in the database")
+}
+
+#[derive(thiserror::Error)]
+pub enum CreateUserError {
+    #[error("The username is already taken.")]
+    UsernameTaken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for CreateUserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Add an admin able to log in with `username` and `password`.
+#[tracing::instrument(name = "Create user", skip(password, pg_pool))]
+pub async fn create_user(
+    username: &str,
+    password: Password,
+    pg_pool: &PgPool,
+) -> Result<uuid::Uuid, CreateUserError> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")?
+        .context("Failed to hash password")?;
+    let user_id = uuid::Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+    )
+    .execute(pg_pool)
+    .await
+    .context("Failed to store the new user in the database")?
+    .rows_affected();
+    if inserted == 0 {
+        return Err(CreateUserError::UsernameTaken);
+    }
+    Ok(user_id)
+}
+
+fn compute_password_hash(password: Password) -> Result<SecretString, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).map_err(|e| anyhow::anyhow!(e))?,
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)
+    .map_err(|e| anyhow::anyhow!(e))?
+    .to_string();
+    Ok(SecretString::from(password_hash))
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+    expected_password_hash: SecretString,
+    password_candidate: SecretString,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password.")
+        .map_err(AuthError::InvalidCredentials)
+}
@@ -0,0 +1,248 @@
+use crate::blob_store::BlobStore;
+use crate::encryption::FieldCipher;
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use sqlx::{Connection, PgConnection};
+use std::path::PathBuf;
+
+/// First line of every backup, before encryption.
+const HEADER: &str = "zero2prod-backup v1";
+
+/// Every table of the schema, in an order respecting their foreign keys.
+/// A test checks the list against the migrated database.
+pub const TABLES: &[&str] = &[
+    "users",
+    "api_keys",
+    "idempotency",
+    "segments",
+    "segment_changes",
+    "subscriptions",
+    "subscription_tokens",
+    "subscriber_tags",
+    "consent_records",
+    "confirmation_emails",
+    "email_outbox",
+    "subscriber_merges",
+    "subscriber_erasures",
+    "newsletter_issues",
+    "newsletter_issue_audiences",
+    "newsletter_issue_recipients",
+    "newsletter_drafts",
+    "draft_comments",
+    "watched_feeds",
+    "feed_entries",
+    "newsletter_deliveries",
+    "delivery_events",
+    "delivery_tasks",
+    "digest_runs",
+    "email_opens",
+    "internal_copies",
+    "complaint_alerts",
+    "suppressions",
+    "short_links",
+    "short_link_clicks",
+    "link_previews",
+    "template_fragments",
+    "invitation_batches",
+    "invitations",
+    "subscriber_imports",
+    "subscriber_import_rows",
+    "subscriber_import_errors",
+    "reengagement_campaigns",
+    "reengagement_recipients",
+    "verified_senders",
+    "domain_reputation",
+    "dmarc_reports",
+    "feature_flags",
+    "alert_notifications",
+    "admin_report_runs",
+];
+
+/// Where a backup is written to or read from: a local file, an HTTP(S) URL
+/// such as a presigned S3 URL, uploaded with `PUT` and downloaded with
+/// `GET`, or a key of the storage given as `store:<key>`.
+#[derive(Debug)]
+pub enum BackupTarget {
+    File(PathBuf),
+    Url(reqwest::Url),
+    Stored(String),
+}
+
+impl BackupTarget {
+    pub fn parse(target: &str) -> Result<Self, url::ParseError> {
+        if target.starts_with("http://") || target.starts_with("https://") {
+            Ok(Self::Url(reqwest::Url::parse(target)?))
+        } else if let Some(key) = target.strip_prefix("store:") {
+            Ok(Self::Stored(key.to_owned()))
+        } else {
+            Ok(Self::File(PathBuf::from(target)))
+        }
+    }
+
+    pub async fn write(&self, store: &dyn BlobStore, backup: String) -> Result<(), anyhow::Error> {
+        match self {
+            Self::File(path) => tokio::fs::write(path, backup)
+                .await
+                .with_context(|| format!("Failed to write the backup to {}", path.display())),
+            Self::Url(url) => {
+                reqwest::Client::new()
+                    .put(url.clone())
+                    .body(backup)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .context("Failed to upload the backup")?;
+                Ok(())
+            }
+            Self::Stored(key) => store
+                .put(key, backup.into_bytes())
+                .await
+                .context("Failed to store the backup"),
+        }
+    }
+
+    pub async fn read(&self, store: &dyn BlobStore) -> Result<String, anyhow::Error> {
+        match self {
+            Self::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Failed to read the backup from {}", path.display())),
+            Self::Url(url) => reqwest::get(url.clone())
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .context("Failed to download the backup")?
+                .text()
+                .await
+                .context("Failed to download the backup"),
+            Self::Stored(key) => {
+                let backup = store
+                    .get(key)
+                    .await
+                    .context("Failed to retrieve the backup from the storage")?
+                    .with_context(|| format!("There is no backup stored as {}", key))?;
+                String::from_utf8(backup).context("The stored backup is not valid UTF-8")
+            }
+        }
+    }
+}
+
+/// Dump every table with `COPY`, from a single snapshot of the database.
+///
+/// The backup is encrypted with the `encryption` key, and refused without
+/// one: it holds the address of every subscriber.
+#[tracing::instrument(name = "Back up the database", skip_all)]
+pub async fn backup(
+    connection: &mut PgConnection,
+    cipher: &FieldCipher,
+) -> Result<String, anyhow::Error> {
+    anyhow::ensure!(
+        cipher.is_enabled(),
+        "Backups are encrypted, configure an encryption key to take one"
+    );
+    let mut transaction = connection.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to start a snapshot of the database")?;
+    let mut backup = format!("{}\n", HEADER);
+    for table in TABLES {
+        let rows: Vec<u8> = transaction
+            .copy_out_raw(&format!(
+                "COPY {} TO STDOUT WITH (FORMAT csv, HEADER true)",
+                table
+            ))
+            .await
+            .with_context(|| format!("Failed to start copying {}", table))?
+            .try_fold(Vec::new(), |mut rows, chunk| async move {
+                rows.extend_from_slice(&chunk);
+                Ok(rows)
+            })
+            .await
+            .with_context(|| format!("Failed to copy {}", table))?;
+        let rows = String::from_utf8(rows).context("Postgres sent rows that are not UTF-8")?;
+        backup.push_str(&format!("{} {}\n{}", table, rows.len(), rows));
+    }
+    transaction.commit().await?;
+    Ok(cipher.encrypt(&backup))
+}
+
+/// Load a backup into a migrated database, whose tables must be empty.
+///
+/// Columns are matched by name, so a backup taken before later migrations
+/// added columns with a default can still be restored.
+#[tracing::instrument(name = "Restore the database", skip_all)]
+pub async fn restore(
+    connection: &mut PgConnection,
+    cipher: &FieldCipher,
+    backup: String,
+) -> Result<(), anyhow::Error> {
+    let backup = cipher
+        .decrypt(backup)
+        .context("Failed to decrypt the backup")?;
+    let mut rest = backup
+        .strip_prefix(HEADER)
+        .and_then(|rest| rest.strip_prefix('\n'))
+        .context("The file is not a backup")?;
+
+    let mut transaction = connection.begin().await?;
+    for table in TABLES {
+        let rows_exist: bool =
+            sqlx::query_scalar(&format!("SELECT EXISTS (SELECT 1 FROM {})", table))
+                .fetch_one(&mut *transaction)
+                .await?;
+        if rows_exist {
+            anyhow::bail!(
+                "{} is not empty, restore into a freshly migrated database",
+                table
+            );
+        }
+    }
+    while !rest.is_empty() {
+        let (section, remaining) = rest.split_once('\n').context("The backup is truncated")?;
+        let (table, length) = section
+            .split_once(' ')
+            .and_then(|(table, length)| Some((table, length.parse::<usize>().ok()?)))
+            .context("The backup is corrupted")?;
+        if !TABLES.contains(&table) {
+            anyhow::bail!("The backup contains an unknown table {}", table);
+        }
+        let rows = remaining.get(..length).context("The backup is truncated")?;
+        rest = &remaining[length..];
+        let Some((columns, _)) = rows.split_once('\n') else {
+            continue;
+        };
+        let mut copy = transaction
+            .copy_in_raw(&format!(
+                "COPY {} ({}) FROM STDIN WITH (FORMAT csv, HEADER true)",
+                table, columns
+            ))
+            .await
+            .with_context(|| format!("Failed to start restoring {}", table))?;
+        copy.send(rows.as_bytes())
+            .await
+            .with_context(|| format!("Failed to restore {}", table))?;
+        copy.finish()
+            .await
+            .with_context(|| format!("Failed to restore {}", table))?;
+    }
+    // Rows are restored with their ids, which serial columns must not hand
+    // out again.
+    let serial_columns: Vec<(String, String)> = sqlx::query_as(
+        "SELECT table_name::text, column_name::text FROM information_schema.columns \
+         WHERE table_schema = current_schema() AND column_default LIKE 'nextval(%'",
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to list the serial columns")?;
+    for (table, column) in serial_columns {
+        sqlx::query(&format!(
+            "SELECT setval(pg_get_serial_sequence('{0}', '{1}'), COALESCE(MAX({1}), 0) + 1, false) \
+             FROM {0}",
+            table, column
+        ))
+        .execute(&mut *transaction)
+        .await
+        .with_context(|| format!("Failed to reset the sequence of {}", table))?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,322 @@
+//! Storage of uploaded and exported files, on the local filesystem or in an
+//! S3-compatible bucket depending on the `storage` settings.
+use crate::configuration::S3Settings;
+use anyhow::Context;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+
+/// Headers covered by the signature of S3 requests, in the order they are
+/// signed.
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Files stored under `/`-separated keys, e.g. `imports/<id>.csv`.
+pub trait BlobStore: Send + Sync {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>>;
+
+    /// The content stored under `key`, `None` if there is none.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, anyhow::Error>>;
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>>;
+
+    /// Remove the content stored under `key`, if any.
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), anyhow::Error>>;
+}
+
+/// Keeps each file under `root`, in the directory its key names.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Keys only name paths below `root`.
+    fn path(&self, key: &str) -> Result<PathBuf, anyhow::Error> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            anyhow::bail!("'{}' is not a valid storage key", key);
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+impl BlobStore for LocalBlobStore {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            if let Some(directory) = path.parent() {
+                tokio::fs::create_dir_all(directory)
+                    .await
+                    .with_context(|| format!("Failed to create {}", directory.display()))?;
+            }
+            tokio::fs::write(&path, content)
+                .await
+                .with_context(|| format!("Failed to write {}", path.display()))
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, anyhow::Error>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            match tokio::fs::read(&path).await {
+                Ok(content) => Ok(Some(content)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+            }
+        })
+    }
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            tokio::fs::try_exists(&path)
+                .await
+                .with_context(|| format!("Failed to look for {}", path.display()))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).with_context(|| format!("Failed to delete {}", path.display()))
+                }
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// Keeps each file as an object of a bucket, addressed path-style so that
+/// S3-compatible services such as MinIO work as well.
+///
+/// Requests are signed with AWS Signature Version 4.
+pub struct S3BlobStore {
+    settings: S3Settings,
+    http_client: reqwest::Client,
+}
+
+impl S3BlobStore {
+    pub fn new(settings: S3Settings) -> Self {
+        let http_client = reqwest::Client::builder()
+            .connect_timeout(settings.connect_timeout)
+            .timeout(settings.timeout)
+            .build()
+            .expect("Failed to build the storage HTTP client");
+        Self {
+            settings,
+            http_client,
+        }
+    }
+
+    fn object_url(&self, key: &str) -> Result<reqwest::Url, anyhow::Error> {
+        let path = format!("{}/{}", self.settings.bucket, key);
+        reqwest::Url::parse(&format!(
+            "{}/{}",
+            self.settings.endpoint.trim_end_matches('/'),
+            uri_encode(&path)
+        ))
+        .with_context(|| format!("'{}' is not a valid storage key", key))
+    }
+
+    /// Send a request for the object under `key`, signed for `content`.
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        content: Vec<u8>,
+    ) -> Result<reqwest::Response, anyhow::Error> {
+        let url = self.object_url(key)?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let content_hash = hex::encode(Sha256::digest(&content));
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            url.path(),
+            host,
+            content_hash,
+            timestamp,
+            SIGNED_HEADERS,
+            content_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.settings.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = signing_key(
+            self.settings.secret_access_key.expose_secret(),
+            &date,
+            &self.settings.region,
+            "s3",
+        );
+        let signature = hex::encode(hmac(&signing_key, string_to_sign.as_bytes()));
+        self.http_client
+            .request(method, url)
+            .header("x-amz-date", timestamp)
+            .header("x-amz-content-sha256", content_hash)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.settings.access_key_id, scope, SIGNED_HEADERS, signature
+                ),
+            )
+            .body(content)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach the bucket for {}", key))
+    }
+}
+
+impl BlobStore for S3BlobStore {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        content: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            self.send(reqwest::Method::PUT, key, content)
+                .await?
+                .error_for_status()
+                .with_context(|| format!("Failed to upload {}", key))?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, anyhow::Error>> {
+        Box::pin(async move {
+            let response = self.send(reqwest::Method::GET, key, Vec::new()).await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let content = response
+                .error_for_status()
+                .with_context(|| format!("Failed to download {}", key))?
+                .bytes()
+                .await
+                .with_context(|| format!("Failed to download {}", key))?;
+            Ok(Some(content.to_vec()))
+        })
+    }
+
+    fn exists<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, anyhow::Error>> {
+        Box::pin(async move {
+            let response = self.send(reqwest::Method::HEAD, key, Vec::new()).await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(false);
+            }
+            response
+                .error_for_status()
+                .with_context(|| format!("Failed to look for {}", key))?;
+            Ok(true)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            self.send(reqwest::Method::DELETE, key, Vec::new())
+                .await?
+                .error_for_status()
+                .with_context(|| format!("Failed to delete {}", key))?;
+            Ok(())
+        })
+    }
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The key requests are signed with, derived from the secret for a day,
+/// region and service.
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    hmac(&key, b"aws4_request")
+}
+
+/// Percent-encode everything but the unreserved characters and `/`, as
+/// Signature Version 4 expects of paths.
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalBlobStore, signing_key, uri_encode};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn the_signing_key_matches_the_aws_example() {
+        // From the Signature Version 4 documentation.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+    }
+
+    #[test]
+    fn keys_are_percent_encoded() {
+        assert_eq!(
+            uri_encode("bucket/archive/my issue+1.html"),
+            "bucket/archive/my%20issue%2B1.html"
+        );
+    }
+
+    #[test]
+    fn local_keys_cannot_escape_the_root() {
+        let store = LocalBlobStore::new("/var/lib/zero2prod");
+        assert_ok!(store.path("imports/1.csv"));
+        assert_err!(store.path("../etc/passwd"));
+        assert_err!(store.path("/etc/passwd"));
+        assert_err!(store.path(""));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::configuration::BrandingSettings;
+use crate::publishing::escape_html;
+use chrono::{DateTime, Utc};
+
+/// How a public page is described in its `<head>`.
+pub struct PageMetadata<'a> {
+    pub description: &'a str,
+    /// Absolute URL of the page, when known.
+    pub url: Option<&'a str>,
+    /// Set for articles, e.g. an issue, the page being a website otherwise.
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Renders the pages hosted for subscribers with the sender's brand: logo or
+/// product name, colors and postal address.
+pub struct Branding {
+    settings: BrandingSettings,
+}
+
+impl Branding {
+    pub fn new(settings: BrandingSettings) -> Self {
+        Self { settings }
+    }
+
+    /// A full HTML page titled `title`, escaped here, `content` being
+    /// trusted markup.
+    ///
+    /// Pages are kept out of search engines, most of them being personal.
+    pub fn page(&self, title: &str, content: &str) -> String {
+        self.render(
+            title,
+            content,
+            r#"<meta name="robots" content="noindex">
+"#,
+        )
+    }
+
+    /// Like [`Branding::page`], for pages meant to be found, e.g. the
+    /// archive of the issues, described by `metadata` to search engines
+    /// and to the apps unfurling links to them.
+    pub fn public_page(&self, title: &str, content: &str, metadata: &PageMetadata) -> String {
+        self.render(title, content, &self.meta_tags(title, metadata))
+    }
+
+    /// OpenGraph and Twitter card tags, the logo standing for the image.
+    fn meta_tags(&self, title: &str, metadata: &PageMetadata) -> String {
+        let title = escape_html(title);
+        let description = escape_html(metadata.description);
+        let mut tags = format!(
+            r#"<meta name="description" content="{description}">
+<meta property="og:site_name" content="{site_name}">
+<meta property="og:title" content="{title}">
+<meta property="og:description" content="{description}">
+<meta name="twitter:card" content="summary">
+<meta name="twitter:title" content="{title}">
+<meta name="twitter:description" content="{description}">
+"#,
+            site_name = escape_html(&self.settings.product_name),
+        );
+        match metadata.published_at {
+            Some(published_at) => tags.push_str(&format!(
+                r#"<meta property="og:type" content="article">
+<meta property="article:published_time" content="{}">
+"#,
+                published_at.to_rfc3339()
+            )),
+            None => tags.push_str(
+                r#"<meta property="og:type" content="website">
+"#,
+            ),
+        }
+        if let Some(url) = metadata.url {
+            tags.push_str(&format!(
+                r#"<link rel="canonical" href="{url}">
+<meta property="og:url" content="{url}">
+"#,
+                url = escape_html(url)
+            ));
+        }
+        if let Some(logo_url) = &self.settings.logo_url {
+            tags.push_str(&format!(
+                r#"<meta property="og:image" content="{logo_url}">
+<meta name="twitter:image" content="{logo_url}">
+"#,
+                logo_url = escape_html(logo_url)
+            ));
+        }
+        tags
+    }
+
+    fn render(&self, title: &str, content: &str, head: &str) -> String {
+        let settings = &self.settings;
+        let product_name = escape_html(&settings.product_name);
+        let header = match &settings.logo_url {
+            Some(logo_url) => format!(
+                r#"<img src="{}" alt="{}" height="48">"#,
+                escape_html(logo_url),
+                product_name
+            ),
+            None => format!("<strong>{}</strong>", product_name),
+        };
+        let address = settings
+            .footer_address
+            .as_deref()
+            .map(|address| format!("<br>{}", escape_html(address)))
+            .unwrap_or_default();
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+{head}<title>{title} - {product_name}</title>
+<style>
+body {{ background-color: {background}; color: {text}; font-family: sans-serif; max-width: 40em; margin: 0 auto; padding: 1em; }}
+a {{ color: {primary}; }}
+button {{ background-color: {primary}; color: {background}; border: none; padding: 0.5em 1em; }}
+footer {{ margin-top: 2em; font-size: small; }}
+</style>
+</head>
+<body>
+<header>{header}</header>
+<main>
+<h1>{title}</h1>
+{content}
+</main>
+<footer>{product_name}{address}</footer>
+</body>
+</html>"#,
+            title = escape_html(title),
+            product_name = product_name,
+            background = escape_html(&settings.background_color),
+            text = escape_html(&settings.text_color),
+            primary = escape_html(&settings.primary_color),
+            header = header,
+            content = content,
+            address = address,
+            head = head,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Branding, PageMetadata};
+    use crate::configuration::BrandingSettings;
+
+    #[test]
+    fn pages_carry_the_brand() {
+        let branding = Branding::new(BrandingSettings {
+            product_name: "Rust & Friends".into(),
+            logo_url: Some("https://example.com/logo.png".into()),
+            primary_color: "#ff6600".into(),
+            footer_address: Some("1 Main Street, Springfield".into()),
+            ..BrandingSettings::default()
+        });
+
+        let page = branding.page("Your subscription", "<p>Hello</p>");
+
+        assert!(page.contains("<title>Your subscription - Rust &amp; Friends</title>"));
+        assert!(page.contains(
+            r#"<img src="https://example.com/logo.png" alt="Rust &amp; Friends" height="48">"#
+        ));
+        assert!(page.contains("a { color: #ff6600; }"));
+        assert!(page.contains("<p>Hello</p>"));
+        assert!(page.contains("<footer>Rust &amp; Friends<br>1 Main Street, Springfield</footer>"));
+    }
+
+    #[test]
+    fn the_product_name_stands_in_for_a_missing_logo() {
+        let page = Branding::new(BrandingSettings::default()).page("Title", "");
+        assert!(page.contains("<header><strong>Newsletter</strong></header>"));
+        assert!(page.contains("<footer>Newsletter</footer>"));
+    }
+
+    fn metadata() -> PageMetadata<'static> {
+        PageMetadata {
+            description: "What happened this week",
+            url: None,
+            published_at: None,
+        }
+    }
+
+    #[test]
+    fn only_public_pages_can_be_indexed() {
+        let branding = Branding::new(BrandingSettings::default());
+        assert!(branding.page("Title", "").contains(r#"content="noindex""#));
+        assert!(
+            !branding
+                .public_page("Title", "", &metadata())
+                .contains("noindex")
+        );
+    }
+
+    #[test]
+    fn public_pages_unfurl_into_cards() {
+        let branding = Branding::new(BrandingSettings {
+            logo_url: Some("https://example.com/logo.png".into()),
+            ..BrandingSettings::default()
+        });
+        let published_at = "2025-07-01T08:00:00Z".parse().unwrap();
+
+        let page = branding.public_page(
+            "Issue #1 \"Hello\"",
+            "",
+            &PageMetadata {
+                url: Some("https://archive.example.com/issues/1.html"),
+                published_at: Some(published_at),
+                ..metadata()
+            },
+        );
+
+        assert!(
+            page.contains(r#"<meta property="og:title" content="Issue #1 &quot;Hello&quot;">"#)
+        );
+        assert!(
+            page.contains(r#"<meta name="twitter:description" content="What happened this week">"#)
+        );
+        assert!(page.contains(r#"<meta property="og:type" content="article">"#));
+        assert!(page.contains(
+            r#"<meta property="article:published_time" content="2025-07-01T08:00:00+00:00">"#
+        ));
+        assert!(page.contains(
+            r#"<link rel="canonical" href="https://archive.example.com/issues/1.html">"#
+        ));
+        assert!(
+            page.contains(r#"<meta name="twitter:image" content="https://example.com/logo.png">"#)
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::publishing::escape_html;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Lines of an iCalendar file are folded past this many octets.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// An event announced by an issue, e.g. a webinar or a meetup.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct IssueEvent {
+    pub title: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub location: Option<String>,
+}
+
+impl IssueEvent {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.title.trim().is_empty() {
+            return Err("The event title cannot be empty.".into());
+        }
+        if self.ends_at <= self.starts_at {
+            return Err("The event must end after it starts.".into());
+        }
+        Ok(())
+    }
+
+    /// The event as an iCalendar file, for an `.ics` attachment or download.
+    ///
+    /// The uid is derived from the issue, so that calendars update the event
+    /// rather than duplicate it when it is added twice.
+    pub fn to_ics(&self, newsletter_issue_id: Uuid, stamp: DateTime<Utc>) -> String {
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_owned(),
+            "VERSION:2.0".to_owned(),
+            "PRODID:-//zero2prod//newsletter//EN".to_owned(),
+            "CALSCALE:GREGORIAN".to_owned(),
+            "METHOD:PUBLISH".to_owned(),
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:{}@zero2prod", newsletter_issue_id),
+            format!("DTSTAMP:{}", format_timestamp(stamp)),
+            format!("DTSTART:{}", format_timestamp(self.starts_at)),
+            format!("DTEND:{}", format_timestamp(self.ends_at)),
+            format!("SUMMARY:{}", escape_text(&self.title)),
+        ];
+        if let Some(location) = &self.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        lines.push("END:VEVENT".to_owned());
+        lines.push("END:VCALENDAR".to_owned());
+        lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+    }
+
+    /// Link adding the event to Google Calendar, which cannot import an
+    /// attachment from the email itself.
+    pub fn google_calendar_link(&self) -> String {
+        let mut url = url::Url::parse("https://calendar.google.com/calendar/render")
+            .expect("The Google Calendar URL is valid");
+        url.query_pairs_mut()
+            .append_pair("action", "TEMPLATE")
+            .append_pair("text", &self.title)
+            .append_pair(
+                "dates",
+                &format!(
+                    "{}/{}",
+                    format_timestamp(self.starts_at),
+                    format_timestamp(self.ends_at)
+                ),
+            );
+        if let Some(location) = &self.location {
+            url.query_pairs_mut().append_pair("location", location);
+        }
+        url.into()
+    }
+
+    /// Append "Add to calendar" links to both bodies of an issue, `ics_link`
+    /// downloading the file calendar apps import.
+    pub fn add_to_calendar(&self, html: &str, text: &str, ics_link: &str) -> (String, String) {
+        let google_link = self.google_calendar_link();
+        (
+            format!(
+                r#"{}<p>Add to calendar: <a href="{}">Google Calendar</a> | <a href="{}">Apple, Outlook and others</a></p>"#,
+                html,
+                escape_html(&google_link),
+                escape_html(ics_link)
+            ),
+            format!(
+                "{}\n\nAdd to calendar:\n- Google Calendar: {}\n- Apple, Outlook and others: {}",
+                text, google_link, ics_link
+            ),
+        )
+    }
+}
+
+/// Where the `.ics` file of the event of an issue can be downloaded.
+pub fn event_ics_link(base_url: &str, newsletter_issue_id: Uuid) -> String {
+    format!(
+        "{}/newsletters/{}/event.ics",
+        base_url.trim_end_matches('/'),
+        newsletter_issue_id
+    )
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Continuation lines start with a space, lines are never split within a
+/// character.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueEvent, fold_line};
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    fn event() -> IssueEvent {
+        IssueEvent {
+            title: "Rust meetup; talks, pizza".into(),
+            starts_at: Utc.with_ymd_and_hms(2025, 7, 10, 18, 0, 0).unwrap(),
+            ends_at: Utc.with_ymd_and_hms(2025, 7, 10, 20, 30, 0).unwrap(),
+            location: Some("Berlin".into()),
+        }
+    }
+
+    #[test]
+    fn events_are_rendered_as_icalendar() {
+        let issue_id = Uuid::nil();
+        let stamp = Utc.with_ymd_and_hms(2025, 7, 1, 9, 0, 0).unwrap();
+
+        let ics = event().to_ics(issue_id, stamp);
+
+        assert_eq!(
+            ics,
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//zero2prod//newsletter//EN\r\n\
+             CALSCALE:GREGORIAN\r\n\
+             METHOD:PUBLISH\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:00000000-0000-0000-0000-000000000000@zero2prod\r\n\
+             DTSTAMP:20250701T090000Z\r\n\
+             DTSTART:20250710T180000Z\r\n\
+             DTEND:20250710T203000Z\r\n\
+             SUMMARY:Rust meetup\\; talks\\, pizza\r\n\
+             LOCATION:Berlin\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn long_lines_are_folded() {
+        let line = format!("SUMMARY:{}", "é".repeat(50));
+        let folded = fold_line(&line);
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn google_calendar_links_carry_the_event() {
+        assert_eq!(
+            event().google_calendar_link(),
+            "https://calendar.google.com/calendar/render?action=TEMPLATE\
+             &text=Rust+meetup%3B+talks%2C+pizza\
+             &dates=20250710T180000Z%2F20250710T203000Z&location=Berlin"
+        );
+    }
+
+    #[test]
+    fn events_must_end_after_they_start() {
+        let mut event = event();
+        event.ends_at = event.starts_at;
+        assert!(event.validate().is_err());
+    }
+}
@@ -0,0 +1,407 @@
+//! Outcome of the `zero2prod` subcommands, for provisioning pipelines to
+//! branch on: a documented exit code and, with `--format json`, a single
+//! JSON object on stdout.
+//!
+//! | Exit code | Status                  |
+//! |-----------|-------------------------|
+//! | 0         | `ok`                    |
+//! | 1         | `unexpected_error`      |
+//! | 2         | `invalid_arguments`     |
+//! | 3         | `invalid_configuration` |
+//! | 4         | `database_unavailable`  |
+//! | 5         | `check_failed`          |
+//! | 6         | `conflict`              |
+use crate::authentication::{CreateUserError, create_user};
+use crate::configuration::Settings;
+use crate::domain::Password;
+use crate::migrations::{check_pending_migrations, pending_migrations, run_online};
+use crate::routes::error_chain_fmt;
+use anyhow::Context;
+use secrecy::SecretString;
+use sqlx::{Connection, PgConnection};
+use std::process::ExitCode;
+
+/// Whether the outcome of a command is written for humans or as JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    /// Take `--format text` or `--format json` out of `args`, `Text` when
+    /// absent.
+    pub fn extract(args: &mut Vec<String>) -> Result<Self, CommandError> {
+        let Some(position) = args.iter().position(|arg| arg == "--format") else {
+            return Ok(Self::Text);
+        };
+        let format = match args.get(position + 1).map(String::as_str) {
+            Some("text") => Self::Text,
+            Some("json") => Self::Json,
+            _ => {
+                return Err(CommandError::InvalidArguments(
+                    "`--format` is either `text` or `json`".into(),
+                ));
+            }
+        };
+        args.drain(position..=position + 1);
+        Ok(format)
+    }
+}
+
+/// What a successful command has to say.
+#[derive(Debug, Default)]
+pub struct Output {
+    pub message: String,
+    /// Command-specific, e.g. the migrations applied.
+    pub details: serde_json::Value,
+}
+
+#[derive(thiserror::Error)]
+pub enum CommandError {
+    #[error("{0}")]
+    InvalidArguments(String),
+    #[error("The configuration is invalid: {0}")]
+    InvalidConfiguration(String),
+    #[error("Failed to connect to the database")]
+    DatabaseUnavailable(#[source] sqlx::Error),
+    #[error("{message}")]
+    CheckFailed {
+        message: String,
+        details: serde_json::Value,
+    },
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl CommandError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CommandError::UnexpectedError(_) => 1,
+            CommandError::InvalidArguments(_) => 2,
+            CommandError::InvalidConfiguration(_) => 3,
+            CommandError::DatabaseUnavailable(_) => 4,
+            CommandError::CheckFailed { .. } => 5,
+            CommandError::Conflict(_) => 6,
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        match self {
+            CommandError::UnexpectedError(_) => "unexpected_error",
+            CommandError::InvalidArguments(_) => "invalid_arguments",
+            CommandError::InvalidConfiguration(_) => "invalid_configuration",
+            CommandError::DatabaseUnavailable(_) => "database_unavailable",
+            CommandError::CheckFailed { .. } => "check_failed",
+            CommandError::Conflict(_) => "conflict",
+        }
+    }
+}
+
+/// The JSON object printed for `outcome` with `--format json`.
+pub fn json_report(command: &str, outcome: &Result<Output, CommandError>) -> serde_json::Value {
+    match outcome {
+        Ok(output) => serde_json::json!({
+            "command": command,
+            "status": "ok",
+            "exit_code": 0,
+            "message": output.message,
+            "details": output.details,
+        }),
+        Err(e) => {
+            let mut causes = Vec::new();
+            let mut source = std::error::Error::source(e);
+            while let Some(cause) = source {
+                causes.push(cause.to_string());
+                source = cause.source();
+            }
+            let details = match e {
+                CommandError::CheckFailed { details, .. } => details.clone(),
+                _ => serde_json::Value::Null,
+            };
+            serde_json::json!({
+                "command": command,
+                "status": e.status(),
+                "exit_code": e.exit_code(),
+                "message": e.to_string(),
+                "causes": causes,
+                "details": details,
+            })
+        }
+    }
+}
+
+/// Print the outcome of `command`, to stdout when it succeeded or in JSON,
+/// to stderr otherwise, and return the exit code of the process.
+pub fn report(
+    command: &str,
+    outcome: Result<Output, CommandError>,
+    format: OutputFormat,
+) -> ExitCode {
+    let exit_code = match &outcome {
+        Ok(_) => 0,
+        Err(e) => e.exit_code(),
+    };
+    match (format, outcome) {
+        (OutputFormat::Json, outcome) => println!("{}", json_report(command, &outcome)),
+        (OutputFormat::Text, Ok(output)) => {
+            if !output.message.is_empty() {
+                println!("{}", output.message);
+            }
+        }
+        (OutputFormat::Text, Err(e)) => eprintln!("Error: {:?}", e),
+    }
+    ExitCode::from(exit_code)
+}
+
+async fn connect(configuration: &Settings) -> Result<PgConnection, CommandError> {
+    PgConnection::connect_with(&configuration.database.with_db())
+        .await
+        .map_err(CommandError::DatabaseUnavailable)
+}
+
+/// `check-config`: the configuration was read, make sure it can be served
+/// with.
+pub fn check_config(configuration: &Settings) -> Result<Output, CommandError> {
+    configuration
+        .validate()
+        .map_err(CommandError::InvalidConfiguration)?;
+    Ok(Output {
+        message: "The configuration is valid".into(),
+        details: serde_json::Value::Null,
+    })
+}
+
+/// `migrate`: apply the pending migrations, refusing the ones the running
+/// version cannot cope with.
+pub async fn migrate(configuration: &Settings) -> Result<Output, CommandError> {
+    let mut connection = connect(configuration).await?;
+    let applied = run_online(&mut connection, &configuration.database.migrations).await?;
+    connection
+        .close()
+        .await
+        .context("Failed to close the connection")?;
+    let message = if applied.is_empty() {
+        "No pending migrations".to_string()
+    } else {
+        applied
+            .iter()
+            .map(|version| format!("Applied {}", version))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    Ok(Output {
+        message,
+        details: serde_json::json!({ "applied": applied }),
+    })
+}
+
+/// `migrate --check`: fail if a pending migration is not
+/// backward-compatible, e.g. in CI before a blue/green deployment.
+pub async fn check_migrations(configuration: &Settings) -> Result<Output, CommandError> {
+    let mut connection = connect(configuration).await?;
+    let incompatibilities = check_pending_migrations(&mut connection).await?;
+    connection
+        .close()
+        .await
+        .context("Failed to close the connection")?;
+    if incompatibilities.is_empty() {
+        return Ok(Output {
+            message: "Pending migrations are backward-compatible".into(),
+            details: serde_json::json!({ "incompatibilities": [] }),
+        });
+    }
+    let mut message = format!(
+        "{} statements would break the running version, split them with expand-contract",
+        incompatibilities.len()
+    );
+    for incompatibility in &incompatibilities {
+        message.push_str(&format!("\n{}", incompatibility));
+    }
+    let incompatibilities: Vec<_> = incompatibilities
+        .iter()
+        .map(|i| {
+            serde_json::json!({
+                "version": i.version,
+                "description": i.description,
+                "statement": i.statement,
+                "reason": i.reason,
+            })
+        })
+        .collect();
+    Err(CommandError::CheckFailed {
+        message,
+        details: serde_json::json!({ "incompatibilities": incompatibilities }),
+    })
+}
+
+/// `create-user <username>`: add an admin, e.g. the first one of a new
+/// deployment.
+pub async fn create_admin(
+    configuration: &Settings,
+    username: &str,
+    password: SecretString,
+) -> Result<Output, CommandError> {
+    let password = Password::parse(password).map_err(CommandError::InvalidArguments)?;
+    let pg_pool = sqlx::PgPool::connect_with(configuration.database.with_db())
+        .await
+        .map_err(CommandError::DatabaseUnavailable)?;
+    let outcome = create_user(username, password, &pg_pool).await;
+    pg_pool.close().await;
+    let user_id = outcome.map_err(|e| match e {
+        CreateUserError::UsernameTaken => CommandError::Conflict(e.to_string()),
+        CreateUserError::UnexpectedError(e) => e.into(),
+    })?;
+    Ok(Output {
+        message: format!("Created {} ({})", username, user_id),
+        details: serde_json::json!({ "user_id": user_id, "username": username }),
+    })
+}
+
+/// A check run by `self-test`, failed when `error` is set.
+#[derive(Debug, serde::Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub error: Option<String>,
+}
+
+/// `self-test`: make sure the dependencies of the application are
+/// reachable and ready, reporting every failed check.
+pub async fn self_test(configuration: &Settings) -> Result<Output, CommandError> {
+    let checks = vec![
+        Check {
+            name: "database",
+            error: check_database(configuration).await.err(),
+        },
+        Check {
+            name: "storage",
+            error: check_storage(configuration).await.err(),
+        },
+        Check {
+            name: "email_provider",
+            error: check_email_provider(configuration).await.err(),
+        },
+    ];
+    let failed = checks.iter().filter(|c| c.error.is_some()).count();
+    let message = checks
+        .iter()
+        .map(|check| match &check.error {
+            None => format!("ok      {}", check.name),
+            Some(error) => format!("failed  {}: {}", check.name, error),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let details = serde_json::json!({ "checks": checks });
+    if failed > 0 {
+        return Err(CommandError::CheckFailed {
+            message: format!("{} of {} checks failed\n{}", failed, checks.len(), message),
+            details,
+        });
+    }
+    Ok(Output { message, details })
+}
+
+/// The database is reachable and fully migrated.
+async fn check_database(configuration: &Settings) -> Result<(), String> {
+    let mut connection = PgConnection::connect_with(&configuration.database.with_db())
+        .await
+        .map_err(|e| e.to_string())?;
+    let (pending, _) = pending_migrations(&mut connection)
+        .await
+        .map_err(|e| e.to_string())?;
+    let _ = connection.close().await;
+    if !pending.is_empty() {
+        return Err(format!("{} migrations are pending", pending.len()));
+    }
+    Ok(())
+}
+
+/// Files can be written to, read back from and deleted from the storage.
+async fn check_storage(configuration: &Settings) -> Result<(), String> {
+    let store = configuration.storage.store();
+    let key = format!("self-test/{}", uuid::Uuid::new_v4());
+    let content = b"zero2prod self-test".to_vec();
+    store
+        .put(&key, content.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    let read = store.get(&key).await.map_err(|e| e.to_string());
+    store.delete(&key).await.map_err(|e| e.to_string())?;
+    if read? != Some(content) {
+        return Err("The file written could not be read back".into());
+    }
+    Ok(())
+}
+
+/// The API of the email provider answers, whatever the status.
+async fn check_email_provider(configuration: &Settings) -> Result<(), String> {
+    reqwest::Client::builder()
+        .timeout(configuration.email_client.timeout)
+        .build()
+        .map_err(|e| e.to_string())?
+        .get(&configuration.email_client.base_url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn the_format_flag_is_taken_out_of_the_arguments() {
+        let mut arguments = args(&["migrate", "--format", "json", "--check"]);
+
+        let format = OutputFormat::extract(&mut arguments).unwrap();
+
+        assert_eq!(format, OutputFormat::Json);
+        assert_eq!(arguments, args(&["migrate", "--check"]));
+    }
+
+    #[test]
+    fn text_is_the_default_format() {
+        let mut arguments = args(&["self-test"]);
+
+        assert_eq!(
+            OutputFormat::extract(&mut arguments).unwrap(),
+            OutputFormat::Text
+        );
+    }
+
+    #[test]
+    fn an_unknown_format_is_an_invalid_argument() {
+        let mut arguments = args(&["self-test", "--format", "yaml"]);
+
+        let e = OutputFormat::extract(&mut arguments).unwrap_err();
+
+        assert_eq!(e.exit_code(), 2);
+    }
+
+    #[test]
+    fn failed_checks_are_reported_with_their_details() {
+        let outcome = Err(CommandError::CheckFailed {
+            message: "1 of 1 checks failed".into(),
+            details: serde_json::json!({ "checks": [{ "name": "storage", "error": "denied" }] }),
+        });
+
+        let report = json_report("self-test", &outcome);
+
+        assert_eq!(report["status"], "check_failed");
+        assert_eq!(report["exit_code"], 5);
+        assert_eq!(report["details"]["checks"][0]["name"], "storage");
+    }
+}
@@ -0,0 +1,157 @@
+use crate::configuration::ComplaintSettings;
+use crate::repositories::subscribers::unsubscribe_subscribers;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+/// Notification sent to `complaints.webhook_url` when an issue crosses the
+/// complaint rate threshold.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct ComplaintAlert {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub deliveries: i64,
+    pub complaints: i64,
+    pub complaint_rate: f64,
+    pub raised_at: DateTime<Utc>,
+}
+
+/// Raises an alert the first time the complaint rate of an issue reaches the
+/// configured threshold.
+pub struct ComplaintAlerts {
+    settings: ComplaintSettings,
+    http_client: reqwest::Client,
+}
+
+impl ComplaintAlerts {
+    pub fn new(settings: ComplaintSettings) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.webhook_timeout)
+            .build()
+            .expect("Failed to build the complaint webhook HTTP client");
+        Self {
+            settings,
+            http_client,
+        }
+    }
+
+    /// Look at the complaints received for an issue so far, returning the
+    /// alert raised, if any.
+    ///
+    /// Failing to notify the webhook is logged rather than returned, the
+    /// alert stays visible on the deliverability dashboard.
+    #[tracing::instrument(name = "Check the complaint rate of an issue", skip(self, pg_pool))]
+    pub async fn check(
+        &self,
+        pg_pool: &PgPool,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<ComplaintAlert>, sqlx::Error> {
+        let Some(alert) = raise_alert(pg_pool, &self.settings, newsletter_issue_id).await? else {
+            return Ok(None);
+        };
+        tracing::warn!(
+            complaints = alert.complaints,
+            deliveries = alert.deliveries,
+            "The complaint rate of an issue crossed the alert threshold"
+        );
+        if let Some(url) = &self.settings.webhook_url
+            && let Err(e) = self.notify(url, &alert).await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                "Failed to notify the complaint webhook"
+            );
+        }
+        Ok(Some(alert))
+    }
+
+    async fn notify(&self, url: &str, alert: &ComplaintAlert) -> Result<(), reqwest::Error> {
+        self.http_client
+            .post(url)
+            .json(alert)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Record the alert, unless the issue is below the threshold or already
+/// raised one.
+async fn raise_alert(
+    pg_pool: &PgPool,
+    settings: &ComplaintSettings,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<ComplaintAlert>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        WITH stats AS (
+            SELECT
+                COUNT(*) AS deliveries,
+                COUNT(*) FILTER (WHERE complained) AS complaints
+            FROM delivery_outcomes
+            WHERE newsletter_issue_id = $1
+        )
+        INSERT INTO complaint_alerts (newsletter_issue_id, deliveries, complaints, raised_at)
+        SELECT $1, deliveries, complaints, now()
+        FROM stats
+        WHERE deliveries >= $2
+            AND complaints::float8 / deliveries >= $3
+        ON CONFLICT DO NOTHING
+        RETURNING deliveries, complaints, raised_at,
+            (SELECT title FROM newsletter_issues WHERE newsletter_issue_id = $1) AS "title!"
+        "#,
+        newsletter_issue_id,
+        i64::from(settings.min_deliveries.max(1)),
+        settings.alert_rate,
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(r.map(|r| ComplaintAlert {
+        newsletter_issue_id,
+        title: r.title,
+        deliveries: r.deliveries,
+        complaints: r.complaints,
+        complaint_rate: r.complaints as f64 / r.deliveries as f64,
+        raised_at: r.raised_at,
+    }))
+}
+
+/// Unsubscribe a subscriber who reported an issue as spam and suppress
+/// their address, so that signing up again does not get them emailed.
+#[tracing::instrument(name = "Unsubscribe a complaining subscriber", skip(transaction))]
+pub async fn unsubscribe_complainer(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    unsubscribe_subscribers(&mut *transaction, &[subscriber_id]).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, newsletter_issue_id, suppressed_at)
+        SELECT lower(email), 'complaint', $2, now()
+        FROM subscriptions
+        WHERE id = $1
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        subscriber_id,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Whether `email` was suppressed, in which case nothing is sent to it.
+pub async fn is_suppressed(
+    connection: &mut PgConnection,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let suppressed = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM suppressions WHERE email = lower($1)) AS "suppressed!""#,
+        email,
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(suppressed)
+}
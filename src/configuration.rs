@@ -1,16 +1,102 @@
-use crate::domain::SubscriberEmail;
+use crate::EmailClient;
+use crate::accessibility::{make_accessible, strip_article};
+use crate::blob_store::{BlobStore, LocalBlobStore, S3BlobStore};
+use crate::dns::CachingResolver;
+use crate::domain::{SubscriberEmail, SubscriberRegion};
+use crate::email_client::RetryPolicy;
+use crate::publishing::escape_html;
+use crate::templates::read_template_files;
+use crate::tracking::TrackingMode;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use chrono::{DateTime, Utc};
+use regex::Regex;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::ConnectOptions;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
+    /// Redis keeping the sessions of logged in admins, kept in the session
+    /// cookie itself when absent.
+    #[serde(default)]
+    pub redis_uri: Option<SecretString>,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_templates: EmailTemplatesSettings,
+    pub short_links: ShortLinkSettings,
+    /// How long the responses to requests sent with an `Idempotency-Key`
+    /// are kept for retries.
+    #[serde(default)]
+    pub idempotency: IdempotencySettings,
+    pub tracking: TrackingSettings,
+    pub token_guard: TokenGuardSettings,
+    pub signup_anomalies: SignupAnomalySettings,
+    /// Acceptance policy of the deployment for new subscribers.
+    #[serde(default)]
+    pub subscriber_validation: SubscriberValidationSettings,
+    /// Whether subscribing takes an invite code.
+    #[serde(default)]
+    pub invitations: InvitationSettings,
+    /// Pages fetched on behalf of the authors, e.g. to draft an issue from.
+    #[serde(default)]
+    pub web_pages: WebPageSettings,
+    pub confirmation_emails: ConfirmationEmailSettings,
+    pub complaints: ComplaintSettings,
+    pub maintenance: MaintenanceSettings,
+    /// Look of the pages hosted for subscribers.
+    #[serde(default)]
+    pub branding: BrandingSettings,
+    /// Static archive written by `zero2prod export-archive`.
+    #[serde(default)]
+    pub archive: ArchiveSettings,
+    /// Pace of the newsletter deliveries to each recipient domain.
+    #[serde(default)]
+    pub delivery_throttling: DeliveryThrottlingSettings,
+    /// Default value of each feature flag, see [`crate::feature_flags`].
+    #[serde(default)]
+    pub feature_flags: HashMap<String, FeatureFlagSettings>,
+    /// Application-level encryption of stored content, disabled when absent.
+    #[serde(default)]
+    pub encryption: Option<EncryptionSettings>,
+    /// Signing of the exported proofs of consent.
+    pub consent_proofs: ConsentProofSettings,
+    /// Blog feed turned into newsletter issues, disabled when absent.
+    #[serde(default)]
+    pub feed: Option<FeedSettings>,
+    /// Recurring digests of the issues published since their previous run.
+    #[serde(default)]
+    pub digests: Vec<DigestSettings>,
+    /// Stats summaries emailed to admins on a schedule.
+    #[serde(default)]
+    pub admin_reports: Vec<AdminReportSettings>,
+    /// Notifications to operators when deliveries go wrong, disabled when
+    /// absent.
+    #[serde(default)]
+    pub alerts: Option<AlertSettings>,
+    /// Slash commands of the editorial Slack workspace, disabled when
+    /// absent.
+    #[serde(default)]
+    pub slack: Option<SlackSettings>,
+    /// Reputation reports of the sending domains, disabled when absent.
+    #[serde(default)]
+    pub postmaster: Option<PostmasterSettings>,
+    /// How spans are logged and whether they are exported to an
+    /// OpenTelemetry collector.
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    /// Where uploaded files and exports are kept.
+    #[serde(default)]
+    pub storage: StorageSettings,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -27,6 +113,37 @@ pub struct DatabaseSettings {
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    #[serde(default)]
+    pub migrations: MigrationSettings,
+}
+
+/// How `zero2prod migrate` behaves while the previous version keeps serving
+/// traffic.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MigrationSettings {
+    /// How long a migration may wait for a table lock before giving up,
+    /// rather than stalling every query queued behind it.
+    #[serde(
+        rename = "lock_timeout_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub lock_timeout: Duration,
+    /// How long to wait for another instance to finish migrating.
+    #[serde(
+        rename = "advisory_lock_wait_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub advisory_lock_wait: Duration,
+}
+
+impl Default for MigrationSettings {
+    fn default() -> Self {
+        Self {
+            lock_timeout: Duration::from_secs(5),
+            advisory_lock_wait: Duration::from_secs(60),
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -35,18 +152,1058 @@ pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub base_url: String,
+    /// Key signing the links handed out without authentication, e.g. draft
+    /// previews.
+    pub hmac_secret: SecretString,
+    /// Expose `/dev/*` helpers (e.g. fixture generation). Never in production.
+    #[serde(default)]
+    pub enable_dev_routes: bool,
+    /// Second listener serving the admin, newsletter and dev routes, which
+    /// the public one then leaves out. When absent, the public listener
+    /// serves every route.
+    #[serde(default)]
+    pub admin_listener: Option<ListenerSettings>,
+    /// Custom domain, CNAMEd to the application, that the tracking, short
+    /// and unsubscribe links of emails point to, e.g.
+    /// `https://links.example.com`. Only those links are served on it.
+    #[serde(default)]
+    pub link_base_url: Option<String>,
+    /// On SIGTERM or SIGINT, how long in-flight requests have to complete
+    /// once new connections are refused.
+    #[serde(
+        rename = "shutdown_timeout_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub shutdown_timeout: Duration,
+    /// Per-address limits of the public endpoints sending emails.
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    /// Serve HTTPS on `port`, to run without a TLS-terminating reverse
+    /// proxy. The admin listener, meant for a private network, stays HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+}
+
+impl ApplicationSettings {
+    /// Where the links embedded in emails point to, `base_url` unless a
+    /// custom link domain is configured.
+    pub fn link_base_url(&self) -> &str {
+        self.link_base_url.as_deref().unwrap_or(&self.base_url)
+    }
+
+    /// Host of the custom link domain, if it differs from the one of
+    /// `base_url`.
+    pub fn link_host(&self) -> Option<String> {
+        let host = |url: &str| url::Url::parse(url).ok()?.host_str().map(str::to_owned);
+        let link_host = host(self.link_base_url.as_deref()?)?;
+        (Some(&link_host) != host(&self.base_url).as_ref()).then_some(link_host)
+    }
+}
+
+/// A token bucket per address: `burst` requests at once, then one every
+/// `refill_interval`.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub burst: u32,
+    #[serde(
+        rename = "refill_interval_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub refill_interval: Duration,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            burst: 10,
+            refill_interval: Duration::from_secs(6),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ListenerSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TlsSettings {
+    /// PEM file of the certificate chain, leaf certificate first.
+    pub cert_path: PathBuf,
+    /// PEM file of the private key, PKCS#8, PKCS#1 or SEC1.
+    pub key_path: PathBuf,
+    /// Plain HTTP listener, e.g. on port 80, redirecting every request to
+    /// the same path on `base_url`.
+    #[serde(default)]
+    pub redirect_listener: Option<ListenerSettings>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct EmailClientSettings {
     pub base_url: String,
+    /// Tried in order when `base_url` fails.
+    #[serde(default)]
+    pub fallback_base_urls: Vec<String>,
+    /// Dedicated endpoints for subscribers of a given region.
+    #[serde(default)]
+    pub regions: Vec<EmailRegionSettings>,
     pub sender_email: SubscriberEmail,
+    #[serde(default)]
+    pub sender_name: String,
     pub authorization_token: SecretString,
     #[serde(
         rename = "timeout_duration_millis",
         deserialize_with = "deserialize_duration_from_millis"
     )]
     pub timeout: Duration,
+    #[serde(default)]
+    pub http: HttpClientSettings,
+    #[serde(default)]
+    pub dns: DnsSettings,
+    /// Outbound proxy all calls to the provider must go through.
+    pub proxy: Option<ProxySettings>,
+    /// Bearer token the email provider must present when calling our
+    /// bounce and complaint webhook. Only set in `local.yaml`, startup fails
+    /// without it elsewhere.
+    pub webhook_token: SecretString,
+    /// Whether the provider accepts AMP for Email bodies, which are left out
+    /// of issues otherwise.
+    #[serde(default)]
+    pub supports_amp: bool,
+    /// Whether the provider relays subjects as is over SMTP, in which case
+    /// those that are not plain ASCII are sent as RFC 2047 encoded words.
+    #[serde(default)]
+    pub encode_subjects: bool,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    /// Emails of a newsletter issue in flight at once.
+    #[serde(default = "default_max_concurrent_sends")]
+    pub max_concurrent_sends: NonZeroUsize,
+}
+
+fn default_max_concurrent_sends() -> NonZeroUsize {
+    NonZeroUsize::new(10).expect("The default concurrency is not zero")
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EmailRegionSettings {
+    pub region: SubscriberRegion,
+    /// Endpoints tried in order, subscribers of the region never go
+    /// through any other one.
+    pub base_urls: Vec<String>,
+}
+
+/// Retries of timeouts and 5xx responses of an endpoint, before falling back
+/// to the next one.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetrySettings {
+    /// Attempts per endpoint, the first one included. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled before each of the next ones.
+    #[serde(
+        rename = "base_delay_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub base_delay: Duration,
+    /// Fraction of each delay to randomly add or remove, between 0 and 1.
+    pub jitter: f64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            jitter: 0.5,
+        }
+    }
+}
+
+/// Connection reuse towards the email provider. Bulk sends go through a
+/// single client, these keep its connections (and TLS sessions) alive.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HttpClientSettings {
+    pub max_idle_per_host: usize,
+    #[serde(
+        rename = "idle_timeout_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub idle_timeout: Duration,
+    pub version: HttpVersionPreference,
+    #[serde(
+        rename = "tcp_keepalive_millis",
+        deserialize_with = "deserialize_optional_duration_from_millis"
+    )]
+    pub tcp_keepalive: Option<Duration>,
+}
+
+impl Default for HttpClientSettings {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 32,
+            idle_timeout: Duration::from_secs(90),
+            version: HttpVersionPreference::default(),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpVersionPreference {
+    /// Negotiated through ALPN, HTTP/2 when the provider supports it.
+    #[default]
+    Auto,
+    Http1Only,
+    /// Skip negotiation and speak HTTP/2 straight away.
+    Http2PriorKnowledge,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DnsSettings {
+    /// Cache lookups for this long, and keep serving the last known
+    /// addresses when the resolver fails. `None` disables caching.
+    #[serde(
+        rename = "cache_ttl_millis",
+        deserialize_with = "deserialize_optional_duration_from_millis"
+    )]
+    pub cache_ttl: Option<Duration>,
+    /// Static resolution, bypassing DNS altogether for these hosts.
+    pub overrides: Vec<DnsOverride>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DnsOverride {
+    pub host: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ProxySettings {
+    /// e.g. `http://proxy.internal:3128`, used for both HTTP and HTTPS.
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    /// Comma-separated hosts that must be reached directly.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    fn proxy(&self) -> Result<reqwest::Proxy, String> {
+        let proxy = reqwest::Proxy::all(&self.url)
+            .map_err(|e| format!("Invalid email client proxy URL: {}", e))?;
+        let proxy = match &self.username {
+            Some(username) => proxy.basic_auth(
+                username,
+                self.password
+                    .as_ref()
+                    .map(|p| p.expose_secret())
+                    .unwrap_or_default(),
+            ),
+            None => proxy,
+        };
+        Ok(proxy.no_proxy(
+            self.no_proxy
+                .as_deref()
+                .and_then(reqwest::NoProxy::from_string),
+        ))
+    }
+}
+
+impl EmailClientSettings {
+    pub fn http_client(&self) -> Result<reqwest::Client, String> {
+        let builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .pool_max_idle_per_host(self.http.max_idle_per_host)
+            .pool_idle_timeout(self.http.idle_timeout)
+            .tcp_keepalive(self.http.tcp_keepalive);
+        let builder = match self.http.version {
+            HttpVersionPreference::Auto => builder,
+            HttpVersionPreference::Http1Only => builder.http1_only(),
+            HttpVersionPreference::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+        };
+        let builder = match self.dns.cache_ttl {
+            Some(ttl) => builder.dns_resolver(Arc::new(CachingResolver::new(ttl))),
+            None => builder,
+        };
+        let builder = self.dns.overrides.iter().fold(builder, |builder, o| {
+            // The port is ignored, the one of the URL is used.
+            let addrs: Vec<SocketAddr> = o
+                .addresses
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, 0))
+                .collect();
+            builder.resolve_to_addrs(&o.host, &addrs)
+        });
+        let builder = match &self.proxy {
+            Some(proxy) => builder.proxy(proxy.proxy()?),
+            None => builder,
+        };
+        builder
+            .build()
+            .map_err(|e| format!("Failed to build the email provider HTTP client: {}", e))
+    }
+
+    pub fn client(&self) -> Result<EmailClient, String> {
+        let client = EmailClient::new(
+            self.http_client()?,
+            self.base_url.clone(),
+            self.sender_email.clone(),
+            self.sender_name.clone(),
+            self.authorization_token.clone(),
+        )
+        .with_retry_policy(RetryPolicy {
+            max_attempts: self.retry.max_attempts.max(1),
+            base_delay: self.retry.base_delay,
+            jitter: self.retry.jitter,
+        })
+        .with_max_concurrent_sends(self.max_concurrent_sends);
+        let client = self
+            .fallback_base_urls
+            .iter()
+            .fold(client, |client, base_url| {
+                client.with_fallback(base_url.clone())
+            });
+        let client = self.regions.iter().try_fold(client, |client, r| {
+            client.with_region(&r.region, r.base_urls.clone())
+        })?;
+        let client = if self.encode_subjects {
+            client.with_encoded_subjects()
+        } else {
+            client
+        };
+        if self.supports_amp {
+            Ok(client.with_amp_support())
+        } else {
+            Ok(client)
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EmailTemplatesSettings {
+    /// Sent to new subscribers, with `{confirmation_link}` and `{name}`
+    /// placeholders, the subject included.
+    pub confirmation: EmailTemplate,
+    /// Sent to inactive subscribers by a re-engagement campaign, with a
+    /// `{reengagement_link}` placeholder.
+    pub reengagement: EmailTemplate,
+    /// Turns a new feed entry, or a page a draft is created from, into a
+    /// newsletter issue, with `{title}`, `{link}` and `{summary}`
+    /// placeholders, the subject included.
+    pub feed_entry: EmailTemplate,
+    /// Digest of recent issues, with `{name}` and `{issues}` placeholders,
+    /// the subject included.
+    pub digest: EmailTemplate,
+    /// Sent to subscribers asking to access their subscription, with
+    /// `{magic_link}` and `{expires_in_minutes}` placeholders.
+    pub magic_link: EmailTemplate,
+    /// Stats summary emailed to admins, with `{name}`, `{period}`,
+    /// `{new_subscribers}`, `{unsubscribes}`, `{confirmed_subscribers}`,
+    /// `{top_issues}`, `{deliveries}`, `{bounce_rate}` and
+    /// `{complaint_rate}` placeholders, the subject included.
+    pub admin_report: EmailTemplate,
+    /// Appended to newsletters and re-engagement emails.
+    pub footer: FooterTemplate,
+    /// Directory of template files taking over the copy above, see
+    /// [`read_template_files`](crate::templates::read_template_files).
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+}
+
+/// Copy of a transactional email.
+///
+/// `{name}` placeholders in `html` and `text` are replaced with the matching
+/// variable when the email is rendered.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    /// Language of the copy, announced to screen readers.
+    #[serde(default = "default_email_lang")]
+    pub lang: String,
+}
+
+fn default_email_lang() -> String {
+    "en".into()
+}
+
+impl EmailTemplate {
+    pub fn render_subject(&self, variables: &[(&str, &str)]) -> String {
+        render(&self.subject, variables)
+    }
+
+    pub fn render_html(&self, variables: &[(&str, &str)]) -> String {
+        make_accessible(&render(&self.html, variables), &self.lang)
+    }
+
+    pub fn render_text(&self, variables: &[(&str, &str)]) -> String {
+        render(&self.text, variables)
+    }
+}
+
+/// Closes every email sent to a subscriber, with a `{status_link}`
+/// placeholder pointing at their subscription status page, an
+/// `{unsubscribe_link}` one and a `{postal_address}` one.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct FooterTemplate {
+    pub html: String,
+    pub text: String,
+    /// Postal address of the sender, required by CAN-SPAM in every
+    /// newsletter.
+    #[serde(default)]
+    pub postal_address: String,
+    /// Language of the newsletter, announced to screen readers.
+    #[serde(default = "default_email_lang")]
+    pub lang: String,
+}
+
+impl FooterTemplate {
+    /// Fail unless the footer can be appended to newsletters.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.postal_address.trim().is_empty() {
+            return Err(
+                "`email_templates.footer.postal_address` must be set, CAN-SPAM requires it in every newsletter."
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Replace the placeholders of an HTML body shown to anyone rather than
+    /// sent to a recipient, e.g. in the archive: links to a subscription
+    /// point to `manage_link`, where subscribers can ask for theirs.
+    pub fn render_without_recipient(&self, html: &str, manage_link: &str) -> String {
+        let escaped_address = escape_html(&self.postal_address);
+        render(
+            html,
+            &[
+                ("status_link", manage_link),
+                ("unsubscribe_link", manage_link),
+                ("postal_address", escaped_address.as_str()),
+            ],
+        )
+    }
+
+    /// Append the footer to both bodies of an email.
+    ///
+    /// Placeholders are also replaced in the bodies, where template
+    /// fragments put them. The postal address and the unsubscribe link are
+    /// added after the footer when neither the bodies nor the footer carry
+    /// their placeholder.
+    pub fn append(
+        &self,
+        html: &str,
+        text: &str,
+        status_link: &str,
+        unsubscribe_link: &str,
+    ) -> (String, String) {
+        let escaped_address = escape_html(&self.postal_address);
+        let html_variables = [
+            ("status_link", status_link),
+            ("unsubscribe_link", unsubscribe_link),
+            ("postal_address", escaped_address.as_str()),
+        ];
+        let text_variables = [
+            ("status_link", status_link),
+            ("unsubscribe_link", unsubscribe_link),
+            ("postal_address", self.postal_address.as_str()),
+        ];
+        let html_carries = |token: &str| html.contains(token) || self.html.contains(token);
+        let text_carries = |token: &str| text.contains(token) || self.text.contains(token);
+
+        let mut footer_html = render(&self.html, &html_variables);
+        let mut missing = Vec::new();
+        if !html_carries("{postal_address}") {
+            missing.push(escaped_address.clone());
+        }
+        if !html_carries("{unsubscribe_link}") {
+            missing.push(format!(r#"<a href="{}">Unsubscribe</a>"#, unsubscribe_link));
+        }
+        if !missing.is_empty() {
+            footer_html.push_str(&format!(
+                r#"<p style="font-size: small">{}</p>"#,
+                missing.join("<br>")
+            ));
+        }
+        let mut footer_text = render(&self.text, &text_variables);
+        if !text_carries("{postal_address}") {
+            footer_text.push_str(&format!("\n{}", self.postal_address));
+        }
+        if !text_carries("{unsubscribe_link}") {
+            footer_text.push_str(&format!("\nUnsubscribe: {}", unsubscribe_link));
+        }
+        let html = format!(
+            "{}{}",
+            render(strip_article(html), &html_variables),
+            footer_html
+        );
+        (
+            make_accessible(&html, &self.lang),
+            format!("{}\n\n-- \n{}", render(text, &text_variables), footer_text),
+        )
+    }
+
+    /// Add the postal address and the unsubscribe link to an AMP for Email
+    /// body, before `</body>`, unless it carries their placeholders.
+    ///
+    /// The footer template is left out, its markup need not be valid AMP.
+    pub fn append_amp(&self, amp: &str, status_link: &str, unsubscribe_link: &str) -> String {
+        let escaped_address = escape_html(&self.postal_address);
+        let mut missing = Vec::new();
+        if !amp.contains("{postal_address}") {
+            missing.push(escaped_address.clone());
+        }
+        if !amp.contains("{unsubscribe_link}") {
+            missing.push(format!(r#"<a href="{}">Unsubscribe</a>"#, unsubscribe_link));
+        }
+        let amp = render(
+            amp,
+            &[
+                ("status_link", status_link),
+                ("unsubscribe_link", unsubscribe_link),
+                ("postal_address", escaped_address.as_str()),
+            ],
+        );
+        if missing.is_empty() {
+            return amp;
+        }
+        let footer = format!(
+            r#"<p style="font-size: small">{}</p>"#,
+            missing.join("<br>")
+        );
+        match amp.to_ascii_lowercase().rfind("</body>") {
+            Some(end) => format!("{}{}{}", &amp[..end], footer, &amp[end..]),
+            None => format!("{}{}", amp, footer),
+        }
+    }
+}
+
+fn render(template: &str, variables: &[(&str, &str)]) -> String {
+    variables
+        .iter()
+        .fold(template.to_owned(), |rendered, (name, value)| {
+            rendered.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct FeatureFlagSettings {
+    pub enabled: bool,
+    /// Share of keys (e.g. subscribers) the flag is on for, all of them when
+    /// absent.
+    #[serde(default)]
+    pub rollout_percentage: Option<u8>,
+}
+
+/// Maintenance mode, e.g. during database migrations: public endpoints
+/// answer `503` with `page` while health and admin endpoints keep working.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct MaintenanceSettings {
+    /// Whether the service starts in maintenance mode, it can be toggled at
+    /// runtime through `/admin/maintenance`.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Sent as `Retry-After`.
+    #[serde(
+        rename = "retry_after_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub retry_after: Duration,
+    /// HTML page, with a `{retry_after_minutes}` placeholder.
+    pub page: String,
+}
+
+impl MaintenanceSettings {
+    pub fn render_page(&self) -> String {
+        let minutes = self.retry_after.as_secs().div_ceil(60).max(1);
+        render(&self.page, &[("retry_after_minutes", &minutes.to_string())])
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IdempotencySettings {
+    /// Past this, a request reusing a key is processed again.
+    #[serde(
+        rename = "expire_after_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub expire_after: Duration,
+}
+
+impl Default for IdempotencySettings {
+    fn default() -> Self {
+        Self {
+            expire_after: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ShortLinkSettings {
+    /// How long short links keep redirecting, `None` meaning forever.
+    pub expire_after_days: Option<u32>,
+}
+
+impl ShortLinkSettings {
+    pub fn expires_at(&self, created_at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.expire_after_days
+            .map(|days| created_at + chrono::Duration::days(days.into()))
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TrackingSettings {
+    /// Opens and clicks happening sooner than this after an issue was
+    /// published are attributed to link prefetchers and flagged.
+    #[serde(
+        rename = "prefetch_window_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub prefetch_window: Duration,
+    /// Default tracking mode, issues can override it when published.
+    #[serde(default)]
+    pub mode: TrackingMode,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TokenGuardSettings {
+    /// Failed token lookups from one address that get it banned.
+    pub max_failures: u32,
+    #[serde(
+        rename = "failure_window_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub failure_window: Duration,
+    /// Length of the first ban, doubled for each subsequent one.
+    #[serde(
+        rename = "ban_duration_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub ban_duration: Duration,
+    #[serde(
+        rename = "max_ban_duration_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub max_ban_duration: Duration,
+}
+
+/// Signups beyond these counts within `window` are quarantined pending
+/// admin review.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SignupAnomalySettings {
+    #[serde(
+        rename = "window_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub window: Duration,
+    /// Per IPv4 /24 or IPv6 /48.
+    pub max_signups_per_network: u32,
+    /// Per email domain, large providers included.
+    pub max_signups_per_domain: u32,
+}
+
+/// Checks new subscribers go through on top of the built-in ones, e.g. to
+/// only accept corporate addresses. Values failing a rule are rejected as
+/// invalid.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct SubscriberValidationSettings {
+    /// Only addresses at these domains can subscribe, e.g. `corp.com` for an
+    /// internal newsletter, any address when empty.
+    #[serde(default)]
+    pub allowed_email_domains: Vec<String>,
+    #[serde(default)]
+    pub name: Vec<PatternRule>,
+    #[serde(default)]
+    pub email: Vec<PatternRule>,
+    #[serde(default)]
+    pub region: Vec<PatternRule>,
+    /// Checked against each tag picked.
+    #[serde(default)]
+    pub tags: Vec<PatternRule>,
+}
+
+/// A regular expression a value must, or must not, match somewhere in it:
+/// anchor it with `^` and `$` to match whole values.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternRule {
+    MustMatch(#[serde(deserialize_with = "deserialize_regex")] Regex),
+    MustNotMatch(#[serde(deserialize_with = "deserialize_regex")] Regex),
+}
+
+impl PatternRule {
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            PatternRule::MustMatch(pattern) => pattern.is_match(value),
+            PatternRule::MustNotMatch(pattern) => !pattern.is_match(value),
+        }
+    }
+}
+
+/// Invitation-only mode, e.g. for a private beta: subscribing takes an
+/// invite code, generated in batches through `/admin/invitations`.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct InvitationSettings {
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct WebPageSettings {
+    /// Let pages be fetched from loopback and private network addresses,
+    /// for local development: in production, they would let authors reach
+    /// internal services and cloud metadata endpoints.
+    #[serde(default)]
+    pub allow_private_addresses: bool,
+}
+
+/// Caps the confirmation emails an address receives, whoever signs it up.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ConfirmationEmailSettings {
+    pub max_per_recipient: u32,
+    #[serde(
+        rename = "window_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub window: Duration,
+    /// How long a confirmation link stays valid, counted from when the email
+    /// is sent.
+    #[serde(
+        rename = "token_ttl_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub token_ttl: Duration,
+}
+
+/// Admins are alerted once an issue gets reported as spam too often, as
+/// mailbox providers start filtering senders past a fraction of a percent.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ComplaintSettings {
+    /// Complaints per delivery of an issue that raise an alert, e.g. `0.001`.
+    pub alert_rate: f64,
+    /// Issues sent to fewer subscribers never raise an alert, a single
+    /// complaint would otherwise be enough.
+    pub min_deliveries: u32,
+    /// Receives a JSON notification of each alert.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(
+        rename = "webhook_timeout_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub webhook_timeout: Duration,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct ArchiveSettings {
+    /// Where the archive is hosted, e.g. `https://archive.example.com`.
+    /// The sitemap and canonical links need it and are left out otherwise.
+    pub base_url: Option<String>,
+}
+
+/// The sender's brand, applied to every page hosted for subscribers, e.g.
+/// the subscription status page.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BrandingSettings {
+    pub product_name: String,
+    /// Shown above the content of every page instead of `product_name`.
+    pub logo_url: Option<String>,
+    /// Color of links and buttons.
+    pub primary_color: String,
+    pub background_color: String,
+    pub text_color: String,
+    /// Postal address shown at the bottom of every page.
+    pub footer_address: Option<String>,
+}
+
+impl Default for BrandingSettings {
+    fn default() -> Self {
+        Self {
+            product_name: "Newsletter".into(),
+            logo_url: None,
+            primary_color: "#1a5fb4".into(),
+            background_color: "#ffffff".into(),
+            text_color: "#1f1f1f".into(),
+            footer_address: None,
+        }
+    }
+}
+
+/// Mailbox providers rate-limit senders per domain, so deliveries to each
+/// recipient domain are spread out rather than sent as fast as possible.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DeliveryThrottlingSettings {
+    /// Emails per second to a domain without a limit of its own.
+    pub default_per_second: NonZeroU32,
+    pub domains: Vec<DomainThrottlingSettings>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DomainThrottlingSettings {
+    pub domain: String,
+    pub per_second: NonZeroU32,
+}
+
+impl Default for DeliveryThrottlingSettings {
+    fn default() -> Self {
+        let per_second = |n| NonZeroU32::new(n).expect("The default limits are not zero");
+        Self {
+            default_per_second: per_second(10),
+            domains: [
+                "gmail.com",
+                "googlemail.com",
+                "outlook.com",
+                "hotmail.com",
+                "yahoo.com",
+            ]
+            .into_iter()
+            .map(|domain| DomainThrottlingSettings {
+                domain: domain.to_owned(),
+                per_second: per_second(20),
+            })
+            .collect(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EncryptionSettings {
+    /// Base64-encoded 256-bit key, e.g. injected from a KMS-backed secret
+    /// through `APP_ENCRYPTION__KEY`.
+    #[serde(deserialize_with = "deserialize_encryption_key")]
+    pub key: SecretString,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ConsentProofSettings {
+    /// Base64-encoded Ed25519 private key of 32 bytes, e.g. injected through
+    /// `APP_CONSENT_PROOFS__SIGNING_KEY`. Unlike `hmac_secret`, checking a
+    /// bundle only takes its public key.
+    #[serde(deserialize_with = "deserialize_signing_key")]
+    pub signing_key: SecretString,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct FeedSettings {
+    /// RSS or Atom feed to watch.
+    pub url: String,
+    #[serde(
+        rename = "poll_interval_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub poll_interval: Duration,
+    #[serde(
+        rename = "timeout_duration_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub timeout: Duration,
+    /// Publish new entries straight away instead of saving them as drafts.
+    #[serde(default)]
+    pub auto_publish: bool,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DigestSettings {
+    /// Identifies the digest across runs, keep it stable.
+    pub name: String,
+    /// When to send the digest, as a cron expression evaluated in UTC with a
+    /// leading seconds field, e.g. `0 0 9 * * Mon` for Mondays at 9:00.
+    #[serde(deserialize_with = "deserialize_cron_schedule")]
+    pub schedule: cron::Schedule,
+    /// Send the digest even when no issue was published since the last one.
+    #[serde(default)]
+    pub send_if_empty: bool,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AdminReportSettings {
+    /// Identifies the report across runs, keep it stable.
+    pub name: String,
+    /// When to send the report, as a cron expression evaluated in UTC with a
+    /// leading seconds field, e.g. `0 0 8 * * Mon` weekly or `0 0 8 1 * *`
+    /// monthly.
+    #[serde(deserialize_with = "deserialize_cron_schedule")]
+    pub schedule: cron::Schedule,
+    /// The admins the report is emailed to.
+    pub recipients: Vec<SubscriberEmail>,
+}
+
+/// Rules checked periodically, notifying operators through every configured
+/// channel while one of them holds.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AlertSettings {
+    #[serde(
+        rename = "check_interval_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub check_interval: Duration,
+    /// An alert raised by a rule is not raised again for this long, however
+    /// many instances are checking.
+    #[serde(
+        rename = "cool_down_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub cool_down: Duration,
+    #[serde(default)]
+    pub email_recipients: Vec<SubscriberEmail>,
+    /// Receives a JSON notification of each alert.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Slack incoming webhook posting each alert to a channel.
+    #[serde(default)]
+    pub slack_webhook_url: Option<String>,
+    #[serde(
+        rename = "webhook_timeout_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub webhook_timeout: Duration,
+    pub rules: Vec<AlertRule>,
+}
+
+/// A condition worth waking an operator up for, chosen with `kind`.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertRule {
+    /// Deliveries failing, as a share of the ones attempted over `window`.
+    DeliveryFailureRate {
+        /// e.g. `0.05` for 5%.
+        above: f64,
+        /// Fewer attempts never raise an alert, a single failure would
+        /// otherwise be enough.
+        min_attempts: u32,
+        #[serde(
+            rename = "window_millis",
+            deserialize_with = "deserialize_duration_from_millis"
+        )]
+        window: Duration,
+    },
+    /// Deliveries waiting to be sent.
+    QueueDepth { above: u32 },
+    /// Every endpoint of the default route failing its last sends in a row,
+    /// as seen by one instance.
+    ProviderUnavailable { consecutive_failures: u32 },
+}
+
+impl AlertRule {
+    /// Identifies the rule across checks and instances.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertRule::DeliveryFailureRate { .. } => "delivery_failure_rate",
+            AlertRule::QueueDepth { .. } => "queue_depth",
+            AlertRule::ProviderUnavailable { .. } => "provider_unavailable",
+        }
+    }
+
+    /// Tells apart the rules of one kind, by what they look for.
+    pub fn subject(&self) -> String {
+        match self {
+            AlertRule::DeliveryFailureRate {
+                above,
+                min_attempts,
+                window,
+            } => format!(
+                "above {} of at least {} attempts over {}s",
+                above,
+                min_attempts,
+                window.as_secs()
+            ),
+            AlertRule::QueueDepth { above } => format!("above {}", above),
+            AlertRule::ProviderUnavailable {
+                consecutive_failures,
+            } => format!("{} consecutive failures", consecutive_failures),
+        }
+    }
+}
+
+/// The Slack app sending the `/newsletter` slash command.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SlackSettings {
+    /// Signing secret of the app, authenticating its requests.
+    pub signing_secret: SecretString,
+    /// Slack user ids allowed to run the commands. Startup fails when it is
+    /// empty, rather than letting anyone in the workspace publish.
+    pub allowed_users: Vec<String>,
+}
+
+/// Where domain reputation reports are pulled from, for the deliverability
+/// dashboard.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct PostmasterSettings {
+    #[serde(
+        rename = "poll_interval_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub poll_interval: Duration,
+    #[serde(
+        rename = "timeout_duration_millis",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub timeout: Duration,
+    /// Serves the DMARC aggregate reports received, as a JSON array of the
+    /// XML documents, e.g. a bridge to the `rua` mailbox.
+    #[serde(default)]
+    pub dmarc_reports_url: Option<String>,
+    #[serde(default)]
+    pub google: Option<GooglePostmasterSettings>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct GooglePostmasterSettings {
+    #[serde(default = "default_google_postmaster_base_url")]
+    pub base_url: String,
+    /// OAuth token with the `postmaster.readonly` scope.
+    pub access_token: SecretString,
+    /// Sending domains verified in Postmaster Tools.
+    pub domains: Vec<String>,
+}
+
+fn default_google_postmaster_base_url() -> String {
+    "https://gmailpostmastertools.googleapis.com".into()
+}
+
+fn deserialize_cron_schedule<'de, D>(deserializer: D) -> Result<cron::Schedule, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let expression = String::deserialize(deserializer)?;
+    expression.parse().map_err(serde::de::Error::custom)
+}
+
+fn deserialize_encryption_key<'de, D>(deserializer: D) -> Result<SecretString, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let key = SecretString::deserialize(deserializer)?;
+    match BASE64_STANDARD.decode(key.expose_secret()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(key),
+        _ => Err(serde::de::Error::custom(
+            "the encryption key must be 32 bytes encoded in base64",
+        )),
+    }
+}
+
+fn deserialize_signing_key<'de, D>(deserializer: D) -> Result<SecretString, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let key = SecretString::deserialize(deserializer)?;
+    match BASE64_STANDARD.decode(key.expose_secret()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(key),
+        _ => Err(serde::de::Error::custom(
+            "the signing key must be 32 bytes encoded in base64",
+        )),
+    }
 }
 
 fn deserialize_duration_from_millis<'de, D>(deserializer: D) -> Result<Duration, D::Error>
@@ -57,6 +1214,24 @@ where
     Ok(Duration::from_millis(millis))
 }
 
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_duration_from_millis<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let millis = Option::<u64>::deserialize(deserializer)?;
+    Ok(millis.map(Duration::from_millis))
+}
+
 pub enum Environment {
     Local,
     Production,
@@ -82,6 +1257,36 @@ impl TryFrom<String> for Environment {
     }
 }
 
+impl Settings {
+    /// Fail on settings that deserialize but cannot be served with.
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .application
+            .hmac_secret
+            .expose_secret()
+            .trim()
+            .is_empty()
+        {
+            return Err("The application needs an `hmac_secret`.".into());
+        }
+        if self
+            .email_client
+            .webhook_token
+            .expose_secret()
+            .trim()
+            .is_empty()
+        {
+            return Err("The email client needs a `webhook_token`.".into());
+        }
+        if let Some(slack) = &self.slack
+            && slack.allowed_users.is_empty()
+        {
+            return Err("`slack.allowed_users` must list who can run the commands.".into());
+        }
+        self.email_templates.footer.validate()
+    }
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
@@ -107,7 +1312,17 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         )
         .build()?;
 
-    settings.try_deserialize::<Settings>()
+    let mut settings = settings.try_deserialize::<Settings>()?;
+    if let Some(directory) = settings.email_templates.directory.clone() {
+        read_template_files(&mut settings.email_templates, &directory).map_err(|e| {
+            config::ConfigError::Message(format!(
+                "Failed to read the email templates in {}: {}",
+                directory.display(),
+                e
+            ))
+        })?;
+    }
+    Ok(settings)
 }
 
 impl DatabaseSettings {
@@ -132,3 +1347,295 @@ impl DatabaseSettings {
             .log_statements(tracing::log::LevelFilter::Trace)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ConsentProofSettings, FooterTemplate, ProxySettings, SubscriberValidationSettings,
+        TelemetrySettings,
+    };
+
+    fn footer(html: &str, text: &str) -> FooterTemplate {
+        FooterTemplate {
+            html: html.into(),
+            text: text.into(),
+            postal_address: "1 Main Street & Co, Springfield".into(),
+            lang: "en".into(),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        let footer = footer(
+            r#"<p>{postal_address} <a href="{status_link}">Manage</a> <a href="{unsubscribe_link}">Leave</a></p>"#,
+            "{postal_address}\nManage: {status_link}\nLeave: {unsubscribe_link}",
+        );
+
+        let (html, text) = footer.append(
+            "<p>Body</p>",
+            "Body",
+            "https://example.com/s",
+            "https://example.com/u",
+        );
+
+        assert!(html.contains(
+            r#"<p>1 Main Street &amp; Co, Springfield <a href="https://example.com/s">Manage</a> <a href="https://example.com/u">Leave</a></p></div>"#
+        ));
+        assert_eq!(
+            text,
+            "Body\n\n-- \n1 Main Street & Co, Springfield\nManage: https://example.com/s\nLeave: https://example.com/u"
+        );
+    }
+
+    #[test]
+    fn the_address_and_unsubscribe_link_are_added_when_the_template_forgets_them() {
+        let footer = footer("<p>Thanks for reading</p>", "Thanks for reading");
+
+        let (html, text) = footer.append(
+            "<p>Body</p>",
+            "Body",
+            "https://example.com/s",
+            "https://example.com/u",
+        );
+
+        assert!(html.contains(
+            r#"<p style="font-size: small">1 Main Street &amp; Co, Springfield<br><a href="https://example.com/u">Unsubscribe</a></p>"#
+        ));
+        assert!(text.ends_with(
+            "Thanks for reading\n1 Main Street & Co, Springfield\nUnsubscribe: https://example.com/u"
+        ));
+    }
+
+    #[test]
+    fn placeholders_carried_by_the_bodies_are_not_added_twice() {
+        let footer = footer("<p>Thanks for reading</p>", "Thanks for reading");
+
+        let (html, text) = footer.append(
+            r#"<p>Body</p><p>{postal_address} <a href="{unsubscribe_link}">Leave</a></p>"#,
+            "Body\n{postal_address} {unsubscribe_link}",
+            "https://example.com/s",
+            "https://example.com/u",
+        );
+
+        assert!(html.contains(
+            r#"<p>1 Main Street &amp; Co, Springfield <a href="https://example.com/u">Leave</a></p><p>Thanks for reading</p></div>"#
+        ));
+        assert!(text.starts_with("Body\n1 Main Street & Co, Springfield https://example.com/u"));
+        assert!(text.ends_with("Thanks for reading"));
+    }
+
+    #[test]
+    fn amp_bodies_get_the_address_and_unsubscribe_link_before_their_end() {
+        let footer = footer("<p>Thanks for reading</p>", "Thanks for reading");
+
+        let amp = footer.append_amp(
+            "<html ⚡4email><body><p>Body</p></BODY></html>",
+            "https://example.com/s",
+            "https://example.com/u",
+        );
+
+        assert_eq!(
+            amp,
+            r#"<html ⚡4email><body><p>Body</p><p style="font-size: small">1 Main Street &amp; Co, Springfield<br><a href="https://example.com/u">Unsubscribe</a></p></BODY></html>"#
+        );
+    }
+
+    #[test]
+    fn a_blank_postal_address_is_rejected() {
+        let mut footer = footer("", "");
+        assert!(footer.validate().is_ok());
+        footer.postal_address = " ".into();
+        assert!(footer.validate().is_err());
+    }
+
+    #[test]
+    fn subscriber_validation_patterns_are_compiled_when_loading() {
+        let load = |yaml: &str| {
+            config::Config::builder()
+                .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+                .build()
+                .unwrap()
+                .try_deserialize::<SubscriberValidationSettings>()
+        };
+
+        let settings = load("email:\n  - must_match: \"@corp\\\\.com$\"\n").unwrap();
+        assert!(settings.email[0].accepts("ursula@corp.com"));
+        assert!(!settings.email[0].accepts("ursula@corpxcom"));
+        assert!(load("name:\n  - must_not_match: \"(\"\n").is_err());
+    }
+
+    #[test]
+    fn sampling_ratios_out_of_range_are_rejected_when_loading() {
+        let load = |yaml: &str| {
+            config::Config::builder()
+                .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+                .build()
+                .unwrap()
+                .try_deserialize::<TelemetrySettings>()
+        };
+
+        assert_eq!(load("sampling_ratio: 0.25").unwrap().sampling_ratio, 0.25);
+        assert_eq!(load("{}").unwrap().sampling_ratio, 1.0);
+        assert!(load("sampling_ratio: 1.5").is_err());
+        assert!(load("sampling_ratio: -0.1").is_err());
+    }
+
+    #[test]
+    fn missing_or_invalid_signing_keys_are_rejected_when_loading() {
+        let load = |yaml: &str| {
+            config::Config::builder()
+                .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+                .build()
+                .unwrap()
+                .try_deserialize::<ConsentProofSettings>()
+        };
+
+        assert!(load("signing_key: IEUlnMHrTZHBxoAydybNsdGiTAzxvtdVqd32xd3hNmY=").is_ok());
+        assert!(load("{}").is_err());
+        assert!(load("signing_key: ''").is_err());
+        assert!(load("signing_key: c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn an_invalid_proxy_url_is_reported() {
+        let proxy = |url: &str| ProxySettings {
+            url: url.into(),
+            username: None,
+            password: None,
+            no_proxy: None,
+        };
+
+        assert!(proxy("http://proxy.internal:3128").proxy().is_ok());
+        assert!(proxy("http://[proxy").proxy().is_err());
+    }
+}
+
+/// How spans are logged and where traces are exported, e.g. to Jaeger or
+/// Tempo, with OTLP over HTTP.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TelemetrySettings {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Traces endpoint of the collector, e.g.
+    /// `http://localhost:4318/v1/traces`, traces only being logged when
+    /// absent.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Name of the service in the traces, the name given to the tracing
+    /// subscriber when absent.
+    #[serde(default)]
+    pub service_name: Option<String>,
+    /// Share of the traces exported, from 0 to 1. Traces started upstream
+    /// follow the sampling decision of the caller.
+    #[serde(
+        default = "default_sampling_ratio",
+        deserialize_with = "deserialize_sampling_ratio"
+    )]
+    pub sampling_ratio: f64,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            otlp_endpoint: None,
+            service_name: None,
+            sampling_ratio: default_sampling_ratio(),
+        }
+    }
+}
+
+fn default_sampling_ratio() -> f64 {
+    1.0
+}
+
+/// Ratios out of range would silently export every trace or none.
+fn deserialize_sampling_ratio<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let ratio = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(serde::de::Error::custom(format!(
+            "`telemetry.sampling_ratio` must be between 0 and 1, got {}.",
+            ratio
+        )));
+    }
+    Ok(ratio)
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// One bunyan JSON object per line, for log aggregators.
+    #[default]
+    Json,
+    /// Multi-line and coloured, for humans reading a terminal.
+    Pretty,
+}
+
+/// Backend of the [`BlobStore`], chosen with `backend`.
+///
+/// Keys are prefixed by what they hold: `imports/` for the files of
+/// subscriber imports, `archive/` for the static archive and whatever
+/// `backup` is given with a `store:` target.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StorageSettings {
+    /// A directory of the local filesystem, created when needed.
+    Local {
+        path: PathBuf,
+    },
+    S3(S3Settings),
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        Self::Local {
+            path: PathBuf::from("storage"),
+        }
+    }
+}
+
+impl StorageSettings {
+    pub fn store(&self) -> Arc<dyn BlobStore> {
+        match self {
+            Self::Local { path } => Arc::new(LocalBlobStore::new(path.clone())),
+            Self::S3(settings) => Arc::new(S3BlobStore::new(settings.clone())),
+        }
+    }
+}
+
+/// A bucket of S3 or of an S3-compatible service.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct S3Settings {
+    /// e.g. `https://s3.eu-west-1.amazonaws.com` or the URL of a MinIO
+    /// server.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: SecretString,
+    #[serde(
+        rename = "connect_timeout_millis",
+        default = "default_s3_connect_timeout",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub connect_timeout: Duration,
+    /// Covers the whole request, up to the end of the body: large enough for
+    /// the biggest import files and backups.
+    #[serde(
+        rename = "timeout_millis",
+        default = "default_s3_timeout",
+        deserialize_with = "deserialize_duration_from_millis"
+    )]
+    pub timeout: Duration,
+}
+
+fn default_s3_connect_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_s3_timeout() -> Duration {
+    Duration::from_secs(120)
+}
@@ -0,0 +1,276 @@
+use crate::configuration::ConsentProofSettings;
+use actix_web::HttpRequest;
+use actix_web::http::header::USER_AGENT;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use chrono::{DateTime, Utc};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use secrecy::ExposeSecret;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+/// Algorithm of the proof bundle signatures.
+pub const PROOF_SIGNATURE_ALGORITHM: &str = "Ed25519";
+
+/// Where a subscriber acted from when signing up or confirming.
+#[derive(Debug)]
+pub struct RequestOrigin {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl RequestOrigin {
+    pub fn from_request(request: &HttpRequest) -> Self {
+        Self {
+            ip: request.peer_addr().map(|a| a.ip().to_string()),
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_owned),
+        }
+    }
+}
+
+/// Record the request a subscriber signed up with, `channel` being the kind
+/// of body they sent, `form` or `json`, or `import` for subscribers imported
+/// by an admin.
+#[tracing::instrument(name = "Record a signup as evidence of consent", skip(connection))]
+pub async fn record_signup(
+    connection: &mut PgConnection,
+    subscriber_id: Uuid,
+    channel: &str,
+    origin: &RequestOrigin,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (subscriber_id, signup_channel, signup_ip, signup_user_agent)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        subscriber_id,
+        channel,
+        origin.ip,
+        origin.user_agent,
+    )
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+/// Record the confirmation email sent to a subscriber, the last one when
+/// several were.
+#[tracing::instrument(
+    name = "Record a confirmation email as evidence of consent",
+    skip(pg_pool)
+)]
+pub async fn record_confirmation_email(
+    pg_pool: &PgPool,
+    subscriber_id: Uuid,
+    message_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (subscriber_id, confirmation_message_id, confirmation_sent_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (subscriber_id) DO UPDATE
+        SET confirmation_message_id = EXCLUDED.confirmation_message_id,
+            confirmation_sent_at = EXCLUDED.confirmation_sent_at
+        "#,
+        subscriber_id,
+        message_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Record the request a subscriber confirmed with. Following the link again
+/// keeps the first confirmation.
+#[tracing::instrument(name = "Record a confirmation as evidence of consent", skip(pg_pool))]
+pub async fn record_confirmation(
+    pg_pool: &PgPool,
+    subscriber_id: Uuid,
+    origin: &RequestOrigin,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            subscriber_id, confirmed_at, confirmation_ip, confirmation_user_agent
+        )
+        VALUES ($1, now(), $2, $3)
+        ON CONFLICT (subscriber_id) DO UPDATE
+        SET confirmed_at = EXCLUDED.confirmed_at,
+            confirmation_ip = EXCLUDED.confirmation_ip,
+            confirmation_user_agent = EXCLUDED.confirmation_user_agent
+        WHERE consent_records.confirmed_at IS NULL
+        "#,
+        subscriber_id,
+        origin.ip,
+        origin.user_agent,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// What we know about the double opt-in of a subscriber. Fields are `null`
+/// when it happened before we kept evidence of it.
+#[derive(serde::Serialize)]
+pub struct ConsentProof {
+    subscriber_id: Uuid,
+    email: String,
+    status: String,
+    consent: Consent,
+    confirmation: Confirmation,
+    /// SHA-256 of the confirmation tokens emailed to the subscriber, which
+    /// only the recipient of the email could present.
+    token_hashes: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Consent {
+    subscribed_at: DateTime<Utc>,
+    channel: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Confirmation {
+    email_message_id: Option<String>,
+    email_sent_at: Option<DateTime<Utc>>,
+    confirmed_at: Option<DateTime<Utc>>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+}
+
+/// The proofs of consent of a single subscriber, or of every subscriber
+/// when `subscriber_id` is `None`, oldest first.
+#[tracing::instrument(name = "Get proofs of consent", skip(pg_pool))]
+pub async fn get_consent_proofs(
+    pg_pool: &PgPool,
+    subscriber_id: Option<Uuid>,
+) -> Result<Vec<ConsentProof>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id, s.email, s.status, s.subscribed_at,
+            c.signup_channel AS "signup_channel?",
+            c.signup_ip AS "signup_ip?",
+            c.signup_user_agent AS "signup_user_agent?",
+            c.confirmation_message_id AS "confirmation_message_id?",
+            c.confirmation_sent_at AS "confirmation_sent_at?",
+            c.confirmed_at AS "confirmed_at?",
+            c.confirmation_ip AS "confirmation_ip?",
+            c.confirmation_user_agent AS "confirmation_user_agent?",
+            COALESCE(
+                (
+                    SELECT array_agg(
+                        encode(sha256(convert_to(t.subscription_token, 'UTF8')), 'hex')
+                        ORDER BY t.subscription_token
+                    )
+                    FROM subscription_tokens t
+                    WHERE t.subscriber_id = s.id
+                ),
+                '{}'
+            ) AS "token_hashes!"
+        FROM subscriptions s
+        LEFT JOIN consent_records c ON c.subscriber_id = s.id
+        WHERE $1::uuid IS NULL OR s.id = $1
+        ORDER BY s.subscribed_at, s.id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| ConsentProof {
+            subscriber_id: r.id,
+            email: r.email,
+            status: r.status,
+            consent: Consent {
+                subscribed_at: r.subscribed_at,
+                channel: r.signup_channel,
+                ip: r.signup_ip,
+                user_agent: r.signup_user_agent,
+            },
+            confirmation: Confirmation {
+                email_message_id: r.confirmation_message_id,
+                email_sent_at: r.confirmation_sent_at,
+                confirmed_at: r.confirmed_at,
+                ip: r.confirmation_ip,
+                user_agent: r.confirmation_user_agent,
+            },
+            token_hashes: r.token_hashes,
+        })
+        .collect())
+}
+
+#[derive(serde::Serialize)]
+struct ProofBundle {
+    generated_at: DateTime<Utc>,
+    subscribers: Vec<ConsentProof>,
+}
+
+/// Signs the proof bundles with an Ed25519 key: a court or a mailbox
+/// provider can check a bundle against the published public key, without
+/// being able to sign one.
+pub struct ProofSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl ProofSigner {
+    pub fn new(settings: &ConsentProofSettings) -> Self {
+        let seed = BASE64_STANDARD
+            .decode(settings.signing_key.expose_secret())
+            .expect("The signing key is checked when loading the configuration");
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .expect("The signing key is checked when loading the configuration");
+        Self { key_pair }
+    }
+
+    /// Base64-encoded public key the bundles are checked against.
+    pub fn public_key(&self) -> String {
+        BASE64_STANDARD.encode(self.key_pair.public_key().as_ref())
+    }
+
+    fn sign(&self, document: &str) -> String {
+        BASE64_STANDARD.encode(self.key_pair.sign(document.as_bytes()).as_ref())
+    }
+}
+
+/// Proofs of consent, signed so that they cannot be altered once exported.
+///
+/// `signature` is the base64-encoded Ed25519 signature of the compact JSON
+/// serialization of `bundle` with its keys sorted, made with the key whose
+/// public half is `public_key`.
+#[derive(serde::Serialize)]
+pub struct SignedProofBundle {
+    bundle: serde_json::Value,
+    algorithm: &'static str,
+    public_key: String,
+    signature: String,
+}
+
+impl SignedProofBundle {
+    pub fn sign(
+        subscribers: Vec<ConsentProof>,
+        generated_at: DateTime<Utc>,
+        proof_signer: &ProofSigner,
+    ) -> Result<Self, serde_json::Error> {
+        // Maps of `serde_json::Value` keep their keys sorted, which makes the
+        // signed serialization reproducible from the exported JSON.
+        let bundle = serde_json::to_value(ProofBundle {
+            generated_at,
+            subscribers,
+        })?;
+        let signature = proof_signer.sign(&bundle.to_string());
+        Ok(Self {
+            bundle,
+            algorithm: PROOF_SIGNATURE_ALGORITHM,
+            public_key: proof_signer.public_key(),
+            signature,
+        })
+    }
+}
@@ -0,0 +1,1214 @@
+use crate::EmailClient;
+use crate::configuration::Settings;
+use crate::consent::record_confirmation_email;
+use crate::domain::{
+    NewSubscriber, Segment, SubscriberEmail, SubscriberName, SubscriberRegion, SubscriptionToken,
+};
+use crate::email_client::{Attachment, EmailClientError, EmailHeader, EmailInfo, ExtraParts};
+use crate::encryption::FieldCipher;
+use crate::feature_flags::{FeatureFlags, FlagSet, OPEN_TRACKING, PAUSE_DELIVERIES};
+use crate::jobs::Job;
+use crate::publishing::{StoredIssue, SubscriberFooter, get_stored_issue};
+use crate::routes::subscriptions::{
+    SubscriberLinks, create_confirmation_link, extend_token, send_confirm_email,
+};
+use crate::signing::UrlSigner;
+use crate::subscriber_search::{SubscriberFilter, TagFilter};
+use crate::templates::EmailTemplates;
+use crate::throttling::DeliveryThrottle;
+use crate::tracking::{RecipientTracking, TrackingMode};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, stream};
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// How long a claimed delivery stays reserved to its worker, unless the
+/// worker renews the lease.
+const LEASE: Duration = Duration::from_secs(60);
+/// Leases are renewed well before they run out, so that a slow email
+/// provider does not let another worker send the same email.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+/// How often every instance looks for deliveries left behind.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Deliveries claimed at once, unless more emails can be in flight.
+const BATCH_SIZE: i64 = 10;
+/// Deliveries failing this many times stay in the queue for an admin to
+/// look at.
+const MAX_ATTEMPTS: i32 = 5;
+/// Queued confirmation emails failing to send are retried after this delay,
+/// growing with each attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Name of the [`DeliveryWorker`] job, triggered when an issue is queued.
+pub const DELIVERY_JOB: &str = "deliveries";
+
+/// Sends the deliveries no instance is working on: left behind by a worker
+/// that crashed, released after a failed attempt, or queued while deliveries
+/// were paused, and relays the confirmation emails left in the outbox.
+///
+/// Every instance runs one. Deliveries are claimed with `SKIP LOCKED` and
+/// held under a lease, so each attempt is made by a single worker however
+/// many instances are running.
+pub struct DeliveryWorker {
+    footer: SubscriberFooter,
+    pg_pool: PgPool,
+    cipher: FieldCipher,
+    feature_flags: FeatureFlags,
+    email_client: Arc<EmailClient>,
+    throttle: Arc<DeliveryThrottle>,
+    base_url: String,
+    /// Base of the links of newsletter issues, see
+    /// [`ApplicationSettings::link_base_url`](crate::configuration::ApplicationSettings::link_base_url).
+    link_base_url: String,
+    email_templates: EmailTemplates,
+    url_signer: UrlSigner,
+    confirmation_token_ttl: Duration,
+    job: Arc<Job>,
+}
+
+impl DeliveryWorker {
+    pub fn build(
+        configuration: &Settings,
+        pg_pool: PgPool,
+        email_client: Arc<EmailClient>,
+        throttle: Arc<DeliveryThrottle>,
+    ) -> Self {
+        Self {
+            footer: SubscriberFooter::new(
+                EmailTemplates::new(configuration.email_templates.clone()),
+                UrlSigner::new(configuration.application.hmac_secret.clone()),
+            ),
+            pg_pool,
+            cipher: FieldCipher::new(configuration.encryption.as_ref()),
+            feature_flags: FeatureFlags::new(configuration.feature_flags.clone()),
+            email_client,
+            throttle,
+            base_url: configuration.application.base_url.clone(),
+            link_base_url: configuration.application.link_base_url().to_owned(),
+            email_templates: EmailTemplates::new(configuration.email_templates.clone()),
+            url_signer: UrlSigner::new(configuration.application.hmac_secret.clone()),
+            confirmation_token_ttl: configuration.confirmation_emails.token_ttl,
+            job: Job::new(DELIVERY_JOB),
+        }
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(POLL_INTERVAL, None, &shutdown, || async {
+                self.deliver_pending().await.map(|_| ())
+            })
+            .await
+    }
+
+    /// Send every delivery that can be claimed and return how many emails
+    /// went out, or an error once every issue was attempted if any of its
+    /// deliveries failed.
+    #[tracing::instrument(name = "Deliver pending newsletter deliveries", skip(self))]
+    pub async fn deliver_pending(&self) -> Result<usize, anyhow::Error> {
+        let flags = self
+            .feature_flags
+            .load(&self.pg_pool)
+            .await
+            .context("Failed to load the feature flags")?;
+        if flags.is_enabled(PAUSE_DELIVERIES) {
+            tracing::info!("Deliveries are paused, leaving them queued");
+            return Ok(0);
+        }
+        let sent = self.relay_email_outbox().await?;
+        let mut report = DeliveryReport::default();
+        let newsletter_issue_ids = get_issues_with_claimable_deliveries(&self.pg_pool)
+            .await
+            .context("Failed to look for pending deliveries")?;
+        for newsletter_issue_id in newsletter_issue_ids {
+            let issue = get_stored_issue(&self.pg_pool, &self.cipher, newsletter_issue_id)
+                .await
+                .context("Failed to retrieve the issue of pending deliveries")?;
+            report.merge(
+                deliver_queued(
+                    IssueDelivery {
+                        pg_pool: &self.pg_pool,
+                        email_client: &self.email_client,
+                        throttle: &self.throttle,
+                        base_url: &self.link_base_url,
+                        footer: &self.footer,
+                    },
+                    &flags,
+                    &issue,
+                )
+                .await?,
+            );
+        }
+        Ok(sent + report.into_result()?)
+    }
+
+    /// Send the confirmation emails left in the outbox: written while
+    /// deliveries were paused, after a failed attempt, or by a request that
+    /// did not get to send them.
+    ///
+    /// Each one stays locked, and in the outbox, until it is sent. Failures are
+    /// recorded and retried later, up to `MAX_ATTEMPTS` times.
+    #[tracing::instrument(name = "Relay the email outbox", skip(self))]
+    async fn relay_email_outbox(&self) -> Result<usize, anyhow::Error> {
+        let mut sent = 0;
+        loop {
+            let mut transaction = self
+                .pg_pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?;
+            let Some(r) = sqlx::query!(
+                r#"
+                SELECT q.subscriber_id, q.subscription_token, s.email, s.name, s.region
+                FROM email_outbox q
+                JOIN subscriptions s ON s.id = q.subscriber_id
+                WHERE q.next_attempt_at <= now() AND q.attempts < $1
+                ORDER BY q.enqueued_at
+                LIMIT 1
+                FOR UPDATE OF q SKIP LOCKED
+                "#,
+                MAX_ATTEMPTS,
+            )
+            .fetch_optional(&mut *transaction)
+            .await
+            .context("Failed to claim a queued confirmation email")?
+            else {
+                return Ok(sent);
+            };
+            let subscriber = parse_subscriber(&self.cipher, r.email, r.name, r.region);
+            match subscriber {
+                Ok(subscriber) => {
+                    let token = SubscriptionToken::from(r.subscription_token);
+                    // The link must stay valid for as long as if it had not
+                    // been queued.
+                    extend_token(&mut transaction, &token, self.confirmation_token_ttl)
+                        .await
+                        .context("Failed to extend the token of a queued confirmation email")?;
+                    let confirmation_link = create_confirmation_link(&self.base_url, &token)
+                        .context("Failed to create a confirmation link for a queued email")?;
+                    let links = SubscriberLinks {
+                        base_url: &self.base_url,
+                        link_base_url: &self.link_base_url,
+                        url_signer: &self.url_signer,
+                    };
+                    let message_id = match send_confirm_email(
+                        &self.email_client,
+                        &*self.email_templates.current().await,
+                        &links,
+                        r.subscriber_id,
+                        subscriber,
+                        confirmation_link,
+                    )
+                    .await
+                    {
+                        Ok(message_id) => message_id,
+                        // Rolling back leaves the email queued as it was.
+                        Err(EmailClientError::Paused | EmailClientError::PauseUnknown(_)) => {
+                            tracing::info!(
+                                "Deliveries were paused, leaving the confirmation emails queued"
+                            );
+                            return Ok(sent);
+                        }
+                        Err(e) => {
+                            tracing::warn!(
+                                error.cause_chain = ?e,
+                                "Failed to send a queued confirmation email, it will be retried",
+                            );
+                            record_confirmation_email_failure(
+                                &mut transaction,
+                                r.subscriber_id,
+                                &e.to_string(),
+                            )
+                            .await
+                            .context("Failed to record a confirmation email failure")?;
+                            transaction.commit().await.context(
+                                "Failed to commit SQL transaction to reschedule a confirmation email",
+                            )?;
+                            continue;
+                        }
+                    };
+                    record_confirmation_email(
+                        &self.pg_pool,
+                        r.subscriber_id,
+                        message_id.as_deref(),
+                    )
+                    .await
+                    .context("Failed to record a queued confirmation email")?;
+                    sent += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Dropping a queued confirmation email. The stored contact details are invalid",
+                    );
+                }
+            }
+            sqlx::query!(
+                "DELETE FROM email_outbox WHERE subscriber_id = $1",
+                r.subscriber_id,
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to dequeue a confirmation email")?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to dequeue a confirmation email")?;
+        }
+    }
+}
+
+#[tracing::instrument(name = "Record a confirmation email failure", skip(transaction))]
+async fn record_confirmation_email_failure(
+    transaction: &mut PgConnection,
+    subscriber_id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET attempts = attempts + 1,
+            last_error = $2,
+            next_attempt_at = now() + make_interval(secs => $3::float8 * (attempts + 1))
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+        error,
+        RETRY_DELAY.as_secs_f64(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+fn parse_subscriber(
+    cipher: &FieldCipher,
+    email: String,
+    name: String,
+    region: Option<String>,
+) -> Result<NewSubscriber, anyhow::Error> {
+    Ok(NewSubscriber {
+        email: SubscriberEmail::try_from(email).map_err(anyhow::Error::msg)?,
+        name: SubscriberName::try_from(cipher.decrypt(name)?).map_err(anyhow::Error::msg)?,
+        region: region
+            .map(SubscriberRegion::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)?,
+        tags: Vec::new(),
+    })
+}
+
+/// Emails held back while deliveries are paused, or not sent yet.
+#[derive(serde::Serialize, Debug)]
+pub struct QueuedEmails {
+    pub deliveries: i64,
+    pub confirmation_emails: i64,
+}
+
+#[tracing::instrument(name = "Count queued emails", skip(pg_pool))]
+pub async fn count_queued_emails(pg_pool: &PgPool) -> Result<QueuedEmails, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM delivery_tasks) AS "deliveries!",
+            (SELECT COUNT(*) FROM email_outbox) AS "confirmation_emails!"
+        "#,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    Ok(QueuedEmails {
+        deliveries: r.deliveries,
+        confirmation_emails: r.confirmation_emails,
+    })
+}
+
+/// Queue a delivery of an issue to every confirmed subscriber in `segment`.
+///
+/// The recipients are recorded as the audience of the issue, along with
+/// the segment they were resolved from, for its reports to be unaffected
+/// by later changes to the subscribers or to the segment.
+#[tracing::instrument(name = "Enqueue newsletter deliveries", skip(connection))]
+pub async fn enqueue_deliveries(
+    connection: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    segment: &Segment,
+) -> Result<u64, anyhow::Error> {
+    let filter = segment
+        .filter
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .context("Failed to serialize the filter of a segment")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_audiences (
+            newsletter_issue_id, segment_id, filter, engagement, tags, evaluated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        segment.segment_id,
+        filter,
+        segment.engagement.map(|e| e.as_str()),
+        &segment.tags,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to record the audience of a newsletter issue")?;
+    let mut query = QueryBuilder::new(
+        "WITH recipients AS (\
+        INSERT INTO newsletter_issue_recipients (newsletter_issue_id, subscriber_id) SELECT ",
+    );
+    query.push_bind(newsletter_issue_id).push(", s.id");
+    push_recipients(&mut query, segment);
+    query.push(
+        " ON CONFLICT DO NOTHING RETURNING newsletter_issue_id, subscriber_id) \
+        INSERT INTO delivery_tasks (newsletter_issue_id, subscriber_id, enqueued_at) \
+        SELECT newsletter_issue_id, subscriber_id, now() FROM recipients \
+        ON CONFLICT DO NOTHING",
+    );
+    let enqueued = query
+        .build()
+        .execute(connection)
+        .await
+        .context("Failed to insert the deliveries of a newsletter issue")?
+        .rows_affected();
+    Ok(enqueued)
+}
+
+/// Who an issue was sent to, as resolved when its deliveries were queued.
+#[derive(serde::Serialize)]
+pub struct IssueAudience {
+    /// Saved segment the issue was sent to, which may since have been
+    /// changed or deleted.
+    pub segment_id: Option<Uuid>,
+    pub filter: Option<SubscriberFilter>,
+    pub engagement: Option<String>,
+    pub tags: Vec<String>,
+    pub evaluated_at: DateTime<Utc>,
+    pub recipients: i64,
+    pub delivered: i64,
+    pub pending: i64,
+}
+
+/// The audience of an issue, `None` for issues queued before audiences
+/// were recorded.
+#[tracing::instrument(name = "Get the audience of a newsletter issue", skip(pg_pool))]
+pub async fn get_issue_audience(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueAudience>, anyhow::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT
+            a.segment_id,
+            a.filter,
+            a.engagement,
+            a.tags,
+            a.evaluated_at,
+            (SELECT COUNT(*) FROM newsletter_issue_recipients r
+             WHERE r.newsletter_issue_id = a.newsletter_issue_id) AS "recipients!",
+            (SELECT COUNT(*) FROM newsletter_deliveries d
+             WHERE d.newsletter_issue_id = a.newsletter_issue_id) AS "delivered!",
+            (SELECT COUNT(*) FROM delivery_tasks t
+             WHERE t.newsletter_issue_id = a.newsletter_issue_id) AS "pending!"
+        FROM newsletter_issue_audiences a
+        WHERE a.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let filter = r
+        .filter
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()
+        .context("Failed to parse the filter of an issue audience")?;
+    Ok(Some(IssueAudience {
+        segment_id: r.segment_id,
+        filter,
+        engagement: r.engagement,
+        tags: r.tags,
+        evaluated_at: r.evaluated_at,
+        recipients: r.recipients,
+        delivered: r.delivered,
+        pending: r.pending,
+    }))
+}
+
+/// Failed deliveries listed in an [`IssueDeliveryReport`], those attempted the
+/// most first.
+const MAX_REPORTED_FAILURES: i64 = 100;
+
+/// What became of the deliveries of an issue so far.
+#[derive(serde::Serialize, Debug)]
+pub struct IssueDeliveryReport {
+    /// Deliveries not attempted yet.
+    pub queued: i64,
+    pub sent: i64,
+    /// Recipients dropped from the queue without being emailed, e.g. after
+    /// they unsubscribed or were suppressed.
+    pub skipped: i64,
+    /// Deliveries whose last attempt failed. They stay queued, to be retried
+    /// up to `MAX_ATTEMPTS` times.
+    pub failed: i64,
+    pub failures: Vec<FailedDelivery>,
+}
+
+impl IssueDeliveryReport {
+    /// The report of an issue whose deliveries were just queued.
+    pub fn queued(deliveries: u64) -> Self {
+        Self {
+            queued: deliveries as i64,
+            sent: 0,
+            skipped: 0,
+            failed: 0,
+            failures: Vec::new(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct FailedDelivery {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub attempts: i32,
+    pub error: String,
+}
+
+/// The deliveries of an issue, `None` if there is no such issue.
+///
+/// Skipped deliveries are only counted for issues whose audience was
+/// recorded when they were queued.
+#[tracing::instrument(name = "Get the delivery report of a newsletter issue", skip(pg_pool))]
+pub async fn get_delivery_report(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueDeliveryReport>, sqlx::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM newsletter_issue_recipients r
+             WHERE r.newsletter_issue_id = i.newsletter_issue_id) AS "recipients!",
+            (SELECT COUNT(*) FROM newsletter_deliveries d
+             WHERE d.newsletter_issue_id = i.newsletter_issue_id) AS "sent!",
+            (SELECT COUNT(*) FROM delivery_tasks t
+             WHERE t.newsletter_issue_id = i.newsletter_issue_id
+                AND t.last_error IS NULL) AS "queued!",
+            (SELECT COUNT(*) FROM delivery_tasks t
+             WHERE t.newsletter_issue_id = i.newsletter_issue_id
+                AND t.last_error IS NOT NULL) AS "failed!"
+        FROM newsletter_issues i
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let failures = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT t.subscriber_id, s.email, t.attempts, t.last_error AS "error!"
+        FROM delivery_tasks t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.newsletter_issue_id = $1 AND t.last_error IS NOT NULL
+        ORDER BY t.attempts DESC, s.email
+        LIMIT $2
+        "#,
+        newsletter_issue_id,
+        MAX_REPORTED_FAILURES,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    Ok(Some(IssueDeliveryReport {
+        queued: r.queued,
+        sent: r.sent,
+        skipped: (r.recipients - r.sent - r.queued - r.failed).max(0),
+        failed: r.failed,
+        failures,
+    }))
+}
+
+/// How many subscribers an issue sent to `segment` now would be delivered
+/// to.
+#[tracing::instrument(name = "Count the recipients of a segment", skip(pg_pool))]
+pub async fn count_recipients(pg_pool: &PgPool, segment: &Segment) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT COUNT(*)");
+    push_recipients(&mut query, segment);
+    query.build_query_scalar().fetch_one(pg_pool).await
+}
+
+/// Append the `FROM` and `WHERE` clauses selecting, as `s`, the confirmed
+/// subscribers in `segment` whose address is not suppressed.
+fn push_recipients(query: &mut QueryBuilder<'_, Postgres>, segment: &Segment) {
+    query.push(" FROM subscriptions s JOIN subscriber_engagement e ON e.subscriber_id = s.id");
+    match &segment.filter {
+        Some(filter) => filter.push_where(query),
+        None => {
+            query.push(" WHERE TRUE");
+        }
+    }
+    query.push(" AND s.status = 'confirmed'");
+    if let Some(engagement) = segment.engagement {
+        query
+            .push(" AND e.inactive_90d = ")
+            .push_bind(engagement.is_inactive());
+    }
+    TagFilter {
+        all: Vec::new(),
+        any: segment.tags.clone(),
+    }
+    .push_conditions(query);
+    query.push(" AND NOT EXISTS (SELECT 1 FROM suppressions x WHERE x.email = lower(s.email))");
+}
+
+/// What became of the deliveries of an issue attempted by a worker.
+#[derive(Default)]
+pub struct DeliveryReport {
+    pub sent: usize,
+    pub skipped: usize,
+    /// Deliveries put back in the queue as they were, deliveries having been
+    /// paused while they were claimed.
+    pub held: usize,
+    /// Recipients the email could not be sent to, with the error. Their
+    /// deliveries stay queued, for a later run to retry them.
+    pub failed: Vec<(Uuid, anyhow::Error)>,
+}
+
+impl DeliveryReport {
+    /// How many emails went out, or the first error if any delivery failed.
+    pub fn into_result(self) -> Result<usize, anyhow::Error> {
+        let failed = self.failed.len();
+        match self.failed.into_iter().next() {
+            Some((_, e)) => Err(e.context(format!(
+                "{} newsletter deliveries failed, they will be retried",
+                failed
+            ))),
+            None => Ok(self.sent),
+        }
+    }
+
+    fn merge(&mut self, other: DeliveryReport) {
+        self.sent += other.sent;
+        self.skipped += other.skipped;
+        self.held += other.held;
+        self.failed.extend(other.failed);
+    }
+}
+
+/// What sending the deliveries of an issue takes, shared by the workers and
+/// the requests publishing one.
+#[derive(Clone, Copy)]
+pub struct IssueDelivery<'a> {
+    pub pg_pool: &'a PgPool,
+    pub email_client: &'a EmailClient,
+    pub throttle: &'a DeliveryThrottle,
+    /// Where the links embedded in the emails point to.
+    pub base_url: &'a str,
+    pub footer: &'a SubscriberFooter,
+}
+
+/// Claim and send the queued deliveries of `issue` until none is left, and
+/// report what became of each of them. Its internal copies are sent first,
+/// without being reported.
+///
+/// Up to [`EmailClient::max_concurrent_sends`] emails are in flight at once.
+/// A failed delivery is released for any worker to retry it, without holding
+/// up the others of its batch, and no further batch is claimed. Neither is
+/// one claimed once deliveries are paused.
+#[tracing::instrument(
+    name = "Deliver queued newsletter deliveries",
+    skip_all,
+    fields(newsletter_issue_id=%issue.newsletter_issue_id, claimed_by=tracing::field::Empty)
+)]
+pub async fn deliver_queued(
+    delivery: IssueDelivery<'_>,
+    flags: &FlagSet,
+    issue: &StoredIssue,
+) -> Result<DeliveryReport, anyhow::Error> {
+    let IssueDelivery {
+        pg_pool,
+        email_client,
+        throttle,
+        base_url,
+        footer,
+    } = delivery;
+    send_internal_copies(pg_pool, email_client, base_url, issue).await?;
+    let claimed_by = Uuid::new_v4();
+    tracing::Span::current().record("claimed_by", tracing::field::display(&claimed_by));
+    let _heartbeat = Heartbeat::start(pg_pool.clone(), claimed_by);
+    let concurrency = email_client.max_concurrent_sends();
+    let batch_size = BATCH_SIZE.max(concurrency as i64);
+    let mut report = DeliveryReport::default();
+    loop {
+        if email_client
+            .is_paused()
+            .await
+            .context("Failed to check whether deliveries are paused")?
+        {
+            tracing::info!("Deliveries are paused, leaving them queued");
+            return Ok(report);
+        }
+        let deliveries =
+            claim_deliveries(pg_pool, claimed_by, issue.newsletter_issue_id, batch_size)
+                .await
+                .context("Failed to claim newsletter deliveries")?;
+        if deliveries.is_empty() {
+            return Ok(report);
+        }
+        let mut outcomes = stream::iter(deliveries)
+            .map(|delivery| async move {
+                let outcome = send(
+                    email_client,
+                    throttle,
+                    flags,
+                    base_url,
+                    footer,
+                    issue,
+                    &delivery,
+                )
+                .await;
+                record_outcome(pg_pool, claimed_by, issue, &delivery, outcome).await
+            })
+            .buffer_unordered(concurrency);
+        // Every send of the batch is waited for, even once recording one
+        // failed: dropping the others would leave them sent but still queued.
+        let mut batch = DeliveryReport::default();
+        let mut recording_error = None;
+        while let Some(outcome) = outcomes.next().await {
+            match outcome {
+                Ok(outcome) => batch.merge(outcome),
+                Err(e) if recording_error.is_some() => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        "Failed to record the outcome of a newsletter delivery",
+                    );
+                }
+                Err(e) => recording_error = Some(e),
+            }
+        }
+        if let Some(e) = recording_error {
+            return Err(e);
+        }
+        let stop = !batch.failed.is_empty() || batch.held > 0;
+        report.merge(batch);
+        if stop {
+            return Ok(report);
+        }
+    }
+}
+
+/// Complete a delivery, or release it when sending failed, and report it.
+///
+/// A delivery held back by a pause goes back to the queue without counting
+/// as an attempt, however many times deliveries are paused.
+async fn record_outcome(
+    pg_pool: &PgPool,
+    claimed_by: Uuid,
+    issue: &StoredIssue,
+    delivery: &ClaimedDelivery,
+    outcome: Result<DeliveryOutcome, anyhow::Error>,
+) -> Result<DeliveryReport, anyhow::Error> {
+    let mut report = DeliveryReport::default();
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                subscriber_id = %delivery.subscriber_id,
+                "Failed to send a newsletter issue, the delivery will be retried",
+            );
+            release_delivery(pg_pool, claimed_by, delivery.subscriber_id, &e)
+                .await
+                .context("Failed to release a newsletter delivery")?;
+            report.failed.push((delivery.subscriber_id, e));
+            return Ok(report);
+        }
+    };
+    if let DeliveryOutcome::Held = outcome {
+        return_delivery(pg_pool, claimed_by, delivery.subscriber_id)
+            .await
+            .context("Failed to return a newsletter delivery to the queue")?;
+        report.held += 1;
+        return Ok(report);
+    }
+    let completed = complete_delivery(
+        pg_pool,
+        claimed_by,
+        issue.newsletter_issue_id,
+        delivery,
+        &outcome,
+    )
+    .await
+    .context("Failed to record a newsletter delivery")?;
+    if !completed {
+        tracing::warn!(
+            subscriber_id = %delivery.subscriber_id,
+            "The lease of a delivery ran out while sending it, it was claimed again",
+        );
+    }
+    match outcome {
+        DeliveryOutcome::Sent { .. } => report.sent += 1,
+        DeliveryOutcome::Skipped => report.skipped += 1,
+        DeliveryOutcome::Held => unreachable!("Held deliveries are returned to the queue"),
+    }
+    Ok(report)
+}
+
+struct ClaimedDelivery {
+    subscriber_id: Uuid,
+    attempts: i32,
+    email: String,
+    region: Option<String>,
+    /// The recipient opted out of tracking, their delivery is still counted.
+    do_not_track: bool,
+}
+
+/// What became of a claimed delivery, retrying a skipped one would not help.
+enum DeliveryOutcome {
+    Sent {
+        provider_message_id: Option<String>,
+    },
+    Skipped,
+    /// Not sent as deliveries were paused, or whether they were could not
+    /// be told.
+    Held,
+}
+
+/// Email `issue` to the recipient of `delivery`.
+async fn send(
+    email_client: &EmailClient,
+    throttle: &DeliveryThrottle,
+    flags: &FlagSet,
+    base_url: &str,
+    footer: &SubscriberFooter,
+    issue: &StoredIssue,
+    delivery: &ClaimedDelivery,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let recipient = SubscriberEmail::try_from(delivery.email.clone())
+        .map_err(|e| anyhow::anyhow!(e))
+        .and_then(|email| {
+            let region = delivery
+                .region
+                .clone()
+                .map(SubscriberRegion::parse)
+                .transpose()
+                .map_err(|e| anyhow::anyhow!(e))?;
+            Ok((email, region))
+        });
+    let (email, region) = match recipient {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::warn!(
+                // We record the error chain as a structured field
+                // on the log record.
+                error.cause_chain = ?e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+            return Ok(DeliveryOutcome::Skipped);
+        }
+    };
+    throttle.wait_for(email.domain()).await;
+    let tracking = if delivery.do_not_track {
+        RecipientTracking::untracked(
+            base_url,
+            issue.newsletter_issue_id,
+            &issue.link_destinations,
+        )
+    } else {
+        RecipientTracking {
+            base_url,
+            newsletter_issue_id: issue.newsletter_issue_id,
+            subscriber_id: (issue.tracking_mode == TrackingMode::Detailed)
+                .then_some(delivery.subscriber_id),
+            open_pixel: flags.is_enabled_for(OPEN_TRACKING, &delivery.subscriber_id.to_string()),
+            link_destinations: None,
+        }
+    };
+    let (html, text) = footer
+        .append(
+            base_url,
+            delivery.subscriber_id,
+            &issue.html_content,
+            &issue.text_content,
+        )
+        .await;
+    let amp = match issue.amp_content.as_deref() {
+        Some(amp) => Some(
+            footer
+                .append_amp(base_url, delivery.subscriber_id, amp)
+                .await,
+        ),
+        None => None,
+    };
+    let attachments = issue_attachments(issue);
+    let headers = footer.unsubscribe_headers(base_url, delivery.subscriber_id);
+    let outcome = email_client
+        .send_email_with_extras_in_region(
+            region.as_ref(),
+            &email,
+            &issue.title,
+            &tracking.html(&html),
+            &tracking.text(&text),
+            &issue_extras(issue, amp.as_deref(), &attachments, &headers),
+        )
+        .await;
+    match outcome {
+        Ok(provider_message_id) => Ok(DeliveryOutcome::Sent {
+            provider_message_id,
+        }),
+        Err(e @ EmailClientError::InvalidRecipient(_)) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Skipping a confirmed subscriber. The email provider rejected their address",
+            );
+            Ok(DeliveryOutcome::Skipped)
+        }
+        Err(EmailClientError::Paused | EmailClientError::PauseUnknown(_)) => {
+            Ok(DeliveryOutcome::Held)
+        }
+        Err(e) => {
+            Err(anyhow::Error::new(e)
+                .context(format!("Failed to send newsletter issue to {}", email)))
+        }
+    }
+}
+
+/// The calendar invite of the event the issue announces, if any.
+fn issue_attachments(issue: &StoredIssue) -> Vec<Attachment> {
+    issue
+        .event
+        .iter()
+        .map(|event| {
+            let ics = event.to_ics(issue.newsletter_issue_id, Utc::now());
+            Attachment::new("event.ics", "text/calendar", ics.as_bytes())
+        })
+        .collect()
+}
+
+fn issue_extras<'a>(
+    issue: &'a StoredIssue,
+    amp: Option<&'a str>,
+    attachments: &'a [Attachment],
+    headers: &'a [EmailHeader],
+) -> ExtraParts<'a> {
+    ExtraParts {
+        amp,
+        attachments,
+        from: issue.sender.as_ref().map(|sender| EmailInfo {
+            email: &sender.email,
+            name: &sender.name,
+        }),
+        headers,
+    }
+}
+
+/// Email `issue` to its internal recipients, each of them once: a copy
+/// failing to be sent is not retried.
+///
+/// Copies are the issue as subscribers receive it, less their footer, with
+/// links going straight to their destination and no open pixel, so that
+/// they are left out of the stats of the issue.
+#[tracing::instrument(
+    name = "Send internal copies",
+    skip_all,
+    fields(newsletter_issue_id=%issue.newsletter_issue_id)
+)]
+async fn send_internal_copies(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    issue: &StoredIssue,
+) -> Result<(), anyhow::Error> {
+    let emails = sqlx::query_scalar!(
+        r#"
+        UPDATE internal_copies
+        SET sent_at = now()
+        WHERE newsletter_issue_id = $1 AND sent_at IS NULL
+        RETURNING email
+        "#,
+        issue.newsletter_issue_id,
+    )
+    .fetch_all(pg_pool)
+    .await
+    .context("Failed to claim the internal copies of a newsletter issue")?;
+    if emails.is_empty() {
+        return Ok(());
+    }
+    let tracking = RecipientTracking::untracked(
+        base_url,
+        issue.newsletter_issue_id,
+        &issue.link_destinations,
+    );
+    let subject = format!("[Internal copy] {}", issue.title);
+    let html = format!(
+        "<p><em>Internal copy: not tracked, and without the footer of subscribers.</em></p>{}",
+        tracking.html(&issue.html_content)
+    );
+    let text = format!(
+        "Internal copy: not tracked, and without the footer of subscribers.\n\n{}",
+        tracking.text(&issue.text_content)
+    );
+    let attachments = issue_attachments(issue);
+    let extras = issue_extras(issue, issue.amp_content.as_deref(), &attachments, &[]);
+    for email in emails {
+        let outcome = match SubscriberEmail::try_from(email.clone()) {
+            Ok(recipient) => email_client
+                .send_email_with_extras_in_region(None, &recipient, &subject, &html, &text, &extras)
+                .await
+                .map_err(anyhow::Error::new),
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        if let Err(e) = outcome {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                %email,
+                "Failed to send an internal copy of a newsletter issue",
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Renews the leases held by a worker until dropped.
+struct Heartbeat(JoinHandle<()>);
+
+impl Heartbeat {
+    fn start(pg_pool: PgPool, claimed_by: Uuid) -> Self {
+        Self(tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = renew_leases(&pg_pool, claimed_by).await {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Failed to renew the leases of newsletter deliveries",
+                    );
+                }
+            }
+        }))
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Issues with deliveries to claim, or internal copies left to send once
+/// the issue is out.
+#[tracing::instrument(name = "Get issues with claimable deliveries", skip(pg_pool))]
+async fn get_issues_with_claimable_deliveries(pg_pool: &PgPool) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT t.newsletter_issue_id AS "newsletter_issue_id!"
+        FROM delivery_tasks t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.attempts < $1
+            AND (t.lease_expires_at IS NULL OR t.lease_expires_at < now())
+            AND s.status = 'confirmed'
+        UNION
+        SELECT c.newsletter_issue_id
+        FROM internal_copies c
+        JOIN newsletter_issues i ON i.newsletter_issue_id = c.newsletter_issue_id
+        WHERE c.sent_at IS NULL AND i.published_at <= now()
+        "#,
+        MAX_ATTEMPTS,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+/// Lease a batch of deliveries nobody holds, skipping the rows another
+/// worker is claiming at the same time, and the recipients who are no
+/// longer confirmed subscribers.
+#[tracing::instrument(name = "Claim newsletter deliveries", skip(pg_pool))]
+async fn claim_deliveries(
+    pg_pool: &PgPool,
+    claimed_by: Uuid,
+    newsletter_issue_id: Uuid,
+    batch_size: i64,
+) -> Result<Vec<ClaimedDelivery>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        WITH claimable AS (
+            SELECT t.subscriber_id
+            FROM delivery_tasks t
+            JOIN subscriptions s ON s.id = t.subscriber_id
+            WHERE t.newsletter_issue_id = $1
+                AND t.attempts < $3
+                AND (t.lease_expires_at IS NULL OR t.lease_expires_at < now())
+                AND s.status = 'confirmed'
+            ORDER BY t.enqueued_at
+            LIMIT $4
+            FOR UPDATE OF t SKIP LOCKED
+        )
+        UPDATE delivery_tasks t
+        SET claimed_by = $2,
+            lease_expires_at = now() + make_interval(secs => $5),
+            attempts = t.attempts + 1
+        FROM claimable c, subscriptions s
+        WHERE t.newsletter_issue_id = $1
+            AND t.subscriber_id = c.subscriber_id
+            AND s.id = t.subscriber_id
+            AND s.status = 'confirmed'
+        RETURNING t.subscriber_id, t.attempts, s.email, s.region, s.do_not_track
+        "#,
+        newsletter_issue_id,
+        claimed_by,
+        MAX_ATTEMPTS,
+        batch_size,
+        LEASE.as_secs_f64(),
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| ClaimedDelivery {
+            subscriber_id: r.subscriber_id,
+            attempts: r.attempts,
+            email: r.email,
+            region: r.region,
+            do_not_track: r.do_not_track,
+        })
+        .collect())
+}
+
+#[tracing::instrument(name = "Renew newsletter delivery leases", skip(pg_pool))]
+async fn renew_leases(pg_pool: &PgPool, claimed_by: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE delivery_tasks
+        SET lease_expires_at = now() + make_interval(secs => $2)
+        WHERE claimed_by = $1
+        "#,
+        claimed_by,
+        LEASE.as_secs_f64(),
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Make a delivery held by `claimed_by` claimable again straight away,
+/// recording why it failed.
+#[tracing::instrument(name = "Release a newsletter delivery", skip(pg_pool, error))]
+async fn release_delivery(
+    pg_pool: &PgPool,
+    claimed_by: Uuid,
+    subscriber_id: Uuid,
+    error: &anyhow::Error,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE delivery_tasks
+        SET claimed_by = NULL, lease_expires_at = NULL, last_error = $3, failed_at = now()
+        WHERE claimed_by = $1 AND subscriber_id = $2
+        "#,
+        claimed_by,
+        subscriber_id,
+        format!("{:#}", error),
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Make a delivery held by `claimed_by` claimable again straight away, as
+/// if it had not been claimed.
+#[tracing::instrument(name = "Return a newsletter delivery to the queue", skip(pg_pool))]
+async fn return_delivery(
+    pg_pool: &PgPool,
+    claimed_by: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE delivery_tasks
+        SET claimed_by = NULL, lease_expires_at = NULL, attempts = attempts - 1
+        WHERE claimed_by = $1 AND subscriber_id = $2
+        "#,
+        claimed_by,
+        subscriber_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Remove a delivery from the queue, recording it when an email was sent.
+///
+/// Returns `false` if the attempt no longer holds the delivery, another
+/// worker having claimed it after the lease ran out.
+#[tracing::instrument(
+    name = "Complete newsletter delivery",
+    skip(pg_pool, delivery, outcome),
+    fields(subscriber_id=%delivery.subscriber_id)
+)]
+async fn complete_delivery(
+    pg_pool: &PgPool,
+    claimed_by: Uuid,
+    newsletter_issue_id: Uuid,
+    delivery: &ClaimedDelivery,
+    outcome: &DeliveryOutcome,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pg_pool.begin().await?;
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM delivery_tasks
+        WHERE newsletter_issue_id = $1
+            AND subscriber_id = $2
+            AND claimed_by = $3
+            AND attempts = $4
+        "#,
+        newsletter_issue_id,
+        delivery.subscriber_id,
+        claimed_by,
+        delivery.attempts,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if deleted == 0 {
+        return Ok(false);
+    }
+    if let DeliveryOutcome::Sent {
+        provider_message_id,
+    } = outcome
+    {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_deliveries (
+                newsletter_issue_id, subscriber_id, delivered_at, provider_message_id
+            )
+            VALUES ($1, $2, now(), $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            newsletter_issue_id,
+            delivery.subscriber_id,
+            provider_message_id.as_deref(),
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(true)
+}
@@ -0,0 +1,78 @@
+//! Disposable Postgres instances, so that neither contributors nor CI need a
+//! pre-provisioned database matching `configuration/base.yaml`.
+use crate::configuration::DatabaseSettings;
+use secrecy::ExposeSecret;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::ContainerRequest;
+use testcontainers_modules::testcontainers::core::IntoContainerPort;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt, ReuseDirective};
+
+const POSTGRES_PORT: u16 = 5432;
+
+pub struct EphemeralPostgres {
+    container: ContainerAsync<Postgres>,
+    pub host: String,
+    pub port: u16,
+}
+
+impl EphemeralPostgres {
+    /// Start a Postgres container accepting the credentials of `settings`.
+    /// It is published on `host_port` if provided, on a random port otherwise.
+    pub async fn start(
+        settings: &DatabaseSettings,
+        host_port: Option<u16>,
+    ) -> Result<Self, anyhow::Error> {
+        let image = Self::image(settings);
+        let image = match host_port {
+            Some(host_port) => image.with_mapped_port(host_port, POSTGRES_PORT.tcp()),
+            None => image,
+        };
+        Self::from_container(image.start().await?).await
+    }
+
+    /// Reuse the container called `name`, starting it if needed. It is left
+    /// running afterwards, for the next run to pick it up.
+    pub async fn reuse_or_start(
+        settings: &DatabaseSettings,
+        name: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let container = Self::image(settings)
+            .with_container_name(name)
+            .with_reuse(ReuseDirective::Always)
+            .start()
+            .await?;
+        Self::from_container(container).await
+    }
+
+    fn image(settings: &DatabaseSettings) -> ContainerRequest<Postgres> {
+        Postgres::default()
+            .with_user(&settings.username)
+            .with_password(settings.password.expose_secret())
+            .with_db_name("postgres")
+            .with_tag("17-alpine")
+            .with_cmd(["postgres", "-N", "1000"])
+    }
+
+    async fn from_container(container: ContainerAsync<Postgres>) -> Result<Self, anyhow::Error> {
+        let host = container.get_host().await?.to_string();
+        let port = container.get_host_port_ipv4(POSTGRES_PORT).await?;
+        Ok(Self {
+            container,
+            host,
+            port,
+        })
+    }
+
+    /// Point `settings` at the container.
+    pub fn configure(&self, settings: &mut DatabaseSettings) {
+        settings.host = self.host.clone();
+        settings.port = self.port;
+        settings.require_ssl = false;
+    }
+
+    pub async fn stop(self) -> Result<(), anyhow::Error> {
+        self.container.rm().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use crate::EmailClient;
+use crate::configuration::{DigestSettings, EmailTemplate, Settings, ShortLinkSettings};
+use crate::delivery::IssueDelivery;
+use crate::domain::Segment;
+use crate::encryption::FieldCipher;
+use crate::extensions::{DomainEvents, Published};
+use crate::feature_flags::FeatureFlags;
+use crate::jobs::Job;
+use crate::leader_election::LeaderElection;
+use crate::publishing::{IssueContent, SubscriberFooter, deliver_issue, escape_html, store_issue};
+use crate::signing::UrlSigner;
+use crate::templates::EmailTemplates;
+use crate::throttling::DeliveryThrottle;
+use crate::tracking::TrackingMode;
+use crate::web_pages::PageFetcher;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Cron expressions cannot be more precise than this.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sends the configured digests when their schedule is due.
+///
+/// A digest covers the issues published since its previous run, digests
+/// excluded. The first time a digest is seen it is only registered, the
+/// first issue going out at the next scheduled time.
+pub struct DigestScheduler {
+    digests: Vec<DigestSettings>,
+    template: EmailTemplate,
+    footer: SubscriberFooter,
+    pg_pool: PgPool,
+    cipher: FieldCipher,
+    page_fetcher: PageFetcher,
+    feature_flags: FeatureFlags,
+    email_client: Arc<EmailClient>,
+    throttle: Arc<DeliveryThrottle>,
+    link_base_url: String,
+    short_link_settings: ShortLinkSettings,
+    tracking_mode: TrackingMode,
+    events: DomainEvents,
+    leader_election: Arc<LeaderElection>,
+    job: Arc<Job>,
+}
+
+/// A scheduled digest that was due, `newsletter_issue_id` being `None` when
+/// it was skipped for lack of content.
+#[derive(Debug)]
+pub struct DigestRun {
+    pub name: String,
+    pub scheduled_for: DateTime<Utc>,
+    pub newsletter_issue_id: Option<Uuid>,
+}
+
+/// An issue listed in a digest, with the links it contained.
+pub struct DigestEntry {
+    pub title: String,
+    pub links: Vec<String>,
+}
+
+impl DigestScheduler {
+    /// `None` when no digest is configured.
+    pub fn build(
+        configuration: &Settings,
+        pg_pool: PgPool,
+        email_client: Arc<EmailClient>,
+        throttle: Arc<DeliveryThrottle>,
+    ) -> Option<Self> {
+        if configuration.digests.is_empty() {
+            return None;
+        }
+        Some(Self {
+            digests: configuration.digests.clone(),
+            template: configuration.email_templates.digest.clone(),
+            footer: SubscriberFooter::new(
+                EmailTemplates::new(configuration.email_templates.clone()),
+                UrlSigner::new(configuration.application.hmac_secret.clone()),
+            ),
+            leader_election: Arc::new(LeaderElection::new("digests", pg_pool.clone())),
+            pg_pool,
+            cipher: FieldCipher::new(configuration.encryption.as_ref()),
+            page_fetcher: PageFetcher::new(&configuration.web_pages),
+            feature_flags: FeatureFlags::new(configuration.feature_flags.clone()),
+            email_client,
+            throttle,
+            link_base_url: configuration.application.link_base_url().to_owned(),
+            short_link_settings: configuration.short_links.clone(),
+            tracking_mode: configuration.tracking.mode,
+            events: DomainEvents::default(),
+            job: Job::new("digests"),
+        })
+    }
+
+    /// Only the leader among the running instances sends the digests.
+    pub fn leader_election(&self) -> Arc<LeaderElection> {
+        self.leader_election.clone()
+    }
+
+    /// Tell the listeners of `events` about the issues published.
+    pub fn with_events(mut self, events: DomainEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(
+                CHECK_INTERVAL,
+                Some(&self.leader_election),
+                &shutdown,
+                || async { self.run_due_digests(Utc::now()).await.map(|_| ()) },
+            )
+            .await
+    }
+
+    /// Send every digest whose schedule fired by `now`.
+    ///
+    /// Missed runs are not caught up one by one, the latest one covers them.
+    #[tracing::instrument(name = "Run due digests", skip(self))]
+    pub async fn run_due_digests(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DigestRun>, anyhow::Error> {
+        let mut runs = Vec::new();
+        for digest in &self.digests {
+            let Some(last_run) = get_last_run(&self.pg_pool, &digest.name)
+                .await
+                .context("Failed to retrieve the last digest run")?
+            else {
+                register_digest(&self.pg_pool, &digest.name, now)
+                    .await
+                    .context("Failed to register a digest")?;
+                continue;
+            };
+            let Some(scheduled_for) = digest
+                .schedule
+                .after(&last_run)
+                .take_while(|t| *t <= now)
+                .last()
+            else {
+                continue;
+            };
+            if let Some(run) = self.run_digest(digest, last_run, scheduled_for).await? {
+                runs.push(run);
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Returns `None` if another instance claimed the run first.
+    #[tracing::instrument(
+        name = "Run digest",
+        skip(self, digest),
+        fields(digest_name = %digest.name)
+    )]
+    async fn run_digest(
+        &self,
+        digest: &DigestSettings,
+        since: DateTime<Utc>,
+        scheduled_for: DateTime<Utc>,
+    ) -> Result<Option<DigestRun>, anyhow::Error> {
+        // Rendered before the run is claimed, for the link cards of the
+        // template not to be fetched within the transaction.
+        let entries = get_digest_entries(&self.pg_pool, since, scheduled_for)
+            .await
+            .context("Failed to retrieve the issues of a digest")?;
+        let content = if entries.is_empty() && !digest.send_if_empty {
+            None
+        } else {
+            Some(
+                render_digest(&self.template, &digest.name, &entries)
+                    .with_cards_expanded(&self.pg_pool, &self.page_fetcher)
+                    .await?,
+            )
+        };
+        let mut transaction = self
+            .pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        if !claim_run(&mut transaction, &digest.name, scheduled_for)
+            .await
+            .context("Failed to claim a digest run")?
+        {
+            return Ok(None);
+        }
+        let Some(content) = content else {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to skip a digest")?;
+            tracing::info!("Skipping the digest, no issue was published since the last one");
+            return Ok(Some(DigestRun {
+                name: digest.name.clone(),
+                scheduled_for,
+                newsletter_issue_id: None,
+            }));
+        };
+        let issue = store_issue(
+            &mut transaction,
+            &self.cipher,
+            &self.link_base_url,
+            &self.short_link_settings,
+            &content,
+            self.tracking_mode,
+            None,
+        )
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE digest_runs
+            SET newsletter_issue_id = $3
+            WHERE digest_name = $1 AND scheduled_for = $2
+            "#,
+            digest.name,
+            scheduled_for,
+            issue.newsletter_issue_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to link a digest run to its issue")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a digest")?;
+        self.events.published(Published {
+            newsletter_issue_id: issue.newsletter_issue_id,
+            title: issue.title.clone(),
+        });
+
+        deliver_issue(
+            IssueDelivery {
+                pg_pool: &self.pg_pool,
+                email_client: &self.email_client,
+                throttle: &self.throttle,
+                base_url: &self.link_base_url,
+                footer: &self.footer,
+            },
+            &self.feature_flags,
+            &issue,
+            &Segment::default(),
+        )
+        .await?;
+        Ok(Some(DigestRun {
+            name: digest.name.clone(),
+            scheduled_for,
+            newsletter_issue_id: Some(issue.newsletter_issue_id),
+        }))
+    }
+}
+
+/// List the titles and links of `entries` in the body of a digest issue.
+pub fn render_digest(
+    template: &EmailTemplate,
+    name: &str,
+    entries: &[DigestEntry],
+) -> IssueContent {
+    let mut html = String::new();
+    let mut text = String::new();
+    for entry in entries {
+        html.push_str(&format!("<h2>{}</h2>", escape_html(&entry.title)));
+        text.push_str(&entry.title);
+        text.push('\n');
+        if !entry.links.is_empty() {
+            html.push_str("<ul>");
+            for link in &entry.links {
+                let link_html = escape_html(link);
+                html.push_str(&format!(
+                    r#"<li><a href="{link_html}">{link_html}</a></li>"#
+                ));
+                text.push_str(&format!("- {}\n", link));
+            }
+            html.push_str("</ul>");
+        }
+        text.push('\n');
+    }
+    let escaped_name = escape_html(name);
+    IssueContent {
+        title: template.render_subject(&[("name", name)]),
+        html: template.render_html(&[("name", &escaped_name), ("issues", &html)]),
+        text: template.render_text(&[("name", name), ("issues", text.trim_end())]),
+        amp: None,
+        event: None,
+        sender: None,
+        internal_copies: Vec::new(),
+    }
+}
+
+#[tracing::instrument(name = "Get last digest run", skip(pg_pool))]
+async fn get_last_run(
+    pg_pool: &PgPool,
+    digest_name: &str,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let last_run = sqlx::query!(
+        r#"SELECT max(scheduled_for) AS last_run FROM digest_runs WHERE digest_name = $1"#,
+        digest_name,
+    )
+    .fetch_one(pg_pool)
+    .await?
+    .last_run;
+    Ok(last_run)
+}
+
+/// Record an empty run, so that the first digest covers what is published
+/// from now on.
+#[tracing::instrument(name = "Register digest", skip(pg_pool))]
+async fn register_digest(
+    pg_pool: &PgPool,
+    digest_name: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO digest_runs (digest_name, scheduled_for, ran_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        digest_name,
+        now,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+async fn claim_run(
+    transaction: &mut PgConnection,
+    digest_name: &str,
+    scheduled_for: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO digest_runs (digest_name, scheduled_for, ran_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        digest_name,
+        scheduled_for,
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+async fn get_digest_entries(
+    pg_pool: &PgPool,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<Vec<DigestEntry>, sqlx::Error> {
+    let entries = sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            COALESCE(
+                array_agg(l.destination_url ORDER BY l.created_at, l.code)
+                    FILTER (WHERE l.code IS NOT NULL),
+                '{}'
+            ) AS "links!"
+        FROM newsletter_issues i
+        LEFT JOIN short_links l ON l.newsletter_issue_id = i.newsletter_issue_id
+        WHERE i.published_at > $1 AND i.published_at <= $2
+            AND NOT EXISTS (
+                SELECT 1 FROM digest_runs d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+            )
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at
+        "#,
+        since,
+        until,
+    )
+    .fetch_all(pg_pool)
+    .await?
+    .into_iter()
+    .map(|r| DigestEntry {
+        title: r.title,
+        links: r.links,
+    })
+    .collect();
+    Ok(entries)
+}
@@ -0,0 +1,142 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// DNS resolver remembering answers for `ttl`, and serving the last known
+/// answer when a lookup fails so that a flaky resolver does not fail sends
+/// to a host we already reached.
+#[derive(Clone)]
+pub struct CachingResolver {
+    ttl: Duration,
+    cache: Arc<Mutex<HashMap<String, CachedLookup>>>,
+}
+
+struct CachedLookup {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+}
+
+impl CachingResolver {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    async fn resolve_with<F, Fut>(
+        &self,
+        host: &str,
+        lookup: F,
+    ) -> Result<Vec<SocketAddr>, std::io::Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<SocketAddr>, std::io::Error>>,
+    {
+        let cached = {
+            let cache = self.cache.lock().unwrap();
+            cache
+                .get(host)
+                .map(|c| (c.addrs.clone(), c.resolved_at.elapsed() < self.ttl))
+        };
+        if let Some((addrs, true)) = &cached {
+            return Ok(addrs.clone());
+        }
+        match lookup().await {
+            Ok(addrs) if !addrs.is_empty() => {
+                self.cache.lock().unwrap().insert(
+                    host.to_owned(),
+                    CachedLookup {
+                        addrs: addrs.clone(),
+                        resolved_at: Instant::now(),
+                    },
+                );
+                Ok(addrs)
+            }
+            outcome => match cached {
+                Some((stale_addrs, _)) => {
+                    tracing::warn!(host, "DNS lookup failed, using the last known addresses",);
+                    Ok(stale_addrs)
+                }
+                None => outcome,
+            },
+        }
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = resolver
+                .resolve_with(host, || async move {
+                    // The port is ignored, the one of the URL is used.
+                    Ok(tokio::net::lookup_host((host, 0)).await?.collect())
+                })
+                .await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachingResolver;
+    use claims::{assert_err, assert_ok};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    fn addr() -> SocketAddr {
+        "10.0.0.1:0".parse().unwrap()
+    }
+
+    fn lookup_error() -> std::io::Error {
+        std::io::Error::other("resolver unavailable")
+    }
+
+    #[tokio::test]
+    async fn fresh_answers_are_served_from_the_cache() {
+        let resolver = CachingResolver::new(Duration::from_secs(60));
+        assert_ok!(
+            resolver
+                .resolve_with("example.com", || async { Ok(vec![addr()]) })
+                .await
+        );
+
+        let addrs = resolver
+            .resolve_with("example.com", || async { panic!("Lookup not expected") })
+            .await;
+
+        assert_eq!(assert_ok!(addrs), vec![addr()]);
+    }
+
+    #[tokio::test]
+    async fn stale_answers_are_served_when_the_lookup_fails() {
+        let resolver = CachingResolver::new(Duration::ZERO);
+        assert_ok!(
+            resolver
+                .resolve_with("example.com", || async { Ok(vec![addr()]) })
+                .await
+        );
+
+        let addrs = resolver
+            .resolve_with("example.com", || async { Err(lookup_error()) })
+            .await;
+
+        assert_eq!(assert_ok!(addrs), vec![addr()]);
+    }
+
+    #[tokio::test]
+    async fn lookup_errors_are_returned_for_unknown_hosts() {
+        let resolver = CachingResolver::new(Duration::from_secs(60));
+
+        let addrs = resolver
+            .resolve_with("example.com", || async { Err(lookup_error()) })
+            .await;
+
+        assert_err!(addrs);
+    }
+}
@@ -1,7 +1,19 @@
 pub mod new_subscriber;
+pub mod password;
+pub mod segment;
 pub mod subscriber_email;
 pub mod subscriber_name;
+pub mod subscriber_region;
+pub mod subscriber_rules;
+pub mod subscriber_tag;
+pub mod subscription_token;
 
 pub use new_subscriber::NewSubscriber;
+pub use password::Password;
+pub use segment::{EngagementSegment, Segment};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_region::SubscriberRegion;
+pub use subscriber_rules::{SubscriberRules, SubscriberValidator};
+pub use subscriber_tag::SubscriberTag;
+pub use subscription_token::SubscriptionToken;
@@ -1,18 +1,59 @@
-use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::domain::{
+    SubscriberEmail, SubscriberName, SubscriberRegion, SubscriberRules, SubscriberTag,
+};
+use crate::locale::{LocalizedError, Message};
 use crate::routes::subscriptions::FormData;
 
+/// Tags a subscriber can pick when signing up, which keeps a single signup
+/// from filling `subscriber_tags`.
+pub const MAX_SIGNUP_TAGS: usize = 20;
+
 #[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub region: Option<SubscriberRegion>,
+    /// Without duplicates, empty unless picked when signing up.
+    pub tags: Vec<SubscriberTag>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::try_from(form.name)?;
-        let email = SubscriberEmail::try_from(form.email)?;
-        Ok(Self { email, name })
+impl NewSubscriber {
+    /// Parse a signup, then hold it to the acceptance policy of the
+    /// deployment. Signups are only ever turned into subscribers through
+    /// here, so that no path skips the policy.
+    pub fn parse(form: FormData, rules: &SubscriberRules) -> Result<Self, LocalizedError> {
+        let name = SubscriberName::try_from(form.name.clone())
+            .map_err(|_| LocalizedError::new(Message::InvalidName, form.name))?;
+        let email = SubscriberEmail::try_from(form.email.clone())
+            .map_err(|_| LocalizedError::new(Message::InvalidEmail, form.email))?;
+        let region = form
+            .region
+            .map(|region| {
+                SubscriberRegion::parse(region.clone())
+                    .map_err(|_| LocalizedError::new(Message::InvalidRegion, region))
+            })
+            .transpose()?;
+        let mut tags = Vec::with_capacity(form.tags.len());
+        for tag in form.tags {
+            let tag = SubscriberTag::parse(tag.clone())
+                .map_err(|_| LocalizedError::new(Message::InvalidTag, tag))?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+            if tags.len() > MAX_SIGNUP_TAGS {
+                return Err(LocalizedError::new(
+                    Message::TooManyTags,
+                    MAX_SIGNUP_TAGS.to_string(),
+                ));
+            }
+        }
+        let subscriber = Self {
+            email,
+            name,
+            region,
+            tags,
+        };
+        rules.check(&subscriber)?;
+        Ok(subscriber)
     }
 }
@@ -0,0 +1,108 @@
+use secrecy::{ExposeSecret, SecretString};
+use std::collections::HashSet;
+use unicode_segmentation::UnicodeSegmentation;
+
+const MIN_LENGTH: usize = 12;
+/// Argon2 hashes passwords of any length, the cap keeps hashing cheap.
+const MAX_LENGTH: usize = 128;
+/// Rules out repeated patterns such as `abababababab`.
+const MIN_DISTINCT_CHARACTERS: usize = 6;
+/// Estimated as the length times the bits of the character classes used.
+const MIN_ENTROPY_BITS: f64 = 60.0;
+
+/// A new password for an admin, long and varied enough to be worth hashing.
+#[derive(Debug)]
+pub struct Password(SecretString);
+
+impl Password {
+    pub fn parse(s: SecretString) -> Result<Password, String> {
+        let password = s.expose_secret();
+        let graphemes: Vec<&str> = password.graphemes(true).collect();
+        if graphemes.len() < MIN_LENGTH {
+            return Err(format!(
+                "The password must be at least {} characters long.",
+                MIN_LENGTH
+            ));
+        }
+        if graphemes.len() > MAX_LENGTH {
+            return Err(format!(
+                "The password cannot be longer than {} characters.",
+                MAX_LENGTH
+            ));
+        }
+        if graphemes.iter().collect::<HashSet<_>>().len() < MIN_DISTINCT_CHARACTERS {
+            return Err(format!(
+                "The password must use at least {} different characters.",
+                MIN_DISTINCT_CHARACTERS
+            ));
+        }
+        if estimated_entropy_bits(password, graphemes.len()) < MIN_ENTROPY_BITS {
+            return Err(
+                "The password is too easy to guess, make it longer or mix letters, digits and symbols."
+                    .into(),
+            );
+        }
+        Ok(Self(s))
+    }
+}
+
+impl ExposeSecret<str> for Password {
+    fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+fn estimated_entropy_bits(password: &str, length: usize) -> f64 {
+    let has = |class: fn(&char) -> bool| password.chars().any(|c| class(&c));
+    let pool_size: u32 = [
+        (has(char::is_ascii_lowercase), 26),
+        (has(char::is_ascii_uppercase), 26),
+        (has(char::is_ascii_digit), 10),
+        (has(|c| c.is_ascii() && !c.is_ascii_alphanumeric()), 33),
+        (has(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|(used, _)| *used)
+    .map(|(_, size)| size)
+    .sum();
+    length as f64 * f64::from(pool_size).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Password;
+    use claims::{assert_err, assert_ok};
+    use secrecy::SecretString;
+
+    fn parse(s: &str) -> Result<Password, String> {
+        Password::parse(SecretString::from(s))
+    }
+
+    #[test]
+    fn short_passwords_are_rejected() {
+        assert_err!(parse("Sh0rt&Sweet"));
+    }
+
+    #[test]
+    fn overly_long_passwords_are_rejected() {
+        assert_err!(parse(&"Correct horse 1".repeat(9)));
+    }
+
+    #[test]
+    fn repeated_patterns_are_rejected() {
+        assert_err!(parse("abababababababababab"));
+        assert_err!(parse("aaaaaaaaaaaaaaaaaaaa"));
+    }
+
+    #[test]
+    fn lowercase_passwords_need_to_be_longer() {
+        assert_err!(parse("abcdefghijkl"));
+        assert_ok!(parse("correcthorsebattery"));
+    }
+
+    #[test]
+    fn mixed_passwords_are_accepted() {
+        assert_ok!(parse("Tr0ub4dor&3xyz"));
+        assert_ok!(parse("correct horse battery staple"));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::subscriber_search::SubscriberFilter;
+use uuid::Uuid;
+
+/// Restricts which confirmed subscribers an email goes to.
+/// An empty segment matches every confirmed subscriber.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Segment {
+    pub engagement: Option<EngagementSegment>,
+    /// Subscribers having any of these tags, all of them when empty.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Filter of the saved segment given by `segment_id`, as newsletters
+    /// reference saved segments by id.
+    #[serde(skip)]
+    pub filter: Option<SubscriberFilter>,
+    /// Saved segment the filter comes from, recorded with the audience of
+    /// the issues sent to it.
+    #[serde(skip)]
+    pub segment_id: Option<Uuid>,
+}
+
+/// Subscribers bucketed by their engagement over the last 90 days,
+/// as computed by the `subscriber_engagement` view.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngagementSegment {
+    #[serde(rename = "active_90d")]
+    Active90d,
+    #[serde(rename = "inactive_90d")]
+    Inactive90d,
+}
+
+impl EngagementSegment {
+    pub fn is_inactive(&self) -> bool {
+        *self == EngagementSegment::Inactive90d
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngagementSegment::Active90d => "active_90d",
+            EngagementSegment::Inactive90d => "inactive_90d",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "active_90d" => Some(EngagementSegment::Active90d),
+            "inactive_90d" => Some(EngagementSegment::Inactive90d),
+            _ => None,
+        }
+    }
+}
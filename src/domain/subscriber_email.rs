@@ -13,6 +13,22 @@ impl std::fmt::Display for SubscriberEmail {
     }
 }
 
+impl SubscriberEmail {
+    /// The part after the last `@`, which is where the address is
+    /// delivered whatever its local part holds.
+    pub fn domain(&self) -> &str {
+        self.email.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
+
+    /// The address with all but the first character of the local part
+    /// hidden, e.g. `u***@gmail.com`, to be shown back without disclosing it.
+    pub fn masked(&self) -> String {
+        let (local, domain) = self.email.rsplit_once('@').unwrap_or((&self.email, ""));
+        let first: String = local.chars().take(1).collect();
+        format!("{}***@{}", first, domain)
+    }
+}
+
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
         &self.email
@@ -80,4 +96,16 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::try_from(email));
     }
+
+    #[test]
+    fn masked_emails_only_show_the_first_character_and_the_domain() {
+        let email = SubscriberEmail::try_from("ursula_le_guin@gmail.com".to_string()).unwrap();
+        assert_eq!(email.masked(), "u***@gmail.com");
+    }
+
+    #[test]
+    fn masked_emails_hide_the_length_of_the_local_part() {
+        let email = SubscriberEmail::try_from("u@gmail.com".to_string()).unwrap();
+        assert_eq!(email.masked(), "u***@gmail.com");
+    }
 }
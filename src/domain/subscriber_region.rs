@@ -0,0 +1,56 @@
+/// Data-residency region of a subscriber (e.g. `eu`), used to pick the
+/// email provider endpoint their emails go through.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct SubscriberRegion(String);
+
+impl SubscriberRegion {
+    pub fn parse(s: String) -> Result<SubscriberRegion, String> {
+        let region = s.trim().to_lowercase();
+        let is_valid = !region.is_empty()
+            && region.len() <= 32
+            && region
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if is_valid {
+            Ok(Self(region))
+        } else {
+            Err(format!("{} is not a valid subscriber region", s))
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriberRegion {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(value)
+    }
+}
+
+impl AsRef<str> for SubscriberRegion {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberRegion;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn regions_are_normalized_to_lowercase() {
+        let region = assert_ok!(SubscriberRegion::parse(" EU ".to_string()));
+        assert_eq!(region.as_ref(), "eu");
+    }
+
+    #[test]
+    fn empty_region_is_rejected() {
+        assert_err!(SubscriberRegion::parse("  ".to_string()));
+    }
+
+    #[test]
+    fn region_with_forbidden_characters_is_rejected() {
+        assert_err!(SubscriberRegion::parse("eu/west".to_string()));
+    }
+}
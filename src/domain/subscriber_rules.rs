@@ -0,0 +1,192 @@
+use crate::configuration::{PatternRule, SubscriberValidationSettings};
+use crate::domain::NewSubscriber;
+use crate::locale::{LocalizedError, Message};
+use std::sync::Arc;
+
+/// A check of new subscribers specific to a deployment, registered through
+/// [`Extensions::validator`](crate::extensions::Extensions::validator).
+pub trait SubscriberValidator: Send + Sync {
+    fn validate(&self, subscriber: &NewSubscriber) -> Result<(), LocalizedError>;
+}
+
+/// The acceptance policy of the deployment: the allowed email domains and
+/// the configured patterns, then the registered [`SubscriberValidator`]s in
+/// order.
+#[derive(Clone, Default)]
+pub struct SubscriberRules {
+    settings: SubscriberValidationSettings,
+    validators: Vec<Arc<dyn SubscriberValidator>>,
+}
+
+impl SubscriberRules {
+    pub fn new(
+        settings: SubscriberValidationSettings,
+        validators: Vec<Arc<dyn SubscriberValidator>>,
+    ) -> Self {
+        Self {
+            settings,
+            validators,
+        }
+    }
+
+    pub fn check(&self, subscriber: &NewSubscriber) -> Result<(), LocalizedError> {
+        let allowed_domains = &self.settings.allowed_email_domains;
+        let domain = subscriber.email.domain();
+        if !allowed_domains.is_empty()
+            && !allowed_domains
+                .iter()
+                .any(|allowed| allowed.trim_start_matches('@').eq_ignore_ascii_case(domain))
+        {
+            let domains: Vec<String> = allowed_domains
+                .iter()
+                .map(|allowed| format!("@{}", allowed.trim_start_matches('@')))
+                .collect();
+            return Err(LocalizedError::new(
+                Message::EmailDomainNotAllowed,
+                domains.join(", "),
+            ));
+        }
+        check_patterns(&self.settings.name, subscriber.name.as_ref())?;
+        // Addresses are matched lowercased, whatever the case they were
+        // typed in.
+        let email = subscriber.email.as_ref();
+        if !self
+            .settings
+            .email
+            .iter()
+            .all(|rule| rule.accepts(&email.to_lowercase()))
+        {
+            return Err(LocalizedError::new(Message::NotAccepted, email));
+        }
+        if let Some(region) = &subscriber.region {
+            check_patterns(&self.settings.region, region.as_ref())?;
+        }
+        for tag in &subscriber.tags {
+            check_patterns(&self.settings.tags, tag.as_ref())?;
+        }
+        for validator in &self.validators {
+            validator.validate(subscriber)?;
+        }
+        Ok(())
+    }
+}
+
+/// Patterns are a policy of the deployment, so values failing them are
+/// told apart from malformed ones.
+fn check_patterns(rules: &[PatternRule], value: &str) -> Result<(), LocalizedError> {
+    if rules.iter().all(|rule| rule.accepts(value)) {
+        Ok(())
+    } else {
+        Err(LocalizedError::new(Message::NotAccepted, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubscriberRules, SubscriberValidator};
+    use crate::configuration::{PatternRule, SubscriberValidationSettings};
+    use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag};
+    use crate::locale::{LocalizedError, Message};
+    use regex::Regex;
+    use std::sync::Arc;
+
+    fn subscriber(email: &str, tags: &[&str]) -> NewSubscriber {
+        NewSubscriber {
+            email: SubscriberEmail::try_from(email.to_owned()).unwrap(),
+            name: SubscriberName::try_from("Ursula".to_owned()).unwrap(),
+            region: None,
+            tags: tags
+                .iter()
+                .map(|tag| SubscriberTag::parse(tag.to_string()).unwrap())
+                .collect(),
+        }
+    }
+
+    fn pattern(pattern: &str) -> Regex {
+        Regex::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn values_must_pass_every_pattern() {
+        let rules = SubscriberRules::new(
+            SubscriberValidationSettings {
+                email: vec![
+                    PatternRule::MustMatch(pattern(r"@corp\.com$")),
+                    PatternRule::MustNotMatch(pattern(r"^noreply@")),
+                ],
+                tags: vec![PatternRule::MustMatch(pattern(r"^[a-z]+$"))],
+                ..Default::default()
+            },
+            Vec::new(),
+        );
+
+        assert!(
+            rules
+                .check(&subscriber("ursula@corp.com", &["rust"]))
+                .is_ok()
+        );
+        assert_eq!(
+            rules.check(&subscriber("ursula@gmail.com", &[])),
+            Err(LocalizedError::new(
+                Message::NotAccepted,
+                "ursula@gmail.com"
+            ))
+        );
+        assert!(rules.check(&subscriber("noreply@corp.com", &[])).is_err());
+        assert!(rules.check(&subscriber("NoReply@corp.com", &[])).is_err());
+        assert!(rules.check(&subscriber("ursula@CORP.COM", &[])).is_ok());
+        assert_eq!(
+            rules.check(&subscriber("ursula@corp.com", &["rust", "c++"])),
+            Err(LocalizedError::new(Message::NotAccepted, "c++"))
+        );
+    }
+
+    #[test]
+    fn only_addresses_at_the_allowed_domains_are_accepted() {
+        let rules = SubscriberRules::new(
+            SubscriberValidationSettings {
+                allowed_email_domains: vec!["corp.com".into(), "@corp.de".into()],
+                ..Default::default()
+            },
+            Vec::new(),
+        );
+
+        assert!(rules.check(&subscriber("ursula@corp.com", &[])).is_ok());
+        assert!(rules.check(&subscriber("ursula@CORP.de", &[])).is_ok());
+        assert_eq!(
+            rules.check(&subscriber("ursula@corp.com.evil.com", &[])),
+            Err(LocalizedError::new(
+                Message::EmailDomainNotAllowed,
+                "@corp.com, @corp.de"
+            ))
+        );
+        assert!(
+            SubscriberRules::default()
+                .check(&subscriber("ursula@gmail.com", &[]))
+                .is_ok()
+        );
+    }
+
+    struct NoTags;
+
+    impl SubscriberValidator for NoTags {
+        fn validate(&self, subscriber: &NewSubscriber) -> Result<(), LocalizedError> {
+            match subscriber.tags.first() {
+                Some(tag) => Err(LocalizedError::new(Message::InvalidTag, tag.as_ref())),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn validators_are_run_after_the_patterns() {
+        let rules = SubscriberRules::new(Default::default(), vec![Arc::new(NoTags)]);
+
+        assert!(rules.check(&subscriber("ursula@gmail.com", &[])).is_ok());
+        assert!(
+            rules
+                .check(&subscriber("ursula@gmail.com", &["rust"]))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,53 @@
+/// Label a subscriber picked when signing up (e.g. `rust`), which issues
+/// can be sent to. Tags are lowercased, so that `Rust` and `rust` are the
+/// same tag whether they come from a signup, an import or a newsletter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim();
+        // Commas and semicolons separate the tags of form fields and imports.
+        let is_valid = !tag.is_empty()
+            && tag.chars().count() <= 64
+            && !tag.chars().any(|c| c.is_control() || c == ',' || c == ';');
+        if is_valid {
+            Ok(Self(tag.to_lowercase()))
+        } else {
+            Err(format!("{} is not a valid subscriber tag", s))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = assert_ok!(SubscriberTag::parse(" Rust ".to_string()));
+        assert_eq!(tag.as_ref(), "rust");
+    }
+
+    #[test]
+    fn empty_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse("  ".to_string()));
+    }
+
+    #[test]
+    fn tag_with_a_separator_is_rejected() {
+        assert_err!(SubscriberTag::parse("rust;go".to_string()));
+    }
+
+    #[test]
+    fn a_65_characters_long_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+}
@@ -0,0 +1,80 @@
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use secrecy::{ExposeSecret, SecretString};
+
+/// A token granting access to whoever holds it: confirmation, re-engagement,
+/// subscription status and unsubscribe links, and sender verifications.
+///
+/// Like `SecretString`, its `Debug` output is redacted and it has no
+/// `Display`, so that it cannot end up in logs or span fields by mistake.
+/// The value is only reachable through `expose_secret`, when it is stored or
+/// put in a link.
+#[derive(Clone, serde::Deserialize)]
+#[serde(transparent)]
+pub struct SubscriptionToken(SecretString);
+
+impl SubscriptionToken {
+    /// Generate a random 25-characters-long case-sensitive token.
+    pub fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(25)
+            .collect();
+        Self(token.into())
+    }
+
+    pub fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+/// A token read back from the database.
+impl From<String> for SubscriptionToken {
+    fn from(token: String) -> Self {
+        Self(token.into())
+    }
+}
+
+impl std::fmt::Debug for SubscriptionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SubscriptionToken([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionToken;
+
+    #[test]
+    fn tokens_are_25_alphanumeric_characters() {
+        let token = SubscriptionToken::generate();
+        assert_eq!(token.expose_secret().len(), 25);
+        assert!(
+            token
+                .expose_secret()
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+        );
+    }
+
+    #[test]
+    fn tokens_are_redacted_from_debug_output() {
+        let token = SubscriptionToken::generate();
+        let debug = format!("{:?}", token);
+        assert_eq!(debug, "SubscriptionToken([REDACTED])");
+        assert!(!debug.contains(token.expose_secret()));
+    }
+
+    #[test]
+    fn tokens_deserialized_from_requests_are_redacted() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Parameters {
+            #[allow(dead_code)]
+            token: SubscriptionToken,
+        }
+        let parameters: Parameters = serde_json::from_str(r#"{"token": "guessedToken"}"#).unwrap();
+        assert_eq!(parameters.token.expose_secret(), "guessedToken");
+        assert!(!format!("{:?}", parameters).contains("guessedToken"));
+    }
+}
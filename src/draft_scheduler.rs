@@ -0,0 +1,165 @@
+use crate::EmailClient;
+use crate::configuration::{Settings, ShortLinkSettings};
+use crate::delivery::IssueDelivery;
+use crate::encryption::FieldCipher;
+use crate::extensions::DomainEvents;
+use crate::feature_flags::FeatureFlags;
+use crate::jobs::Job;
+use crate::publishing::{
+    IssuePublishing, PublishDraftError, SubscriberFooter, get_due_drafts, publish_draft,
+    record_publish_failure,
+};
+use crate::signing::UrlSigner;
+use crate::templates::EmailTemplates;
+use crate::throttling::DeliveryThrottle;
+use crate::tracking::TrackingMode;
+use crate::web_pages::PageFetcher;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// How often every instance looks for drafts due to be published.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// A draft failing to publish is tried again after this long, then twice as
+/// long after each failure...
+const FIRST_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// ...until it failed this many times, when it is unscheduled.
+const MAX_PUBLISH_ATTEMPTS: i32 = 5;
+/// Name of the [`DraftScheduler`] job, triggered when a draft is approved
+/// from Slack.
+pub const DRAFT_SCHEDULER_JOB: &str = "draft_scheduler";
+
+/// Publishes the drafts whose scheduled time has come.
+///
+/// Every instance runs one. Publishing locks the draft, so a draft is
+/// published once however many instances find it due.
+pub struct DraftScheduler {
+    footer: SubscriberFooter,
+    pg_pool: PgPool,
+    cipher: FieldCipher,
+    page_fetcher: PageFetcher,
+    feature_flags: FeatureFlags,
+    email_client: Arc<EmailClient>,
+    throttle: Arc<DeliveryThrottle>,
+    link_base_url: String,
+    short_link_settings: ShortLinkSettings,
+    tracking_mode: TrackingMode,
+    events: DomainEvents,
+    job: Arc<Job>,
+}
+
+impl DraftScheduler {
+    /// Drafts are published with the `templates`, `cipher` and
+    /// `page_fetcher` of the application, like those published by hand.
+    pub fn build(
+        configuration: &Settings,
+        pg_pool: PgPool,
+        email_client: Arc<EmailClient>,
+        throttle: Arc<DeliveryThrottle>,
+        templates: EmailTemplates,
+        cipher: FieldCipher,
+        page_fetcher: PageFetcher,
+    ) -> Self {
+        Self {
+            footer: SubscriberFooter::new(
+                templates,
+                UrlSigner::new(configuration.application.hmac_secret.clone()),
+            ),
+            pg_pool,
+            cipher,
+            page_fetcher,
+            feature_flags: FeatureFlags::new(configuration.feature_flags.clone()),
+            email_client,
+            throttle,
+            link_base_url: configuration.application.link_base_url().to_owned(),
+            short_link_settings: configuration.short_links.clone(),
+            tracking_mode: configuration.tracking.mode,
+            events: DomainEvents::default(),
+            job: Job::new(DRAFT_SCHEDULER_JOB),
+        }
+    }
+
+    /// Tell the listeners of `events` about the issues published.
+    pub fn with_events(mut self, events: DomainEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(POLL_INTERVAL, None, &shutdown, || async {
+                self.publish_due(Utc::now()).await.map(|_| ())
+            })
+            .await
+    }
+
+    /// Publish the drafts due by `now`, returning the issues published, or an
+    /// error once every draft was attempted if any of them failed. Drafts
+    /// failing are retried with a backoff, then unscheduled.
+    #[tracing::instrument(name = "Publish scheduled drafts", skip(self))]
+    pub async fn publish_due(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>, anyhow::Error> {
+        let newsletter_draft_ids = get_due_drafts(&self.pg_pool, now)
+            .await
+            .context("Failed to look for scheduled drafts")?;
+        let mut issues = Vec::new();
+        let mut failed = 0;
+        for newsletter_draft_id in newsletter_draft_ids {
+            let outcome = publish_draft(
+                IssueDelivery {
+                    pg_pool: &self.pg_pool,
+                    email_client: &self.email_client,
+                    throttle: &self.throttle,
+                    base_url: &self.link_base_url,
+                    footer: &self.footer,
+                },
+                IssuePublishing {
+                    cipher: &self.cipher,
+                    page_fetcher: &self.page_fetcher,
+                    feature_flags: &self.feature_flags,
+                    short_link_settings: &self.short_link_settings,
+                    events: &self.events,
+                },
+                newsletter_draft_id,
+                self.tracking_mode,
+                None,
+            )
+            .await;
+            match outcome {
+                Ok(newsletter_issue_id) => issues.push(newsletter_issue_id),
+                // Published by another instance in the meantime.
+                Err(PublishDraftError::AlreadyPublished(_)) => {}
+                Err(e) => {
+                    let unscheduled = record_publish_failure(
+                        &self.pg_pool,
+                        newsletter_draft_id,
+                        &e.to_string(),
+                        now,
+                        FIRST_RETRY_AFTER,
+                        MAX_PUBLISH_ATTEMPTS,
+                    )
+                    .await
+                    .context("Failed to record the failure of a scheduled draft")?;
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        %newsletter_draft_id,
+                        unscheduled,
+                        "Failed to publish a scheduled draft",
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            anyhow::bail!("Failed to publish {} scheduled drafts", failed);
+        }
+        Ok(issues)
+    }
+}
@@ -1,79 +1,672 @@
-use crate::domain::SubscriberEmail;
+use crate::domain::{SubscriberEmail, SubscriberRegion};
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::request_id::{REQUEST_ID_HEADER, current_request_id};
+use crate::subject_lines::encode_subject;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 pub struct EmailClient {
     http_client: reqwest::Client,
-    base_url: String,
+    endpoints: Vec<EmailEndpoint>,
+    /// Indexes into `endpoints`, tried in order until one accepts the email.
+    default_route: Vec<usize>,
+    regional_routes: HashMap<String, Vec<usize>>,
     sender: SubscriberEmail,
+    sender_name: String,
     authorization_token: SecretString,
+    /// Whether the provider accepts an AMP for Email part, AMP bodies are
+    /// dropped otherwise.
+    supports_amp: bool,
+    /// Whether subjects are sent as RFC 2047 encoded words, for providers
+    /// that relay them as is over SMTP.
+    encode_subjects: bool,
+    retry_policy: RetryPolicy,
+    max_concurrent_sends: usize,
+    pause_switch: Option<PauseSwitch>,
+}
+
+/// How long the email client trusts the `PAUSE_DELIVERIES` flag it read,
+/// rather than reading it before every email. A pause set from another
+/// instance holds back the emails of this one after up to this long.
+pub const PAUSE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where the email client reads `PAUSE_DELIVERIES` from, and what it read
+/// last.
+struct PauseSwitch {
+    feature_flags: FeatureFlags,
+    pg_pool: PgPool,
+    /// When the flag was read and whether it was on. Sends waiting for a
+    /// read share its outcome, rather than each running the query.
+    last_read: tokio::sync::Mutex<Option<(Instant, bool)>>,
+}
+
+/// How many times an endpoint is tried before giving up on it, for failures
+/// that are likely to go away on their own: timeouts and 5xx responses.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts per endpoint, the first one included.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled before each of the next ones.
+    pub base_delay: Duration,
+    /// Fraction of the delay to randomly add or remove, so that sends which
+    /// failed together do not all retry at the same time.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// A single attempt per endpoint.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            jitter: 0.0,
+        }
+    }
+
+    /// How long to wait after the `attempt`-th attempt failed.
+    fn delay(&self, attempt: u32) -> Duration {
+        use rand::Rng;
+
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 + jitter * rand::thread_rng().gen_range(-1.0..=1.0))
+    }
+}
+
+struct EmailEndpoint {
+    base_url: String,
+    sent: AtomicU64,
+    fallbacks: AtomicU64,
+    failures: AtomicU64,
+    consecutive_failures: AtomicU64,
+}
+
+/// Point-in-time counters of a provider endpoint.
+#[derive(Serialize, Debug)]
+pub struct EmailEndpointStats {
+    pub base_url: String,
+    pub routes: Vec<String>,
+    /// Emails accepted by this endpoint.
+    pub sent: u64,
+    /// Emails accepted by this endpoint after an earlier one in the route failed.
+    pub fallbacks: u64,
+    /// Timeouts, transport errors, rate limiting and 5xx responses.
+    pub failures: u64,
+    /// Failures since the last email this endpoint accepted.
+    pub consecutive_failures: u64,
 }
 
 impl EmailClient {
+    /// `http_client` is shared by all endpoints so that connections to the
+    /// provider are pooled across sends.
     pub fn new(
+        http_client: reqwest::Client,
         base_url: String,
         sender: SubscriberEmail,
+        sender_name: String,
         authorization_token: SecretString,
-        timeout_duration: std::time::Duration,
     ) -> Self {
-        let http_client = reqwest::Client::builder()
-            .timeout(timeout_duration)
-            .build()
-            .unwrap();
-
         Self {
             http_client,
-            base_url,
+            endpoints: vec![EmailEndpoint::new(base_url)],
+            default_route: vec![0],
+            regional_routes: HashMap::new(),
             sender,
+            sender_name,
             authorization_token,
+            supports_amp: false,
+            encode_subjects: false,
+            retry_policy: RetryPolicy::none(),
+            max_concurrent_sends: 1,
+            pause_switch: None,
+        }
+    }
+
+    /// Let bulk senders, such as the delivery worker, have up to
+    /// `max_concurrent_sends` emails in flight at once.
+    pub fn with_max_concurrent_sends(mut self, max_concurrent_sends: NonZeroUsize) -> Self {
+        self.max_concurrent_sends = max_concurrent_sends.get();
+        self
+    }
+
+    pub fn max_concurrent_sends(&self) -> usize {
+        self.max_concurrent_sends
+    }
+
+    /// Retry transient failures of an endpoint before falling back to the
+    /// next one.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Hold back every email while the `PAUSE_DELIVERIES` flag is on,
+    /// whichever part of the application sends it.
+    pub fn with_pause_switch(mut self, feature_flags: FeatureFlags, pg_pool: PgPool) -> Self {
+        self.pause_switch = Some(PauseSwitch {
+            feature_flags,
+            pg_pool,
+            last_read: tokio::sync::Mutex::new(None),
+        });
+        self
+    }
+
+    /// Whether emails are held back by the `PAUSE_DELIVERIES` flag, as read
+    /// at most [`PAUSE_CHECK_INTERVAL`] ago. The last value read is kept
+    /// while reading the flag again fails.
+    pub async fn is_paused(&self) -> Result<bool, sqlx::Error> {
+        let Some(switch) = &self.pause_switch else {
+            return Ok(false);
+        };
+        let mut last_read = switch.last_read.lock().await;
+        if let Some((read_at, paused)) = *last_read
+            && read_at.elapsed() < PAUSE_CHECK_INTERVAL
+        {
+            return Ok(paused);
+        }
+        match switch.feature_flags.load(&switch.pg_pool).await {
+            Ok(flags) => {
+                let paused = flags.is_enabled(PAUSE_DELIVERIES);
+                *last_read = Some((Instant::now(), paused));
+                Ok(paused)
+            }
+            Err(e) => match *last_read {
+                Some((_, paused)) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        paused,
+                        "Failed to check whether deliveries are paused, keeping the last known state",
+                    );
+                    Ok(paused)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Read `PAUSE_DELIVERIES` again before the next email, e.g. once it
+    /// was changed from this instance.
+    pub async fn forget_pause_state(&self) {
+        if let Some(switch) = &self.pause_switch {
+            *switch.last_read.lock().await = None;
+        }
+    }
+
+    /// Pass AMP bodies through to the provider.
+    pub fn with_amp_support(mut self) -> Self {
+        self.supports_amp = true;
+        self
+    }
+
+    /// Send subjects that are not plain ASCII as RFC 2047 encoded words.
+    pub fn with_encoded_subjects(mut self) -> Self {
+        self.encode_subjects = true;
+        self
+    }
+
+    /// Add an endpoint to try when the default ones fail.
+    pub fn with_fallback(mut self, base_url: String) -> Self {
+        let index = self.endpoint_index(base_url);
+        self.default_route.push(index);
+        self
+    }
+
+    /// Route subscribers of `region` through `base_urls`, tried in order.
+    /// They never fall back to the default endpoints, so at least one is
+    /// required.
+    pub fn with_region(
+        mut self,
+        region: &SubscriberRegion,
+        base_urls: Vec<String>,
+    ) -> Result<Self, String> {
+        if base_urls.is_empty() {
+            return Err(format!(
+                "Region {} must have at least one email endpoint.",
+                region.as_ref()
+            ));
         }
+        let route = base_urls
+            .into_iter()
+            .map(|base_url| self.endpoint_index(base_url))
+            .collect();
+        self.regional_routes
+            .insert(region.as_ref().to_owned(), route);
+        Ok(self)
     }
 
+    fn endpoint_index(&mut self, base_url: String) -> usize {
+        match self.endpoints.iter().position(|e| e.base_url == base_url) {
+            Some(index) => index,
+            None => {
+                self.endpoints.push(EmailEndpoint::new(base_url));
+                self.endpoints.len() - 1
+            }
+        }
+    }
+
+    pub fn endpoint_stats(&self) -> Vec<EmailEndpointStats> {
+        self.endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, endpoint)| {
+                let mut routes: Vec<String> = self
+                    .regional_routes
+                    .iter()
+                    .filter(|(_, route)| route.contains(&index))
+                    .map(|(region, _)| region.clone())
+                    .collect();
+                routes.sort();
+                if self.default_route.contains(&index) {
+                    routes.insert(0, "default".into());
+                }
+                EmailEndpointStats {
+                    base_url: endpoint.base_url.clone(),
+                    routes,
+                    sent: endpoint.sent.load(Ordering::Relaxed),
+                    fallbacks: endpoint.fallbacks.load(Ordering::Relaxed),
+                    failures: endpoint.failures.load(Ordering::Relaxed),
+                    consecutive_failures: endpoint.consecutive_failures.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    /// Send an email through the default endpoints, returning the id the
+    /// provider assigned to the message (if it reported one) so that later
+    /// webhook events can be matched to it.
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = format!("{}/api/send", self.base_url);
-        let sender = EmailInfo {
-            email: self.sender.as_ref(),
-            name: "",
+    ) -> Result<Option<String>, EmailClientError> {
+        self.send_email_in_region(None, recipient, subject, html_content, text_content)
+            .await
+    }
+
+    /// Send an email through the endpoints configured for the recipient's
+    /// region, or the default ones if the region has no dedicated route.
+    pub async fn send_email_in_region(
+        &self,
+        region: Option<&SubscriberRegion>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Option<String>, EmailClientError> {
+        self.send_email_with_extras_in_region(
+            region,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            &ExtraParts::default(),
+        )
+        .await
+    }
+
+    /// Like [`EmailClient::send_email_in_region`], with an AMP for Email
+    /// body when the provider supports it and attachments.
+    pub async fn send_email_with_extras_in_region(
+        &self,
+        region: Option<&SubscriberRegion>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        extras: &ExtraParts<'_>,
+    ) -> Result<Option<String>, EmailClientError> {
+        if self
+            .is_paused()
+            .await
+            .map_err(EmailClientError::PauseUnknown)?
+        {
+            return Err(EmailClientError::Paused);
+        }
+        let extras = ExtraParts {
+            amp: extras.amp.filter(|_| self.supports_amp),
+            attachments: extras.attachments,
+            from: extras.from,
+            headers: extras.headers,
         };
+        let route = region
+            .and_then(|r| self.regional_routes.get(r.as_ref()))
+            .unwrap_or(&self.default_route);
+        let mut last_error = None;
+        for (position, &index) in route.iter().enumerate() {
+            let endpoint = &self.endpoints[index];
+            let outcome = self
+                .send_with_retries(
+                    endpoint,
+                    recipient,
+                    subject,
+                    html_content,
+                    text_content,
+                    &extras,
+                )
+                .await;
+            match outcome {
+                Ok(message_id) => {
+                    endpoint.sent.fetch_add(1, Ordering::Relaxed);
+                    endpoint.consecutive_failures.store(0, Ordering::Relaxed);
+                    if position > 0 {
+                        endpoint.fallbacks.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Ok(message_id);
+                }
+                Err(e) if e.is_endpoint_failure() => {
+                    endpoint.failures.fetch_add(1, Ordering::Relaxed);
+                    endpoint
+                        .consecutive_failures
+                        .fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        base_url = endpoint.base_url,
+                        "Email endpoint failed, trying the next one in the route",
+                    );
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.expect("Every route has at least one endpoint"))
+    }
+
+    /// Send through `endpoint`, retrying timeouts and 5xx responses as the
+    /// retry policy allows.
+    async fn send_with_retries(
+        &self,
+        endpoint: &EmailEndpoint,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        extras: &ExtraParts<'_>,
+    ) -> Result<Option<String>, EmailClientError> {
+        let mut attempt = 1;
+        loop {
+            let span =
+                tracing::info_span!("Email send attempt", attempt, base_url = endpoint.base_url,);
+            let outcome = self
+                .send_via(
+                    endpoint,
+                    recipient,
+                    subject,
+                    html_content,
+                    text_content,
+                    extras,
+                )
+                .instrument(span)
+                .await;
+            match outcome {
+                Err(e) if e.is_retryable() && attempt < self.retry_policy.max_attempts => {
+                    let delay = self.retry_policy.delay(attempt);
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        base_url = endpoint.base_url,
+                        attempt,
+                        delay_millis = delay.as_millis() as u64,
+                        "Email send failed, retrying",
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) if attempt > 1 => {
+                    return Err(EmailClientError::RetriesExhausted {
+                        attempts: attempt,
+                        last_error: Box::new(e),
+                    });
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    async fn send_via(
+        &self,
+        endpoint: &EmailEndpoint,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        extras: &ExtraParts<'_>,
+    ) -> Result<Option<String>, EmailClientError> {
+        let url = format!("{}/api/send", endpoint.base_url);
+        let sender = extras.from.unwrap_or(EmailInfo {
+            email: self.sender.as_ref(),
+            name: &self.sender_name,
+        });
         let to = EmailInfo {
             email: recipient.as_ref(),
             name: "",
         };
         let request_body = SendEmailRequest {
-            subject: subject.into(),
+            subject: if self.encode_subjects {
+                encode_subject(subject)
+            } else {
+                subject.into()
+            },
             from: sender,
             to: vec![to],
             text: text_content.into(),
             html: html_content.into(),
+            amp_html: extras.amp.map(Into::into),
+            attachments: extras.attachments.to_vec(),
+            headers: extras.headers.to_vec(),
             category: "".into(),
         };
-        self.http_client
-            .post(&url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", self.authorization_token.expose_secret()),
-            )
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+        let mut request = self.http_client.post(&url).header(
+            "Authorization",
+            format!("Bearer {}", self.authorization_token.expose_secret()),
+        );
+        if let Some(request_id) = current_request_id() {
+            request = request.header(REQUEST_ID_HEADER, request_id);
+        }
+        let response = request.json(&request_body).send().await?;
+        if !response.status().is_success() {
+            return Err(EmailClientError::from_response(response).await);
+        }
+        // The email has been accepted at this point: a response we cannot
+        // make sense of must not turn the send into a failure.
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.message_ids.into_iter().next());
+        Ok(message_id)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl EmailEndpoint {
+    fn new(base_url: String) -> Self {
+        Self {
+            base_url,
+            sent: AtomicU64::new(0),
+            fallbacks: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            consecutive_failures: AtomicU64::new(0),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SendEmailResponse {
+    #[serde(default)]
+    message_ids: Vec<String>,
+}
+
+/// Why the provider did not accept an email, detailed enough for callers to
+/// decide whether to retry later, skip the recipient or give up.
+#[derive(thiserror::Error, Debug)]
+pub enum EmailClientError {
+    #[error("Deliveries are paused, the email was not sent.")]
+    Paused,
+    #[error("Failed to check whether deliveries are paused.")]
+    PauseUnknown(#[source] sqlx::Error),
+    #[error("The email provider did not answer in time.")]
+    Timeout(#[source] reqwest::Error),
+    #[error("The email provider is rate limiting us.")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("The email provider rejected the recipient: {0}")]
+    InvalidRecipient(String),
+    #[error("The email provider failed with status {status}: {body}")]
+    ProviderError {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("Failed to reach the email provider.")]
+    Transport(#[source] reqwest::Error),
+    #[error("The email provider still failed after {attempts} attempts.")]
+    RetriesExhausted {
+        attempts: u32,
+        #[source]
+        last_error: Box<EmailClientError>,
+    },
+}
+
+impl From<reqwest::Error> for EmailClientError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            EmailClientError::Timeout(e)
+        } else {
+            EmailClientError::Transport(e)
+        }
+    }
+}
+
+/// Error payload returned by the provider, e.g.
+/// `{"success": false, "errors": ["'to' address is invalid"]}`.
+#[derive(Deserialize, Debug)]
+struct ProviderErrorResponse {
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+impl EmailClientError {
+    /// Whether the failure is down to the endpoint rather than the email,
+    /// in which case another endpoint may well accept it.
+    pub fn is_endpoint_failure(&self) -> bool {
+        match self {
+            EmailClientError::Timeout(_)
+            | EmailClientError::Transport(_)
+            | EmailClientError::RateLimited { .. } => true,
+            EmailClientError::ProviderError { status, .. } => status.is_server_error(),
+            EmailClientError::InvalidRecipient(_)
+            | EmailClientError::Paused
+            | EmailClientError::PauseUnknown(_) => false,
+            EmailClientError::RetriesExhausted { last_error, .. } => {
+                last_error.is_endpoint_failure()
+            }
+        }
+    }
+
+    /// Whether the same endpoint may accept the email if asked again.
+    fn is_retryable(&self) -> bool {
+        match self {
+            EmailClientError::Timeout(_) => true,
+            EmailClientError::ProviderError { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.trim().parse().ok())
+                .map(Duration::from_secs);
+            return EmailClientError::RateLimited { retry_after };
+        }
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(e) => return e.into(),
+        };
+        if status.is_client_error() {
+            let errors = serde_json::from_str::<ProviderErrorResponse>(&body)
+                .map(|r| r.errors)
+                .unwrap_or_default();
+            if let Some(error) = errors.into_iter().find(|e| is_about_the_recipient(e)) {
+                return EmailClientError::InvalidRecipient(error);
+            }
+        }
+        EmailClientError::ProviderError { status, body }
+    }
+}
+
+/// Fields of the request holding recipients.
+const RECIPIENT_FIELDS: [&str; 3] = ["to", "cc", "bcc"];
+
+/// Provider errors name the field at fault first, e.g. `'to' address is
+/// invalid`: the field is matched exactly, never the wording.
+fn is_about_the_recipient(error: &str) -> bool {
+    error
+        .strip_prefix('\'')
+        .and_then(|rest| rest.split_once('\''))
+        .is_some_and(|(field, _)| RECIPIENT_FIELDS.contains(&field))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct EmailInfo<'a> {
     pub email: &'a str,
     pub name: &'a str,
 }
 
+/// Parts of an email beyond its HTML and text bodies.
+#[derive(Default)]
+pub struct ExtraParts<'a> {
+    pub amp: Option<&'a str>,
+    pub attachments: &'a [Attachment],
+    /// Sender in place of the configured one, e.g. a verified sender.
+    pub from: Option<EmailInfo<'a>>,
+    pub headers: &'a [EmailHeader],
+}
+
+/// A header added to the email, e.g. `List-Unsubscribe`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub filename: String,
+    #[serde(rename = "type")]
+    pub content_type: String,
+    /// Base64 encoded.
+    pub content: String,
+    pub disposition: String,
+}
+
+impl Attachment {
+    pub fn new(filename: &str, content_type: &str, content: &[u8]) -> Self {
+        Self {
+            filename: filename.to_owned(),
+            content_type: content_type.to_owned(),
+            content: BASE64_STANDARD.encode(content),
+            disposition: "attachment".to_owned(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SendEmailRequest<'a> {
     pub from: EmailInfo<'a>,
@@ -84,6 +677,13 @@ pub struct SendEmailRequest<'a> {
     pub text: Cow<'a, str>,
     #[serde(borrow)]
     pub html: Cow<'a, str>,
+    /// Sent as the `text/x-amp-html` part.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub amp_html: Option<Cow<'a, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<EmailHeader>,
     #[serde(borrow)]
     pub category: Cow<'a, str>,
 }
@@ -91,13 +691,14 @@ pub struct SendEmailRequest<'a> {
 #[cfg(test)]
 mod tests {
     use crate::EmailClient;
-    use crate::domain::SubscriberEmail;
+    use crate::domain::{SubscriberEmail, SubscriberRegion};
+    use crate::email_client::{EmailClientError, RetryPolicy, is_about_the_recipient};
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::{SecretBox, SecretString};
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
     struct SendEmailBodyMatcher;
 
@@ -130,12 +731,11 @@ mod tests {
 
     /// Get a test instance of `EmailClient`
     fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
-            base_url,
-            email(),
-            token(),
-            std::time::Duration::from_millis(200),
-        )
+        let http_client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(200))
+            .build()
+            .unwrap();
+        EmailClient::new(http_client, base_url, email(), Faker.fake(), token())
     }
 
     #[tokio::test]
@@ -158,6 +758,26 @@ mod tests {
             .await;
     }
 
+    #[tokio::test]
+    async fn subjects_are_encoded_when_the_provider_needs_it() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_encoded_subjects();
+
+        Mock::given(body_partial_json(
+            serde_json::json!({"subject": "=?UTF-8?B?8J+OiSBMYXVuY2g=?="}),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let outcome = email_client
+            .send_email(&email(), "🎉 Launch", &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         // Arrange
@@ -179,6 +799,33 @@ mod tests {
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_provider_message_id() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true,
+                "message_ids": ["0c7fd939-02cf-11ed-88c2-0a58a9feac02"]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(
+            assert_ok!(outcome).as_deref(),
+            Some("0c7fd939-02cf-11ed-88c2-0a58a9feac02")
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         // Arrange
@@ -220,7 +867,384 @@ mod tests {
             .send_email(&email(), &subject(), &content(), &content())
             .await;
 
+        // Assert
+        assert!(matches!(assert_err!(outcome), EmailClientError::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_rate_limiting_with_the_retry_delay() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            assert_err!(outcome),
+            EmailClientError::RateLimited {
+                retry_after: Some(d)
+            } if d == std::time::Duration::from_secs(30)
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_invalid_recipients() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "success": false,
+                "errors": ["'to' address is invalid"]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            assert_err!(outcome),
+            EmailClientError::InvalidRecipient(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_keeps_the_body_of_other_provider_errors() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        match assert_err!(outcome) {
+            EmailClientError::ProviderError { status, body } => {
+                assert_eq!(status.as_u16(), 503);
+                assert_eq!(body, "Service Unavailable");
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn only_errors_about_a_recipient_field_are_about_the_recipient() {
+        assert!(is_about_the_recipient("'to' address is invalid"));
+        assert!(is_about_the_recipient("'bcc' address is invalid"));
+        assert!(!is_about_the_recipient("'from' address is invalid email"));
+        assert!(!is_about_the_recipient(
+            "Sending to this recipient is not allowed on your plan"
+        ));
+    }
+
+    fn region(name: &str) -> SubscriberRegion {
+        SubscriberRegion::parse(name.into()).unwrap()
+    }
+
+    #[test]
+    fn regions_without_an_endpoint_are_rejected() {
+        let result = email_client("http://127.0.0.1".into()).with_region(&region("eu"), vec![]);
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_in_region_uses_the_regional_endpoint() {
+        // Arrange
+        let default_server = MockServer::start().await;
+        let eu_server = MockServer::start().await;
+        let email_client = email_client(default_server.uri())
+            .with_region(&region("eu"), vec![eu_server.uri()])
+            .unwrap();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&default_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&eu_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_in_region(
+                Some(&region("eu")),
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_back_to_the_next_endpoint_of_the_route() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let fallback_server = MockServer::start().await;
+        let email_client = email_client(primary_server.uri()).with_fallback(fallback_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let stats = email_client.endpoint_stats();
+        assert_eq!((stats[0].sent, stats[0].failures), (0, 1));
+        assert_eq!(stats[0].consecutive_failures, 1);
+        assert_eq!((stats[1].sent, stats[1].fallbacks), (1, 1));
+        assert_eq!(stats[1].consecutive_failures, 0);
+    }
+
+    fn retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: std::time::Duration::from_millis(1),
+            jitter: 0.5,
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_a_transient_server_error() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_retry_policy(retry_policy(3));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let stats = email_client.endpoint_stats();
+        assert_eq!((stats[0].sent, stats[0].failures), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_attempts_once_retries_are_exhausted() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_retry_policy(retry_policy(3));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        match assert_err!(outcome) {
+            EmailClientError::RetriesExhausted {
+                attempts,
+                last_error,
+            } => {
+                assert_eq!(attempts, 3);
+                assert!(matches!(
+                    *last_error,
+                    EmailClientError::ProviderError { .. }
+                ));
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_client_errors() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_retry_policy(retry_policy(3));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_back_once_retries_are_exhausted() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let fallback_server = MockServer::start().await;
+        let email_client = email_client(primary_server.uri())
+            .with_fallback(fallback_server.uri())
+            .with_retry_policy(retry_policy(2));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let stats = email_client.endpoint_stats();
+        assert_eq!((stats[0].sent, stats[0].failures), (0, 1));
+        assert_eq!((stats[1].sent, stats[1].fallbacks), (1, 1));
+    }
+
+    #[test]
+    fn retry_delays_grow_exponentially_within_the_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: std::time::Duration::from_millis(100),
+            jitter: 0.5,
+        };
+        for (attempt, expected) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = policy.delay(attempt).as_millis();
+            assert!(
+                (expected / 2..=expected * 3 / 2).contains(&delay),
+                "attempt {}: {}ms",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn regional_sends_never_fall_back_to_the_default_endpoints() {
+        // Arrange
+        let default_server = MockServer::start().await;
+        let eu_server = MockServer::start().await;
+        let email_client = email_client(default_server.uri())
+            .with_region(&region("eu"), vec![eu_server.uri()])
+            .unwrap();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&default_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&eu_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_in_region(
+                Some(&region("eu")),
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
+            .await;
+
         // Assert
         assert_err!(outcome);
     }
+
+    #[tokio::test]
+    async fn invalid_recipients_are_not_retried_on_another_endpoint() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let fallback_server = MockServer::start().await;
+        let email_client = email_client(primary_server.uri()).with_fallback(fallback_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "success": false,
+                "errors": ["'to' address is invalid"]
+            })))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            assert_err!(outcome),
+            EmailClientError::InvalidRecipient(_)
+        ));
+    }
 }
@@ -0,0 +1,155 @@
+use crate::configuration::EncryptionSettings;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+
+/// Marks values encrypted by [`FieldCipher`], the version leaves room for
+/// another algorithm.
+const PREFIX: &str = "enc:v1:";
+
+/// Application-level encryption of sensitive columns: newsletter bodies and
+/// subscriber names.
+///
+/// Values are sealed with AES-256-GCM under a random nonce and stored as
+/// `enc:v1:{base64(nonce || ciphertext)}`. Values without the prefix are
+/// read as plain text, so that encryption can be turned on for a database
+/// holding data written before. Without a key, values are stored as is.
+#[derive(Clone)]
+pub struct FieldCipher {
+    key: Option<Arc<LessSafeKey>>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DecryptionError {
+    #[error("The value is encrypted but no encryption key is configured.")]
+    MissingKey,
+    #[error("The value could not be decrypted with the configured key.")]
+    Invalid,
+}
+
+impl FieldCipher {
+    pub fn new(settings: Option<&EncryptionSettings>) -> Self {
+        let key = settings.map(|settings| {
+            let bytes = BASE64_STANDARD
+                .decode(settings.key.expose_secret())
+                .expect("The encryption key is validated when loading the configuration");
+            let key = UnboundKey::new(&AES_256_GCM, &bytes)
+                .expect("The encryption key is validated when loading the configuration");
+            Arc::new(LessSafeKey::new(key))
+        });
+        Self { key }
+    }
+
+    pub fn disabled() -> Self {
+        Self { key: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let Some(key) = &self.key else {
+            return plaintext.to_owned();
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .expect("The system random number generator failed");
+        let mut sealed = plaintext.as_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .expect("Values are too small for sealing to fail");
+        let mut stored = nonce.to_vec();
+        stored.extend(sealed);
+        format!("{}{}", PREFIX, BASE64_STANDARD.encode(stored))
+    }
+
+    pub fn decrypt(&self, stored: String) -> Result<String, DecryptionError> {
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored);
+        };
+        let key = self.key.as_ref().ok_or(DecryptionError::MissingKey)?;
+        let decoded = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|_| DecryptionError::Invalid)?;
+        if decoded.len() < NONCE_LEN {
+            return Err(DecryptionError::Invalid);
+        }
+        let (nonce, sealed) = decoded.split_at(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| DecryptionError::Invalid)?;
+        let mut sealed = sealed.to_vec();
+        let plaintext = key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| DecryptionError::Invalid)?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| DecryptionError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecryptionError, FieldCipher};
+    use crate::configuration::EncryptionSettings;
+    use claims::{assert_err, assert_ok_eq};
+
+    fn cipher(key: &str) -> FieldCipher {
+        FieldCipher::new(Some(&EncryptionSettings {
+            key: key.to_string().into(),
+        }))
+    }
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+    const OTHER_KEY: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+    #[test]
+    fn encrypted_values_round_trip() {
+        let cipher = cipher(KEY);
+        let stored = cipher.encrypt("<p>Newsletter body</p>");
+        assert!(stored.starts_with("enc:v1:"));
+        assert!(!stored.contains("Newsletter"));
+        assert_ok_eq!(cipher.decrypt(stored), "<p>Newsletter body</p>");
+    }
+
+    #[test]
+    fn the_same_value_is_encrypted_differently_each_time() {
+        let cipher = cipher(KEY);
+        assert_ne!(cipher.encrypt("le guin"), cipher.encrypt("le guin"));
+    }
+
+    #[test]
+    fn plain_text_values_are_read_as_is() {
+        assert_ok_eq!(cipher(KEY).decrypt("le guin".into()), "le guin");
+        assert_ok_eq!(FieldCipher::disabled().decrypt("le guin".into()), "le guin");
+    }
+
+    #[test]
+    fn values_are_stored_as_is_without_a_key() {
+        assert_eq!(FieldCipher::disabled().encrypt("le guin"), "le guin");
+    }
+
+    #[test]
+    fn encrypted_values_cannot_be_read_without_the_key() {
+        let stored = cipher(KEY).encrypt("le guin");
+        assert!(matches!(
+            FieldCipher::disabled().decrypt(stored.clone()),
+            Err(DecryptionError::MissingKey)
+        ));
+        assert_err!(cipher(OTHER_KEY).decrypt(stored));
+    }
+
+    #[test]
+    fn tampered_values_are_rejected() {
+        let stored = cipher(KEY).encrypt("le guin");
+        let mut tampered = stored.into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert_err!(cipher(KEY).decrypt(String::from_utf8(tampered).unwrap()));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::domain::SubscriberValidator;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web::ServiceConfig;
+use actix_web::{HttpResponse, web};
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// A new subscriber, pending confirmation. Subscriptions quarantined as part
+/// of a signup burst are left out.
+#[derive(Debug, Clone)]
+pub struct Subscribed {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+}
+
+/// A subscriber who followed their confirmation link.
+#[derive(Debug, Clone)]
+pub struct Confirmed {
+    pub subscriber_id: Uuid,
+}
+
+/// An issue stored and queued for delivery to its segment.
+#[derive(Debug, Clone)]
+pub struct Published {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+}
+
+/// Told about domain events once they are committed, e.g. to feed a CRM.
+///
+/// Listeners run in the background, in the order they were registered, and
+/// cannot fail the request or the job the event comes from.
+pub trait EventListener: Send + Sync {
+    fn on_subscribe<'a>(&'a self, _event: &'a Subscribed) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    fn on_confirm<'a>(&'a self, _event: &'a Confirmed) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    fn on_publish<'a>(&'a self, _event: &'a Published) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// Hands domain events over to the registered [`EventListener`]s.
+#[derive(Clone, Default)]
+pub struct DomainEvents(Arc<Vec<Arc<dyn EventListener>>>);
+
+impl DomainEvents {
+    pub fn subscribed(&self, event: Subscribed) {
+        self.notify(move |listener| {
+            let event = event.clone();
+            Box::pin(async move { listener.on_subscribe(&event).await })
+        });
+    }
+
+    pub fn confirmed(&self, event: Confirmed) {
+        self.notify(move |listener| {
+            let event = event.clone();
+            Box::pin(async move { listener.on_confirm(&event).await })
+        });
+    }
+
+    pub fn published(&self, event: Published) {
+        self.notify(move |listener| {
+            let event = event.clone();
+            Box::pin(async move { listener.on_publish(&event).await })
+        });
+    }
+
+    fn notify(
+        &self,
+        call: impl Fn(Arc<dyn EventListener>) -> BoxFuture<'static, ()> + Send + 'static,
+    ) {
+        if self.0.is_empty() {
+            return;
+        }
+        let listeners = self.0.clone();
+        tokio::spawn(
+            async move {
+                for listener in listeners.iter() {
+                    call(listener.clone()).await;
+                }
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+/// Runs around every request of the public and admin listeners.
+pub trait Middleware: Send + Sync {
+    /// Answer `request` straight away, or let it through with `None`.
+    fn on_request(&self, _request: &ServiceRequest) -> Option<HttpResponse> {
+        None
+    }
+
+    /// Amend the response, e.g. with a header.
+    fn on_response(&self, _response: &mut ServiceResponse<BoxBody>) {}
+}
+
+type ConfigureServices = dyn Fn(&mut ServiceConfig) + Send + Sync;
+
+/// What a binary embedding the application adds to it, registered with
+/// [`ServerBuilder::extensions`](crate::server::ServerBuilder::extensions)
+/// rather than by patching [`startup`](crate::startup).
+#[derive(Clone, Default)]
+pub struct Extensions {
+    services: Vec<Arc<ConfigureServices>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    listeners: Vec<Arc<dyn EventListener>>,
+    validators: Vec<Arc<dyn SubscriberValidator>>,
+}
+
+impl Extensions {
+    /// Routes served by the public listener, next to the built-in ones which
+    /// take precedence.
+    pub fn service(
+        mut self,
+        configure: impl Fn(&mut ServiceConfig) + Send + Sync + 'static,
+    ) -> Self {
+        self.services.push(Arc::new(configure));
+        self
+    }
+
+    /// Middleware run in the order registered, within the tracing of the
+    /// request but ahead of sessions, rate limiting and maintenance mode.
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn listener(mut self, listener: impl EventListener + 'static) -> Self {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// Checks new subscribers go through after the configured
+    /// `subscriber_validation` patterns.
+    pub fn validator(mut self, validator: impl SubscriberValidator + 'static) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    pub fn events(&self) -> DomainEvents {
+        DomainEvents(Arc::new(self.listeners.clone()))
+    }
+
+    pub(crate) fn validators(&self) -> Vec<Arc<dyn SubscriberValidator>> {
+        self.validators.clone()
+    }
+
+    pub(crate) fn configure(&self, cfg: &mut ServiceConfig) {
+        for configure in &self.services {
+            configure(cfg);
+        }
+    }
+}
+
+/// The registered [`Middleware`] of the [`Extensions`], found in the app
+/// data.
+pub async fn run_extension_middleware(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(extensions) = request.app_data::<web::Data<Extensions>>().cloned() else {
+        return Ok(next.call(request).await?.map_into_boxed_body());
+    };
+    for middleware in &extensions.middleware {
+        if let Some(response) = middleware.on_request(&request) {
+            return Ok(request.into_response(response));
+        }
+    }
+    let mut response = next.call(request).await?.map_into_boxed_body();
+    for middleware in extensions.middleware.iter().rev() {
+        middleware.on_response(&mut response);
+    }
+    Ok(response)
+}
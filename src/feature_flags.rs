@@ -0,0 +1,207 @@
+use crate::configuration::FeatureFlagSettings;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Open-tracking pixel in newsletter issues, rolled out per subscriber.
+pub const OPEN_TRACKING: &str = "open_tracking";
+/// Holds back every outgoing email, e.g. during an incident at the email
+/// provider. Issues and confirmation emails are queued until it is off, the
+/// [`EmailClient`](crate::email_client::EmailClient) refuses any other email.
+pub const PAUSE_DELIVERIES: &str = "pause_deliveries";
+
+/// Flags gating risky behaviors, so that they can be turned on per
+/// environment, or for a share of subscribers, without a redeploy.
+///
+/// Flags come from the configuration and are overridden by the rows of the
+/// `feature_flags` table, set through `/admin/feature_flags`. Unknown flags
+/// are off.
+#[derive(Clone)]
+pub struct FeatureFlags {
+    defaults: HashMap<String, FeatureFlagSettings>,
+}
+
+/// Where the value of a flag comes from.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagSource {
+    Configuration,
+    Database,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Flag {
+    pub name: String,
+    #[serde(flatten)]
+    pub settings: FeatureFlagSettings,
+    pub source: FlagSource,
+}
+
+/// The flags in effect when they were loaded, to be evaluated many times,
+/// e.g. once per recipient of an issue.
+pub struct FlagSet {
+    flags: HashMap<String, Flag>,
+}
+
+impl FeatureFlags {
+    pub fn new(defaults: HashMap<String, FeatureFlagSettings>) -> Self {
+        Self { defaults }
+    }
+
+    #[tracing::instrument(name = "Load feature flags", skip_all)]
+    pub async fn load(&self, pg_pool: &PgPool) -> Result<FlagSet, sqlx::Error> {
+        let mut flags: HashMap<String, Flag> = self
+            .defaults
+            .iter()
+            .map(|(name, settings)| {
+                let flag = Flag {
+                    name: name.clone(),
+                    settings: settings.clone(),
+                    source: FlagSource::Configuration,
+                };
+                (name.clone(), flag)
+            })
+            .collect();
+        let overrides = sqlx::query!("SELECT name, enabled, rollout_percentage FROM feature_flags")
+            .fetch_all(pg_pool)
+            .await?;
+        for row in overrides {
+            let flag = Flag {
+                name: row.name.clone(),
+                settings: FeatureFlagSettings {
+                    enabled: row.enabled,
+                    rollout_percentage: row.rollout_percentage.map(|p| p.clamp(0, 100) as u8),
+                },
+                source: FlagSource::Database,
+            };
+            flags.insert(row.name, flag);
+        }
+        Ok(FlagSet { flags })
+    }
+}
+
+/// Override the configured value of a flag, on every instance.
+#[tracing::instrument(name = "Store feature flag override", skip(pg_pool))]
+pub async fn store_override(
+    pg_pool: &PgPool,
+    name: &str,
+    settings: &FeatureFlagSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO feature_flags (name, enabled, rollout_percentage, updated_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (name) DO UPDATE
+        SET enabled = EXCLUDED.enabled,
+            rollout_percentage = EXCLUDED.rollout_percentage,
+            updated_at = now()
+        "#,
+        name,
+        settings.enabled,
+        settings.rollout_percentage.map(i16::from),
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+impl FlagSet {
+    /// Whether `name` is on for everyone.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.flags.get(name).is_some_and(|flag| {
+            flag.settings.enabled && flag.settings.rollout_percentage.is_none_or(|p| p >= 100)
+        })
+    }
+
+    /// Whether `name` is on for `key`, e.g. a subscriber id. A given key
+    /// stays in the rollout as its percentage grows.
+    pub fn is_enabled_for(&self, name: &str, key: &str) -> bool {
+        self.flags.get(name).is_some_and(|flag| {
+            flag.settings.enabled
+                && flag
+                    .settings
+                    .rollout_percentage
+                    .is_none_or(|p| rollout_bucket(name, key) < p)
+        })
+    }
+
+    /// Every known flag, sorted by name.
+    pub fn all(&self) -> Vec<&Flag> {
+        let mut flags: Vec<_> = self.flags.values().collect();
+        flags.sort_by(|a, b| a.name.cmp(&b.name));
+        flags
+    }
+}
+
+/// Stable bucket in `0..100` of `key` for the flag `name`, hashing the name
+/// too so that different flags roll out to different keys first.
+fn rollout_bucket(name: &str, key: &str) -> u8 {
+    let digest = Sha256::digest(format!("{}:{}", name, key));
+    (u16::from_be_bytes([digest[0], digest[1]]) % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flag, FlagSet, FlagSource, rollout_bucket};
+    use crate::configuration::FeatureFlagSettings;
+    use std::collections::HashMap;
+
+    fn flag_set(enabled: bool, rollout_percentage: Option<u8>) -> FlagSet {
+        let flag = Flag {
+            name: "new_thing".into(),
+            settings: FeatureFlagSettings {
+                enabled,
+                rollout_percentage,
+            },
+            source: FlagSource::Configuration,
+        };
+        FlagSet {
+            flags: HashMap::from([("new_thing".to_string(), flag)]),
+        }
+    }
+
+    #[test]
+    fn unknown_flags_are_off() {
+        let flags = flag_set(true, None);
+        assert!(!flags.is_enabled("other_thing"));
+        assert!(!flags.is_enabled_for("other_thing", "key"));
+    }
+
+    #[test]
+    fn disabled_flags_are_off_whatever_the_rollout() {
+        let flags = flag_set(false, Some(100));
+        assert!(!flags.is_enabled("new_thing"));
+        assert!(!flags.is_enabled_for("new_thing", "key"));
+    }
+
+    #[test]
+    fn partial_rollouts_are_only_on_for_some_keys() {
+        let flags = flag_set(true, Some(30));
+        let enabled = (0..1000)
+            .filter(|i| flags.is_enabled_for("new_thing", &i.to_string()))
+            .count();
+        assert!((200..400).contains(&enabled), "{} keys enabled", enabled);
+        assert!(!flags.is_enabled("new_thing"));
+    }
+
+    #[test]
+    fn keys_stay_in_a_growing_rollout() {
+        let keys: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+        let small = flag_set(true, Some(10));
+        let large = flag_set(true, Some(50));
+        for key in keys
+            .iter()
+            .filter(|key| small.is_enabled_for("new_thing", key))
+        {
+            assert!(large.is_enabled_for("new_thing", key));
+        }
+    }
+
+    #[test]
+    fn full_rollouts_are_on_for_everyone() {
+        let flags = flag_set(true, Some(100));
+        assert!(flags.is_enabled("new_thing"));
+        assert!(flags.is_enabled_for("new_thing", "key"));
+        assert!(rollout_bucket("new_thing", "key") < 100);
+    }
+}
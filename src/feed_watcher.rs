@@ -0,0 +1,338 @@
+use crate::EmailClient;
+use crate::configuration::{EmailTemplate, FeedSettings, Settings, ShortLinkSettings};
+use crate::delivery::IssueDelivery;
+use crate::encryption::FieldCipher;
+use crate::extensions::DomainEvents;
+use crate::feature_flags::FeatureFlags;
+use crate::jobs::Job;
+use crate::leader_election::LeaderElection;
+use crate::publishing::{
+    IssueContent, IssuePublishing, SubscriberFooter, escape_html, insert_draft, publish_draft,
+};
+use crate::signing::UrlSigner;
+use crate::templates::EmailTemplates;
+use crate::throttling::DeliveryThrottle;
+use crate::tracking::TrackingMode;
+use crate::web_pages::{PageFetcher, sanitize_fragment};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use url::Url;
+use uuid::Uuid;
+
+/// Polls a blog's RSS or Atom feed and turns every new entry into a
+/// newsletter draft, published straight away when `auto_publish` is set.
+///
+/// The entries found on the first poll of a feed are only remembered, so
+/// enabling the watcher does not mail out the whole blog archive.
+pub struct FeedWatcher {
+    settings: FeedSettings,
+    template: EmailTemplate,
+    footer: SubscriberFooter,
+    http_client: reqwest::Client,
+    pg_pool: PgPool,
+    cipher: FieldCipher,
+    page_fetcher: PageFetcher,
+    feature_flags: FeatureFlags,
+    email_client: Arc<EmailClient>,
+    throttle: Arc<DeliveryThrottle>,
+    link_base_url: String,
+    short_link_settings: ShortLinkSettings,
+    tracking_mode: TrackingMode,
+    events: DomainEvents,
+    leader_election: Arc<LeaderElection>,
+    job: Arc<Job>,
+}
+
+impl FeedWatcher {
+    /// `None` when no feed is configured.
+    pub fn build(
+        configuration: &Settings,
+        pg_pool: PgPool,
+        email_client: Arc<EmailClient>,
+        throttle: Arc<DeliveryThrottle>,
+    ) -> Option<Self> {
+        let settings = configuration.feed.clone()?;
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.timeout)
+            .build()
+            .expect("Failed to build the feed HTTP client");
+        Some(Self {
+            settings,
+            template: configuration.email_templates.feed_entry.clone(),
+            footer: SubscriberFooter::new(
+                EmailTemplates::new(configuration.email_templates.clone()),
+                UrlSigner::new(configuration.application.hmac_secret.clone()),
+            ),
+            http_client,
+            leader_election: Arc::new(LeaderElection::new("feed_watcher", pg_pool.clone())),
+            pg_pool,
+            cipher: FieldCipher::new(configuration.encryption.as_ref()),
+            page_fetcher: PageFetcher::new(&configuration.web_pages),
+            feature_flags: FeatureFlags::new(configuration.feature_flags.clone()),
+            email_client,
+            throttle,
+            link_base_url: configuration.application.link_base_url().to_owned(),
+            short_link_settings: configuration.short_links.clone(),
+            tracking_mode: configuration.tracking.mode,
+            events: DomainEvents::default(),
+            job: Job::new("feed_watcher"),
+        })
+    }
+
+    /// Tell the listeners of `events` about the issues published.
+    pub fn with_events(mut self, events: DomainEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    /// Only the leader among the running instances polls the feed.
+    pub fn leader_election(&self) -> Arc<LeaderElection> {
+        self.leader_election.clone()
+    }
+
+    /// Poll the feed every `poll_interval`, the first poll happening one
+    /// interval after startup.
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(
+                self.settings.poll_interval,
+                Some(&self.leader_election),
+                &shutdown,
+                || async { self.poll_once().await.map(|_| ()) },
+            )
+            .await
+    }
+
+    /// Fetch the feed once and return the drafts created for new entries.
+    #[tracing::instrument(name = "Poll blog feed", skip(self), fields(feed_url=%self.settings.url))]
+    pub async fn poll_once(&self) -> Result<Vec<Uuid>, anyhow::Error> {
+        let body = self
+            .http_client
+            .get(&self.settings.url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("Failed to fetch the feed")?
+            .bytes()
+            .await
+            .context("Failed to read the feed")?;
+        let feed = feed_rs::parser::parse(&body[..]).context("Failed to parse the feed")?;
+        let feed_url = Url::parse(&self.settings.url).context("Invalid feed URL")?;
+
+        let first_poll = self
+            .start_watching()
+            .await
+            .context("Failed to register the watched feed")?;
+        let mut drafts = Vec::new();
+        // Feeds list the most recent entries first.
+        for entry in feed.entries.iter().rev() {
+            let content = (!first_poll).then(|| self.render(entry, &feed_url));
+            let Some(newsletter_draft_id) = self.record_entry(&entry.id, content.as_ref()).await?
+            else {
+                continue;
+            };
+            if self.settings.auto_publish
+                && let Err(e) = publish_draft(
+                    IssueDelivery {
+                        pg_pool: &self.pg_pool,
+                        email_client: &self.email_client,
+                        throttle: &self.throttle,
+                        base_url: &self.link_base_url,
+                        footer: &self.footer,
+                    },
+                    IssuePublishing {
+                        cipher: &self.cipher,
+                        page_fetcher: &self.page_fetcher,
+                        feature_flags: &self.feature_flags,
+                        short_link_settings: &self.short_link_settings,
+                        events: &self.events,
+                    },
+                    newsletter_draft_id,
+                    self.tracking_mode,
+                    None,
+                )
+                .await
+            {
+                // The draft is kept: it can be published by hand, and the
+                // other entries are still turned into drafts.
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    %newsletter_draft_id,
+                    "Failed to publish the draft of a feed entry"
+                );
+            }
+            drafts.push(newsletter_draft_id);
+        }
+        Ok(drafts)
+    }
+
+    /// Feeds are written by anyone: only `http(s)` links are kept, and the
+    /// markup of summaries is cleaned before reaching the inboxes.
+    fn render(&self, entry: &feed_rs::model::Entry, feed_url: &Url) -> IssueContent {
+        let title = entry
+            .title
+            .as_ref()
+            .map(|t| t.content.as_str())
+            .unwrap_or_default();
+        let link = entry
+            .links
+            .iter()
+            .find(|l| l.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+            .or(entry.links.first())
+            .and_then(|l| feed_url.join(&l.href).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"));
+        let base_url = link.as_ref().unwrap_or(feed_url);
+        let summary = entry
+            .summary
+            .as_ref()
+            .map(|s| s.content.as_str())
+            .or_else(|| entry.content.as_ref().and_then(|c| c.body.as_deref()))
+            .unwrap_or_default();
+        let summary = sanitize_fragment(summary, base_url);
+
+        render_feed_entry(
+            &self.template,
+            title,
+            link.as_ref().map(Url::as_str).unwrap_or_default(),
+            &summary,
+        )
+    }
+
+    /// Returns `true` the first time the feed is polled.
+    async fn start_watching(&self) -> Result<bool, sqlx::Error> {
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO watched_feeds (feed_url, first_polled_at)
+            VALUES ($1, now())
+            ON CONFLICT DO NOTHING
+            "#,
+            self.settings.url,
+        )
+        .execute(&self.pg_pool)
+        .await?
+        .rows_affected();
+        Ok(inserted == 1)
+    }
+
+    /// Remember an entry, storing `content` as a draft if given.
+    ///
+    /// Returns `None` for entries seen before, or when there is no content.
+    #[tracing::instrument(name = "Record feed entry", skip(self, content))]
+    async fn record_entry(
+        &self,
+        entry_id: &str,
+        content: Option<&IssueContent>,
+    ) -> Result<Option<Uuid>, anyhow::Error> {
+        let mut transaction = self
+            .pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO feed_entries (feed_url, entry_id, seen_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT DO NOTHING
+            "#,
+            self.settings.url,
+            entry_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to record a feed entry")?
+        .rows_affected();
+        let (1, Some(content)) = (inserted, content) else {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to record a feed entry")?;
+            return Ok(None);
+        };
+
+        let newsletter_draft_id = insert_draft(&mut transaction, &self.cipher, content)
+            .await
+            .context("Failed to store the draft of a feed entry")?;
+        sqlx::query!(
+            r#"
+            UPDATE feed_entries
+            SET newsletter_draft_id = $3
+            WHERE feed_url = $1 AND entry_id = $2
+            "#,
+            self.settings.url,
+            entry_id,
+            newsletter_draft_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to link a feed entry to its draft")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record a feed entry")?;
+        Ok(Some(newsletter_draft_id))
+    }
+}
+
+/// Turn a feed entry into the content of an issue, `summary` being HTML.
+pub fn render_feed_entry(
+    template: &EmailTemplate,
+    title: &str,
+    link: &str,
+    summary: &str,
+) -> IssueContent {
+    let escaped_title = escape_html(title);
+    let text_summary = strip_html_tags(summary);
+    IssueContent {
+        title: template.render_subject(&[("title", title), ("link", link)]),
+        html: template.render_html(&[
+            ("title", &escaped_title),
+            ("link", &escape_html(link)),
+            ("summary", summary),
+        ]),
+        text: template.render_text(&[("title", title), ("link", link), ("summary", &text_summary)]),
+        amp: None,
+        event: None,
+        sender: None,
+        internal_copies: Vec::new(),
+    }
+}
+
+/// Good enough to turn a feed summary into plain text: tags are dropped,
+/// entities are left alone.
+fn strip_html_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_html_tags;
+
+    #[test]
+    fn tags_are_stripped_from_summaries() {
+        assert_eq!(
+            strip_html_tags("<p>Hello <a href=\"https://example.com\">world</a></p>"),
+            "Hello world"
+        );
+    }
+
+    #[test]
+    fn summaries_without_tags_are_kept() {
+        assert_eq!(strip_html_tags(" 3 > 2 "), "3 > 2");
+    }
+}
@@ -0,0 +1,181 @@
+use actix_web::HttpResponse;
+use actix_web::body::to_bytes;
+use actix_web::http::StatusCode;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Longest key accepted, clients usually send a UUID.
+const MAX_KEY_LENGTH: usize = 50;
+
+/// Key a client sends in the `Idempotency-Key` header, so that retrying a
+/// request returns the first response instead of processing it again.
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s.trim().is_empty() {
+            return Err("The idempotency key cannot be empty.".into());
+        }
+        if s.len() > MAX_KEY_LENGTH {
+            return Err(format!(
+                "The idempotency key cannot be longer than {} characters.",
+                MAX_KEY_LENGTH
+            ));
+        }
+        Ok(Self(s))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+pub enum NextAction {
+    /// First time the key is seen: process the request within the
+    /// transaction, then [`save_response`] in it.
+    StartProcessing(Transaction<'static, Postgres>),
+    ReturnSavedResponse(HttpResponse),
+}
+
+/// Reserve `idempotency_key` for this request, or return the response saved
+/// for it.
+///
+/// A request retried while the first one is still processed waits for it
+/// to commit, the key being reserved by a row it holds. Keys older than
+/// `expire_after` are forgotten.
+#[tracing::instrument(name = "Try processing an idempotent request", skip(pg_pool))]
+pub async fn try_processing(
+    pg_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    expire_after: Duration,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = pg_pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE user_id = $1 AND created_at < now() - make_interval(secs => $2)
+        "#,
+        user_id,
+        expire_after.as_secs_f64(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if inserted > 0 {
+        return Ok(NextAction::StartProcessing(transaction));
+    }
+    let saved_response = get_saved_response(&mut transaction, idempotency_key, user_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+    Ok(NextAction::ReturnSavedResponse(saved_response))
+}
+
+async fn get_saved_response(
+    connection: &mut PgConnection,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            response_status_code AS "response_status_code!",
+            response_headers AS "response_headers!: Vec<HeaderPairRecord>",
+            response_body AS "response_body!"
+        FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+    )
+    .fetch_optional(connection)
+    .await?;
+    let Some(r) = saved_response else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in r.response_headers {
+        response.append_header((name, value));
+    }
+    Ok(Some(response.body(r.response_body)))
+}
+
+/// Save the response to a request processed after [`try_processing`], and
+/// return it.
+#[tracing::instrument(name = "Save the response to an idempotent request", skip_all)]
+pub async fn save_response(
+    transaction: &mut PgConnection,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers: Vec<_> = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect();
+    sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(response_head.set_body(body).map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdempotencyKey;
+
+    #[test]
+    fn empty_keys_are_rejected() {
+        assert!(IdempotencyKey::try_from(" ".to_owned()).is_err());
+    }
+
+    #[test]
+    fn overly_long_keys_are_rejected() {
+        assert!(IdempotencyKey::try_from("a".repeat(51)).is_err());
+        assert!(IdempotencyKey::try_from("a".repeat(50)).is_ok());
+    }
+}
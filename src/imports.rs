@@ -0,0 +1,696 @@
+use crate::blob_store::BlobStore;
+use crate::complaints::is_suppressed;
+use crate::consent::{RequestOrigin, record_signup};
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriberRules, SubscriberTag,
+};
+use crate::encryption::FieldCipher;
+use crate::jobs::Job;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// How often every instance looks for imports left unfinished.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Rows imported in one transaction, after which the progress of the import
+/// shows.
+const BATCH_SIZE: i64 = 500;
+/// Rejected rows listed with the progress of an import, the first ones in
+/// the file.
+const MAX_REPORTED_ERRORS: i64 = 100;
+/// How long the uploaded file of an import is kept once it completes, for
+/// it to be downloaded again.
+const FILE_RETENTION_DAYS: i32 = 30;
+/// Separates the tags of a subscriber in the `tags` column.
+const TAG_SEPARATOR: char = ';';
+/// Name of the [`ImportWorker`] job, triggered when a file is uploaded.
+pub const IMPORT_JOB: &str = "imports";
+
+/// A row of an import file, ready to be imported.
+#[derive(Debug)]
+pub struct ImportRow {
+    /// Line of the file the row starts on, the header being line 1.
+    pub line: i32,
+    pub email: SubscriberEmail,
+    pub name: SubscriberName,
+    /// Parsed as [`SubscriberTag`]s, sorted and without duplicates.
+    pub tags: Vec<String>,
+}
+
+/// A row of an import file that was not imported.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct RowError {
+    pub line: i32,
+    pub error: String,
+}
+
+/// The rows of an import file, split between those to import and those
+/// that failed validation.
+#[derive(Debug)]
+pub struct ParsedImport {
+    pub rows: Vec<ImportRow>,
+    pub rejected: Vec<RowError>,
+}
+
+impl ParsedImport {
+    /// Parse a CSV file with a header naming its `email` and `name` columns,
+    /// and optionally a `tags` column separated by semicolons. Other columns
+    /// are ignored.
+    ///
+    /// Invalid rows, and those `rules` do not accept, are rejected one by
+    /// one, the file as a whole only when it cannot be read.
+    pub fn parse(file: &str, rules: &SubscriberRules) -> Result<Self, String> {
+        let mut records = parse_csv(file.strip_prefix('\u{feff}').unwrap_or(file))?.into_iter();
+        let (_, header) = records.next().ok_or("The file is empty.")?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.trim().eq_ignore_ascii_case(name))
+        };
+        let (Some(email_column), Some(name_column)) = (column("email"), column("name")) else {
+            return Err("The header must name an `email` and a `name` column.".into());
+        };
+        let tags_column = column("tags");
+
+        let mut rows = Vec::new();
+        let mut rejected = Vec::new();
+        for (line, fields) in records {
+            if fields.len() != header.len() {
+                rejected.push(RowError {
+                    line,
+                    error: format!(
+                        "Expected {} fields like the header, found {}.",
+                        header.len(),
+                        fields.len()
+                    ),
+                });
+                continue;
+            }
+            let email = SubscriberEmail::try_from(fields[email_column].trim().to_owned());
+            let name = SubscriberName::try_from(fields[name_column].trim().to_owned());
+            let (email, name) = match (email, name) {
+                (Ok(email), Ok(name)) => (email, name),
+                (Err(error), _) | (_, Err(error)) => {
+                    rejected.push(RowError { line, error });
+                    continue;
+                }
+            };
+            let tags = tags_column
+                .map(|column| {
+                    fields[column]
+                        .split(TAG_SEPARATOR)
+                        .filter(|tag| !tag.trim().is_empty())
+                        .map(|tag| SubscriberTag::parse(tag.to_owned()))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose();
+            let tags = match tags {
+                Ok(tags) => tags.unwrap_or_default(),
+                Err(error) => {
+                    rejected.push(RowError { line, error });
+                    continue;
+                }
+            };
+            let subscriber = NewSubscriber {
+                email,
+                name,
+                region: None,
+                tags,
+            };
+            if let Err(e) = rules.check(&subscriber) {
+                rejected.push(RowError {
+                    line,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+            let mut tags: Vec<String> = subscriber
+                .tags
+                .iter()
+                .map(|tag| tag.as_ref().to_owned())
+                .collect();
+            tags.sort();
+            tags.dedup();
+            rows.push(ImportRow {
+                line,
+                email: subscriber.email,
+                name: subscriber.name,
+                tags,
+            });
+        }
+        Ok(Self { rows, rejected })
+    }
+
+    pub fn total_rows(&self) -> usize {
+        self.rows.len() + self.rejected.len()
+    }
+}
+
+/// Split `file` into records, along with the line each of them starts on.
+/// Fields can be quoted to hold commas, line breaks or doubled quotes.
+/// Blank lines are skipped.
+fn parse_csv(file: &str) -> Result<Vec<(i32, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut quoted = false;
+    let mut chars = file.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                if !record.is_empty() || !field.is_empty() {
+                    record.push(std::mem::take(&mut field));
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                line += 1;
+                record_line = line;
+            }
+            '\n' => {
+                line += 1;
+                field.push(c);
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!(
+            "The quoted field starting on line {} is never closed.",
+            record_line
+        ));
+    }
+    if !record.is_empty() || !field.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    Ok(records)
+}
+
+/// Key of the uploaded file of an import in the
+/// [`BlobStore`](crate::blob_store::BlobStore).
+pub fn import_file_key(import_id: Uuid) -> String {
+    format!("imports/{}.csv", import_id)
+}
+
+/// Delete the uploaded files of the imports listing `email`, once the
+/// subscriber it belongs to is erased. Their progress and rejected rows are
+/// kept.
+#[tracing::instrument(name = "Delete the import files listing an address", skip_all)]
+pub async fn delete_import_files_listing(
+    pg_pool: &PgPool,
+    blob_store: &dyn BlobStore,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    let import_ids = sqlx::query_scalar!("SELECT import_id FROM subscriber_imports")
+        .fetch_all(pg_pool)
+        .await
+        .context("Failed to list the imports")?;
+    for import_id in import_ids {
+        let key = import_file_key(import_id);
+        let Some(file) = blob_store
+            .get(&key)
+            .await
+            .context("Failed to retrieve the file of an import")?
+        else {
+            continue;
+        };
+        // Files that cannot be read were never imported. Every row is looked
+        // at, whatever the rules of the deployment.
+        let Ok(import) =
+            ParsedImport::parse(&String::from_utf8_lossy(&file), &SubscriberRules::default())
+        else {
+            continue;
+        };
+        if import
+            .rows
+            .iter()
+            .any(|row| row.email.as_ref().eq_ignore_ascii_case(email))
+        {
+            blob_store
+                .delete(&key)
+                .await
+                .context("Failed to delete the file of an import")?;
+        }
+    }
+    Ok(())
+}
+
+/// Record an import and stage its rows for the [`ImportWorker`].
+#[tracing::instrument(
+    name = "Create a subscriber import",
+    skip(pg_pool, cipher, import),
+    fields(rows = import.total_rows())
+)]
+pub async fn create_import(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    import_id: Uuid,
+    user_id: Uuid,
+    import: &ParsedImport,
+) -> Result<(), anyhow::Error> {
+    let status = if import.rows.is_empty() {
+        "completed"
+    } else {
+        "processing"
+    };
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (
+            import_id, status, total_rows, processed_rows, rejected, created_by, created_at,
+            completed_at
+        )
+        VALUES ($1, $2, $3, $4, $4, $5, now(), CASE WHEN $2 = 'completed' THEN now() END)
+        "#,
+        import_id,
+        status,
+        import.total_rows() as i32,
+        import.rejected.len() as i32,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record the import")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_import_rows (import_id, line, email, name, tags)
+        SELECT $1, line, email, name, string_to_array(tags, $6)
+        FROM UNNEST($2::int4[], $3::text[], $4::text[], $5::text[]) AS rows(line, email, name, tags)
+        "#,
+        import_id,
+        &import.rows.iter().map(|r| r.line).collect::<Vec<_>>(),
+        &import
+            .rows
+            .iter()
+            .map(|r| r.email.as_ref().to_owned())
+            .collect::<Vec<_>>(),
+        &import
+            .rows
+            .iter()
+            .map(|r| cipher.encrypt(r.name.as_ref()))
+            .collect::<Vec<_>>(),
+        &import
+            .rows
+            .iter()
+            .map(|r| r.tags.join(&TAG_SEPARATOR.to_string()))
+            .collect::<Vec<_>>(),
+        TAG_SEPARATOR.to_string(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to stage the rows to import")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_import_errors (import_id, line, error)
+        SELECT $1, line, error FROM UNNEST($2::int4[], $3::text[]) AS errors(line, error)
+        "#,
+        import_id,
+        &import.rejected.iter().map(|r| r.line).collect::<Vec<_>>(),
+        &import
+            .rejected
+            .iter()
+            .map(|r| r.error.clone())
+            .collect::<Vec<_>>(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record the rejected rows")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the import")?;
+    Ok(())
+}
+
+/// Where an import is at.
+#[derive(serde::Serialize, Debug)]
+pub struct ImportProgress {
+    pub import_id: Uuid,
+    /// `processing` until every row was processed, `completed` afterwards.
+    pub status: String,
+    pub total_rows: i32,
+    pub processed_rows: i32,
+    pub imported: i32,
+    /// Rows whose address was already subscribed or suppressed.
+    pub skipped: i32,
+    pub rejected: i32,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// The first rejected rows of the file.
+    pub errors: Vec<RowError>,
+}
+
+#[tracing::instrument(name = "Get the progress of an import", skip(pg_pool))]
+pub async fn get_import(
+    pg_pool: &PgPool,
+    import_id: Uuid,
+) -> Result<Option<ImportProgress>, sqlx::Error> {
+    let Some(import) = sqlx::query!(
+        r#"
+        SELECT status, total_rows, processed_rows, imported, skipped, rejected, created_at,
+            completed_at
+        FROM subscriber_imports
+        WHERE import_id = $1
+        "#,
+        import_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let errors = sqlx::query_as!(
+        RowError,
+        r#"
+        SELECT line, error FROM subscriber_import_errors
+        WHERE import_id = $1
+        ORDER BY line
+        LIMIT $2
+        "#,
+        import_id,
+        MAX_REPORTED_ERRORS,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    Ok(Some(ImportProgress {
+        import_id,
+        status: import.status,
+        total_rows: import.total_rows,
+        processed_rows: import.processed_rows,
+        imported: import.imported,
+        skipped: import.skipped,
+        rejected: import.rejected,
+        created_at: import.created_at,
+        completed_at: import.completed_at,
+        errors,
+    }))
+}
+
+/// Imports the staged rows of uploaded files, a batch at a time, as
+/// confirmed subscribers, and deletes the files of the imports completed
+/// more than [`FILE_RETENTION_DAYS`] ago.
+///
+/// Every instance runs one, like the
+/// [`DeliveryWorker`](crate::delivery::DeliveryWorker). Imports are claimed
+/// with `SKIP LOCKED` for the length of a batch, so that each row is imported
+/// once however many instances are running.
+pub struct ImportWorker {
+    pg_pool: PgPool,
+    blob_store: Arc<dyn BlobStore>,
+    job: Arc<Job>,
+}
+
+impl ImportWorker {
+    pub fn build(pg_pool: PgPool, blob_store: Arc<dyn BlobStore>) -> Self {
+        Self {
+            pg_pool,
+            blob_store,
+            job: Job::new(IMPORT_JOB),
+        }
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(POLL_INTERVAL, None, &shutdown, || async {
+                self.import_pending().await?;
+                self.delete_expired_files().await
+            })
+            .await
+    }
+
+    /// Delete the uploaded files of the imports completed more than
+    /// [`FILE_RETENTION_DAYS`] ago.
+    #[tracing::instrument(name = "Delete expired import files", skip(self))]
+    pub async fn delete_expired_files(&self) -> Result<(), anyhow::Error> {
+        let import_ids = sqlx::query_scalar!(
+            r#"
+            SELECT import_id FROM subscriber_imports
+            WHERE completed_at < now() - make_interval(days => $1)
+                AND file_deleted_at IS NULL
+            "#,
+            FILE_RETENTION_DAYS,
+        )
+        .fetch_all(&self.pg_pool)
+        .await
+        .context("Failed to look for expired import files")?;
+        for import_id in import_ids {
+            // Deleting a file twice, e.g. from two instances, is harmless.
+            self.blob_store
+                .delete(&import_file_key(import_id))
+                .await
+                .context("Failed to delete the file of an import")?;
+            sqlx::query!(
+                "UPDATE subscriber_imports SET file_deleted_at = now() WHERE import_id = $1",
+                import_id,
+            )
+            .execute(&self.pg_pool)
+            .await
+            .context("Failed to record the deletion of an import file")?;
+        }
+        Ok(())
+    }
+
+    /// Import every staged row that can be claimed and return how many
+    /// subscribers were added.
+    #[tracing::instrument(name = "Import pending subscribers", skip(self))]
+    pub async fn import_pending(&self) -> Result<u64, anyhow::Error> {
+        let mut imported = 0;
+        while let Some(batch) = self.import_batch().await? {
+            imported += batch;
+        }
+        Ok(imported)
+    }
+
+    /// Import the next batch of rows of an import no other worker is on,
+    /// `None` if there is none.
+    async fn import_batch(&self) -> Result<Option<u64>, anyhow::Error> {
+        let mut transaction = self
+            .pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let Some(import_id) = sqlx::query_scalar!(
+            r#"
+            SELECT import_id FROM subscriber_imports
+            WHERE status = 'processing'
+            ORDER BY created_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to claim an import")?
+        else {
+            return Ok(None);
+        };
+        let rows = sqlx::query!(
+            r#"
+            SELECT line, email, name, tags FROM subscriber_import_rows
+            WHERE import_id = $1
+            ORDER BY line
+            LIMIT $2
+            "#,
+            import_id,
+            BATCH_SIZE,
+        )
+        .fetch_all(&mut *transaction)
+        .await
+        .context("Failed to fetch the rows to import")?;
+
+        let origin = RequestOrigin {
+            ip: None,
+            user_agent: None,
+        };
+        let mut imported = 0;
+        for row in &rows {
+            if is_suppressed(&mut transaction, &row.email)
+                .await
+                .context("Failed to check if an address is suppressed")?
+            {
+                continue;
+            }
+            let subscriber_id = Uuid::new_v4();
+            let inserted = sqlx::query!(
+                r#"
+                INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+                VALUES ($1, $2, $3, now(), 'confirmed')
+                ON CONFLICT (email) DO NOTHING
+                "#,
+                subscriber_id,
+                row.email,
+                row.name,
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to insert an imported subscriber")?
+            .rows_affected();
+            if inserted == 0 {
+                continue;
+            }
+            sqlx::query!(
+                r#"
+                INSERT INTO subscriber_tags (subscriber_id, tag)
+                SELECT $1, UNNEST($2::text[])
+                "#,
+                subscriber_id,
+                &row.tags,
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to tag an imported subscriber")?;
+            record_signup(&mut transaction, subscriber_id, "import", &origin)
+                .await
+                .context("Failed to record the import of a subscriber")?;
+            imported += 1;
+        }
+
+        let lines: Vec<i32> = rows.iter().map(|row| row.line).collect();
+        sqlx::query!(
+            "DELETE FROM subscriber_import_rows WHERE import_id = $1 AND line = ANY($2)",
+            import_id,
+            &lines,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to remove the imported rows")?;
+        sqlx::query!(
+            r#"
+            UPDATE subscriber_imports
+            SET processed_rows = processed_rows + $2,
+                imported = imported + $3,
+                skipped = skipped + $2 - $3,
+                status = CASE WHEN remaining.pending THEN status ELSE 'completed' END,
+                completed_at = CASE WHEN remaining.pending THEN NULL ELSE now() END
+            FROM (
+                SELECT EXISTS (
+                    SELECT 1 FROM subscriber_import_rows WHERE import_id = $1
+                ) AS pending
+            ) AS remaining
+            WHERE import_id = $1
+            "#,
+            import_id,
+            lines.len() as i32,
+            imported as i32,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to record the progress of the import")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the imported subscribers")?;
+        Ok(Some(imported))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParsedImport, RowError, parse_csv};
+    use crate::configuration::SubscriberValidationSettings;
+    use crate::domain::SubscriberRules;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn quoted_fields_can_hold_commas_quotes_and_line_breaks() {
+        let records = parse_csv(
+            "email,name\r\n\"a@b.com\",\"Le Guin, \"\"Ursula\"\"\nK\"\n\nc@d.com,Octavia\n",
+        )
+        .unwrap();
+        assert_eq!(
+            records,
+            [
+                (1, vec!["email".to_owned(), "name".to_owned()]),
+                (
+                    2,
+                    vec!["a@b.com".to_owned(), "Le Guin, \"Ursula\"\nK".to_owned()]
+                ),
+                (5, vec!["c@d.com".to_owned(), "Octavia".to_owned()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unclosed_quote_fails_the_whole_file() {
+        assert_err!(parse_csv("email,name\na@b.com,\"Ursula\n"));
+    }
+
+    #[test]
+    fn invalid_rows_are_rejected_with_their_line() {
+        let import = assert_ok!(ParsedImport::parse(
+            "Name,Email,Tags\nUrsula,ursula@gmail.com,rust; Go;rust\nOctavia,not-an-email,\nNK,nk@gmail.com\nMary,mary@gmail.com,\u{7}\n",
+            &SubscriberRules::default(),
+        ));
+        assert_eq!(import.rows.len(), 1);
+        assert_eq!(import.rows[0].line, 2);
+        assert_eq!(import.rows[0].tags, ["go", "rust"]);
+        assert_eq!(
+            import.rejected,
+            [
+                RowError {
+                    line: 3,
+                    error: "'not-an-email' is not a valid subscriber email".into()
+                },
+                RowError {
+                    line: 4,
+                    error: "Expected 3 fields like the header, found 2.".into()
+                },
+                RowError {
+                    line: 5,
+                    error: "\u{7} is not a valid subscriber tag".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_header_without_an_email_column_fails_the_whole_file() {
+        assert_err!(ParsedImport::parse(
+            "name,address\nUrsula,ursula@gmail.com\n",
+            &SubscriberRules::default(),
+        ));
+    }
+
+    #[test]
+    fn rows_are_held_to_the_rules_of_the_deployment() {
+        let rules = SubscriberRules::new(
+            SubscriberValidationSettings {
+                allowed_email_domains: vec!["corp.com".into()],
+                ..Default::default()
+            },
+            Vec::new(),
+        );
+        let import = assert_ok!(ParsedImport::parse(
+            "email,name\nursula@corp.com,Ursula\noctavia@gmail.com,Octavia\n",
+            &rules,
+        ));
+        assert_eq!(import.rows.len(), 1);
+        assert_eq!(
+            import.rejected,
+            [RowError {
+                line: 3,
+                error: "Only @corp.com addresses can subscribe to this newsletter".into()
+            }]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Invite codes, required to subscribe once `invitations.required` is set,
+//! e.g. for a private beta.
+//!
+//! Codes are generated in batches, each code being good for `max_uses`
+//! subscriptions. Like API keys, only their hash is stored: the codes are
+//! shown once, when the batch is generated.
+use chrono::{DateTime, Utc};
+use rand::{Rng, thread_rng};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+/// Without `0`, `O`, `1` and `I`, which are easily mistaken for one another
+/// when typed in.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 12;
+pub const MAX_BATCH_SIZE: u32 = 1000;
+pub const MAX_USES: u32 = 1_000_000;
+
+#[derive(serde::Serialize, Debug)]
+pub struct InvitationBatch {
+    pub batch_id: Uuid,
+    pub codes: i64,
+    pub max_uses: i32,
+    /// Subscriptions made with the codes of the batch so far.
+    pub uses: i64,
+    /// The admin who generated the batch, `None` once they are deleted.
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+fn generate_code() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .take(CODE_LENGTH)
+        .collect()
+}
+
+/// Codes are matched whatever their case and surrounding whitespace.
+fn hash_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_uppercase().as_bytes()))
+}
+
+/// Generate `count` codes, each good for `max_uses` subscriptions, returned
+/// along with the batch they belong to: they cannot be retrieved later.
+#[tracing::instrument(name = "Create a batch of invitations", skip(pg_pool))]
+pub async fn create_invitation_batch(
+    pg_pool: &PgPool,
+    user_id: Uuid,
+    count: u32,
+    max_uses: u32,
+) -> Result<(Vec<String>, Uuid), sqlx::Error> {
+    let batch_id = Uuid::new_v4();
+    let codes: Vec<String> = std::iter::repeat_with(generate_code)
+        .take(count as usize)
+        .collect();
+    let hashes: Vec<String> = codes.iter().map(|code| hash_code(code)).collect();
+    let mut transaction = pg_pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO invitation_batches (batch_id, max_uses, created_by, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        batch_id,
+        max_uses as i32,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO invitations (code_hash, batch_id)
+        SELECT UNNEST($1::text[]), $2
+        "#,
+        &hashes,
+        batch_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok((codes, batch_id))
+}
+
+/// Every batch, newest first.
+pub async fn list_invitation_batches(
+    pg_pool: &PgPool,
+) -> Result<Vec<InvitationBatch>, sqlx::Error> {
+    sqlx::query_as!(
+        InvitationBatch,
+        r#"
+        SELECT
+            b.batch_id,
+            COUNT(i.code_hash) AS "codes!",
+            b.max_uses,
+            COALESCE(SUM(i.uses), 0) AS "uses!",
+            u.username AS "created_by?",
+            b.created_at
+        FROM invitation_batches b
+        LEFT JOIN invitations i ON i.batch_id = b.batch_id
+        LEFT JOIN users u ON u.user_id = b.created_by
+        GROUP BY b.batch_id, u.username
+        ORDER BY b.created_at DESC
+        "#
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+/// Stop accepting the codes of a batch, e.g. once they leaked. Return
+/// whether there is such a batch.
+#[tracing::instrument(name = "Delete a batch of invitations", skip(pg_pool))]
+pub async fn delete_invitation_batch(
+    pg_pool: &PgPool,
+    batch_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query!(
+        "DELETE FROM invitation_batches WHERE batch_id = $1",
+        batch_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(deleted.rows_affected() == 1)
+}
+
+/// Use up `code` for a subscription. Return whether it was valid, with uses
+/// left.
+///
+/// Meant to run in the transaction storing the subscription, which gives the
+/// use back if it is rolled back.
+#[tracing::instrument(name = "Redeem an invitation", skip_all)]
+pub async fn redeem_invitation(
+    connection: &mut PgConnection,
+    code: &str,
+) -> Result<bool, sqlx::Error> {
+    let redeemed = sqlx::query!(
+        r#"
+        UPDATE invitations i
+        SET uses = i.uses + 1
+        FROM invitation_batches b
+        WHERE i.code_hash = $1
+            AND b.batch_id = i.batch_id
+            AND i.uses < b.max_uses
+        "#,
+        hash_code(code),
+    )
+    .execute(connection)
+    .await?;
+    Ok(redeemed.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CODE_ALPHABET, CODE_LENGTH, generate_code, hash_code};
+
+    #[test]
+    fn codes_are_made_of_unambiguous_characters() {
+        let code = generate_code();
+        assert_eq!(code.len(), CODE_LENGTH);
+        assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+    }
+
+    #[test]
+    fn codes_are_matched_whatever_their_case() {
+        assert_eq!(hash_code(" abcd2345efgh\n"), hash_code("ABCD2345EFGH"));
+        assert_ne!(hash_code("ABCD2345EFGH"), hash_code("ABCD2345EFGK"));
+    }
+}
@@ -0,0 +1,278 @@
+use crate::delivery::enqueue_deliveries;
+use crate::domain::{EngagementSegment, Segment};
+use crate::extensions::{DomainEvents, Published};
+use crate::jobs::Job;
+use crate::routes::error_chain_fmt;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// How often every instance looks for issues due to be sent.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+pub const ISSUE_SCHEDULER_JOB: &str = "issue_scheduler";
+
+#[derive(thiserror::Error)]
+pub enum CancelScheduleError {
+    #[error("There is no newsletter issue associated with the provided id.")]
+    UnknownIssue,
+    #[error("The issue is not scheduled, or its deliveries were already queued.")]
+    NotScheduled,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for CancelScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Queues the deliveries of the issues whose scheduled time has come, and
+/// wakes the [`DeliveryWorker`](crate::delivery::DeliveryWorker) up to send
+/// them.
+///
+/// Every instance runs one. Due issues are claimed with `SKIP LOCKED`, so an
+/// issue is queued once however many instances find it due.
+pub struct IssueScheduler {
+    pg_pool: PgPool,
+    deliveries: Arc<Job>,
+    events: DomainEvents,
+    job: Arc<Job>,
+}
+
+impl IssueScheduler {
+    /// `deliveries` is the job of the delivery worker.
+    pub fn build(pg_pool: PgPool, deliveries: Arc<Job>) -> Self {
+        Self {
+            pg_pool,
+            deliveries,
+            events: DomainEvents::default(),
+            job: Job::new(ISSUE_SCHEDULER_JOB),
+        }
+    }
+
+    /// Tell the listeners of `events` about the issues queued.
+    pub fn with_events(mut self, events: DomainEvents) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(POLL_INTERVAL, None, &shutdown, || async {
+                self.enqueue_due(Utc::now()).await.map(|_| ())
+            })
+            .await
+    }
+
+    /// Queue the deliveries of the issues due by `now`, to the segment they
+    /// were scheduled for, and return the issues queued.
+    ///
+    /// Each issue is queued in its own transaction. One that cannot be is
+    /// logged and set aside with the reason, the others being queued anyway.
+    #[tracing::instrument(name = "Enqueue scheduled newsletter issues", skip(self))]
+    pub async fn enqueue_due(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>, anyhow::Error> {
+        let mut issues = Vec::new();
+        while let Some(outcome) = self.enqueue_next_due(now).await? {
+            let event = match outcome {
+                Ok(event) => event,
+                Err((newsletter_issue_id, e)) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        %newsletter_issue_id,
+                        "Failed to enqueue a scheduled issue, setting it aside"
+                    );
+                    sqlx::query!(
+                        r#"
+                        UPDATE newsletter_issues SET schedule_error = $2
+                        WHERE newsletter_issue_id = $1
+                        "#,
+                        newsletter_issue_id,
+                        format!("{:#}", e),
+                    )
+                    .execute(&self.pg_pool)
+                    .await
+                    .context("Failed to record the failure of a scheduled issue")?;
+                    continue;
+                }
+            };
+            self.deliveries.trigger();
+            issues.push(event.newsletter_issue_id);
+            self.events.published(event);
+        }
+        Ok(issues)
+    }
+
+    /// Queue the earliest issue due by `now`, if any. The outer error is a
+    /// failure to look for one, the inner one a failure to queue it.
+    async fn enqueue_next_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Result<Published, (Uuid, anyhow::Error)>>, anyhow::Error> {
+        let mut transaction = self
+            .pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let Some(r) = sqlx::query!(
+            r#"
+            SELECT
+                i.newsletter_issue_id, i.title, i.scheduled_segment_id, i.scheduled_filter,
+                i.scheduled_engagement, i.scheduled_tags
+            FROM newsletter_issues i
+            WHERE i.scheduled_at <= $1
+                AND i.schedule_error IS NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM newsletter_issue_audiences a
+                    WHERE a.newsletter_issue_id = i.newsletter_issue_id
+                )
+            ORDER BY i.scheduled_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            now,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to look for scheduled issues")?
+        else {
+            return Ok(None);
+        };
+        let newsletter_issue_id = r.newsletter_issue_id;
+        let queued = async {
+            let filter = r
+                .scheduled_filter
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .context("Failed to parse the filter of a scheduled issue")?;
+            let segment = Segment {
+                engagement: r
+                    .scheduled_engagement
+                    .as_deref()
+                    .and_then(EngagementSegment::parse),
+                tags: r.scheduled_tags.unwrap_or_default(),
+                filter,
+                segment_id: r.scheduled_segment_id,
+            };
+            enqueue_deliveries(&mut transaction, newsletter_issue_id, &segment)
+                .await
+                .context("Failed to enqueue the deliveries of a scheduled issue")?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to enqueue a scheduled issue")?;
+            Ok::<_, anyhow::Error>(Published {
+                newsletter_issue_id,
+                title: r.title,
+            })
+        }
+        .await;
+        Ok(Some(queued.map_err(|e| (newsletter_issue_id, e))))
+    }
+}
+
+/// Have the [`IssueScheduler`] queue the deliveries of a stored issue to
+/// `segment` once `scheduled_at` is reached, rather than straight away.
+#[tracing::instrument(name = "Schedule newsletter issue", skip(connection, segment))]
+pub async fn schedule_issue(
+    connection: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    scheduled_at: DateTime<Utc>,
+    segment: &Segment,
+) -> Result<(), anyhow::Error> {
+    let filter = segment
+        .filter
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .context("Failed to serialize the filter of a segment")?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_at = $2, published_at = $2, scheduled_segment_id = $3,
+            scheduled_filter = $4, scheduled_engagement = $5, scheduled_tags = $6
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        scheduled_at,
+        segment.segment_id,
+        filter,
+        segment.engagement.map(|e| e.as_str()),
+        &segment.tags,
+    )
+    .execute(connection)
+    .await
+    .context("Failed to schedule a newsletter issue")?;
+    Ok(())
+}
+
+/// Delete an issue scheduled for later, provided its deliveries are not
+/// queued yet.
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(pg_pool))]
+pub async fn cancel_scheduled_issue(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<(), CancelScheduleError> {
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Waits for an `IssueScheduler` queueing the issue, the audience being
+    // looked up once it is done.
+    let scheduled_at = sqlx::query_scalar!(
+        r#"
+        SELECT scheduled_at FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to lock a scheduled issue")?
+    .ok_or(CancelScheduleError::UnknownIssue)?;
+    let queued = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM newsletter_issue_audiences WHERE newsletter_issue_id = $1
+        ) AS "queued!"
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to check whether a scheduled issue was queued")?;
+    if scheduled_at.is_none() || queued {
+        return Err(CancelScheduleError::NotScheduled);
+    }
+    // Nothing was sent, so nobody followed the short links of the issue.
+    sqlx::query!(
+        "DELETE FROM short_links WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the short links of a scheduled issue")?;
+    sqlx::query!(
+        "DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete a scheduled issue")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a scheduled issue")?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use crate::leader_election::LeaderElection;
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// A background job run periodically, or straight away when an admin asks
+/// for it.
+pub struct Job {
+    name: &'static str,
+    trigger: Notify,
+    status: Mutex<JobStatus>,
+}
+
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct JobStatus {
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// Error of the last run, `None` if it succeeded.
+    pub last_error: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct JobReport {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub status: JobStatus,
+}
+
+impl Job {
+    pub fn new(name: &'static str) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            trigger: Notify::new(),
+            status: Mutex::new(JobStatus::default()),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Call `run` every `period`, the first run happening one period after
+    /// startup.
+    ///
+    /// Scheduled runs of a job with a `leader_election` only happen on the
+    /// leader. Runs triggered by an admin happen on the instance asked.
+    ///
+    /// Return once `shutdown` is cancelled, after the current run if one is
+    /// in progress.
+    pub async fn run_every<F, Fut>(
+        &self,
+        period: Duration,
+        leader_election: Option<&LeaderElection>,
+        shutdown: &CancellationToken,
+        mut run: F,
+    ) where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), anyhow::Error>>,
+    {
+        let mut next_run = Instant::now() + period;
+        loop {
+            self.status.lock().unwrap().next_run_at =
+                chrono::Duration::from_std(next_run.saturating_duration_since(Instant::now()))
+                    .ok()
+                    .map(|delay| Utc::now() + delay);
+            let triggered = tokio::select! {
+                _ = tokio::time::sleep_until(next_run) => false,
+                _ = self.trigger.notified() => true,
+                _ = shutdown.cancelled() => return,
+            };
+            if !triggered {
+                next_run = Instant::now() + period;
+                if let Some(leader_election) = leader_election
+                    && !leader_election.is_leader().await
+                {
+                    continue;
+                }
+            }
+            let outcome = run().await;
+            if let Err(e) = &outcome {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    job = self.name,
+                    "Failed to run a background job",
+                );
+            }
+            let mut status = self.status.lock().unwrap();
+            status.last_run_at = Some(Utc::now());
+            status.last_error = outcome.err().map(|e| format!("{:#}", e));
+        }
+    }
+
+    /// Run the job as soon as possible, once the current run is over if
+    /// one is in progress.
+    pub fn trigger(&self) {
+        self.trigger.notify_one();
+    }
+
+    pub fn report(&self) -> JobReport {
+        JobReport {
+            name: self.name,
+            status: self.status.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// The background jobs running on this instance.
+#[derive(Default)]
+pub struct Jobs(pub Vec<Arc<Job>>);
+
+impl Jobs {
+    pub fn get(&self, name: &str) -> Option<&Job> {
+        self.0
+            .iter()
+            .find(|job| job.name() == name)
+            .map(|job| job.as_ref())
+    }
+
+    pub fn reports(&self) -> Vec<JobReport> {
+        self.0.iter().map(|job| job.report()).collect()
+    }
+}
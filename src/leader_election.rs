@@ -0,0 +1,141 @@
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, PgConnection, PgPool};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::Mutex;
+
+/// Elects the single instance running a job that must run once
+/// cluster-wide, such as the digests or the blog feed polling.
+///
+/// The leader holds a session-level Postgres advisory lock on a connection
+/// taken out of the pool for the job. If the leader crashes or loses its
+/// connection, Postgres releases the lock and the next instance checking
+/// takes over.
+pub struct LeaderElection {
+    job: String,
+    key: i64,
+    pg_pool: PgPool,
+    /// Set while this instance is the leader.
+    connection: Mutex<Option<PgConnection>>,
+    is_leader: AtomicBool,
+    elections_won: AtomicU64,
+    leadership_lost: AtomicU64,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct LeadershipMetrics {
+    pub job: String,
+    pub is_leader: bool,
+    pub elections_won: u64,
+    pub leadership_lost: u64,
+}
+
+impl LeaderElection {
+    pub fn new(job: &str, pg_pool: PgPool) -> Self {
+        Self {
+            job: job.to_owned(),
+            key: lock_key(job),
+            pg_pool,
+            connection: Mutex::new(None),
+            is_leader: AtomicBool::new(false),
+            elections_won: AtomicU64::new(0),
+            leadership_lost: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether this instance leads the job, running for it if nobody does.
+    ///
+    /// Call it before every run: leadership can be lost at any time.
+    #[tracing::instrument(name = "Check job leadership", skip(self), fields(job=%self.job))]
+    pub async fn is_leader(&self) -> bool {
+        let mut connection = self.connection.lock().await;
+        if let Some(held) = connection.as_mut() {
+            if let Err(e) = held.ping().await {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Lost the connection holding the leadership of a job",
+                );
+                *connection = None;
+                self.is_leader.store(false, Ordering::Relaxed);
+                self.leadership_lost.fetch_add(1, Ordering::Relaxed);
+            } else {
+                return true;
+            }
+        }
+        match self.run_for_leadership().await {
+            Ok(Some(held)) => {
+                tracing::info!("Became the leader of a job");
+                *connection = Some(held);
+                self.is_leader.store(true, Ordering::Relaxed);
+                self.elections_won.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to run for the leadership of a job",
+                );
+                false
+            }
+        }
+    }
+
+    /// Step down, letting another instance lead the job.
+    #[tracing::instrument(name = "Resign job leadership", skip(self), fields(job=%self.job))]
+    pub async fn resign(&self) {
+        let Some(held) = self.connection.lock().await.take() else {
+            return;
+        };
+        self.is_leader.store(false, Ordering::Relaxed);
+        self.leadership_lost.fetch_add(1, Ordering::Relaxed);
+        // Closing the session releases the lock.
+        if let Err(e) = held.close().await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to close the connection holding the leadership of a job",
+            );
+        }
+    }
+
+    /// Elections won and leaderships lost since this instance started.
+    pub fn metrics(&self) -> LeadershipMetrics {
+        LeadershipMetrics {
+            job: self.job.clone(),
+            is_leader: self.is_leader.load(Ordering::Relaxed),
+            elections_won: self.elections_won.load(Ordering::Relaxed),
+            leadership_lost: self.leadership_lost.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the connection holding the lock if the election was won.
+    async fn run_for_leadership(&self) -> Result<Option<PgConnection>, sqlx::Error> {
+        // Detached, the connection and its lock outlive the pool's recycling.
+        let mut connection = self.pg_pool.acquire().await?.detach();
+        let won: bool =
+            sqlx::query_scalar!(r#"SELECT pg_try_advisory_lock($1) AS "won!""#, self.key)
+                .fetch_one(&mut connection)
+                .await?;
+        if !won {
+            connection.close().await?;
+            return Ok(None);
+        }
+        Ok(Some(connection))
+    }
+}
+
+/// The elections of the singleton jobs running on this instance.
+#[derive(Default)]
+pub struct LeaderElections(pub Vec<Arc<LeaderElection>>);
+
+impl LeaderElections {
+    pub fn metrics(&self) -> Vec<LeadershipMetrics> {
+        self.0.iter().map(|election| election.metrics()).collect()
+    }
+}
+
+/// Advisory lock key of a job, derived from its name.
+fn lock_key(job: &str) -> i64 {
+    let digest = Sha256::digest(format!("leader:{}", job));
+    i64::from_be_bytes(digest[..8].try_into().unwrap())
+}
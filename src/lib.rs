@@ -1,9 +1,67 @@
+pub mod accessibility;
+pub mod admin_reports;
+pub mod alerts;
+pub mod amp;
+pub mod api_keys;
+pub mod archive;
+pub mod authentication;
+pub mod backup;
+pub mod blob_store;
+pub mod branding;
+pub mod calendar;
+pub mod cli;
+pub mod complaints;
 pub mod configuration;
+pub mod consent;
+pub mod delivery;
+#[cfg(feature = "dev")]
+pub mod dev;
+pub mod digests;
+pub mod dns;
 pub mod domain;
+pub mod draft_scheduler;
 pub mod email_client;
+pub mod encryption;
+pub mod extensions;
+pub mod feature_flags;
+pub mod feed_watcher;
+pub mod idempotency;
+pub mod imports;
+pub mod invitations;
+pub mod issue_scheduler;
+pub mod jobs;
+pub mod leader_election;
+pub mod link_cards;
+pub mod link_shortener;
+pub mod locale;
+pub mod maintenance;
+pub mod migrations;
+pub mod postmaster;
+pub mod publishing;
+pub mod rate_limiting;
+pub mod rendering;
+pub mod repositories;
+pub mod request_id;
 pub mod routes;
+pub mod segments;
+pub mod senders;
+pub mod server;
+pub mod session_state;
+pub mod signing;
+pub mod signup_anomalies;
+pub mod slack;
 pub mod startup;
+pub mod subject_lines;
+pub mod subscriber_merge;
+pub mod subscriber_search;
 pub mod telemetry;
+pub mod template_fragments;
+pub mod templates;
+pub mod throttling;
+pub mod token_guard;
+pub mod tracking;
+pub mod web_pages;
 
 pub use configuration::get_configuration;
 pub use email_client::EmailClient;
+pub use server::{Server, ServerHandle};
@@ -0,0 +1,249 @@
+//! `{{card url="..."}}` shortcodes, expanded when an issue is published into
+//! a card previewing the page linked to: its title, description and image.
+use crate::accessibility::attribute;
+use crate::publishing::escape_html;
+use crate::web_pages::{PageFetcher, PagePreview, extract_preview};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::ops::Range;
+use url::Url;
+
+const OPENING: &str = "{{card";
+const CLOSING: &str = "}}";
+
+/// How long the preview of a page is reused before fetching it again.
+const PREVIEW_TTL_HOURS: i64 = 24;
+
+/// A shortcode found in the content of an issue.
+#[derive(Debug, PartialEq)]
+struct Card {
+    range: Range<usize>,
+    url: String,
+}
+
+/// Replace the card shortcodes of an issue, fetching the pages they link to
+/// unless they were previewed recently.
+///
+/// Pages that cannot be fetched, or do not describe themselves, are linked
+/// to without a preview rather than holding the issue back. Fetching them
+/// takes a while: this is not meant to run within a transaction.
+#[tracing::instrument(name = "Expand link cards", skip_all)]
+pub async fn expand_cards(
+    pg_pool: &PgPool,
+    page_fetcher: &PageFetcher,
+    html: &str,
+    text: &str,
+) -> Result<(String, String), anyhow::Error> {
+    let mut previews = HashMap::new();
+    for card in find_cards(html).into_iter().chain(find_cards(text)) {
+        if let Entry::Vacant(entry) = previews.entry(card.url) {
+            let preview = get_preview(pg_pool, page_fetcher, entry.key()).await?;
+            entry.insert(preview);
+        }
+    }
+    let html = replace_cards(html, true, |url| render_html_card(url, &previews[url]));
+    let text = replace_cards(text, false, |url| render_text_card(url, &previews[url]));
+    Ok((html, text))
+}
+
+#[tracing::instrument(name = "Get link preview", skip(pg_pool, page_fetcher))]
+async fn get_preview(
+    pg_pool: &PgPool,
+    page_fetcher: &PageFetcher,
+    url: &str,
+) -> Result<PagePreview, anyhow::Error> {
+    let cached = sqlx::query!(
+        r#"
+        SELECT title, description, image_url
+        FROM link_previews
+        WHERE url = $1 AND fetched_at > $2
+        "#,
+        url,
+        Utc::now() - chrono::Duration::hours(PREVIEW_TTL_HOURS),
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to retrieve a link preview")?;
+    if let Some(cached) = cached {
+        return Ok(PagePreview {
+            title: cached.title,
+            description: cached.description,
+            image_url: cached.image_url,
+        });
+    }
+
+    // Failures are not cached, for the next issue to try again.
+    let page = match page_fetcher.fetch(url).await {
+        Ok(page) => page,
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to fetch the page of a link card, linking to it without a preview",
+            );
+            return Ok(PagePreview::default());
+        }
+    };
+    let preview = extract_preview(&page.html, &page.url);
+    sqlx::query!(
+        r#"
+        INSERT INTO link_previews (url, title, description, image_url, fetched_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (url) DO UPDATE
+        SET title = EXCLUDED.title,
+            description = EXCLUDED.description,
+            image_url = EXCLUDED.image_url,
+            fetched_at = EXCLUDED.fetched_at
+        "#,
+        url,
+        preview.title,
+        preview.description,
+        preview.image_url,
+    )
+    .execute(pg_pool)
+    .await
+    .context("Failed to store a link preview")?;
+    Ok(preview)
+}
+
+/// The shortcodes of `content` linking to an http(s) URL, the others being
+/// left as written.
+fn find_cards(content: &str) -> Vec<Card> {
+    let mut cards = Vec::new();
+    let mut from = 0;
+    while let Some(start) = content[from..].find(OPENING).map(|i| from + i) {
+        let Some(end) = content[start..]
+            .find(CLOSING)
+            .map(|i| start + i + CLOSING.len())
+        else {
+            break;
+        };
+        from = start + OPENING.len();
+        // The shortcode without its braces, e.g. `card url="..."`.
+        let shortcode = &content[start + 2..end - CLOSING.len()];
+        if !shortcode[OPENING.len() - 2..].starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some(url) = attribute(shortcode, "url")
+            .and_then(|url| Url::parse(url).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+        else {
+            continue;
+        };
+        cards.push(Card {
+            range: start..end,
+            url: url.into(),
+        });
+        from = end;
+    }
+    cards
+}
+
+/// `content` with each card rendered by `render`, given its URL.
+///
+/// In HTML, a card alone in its paragraph replaces the paragraph, as
+/// paragraphs cannot hold it.
+fn replace_cards(content: &str, html: bool, render: impl Fn(&str) -> String) -> String {
+    let mut replaced = String::with_capacity(content.len());
+    let mut copied = 0;
+    for card in find_cards(content) {
+        let mut range = card.range;
+        if html
+            && content[copied..range.start].ends_with("<p>")
+            && content[range.end..].starts_with("</p>")
+        {
+            range = range.start - "<p>".len()..range.end + "</p>".len();
+        }
+        replaced.push_str(&content[copied..range.start]);
+        replaced.push_str(&render(&card.url));
+        copied = range.end;
+    }
+    replaced.push_str(&content[copied..]);
+    replaced
+}
+
+fn render_html_card(url: &str, preview: &PagePreview) -> String {
+    let Some(title) = &preview.title else {
+        return format!(r#"<p><a href="{}">{}</a></p>"#, url, escape_html(url));
+    };
+    let mut card = String::from(
+        r#"<table role="presentation" width="100%" cellpadding="12" style="border: 1px solid #dddddd; border-collapse: collapse;"><tr><td>"#,
+    );
+    if let Some(image_url) = &preview.image_url {
+        card.push_str(&format!(
+            r#"<a href="{}"><img src="{}" alt="" width="100%" style="display: block; max-width: 100%;"></a>"#,
+            url,
+            image_url.replace('"', "%22")
+        ));
+    }
+    card.push_str(&format!(
+        r#"<p><a href="{}"><strong>{}</strong></a></p>"#,
+        url,
+        escape_html(title)
+    ));
+    if let Some(description) = &preview.description {
+        card.push_str(&format!("<p>{}</p>", escape_html(description)));
+    }
+    card.push_str("</td></tr></table>");
+    card
+}
+
+fn render_text_card(url: &str, preview: &PagePreview) -> String {
+    match &preview.title {
+        Some(title) => format!("{}\n{}", title, url),
+        None => url.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_cards, render_html_card, replace_cards};
+    use crate::web_pages::PagePreview;
+
+    #[test]
+    fn cards_with_an_http_url_are_found() {
+        let content = r#"{{card url="https://example.com/a"}} {{cards url="https://example.com/b"}}
+{{card url="javascript:alert(1)"}} {{card}} {{ card url='http://example.com/c' }}"#;
+
+        let urls: Vec<_> = find_cards(content).into_iter().map(|c| c.url).collect();
+
+        assert_eq!(urls, ["https://example.com/a"]);
+    }
+
+    #[test]
+    fn cards_replace_the_paragraph_they_are_alone_in() {
+        let content = r#"<p>Read this:</p><p>{{card url="https://example.com/"}}</p><p>Or {{card url="https://example.com/"}}</p>"#;
+
+        let replaced = replace_cards(content, true, |_| "<table></table>".into());
+
+        assert_eq!(
+            replaced,
+            "<p>Read this:</p><table></table><p>Or <table></table></p>"
+        );
+    }
+
+    #[test]
+    fn pages_without_a_title_are_linked_to_without_a_card() {
+        assert_eq!(
+            render_html_card("https://example.com/", &PagePreview::default()),
+            r#"<p><a href="https://example.com/">https://example.com/</a></p>"#
+        );
+    }
+
+    #[test]
+    fn cards_escape_the_preview_of_the_page() {
+        let preview = PagePreview {
+            title: Some("<script>".into()),
+            description: Some("Tom & Jerry".into()),
+            image_url: Some("https://example.com/cover.png".into()),
+        };
+
+        let card = render_html_card("https://example.com/", &preview);
+
+        assert!(card.contains(r#"<img src="https://example.com/cover.png" alt=""#));
+        assert!(card.contains("<strong>&lt;script&gt;</strong>"));
+        assert!(card.contains("<p>Tom &amp; Jerry</p>"));
+    }
+}
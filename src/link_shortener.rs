@@ -0,0 +1,238 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const CODE_LENGTH: usize = 7;
+const MAX_CODE_ATTEMPTS: usize = 5;
+
+/// Rewrites the URLs found in a newsletter issue into `/l/{code}` short links.
+///
+/// Every distinct destination gets a single code per issue, so the same URL
+/// appearing in both the HTML and the plain text body is counted as one link.
+/// URLs that already point at our own base URL are left untouched.
+pub struct LinkShortener<'a> {
+    base_url: &'a str,
+    newsletter_issue_id: Uuid,
+    expires_at: Option<DateTime<Utc>>,
+    codes: HashMap<String, String>,
+}
+
+impl<'a> LinkShortener<'a> {
+    pub fn new(
+        base_url: &'a str,
+        newsletter_issue_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            base_url,
+            newsletter_issue_id,
+            expires_at,
+            codes: HashMap::new(),
+        }
+    }
+
+    #[tracing::instrument(name = "Shorten links in newsletter content", skip_all)]
+    pub async fn shorten(
+        &mut self,
+        pg_connection: &mut PgConnection,
+        content: &str,
+    ) -> Result<String, anyhow::Error> {
+        for url in find_links(content) {
+            if url.starts_with(self.base_url) || self.codes.contains_key(&url) {
+                continue;
+            }
+            let code = store_short_link(
+                pg_connection,
+                self.newsletter_issue_id,
+                &url,
+                self.expires_at,
+            )
+            .await?;
+            self.codes.insert(url, code);
+        }
+
+        let short_links: HashMap<_, _> = self
+            .codes
+            .iter()
+            .map(|(url, code)| (url.as_str(), short_link_url(self.base_url, code)))
+            .collect();
+        Ok(replace_links(content, &short_links))
+    }
+
+    /// Destination of every short link created so far, by code.
+    pub fn destinations(&self) -> HashMap<String, String> {
+        self.codes
+            .iter()
+            .map(|(url, code)| (code.clone(), url.clone()))
+            .collect()
+    }
+}
+
+/// Destination of every short link of an issue, by code.
+#[tracing::instrument(name = "Get the short links of a newsletter issue", skip(pg_pool))]
+pub async fn get_link_destinations(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT code, destination_url
+        FROM short_links
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.code, r.destination_url))
+        .collect())
+}
+
+/// Point the short links found in `content` back to their destination, so
+/// that following them goes unrecorded.
+pub fn restore_links(
+    base_url: &str,
+    content: &str,
+    destinations: &HashMap<String, String>,
+) -> String {
+    let short_link_prefix = short_link_url(base_url, "");
+    let mut output = String::with_capacity(content.len());
+    let mut last = 0;
+    for link in linkify::LinkFinder::new()
+        .kinds(&[linkify::LinkKind::Url])
+        .links(content)
+    {
+        let destination = link
+            .as_str()
+            .strip_prefix(&short_link_prefix)
+            .and_then(|code| destinations.get(code));
+        if let Some(destination) = destination {
+            output.push_str(&content[last..link.start()]);
+            output.push_str(destination);
+            last = link.end();
+        }
+    }
+    output.push_str(&content[last..]);
+    output
+}
+
+/// Generate a random case-sensitive short link code.
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(CODE_LENGTH)
+        .collect()
+}
+
+fn short_link_url(base_url: &str, code: &str) -> String {
+    format!("{}/l/{}", base_url.trim_end_matches('/'), code)
+}
+
+fn find_links(content: &str) -> Vec<String> {
+    linkify::LinkFinder::new()
+        .kinds(&[linkify::LinkKind::Url])
+        .links(content)
+        .map(|l| l.as_str().to_owned())
+        .collect()
+}
+
+fn replace_links(content: &str, short_links: &HashMap<&str, String>) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut last = 0;
+    for link in linkify::LinkFinder::new()
+        .kinds(&[linkify::LinkKind::Url])
+        .links(content)
+    {
+        if let Some(short_link) = short_links.get(link.as_str()) {
+            output.push_str(&content[last..link.start()]);
+            output.push_str(short_link);
+            last = link.end();
+        }
+    }
+    output.push_str(&content[last..]);
+    output
+}
+
+/// Persist a new short link, retrying with a fresh code on collision.
+#[tracing::instrument(name = "Store short link", skip(pg_connection, expires_at))]
+async fn store_short_link(
+    pg_connection: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    destination_url: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<String, anyhow::Error> {
+    for _ in 0..MAX_CODE_ATTEMPTS {
+        let code = generate_code();
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO short_links (
+                code, newsletter_issue_id, destination_url, created_at, expires_at
+            )
+            VALUES ($1, $2, $3, now(), $4)
+            ON CONFLICT (code) DO NOTHING
+            "#,
+            code,
+            newsletter_issue_id,
+            destination_url,
+            expires_at,
+        )
+        .execute(&mut *pg_connection)
+        .await
+        .context("Failed to insert a short link")?
+        .rows_affected();
+        if inserted == 1 {
+            return Ok(code);
+        }
+        tracing::warn!(code, "Short link code collision, retrying with a new code");
+    }
+    anyhow::bail!("Failed to generate a unique short link code")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_links, replace_links, restore_links, short_link_url};
+    use std::collections::HashMap;
+
+    #[test]
+    fn links_are_found_in_html_attributes_and_plain_text() {
+        let content = r#"<a href="https://example.com/a">here</a> or https://example.com/b"#;
+        assert_eq!(
+            find_links(content),
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn only_mapped_links_are_replaced() {
+        let content = "Read https://example.com/a and https://example.com/b today";
+        let short_links = HashMap::from([(
+            "https://example.com/a",
+            short_link_url("http://127.0.0.1/", "abc1234"),
+        )]);
+        assert_eq!(
+            replace_links(content, &short_links),
+            "Read http://127.0.0.1/l/abc1234 and https://example.com/b today"
+        );
+    }
+
+    #[test]
+    fn short_links_are_restored_to_their_destination() {
+        let destinations =
+            HashMap::from([("abc1234".to_owned(), "https://example.com/a".to_owned())]);
+        assert_eq!(
+            restore_links(
+                "http://127.0.0.1",
+                r#"<a href="http://127.0.0.1/l/abc1234">a</a> http://127.0.0.1/l/unknown"#,
+                &destinations
+            ),
+            r#"<a href="https://example.com/a">a</a> http://127.0.0.1/l/unknown"#
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use actix_web::HttpRequest;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+
+/// Languages the public endpoints answer in, English being the fallback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    De,
+    Es,
+    Fr,
+}
+
+impl Locale {
+    const SUPPORTED: [Locale; 4] = [Locale::En, Locale::De, Locale::Es, Locale::Fr];
+
+    /// The language tag, for the `Content-Language` header.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Es => "es",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /// The supported language the requester prefers, according to the
+    /// quality values of their `Accept-Language` header.
+    pub fn negotiate(accept_language: Option<&str>) -> Self {
+        let mut best: Option<(Locale, f32)> = None;
+        for range in accept_language.unwrap_or_default().split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or_default().trim();
+            let primary = tag.split('-').next().unwrap_or_default();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())
+                .unwrap_or(0.0);
+            let Some(locale) = Self::SUPPORTED
+                .into_iter()
+                .find(|l| l.tag().eq_ignore_ascii_case(primary))
+            else {
+                continue;
+            };
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((locale, quality));
+            }
+        }
+        best.map_or(Locale::En, |(locale, _)| locale)
+    }
+
+    pub fn from_request(request: &HttpRequest) -> Self {
+        Self::negotiate(
+            request
+                .headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|h| h.to_str().ok()),
+        )
+    }
+}
+
+/// Entries of the message catalog, `{value}` standing for the value at fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    InvalidEmail,
+    EmailDomainNotAllowed,
+    InvalidName,
+    InvalidRegion,
+    InvalidTag,
+    TooManyTags,
+    /// A value failing the patterns an operator configured.
+    NotAccepted,
+}
+
+impl Message {
+    fn template(self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (Message::InvalidEmail, Locale::En) => "'{value}' is not a valid subscriber email",
+            (Message::InvalidEmail, Locale::De) => "'{value}' ist keine gültige E-Mail-Adresse",
+            (Message::InvalidEmail, Locale::Es) => {
+                "'{value}' no es una dirección de correo electrónico válida"
+            }
+            (Message::InvalidEmail, Locale::Fr) => "'{value}' n'est pas une adresse e-mail valide",
+            (Message::EmailDomainNotAllowed, Locale::En) => {
+                "Only {value} addresses can subscribe to this newsletter"
+            }
+            (Message::EmailDomainNotAllowed, Locale::De) => {
+                "Nur {value}-Adressen können diesen Newsletter abonnieren"
+            }
+            (Message::EmailDomainNotAllowed, Locale::Es) => {
+                "Solo las direcciones {value} pueden suscribirse a este boletín"
+            }
+            (Message::EmailDomainNotAllowed, Locale::Fr) => {
+                "Seules les adresses {value} peuvent s'abonner à cette newsletter"
+            }
+            (Message::InvalidName, Locale::En) => "{value} is not a valid subscriber name",
+            (Message::InvalidName, Locale::De) => "{value} ist kein gültiger Name",
+            (Message::InvalidName, Locale::Es) => "{value} no es un nombre válido",
+            (Message::InvalidName, Locale::Fr) => "{value} n'est pas un nom valide",
+            (Message::InvalidRegion, Locale::En) => "{value} is not a valid subscriber region",
+            (Message::InvalidRegion, Locale::De) => "{value} ist keine gültige Region",
+            (Message::InvalidRegion, Locale::Es) => "{value} no es una región válida",
+            (Message::InvalidRegion, Locale::Fr) => "{value} n'est pas une région valide",
+            (Message::InvalidTag, Locale::En) => "{value} is not a valid subscriber tag",
+            (Message::InvalidTag, Locale::De) => "{value} ist kein gültiges Schlagwort",
+            (Message::InvalidTag, Locale::Es) => "{value} no es una etiqueta válida",
+            (Message::InvalidTag, Locale::Fr) => "{value} n'est pas une étiquette valide",
+            (Message::TooManyTags, Locale::En) => "At most {value} tags can be picked",
+            (Message::TooManyTags, Locale::De) => "Höchstens {value} Schlagwörter sind wählbar",
+            (Message::TooManyTags, Locale::Es) => "Se pueden elegir {value} etiquetas como máximo",
+            (Message::TooManyTags, Locale::Fr) => {
+                "Au plus {value} étiquettes peuvent être choisies"
+            }
+            (Message::NotAccepted, Locale::En) => "{value} is not accepted by this newsletter",
+            (Message::NotAccepted, Locale::De) => {
+                "{value} wird von diesem Newsletter nicht akzeptiert"
+            }
+            (Message::NotAccepted, Locale::Es) => "{value} no es aceptado por este boletín",
+            (Message::NotAccepted, Locale::Fr) => "{value} n'est pas accepté par cette newsletter",
+        }
+    }
+}
+
+/// An error about a value submitted by the requester, to be shown to them
+/// in their language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalizedError {
+    message: Message,
+    value: String,
+}
+
+impl LocalizedError {
+    pub fn new(message: Message, value: impl Into<String>) -> Self {
+        Self {
+            message,
+            value: value.into(),
+        }
+    }
+
+    pub fn localize(&self, locale: Locale) -> String {
+        self.message
+            .template(locale)
+            .replace("{value}", &self.value)
+    }
+}
+
+impl std::fmt::Display for LocalizedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Locale::En))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Locale, LocalizedError, Message};
+
+    #[test]
+    fn the_preferred_supported_language_is_picked() {
+        assert_eq!(
+            Locale::negotiate(Some("fr-CH, fr;q=0.9, en;q=0.8")),
+            Locale::Fr
+        );
+        assert_eq!(Locale::negotiate(Some("en;q=0.5, de;q=0.7")), Locale::De);
+        assert_eq!(Locale::negotiate(Some("ja, es;q=0.1")), Locale::Es);
+    }
+
+    #[test]
+    fn english_is_the_fallback() {
+        assert_eq!(Locale::negotiate(None), Locale::En);
+        assert_eq!(Locale::negotiate(Some("ja, *;q=0.5")), Locale::En);
+        assert_eq!(Locale::negotiate(Some("fr;q=0")), Locale::En);
+        assert_eq!(Locale::negotiate(Some("fr;q=nonsense")), Locale::En);
+    }
+
+    #[test]
+    fn messages_are_localized() {
+        let error = LocalizedError::new(Message::InvalidRegion, "eu/west");
+        assert_eq!(
+            error.localize(Locale::Fr),
+            "eu/west n'est pas une région valide"
+        );
+        assert_eq!(
+            error.to_string(),
+            "eu/west is not a valid subscriber region"
+        );
+    }
+}
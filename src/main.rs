@@ -1,14 +1,214 @@
+use anyhow::Context;
+use secrecy::SecretString;
+use sqlx::{Connection, PgConnection, PgPool};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+use zero2prod::accessibility::check_accessibility;
+use zero2prod::archive::{ARCHIVE_PREFIX, Archive};
+use zero2prod::backup::{BackupTarget, backup, restore};
+use zero2prod::blob_store::LocalBlobStore;
+use zero2prod::cli::{
+    CommandError, Output, OutputFormat, check_config, check_migrations, create_admin, migrate,
+    report, self_test,
+};
+use zero2prod::configuration::{Settings, TelemetrySettings};
+use zero2prod::encryption::FieldCipher;
 use zero2prod::get_configuration;
+use zero2prod::rendering::{render_email_fixtures, write_email_fixtures};
 use zero2prod::startup::Application;
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::telemetry::{TelemetryGuard, get_subscriber, init_subscriber};
 
+const USAGE: &str = "Usage: zero2prod [dev up | migrate [--check] | check-config | create-user <username> | self-test | backup <file, URL or store:key> | restore <file, URL or store:key> | render <output directory> | export-archive [<output directory>]] [--format text|json]";
+
+/// Exit codes and the JSON printed with `--format json` are described in
+/// [`zero2prod::cli`].
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let (format, outcome) = match OutputFormat::extract(&mut args) {
+        Ok(format) => (format, run(&args).await),
+        Err(e) => (OutputFormat::Text, Err(e)),
+    };
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => "serve",
+    };
+    report(command, outcome, format)
+}
+
+async fn run(args: &[String]) -> Result<Output, CommandError> {
+    let configuration =
+        get_configuration().map_err(|e| CommandError::InvalidConfiguration(e.to_string()))?;
+    // Subcommands keep stdout for what they report.
+    let _telemetry = if args.is_empty() {
+        init_telemetry(std::io::stdout, &configuration.telemetry)?
+    } else {
+        init_telemetry(std::io::stderr, &configuration.telemetry)?
+    };
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => {
+            let application = Application::build(configuration)
+                .await
+                .context("Failed to build the application")?;
+            application
+                .run_until_stopped()
+                .await
+                .context("Failed to serve")?;
+            Ok(Output::default())
+        }
+        ["dev", "up"] => {
+            dev_up(configuration).await?;
+            Ok(Output::default())
+        }
+        ["migrate"] => migrate(&configuration).await,
+        ["migrate", "--check"] => check_migrations(&configuration).await,
+        ["check-config"] => check_config(&configuration),
+        ["create-user", username] => create_admin(&configuration, username, read_password()?).await,
+        ["self-test"] => self_test(&configuration).await,
+        ["backup", target] => {
+            backup_to(&configuration, target).await?;
+            Ok(Output::default())
+        }
+        ["restore", target] => {
+            restore_from(&configuration, target).await?;
+            Ok(Output::default())
+        }
+        ["render", directory] => {
+            render(&configuration, directory)?;
+            Ok(Output::default())
+        }
+        ["export-archive"] => {
+            export_archive(&configuration, None).await?;
+            Ok(Output::default())
+        }
+        ["export-archive", directory] => {
+            export_archive(&configuration, Some(directory)).await?;
+            Ok(Output::default())
+        }
+        _ => Err(CommandError::InvalidArguments(USAGE.into())),
+    }
+}
+
+fn init_telemetry<Sink>(
+    sink: Sink,
+    telemetry: &TelemetrySettings,
+) -> Result<TelemetryGuard, CommandError>
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let (subscriber, guard) = get_subscriber("zero2prod".into(), "info".into(), sink, telemetry)
+        .context("Failed to set up the trace exporter")?;
     init_subscriber(subscriber);
+    Ok(guard)
+}
+
+/// The password of the admin `create-user` adds, read from the first line
+/// of stdin so that it stays out of the process list.
+fn read_password() -> Result<SecretString, CommandError> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("Failed to read the password from stdin")?;
+    Ok(SecretString::from(
+        password.trim_end_matches(['\r', '\n']).to_owned(),
+    ))
+}
 
-    let configuration = get_configuration().expect("Failed to read configurations");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+/// Dump the database to a file, an HTTP(S) URL such as a presigned S3 URL
+/// or a key of the storage.
+async fn backup_to(configuration: &Settings, target: &str) -> anyhow::Result<()> {
+    let target = BackupTarget::parse(target)?;
+    let cipher = FieldCipher::new(configuration.encryption.as_ref());
+    let mut connection = PgConnection::connect_with(&configuration.database.with_db()).await?;
+    let backup = backup(&mut connection, &cipher).await?;
+    connection.close().await?;
+    target
+        .write(configuration.storage.store().as_ref(), backup)
+        .await
+}
+
+/// Load a backup into a freshly migrated database.
+async fn restore_from(configuration: &Settings, target: &str) -> anyhow::Result<()> {
+    let target = BackupTarget::parse(target)?;
+    let cipher = FieldCipher::new(configuration.encryption.as_ref());
+    let backup = target.read(configuration.storage.store().as_ref()).await?;
+    let mut connection = PgConnection::connect_with(&configuration.database.with_db()).await?;
+    restore(&mut connection, &cipher, backup).await?;
+    connection.close().await?;
     Ok(())
 }
+
+/// Render every email template with fixture data into `directory`, with
+/// a warning for each accessibility issue found on the way.
+fn render(configuration: &Settings, directory: &str) -> anyhow::Result<()> {
+    let written = write_email_fixtures(configuration, Path::new(directory))?;
+    for path in written {
+        println!("{}", path.display());
+    }
+    for email in render_email_fixtures(configuration) {
+        for warning in check_accessibility(&email.html) {
+            eprintln!("warning: {}: {}", email.template, warning);
+        }
+    }
+    Ok(())
+}
+
+/// Render the published issues into a static archive in `directory`, or
+/// under `archive/` in the storage, rewriting only the pages that changed
+/// since the last export.
+async fn export_archive(configuration: &Settings, directory: Option<&str>) -> anyhow::Result<()> {
+    let (store, prefix) = match directory {
+        Some(directory) => (Arc::new(LocalBlobStore::new(directory)) as _, ""),
+        None => (configuration.storage.store(), ARCHIVE_PREFIX),
+    };
+    let pg_pool = PgPool::connect_with(configuration.database.with_db()).await?;
+    let cipher = FieldCipher::new(configuration.encryption.as_ref());
+    let export = Archive::new(configuration)
+        .export(&pg_pool, &cipher, store.as_ref(), prefix)
+        .await?;
+    pg_pool.close().await;
+    for key in &export.written {
+        println!("{}", key);
+    }
+    eprintln!("{} pages unchanged", export.unchanged);
+    Ok(())
+}
+
+/// Run a disposable, migrated Postgres matching the configuration until
+/// Ctrl-C is pressed.
+#[cfg(feature = "dev")]
+async fn dev_up(configuration: Settings) -> anyhow::Result<()> {
+    use sqlx::{Connection, Executor, PgConnection};
+    use zero2prod::dev::EphemeralPostgres;
+
+    let database = configuration.database;
+    let postgres = EphemeralPostgres::start(&database, Some(database.port)).await?;
+    let mut connection = PgConnection::connect_with(&database.without_db()).await?;
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, database.database_name).as_str())
+        .await?;
+    let mut connection = PgConnection::connect_with(&database.with_db()).await?;
+    sqlx::migrate!("./migrations").run(&mut connection).await?;
+    connection.close().await?;
+
+    tracing::info!(
+        "Postgres is up on {}:{}, press Ctrl-C to tear it down",
+        postgres.host,
+        postgres.port
+    );
+    tokio::signal::ctrl_c().await?;
+    postgres.stop().await
+}
+
+#[cfg(not(feature = "dev"))]
+async fn dev_up(_configuration: Settings) -> anyhow::Result<()> {
+    anyhow::bail!("`dev up` requires building with `--features dev`")
+}
@@ -0,0 +1,75 @@
+use crate::configuration::MaintenanceSettings;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Paths still served during maintenance: the health check, so that the
+/// instance stays in rotation, the admin routes and their login, when they
+/// share the public listener, and the webhook of the email provider, whose
+/// bounces and complaints must not be lost.
+const AVAILABLE_PATHS: &[&str] = &[
+    "/health_check",
+    "/admin/",
+    "/newsletters",
+    "/dev/",
+    "/login",
+    "/logout",
+    "/webhooks/email_events",
+];
+
+/// Whether public endpoints are down for maintenance.
+///
+/// Starts from the configuration and is toggled at runtime by an admin. State
+/// is kept in memory, per instance.
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    retry_after_seconds: u64,
+    page: String,
+}
+
+impl MaintenanceMode {
+    pub fn new(settings: &MaintenanceSettings) -> Self {
+        Self {
+            enabled: AtomicBool::new(settings.enabled),
+            retry_after_seconds: settings.retry_after.as_secs().max(1),
+            page: settings.render_page(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .content_type(ContentType::html())
+            .insert_header((RETRY_AFTER, self.retry_after_seconds.to_string()))
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .body(self.page.clone())
+    }
+}
+
+pub async fn reject_during_maintenance(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let maintenance = request.app_data::<web::Data<MaintenanceMode>>().cloned();
+    let Some(maintenance) = maintenance else {
+        return Ok(next.call(request).await?.map_into_left_body());
+    };
+    let available = AVAILABLE_PATHS
+        .iter()
+        .any(|path| request.path().starts_with(path));
+    if !maintenance.is_enabled() || available {
+        return Ok(next.call(request).await?.map_into_left_body());
+    }
+    let response = maintenance.response();
+    Ok(request.into_response(response).map_into_right_body())
+}
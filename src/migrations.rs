@@ -0,0 +1,289 @@
+use crate::configuration::MigrationSettings;
+use anyhow::Context;
+use sqlx::migrate::{Migrate, Migration, Migrator};
+use sqlx::{Executor, PgConnection};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Marks the contract step of an expand-contract change, allowed to drop or
+/// rename what the running version no longer uses.
+pub const CONTRACT_MARKER: &str = "-- migrate:contract";
+
+/// Key of the advisory lock held while migrating, only one instance of a
+/// blue/green deployment applies the pending migrations.
+pub const ADVISORY_LOCK_KEY: i64 = 0x7a65_726f_3270_726f;
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A statement of a pending migration that would break the version still
+/// serving traffic while, or after, it runs.
+#[derive(Debug, PartialEq)]
+pub struct Incompatibility {
+    pub version: i64,
+    pub description: String,
+    pub statement: String,
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}_{}: {} ({})",
+            self.version, self.description, self.reason, self.statement
+        )
+    }
+}
+
+/// Statements the previous version cannot cope with. Changes like these go
+/// through expand-contract: add the new schema, deploy code using it, then
+/// remove the old one in a migration marked with [`CONTRACT_MARKER`].
+pub fn check_backward_compatible(migration: &Migration) -> Vec<Incompatibility> {
+    if migration.sql.contains(CONTRACT_MARKER) {
+        return Vec::new();
+    }
+    statements(&migration.sql)
+        .into_iter()
+        .filter_map(|statement| {
+            let reason = incompatibility(&statement.to_uppercase())?;
+            Some(Incompatibility {
+                version: migration.version,
+                description: migration.description.to_string(),
+                statement,
+                reason,
+            })
+        })
+        .collect()
+}
+
+fn incompatibility(statement: &str) -> Option<&'static str> {
+    if statement.contains("DROP TABLE") {
+        Some("drops a table")
+    } else if statement.contains("DROP COLUMN") {
+        Some("drops a column")
+    } else if statement.contains("RENAME ") {
+        Some("renames a table or a column")
+    } else if statement.contains("ALTER COLUMN") && statement.contains(" TYPE ") {
+        Some("changes the type of a column")
+    } else if statement.contains("ALTER COLUMN") && statement.contains("SET NOT NULL") {
+        Some("makes a column required")
+    } else if statement.contains("ADD COLUMN")
+        && statement.contains("NOT NULL")
+        && !statement.contains("DEFAULT")
+    {
+        Some("adds a required column without a default")
+    } else {
+        None
+    }
+}
+
+/// The statements of `sql`, comments stripped and whitespace collapsed.
+fn statements(sql: &str) -> Vec<String> {
+    let code: String = sql
+        .lines()
+        .map(|line| line.split("--").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(" ");
+    code.split(';')
+        .map(|statement| statement.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Migrations not applied to the database yet, and whether any was.
+#[tracing::instrument(name = "List pending migrations", skip_all)]
+pub async fn pending_migrations(
+    connection: &mut PgConnection,
+) -> Result<(Vec<&'static Migration>, bool), anyhow::Error> {
+    let table_exists: bool =
+        sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(&mut *connection)
+            .await
+            .context("Failed to look up the migrations table")?;
+    let applied: HashSet<i64> = if table_exists {
+        connection
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|m| m.version)
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let pending = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .collect();
+    Ok((pending, !applied.is_empty()))
+}
+
+/// Incompatibilities of the pending migrations. A database without any
+/// migration applied has no running version to break.
+pub async fn check_pending_migrations(
+    connection: &mut PgConnection,
+) -> Result<Vec<Incompatibility>, anyhow::Error> {
+    let (pending, in_use) = pending_migrations(connection).await?;
+    if !in_use {
+        return Ok(Vec::new());
+    }
+    Ok(pending
+        .into_iter()
+        .flat_map(check_backward_compatible)
+        .collect())
+}
+
+/// Apply the pending migrations while the previous version keeps serving
+/// traffic, returning the versions applied.
+///
+/// Only one instance migrates at a time, the others wait for it and find
+/// nothing left to do. Migrations breaking the running version are refused
+/// and no statement waits on a table lock longer than `lock_timeout`.
+#[tracing::instrument(name = "Run migrations online", skip_all)]
+pub async fn run_online(
+    connection: &mut PgConnection,
+    settings: &MigrationSettings,
+) -> Result<Vec<i64>, anyhow::Error> {
+    acquire_advisory_lock(connection, settings.advisory_lock_wait).await?;
+    let outcome = apply_pending(connection, settings).await;
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(ADVISORY_LOCK_KEY)
+        .execute(&mut *connection)
+        .await
+        .context("Failed to release the migration lock")?;
+    outcome
+}
+
+async fn acquire_advisory_lock(
+    connection: &mut PgConnection,
+    wait: Duration,
+) -> Result<(), anyhow::Error> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(ADVISORY_LOCK_KEY)
+            .fetch_one(&mut *connection)
+            .await
+            .context("Failed to take the migration lock")?;
+        if locked {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("Another instance is still migrating the database");
+        }
+        tracing::info!("Waiting for another instance to finish migrating");
+        tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+    }
+}
+
+async fn apply_pending(
+    connection: &mut PgConnection,
+    settings: &MigrationSettings,
+) -> Result<Vec<i64>, anyhow::Error> {
+    let incompatibilities = check_pending_migrations(connection).await?;
+    if !incompatibilities.is_empty() {
+        let list: Vec<_> = incompatibilities.iter().map(|i| i.to_string()).collect();
+        anyhow::bail!(
+            "Pending migrations would break the running version:\n{}",
+            list.join("\n")
+        );
+    }
+    let (pending, _) = pending_migrations(connection).await?;
+    let versions = pending.iter().map(|m| m.version).collect();
+
+    connection
+        .execute(format!("SET lock_timeout = {}", settings.lock_timeout.as_millis()).as_str())
+        .await
+        .context("Failed to set the lock timeout")?;
+    // The advisory lock is already held, the migrator must not wait on its own.
+    let mut migrator = sqlx::migrate!("./migrations");
+    migrator.set_locking(false);
+    let outcome = migrator.run(&mut *connection).await;
+    connection
+        .execute("RESET lock_timeout")
+        .await
+        .context("Failed to reset the lock timeout")?;
+    outcome.context("Failed to apply the pending migrations")?;
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CONTRACT_MARKER, check_backward_compatible};
+    use sqlx::migrate::{Migration, MigrationType};
+
+    fn migration(sql: &str) -> Migration {
+        Migration::new(
+            20250101120000,
+            "change".into(),
+            MigrationType::Simple,
+            sql.to_owned().into(),
+            false,
+        )
+    }
+
+    fn reasons(sql: &str) -> Vec<&'static str> {
+        check_backward_compatible(&migration(sql))
+            .into_iter()
+            .map(|i| i.reason)
+            .collect()
+    }
+
+    #[test]
+    fn additive_changes_are_compatible() {
+        assert!(
+            reasons(
+                "CREATE TABLE things (id uuid NOT NULL PRIMARY KEY);
+                 ALTER TABLE subscriptions ADD COLUMN nickname TEXT;
+                 ALTER TABLE subscriptions ADD COLUMN score INT NOT NULL DEFAULT 0;
+                 CREATE INDEX things_idx ON things (id);"
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn destructive_changes_are_incompatible() {
+        assert_eq!(
+            reasons(
+                "ALTER TABLE subscriptions DROP COLUMN name;
+                 ALTER TABLE users RENAME COLUMN password TO password_hash;
+                 DROP TABLE things;
+                 ALTER TABLE subscriptions ALTER COLUMN status TYPE VARCHAR(20);
+                 ALTER TABLE subscriptions ALTER COLUMN status SET NOT NULL;
+                 ALTER TABLE subscriptions ADD COLUMN score INT NOT NULL;"
+            ),
+            [
+                "drops a column",
+                "renames a table or a column",
+                "drops a table",
+                "changes the type of a column",
+                "makes a column required",
+                "adds a required column without a default",
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_are_matched_across_lines_and_case() {
+        assert_eq!(
+            reasons("alter table subscriptions\n    drop\n    column name;"),
+            ["drops a column"]
+        );
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        assert!(reasons("-- We used to DROP COLUMN here\nSELECT 1;").is_empty());
+    }
+
+    #[test]
+    fn contract_migrations_are_allowed() {
+        let sql = format!(
+            "{}\nALTER TABLE subscriptions DROP COLUMN name;",
+            CONTRACT_MARKER
+        );
+        assert!(reasons(&sql).is_empty());
+    }
+}
@@ -0,0 +1,423 @@
+use crate::configuration::{GooglePostmasterSettings, PostmasterSettings, Settings};
+use crate::jobs::Job;
+use crate::leader_election::LeaderElection;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Pulls the reputation reports of the sending domains: DMARC aggregate
+/// reports, and Google Postmaster Tools statistics, so that reputation
+/// problems show on the deliverability dashboard before open rates drop.
+pub struct PostmasterIngester {
+    settings: PostmasterSettings,
+    http_client: reqwest::Client,
+    pg_pool: PgPool,
+    leader_election: Arc<LeaderElection>,
+    job: Arc<Job>,
+}
+
+/// What a run of the ingester stored.
+#[derive(Debug, Default, PartialEq)]
+pub struct IngestedReports {
+    pub dmarc_reports: usize,
+    pub reputation_days: usize,
+}
+
+impl PostmasterIngester {
+    pub fn build(configuration: &Settings, pg_pool: PgPool) -> Option<Self> {
+        let settings = configuration.postmaster.clone()?;
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.timeout)
+            .build()
+            .expect("Failed to build the postmaster HTTP client");
+        Some(Self {
+            settings,
+            http_client,
+            leader_election: Arc::new(LeaderElection::new("postmaster", pg_pool.clone())),
+            pg_pool,
+            job: Job::new("postmaster"),
+        })
+    }
+
+    pub fn job(&self) -> Arc<Job> {
+        self.job.clone()
+    }
+
+    /// Only the leader among the running instances pulls the reports.
+    pub fn leader_election(&self) -> Arc<LeaderElection> {
+        self.leader_election.clone()
+    }
+
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) {
+        self.job
+            .run_every(
+                self.settings.poll_interval,
+                Some(&self.leader_election),
+                &shutdown,
+                || async { self.ingest_once().await.map(|_| ()) },
+            )
+            .await
+    }
+
+    /// Pull every configured source once.
+    #[tracing::instrument(name = "Ingest postmaster reports", skip(self))]
+    pub async fn ingest_once(&self) -> Result<IngestedReports, anyhow::Error> {
+        let mut ingested = IngestedReports::default();
+        if let Some(url) = &self.settings.dmarc_reports_url {
+            ingested.dmarc_reports = self.ingest_dmarc_reports(url).await?;
+        }
+        if let Some(google) = &self.settings.google {
+            for domain in &google.domains {
+                ingested.reputation_days += self
+                    .ingest_google_traffic_stats(google, domain)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to ingest the Postmaster Tools data of {}", domain)
+                    })?;
+            }
+        }
+        Ok(ingested)
+    }
+
+    /// Returns how many reports were not seen before.
+    async fn ingest_dmarc_reports(&self, url: &str) -> Result<usize, anyhow::Error> {
+        let reports: Vec<String> = self
+            .http_client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("Failed to fetch the DMARC reports")?
+            .json()
+            .await
+            .context("Failed to read the DMARC reports")?;
+        let mut stored = 0;
+        for xml in reports {
+            let report = match parse_dmarc_report(&xml) {
+                Ok(report) => report,
+                Err(e) => {
+                    tracing::warn!(error.cause_chain = ?e, "Skipping a malformed DMARC report");
+                    continue;
+                }
+            };
+            if store_dmarc_report(&self.pg_pool, &report)
+                .await
+                .context("Failed to store a DMARC report")?
+            {
+                stored += 1;
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Returns how many days of statistics were stored or refreshed.
+    async fn ingest_google_traffic_stats(
+        &self,
+        google: &GooglePostmasterSettings,
+        domain: &str,
+    ) -> Result<usize, anyhow::Error> {
+        let url = format!(
+            "{}/v1/domains/{}/trafficStats",
+            google.base_url.trim_end_matches('/'),
+            domain
+        );
+        let mut stored = 0;
+        let mut page_token = None;
+        loop {
+            let mut request = self
+                .http_client
+                .get(&url)
+                .bearer_auth(google.access_token.expose_secret());
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+            let page: TrafficStatsPage = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .context("Failed to fetch the traffic stats")?
+                .json()
+                .await
+                .context("Failed to read the traffic stats")?;
+            for stats in &page.traffic_stats {
+                let Some(day) = stats.day() else {
+                    tracing::warn!(name = stats.name, "Skipping traffic stats without a date");
+                    continue;
+                };
+                store_domain_reputation(&self.pg_pool, domain, day, stats)
+                    .await
+                    .context("Failed to store the traffic stats of a day")?;
+                stored += 1;
+            }
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => return Ok(stored),
+            }
+        }
+    }
+}
+
+/// The totals of a DMARC aggregate report.
+#[derive(Debug, PartialEq)]
+pub struct DmarcReport {
+    pub org_name: String,
+    pub report_id: String,
+    pub domain: String,
+    pub begins_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub messages: i64,
+    /// Messages with an aligned DKIM or SPF pass.
+    pub passed: i64,
+    pub quarantined: i64,
+    pub rejected: i64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DmarcReportError {
+    #[error("The report is not a valid DMARC aggregate report.")]
+    Malformed(#[from] quick_xml::DeError),
+    #[error("The date range of the report is invalid.")]
+    InvalidDateRange,
+}
+
+/// Parse a DMARC aggregate report, as specified by RFC 7489 appendix C.
+pub fn parse_dmarc_report(xml: &str) -> Result<DmarcReport, DmarcReportError> {
+    let feedback: Feedback = quick_xml::de::from_str(xml)?;
+    let date_range = &feedback.report_metadata.date_range;
+    let timestamp = |t| DateTime::from_timestamp(t, 0).ok_or(DmarcReportError::InvalidDateRange);
+    let mut report = DmarcReport {
+        org_name: feedback.report_metadata.org_name,
+        report_id: feedback.report_metadata.report_id,
+        domain: feedback.policy_published.domain.to_ascii_lowercase(),
+        begins_at: timestamp(date_range.begin)?,
+        ends_at: timestamp(date_range.end)?,
+        messages: 0,
+        passed: 0,
+        quarantined: 0,
+        rejected: 0,
+    };
+    for record in feedback.records {
+        let row = record.row;
+        let evaluated = row.policy_evaluated;
+        report.messages += row.count;
+        if evaluated.dkim.as_deref() == Some("pass") || evaluated.spf.as_deref() == Some("pass") {
+            report.passed += row.count;
+        }
+        match evaluated.disposition.as_str() {
+            "quarantine" => report.quarantined += row.count,
+            "reject" => report.rejected += row.count,
+            _ => {}
+        }
+    }
+    Ok(report)
+}
+
+#[derive(serde::Deserialize)]
+struct Feedback {
+    report_metadata: ReportMetadata,
+    policy_published: PolicyPublished,
+    #[serde(default, rename = "record")]
+    records: Vec<Record>,
+}
+
+#[derive(serde::Deserialize)]
+struct ReportMetadata {
+    org_name: String,
+    report_id: String,
+    date_range: DateRange,
+}
+
+#[derive(serde::Deserialize)]
+struct DateRange {
+    begin: i64,
+    end: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct PolicyPublished {
+    domain: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Record {
+    row: Row,
+}
+
+#[derive(serde::Deserialize)]
+struct Row {
+    count: i64,
+    policy_evaluated: PolicyEvaluated,
+}
+
+#[derive(serde::Deserialize)]
+struct PolicyEvaluated {
+    disposition: String,
+    dkim: Option<String>,
+    spf: Option<String>,
+}
+
+/// A page of the `domains.trafficStats.list` response of the Postmaster
+/// Tools API.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrafficStatsPage {
+    #[serde(default)]
+    traffic_stats: Vec<TrafficStats>,
+    next_page_token: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TrafficStats {
+    /// `domains/{domain}/trafficStats/{YYYYMMDD}`
+    name: String,
+    /// `HIGH`, `MEDIUM`, `LOW` or `BAD`.
+    domain_reputation: Option<String>,
+    user_reported_spam_ratio: Option<f64>,
+    spf_success_ratio: Option<f64>,
+    dkim_success_ratio: Option<f64>,
+    dmarc_success_ratio: Option<f64>,
+}
+
+impl TrafficStats {
+    fn day(&self) -> Option<NaiveDate> {
+        let (_, date) = self.name.rsplit_once('/')?;
+        NaiveDate::parse_from_str(date, "%Y%m%d").ok()
+    }
+}
+
+/// Returns `false` if the report was ingested before.
+async fn store_dmarc_report(pg_pool: &PgPool, report: &DmarcReport) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO dmarc_reports (
+            org_name, report_id, domain, begins_at, ends_at,
+            messages, passed, quarantined, rejected, received_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        report.org_name,
+        report.report_id,
+        report.domain,
+        report.begins_at,
+        report.ends_at,
+        report.messages,
+        report.passed,
+        report.quarantined,
+        report.rejected,
+    )
+    .execute(pg_pool)
+    .await?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+/// Statistics of recent days are revised by Google, they are overwritten.
+async fn store_domain_reputation(
+    pg_pool: &PgPool,
+    domain: &str,
+    day: NaiveDate,
+    stats: &TrafficStats,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO domain_reputation (
+            domain, day, reputation, user_reported_spam_ratio,
+            spf_success_ratio, dkim_success_ratio, dmarc_success_ratio, fetched_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (domain, day) DO UPDATE
+        SET reputation = EXCLUDED.reputation,
+            user_reported_spam_ratio = EXCLUDED.user_reported_spam_ratio,
+            spf_success_ratio = EXCLUDED.spf_success_ratio,
+            dkim_success_ratio = EXCLUDED.dkim_success_ratio,
+            dmarc_success_ratio = EXCLUDED.dmarc_success_ratio,
+            fetched_at = EXCLUDED.fetched_at
+        "#,
+        domain.to_ascii_lowercase(),
+        day,
+        stats.domain_reputation,
+        stats.user_reported_spam_ratio,
+        stats.spf_success_ratio,
+        stats.dkim_success_ratio,
+        stats.dmarc_success_ratio,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DmarcReport, parse_dmarc_report};
+    use chrono::{TimeZone, Utc};
+
+    const REPORT: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<feedback>
+  <report_metadata>
+    <org_name>google.com</org_name>
+    <email>noreply-dmarc-support@google.com</email>
+    <report_id>1234567890</report_id>
+    <date_range>
+      <begin>1751328000</begin>
+      <end>1751414399</end>
+    </date_range>
+  </report_metadata>
+  <policy_published>
+    <domain>Example.com</domain>
+    <p>quarantine</p>
+  </policy_published>
+  <record>
+    <row>
+      <source_ip>203.0.113.1</source_ip>
+      <count>8</count>
+      <policy_evaluated>
+        <disposition>none</disposition>
+        <dkim>pass</dkim>
+        <spf>fail</spf>
+      </policy_evaluated>
+    </row>
+    <identifiers><header_from>example.com</header_from></identifiers>
+  </record>
+  <record>
+    <row>
+      <source_ip>198.51.100.7</source_ip>
+      <count>2</count>
+      <policy_evaluated>
+        <disposition>quarantine</disposition>
+        <dkim>fail</dkim>
+        <spf>fail</spf>
+      </policy_evaluated>
+    </row>
+    <identifiers><header_from>example.com</header_from></identifiers>
+  </record>
+</feedback>"#;
+
+    #[test]
+    fn dmarc_reports_are_summed_up() {
+        assert_eq!(
+            parse_dmarc_report(REPORT).unwrap(),
+            DmarcReport {
+                org_name: "google.com".into(),
+                report_id: "1234567890".into(),
+                domain: "example.com".into(),
+                begins_at: Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap(),
+                ends_at: Utc.with_ymd_and_hms(2025, 7, 1, 23, 59, 59).unwrap(),
+                messages: 10,
+                passed: 8,
+                quarantined: 2,
+                rejected: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn other_documents_are_rejected() {
+        assert!(parse_dmarc_report("<feed><entry/></feed>").is_err());
+        assert!(parse_dmarc_report("not xml").is_err());
+    }
+}
@@ -0,0 +1,919 @@
+use crate::calendar::{IssueEvent, event_ics_link};
+use crate::configuration::ShortLinkSettings;
+use crate::delivery::{IssueDelivery, deliver_queued, enqueue_deliveries};
+use crate::domain::{Segment, SubscriberEmail};
+use crate::email_client::EmailHeader;
+use crate::encryption::FieldCipher;
+use crate::extensions::{DomainEvents, Published};
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::link_cards::expand_cards;
+use crate::link_shortener::{LinkShortener, get_link_destinations};
+use crate::routes::error_chain_fmt;
+use crate::routes::subscription_status::subscription_status_link;
+use crate::routes::subscriptions_unsubscribe::unsubscribe_link;
+use crate::senders::{IssueSender, UnverifiedSender, is_verified_sender};
+use crate::signing::UrlSigner;
+use crate::template_fragments::{NonCompliantFooter, TemplateFragments};
+use crate::templates::EmailTemplates;
+use crate::tracking::TrackingMode;
+use crate::web_pages::PageFetcher;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Content of a newsletter issue, as written by its author.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IssueContent {
+    pub title: String,
+    pub html: String,
+    pub text: String,
+    /// AMP for Email body, sent as written: its links are neither shortened
+    /// nor tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<IssueEvent>,
+    /// Sent from this address rather than the configured one. It must be in
+    /// the registry of verified senders by the time the issue is published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<IssueSender>,
+    /// Addresses, e.g. of the marketing team or an archive mailbox, sent a
+    /// copy of the issue without being subscribers. Their copies are not
+    /// tracked nor counted as deliveries.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub internal_copies: Vec<String>,
+}
+
+impl IssueContent {
+    /// The content with its link cards expanded into previews of the pages
+    /// they link to.
+    ///
+    /// The pages are fetched over HTTP: this is done before the transaction
+    /// storing the issue begins, not to hold it open meanwhile.
+    pub async fn with_cards_expanded(
+        mut self,
+        pg_pool: &PgPool,
+        page_fetcher: &PageFetcher,
+    ) -> Result<Self, anyhow::Error> {
+        let (html, text) = expand_cards(pg_pool, page_fetcher, &self.html, &self.text)
+            .await
+            .context("Failed to expand the link cards")?;
+        self.html = html;
+        self.text = text;
+        Ok(self)
+    }
+}
+
+/// Internal copies are sent to addresses we trust, but typos should not wait
+/// for the issue to go out to be noticed.
+pub fn validate_internal_copies(emails: &[String]) -> Result<(), String> {
+    for email in emails {
+        SubscriberEmail::try_from(email.trim().to_owned())
+            .map_err(|_| format!("`{}` is not a valid address for an internal copy.", email))?;
+    }
+    Ok(())
+}
+
+/// A stored issue, with its links already shortened, ready to be emailed.
+pub struct StoredIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub amp_content: Option<String>,
+    pub event: Option<IssueEvent>,
+    pub sender: Option<IssueSender>,
+    pub tracking_mode: TrackingMode,
+    /// Destination of the short links of the issue, by code, for the
+    /// recipients who opted out of tracking.
+    pub link_destinations: HashMap<String, String>,
+}
+
+/// Closes every issue with links to the recipient's subscription status and
+/// to unsubscribe.
+pub struct SubscriberFooter {
+    templates: EmailTemplates,
+    url_signer: UrlSigner,
+}
+
+impl SubscriberFooter {
+    pub fn new(templates: EmailTemplates, url_signer: UrlSigner) -> Self {
+        Self {
+            templates,
+            url_signer,
+        }
+    }
+
+    pub async fn append(
+        &self,
+        base_url: &str,
+        subscriber_id: Uuid,
+        html: &str,
+        text: &str,
+    ) -> (String, String) {
+        let status_link = subscription_status_link(base_url, &self.url_signer, subscriber_id);
+        let unsubscribe_link = unsubscribe_link(base_url, &self.url_signer, subscriber_id);
+        self.templates
+            .current()
+            .await
+            .footer
+            .append(html, text, &status_link, &unsubscribe_link)
+    }
+
+    pub async fn append_amp(&self, base_url: &str, subscriber_id: Uuid, amp: &str) -> String {
+        let status_link = subscription_status_link(base_url, &self.url_signer, subscriber_id);
+        let unsubscribe_link = unsubscribe_link(base_url, &self.url_signer, subscriber_id);
+        self.templates
+            .current()
+            .await
+            .footer
+            .append_amp(amp, &status_link, &unsubscribe_link)
+    }
+
+    /// `List-Unsubscribe` headers, letting mailbox providers show their own
+    /// unsubscribe button, which posts to the link without leaving the inbox
+    /// (RFC 8058).
+    pub fn unsubscribe_headers(&self, base_url: &str, subscriber_id: Uuid) -> Vec<EmailHeader> {
+        vec![
+            EmailHeader {
+                name: "List-Unsubscribe".into(),
+                value: format!(
+                    "<{}>",
+                    unsubscribe_link(base_url, &self.url_signer, subscriber_id)
+                ),
+            },
+            EmailHeader {
+                name: "List-Unsubscribe-Post".into(),
+                value: "List-Unsubscribe=One-Click".into(),
+            },
+        ]
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum StoreIssueError {
+    #[error(transparent)]
+    NonCompliantFooter(#[from] NonCompliantFooter),
+    #[error(transparent)]
+    UnverifiedSender(#[from] UnverifiedSender),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for StoreIssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Store a new issue, wrapped in the template fragments, and shorten its
+/// links. Its link cards are expanded beforehand, see
+/// [`IssueContent::with_cards_expanded`].
+///
+/// Nothing is sent until [`deliver_issue`] is called, after `transaction`
+/// has been committed. Issues are not stored while a footer fragment misses
+/// a required token, nor when they are sent from an unverified sender.
+///
+/// `author_id` is the admin publishing the issue, `None` for the issues
+/// published by the application itself.
+#[tracing::instrument(
+    name = "Store newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty)
+)]
+pub async fn store_issue(
+    transaction: &mut PgConnection,
+    cipher: &FieldCipher,
+    base_url: &str,
+    short_link_settings: &ShortLinkSettings,
+    content: &IssueContent,
+    tracking_mode: TrackingMode,
+    author_id: Option<Uuid>,
+) -> Result<StoredIssue, StoreIssueError> {
+    let fragments = TemplateFragments::load(transaction)
+        .await
+        .context("Failed to load the template fragments")?;
+    fragments.check_compliance()?;
+    if let Some(sender) = &content.sender
+        && !is_verified_sender(transaction, &sender.email)
+            .await
+            .context("Failed to check the sender of the issue")?
+    {
+        return Err(UnverifiedSender(sender.email.clone()).into());
+    }
+    let newsletter_issue_id =
+        insert_newsletter_issue(transaction, cipher, content, tracking_mode, author_id)
+            .await
+            .context("Failed to store newsletter issue details")?;
+    tracing::Span::current().record(
+        "newsletter_issue_id",
+        tracing::field::display(&newsletter_issue_id),
+    );
+    insert_internal_copies(transaction, newsletter_issue_id, &content.internal_copies)
+        .await
+        .context("Failed to store the internal copies of a newsletter issue")?;
+    let mut link_shortener = LinkShortener::new(
+        base_url,
+        newsletter_issue_id,
+        short_link_settings.expires_at(Utc::now()),
+    );
+    let (html, text) = match &content.event {
+        Some(event) => event.add_to_calendar(
+            &content.html,
+            &content.text,
+            &event_ics_link(base_url, newsletter_issue_id),
+        ),
+        None => (content.html.clone(), content.text.clone()),
+    };
+    let (html, text) = fragments.wrap(&html, &text);
+    let html_content = link_shortener.shorten(transaction, &html).await?;
+    let text_content = link_shortener.shorten(transaction, &text).await?;
+    update_newsletter_issue_content(
+        transaction,
+        cipher,
+        newsletter_issue_id,
+        &html_content,
+        &text_content,
+    )
+    .await
+    .context("Failed to store the shortened content of a newsletter issue")?;
+    Ok(StoredIssue {
+        newsletter_issue_id,
+        title: content.title.clone(),
+        html_content,
+        text_content,
+        amp_content: content.amp.clone(),
+        event: content.event.clone(),
+        sender: content.sender.clone(),
+        tracking_mode,
+        link_destinations: link_shortener.destinations(),
+    })
+}
+
+/// Email a stored issue to every confirmed subscriber in `segment`.
+///
+/// The deliveries are queued first, so that the ones left behind when this
+/// instance fails, or held back while deliveries are paused, are picked up
+/// by a [`DeliveryWorker`](crate::delivery::DeliveryWorker).
+#[tracing::instrument(
+    name = "Deliver newsletter issue",
+    skip(delivery, feature_flags, issue),
+    fields(newsletter_issue_id=%issue.newsletter_issue_id)
+)]
+pub async fn deliver_issue(
+    delivery: IssueDelivery<'_>,
+    feature_flags: &FeatureFlags,
+    issue: &StoredIssue,
+    segment: &Segment,
+) -> Result<(), anyhow::Error> {
+    let pg_pool = delivery.pg_pool;
+    let flags = feature_flags
+        .load(pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    let mut connection = pg_pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    enqueue_deliveries(&mut connection, issue.newsletter_issue_id, segment)
+        .await
+        .context("Failed to enqueue the deliveries of a newsletter issue")?;
+    if flags.is_enabled(PAUSE_DELIVERIES) {
+        tracing::warn!("Deliveries are paused, the issue stays queued");
+        return Ok(());
+    }
+    deliver_queued(delivery, &flags, issue)
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Read back an issue as it is emailed, for a worker that did not store it.
+#[tracing::instrument(name = "Get stored newsletter issue", skip(pg_pool, cipher))]
+pub async fn get_stored_issue(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    newsletter_issue_id: Uuid,
+) -> Result<StoredIssue, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            title, text_content, html_content, amp_content, event, sender_email, sender_name,
+            tracking_mode
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    Ok(StoredIssue {
+        newsletter_issue_id,
+        title: r.title,
+        html_content: cipher.decrypt(r.html_content)?,
+        text_content: cipher.decrypt(r.text_content)?,
+        amp_content: r.amp_content.map(|amp| cipher.decrypt(amp)).transpose()?,
+        event: decrypt_event(cipher, r.event)?,
+        sender: issue_sender(r.sender_email, r.sender_name),
+        tracking_mode: TrackingMode::try_from(r.tracking_mode).map_err(anyhow::Error::msg)?,
+        link_destinations: get_link_destinations(pg_pool, newsletter_issue_id)
+            .await
+            .context("Failed to retrieve the short links of the issue")?,
+    })
+}
+
+/// The event announced by an issue, `None` if the issue does not exist or
+/// announces none.
+#[tracing::instrument(name = "Get the event of a newsletter issue", skip(pg_pool, cipher))]
+pub async fn get_issue_event(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueEvent>, anyhow::Error> {
+    let event = sqlx::query_scalar!(
+        r#"SELECT event FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    .flatten();
+    decrypt_event(cipher, event)
+}
+
+/// A published issue, as listed in the archive.
+#[derive(serde::Serialize)]
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    /// Username of the admin who published the issue, `None` for the issues
+    /// published by the application and once the admin is deleted.
+    pub author: Option<String>,
+    /// Set on the issues published for later, see
+    /// [`schedule_issue`](crate::issue_scheduler::schedule_issue).
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+/// A published issue with its content as it was emailed, links shortened
+/// and without the footer of the recipients.
+#[derive(serde::Serialize)]
+pub struct PublishedIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub published_at: DateTime<Utc>,
+    pub author: Option<String>,
+}
+
+/// Published issues, newest first.
+#[tracing::instrument(name = "List newsletter issues", skip(pg_pool))]
+pub async fn list_issues(
+    pg_pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<IssueSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id, i.title, i.published_at, u.username AS "author?",
+            i.scheduled_at
+        FROM newsletter_issues i
+        LEFT JOIN users u ON u.user_id = i.author_id
+        ORDER BY i.published_at DESC, i.newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+#[tracing::instrument(name = "Count newsletter issues", skip(pg_pool))]
+pub async fn count_issues(pg_pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(pg_pool)
+        .await
+}
+
+#[tracing::instrument(name = "Get a published newsletter issue", skip(pg_pool, cipher))]
+pub async fn get_published_issue(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<PublishedIssue>, anyhow::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT i.title, i.html_content, i.text_content, i.published_at, u.username AS "author?"
+        FROM newsletter_issues i
+        LEFT JOIN users u ON u.user_id = i.author_id
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(PublishedIssue {
+        newsletter_issue_id,
+        title: r.title,
+        html_content: cipher.decrypt(r.html_content)?,
+        text_content: cipher.decrypt(r.text_content)?,
+        published_at: r.published_at,
+        author: r.author,
+    }))
+}
+
+async fn insert_newsletter_issue(
+    pg_connection: &mut PgConnection,
+    cipher: &FieldCipher,
+    content: &IssueContent,
+    tracking_mode: TrackingMode,
+    author_id: Option<Uuid>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, amp_content, event,
+            sender_email, sender_name, published_at, tracking_mode, author_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now(), $9, $10)
+        "#,
+        newsletter_issue_id,
+        content.title,
+        cipher.encrypt(&content.text),
+        cipher.encrypt(&content.html),
+        content.amp.as_deref().map(|amp| cipher.encrypt(amp)),
+        encrypt_event(cipher, content.event.as_ref()),
+        content.sender.as_ref().map(|s| s.email.as_str()),
+        content.sender.as_ref().map(|s| s.name.as_str()),
+        tracking_mode.as_str(),
+        author_id,
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// Copies are sent along with the deliveries of the issue, see
+/// [`send_internal_copies`](crate::delivery::send_internal_copies).
+async fn insert_internal_copies(
+    pg_connection: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    emails: &[String],
+) -> Result<(), sqlx::Error> {
+    let emails: Vec<String> = emails.iter().map(|e| e.trim().to_lowercase()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO internal_copies (newsletter_issue_id, email)
+        SELECT $1, email FROM UNNEST($2::text[]) AS email
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        &emails,
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(())
+}
+
+/// Issues are stored as they are emailed, with their links shortened.
+async fn update_newsletter_issue_content(
+    pg_connection: &mut PgConnection,
+    cipher: &FieldCipher,
+    newsletter_issue_id: Uuid,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET html_content = $2, text_content = $3
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        cipher.encrypt(html_content),
+        cipher.encrypt(text_content),
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(())
+}
+
+/// Events are stored as JSON, encrypted like the rest of the content.
+fn encrypt_event(cipher: &FieldCipher, event: Option<&IssueEvent>) -> Option<String> {
+    event.map(|event| {
+        cipher.encrypt(&serde_json::to_string(event).expect("Events can always be serialized"))
+    })
+}
+
+fn decrypt_event(
+    cipher: &FieldCipher,
+    event: Option<String>,
+) -> Result<Option<IssueEvent>, anyhow::Error> {
+    event
+        .map(|event| Ok(serde_json::from_str(&cipher.decrypt(event)?)?))
+        .transpose()
+}
+
+fn issue_sender(email: Option<String>, name: Option<String>) -> Option<IssueSender> {
+    email.map(|email| IssueSender {
+        email,
+        name: name.unwrap_or_default(),
+    })
+}
+
+#[derive(serde::Serialize)]
+pub struct Draft {
+    pub newsletter_draft_id: Uuid,
+    #[serde(flatten)]
+    pub content: IssueContent,
+    pub created_at: DateTime<Utc>,
+    /// When the [`DraftScheduler`](crate::draft_scheduler::DraftScheduler)
+    /// publishes the draft, if it was scheduled.
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// Why the scheduler last failed to publish the draft, if it did.
+    pub publish_error: Option<String>,
+}
+
+/// Save `content` as a draft, to be reviewed and published later.
+#[tracing::instrument(name = "Store newsletter draft", skip_all)]
+pub async fn insert_draft(
+    pg_connection: &mut PgConnection,
+    cipher: &FieldCipher,
+    content: &IssueContent,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            newsletter_draft_id, title, text_content, html_content, amp_content, event,
+            sender_email, sender_name, internal_copies, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+        "#,
+        newsletter_draft_id,
+        content.title,
+        cipher.encrypt(&content.text),
+        cipher.encrypt(&content.html),
+        content.amp.as_deref().map(|amp| cipher.encrypt(amp)),
+        encrypt_event(cipher, content.event.as_ref()),
+        content.sender.as_ref().map(|s| s.email.as_str()),
+        content.sender.as_ref().map(|s| s.name.as_str()),
+        &content.internal_copies,
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(newsletter_draft_id)
+}
+
+#[tracing::instrument(name = "Get unpublished newsletter drafts", skip(pg_pool, cipher))]
+pub async fn get_unpublished_drafts(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_draft_id, title, text_content, html_content, amp_content, event,
+            sender_email, sender_name, internal_copies, created_at, scheduled_at, publish_error
+        FROM newsletter_drafts
+        WHERE newsletter_issue_id IS NULL
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(Draft {
+            newsletter_draft_id: r.newsletter_draft_id,
+            content: IssueContent {
+                title: r.title,
+                html: cipher.decrypt(r.html_content)?,
+                text: cipher.decrypt(r.text_content)?,
+                amp: r.amp_content.map(|amp| cipher.decrypt(amp)).transpose()?,
+                event: decrypt_event(cipher, r.event)?,
+                sender: issue_sender(r.sender_email, r.sender_name),
+                internal_copies: r.internal_copies,
+            },
+            created_at: r.created_at,
+            scheduled_at: r.scheduled_at,
+            publish_error: r.publish_error,
+        })
+    })
+    .collect::<Result<_, anyhow::Error>>()?;
+    Ok(drafts)
+}
+
+#[tracing::instrument(name = "Get newsletter draft", skip(pg_pool, cipher))]
+pub async fn get_draft(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    newsletter_draft_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT
+            title, text_content, html_content, amp_content, event, sender_email, sender_name,
+            internal_copies, created_at, scheduled_at, publish_error
+        FROM newsletter_drafts
+        WHERE newsletter_draft_id = $1
+        "#,
+        newsletter_draft_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(Draft {
+        newsletter_draft_id,
+        content: IssueContent {
+            title: r.title,
+            html: cipher.decrypt(r.html_content)?,
+            text: cipher.decrypt(r.text_content)?,
+            amp: r.amp_content.map(|amp| cipher.decrypt(amp)).transpose()?,
+            event: decrypt_event(cipher, r.event)?,
+            sender: issue_sender(r.sender_email, r.sender_name),
+            internal_copies: r.internal_copies,
+        },
+        created_at: r.created_at,
+        scheduled_at: r.scheduled_at,
+        publish_error: r.publish_error,
+    }))
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishDraftError {
+    #[error("There is no draft associated with the provided id.")]
+    UnknownDraft,
+    #[error("The draft was already published as issue {0}.")]
+    AlreadyPublished(Uuid),
+    #[error(transparent)]
+    NonCompliantFooter(#[from] NonCompliantFooter),
+    #[error(transparent)]
+    UnverifiedSender(#[from] UnverifiedSender),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<StoreIssueError> for PublishDraftError {
+    fn from(e: StoreIssueError) -> Self {
+        match e {
+            StoreIssueError::NonCompliantFooter(e) => PublishDraftError::NonCompliantFooter(e),
+            StoreIssueError::UnverifiedSender(e) => PublishDraftError::UnverifiedSender(e),
+            StoreIssueError::UnexpectedError(e) => PublishDraftError::UnexpectedError(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for PublishDraftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// What turning a draft into an issue takes on top of delivering it, shared
+/// by the schedulers and the requests publishing one.
+#[derive(Clone, Copy)]
+pub struct IssuePublishing<'a> {
+    pub cipher: &'a FieldCipher,
+    /// Expands the link cards of the draft.
+    pub page_fetcher: &'a PageFetcher,
+    pub feature_flags: &'a FeatureFlags,
+    pub short_link_settings: &'a ShortLinkSettings,
+    /// Told about the issue once it is published.
+    pub events: &'a DomainEvents,
+}
+
+/// Turn a draft into an issue and email it to every confirmed subscriber.
+///
+/// The draft row is locked while the issue is stored, so a draft is never
+/// published twice.
+#[tracing::instrument(name = "Publish newsletter draft", skip(delivery, publishing))]
+pub async fn publish_draft(
+    delivery: IssueDelivery<'_>,
+    publishing: IssuePublishing<'_>,
+    newsletter_draft_id: Uuid,
+    tracking_mode: TrackingMode,
+    author_id: Option<Uuid>,
+) -> Result<Uuid, PublishDraftError> {
+    let IssuePublishing {
+        cipher,
+        page_fetcher,
+        feature_flags,
+        short_link_settings,
+        events,
+    } = publishing;
+    let pg_pool = delivery.pg_pool;
+    // Drafts are not edited once written: their content is read, and its
+    // cards expanded, before the draft is locked.
+    let draft = sqlx::query!(
+        r#"
+        SELECT
+            title, text_content, html_content, amp_content, event, sender_email, sender_name,
+            internal_copies, newsletter_issue_id
+        FROM newsletter_drafts
+        WHERE newsletter_draft_id = $1
+        "#,
+        newsletter_draft_id,
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to retrieve the newsletter draft")?
+    .ok_or(PublishDraftError::UnknownDraft)?;
+    if let Some(newsletter_issue_id) = draft.newsletter_issue_id {
+        return Err(PublishDraftError::AlreadyPublished(newsletter_issue_id));
+    }
+
+    let content = IssueContent {
+        title: draft.title,
+        html: cipher
+            .decrypt(draft.html_content)
+            .context("Failed to decrypt the newsletter draft")?,
+        text: cipher
+            .decrypt(draft.text_content)
+            .context("Failed to decrypt the newsletter draft")?,
+        amp: draft
+            .amp_content
+            .map(|amp| cipher.decrypt(amp))
+            .transpose()
+            .context("Failed to decrypt the newsletter draft")?,
+        event: decrypt_event(cipher, draft.event)
+            .context("Failed to decrypt the newsletter draft")?,
+        sender: issue_sender(draft.sender_email, draft.sender_name),
+        internal_copies: draft.internal_copies,
+    }
+    .with_cards_expanded(pg_pool, page_fetcher)
+    .await?;
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let published = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_drafts
+        WHERE newsletter_draft_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_draft_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to lock the newsletter draft")?
+    .ok_or(PublishDraftError::UnknownDraft)?;
+    if let Some(newsletter_issue_id) = published.newsletter_issue_id {
+        return Err(PublishDraftError::AlreadyPublished(newsletter_issue_id));
+    }
+    let issue = store_issue(
+        &mut transaction,
+        cipher,
+        delivery.base_url,
+        short_link_settings,
+        &content,
+        tracking_mode,
+        author_id,
+    )
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET newsletter_issue_id = $2
+        WHERE newsletter_draft_id = $1
+        "#,
+        newsletter_draft_id,
+        issue.newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the newsletter draft as published")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter draft")?;
+    events.published(Published {
+        newsletter_issue_id: issue.newsletter_issue_id,
+        title: issue.title.clone(),
+    });
+
+    deliver_issue(delivery, feature_flags, &issue, &Segment::default()).await?;
+    Ok(issue.newsletter_issue_id)
+}
+
+/// Have the [`DraftScheduler`](crate::draft_scheduler::DraftScheduler)
+/// publish a draft once `scheduled_at` is reached, replacing any previous
+/// schedule along with its failed attempts.
+#[tracing::instrument(name = "Schedule newsletter draft", skip(pg_pool))]
+pub async fn schedule_draft(
+    pg_pool: &PgPool,
+    newsletter_draft_id: Uuid,
+    scheduled_at: DateTime<Utc>,
+) -> Result<(), PublishDraftError> {
+    let draft = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET scheduled_at = CASE WHEN newsletter_issue_id IS NULL THEN $2 ELSE scheduled_at END,
+            publish_attempts = 0,
+            publish_error = NULL
+        WHERE newsletter_draft_id = $1
+        RETURNING newsletter_issue_id
+        "#,
+        newsletter_draft_id,
+        scheduled_at,
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to schedule the newsletter draft")?
+    .ok_or(PublishDraftError::UnknownDraft)?;
+    match draft.newsletter_issue_id {
+        Some(newsletter_issue_id) => Err(PublishDraftError::AlreadyPublished(newsletter_issue_id)),
+        None => Ok(()),
+    }
+}
+
+/// Drafts scheduled to be published by `now`, earliest first.
+#[tracing::instrument(name = "Get due newsletter drafts", skip(pg_pool))]
+pub async fn get_due_drafts(
+    pg_pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let drafts = sqlx::query!(
+        r#"
+        SELECT newsletter_draft_id FROM newsletter_drafts
+        WHERE newsletter_issue_id IS NULL AND scheduled_at <= $1
+        ORDER BY scheduled_at
+        "#,
+        now,
+    )
+    .fetch_all(pg_pool)
+    .await?
+    .into_iter()
+    .map(|r| r.newsletter_draft_id)
+    .collect();
+    Ok(drafts)
+}
+
+/// Record that a scheduled draft failed to publish at `now`: it is tried
+/// again after a delay doubling from `first_retry_after` with each attempt,
+/// and unscheduled after `max_attempts`. Return whether it was unscheduled.
+#[tracing::instrument(name = "Record a failure to publish a draft", skip(pg_pool, error))]
+pub async fn record_publish_failure(
+    pg_pool: &PgPool,
+    newsletter_draft_id: Uuid,
+    error: &str,
+    now: DateTime<Utc>,
+    first_retry_after: std::time::Duration,
+    max_attempts: i32,
+) -> Result<bool, sqlx::Error> {
+    let attempts = sqlx::query_scalar!(
+        r#"
+        UPDATE newsletter_drafts
+        SET publish_attempts = publish_attempts + 1,
+            publish_error = $2,
+            scheduled_at = CASE
+                WHEN publish_attempts + 1 >= $5 THEN NULL
+                ELSE $3::timestamptz + make_interval(secs => $4::float8 * power(2, publish_attempts))
+            END
+        WHERE newsletter_draft_id = $1
+        RETURNING publish_attempts
+        "#,
+        newsletter_draft_id,
+        error,
+        now,
+        first_retry_after.as_secs_f64(),
+        max_attempts,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    Ok(attempts >= max_attempts)
+}
+
+/// Make user supplied text safe to embed in the HTML body of an issue.
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_html;
+
+    #[test]
+    fn text_is_escaped_for_html() {
+        assert_eq!(
+            escape_html("<Tips & \"tricks\">"),
+            "&lt;Tips &amp; &quot;tricks&quot;&gt;"
+        );
+    }
+}
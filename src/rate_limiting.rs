@@ -0,0 +1,246 @@
+use crate::configuration::RateLimitSettings;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits how often an address can use the endpoints sending emails to
+/// whatever address they are given, e.g. to flood someone's inbox with
+/// confirmation emails, or guessing passwords.
+///
+/// Each address has a bucket of `burst` tokens, a request taking one and a
+/// token coming back every `refill_interval`. IPv6 clients are limited per
+/// /64, the smallest block a site is usually given. State is kept in
+/// memory, per instance.
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    by_address: HashMap<IpAddr, Bucket>,
+    swept_at: Option<Instant>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// The address `address` is limited as: itself for IPv4, its /64 for IPv6.
+pub(crate) fn limited_address(address: IpAddr) -> IpAddr {
+    match address.to_canonical() {
+        IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & (u128::MAX << 64))),
+        v4 => v4,
+    }
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(Buckets {
+                by_address: HashMap::new(),
+                swept_at: None,
+            }),
+        }
+    }
+
+    /// Take a token from the bucket of `address`, or tell how long until
+    /// the next one when it is empty.
+    pub fn acquire(&self, address: IpAddr, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(self.settings.burst);
+        let mut buckets = self.buckets.lock().unwrap();
+        // A bucket left alone that long is full again, as good as a new
+        // one: forgetting those keeps the addresses seen lately only, for a
+        // sweep per interval rather than per request.
+        let refill_time = self.settings.refill_interval.mul_f64(burst);
+        let swept_at = *buckets.swept_at.get_or_insert(now);
+        if now.saturating_duration_since(swept_at) >= refill_time {
+            buckets
+                .by_address
+                .retain(|_, bucket| self.refill(bucket, now) < burst);
+            buckets.swept_at = Some(now);
+        }
+        let bucket = buckets
+            .by_address
+            .entry(limited_address(address))
+            .or_insert(Bucket {
+                tokens: burst,
+                refilled_at: now,
+            });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(self.settings.refill_interval.mul_f64(1.0 - bucket.tokens))
+    }
+
+    /// Tokens in `bucket` at `now`.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        let refilled = elapsed.as_secs_f64() / self.settings.refill_interval.as_secs_f64();
+        (bucket.tokens + refilled).min(f64::from(self.settings.burst))
+    }
+}
+
+/// Whether requests to `path` with `method` are rate limited.
+fn is_limited(method: &Method, path: &str) -> bool {
+    let posted = [
+        "/subscriptions",
+        "/subscriptions/resend_confirmation",
+        "/subscriptions/login",
+        "/login",
+    ];
+    (method == Method::POST && posted.contains(&path)) || path == "/subscriptions/confirm"
+}
+
+/// Middleware answering `429 Too Many Requests` to the addresses exceeding
+/// their limit on subscribing, confirming, resending confirmations, asking
+/// for login links and logging in as an admin.
+///
+/// Addresses are taken from the TCP connection: behind a reverse proxy they
+/// all belong to the proxy.
+pub async fn rate_limit_signups(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limiter = request.app_data::<web::Data<RateLimiter>>().cloned();
+    let address = request.peer_addr().map(|a| a.ip());
+    let (Some(limiter), Some(address)) = (limiter, address) else {
+        return Ok(next.call(request).await?.map_into_left_body());
+    };
+    if !is_limited(request.method(), request.path()) {
+        return Ok(next.call(request).await?.map_into_left_body());
+    }
+
+    if let Err(retry_after) = limiter.acquire(address, Instant::now()) {
+        tracing::warn!(%address, path = request.path(), "Rate limiting an address");
+        let response = HttpResponse::TooManyRequests()
+            .insert_header((
+                RETRY_AFTER,
+                retry_after.as_secs_f64().ceil().max(1.0).to_string(),
+            ))
+            .body("Too many requests were sent from your address, try again later.");
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+    Ok(next.call(request).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimiter, is_limited, limited_address};
+    use crate::configuration::RateLimitSettings;
+    use actix_web::http::Method;
+    use claims::{assert_err, assert_ok};
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitSettings {
+            burst: 2,
+            refill_interval: Duration::from_secs(10),
+        })
+    }
+
+    fn address() -> IpAddr {
+        "203.0.113.7".parse().unwrap()
+    }
+
+    #[test]
+    fn bursts_are_allowed_then_limited() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert_ok!(limiter.acquire(address(), now));
+        assert_ok!(limiter.acquire(address(), now));
+        assert_eq!(
+            limiter.acquire(address(), now),
+            Err(Duration::from_secs(10))
+        );
+        assert_ok!(limiter.acquire("203.0.113.8".parse().unwrap(), now));
+    }
+
+    #[test]
+    fn tokens_come_back_over_time() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert_ok!(limiter.acquire(address(), now));
+        }
+
+        assert_eq!(
+            limiter.acquire(address(), now + Duration::from_secs(4)),
+            Err(Duration::from_secs(6))
+        );
+        assert_ok!(limiter.acquire(address(), now + Duration::from_secs(10)));
+        assert_err!(limiter.acquire(address(), now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn buckets_never_hold_more_than_a_burst() {
+        let limiter = limiter();
+        let now = Instant::now();
+        assert_ok!(limiter.acquire(address(), now));
+
+        let later = now + Duration::from_secs(3600);
+        for _ in 0..2 {
+            assert_ok!(limiter.acquire(address(), later));
+        }
+        assert_err!(limiter.acquire(address(), later));
+    }
+
+    #[test]
+    fn ipv6_addresses_are_limited_per_64() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for address in ["2001:db8:1:2::1", "2001:db8:1:2:ffff::7"] {
+            assert_ok!(limiter.acquire(address.parse().unwrap(), now));
+        }
+
+        assert_err!(limiter.acquire("2001:db8:1:2::abcd".parse().unwrap(), now));
+        assert_ok!(limiter.acquire("2001:db8:1:3::1".parse().unwrap(), now));
+        assert_eq!(
+            limited_address("::ffff:203.0.113.7".parse().unwrap()),
+            address()
+        );
+    }
+
+    #[test]
+    fn buckets_left_alone_are_forgotten() {
+        let limiter = limiter();
+        let now = Instant::now();
+        assert_ok!(limiter.acquire(address(), now));
+        assert_ok!(limiter.acquire("203.0.113.8".parse().unwrap(), now));
+
+        let later = now + Duration::from_secs(20);
+        assert_ok!(limiter.acquire("203.0.113.9".parse().unwrap(), later));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_address.len(), 1);
+    }
+
+    #[test]
+    fn only_endpoints_sending_emails_or_logging_in_are_limited() {
+        assert!(is_limited(&Method::POST, "/subscriptions"));
+        assert!(is_limited(&Method::GET, "/subscriptions/confirm"));
+        assert!(is_limited(
+            &Method::POST,
+            "/subscriptions/resend_confirmation"
+        ));
+        assert!(is_limited(&Method::POST, "/subscriptions/login"));
+        assert!(!is_limited(&Method::GET, "/subscriptions/login"));
+        assert!(is_limited(&Method::POST, "/login"));
+        assert!(!is_limited(&Method::GET, "/login"));
+        assert!(!is_limited(&Method::GET, "/subscriptions"));
+        assert!(!is_limited(&Method::POST, "/subscriptions/status"));
+    }
+}
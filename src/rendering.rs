@@ -0,0 +1,203 @@
+use crate::admin_reports::{ReportStats, TopIssue, render_admin_report};
+use crate::configuration::Settings;
+use crate::digests::{DigestEntry, render_digest};
+use crate::feed_watcher::render_feed_entry;
+use chrono::{TimeZone, Utc};
+use std::path::{Path, PathBuf};
+
+/// An email template rendered with fixture data.
+pub struct RenderedEmail {
+    pub template: &'static str,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Render every configured email template with fixed fixture data.
+///
+/// The output only depends on the configuration, so it can be committed as
+/// golden files or fed to email testing tools.
+pub fn render_email_fixtures(configuration: &Settings) -> Vec<RenderedEmail> {
+    let templates = &configuration.email_templates;
+    let base_url = configuration.application.base_url.trim_end_matches('/');
+
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token=fixtureSubscriptionToken0",
+        base_url
+    );
+    let confirmation = [
+        ("confirmation_link", confirmation_link.as_str()),
+        ("name", "Ursula"),
+    ];
+    let reengagement_link = format!(
+        "{}/subscriptions/reengage?reengagement_token=fixtureReengagementToken0",
+        base_url
+    );
+    let reengagement = [("reengagement_link", reengagement_link.as_str())];
+    let magic_link = format!(
+        "{}/subscriptions/status?token=fixtureMagicLinkToken0",
+        base_url
+    );
+    let magic_link = [
+        ("magic_link", magic_link.as_str()),
+        ("expires_in_minutes", "15"),
+    ];
+    let status_link = format!(
+        "{}/subscriptions/status?token=fixtureStatusToken0",
+        base_url
+    );
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?token=fixtureUnsubscribeToken0",
+        base_url
+    );
+    let with_footer = |html: String, text: String| {
+        templates
+            .footer
+            .append(&html, &text, &status_link, &unsubscribe_link)
+    };
+    let feed_entry = render_feed_entry(
+        &templates.feed_entry,
+        "Shipping a newsletter in Rust",
+        "https://blog.example.com/shipping-a-newsletter-in-rust",
+        "<p>What we learned running <em>zero2prod</em> in production.</p>",
+    );
+    let digest = render_digest(
+        &templates.digest,
+        "Weekly digest",
+        &[
+            DigestEntry {
+                title: "Shipping a newsletter in Rust".into(),
+                links: vec!["https://blog.example.com/shipping-a-newsletter-in-rust".into()],
+            },
+            DigestEntry {
+                title: "Tips & tricks".into(),
+                links: vec![],
+            },
+        ],
+    );
+
+    let admin_report = render_admin_report(
+        &templates.admin_report,
+        "Weekly report",
+        Utc.with_ymd_and_hms(2025, 7, 21, 8, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(2025, 7, 28, 8, 0, 0).unwrap(),
+        &ReportStats {
+            new_subscribers: 12,
+            unsubscribes: 2,
+            confirmed_subscribers: 340,
+            top_issues: vec![TopIssue {
+                title: "Shipping a newsletter in Rust".into(),
+                deliveries: 338,
+                unique_opens: 171,
+            }],
+            deliveries: 338,
+            bounces: 3,
+            complaints: 0,
+        },
+    );
+
+    let (confirmation_html, confirmation_text) = with_footer(
+        templates.confirmation.render_html(&confirmation),
+        templates.confirmation.render_text(&confirmation),
+    );
+    let (reengagement_html, reengagement_text) = with_footer(
+        templates.reengagement.render_html(&reengagement),
+        templates.reengagement.render_text(&reengagement),
+    );
+    let (feed_entry_html, feed_entry_text) = with_footer(feed_entry.html, feed_entry.text);
+    let (digest_html, digest_text) = with_footer(digest.html, digest.text);
+    vec![
+        RenderedEmail {
+            template: "confirmation",
+            subject: templates.confirmation.render_subject(&confirmation),
+            html: confirmation_html,
+            text: confirmation_text,
+        },
+        RenderedEmail {
+            template: "reengagement",
+            subject: templates.reengagement.subject.clone(),
+            html: reengagement_html,
+            text: reengagement_text,
+        },
+        RenderedEmail {
+            template: "feed_entry",
+            subject: feed_entry.title,
+            html: feed_entry_html,
+            text: feed_entry_text,
+        },
+        RenderedEmail {
+            template: "digest",
+            subject: digest.title,
+            html: digest_html,
+            text: digest_text,
+        },
+        RenderedEmail {
+            template: "magic_link",
+            subject: templates.magic_link.subject.clone(),
+            html: templates.magic_link.render_html(&magic_link),
+            text: templates.magic_link.render_text(&magic_link),
+        },
+        RenderedEmail {
+            template: "admin_report",
+            subject: admin_report.subject,
+            html: admin_report.html,
+            text: admin_report.text,
+        },
+    ]
+}
+
+/// Write `{template}.subject.txt`, `{template}.html` and `{template}.txt`
+/// for every template into `directory`, creating it if needed.
+pub fn write_email_fixtures(
+    configuration: &Settings,
+    directory: &Path,
+) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(directory)?;
+    let mut written = Vec::new();
+    for email in render_email_fixtures(configuration) {
+        for (extension, content) in [
+            ("subject.txt", &email.subject),
+            ("html", &email.html),
+            ("txt", &email.text),
+        ] {
+            let path = directory.join(format!("{}.{}", email.template, extension));
+            std::fs::write(&path, content)?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_email_fixtures, write_email_fixtures};
+    use crate::get_configuration;
+
+    #[test]
+    fn every_placeholder_is_replaced() {
+        let configuration = get_configuration().unwrap();
+        for email in render_email_fixtures(&configuration) {
+            for content in [&email.subject, &email.html, &email.text] {
+                assert!(
+                    !content.contains('{'),
+                    "{} has an unreplaced placeholder: {}",
+                    email.template,
+                    content
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fixtures_are_written_to_disk() {
+        let configuration = get_configuration().unwrap();
+        let directory = std::env::temp_dir().join(format!("render-{}", uuid::Uuid::new_v4()));
+
+        let written = write_email_fixtures(&configuration, &directory).unwrap();
+
+        assert_eq!(written.len(), 18);
+        let html = std::fs::read_to_string(directory.join("confirmation.html")).unwrap();
+        assert!(html.contains("fixtureSubscriptionToken0"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
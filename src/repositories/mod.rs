@@ -0,0 +1,3 @@
+//! Queries behind the admin API, kept apart from the routes so that they can
+//! be shared and tested against the database on their own.
+pub mod subscribers;
@@ -0,0 +1,353 @@
+use crate::blob_store::BlobStore;
+use crate::encryption::{DecryptionError, FieldCipher};
+use crate::imports::delete_import_files_listing;
+use crate::subscriber_search::STATUSES;
+use anyhow::Context;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ListingError {
+    #[error("`{0}` is not a subscription status.")]
+    UnknownStatus(String),
+    #[error("The cursor is not one returned by a previous page.")]
+    InvalidCursor,
+}
+
+/// Where a page of the listing starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageStart {
+    /// Skip this many subscribers; simple, but pages shift as subscribers
+    /// sign up.
+    Offset(i64),
+    /// Right after the last subscriber of the previous page.
+    After(Cursor),
+}
+
+/// The position of a subscriber in the listing, handed out as an opaque
+/// string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    subscribed_at: DateTime<Utc>,
+    subscriber_id: Uuid,
+}
+
+impl Cursor {
+    pub fn parse(cursor: &str) -> Result<Self, ListingError> {
+        let decoded = BASE64_URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .ok_or(ListingError::InvalidCursor)?;
+        let (subscribed_at, subscriber_id) =
+            decoded.split_once('/').ok_or(ListingError::InvalidCursor)?;
+        Ok(Self {
+            subscribed_at: DateTime::parse_from_rfc3339(subscribed_at)
+                .map_err(|_| ListingError::InvalidCursor)?
+                .with_timezone(&Utc),
+            subscriber_id: subscriber_id
+                .parse()
+                .map_err(|_| ListingError::InvalidCursor)?,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(format!(
+            "{}/{}",
+            self.subscribed_at
+                .to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.subscriber_id
+        ))
+    }
+}
+
+/// Which subscribers to list. Every criterion given must hold.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriberListing {
+    pub status: Option<String>,
+    /// Part of the email address, matched regardless of case.
+    pub email_contains: Option<String>,
+}
+
+impl SubscriberListing {
+    pub fn validate(&self) -> Result<(), ListingError> {
+        match &self.status {
+            Some(status) if !STATUSES.contains(&status.as_str()) => {
+                Err(ListingError::UnknownStatus(status.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The `ILIKE` pattern matching `email_contains` literally.
+    fn email_pattern(&self) -> Option<String> {
+        self.email_contains.as_ref().map(|part| {
+            let escaped = part
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ListedSubscriber {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+impl ListedSubscriber {
+    /// Where the page following this subscriber starts.
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            subscribed_at: self.subscribed_at,
+            subscriber_id: self.subscriber_id,
+        }
+    }
+}
+
+/// Up to `limit` subscribers matching `listing`, newest first.
+#[tracing::instrument(name = "List subscribers", skip(pg_pool, cipher))]
+pub async fn list_subscribers(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    listing: &SubscriberListing,
+    start: &PageStart,
+    limit: i64,
+) -> Result<Vec<ListedSubscriber>, anyhow::Error> {
+    let (offset, after) = match start {
+        PageStart::Offset(offset) => (*offset, None),
+        PageStart::After(cursor) => (0, Some(cursor)),
+    };
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::TEXT IS NULL OR status = $1)
+            AND ($2::TEXT IS NULL OR email ILIKE $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR (subscribed_at, id) < ($3, $4))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $5 OFFSET $6
+        "#,
+        listing.status,
+        listing.email_pattern(),
+        after.map(|c| c.subscribed_at),
+        after.map(|c| c.subscriber_id),
+        limit,
+        offset,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    let subscribers = rows
+        .into_iter()
+        .map(|r| {
+            Ok(ListedSubscriber {
+                subscriber_id: r.id,
+                email: r.email,
+                name: cipher.decrypt(r.name)?,
+                status: r.status,
+                subscribed_at: r.subscribed_at,
+            })
+        })
+        .collect::<Result<_, DecryptionError>>()?;
+    Ok(subscribers)
+}
+
+/// Subscribers matching `listing`, across every page.
+#[tracing::instrument(name = "Count listed subscribers", skip(pg_pool))]
+pub async fn count_subscribers(
+    pg_pool: &PgPool,
+    listing: &SubscriberListing,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE ($1::TEXT IS NULL OR status = $1)
+            AND ($2::TEXT IS NULL OR email ILIKE $2)
+        "#,
+        listing.status,
+        listing.email_pattern(),
+    )
+    .fetch_one(pg_pool)
+    .await
+}
+
+/// Mark `subscriber_ids` as unsubscribed, drop the deliveries queued for
+/// them and revoke their confirmation links. The time of an earlier
+/// unsubscribe is kept.
+///
+/// Every path unsubscribing people goes through here. Return the ids of
+/// the subscribers that exist.
+#[tracing::instrument(name = "Unsubscribe subscribers", skip(connection))]
+pub async fn unsubscribe_subscribers(
+    connection: &mut PgConnection,
+    subscriber_ids: &[Uuid],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let unsubscribed = sqlx::query_scalar!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed',
+            unsubscribed_at = CASE
+                WHEN status = 'unsubscribed' THEN unsubscribed_at ELSE now()
+            END
+        WHERE id = ANY($1)
+        RETURNING id
+        "#,
+        subscriber_ids,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM delivery_tasks WHERE subscriber_id = ANY($1)",
+        &unsubscribed,
+    )
+    .execute(&mut *connection)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)",
+        &unsubscribed,
+    )
+    .execute(&mut *connection)
+    .await?;
+    Ok(unsubscribed)
+}
+
+/// Hard-delete a subscriber, their tokens and everything cascading from
+/// their row, recording the erasure without anything identifying them.
+///
+/// Their opens and clicks are kept for the statistics of past issues, but
+/// no longer point to them. The uploaded files of imports listing their
+/// address are deleted.
+///
+/// `erased_by` is the admin who asked for it, `None` for the subscriber
+/// themselves. Return whether the subscriber existed.
+#[tracing::instrument(name = "Erase a subscriber", skip(pg_pool, blob_store))]
+pub async fn erase_subscriber(
+    pg_pool: &PgPool,
+    blob_store: &dyn BlobStore,
+    subscriber_id: Uuid,
+    erased_by: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the tokens of a subscriber")?;
+    let Some(email) = sqlx::query_scalar!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete a subscriber")?
+    else {
+        return Ok(false);
+    };
+    sqlx::query!(
+        r#"DELETE FROM confirmation_emails WHERE recipient = lower($1)"#,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the confirmation emails sent to a subscriber")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriber_import_rows WHERE lower(email) = lower($1)"#,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the rows of a subscriber waiting to be imported")?;
+    sqlx::query!(
+        r#"
+        UPDATE email_opens SET subscriber_id = NULL, user_agent = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize the opens of a subscriber")?;
+    sqlx::query!(
+        r#"
+        UPDATE short_link_clicks SET subscriber_id = NULL, user_agent = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize the clicks of a subscriber")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_erasures (subscriber_id, erased_by, erased_at)
+        VALUES ($1, $2, now())
+        "#,
+        subscriber_id,
+        erased_by,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record the erasure of a subscriber")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber")?;
+    delete_import_files_listing(pg_pool, blob_store, &email)
+        .await
+        .context("Failed to delete the import files listing a subscriber")?;
+    tracing::info!(
+        %subscriber_id,
+        erased_by = erased_by.map(tracing::field::display),
+        "Subscriber erased",
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cursor, ListingError, SubscriberListing};
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    #[test]
+    fn cursors_survive_a_round_trip() {
+        let cursor = Cursor {
+            subscribed_at: Utc.timestamp_micros(1_752_000_000_123_456).unwrap(),
+            subscriber_id: Uuid::new_v4(),
+        };
+
+        assert_eq!(Cursor::parse(&cursor.encode()), Ok(cursor));
+    }
+
+    #[test]
+    fn made_up_cursors_are_rejected() {
+        assert_eq!(
+            Cursor::parse("not-a-cursor"),
+            Err(ListingError::InvalidCursor)
+        );
+    }
+
+    #[test]
+    fn wildcards_are_matched_literally() {
+        let listing = SubscriberListing {
+            email_contains: Some("a_b%".into()),
+            ..Default::default()
+        };
+
+        assert_eq!(listing.email_pattern().as_deref(), Some(r"%a\_b\%%"));
+    }
+}
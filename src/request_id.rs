@@ -0,0 +1,59 @@
+use actix_web::HttpMessage;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use tracing_actix_web::RootSpan;
+use uuid::Uuid;
+
+/// Header identifying a request across the services it goes through.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Longer ids are replaced, they would bloat every log line.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled by the current task, if any.
+///
+/// Tasks spawned by a handler, e.g. background jobs, do not inherit it.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// The id sent by the caller, or a new UUID when it is missing or unfit for
+/// the logs.
+fn request_id(request: &ServiceRequest) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty() && id.len() <= MAX_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Take the `X-Request-Id` of the caller, or generate one, as the
+/// `request_id` of the logs and echo it on the response. The
+/// [`EmailClient`](crate::EmailClient) forwards it to the email provider.
+pub async fn propagate_request_id(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = request_id(&request);
+    if let Some(root_span) = request.extensions().get::<RootSpan>() {
+        root_span.record("request_id", request_id.as_str());
+    }
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.call(request))
+        .await?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(response)
+}
@@ -0,0 +1,34 @@
+use crate::authentication::{UserId, get_username};
+use crate::branding::Branding;
+use crate::publishing::escape_html;
+use crate::routes::admin::AdminPageError;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, get, web};
+use sqlx::PgPool;
+
+/// The landing page of the admin panel.
+#[tracing::instrument(
+    name = "Show the admin dashboard",
+    skip(user_id, pg_pool, branding),
+    fields(user_id=%*user_id)
+)]
+#[get("/dashboard")]
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pg_pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, AdminPageError> {
+    let username = get_username(*user_id.into_inner(), &pg_pool).await?;
+    let content = format!(
+        r#"<p>Welcome {}!</p>
+<ul>
+<li><a href="/admin/newsletters">Publish a newsletter issue</a></li>
+<li><a href="/admin/password">Change your password</a></li>
+</ul>
+<form action="/logout" method="post"><button type="submit">Log out</button></form>"#,
+        escape_html(&username)
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(branding.page("Dashboard", &content)))
+}
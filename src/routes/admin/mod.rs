@@ -0,0 +1,49 @@
+//! Pages of the admin panel, served under `/admin` to the admins logged in
+//! through `/login`, anonymous visitors being sent there.
+mod dashboard;
+mod newsletters;
+mod password;
+
+pub use dashboard::admin_dashboard;
+pub use newsletters::publish_newsletter_form;
+pub use password::change_password_form;
+
+use crate::routes::error_chain_fmt;
+use actix_web::ResponseError;
+
+#[derive(thiserror::Error)]
+pub enum AdminPageError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AdminPageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AdminPageError {}
+
+/// Submits the form of a page to its JSON endpoint from the browser, fields
+/// named `a.b` being nested as `{"a": {"b": ...}}`.
+///
+/// The `Idempotency-Key` is taken from the `data-idempotency-key` attribute
+/// of the form, if any, and the outcome shown in its `output` element.
+const SUBMIT_AS_JSON: &str = r#"<script>
+document.querySelector("form").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  const form = event.target;
+  const body = {};
+  for (const [name, value] of new FormData(form)) {
+    const path = name.split(".");
+    let target = body;
+    for (const key of path.slice(0, -1)) target = target[key] ??= {};
+    target[path.at(-1)] = value;
+  }
+  const headers = { "Content-Type": "application/json" };
+  if (form.dataset.idempotencyKey) headers["Idempotency-Key"] = form.dataset.idempotencyKey;
+  const response = await fetch(form.action, { method: "POST", headers, body: JSON.stringify(body) });
+  form.querySelector("output").textContent = response.ok ? form.dataset.success : await response.text();
+});
+</script>"#;
@@ -0,0 +1,30 @@
+use crate::branding::Branding;
+use crate::routes::admin::SUBMIT_AS_JSON;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, get, web};
+use uuid::Uuid;
+
+/// A form publishing an issue through `POST /newsletters`.
+///
+/// Each rendering of the form carries its own idempotency key, so that
+/// submitting it twice publishes the issue once.
+#[tracing::instrument(name = "Show the newsletter form", skip(branding))]
+#[get("/newsletters")]
+pub async fn publish_newsletter_form(branding: web::Data<Branding>) -> HttpResponse {
+    let content = format!(
+        r#"<form action="/newsletters" method="post" data-idempotency-key="{}" data-success="The issue is being delivered.">
+<label>Title <input type="text" name="title" required></label>
+<label>HTML content <textarea name="content.html" rows="20" required></textarea></label>
+<label>Plain text content <textarea name="content.text" rows="20" required></textarea></label>
+<button type="submit">Publish</button>
+<output></output>
+</form>
+<p><a href="/admin/dashboard">Back</a></p>
+{}"#,
+        Uuid::new_v4(),
+        SUBMIT_AS_JSON
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(branding.page("Publish a newsletter issue", &content))
+}
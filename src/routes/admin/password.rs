@@ -0,0 +1,24 @@
+use crate::branding::Branding;
+use crate::routes::admin::SUBMIT_AS_JSON;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, get, web};
+
+/// A form changing the password through `POST /admin/password`.
+#[tracing::instrument(name = "Show the change password form", skip(branding))]
+#[get("/password")]
+pub async fn change_password_form(branding: web::Data<Branding>) -> HttpResponse {
+    let content = format!(
+        r#"<form action="/admin/password" method="post" data-success="Your password has been changed.">
+<label>Current password <input type="password" name="current_password" required></label>
+<label>New password <input type="password" name="new_password" required></label>
+<button type="submit">Change password</button>
+<output></output>
+</form>
+<p><a href="/admin/dashboard">Back</a></p>
+{}"#,
+        SUBMIT_AS_JSON
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(branding.page("Change your password", &content))
+}
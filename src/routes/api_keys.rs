@@ -0,0 +1,132 @@
+use crate::api_keys::{self, ApiKey, validate_key_name};
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, delete, get, post, web};
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum ApiKeyError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no API key with the provided id.")]
+    UnknownKey,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiKeyError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiKeyError::UnknownKey => StatusCode::NOT_FOUND,
+            ApiKeyError::AuthError(e) => e.status_code(),
+            ApiKeyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ApiKeyError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ApiKeyList {
+    api_keys: Vec<ApiKey>,
+}
+
+#[tracing::instrument(
+    name = "List API keys",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/api_keys")]
+pub async fn list_api_keys(
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ApiKeyError> {
+    authenticate(credentials, &pg_pool).await?;
+    let api_keys = api_keys::list_api_keys(&pg_pool)
+        .await
+        .context("Failed to list the API keys")?;
+    Ok(HttpResponse::Ok().json(ApiKeyList { api_keys }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewApiKey {
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+struct GeneratedApiKey {
+    /// Only ever shown here.
+    key: String,
+    #[serde(flatten)]
+    api_key: ApiKey,
+}
+
+/// Generate a key acting on behalf of the admin making the request, for
+/// scripts publishing newsletter issues.
+#[tracing::instrument(
+    name = "Generate an API key",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/api_keys")]
+pub async fn generate_api_key(
+    body: web::Json<NewApiKey>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ApiKeyError> {
+    // A leaked key must not be able to outlive its revocation.
+    if let AdminCredentials::ApiKey(_) = credentials {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "API keys cannot generate API keys"
+        ))
+        .into());
+    }
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    validate_key_name(&body.name).map_err(ApiKeyError::ValidationError)?;
+    let (key, api_key) = api_keys::create_api_key(&pg_pool, user_id, &body.name)
+        .await
+        .context("Failed to store a new API key")?;
+    Ok(HttpResponse::Created().json(GeneratedApiKey {
+        key: key.expose_secret().to_owned(),
+        api_key,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Revoke an API key",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[delete("/admin/api_keys/{api_key_id}")]
+pub async fn revoke_api_key(
+    api_key_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ApiKeyError> {
+    authenticate(credentials, &pg_pool).await?;
+    if !api_keys::revoke_api_key(&pg_pool, *api_key_id)
+        .await
+        .context("Failed to revoke an API key")?
+    {
+        return Err(ApiKeyError::UnknownKey);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
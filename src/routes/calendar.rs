@@ -0,0 +1,56 @@
+use crate::encryption::FieldCipher;
+use crate::publishing::get_issue_event;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum CalendarError {
+    #[error("There is no event associated with the provided issue.")]
+    UnknownEvent,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for CalendarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for CalendarError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            CalendarError::UnknownEvent => StatusCode::NOT_FOUND,
+            CalendarError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The `.ics` file behind the "Add to calendar" links of an issue, for email
+/// clients that do not show the attachment.
+#[tracing::instrument(name = "Download the event of an issue", skip(pg_pool, cipher))]
+#[get("/newsletters/{newsletter_issue_id}/event.ics")]
+pub async fn download_issue_event(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+) -> Result<HttpResponse, CalendarError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let event = get_issue_event(&pg_pool, &cipher, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the event of a newsletter issue")?
+        .ok_or(CalendarError::UnknownEvent)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("event.ics".into())],
+        })
+        .body(event.to_ics(newsletter_issue_id, Utc::now())))
+}
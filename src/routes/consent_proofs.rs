@@ -0,0 +1,118 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::consent::{
+    ConsentProof, PROOF_SIGNATURE_ALGORITHM, ProofSigner, SignedProofBundle, get_consent_proofs,
+};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum ConsentProofError {
+    #[error("There is no subscriber with the provided id.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConsentProofError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConsentProofError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConsentProofError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            ConsentProofError::AuthError(e) => e.status_code(),
+            ConsentProofError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConsentProofError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+/// Public key the proof bundles are signed with, for anyone to check them.
+#[get("/consent_proofs/public_key")]
+pub async fn consent_proof_public_key(proof_signer: web::Data<ProofSigner>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "algorithm": PROOF_SIGNATURE_ALGORITHM,
+        "public_key": proof_signer.public_key(),
+    }))
+}
+
+/// Signed proof of the double opt-in of every subscriber, as a single JSON
+/// file.
+#[tracing::instrument(
+    name = "Export the proofs of consent of every subscriber",
+    skip(pg_pool, proof_signer, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/consent_proofs")]
+pub async fn export_consent_proofs(
+    pg_pool: web::Data<PgPool>,
+    proof_signer: web::Data<ProofSigner>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ConsentProofError> {
+    authenticate(credentials, &pg_pool).await?;
+    let proofs = get_consent_proofs(&pg_pool, None)
+        .await
+        .context("Failed to retrieve the proofs of consent")?;
+    proof_bundle_response(proofs, &proof_signer, "consent-proofs.json")
+}
+
+/// Signed proof of the double opt-in of a single subscriber, e.g. one who
+/// disputes having subscribed.
+#[tracing::instrument(
+    name = "Export the proof of consent of a subscriber",
+    skip(pg_pool, proof_signer, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/subscribers/{subscriber_id}/consent_proof")]
+pub async fn export_subscriber_consent_proof(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    proof_signer: web::Data<ProofSigner>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ConsentProofError> {
+    authenticate(credentials, &pg_pool).await?;
+    let subscriber_id = subscriber_id.into_inner();
+    let proofs = get_consent_proofs(&pg_pool, Some(subscriber_id))
+        .await
+        .context("Failed to retrieve the proof of consent of a subscriber")?;
+    if proofs.is_empty() {
+        return Err(ConsentProofError::UnknownSubscriber);
+    }
+    proof_bundle_response(
+        proofs,
+        &proof_signer,
+        &format!("consent-proof-{}.json", subscriber_id),
+    )
+}
+
+fn proof_bundle_response(
+    proofs: Vec<ConsentProof>,
+    proof_signer: &ProofSigner,
+    filename: &str,
+) -> Result<HttpResponse, ConsentProofError> {
+    let bundle = SignedProofBundle::sign(proofs, Utc::now(), proof_signer)
+        .context("Failed to serialize the proofs of consent")?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename.into())],
+        })
+        .json(bundle))
+}
@@ -0,0 +1,339 @@
+use crate::EmailClient;
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::complaints::ComplaintAlert;
+use crate::delivery::count_queued_emails;
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::routes::error_chain_fmt;
+use actix_web::{HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const DEFAULT_WINDOW_DAYS: u32 = 30;
+
+#[derive(thiserror::Error)]
+pub enum DeliverabilityError {
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DeliverabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DeliverabilityError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DeliverabilityError::AuthError(e) => e.error_response(),
+            DeliverabilityError::UnexpectedError(_) => HttpResponse::InternalServerError().finish(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeliverabilityParameters {
+    /// Only deliveries from the last `days` days are taken into account.
+    days: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliverabilityStats {
+    deliveries: i64,
+    bounces: i64,
+    bounce_rate: f64,
+    complaints: i64,
+    complaint_rate: f64,
+    avg_latency_seconds: f64,
+    max_latency_seconds: f64,
+}
+
+impl DeliverabilityStats {
+    fn new(
+        deliveries: i64,
+        bounces: i64,
+        complaints: i64,
+        avg_latency_seconds: f64,
+        max_latency_seconds: f64,
+    ) -> Self {
+        let rate = |count: i64| {
+            if deliveries == 0 {
+                0.0
+            } else {
+                count as f64 / deliveries as f64
+            }
+        };
+        Self {
+            deliveries,
+            bounces,
+            bounce_rate: rate(bounces),
+            complaints,
+            complaint_rate: rate(complaints),
+            avg_latency_seconds,
+            max_latency_seconds,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct IssueDeliverability {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    #[serde(flatten)]
+    stats: DeliverabilityStats,
+}
+
+#[derive(serde::Serialize)]
+struct DomainDeliverability {
+    domain: String,
+    #[serde(flatten)]
+    stats: DeliverabilityStats,
+}
+
+#[derive(serde::Serialize)]
+struct Deliverability {
+    /// Set while deliveries are paused, as the numbers stop moving then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    banner: Option<String>,
+    issues: Vec<IssueDeliverability>,
+    domains: Vec<DomainDeliverability>,
+    /// Issues whose complaint rate crossed `complaints.alert_rate`.
+    complaint_alerts: Vec<ComplaintAlert>,
+    /// Daily Google Postmaster Tools statistics of the sending domains.
+    reputation: Vec<ReputationDay>,
+    /// DMARC aggregate reports of the sending domains, per day.
+    dmarc: Vec<DmarcDay>,
+}
+
+#[derive(serde::Serialize)]
+struct ReputationDay {
+    domain: String,
+    day: NaiveDate,
+    reputation: Option<String>,
+    user_reported_spam_ratio: Option<f64>,
+    spf_success_ratio: Option<f64>,
+    dkim_success_ratio: Option<f64>,
+    dmarc_success_ratio: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct DmarcDay {
+    domain: String,
+    day: NaiveDate,
+    messages: i64,
+    pass_rate: f64,
+    quarantined: i64,
+    rejected: i64,
+}
+
+/// Bounce rate, complaint rate and delivery latency (time between an issue
+/// being published and the email being handed to the provider), broken down
+/// per issue and per recipient domain, along with the reputation of the
+/// sending domains.
+#[tracing::instrument(
+    name = "Get deliverability dashboard",
+    skip(parameters, pg_pool, feature_flags, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/deliverability")]
+pub async fn get_deliverability(
+    parameters: web::Query<DeliverabilityParameters>,
+    pg_pool: web::Data<PgPool>,
+    feature_flags: web::Data<FeatureFlags>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DeliverabilityError> {
+    authenticate(credentials, &pg_pool).await?;
+    let since =
+        Utc::now() - chrono::Duration::days(parameters.days.unwrap_or(DEFAULT_WINDOW_DAYS).into());
+
+    let issues = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            COUNT(*) AS "deliveries!",
+            COUNT(*) FILTER (WHERE o.bounced) AS "bounces!",
+            COUNT(*) FILTER (WHERE o.complained) AS "complaints!",
+            AVG(o.latency_seconds) AS "avg_latency_seconds!",
+            MAX(o.latency_seconds) AS "max_latency_seconds!"
+        FROM delivery_outcomes o
+        JOIN newsletter_issues i ON i.newsletter_issue_id = o.newsletter_issue_id
+        WHERE o.delivered_at >= $1
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#,
+        since,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to compute deliverability per issue")?
+    .into_iter()
+    .map(|r| IssueDeliverability {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        published_at: r.published_at,
+        stats: DeliverabilityStats::new(
+            r.deliveries,
+            r.bounces,
+            r.complaints,
+            r.avg_latency_seconds,
+            r.max_latency_seconds,
+        ),
+    })
+    .collect();
+
+    let domains = sqlx::query!(
+        r#"
+        SELECT
+            recipient_domain AS "domain!",
+            COUNT(*) AS "deliveries!",
+            COUNT(*) FILTER (WHERE bounced) AS "bounces!",
+            COUNT(*) FILTER (WHERE complained) AS "complaints!",
+            AVG(latency_seconds) AS "avg_latency_seconds!",
+            MAX(latency_seconds) AS "max_latency_seconds!"
+        FROM delivery_outcomes
+        WHERE delivered_at >= $1
+        GROUP BY recipient_domain
+        ORDER BY COUNT(*) DESC, recipient_domain
+        "#,
+        since,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to compute deliverability per recipient domain")?
+    .into_iter()
+    .map(|r| DomainDeliverability {
+        domain: r.domain,
+        stats: DeliverabilityStats::new(
+            r.deliveries,
+            r.bounces,
+            r.complaints,
+            r.avg_latency_seconds,
+            r.max_latency_seconds,
+        ),
+    })
+    .collect();
+
+    let complaint_alerts = sqlx::query!(
+        r#"
+        SELECT a.newsletter_issue_id, i.title, a.deliveries, a.complaints, a.raised_at
+        FROM complaint_alerts a
+        JOIN newsletter_issues i ON i.newsletter_issue_id = a.newsletter_issue_id
+        WHERE a.raised_at >= $1
+        ORDER BY a.raised_at DESC
+        "#,
+        since,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to retrieve the complaint alerts")?
+    .into_iter()
+    .map(|r| ComplaintAlert {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        deliveries: r.deliveries,
+        complaints: r.complaints,
+        complaint_rate: r.complaints as f64 / r.deliveries as f64,
+        raised_at: r.raised_at,
+    })
+    .collect();
+
+    let reputation = sqlx::query_as!(
+        ReputationDay,
+        r#"
+        SELECT
+            domain, day, reputation, user_reported_spam_ratio,
+            spf_success_ratio, dkim_success_ratio, dmarc_success_ratio
+        FROM domain_reputation
+        WHERE day >= $1::timestamptz::date
+        ORDER BY domain, day
+        "#,
+        since,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to retrieve the domain reputation")?;
+
+    let dmarc = sqlx::query!(
+        r#"
+        SELECT
+            domain,
+            (begins_at AT TIME ZONE 'UTC')::date AS "day!",
+            SUM(messages)::int8 AS "messages!",
+            SUM(passed)::int8 AS "passed!",
+            SUM(quarantined)::int8 AS "quarantined!",
+            SUM(rejected)::int8 AS "rejected!"
+        FROM dmarc_reports
+        WHERE begins_at >= $1
+        GROUP BY 1, 2
+        ORDER BY 1, 2
+        "#,
+        since,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to aggregate the DMARC reports")?
+    .into_iter()
+    .map(|r| DmarcDay {
+        domain: r.domain,
+        day: r.day,
+        messages: r.messages,
+        pass_rate: if r.messages == 0 {
+            0.0
+        } else {
+            r.passed as f64 / r.messages as f64
+        },
+        quarantined: r.quarantined,
+        rejected: r.rejected,
+    })
+    .collect();
+
+    let flags = feature_flags
+        .load(&pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    let banner = if flags.is_enabled(PAUSE_DELIVERIES) {
+        let queued = count_queued_emails(&pg_pool)
+            .await
+            .context("Failed to count the queued emails")?;
+        Some(format!(
+            "Deliveries are paused: {} issue deliveries and {} confirmation emails are queued \
+            until they are resumed through /admin/delivery.",
+            queued.deliveries, queued.confirmation_emails
+        ))
+    } else {
+        None
+    };
+
+    Ok(HttpResponse::Ok().json(Deliverability {
+        banner,
+        issues,
+        domains,
+        complaint_alerts,
+        reputation,
+        dmarc,
+    }))
+}
+
+/// Traffic and failures per email provider endpoint since the application
+/// started.
+#[tracing::instrument(
+    name = "Get email endpoint stats",
+    skip(pg_pool, email_client, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/deliverability/endpoints")]
+pub async fn get_email_endpoint_stats(
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DeliverabilityError> {
+    authenticate(credentials, &pg_pool).await?;
+    Ok(HttpResponse::Ok().json(email_client.endpoint_stats()))
+}
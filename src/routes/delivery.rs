@@ -0,0 +1,121 @@
+use crate::EmailClient;
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::configuration::FeatureFlagSettings;
+use crate::delivery::{DELIVERY_JOB, QueuedEmails, count_queued_emails};
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES, store_override};
+use crate::jobs::Jobs;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum DeliveryError {
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DeliveryError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DeliveryError::AuthError(e) => e.status_code(),
+            DeliveryError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DeliveryError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PauseRequest {
+    paused: bool,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryStatus {
+    paused: bool,
+    queued: QueuedEmails,
+}
+
+async fn delivery_status(
+    pg_pool: &PgPool,
+    feature_flags: &FeatureFlags,
+) -> Result<DeliveryStatus, anyhow::Error> {
+    let flags = feature_flags
+        .load(pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    let queued = count_queued_emails(pg_pool)
+        .await
+        .context("Failed to count the queued emails")?;
+    Ok(DeliveryStatus {
+        paused: flags.is_enabled(PAUSE_DELIVERIES),
+        queued,
+    })
+}
+
+/// Whether outgoing emails are paused, and how many are queued.
+#[tracing::instrument(
+    name = "Get delivery status",
+    skip(pg_pool, feature_flags, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/delivery")]
+pub async fn get_delivery_status(
+    pg_pool: web::Data<PgPool>,
+    feature_flags: web::Data<FeatureFlags>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DeliveryError> {
+    authenticate(credentials, &pg_pool).await?;
+    Ok(HttpResponse::Ok().json(delivery_status(&pg_pool, &feature_flags).await?))
+}
+
+/// Pause every outgoing email on every instance, e.g. during an incident at
+/// the email provider, or resume them. Emails queued meanwhile start going
+/// out from this instance straight away, the other instances follow within
+/// [`PAUSE_CHECK_INTERVAL`](crate::email_client::PAUSE_CHECK_INTERVAL).
+#[tracing::instrument(
+    name = "Pause or resume deliveries",
+    skip(body, pg_pool, feature_flags, email_client, jobs, credentials),
+    fields(user_id=tracing::field::Empty, paused=body.paused)
+)]
+#[post("/admin/delivery")]
+pub async fn set_delivery_paused(
+    body: web::Json<PauseRequest>,
+    pg_pool: web::Data<PgPool>,
+    feature_flags: web::Data<FeatureFlags>,
+    email_client: web::Data<EmailClient>,
+    jobs: web::Data<Jobs>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DeliveryError> {
+    authenticate(credentials, &pg_pool).await?;
+    let settings = FeatureFlagSettings {
+        enabled: body.paused,
+        rollout_percentage: None,
+    };
+    store_override(&pg_pool, PAUSE_DELIVERIES, &settings)
+        .await
+        .context("Failed to store the delivery pause")?;
+    email_client.forget_pause_state().await;
+    tracing::warn!(paused = body.paused, "Deliveries paused or resumed");
+    if !body.paused
+        && let Some(job) = jobs.get(DELIVERY_JOB)
+    {
+        job.trigger();
+    }
+    Ok(HttpResponse::Ok().json(delivery_status(&pg_pool, &feature_flags).await?))
+}
@@ -0,0 +1,97 @@
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, post, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolCopyExt;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const DEFAULT_COUNT: u32 = 1_000;
+const MAX_COUNT: u32 = 1_000_000;
+/// Rows buffered before being streamed to Postgres.
+const CHUNK_SIZE: u32 = 10_000;
+
+#[derive(thiserror::Error)]
+pub enum FixturesError {
+    #[error("At most {MAX_COUNT} subscribers can be generated at once.")]
+    TooMany,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for FixturesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for FixturesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            FixturesError::TooMany => StatusCode::BAD_REQUEST,
+            FixturesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct FixturesParameters {
+    count: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct FixturesCreated {
+    inserted: u64,
+}
+
+/// Bulk-insert confirmed subscribers with `COPY`, to benchmark deliveries at
+/// a realistic scale. Only registered when `application.enable_dev_routes`
+/// is set, i.e. locally.
+#[tracing::instrument(name = "Generate subscriber fixtures", skip(pg_pool))]
+#[post("/dev/fixtures/subscribers")]
+pub async fn generate_subscriber_fixtures(
+    parameters: web::Query<FixturesParameters>,
+    pg_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, FixturesError> {
+    let count = parameters.count.unwrap_or(DEFAULT_COUNT);
+    if count > MAX_COUNT {
+        return Err(FixturesError::TooMany);
+    }
+
+    let mut copy = pg_pool
+        .copy_in_raw(
+            "COPY subscriptions (id, email, name, subscribed_at, status) \
+             FROM STDIN WITH (FORMAT csv)",
+        )
+        .await
+        .context("Failed to start copying subscribers")?;
+    let subscribed_at = Utc::now().to_rfc3339();
+    let mut chunk = String::new();
+    for i in 0..count {
+        let id = Uuid::new_v4();
+        writeln!(
+            chunk,
+            "{id},loadtest+{}@example.com,Load Test {i},{subscribed_at},confirmed",
+            id.simple()
+        )
+        .expect("Writing to a String cannot fail");
+        if (i + 1) % CHUNK_SIZE == 0 {
+            copy.send(std::mem::take(&mut chunk).into_bytes())
+                .await
+                .context("Failed to copy subscribers")?;
+        }
+    }
+    if !chunk.is_empty() {
+        copy.send(chunk.into_bytes())
+            .await
+            .context("Failed to copy subscribers")?;
+    }
+    let inserted = copy
+        .finish()
+        .await
+        .context("Failed to finish copying subscribers")?;
+
+    Ok(HttpResponse::Ok().json(FixturesCreated { inserted }))
+}
@@ -0,0 +1,311 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::encryption::FieldCipher;
+use crate::publishing::{IssueContent, get_draft};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum DraftCommentError {
+    #[error("There is no draft associated with the provided id.")]
+    UnknownDraft,
+    #[error("There is no comment associated with the provided id.")]
+    UnknownComment,
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The comment was already resolved.")]
+    AlreadyResolved,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DraftCommentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DraftCommentError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DraftCommentError::UnknownDraft | DraftCommentError::UnknownComment => {
+                StatusCode::NOT_FOUND
+            }
+            DraftCommentError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            DraftCommentError::AlreadyResolved => StatusCode::CONFLICT,
+            DraftCommentError::AuthError(e) => e.status_code(),
+            DraftCommentError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DraftCommentError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+/// The part of a draft a comment refers to.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnchorField {
+    Title,
+    Html,
+    Text,
+}
+
+impl AnchorField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnchorField::Title => "title",
+            AnchorField::Html => "html",
+            AnchorField::Text => "text",
+        }
+    }
+
+    fn of<'a>(&self, content: &'a IssueContent) -> &'a str {
+        match self {
+            AnchorField::Title => &content.title,
+            AnchorField::Html => &content.html,
+            AnchorField::Text => &content.text,
+        }
+    }
+}
+
+impl TryFrom<String> for AnchorField {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "title" => Ok(AnchorField::Title),
+            "html" => Ok(AnchorField::Html),
+            "text" => Ok(AnchorField::Text),
+            other => Err(format!("{} is not a valid anchor field", other)),
+        }
+    }
+}
+
+/// The commented range, as character offsets into a field of the draft.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+pub struct Anchor {
+    field: AnchorField,
+    start: u32,
+    end: u32,
+}
+
+impl Anchor {
+    fn validate(&self, content: &IssueContent) -> Result<(), String> {
+        let length = self.field.of(content).chars().count();
+        if self.start > self.end || self.end as usize > length {
+            return Err(format!(
+                "The anchor must be a range within the {} characters of the draft {}.",
+                length,
+                self.field.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CommentBody {
+    body: String,
+    anchor: Anchor,
+}
+
+#[derive(serde::Serialize)]
+struct CommentCreated {
+    comment_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Comment a newsletter draft",
+    skip(body, pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts/{newsletter_draft_id}/comments")]
+async fn create_draft_comment(
+    newsletter_draft_id: web::Path<Uuid>,
+    body: web::Json<CommentBody>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DraftCommentError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let newsletter_draft_id = newsletter_draft_id.into_inner();
+    if body.body.trim().is_empty() {
+        return Err(DraftCommentError::ValidationError(
+            "The comment cannot be empty.".into(),
+        ));
+    }
+    let draft = get_draft(&pg_pool, &cipher, newsletter_draft_id)
+        .await
+        .context("Failed to retrieve the newsletter draft")?
+        .ok_or(DraftCommentError::UnknownDraft)?;
+    body.anchor
+        .validate(&draft.content)
+        .map_err(DraftCommentError::ValidationError)?;
+
+    let comment_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO draft_comments (
+            comment_id, newsletter_draft_id, author_id, body,
+            anchor_field, anchor_start, anchor_end, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        "#,
+        comment_id,
+        newsletter_draft_id,
+        user_id,
+        body.body,
+        body.anchor.field.as_str(),
+        body.anchor.start as i32,
+        body.anchor.end as i32,
+    )
+    .execute(pg_pool.get_ref())
+    .await
+    .context("Failed to store the draft comment")?;
+    Ok(HttpResponse::Ok().json(CommentCreated { comment_id }))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ListCommentsParameters {
+    #[serde(default)]
+    include_resolved: bool,
+}
+
+#[derive(serde::Serialize)]
+struct DraftComment {
+    comment_id: Uuid,
+    author: String,
+    body: String,
+    anchor: Anchor,
+    created_at: DateTime<Utc>,
+    resolved_at: Option<DateTime<Utc>>,
+    resolved_by: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct DraftCommentList {
+    comments: Vec<DraftComment>,
+}
+
+/// Comments of a draft in the order they appear in it, unresolved ones only
+/// unless `include_resolved` is set.
+#[tracing::instrument(
+    name = "List the comments of a newsletter draft",
+    skip(pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/drafts/{newsletter_draft_id}/comments")]
+async fn list_draft_comments(
+    newsletter_draft_id: web::Path<Uuid>,
+    parameters: web::Query<ListCommentsParameters>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DraftCommentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let newsletter_draft_id = newsletter_draft_id.into_inner();
+    get_draft(&pg_pool, &cipher, newsletter_draft_id)
+        .await
+        .context("Failed to retrieve the newsletter draft")?
+        .ok_or(DraftCommentError::UnknownDraft)?;
+
+    let comments = sqlx::query!(
+        r#"
+        SELECT
+            c.comment_id, a.username AS author, c.body,
+            c.anchor_field, c.anchor_start, c.anchor_end,
+            c.created_at, c.resolved_at, r.username AS "resolved_by?"
+        FROM draft_comments c
+        JOIN users a ON a.user_id = c.author_id
+        LEFT JOIN users r ON r.user_id = c.resolved_by
+        WHERE c.newsletter_draft_id = $1
+            AND ($2 OR c.resolved_at IS NULL)
+        ORDER BY c.anchor_field, c.anchor_start, c.created_at
+        "#,
+        newsletter_draft_id,
+        parameters.include_resolved,
+    )
+    .fetch_all(pg_pool.get_ref())
+    .await
+    .context("Failed to retrieve the draft comments")?
+    .into_iter()
+    .map(|r| {
+        Ok(DraftComment {
+            comment_id: r.comment_id,
+            author: r.author,
+            body: r.body,
+            anchor: Anchor {
+                field: AnchorField::try_from(r.anchor_field).map_err(|e| anyhow::anyhow!(e))?,
+                start: r.anchor_start.try_into()?,
+                end: r.anchor_end.try_into()?,
+            },
+            created_at: r.created_at,
+            resolved_at: r.resolved_at,
+            resolved_by: r.resolved_by,
+        })
+    })
+    .collect::<Result<Vec<_>, anyhow::Error>>()
+    .context("Stored draft comments are invalid")?;
+    Ok(HttpResponse::Ok().json(DraftCommentList { comments }))
+}
+
+#[tracing::instrument(
+    name = "Resolve a draft comment",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts/{newsletter_draft_id}/comments/{comment_id}/resolve")]
+async fn resolve_draft_comment(
+    path: web::Path<(Uuid, Uuid)>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DraftCommentError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let (newsletter_draft_id, comment_id) = path.into_inner();
+    let resolved = sqlx::query!(
+        r#"
+        UPDATE draft_comments
+        SET resolved_at = now(), resolved_by = $3
+        WHERE comment_id = $1 AND newsletter_draft_id = $2 AND resolved_at IS NULL
+        "#,
+        comment_id,
+        newsletter_draft_id,
+        user_id,
+    )
+    .execute(pg_pool.get_ref())
+    .await
+    .context("Failed to resolve the draft comment")?
+    .rows_affected();
+    if resolved == 0 {
+        let exists = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM draft_comments
+                WHERE comment_id = $1 AND newsletter_draft_id = $2
+            ) AS "exists!"
+            "#,
+            comment_id,
+            newsletter_draft_id,
+        )
+        .fetch_one(pg_pool.get_ref())
+        .await
+        .context("Failed to retrieve the draft comment")?
+        .exists;
+        return Err(if exists {
+            DraftCommentError::AlreadyResolved
+        } else {
+            DraftCommentError::UnknownComment
+        });
+    }
+    Ok(HttpResponse::Ok().finish())
+}
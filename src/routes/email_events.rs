@@ -0,0 +1,198 @@
+use crate::complaints::{ComplaintAlerts, unsubscribe_complainer};
+use crate::routes::error_chain_fmt;
+use crate::startup::EmailWebhookToken;
+use actix_web::http::StatusCode;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, post, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::{PgConnection, PgPool};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum EmailEventsError {
+    #[error("The webhook token was missing or invalid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailEventsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EmailEventsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EmailEventsError::InvalidToken => StatusCode::UNAUTHORIZED,
+            EmailEventsError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct WebhookPayload {
+    events: Vec<WebhookEvent>,
+}
+
+/// An event as reported by the email provider. Only the fields we act upon
+/// are deserialized.
+#[derive(serde::Deserialize, Debug)]
+struct WebhookEvent {
+    event: String,
+    email: String,
+    message_id: Option<String>,
+    event_id: Option<String>,
+    /// Unix timestamp, in seconds.
+    timestamp: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DeliveryEventType {
+    HardBounce,
+    SoftBounce,
+    Complaint,
+}
+
+impl DeliveryEventType {
+    fn parse(event: &str) -> Option<Self> {
+        match event {
+            "bounce" => Some(Self::HardBounce),
+            "soft bounce" => Some(Self::SoftBounce),
+            "spam" => Some(Self::Complaint),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryEventType::HardBounce => "hard_bounce",
+            DeliveryEventType::SoftBounce => "soft_bounce",
+            DeliveryEventType::Complaint => "complaint",
+        }
+    }
+}
+
+/// Receive bounce and spam complaint notifications from the email provider.
+///
+/// Events that cannot be matched to a newsletter delivery (e.g. bounces of
+/// confirmation emails) are acknowledged and dropped, the provider would
+/// otherwise keep retrying them. Subscribers complaining about an issue are
+/// unsubscribed and suppressed straight away.
+#[tracing::instrument(name = "Receive email provider events", skip_all)]
+#[post("/webhooks/email_events")]
+pub async fn receive_email_events(
+    request: HttpRequest,
+    payload: web::Json<WebhookPayload>,
+    pg_pool: web::Data<PgPool>,
+    webhook_token: web::Data<EmailWebhookToken>,
+    complaint_alerts: web::Data<ComplaintAlerts>,
+) -> Result<HttpResponse, EmailEventsError> {
+    let expected = format!("Bearer {}", webhook_token.0.expose_secret());
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| bool::from(h.as_bytes().ct_eq(expected.as_bytes())));
+    if !authorized {
+        return Err(EmailEventsError::InvalidToken);
+    }
+
+    for event in &payload.events {
+        let Some(event_type) = DeliveryEventType::parse(&event.event) else {
+            continue;
+        };
+        let occurred_at = event
+            .timestamp
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .unwrap_or_else(Utc::now);
+        let mut transaction = pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let recorded = record_delivery_event(&mut transaction, event, event_type, occurred_at)
+            .await
+            .context("Failed to record a delivery event")?;
+        let Some(delivery) = recorded else {
+            tracing::info!(
+                event = event.event,
+                "Dropping an email provider event that matches no newsletter delivery"
+            );
+            continue;
+        };
+        if let DeliveryEventType::Complaint = event_type {
+            unsubscribe_complainer(
+                &mut transaction,
+                delivery.subscriber_id,
+                delivery.newsletter_issue_id,
+            )
+            .await
+            .context("Failed to unsubscribe a complaining subscriber")?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to record a delivery event")?;
+        if let DeliveryEventType::Complaint = event_type {
+            complaint_alerts
+                .check(&pg_pool, delivery.newsletter_issue_id)
+                .await
+                .context("Failed to check the complaint rate of an issue")?;
+        }
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The newsletter delivery an event was attached to.
+struct EventDelivery {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+}
+
+/// Attach the event to the delivery carrying the provider message id it
+/// refers to. Events without a message id fall back to the latest delivery
+/// to that address that happened before the event itself.
+///
+/// Returns `None` if no delivery matches, or if the event was already
+/// recorded.
+#[tracing::instrument(name = "Record delivery event", skip(transaction))]
+async fn record_delivery_event(
+    transaction: &mut PgConnection,
+    event: &WebhookEvent,
+    event_type: DeliveryEventType,
+    occurred_at: DateTime<Utc>,
+) -> Result<Option<EventDelivery>, sqlx::Error> {
+    let delivery = sqlx::query_as!(
+        EventDelivery,
+        r#"
+        INSERT INTO delivery_events (
+            delivery_event_id, newsletter_issue_id, subscriber_id,
+            event_type, provider_event_id, occurred_at, received_at
+        )
+        SELECT $1, d.newsletter_issue_id, d.subscriber_id, $2, $3, $4, now()
+        FROM newsletter_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE CASE
+            WHEN $6::text IS NOT NULL THEN d.provider_message_id = $6
+            ELSE lower(s.email) = lower($5) AND d.delivered_at <= $4
+        END
+        ORDER BY d.delivered_at DESC
+        LIMIT 1
+        ON CONFLICT (provider_event_id) DO NOTHING
+        RETURNING newsletter_issue_id, subscriber_id
+        "#,
+        Uuid::new_v4(),
+        event_type.as_str(),
+        event.event_id,
+        occurred_at,
+        event.email,
+        event.message_id,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(delivery)
+}
@@ -0,0 +1,138 @@
+use crate::EmailClient;
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::configuration::FeatureFlagSettings;
+use crate::feature_flags::{FeatureFlags, Flag, store_override};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, delete, get, put, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum FeatureFlagError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no runtime value for the provided feature flag.")]
+    UnknownFlag,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for FeatureFlagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for FeatureFlagError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            FeatureFlagError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            FeatureFlagError::UnknownFlag => StatusCode::NOT_FOUND,
+            FeatureFlagError::AuthError(e) => e.status_code(),
+            FeatureFlagError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            FeatureFlagError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FlagList<'a> {
+    flags: Vec<&'a Flag>,
+}
+
+/// Every known flag with its current value, and whether that value was set
+/// at runtime or comes from the configuration.
+#[tracing::instrument(
+    name = "List feature flags",
+    skip(pg_pool, feature_flags, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/feature_flags")]
+pub async fn list_feature_flags(
+    pg_pool: web::Data<PgPool>,
+    feature_flags: web::Data<FeatureFlags>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, FeatureFlagError> {
+    authenticate(credentials, &pg_pool).await?;
+    let flags = feature_flags
+        .load(&pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    Ok(HttpResponse::Ok().json(FlagList { flags: flags.all() }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FlagValue {
+    enabled: bool,
+    rollout_percentage: Option<u8>,
+}
+
+/// Override the configured value of a flag, on every instance.
+#[tracing::instrument(
+    name = "Set a feature flag",
+    skip(body, pg_pool, email_client, credentials),
+    fields(
+        user_id=tracing::field::Empty,
+        enabled=body.enabled,
+        rollout_percentage=body.rollout_percentage
+    )
+)]
+#[put("/admin/feature_flags/{name}")]
+pub async fn set_feature_flag(
+    name: web::Path<String>,
+    body: web::Json<FlagValue>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, FeatureFlagError> {
+    authenticate(credentials, &pg_pool).await?;
+    if body.rollout_percentage.is_some_and(|p| p > 100) {
+        return Err(FeatureFlagError::ValidationError(
+            "The rollout percentage cannot be over 100.".into(),
+        ));
+    }
+    let settings = FeatureFlagSettings {
+        enabled: body.enabled,
+        rollout_percentage: body.rollout_percentage,
+    };
+    store_override(&pg_pool, &name, &settings)
+        .await
+        .context("Failed to store a feature flag")?;
+    email_client.forget_pause_state().await;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Drop the runtime value of a flag, going back to the configured one.
+#[tracing::instrument(
+    name = "Reset a feature flag",
+    skip(pg_pool, email_client, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[delete("/admin/feature_flags/{name}")]
+pub async fn reset_feature_flag(
+    name: web::Path<String>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, FeatureFlagError> {
+    authenticate(credentials, &pg_pool).await?;
+    let deleted = sqlx::query!("DELETE FROM feature_flags WHERE name = $1", name.as_str())
+        .execute(pg_pool.as_ref())
+        .await
+        .context("Failed to delete a feature flag")?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(FeatureFlagError::UnknownFlag);
+    }
+    email_client.forget_pause_state().await;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,160 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::blob_store::BlobStore;
+use crate::domain::SubscriberRules;
+use crate::encryption::FieldCipher;
+use crate::imports::{IMPORT_JOB, ParsedImport, create_import, get_import, import_file_key};
+use crate::jobs::Jobs;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use actix_web::web::BytesMut;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Largest file accepted, about 100,000 subscribers.
+const MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error("Files cannot be larger than {} MiB.", MAX_FILE_SIZE / 1024 / 1024)]
+    FileTooLarge,
+    #[error("There is no import associated with the provided id.")]
+    UnknownImport,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ImportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ImportError::InvalidFile(_) => StatusCode::BAD_REQUEST,
+            ImportError::FileTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ImportError::UnknownImport => StatusCode::NOT_FOUND,
+            ImportError::AuthError(e) => e.status_code(),
+            ImportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ImportError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct QueuedImport {
+    import_id: Uuid,
+    total_rows: usize,
+    /// Rows that failed validation, listed with the progress of the import.
+    rejected: usize,
+}
+
+/// Import subscribers from a CSV file sent as the body, in the background.
+///
+/// The file is validated straight away and kept in the storage, its valid
+/// rows being imported as confirmed subscribers by the `imports` job. Follow
+/// along with `GET /admin/imports/{import_id}`.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(payload, pg_pool, cipher, blob_store, jobs, subscriber_rules, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/imports")]
+pub async fn import_subscribers(
+    mut payload: web::Payload,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    blob_store: web::Data<dyn BlobStore>,
+    jobs: web::Data<Jobs>,
+    subscriber_rules: web::Data<SubscriberRules>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ImportError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let mut file = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.context("Failed to read the uploaded file")?;
+        if file.len() + chunk.len() > MAX_FILE_SIZE {
+            return Err(ImportError::FileTooLarge);
+        }
+        file.extend_from_slice(&chunk);
+    }
+    let import = std::str::from_utf8(&file)
+        .map_err(|_| ImportError::InvalidFile("The file must be encoded as UTF-8.".into()))
+        .and_then(|file| {
+            ParsedImport::parse(file, &subscriber_rules).map_err(ImportError::InvalidFile)
+        })?;
+    let import_id = Uuid::new_v4();
+    blob_store
+        .put(&import_file_key(import_id), file.to_vec())
+        .await
+        .context("Failed to store the uploaded file")?;
+    create_import(&pg_pool, &cipher, import_id, user_id, &import).await?;
+    if let Some(job) = jobs.get(IMPORT_JOB) {
+        job.trigger();
+    }
+    Ok(HttpResponse::Accepted().json(QueuedImport {
+        import_id,
+        total_rows: import.total_rows(),
+        rejected: import.rejected.len(),
+    }))
+}
+
+/// How far an import got, with the rows that could not be imported.
+#[tracing::instrument(
+    name = "Get an import",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/imports/{import_id}")]
+pub async fn get_subscriber_import(
+    import_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ImportError> {
+    authenticate(credentials, &pg_pool).await?;
+    let import = get_import(&pg_pool, import_id.into_inner())
+        .await
+        .context("Failed to retrieve an import")?
+        .ok_or(ImportError::UnknownImport)?;
+    Ok(HttpResponse::Ok().json(import))
+}
+
+/// The file an import was uploaded as, kept for 30 days once the import
+/// completes.
+#[tracing::instrument(
+    name = "Download the file of an import",
+    skip(pg_pool, blob_store, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/imports/{import_id}/file")]
+pub async fn download_import_file(
+    import_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ImportError> {
+    authenticate(credentials, &pg_pool).await?;
+    let file = blob_store
+        .get(&import_file_key(*import_id))
+        .await
+        .context("Failed to retrieve the file of an import")?
+        .ok_or(ImportError::UnknownImport)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(actix_web::mime::TEXT_CSV_UTF_8))
+        .body(file))
+}
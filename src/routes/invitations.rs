@@ -0,0 +1,146 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::invitations::{self, InvitationBatch, MAX_BATCH_SIZE, MAX_USES};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, delete, get, post, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum InvitationError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no invitation batch with the provided id.")]
+    UnknownBatch,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for InvitationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for InvitationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            InvitationError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            InvitationError::UnknownBatch => StatusCode::NOT_FOUND,
+            InvitationError::AuthError(e) => e.status_code(),
+            InvitationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            InvitationError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct InvitationBatchList {
+    batches: Vec<InvitationBatch>,
+}
+
+#[tracing::instrument(
+    name = "List invitation batches",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/invitations")]
+pub async fn list_invitation_batches(
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, InvitationError> {
+    authenticate(credentials, &pg_pool).await?;
+    let batches = invitations::list_invitation_batches(&pg_pool)
+        .await
+        .context("Failed to list the invitation batches")?;
+    Ok(HttpResponse::Ok().json(InvitationBatchList { batches }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewInvitationBatch {
+    count: u32,
+    /// Single-use codes unless set.
+    #[serde(default = "single_use")]
+    max_uses: u32,
+}
+
+fn single_use() -> u32 {
+    1
+}
+
+#[derive(serde::Serialize)]
+struct GeneratedInvitationBatch {
+    batch_id: Uuid,
+    max_uses: u32,
+    /// Only ever shown here.
+    codes: Vec<String>,
+}
+
+/// Generate a batch of invite codes, to hand out to the people allowed to
+/// subscribe.
+#[tracing::instrument(
+    name = "Generate a batch of invitations",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/invitations")]
+pub async fn generate_invitation_batch(
+    body: web::Json<NewInvitationBatch>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, InvitationError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    if !(1..=MAX_BATCH_SIZE).contains(&body.count) {
+        return Err(InvitationError::ValidationError(format!(
+            "Batches hold 1 to {} invitations.",
+            MAX_BATCH_SIZE
+        )));
+    }
+    if !(1..=MAX_USES).contains(&body.max_uses) {
+        return Err(InvitationError::ValidationError(format!(
+            "Invitations are good for 1 to {} subscriptions.",
+            MAX_USES
+        )));
+    }
+    let (codes, batch_id) =
+        invitations::create_invitation_batch(&pg_pool, user_id, body.count, body.max_uses)
+            .await
+            .context("Failed to store a batch of invitations")?;
+    Ok(HttpResponse::Created().json(GeneratedInvitationBatch {
+        batch_id,
+        max_uses: body.max_uses,
+        codes,
+    }))
+}
+
+/// Stop accepting the codes of a batch. Subscriptions already made with them
+/// are kept.
+#[tracing::instrument(
+    name = "Delete a batch of invitations",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[delete("/admin/invitations/{batch_id}")]
+pub async fn delete_invitation_batch(
+    batch_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, InvitationError> {
+    authenticate(credentials, &pg_pool).await?;
+    if !invitations::delete_invitation_batch(&pg_pool, *batch_id)
+        .await
+        .context("Failed to delete a batch of invitations")?
+    {
+        return Err(InvitationError::UnknownBatch);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,79 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::jobs::{JobReport, Jobs};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum JobError {
+    #[error("There is no background job with the provided name.")]
+    UnknownJob,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+}
+
+impl std::fmt::Debug for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for JobError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            JobError::UnknownJob => StatusCode::NOT_FOUND,
+            JobError::AuthError(e) => e.status_code(),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            JobError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JobList {
+    jobs: Vec<JobReport>,
+}
+
+/// The background jobs running on this instance, with their last and next
+/// runs.
+#[tracing::instrument(
+    name = "List background jobs",
+    skip(pg_pool, jobs, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/jobs")]
+pub async fn list_jobs(
+    pg_pool: web::Data<PgPool>,
+    jobs: web::Data<Jobs>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, JobError> {
+    authenticate(credentials, &pg_pool).await?;
+    Ok(HttpResponse::Ok().json(JobList {
+        jobs: jobs.reports(),
+    }))
+}
+
+/// Run a job on this instance without waiting for its schedule. The run
+/// happens in the background, its outcome shows up in `GET /admin/jobs`.
+#[tracing::instrument(
+    name = "Trigger a background job",
+    skip(pg_pool, jobs, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/jobs/{name}/run")]
+pub async fn run_job(
+    name: web::Path<String>,
+    pg_pool: web::Data<PgPool>,
+    jobs: web::Data<Jobs>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, JobError> {
+    authenticate(credentials, &pg_pool).await?;
+    jobs.get(&name).ok_or(JobError::UnknownJob)?.trigger();
+    Ok(HttpResponse::Accepted().finish())
+}
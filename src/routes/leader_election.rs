@@ -0,0 +1,49 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::leader_election::{LeaderElections, LeadershipMetrics};
+use crate::routes::error_chain_fmt;
+use actix_web::{HttpResponse, ResponseError, get, web};
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum LeadershipError {
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+}
+
+impl std::fmt::Debug for LeadershipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for LeadershipError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            LeadershipError::AuthError(e) => e.error_response(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct LeadershipList {
+    jobs: Vec<LeadershipMetrics>,
+}
+
+/// Whether this instance leads each singleton job it runs, and how often
+/// leadership changed hands since it started.
+#[tracing::instrument(
+    name = "Get leadership metrics",
+    skip(pg_pool, leader_elections, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/leadership")]
+pub async fn get_leadership_metrics(
+    pg_pool: web::Data<PgPool>,
+    leader_elections: web::Data<LeaderElections>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, LeadershipError> {
+    authenticate(credentials, &pg_pool).await?;
+    Ok(HttpResponse::Ok().json(LeadershipList {
+        jobs: leader_elections.metrics(),
+    }))
+}
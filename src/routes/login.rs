@@ -0,0 +1,116 @@
+use crate::authentication::{
+    AuthError, Credentials, session_user, start_session, validate_credentials,
+};
+use crate::branding::Branding;
+use crate::routes::error_chain_fmt;
+use crate::session_state::TypedSession;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<AuthError> for LoginError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+        }
+    }
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for LoginError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LoginError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            LoginError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).body(self.to_string())
+    }
+}
+
+/// The login form, or a way to log out for an admin already logged in.
+#[tracing::instrument(name = "Show the admin login form", skip(session, pg_pool, branding))]
+#[get("/login")]
+pub async fn login_form(
+    session: TypedSession,
+    pg_pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, LoginError> {
+    let logged_in = session_user(session, &pg_pool).await?.is_some();
+    let page = if logged_in {
+        branding.page(
+            "You are logged in",
+            r#"<form action="/logout" method="post"><button type="submit">Log out</button></form>"#,
+        )
+    } else {
+        branding.page(
+            "Log in",
+            r#"<form action="/login" method="post">
+<label>Username <input type="text" name="username" required></label>
+<label>Password <input type="password" name="password" required></label>
+<button type="submit">Log in</button>
+</form>"#,
+        )
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: SecretString,
+}
+
+/// Start a session for an admin, so that they do not send their credentials
+/// with every request.
+#[tracing::instrument(
+    name = "Log in an admin",
+    skip(form, pg_pool, session),
+    fields(username=form.username, user_id=tracing::field::Empty)
+)]
+#[post("/login")]
+pub async fn log_in(
+    form: web::Form<LoginFormData>,
+    pg_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, LoginError> {
+    let form = form.into_inner();
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password,
+    };
+    let user_id = validate_credentials(credentials, &pg_pool).await?;
+    start_session(&session, user_id, &pg_pool).await?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish())
+}
+
+#[tracing::instrument(name = "Log out an admin", skip(session))]
+#[post("/logout")]
+pub async fn log_out(session: TypedSession) -> HttpResponse {
+    session.log_out();
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish()
+}
@@ -0,0 +1,71 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::maintenance::MaintenanceMode;
+use crate::routes::error_chain_fmt;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum MaintenanceError {
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+}
+
+impl std::fmt::Debug for MaintenanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for MaintenanceError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            MaintenanceError::AuthError(e) => e.error_response(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct MaintenanceStatus {
+    enabled: bool,
+}
+
+#[tracing::instrument(
+    name = "Get maintenance mode",
+    skip(pg_pool, maintenance, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/maintenance")]
+pub async fn get_maintenance_mode(
+    pg_pool: web::Data<PgPool>,
+    maintenance: web::Data<MaintenanceMode>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, MaintenanceError> {
+    authenticate(credentials, &pg_pool).await?;
+    Ok(HttpResponse::Ok().json(MaintenanceStatus {
+        enabled: maintenance.is_enabled(),
+    }))
+}
+
+/// Put public endpoints down for maintenance, or bring them back up.
+#[tracing::instrument(
+    name = "Set maintenance mode",
+    skip(body, pg_pool, maintenance, credentials),
+    fields(user_id=tracing::field::Empty, enabled=body.enabled)
+)]
+#[post("/admin/maintenance")]
+pub async fn set_maintenance_mode(
+    body: web::Json<MaintenanceStatus>,
+    pg_pool: web::Data<PgPool>,
+    maintenance: web::Data<MaintenanceMode>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, MaintenanceError> {
+    authenticate(credentials, &pg_pool).await?;
+    maintenance.set_enabled(body.enabled);
+    tracing::warn!(
+        enabled = body.enabled,
+        "Maintenance mode toggled on this instance"
+    );
+    Ok(HttpResponse::Ok().json(MaintenanceStatus {
+        enabled: maintenance.is_enabled(),
+    }))
+}
@@ -1,9 +1,106 @@
+mod admin;
+mod api_keys;
+mod calendar;
+mod consent_proofs;
+mod deliverability;
+mod delivery;
+mod dev_fixtures;
+mod draft_comments;
+mod email_events;
+mod feature_flags;
 pub mod health_check;
+mod imports;
+mod invitations;
+mod jobs;
+mod leader_election;
+mod login;
+mod maintenance;
+mod newsletter_drafts;
+mod newsletter_issues;
 mod newsletters;
+mod password;
+mod previews;
+mod quarantine;
+mod reengagement;
+mod reports;
+mod segments;
+mod senders;
+mod short_links;
+mod slack;
+mod subscriber_erasure;
+mod subscriber_list;
+mod subscriber_login;
+mod subscriber_merge;
+mod subscriber_search;
+pub mod subscription_status;
 pub mod subscriptions;
 mod subscriptions_confirm;
+pub mod subscriptions_unsubscribe;
+mod template_fragments;
+mod token_guard;
+mod tracking;
 
+pub use admin::{admin_dashboard, change_password_form, publish_newsletter_form};
+pub use api_keys::{generate_api_key, list_api_keys, revoke_api_key};
+pub use calendar::download_issue_event;
+pub use consent_proofs::{
+    consent_proof_public_key, export_consent_proofs, export_subscriber_consent_proof,
+};
+pub use deliverability::{get_deliverability, get_email_endpoint_stats};
+pub use delivery::{get_delivery_status, set_delivery_paused};
+pub use dev_fixtures::generate_subscriber_fixtures;
+pub use draft_comments::{create_draft_comment, list_draft_comments, resolve_draft_comment};
+pub use email_events::receive_email_events;
+pub use feature_flags::{list_feature_flags, reset_feature_flag, set_feature_flag};
 pub use health_check::*;
-pub use newsletters::publish_newsletter;
+pub use imports::{download_import_file, get_subscriber_import, import_subscribers};
+pub use invitations::{
+    delete_invitation_batch, generate_invitation_batch, list_invitation_batches,
+};
+pub use jobs::{list_jobs, run_job};
+pub use leader_election::get_leadership_metrics;
+pub use login::{log_in, log_out, login_form};
+pub use maintenance::{get_maintenance_mode, set_maintenance_mode};
+pub use newsletter_drafts::{
+    create_newsletter_draft, list_newsletter_drafts, publish_newsletter_draft,
+};
+pub use newsletter_issues::{get_newsletter_issue, list_newsletter_issues};
+pub use newsletters::{cancel_newsletter_schedule, publish_newsletter};
+pub use password::change_admin_password;
+pub use previews::{create_preview_link, preview_draft};
+pub use quarantine::{
+    list_quarantined_subscriptions, reject_quarantined_subscription,
+    release_quarantined_subscription,
+};
+pub use reengagement::{
+    complete_reengagement_campaign, reengage, resume_reengagement_campaign,
+    start_reengagement_campaign,
+};
+pub use reports::compare_newsletter_issues;
+pub use segments::{
+    count_segment_recipients, create_saved_segment, delete_saved_segment, get_saved_segment,
+    get_segment_history, list_saved_segments, preview_segment, update_saved_segment,
+};
+pub use senders::{add_verified_sender, list_verified_senders, verify_sender_token};
+pub use short_links::{follow_short_link, get_newsletter_link_stats};
+pub use slack::run_slack_command;
+pub use subscriber_erasure::delete_subscriber;
+pub use subscriber_list::list_all_subscribers;
+pub use subscriber_login::{request_magic_link, subscriber_login_form};
+pub use subscriber_merge::{list_duplicate_subscribers, merge_duplicate_subscribers};
+pub use subscriber_search::search_subscribers;
+pub use subscription_status::{
+    delete_own_subscription, export_subscriber_data, show_subscription_status,
+    update_subscription_preferences,
+};
 pub use subscriptions::{error_chain_fmt, subscribe};
-pub use subscriptions_confirm::confirm;
+pub use subscriptions_confirm::{confirm, resend_confirmation};
+pub use subscriptions_unsubscribe::{unsubscribe, unsubscribe_form};
+pub use template_fragments::{
+    delete_template_fragment, list_template_fragments, set_template_fragment,
+};
+pub use token_guard::get_token_guard_metrics;
+pub use tracking::{
+    get_newsletter_audience, get_newsletter_deliveries, get_newsletter_engagement,
+    get_subscriber_engagement, track_anonymous_open, track_open,
+};
@@ -0,0 +1,310 @@
+use crate::EmailClient;
+use crate::accessibility::{AccessibilityWarning, check_accessibility};
+use crate::amp::{AmpValidationError, validate_amp_email};
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::calendar::IssueEvent;
+use crate::configuration::{ShortLinkSettings, TrackingSettings};
+use crate::delivery::IssueDelivery;
+use crate::encryption::FieldCipher;
+use crate::extensions::DomainEvents;
+use crate::feature_flags::FeatureFlags;
+use crate::feed_watcher::render_feed_entry;
+use crate::publishing::{
+    Draft, IssueContent, IssuePublishing, PublishDraftError, SubscriberFooter,
+    get_unpublished_drafts, insert_draft, publish_draft, validate_internal_copies,
+};
+use crate::routes::error_chain_fmt;
+use crate::senders::{IssueSender, UnverifiedSender};
+use crate::startup::LinkBaseUrl;
+use crate::subject_lines::{SubjectWarning, check_subject};
+use crate::template_fragments::NonCompliantFooter;
+use crate::templates::EmailTemplates;
+use crate::throttling::DeliveryThrottle;
+use crate::web_pages::{FetchPageError, PageFetcher, extract_article};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum DraftError {
+    #[error("There is no draft associated with the provided id.")]
+    UnknownDraft,
+    #[error("The draft was already published as issue {0}.")]
+    AlreadyPublished(Uuid),
+    #[error(transparent)]
+    NonCompliantFooter(#[from] NonCompliantFooter),
+    #[error(transparent)]
+    UnverifiedSender(#[from] UnverifiedSender),
+    #[error(transparent)]
+    InvalidAmp(#[from] AmpValidationError),
+    #[error("{0}")]
+    InvalidEvent(String),
+    #[error("{0}")]
+    InvalidContent(String),
+    #[error("The source page could not be fetched.")]
+    SourceUnavailable(#[source] anyhow::Error),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<PublishDraftError> for DraftError {
+    fn from(e: PublishDraftError) -> Self {
+        match e {
+            PublishDraftError::UnknownDraft => DraftError::UnknownDraft,
+            PublishDraftError::AlreadyPublished(id) => DraftError::AlreadyPublished(id),
+            PublishDraftError::NonCompliantFooter(e) => DraftError::NonCompliantFooter(e),
+            PublishDraftError::UnverifiedSender(e) => DraftError::UnverifiedSender(e),
+            PublishDraftError::UnexpectedError(e) => DraftError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<FetchPageError> for DraftError {
+    fn from(e: FetchPageError) -> Self {
+        match e {
+            FetchPageError::InvalidUrl(_) | FetchPageError::PrivateAddress(_) => {
+                DraftError::InvalidContent(e.to_string())
+            }
+            FetchPageError::Unreachable(e) => DraftError::SourceUnavailable(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for DraftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DraftError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DraftError::UnknownDraft => StatusCode::NOT_FOUND,
+            DraftError::AlreadyPublished(_) => StatusCode::CONFLICT,
+            DraftError::NonCompliantFooter(_) | DraftError::UnverifiedSender(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            DraftError::InvalidAmp(_)
+            | DraftError::InvalidEvent(_)
+            | DraftError::InvalidContent(_) => StatusCode::BAD_REQUEST,
+            DraftError::SourceUnavailable(_) => StatusCode::BAD_GATEWAY,
+            DraftError::AuthError(e) => e.status_code(),
+            DraftError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DraftError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+/// Either `content` or a `source_url` to draft the issue from.
+#[derive(serde::Deserialize)]
+pub struct DraftBody {
+    /// Taken from the source page when missing.
+    title: Option<String>,
+    content: Option<DraftContent>,
+    /// A page, e.g. a blog post, whose article is rendered with the
+    /// `feed_entry` template.
+    source_url: Option<String>,
+    event: Option<IssueEvent>,
+    /// Checked against the registry of verified senders when the draft is
+    /// published.
+    sender: Option<IssueSender>,
+    #[serde(default)]
+    internal_copies: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DraftContent {
+    html: String,
+    text: String,
+    amp: Option<String>,
+}
+
+/// Accessibility and subject warnings are a pre-flight check, the draft is
+/// stored regardless.
+#[derive(serde::Serialize)]
+struct DraftCreated {
+    newsletter_draft_id: Uuid,
+    accessibility_warnings: Vec<AccessibilityWarning>,
+    subject_warnings: Vec<SubjectWarning>,
+}
+
+#[tracing::instrument(
+    name = "Create a newsletter draft",
+    skip(body, pg_pool, cipher, page_fetcher, email_templates, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts")]
+async fn create_newsletter_draft(
+    body: web::Json<DraftBody>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    page_fetcher: web::Data<PageFetcher>,
+    email_templates: web::Data<EmailTemplates>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DraftError> {
+    authenticate(credentials, &pg_pool).await?;
+    let body = body.into_inner();
+    if let Some(event) = &body.event {
+        event.validate().map_err(DraftError::InvalidEvent)?;
+    }
+    validate_internal_copies(&body.internal_copies).map_err(DraftError::InvalidContent)?;
+    let content = match (body.content, body.source_url) {
+        (Some(content), None) => {
+            if let Some(amp) = &content.amp {
+                validate_amp_email(amp)?;
+            }
+            IssueContent {
+                title: body.title.ok_or_else(|| {
+                    DraftError::InvalidContent("The draft must have a title.".into())
+                })?,
+                html: content.html,
+                text: content.text,
+                amp: content.amp,
+                event: body.event,
+                sender: body.sender,
+                internal_copies: body.internal_copies,
+            }
+        }
+        (None, Some(source_url)) => {
+            let page = page_fetcher.fetch(&source_url).await?;
+            let article = extract_article(&page.html, &page.url).ok_or_else(|| {
+                DraftError::InvalidContent(format!("No article was found at {}.", page.url))
+            })?;
+            let title = body.title.or(article.title).ok_or_else(|| {
+                DraftError::InvalidContent(format!(
+                    "The page at {} has no title, provide one.",
+                    page.url
+                ))
+            })?;
+            let content = render_feed_entry(
+                &email_templates.current().await.feed_entry,
+                &title,
+                page.url.as_str(),
+                &article.html,
+            );
+            IssueContent {
+                event: body.event,
+                sender: body.sender,
+                internal_copies: body.internal_copies,
+                ..content
+            }
+        }
+        _ => {
+            return Err(DraftError::InvalidContent(
+                "Provide either `content` or a `source_url`.".into(),
+            ));
+        }
+    };
+    let mut connection = pg_pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let newsletter_draft_id = insert_draft(&mut connection, &cipher, &content)
+        .await
+        .context("Failed to store the newsletter draft")?;
+    Ok(HttpResponse::Ok().json(DraftCreated {
+        newsletter_draft_id,
+        accessibility_warnings: check_accessibility(&content.html),
+        subject_warnings: check_subject(&content.title),
+    }))
+}
+
+#[derive(serde::Serialize)]
+struct DraftList {
+    drafts: Vec<Draft>,
+}
+
+/// Drafts waiting to be published, oldest first.
+#[tracing::instrument(
+    name = "List newsletter drafts",
+    skip(pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/drafts")]
+async fn list_newsletter_drafts(
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DraftError> {
+    authenticate(credentials, &pg_pool).await?;
+    let drafts = get_unpublished_drafts(&pg_pool, &cipher)
+        .await
+        .context("Failed to retrieve newsletter drafts")?;
+    Ok(HttpResponse::Ok().json(DraftList { drafts }))
+}
+
+#[derive(serde::Serialize)]
+struct DraftPublished {
+    newsletter_issue_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter draft",
+    skip(
+        pg_pool,
+        cipher,
+        page_fetcher,
+        feature_flags,
+        email_client,
+        throttle,
+        link_base_url,
+        short_link_settings,
+        tracking_settings,
+        footer,
+        events,
+        credentials
+    ),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts/{newsletter_draft_id}/publish")]
+#[allow(clippy::too_many_arguments)]
+async fn publish_newsletter_draft(
+    newsletter_draft_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    page_fetcher: web::Data<PageFetcher>,
+    feature_flags: web::Data<FeatureFlags>,
+    email_client: web::Data<EmailClient>,
+    throttle: web::Data<DeliveryThrottle>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    short_link_settings: web::Data<ShortLinkSettings>,
+    tracking_settings: web::Data<TrackingSettings>,
+    footer: web::Data<SubscriberFooter>,
+    events: web::Data<DomainEvents>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DraftError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let newsletter_issue_id = publish_draft(
+        IssueDelivery {
+            pg_pool: &pg_pool,
+            email_client: &email_client,
+            throttle: &throttle,
+            base_url: &link_base_url.0,
+            footer: &footer,
+        },
+        IssuePublishing {
+            cipher: &cipher,
+            page_fetcher: &page_fetcher,
+            feature_flags: &feature_flags,
+            short_link_settings: &short_link_settings,
+            events: &events,
+        },
+        newsletter_draft_id.into_inner(),
+        tracking_settings.mode,
+        Some(user_id),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(DraftPublished {
+        newsletter_issue_id,
+    }))
+}
@@ -0,0 +1,128 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::encryption::FieldCipher;
+use crate::publishing::{IssueSummary, count_issues, get_published_issue, list_issues};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(thiserror::Error)]
+pub enum IssueArchiveError {
+    #[error("There is no newsletter issue associated with the provided id.")]
+    UnknownIssue,
+    #[error("Pages start at 1 and hold up to {MAX_PAGE_SIZE} issues.")]
+    InvalidPage,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IssueArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IssueArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IssueArchiveError::UnknownIssue => StatusCode::NOT_FOUND,
+            IssueArchiveError::InvalidPage => StatusCode::BAD_REQUEST,
+            IssueArchiveError::AuthError(e) => e.status_code(),
+            IssueArchiveError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            IssueArchiveError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PageQuery {
+    #[serde(default = "first_page")]
+    page: i64,
+    #[serde(default = "default_page_size")]
+    page_size: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+fn default_page_size() -> i64 {
+    DEFAULT_PAGE_SIZE
+}
+
+#[derive(serde::Serialize)]
+struct IssueList {
+    issues: Vec<IssueSummary>,
+    page: i64,
+    page_size: i64,
+    /// Issues published, across every page.
+    total: i64,
+}
+
+/// Past issues, newest first, a page at a time.
+#[tracing::instrument(
+    name = "List newsletter issues",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters")]
+pub async fn list_newsletter_issues(
+    query: web::Query<PageQuery>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, IssueArchiveError> {
+    authenticate(credentials, &pg_pool).await?;
+    let PageQuery { page, page_size } = query.into_inner();
+    if page < 1 || !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(IssueArchiveError::InvalidPage);
+    }
+    let offset = (page - 1)
+        .checked_mul(page_size)
+        .ok_or(IssueArchiveError::InvalidPage)?;
+    let issues = list_issues(&pg_pool, page_size, offset)
+        .await
+        .context("Failed to list the newsletter issues")?;
+    let total = count_issues(&pg_pool)
+        .await
+        .context("Failed to count the newsletter issues")?;
+    Ok(HttpResponse::Ok().json(IssueList {
+        issues,
+        page,
+        page_size,
+        total,
+    }))
+}
+
+/// A past issue with its content, as it was emailed.
+#[tracing::instrument(
+    name = "Get a newsletter issue",
+    skip(pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/{newsletter_issue_id}")]
+pub async fn get_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, IssueArchiveError> {
+    authenticate(credentials, &pg_pool).await?;
+    let issue = get_published_issue(&pg_pool, &cipher, newsletter_issue_id.into_inner())
+        .await
+        .context("Failed to retrieve a newsletter issue")?
+        .ok_or(IssueArchiveError::UnknownIssue)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
@@ -1,39 +1,118 @@
-use crate::EmailClient;
-use crate::domain::SubscriberEmail;
+use crate::amp::{AmpValidationError, validate_amp_email};
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::calendar::IssueEvent;
+use crate::configuration::{IdempotencySettings, ShortLinkSettings, TrackingSettings};
+use crate::delivery::{DELIVERY_JOB, IssueDeliveryReport, enqueue_deliveries};
+use crate::domain::{Segment, SubscriberTag};
+use crate::encryption::FieldCipher;
+use crate::extensions::{DomainEvents, Published};
+use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
+use crate::issue_scheduler::{CancelScheduleError, cancel_scheduled_issue, schedule_issue};
+use crate::jobs::Jobs;
+use crate::publishing::{IssueContent, StoreIssueError, store_issue, validate_internal_copies};
 use crate::routes::error_chain_fmt;
-use crate::telemetry::spawn_blocking_with_tracing;
-use actix_web::dev::Payload;
+use crate::segments::get_segment;
+use crate::senders::{IssueSender, UnverifiedSender};
+use crate::startup::LinkBaseUrl;
+use crate::template_fragments::NonCompliantFooter;
+use crate::tracking::TrackingMode;
+use crate::web_pages::PageFetcher;
 use actix_web::http::header::HeaderValue;
 use actix_web::http::{StatusCode, header};
-use actix_web::{FromRequest, HttpRequest, HttpResponse, ResponseError, post, web};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, delete, post, web};
 use anyhow::Context;
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use base64::Engine;
-use base64::prelude::BASE64_STANDARD;
-use secrecy::{ExposeSecret, SecretString};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
-use std::future::{Ready, ready};
+use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
     content: Content,
+    /// Webinar or meetup announced by the issue, attached as an `.ics` file.
+    event: Option<IssueEvent>,
+    /// Overrides the configured `tracking.mode` for this issue.
+    tracking_mode: Option<TrackingMode>,
+    #[serde(default)]
+    segment: Segment,
+    /// Saved segment to send the issue to, within `segment`.
+    segment_id: Option<Uuid>,
+    /// A verified sender to send the issue from.
+    sender: Option<IssueSender>,
+    /// Addresses sent a copy of the issue without being subscribers.
+    #[serde(default)]
+    internal_copies: Vec<String>,
+    /// Queue the deliveries at this time rather than straight away.
+    scheduled_at: Option<DateTime<Utc>>,
 }
 
 #[derive(serde::Deserialize)]
 pub struct Content {
     html: String,
     text: String,
+    /// AMP for Email body, sent to providers supporting it.
+    amp: Option<String>,
 }
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error(transparent)]
+    InvalidAmp(#[from] AmpValidationError),
+    #[error("{0}")]
+    InvalidEvent(String),
+    #[error("{0}")]
+    InvalidInternalCopies(String),
+    #[error("{0}")]
+    InvalidIdempotencyKey(String),
+    #[error("There is no segment with the provided id.")]
+    UnknownSegment,
+    #[error("{0}")]
+    InvalidTag(String),
+    #[error("The issue must be scheduled in the future.")]
+    ScheduledInThePast,
+    #[error("There is no newsletter issue associated with the provided id.")]
+    UnknownIssue,
+    #[error("The issue is not scheduled, or its deliveries were already queued.")]
+    NotScheduled,
+    #[error(transparent)]
+    NonCompliantFooter(#[from] NonCompliantFooter),
+    #[error(transparent)]
+    UnverifiedSender(#[from] UnverifiedSender),
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl From<AuthError> for PublishError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+        }
+    }
+}
+
+impl From<StoreIssueError> for PublishError {
+    fn from(e: StoreIssueError) -> Self {
+        match e {
+            StoreIssueError::NonCompliantFooter(e) => PublishError::NonCompliantFooter(e),
+            StoreIssueError::UnverifiedSender(e) => PublishError::UnverifiedSender(e),
+            StoreIssueError::UnexpectedError(e) => PublishError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<CancelScheduleError> for PublishError {
+    fn from(e: CancelScheduleError) -> Self {
+        match e {
+            CancelScheduleError::UnknownIssue => PublishError::UnknownIssue,
+            CancelScheduleError::NotScheduled => PublishError::NotScheduled,
+            CancelScheduleError::UnexpectedError(e) => PublishError::UnexpectedError(e),
+        }
+    }
+}
+
 impl std::fmt::Debug for PublishError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -43,6 +122,24 @@ impl std::fmt::Debug for PublishError {
 impl ResponseError for PublishError {
     fn error_response(&self) -> HttpResponse {
         match self {
+            PublishError::InvalidAmp(_)
+            | PublishError::InvalidEvent(_)
+            | PublishError::InvalidInternalCopies(_)
+            | PublishError::InvalidIdempotencyKey(_)
+            | PublishError::UnknownSegment
+            | PublishError::InvalidTag(_)
+            | PublishError::ScheduledInThePast => {
+                HttpResponse::build(StatusCode::BAD_REQUEST).body(self.to_string())
+            }
+            PublishError::UnknownIssue => {
+                HttpResponse::build(StatusCode::NOT_FOUND).body(self.to_string())
+            }
+            PublishError::NotScheduled => {
+                HttpResponse::build(StatusCode::CONFLICT).body(self.to_string())
+            }
+            PublishError::NonCompliantFooter(_) | PublishError::UnverifiedSender(_) => {
+                HttpResponse::build(StatusCode::UNPROCESSABLE_ENTITY).body(self.to_string())
+            }
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
@@ -58,220 +155,199 @@ impl ResponseError for PublishError {
     }
 }
 
-#[derive(Debug)]
-struct BasicAuthorization {
-    username: String,
-    password: SecretString,
-}
-
-impl FromRequest for BasicAuthorization {
-    type Error = PublishError;
-    type Future = Ready<Result<Self, Self::Error>>;
-
-    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let auth_header = match req
-            .headers()
-            .get(header::AUTHORIZATION)
-            .context("The 'Authorization' header was missing")
-        {
-            Ok(header) => header,
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        };
-
-        let auth_str = match auth_header
-            .to_str()
-            .context("The 'Authorization' header was not a valid UTF8 string")
-        {
-            Ok(s) => s,
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        };
-
-        let base64encoded_segment = match auth_str
-            .strip_prefix("Basic ")
-            .context("The authorization scheme was not 'Basic'")
-        {
-            Ok(s) => s,
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        };
-
-        let decoded_bytes = match BASE64_STANDARD
-            .decode(base64encoded_segment)
-            .context("Failed to base64-decode 'Basic' credentials")
-        {
-            Ok(b) => b,
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        };
-        let decoded_credentials = match String::from_utf8(decoded_bytes)
-            .context("The decoded credential string is not valid UTF8")
-        {
-            Ok(s) => s,
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        };
-
-        let mut credentials = decoded_credentials.splitn(2, ":");
-        let username = match credentials
-            .next()
-            .context("A username must be provided in 'Basic' auth")
-        {
-            Ok(s) => s,
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        }
-        .to_string();
-
-        let password = match credentials
-            .next()
-            .context("A password must be provided in 'Basic' auth")
-        {
-            Ok(s) => s,
-
-            Err(e) => return ready(Err(PublishError::AuthError(e))),
-        }
-        .to_string();
-
-        let password = SecretString::from(password);
-        ready(Ok(BasicAuthorization { username, password }))
-    }
-}
-
+/// Store an issue and queue its delivery to every confirmed subscriber in
+/// the segment.
+///
+/// Emails are sent by the delivery worker, woken up straight away, so that
+/// a slow or failing recipient neither holds up nor fails the request.
+///
+/// Requests sent with an `Idempotency-Key` header are processed once, the
+/// retries getting the first response back.
+///
+/// Admins authenticate with `Basic` credentials, with the session started
+/// by logging in through `/login`, or scripts with an API key sent in an
+/// `X-Api-Key` header.
 #[tracing::instrument(
     name = "publish a newsletters to all confirmed subscribes",
-    skip(pg_pool, body, email_client, auth)
-    fields(username=auth.username, user_id=tracing::field::Empty)
+    skip(
+        request,
+        pg_pool,
+        cipher,
+        page_fetcher,
+        jobs,
+        body,
+        link_base_url,
+        short_link_settings,
+        tracking_settings,
+        idempotency_settings,
+        events,
+        credentials
+    )
+    fields(user_id=tracing::field::Empty)
 )]
 #[post("newsletters")]
+#[allow(clippy::too_many_arguments)]
 async fn publish_newsletter(
+    request: HttpRequest,
     pg_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    cipher: web::Data<FieldCipher>,
+    page_fetcher: web::Data<PageFetcher>,
+    jobs: web::Data<Jobs>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    short_link_settings: web::Data<ShortLinkSettings>,
+    tracking_settings: web::Data<TrackingSettings>,
+    idempotency_settings: web::Data<IdempotencySettings>,
+    events: web::Data<DomainEvents>,
     body: web::Json<BodyData>,
-    auth: BasicAuthorization,
+    credentials: AdminCredentials,
 ) -> Result<HttpResponse, PublishError> {
-    let user_id = validate_credentials(auth, &pg_pool).await?;
-    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
-
-    let subscribers = get_confirmed_subscribers(&pg_pool)
-        .await
-        .context("Failed to get all confirmed subscribers")?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(e) => {
-                tracing::warn!(
-                    // We record the error chain as a structured field
-                    // on the log record.
-                    error.cause_chain = ?e,
-                    "Skipping a confirmed subscriber. Their stored contact details are invalid",
-                );
-            }
-        }
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    if let Some(amp) = &body.content.amp {
+        validate_amp_email(amp)?;
+    }
+    if let Some(event) = &body.event {
+        event.validate().map_err(PublishError::InvalidEvent)?;
+    }
+    validate_internal_copies(&body.internal_copies).map_err(PublishError::InvalidInternalCopies)?;
+    if body.scheduled_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(PublishError::ScheduledInThePast);
+    }
+    let idempotency_key = get_idempotency_key(&request)?;
+    let mut body = body.into_inner();
+    body.segment.tags = std::mem::take(&mut body.segment.tags)
+        .into_iter()
+        .map(|tag| SubscriberTag::parse(tag).map(|tag| tag.as_ref().to_owned()))
+        .collect::<Result<_, _>>()
+        .map_err(PublishError::InvalidTag)?;
+    if let Some(segment_id) = body.segment_id {
+        let segment = get_segment(&pg_pool, segment_id)
+            .await
+            .context("Failed to retrieve the segment of a newsletter issue")?
+            .ok_or(PublishError::UnknownSegment)?;
+        body.segment.filter = Some(segment.filter);
+        body.segment.segment_id = Some(segment_id);
     }
-    Ok(HttpResponse::Ok().finish())
-}
-
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
-}
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pg_pool))]
-async fn get_confirmed_subscribers(
-    pg_pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, sqlx::Error> {
-    let rows = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pg_pool)
-    .await?
-    .into_iter()
-    .map(|r| match r.email.try_into() {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(e) => Err(anyhow::anyhow!(e)),
-    })
-    .collect();
-    Ok(rows)
-}
+    let content = IssueContent {
+        title: body.title,
+        html: body.content.html,
+        text: body.content.text,
+        amp: body.content.amp,
+        event: body.event,
+        sender: body.sender,
+        internal_copies: body.internal_copies,
+    }
+    .with_cards_expanded(&pg_pool, &page_fetcher)
+    .await?;
 
-#[tracing::instrument(name = "Get stored credentials", skip(username, pg_pool))]
-async fn get_stored_credentials(
-    username: &str,
-    pg_pool: &PgPool,
-) -> Result<Option<(uuid::Uuid, SecretString)>, anyhow::Error> {
-    let row: Option<_> = sqlx::query!(
-        r#"
-        SELECT user_id, password_hash
-        FROM users
-        WHERE username = $1
-        "#,
-        username,
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => match try_processing(
+            &pg_pool,
+            idempotency_key,
+            user_id,
+            idempotency_settings.expire_after,
+        )
+        .await?
+        {
+            NextAction::StartProcessing(transaction) => transaction,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => pg_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
+    };
+    let tracking_mode = body.tracking_mode.unwrap_or(tracking_settings.mode);
+    let issue = store_issue(
+        &mut transaction,
+        &cipher,
+        &link_base_url.0,
+        &short_link_settings,
+        &content,
+        tracking_mode,
+        Some(user_id),
     )
-    .fetch_optional(pg_pool)
-    .await
-    .context("Failed to perform a query to validate auth credentials")?
-    .map(|r| (r.user_id, SecretString::from(r.password_hash)));
-    Ok(row)
-}
-
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pg_pool))]
-async fn validate_credentials(
-    credentials: BasicAuthorization,
-    pg_pool: &PgPool,
-) -> Result<uuid::Uuid, PublishError> {
-    let mut user_id = None;
-    let mut expected_password_hash = SecretString::from(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
-    );
-
-    if let Some((stored_user_id, stored_password_hash)) =
-        get_stored_credentials(&credentials.username, pg_pool)
+    .await?;
+    let queued_deliveries = match body.scheduled_at {
+        Some(scheduled_at) => {
+            schedule_issue(
+                &mut transaction,
+                issue.newsletter_issue_id,
+                scheduled_at,
+                &body.segment,
+            )
+            .await?;
+            0
+        }
+        None => enqueue_deliveries(&mut transaction, issue.newsletter_issue_id, &body.segment)
             .await
-            .map_err(PublishError::UnexpectedError)?
-    {
-        user_id = Some(stored_user_id);
-        expected_password_hash = stored_password_hash;
+            .context("Failed to enqueue the deliveries of a newsletter issue")?,
+    };
+    let mut response = HttpResponse::Accepted().json(PublishResponse {
+        newsletter_issue_id: issue.newsletter_issue_id,
+        queued_deliveries,
+        deliveries: IssueDeliveryReport::queued(queued_deliveries),
+        scheduled_at: body.scheduled_at,
+    });
+    if let Some(idempotency_key) = &idempotency_key {
+        response = save_response(&mut transaction, idempotency_key, user_id, response).await?;
     }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue")?;
+    // Scheduled issues are left to the `IssueScheduler`.
+    if body.scheduled_at.is_some() {
+        return Ok(response);
+    }
+    events.published(Published {
+        newsletter_issue_id: issue.newsletter_issue_id,
+        title: issue.title,
+    });
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
-    })
-    .await
-    .context("Failed to spawn blocking task.")
-    .map_err(PublishError::UnexpectedError)??;
-
-    user_id.ok_or_else(|| PublishError::AuthError(anyhow::anyhow!("Unknown username.")))
+    if let Some(job) = jobs.get(DELIVERY_JOB) {
+        job.trigger();
+    }
+    Ok(response)
 }
+
+/// Cancel an issue published with a `scheduled_at`, as long as its
+/// deliveries are not queued yet. The issue is deleted.
 #[tracing::instrument(
-    name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
+    name = "Cancel a scheduled newsletter issue",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
 )]
-fn verify_password_hash(
-    expected_password_hash: SecretString,
-    password_candidate: SecretString,
-) -> Result<(), PublishError> {
-    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
-        .context("Failed to parse hash in PHC string format.")
-        .map_err(PublishError::UnexpectedError)?;
+#[delete("/newsletters/{newsletter_issue_id}/schedule")]
+async fn cancel_newsletter_schedule(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(credentials, &pg_pool).await?;
+    cancel_scheduled_issue(&pg_pool, newsletter_issue_id.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
 
-    Argon2::default()
-        .verify_password(
-            password_candidate.expose_secret().as_bytes(),
-            &expected_password_hash,
-        )
-        .context("Invalid password.")
-        .map_err(PublishError::AuthError)
+fn get_idempotency_key(request: &HttpRequest) -> Result<Option<IdempotencyKey>, PublishError> {
+    let Some(value) = request.headers().get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    let value = value.to_str().map_err(|_| {
+        PublishError::InvalidIdempotencyKey("The idempotency key must be ASCII.".into())
+    })?;
+    IdempotencyKey::try_from(value.to_owned())
+        .map(Some)
+        .map_err(PublishError::InvalidIdempotencyKey)
+}
+
+#[derive(serde::Serialize)]
+struct PublishResponse {
+    newsletter_issue_id: Uuid,
+    queued_deliveries: u64,
+    /// Follow the deliveries along with
+    /// `GET /newsletters/{newsletter_issue_id}/deliveries`.
+    deliveries: IssueDeliveryReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled_at: Option<DateTime<Utc>>,
 }
@@ -0,0 +1,103 @@
+use crate::authentication::{
+    AdminCredentials, AuthError, Credentials, authenticate, change_password, get_username,
+    validate_credentials,
+};
+use crate::domain::Password;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, post, web};
+use anyhow::Context;
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum PasswordChangeError {
+    #[error("{0}")]
+    WeakPassword(String),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PasswordChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PasswordChangeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PasswordChangeError::WeakPassword(_) => StatusCode::BAD_REQUEST,
+            PasswordChangeError::AuthError(e) => e.status_code(),
+            PasswordChangeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PasswordChangeError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    current_password: SecretString,
+    new_password: SecretString,
+}
+
+/// Change the password of the admin making the request.
+///
+/// The current password is asked for even within a session, so that a
+/// session left open cannot be used to lock its admin out.
+#[tracing::instrument(
+    name = "Change an admin password",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/password")]
+pub async fn change_admin_password(
+    body: web::Json<BodyData>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, PasswordChangeError> {
+    // API keys publish issues, they do not manage the admin they belong to.
+    if let AdminCredentials::ApiKey(_) = credentials {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+            "API keys cannot change passwords"
+        ))
+        .into());
+    }
+    let session = match &credentials {
+        AdminCredentials::Session(session) => Some(session.clone()),
+        _ => None,
+    };
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let BodyData {
+        current_password,
+        new_password,
+    } = body.into_inner();
+    let username = get_username(user_id, &pg_pool).await?;
+    validate_credentials(
+        Credentials {
+            username,
+            password: current_password,
+        },
+        &pg_pool,
+    )
+    .await?;
+    let new_password = Password::parse(new_password).map_err(PasswordChangeError::WeakPassword)?;
+    let session_version = change_password(user_id, new_password, &pg_pool).await?;
+    // Every other session of the admin ends, the one they changed their
+    // password from carries on.
+    if let Some(session) = session {
+        session.renew();
+        session
+            .insert_user(user_id, session_version)
+            .context("Failed to store the user id in the session")?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
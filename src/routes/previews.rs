@@ -0,0 +1,155 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::encryption::FieldCipher;
+use crate::publishing::{escape_html, get_draft};
+use crate::routes::error_chain_fmt;
+use crate::signing::{SignatureError, UrlSigner};
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const PREVIEW_PURPOSE: &str = "draft-preview";
+const DEFAULT_EXPIRES_IN_HOURS: u32 = 72;
+/// Links are shared outside the team: they must not outlive the review.
+const MAX_EXPIRES_IN_HOURS: u32 = 30 * 24;
+
+#[derive(thiserror::Error)]
+pub enum PreviewError {
+    #[error("There is no draft associated with the provided id.")]
+    UnknownDraft,
+    #[error("The preview link is invalid.")]
+    InvalidLink,
+    #[error("The preview link has expired.")]
+    Expired,
+    #[error("The preview link must be valid for 1 to {MAX_EXPIRES_IN_HOURS} hours.")]
+    InvalidExpiry,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreviewError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PreviewError::UnknownDraft | PreviewError::InvalidLink => StatusCode::NOT_FOUND,
+            PreviewError::Expired => StatusCode::GONE,
+            PreviewError::InvalidExpiry => StatusCode::BAD_REQUEST,
+            PreviewError::AuthError(e) => e.status_code(),
+            PreviewError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PreviewError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PreviewLinkParameters {
+    expires_in_hours: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct PreviewLink {
+    preview_url: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Hand out a link showing the draft to anyone holding it, until it expires.
+#[tracing::instrument(
+    name = "Create a draft preview link",
+    skip(pg_pool, cipher, base_url, url_signer, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/newsletters/drafts/{newsletter_draft_id}/preview_links")]
+async fn create_preview_link(
+    newsletter_draft_id: web::Path<Uuid>,
+    parameters: web::Query<PreviewLinkParameters>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    url_signer: web::Data<UrlSigner>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, PreviewError> {
+    authenticate(credentials, &pg_pool).await?;
+    let newsletter_draft_id = newsletter_draft_id.into_inner();
+    let expires_in_hours = parameters
+        .expires_in_hours
+        .unwrap_or(DEFAULT_EXPIRES_IN_HOURS);
+    if !(1..=MAX_EXPIRES_IN_HOURS).contains(&expires_in_hours) {
+        return Err(PreviewError::InvalidExpiry);
+    }
+    get_draft(&pg_pool, &cipher, newsletter_draft_id)
+        .await
+        .context("Failed to retrieve the newsletter draft")?
+        .ok_or(PreviewError::UnknownDraft)?;
+
+    let expires_at = Utc::now()
+        .checked_add_signed(chrono::Duration::hours(expires_in_hours.into()))
+        .ok_or(PreviewError::InvalidExpiry)?;
+    let token = url_signer.sign(
+        PREVIEW_PURPOSE,
+        &newsletter_draft_id.to_string(),
+        expires_at,
+    );
+    Ok(HttpResponse::Ok().json(PreviewLink {
+        preview_url: format!("{}/preview/{}", base_url.0, token),
+        expires_at,
+    }))
+}
+
+#[tracing::instrument(name = "Preview a draft", skip(token, pg_pool, cipher, url_signer))]
+#[get("/preview/{token}")]
+async fn preview_draft(
+    token: web::Path<String>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    url_signer: web::Data<UrlSigner>,
+) -> Result<HttpResponse, PreviewError> {
+    let newsletter_draft_id = url_signer
+        .verify(PREVIEW_PURPOSE, &token, Utc::now())
+        .map_err(|e| match e {
+            SignatureError::Invalid => PreviewError::InvalidLink,
+            SignatureError::Expired(_) => PreviewError::Expired,
+        })?
+        .parse::<Uuid>()
+        .map_err(|_| PreviewError::InvalidLink)?;
+    let draft = get_draft(&pg_pool, &cipher, newsletter_draft_id)
+        .await
+        .context("Failed to retrieve the newsletter draft")?
+        .ok_or(PreviewError::UnknownDraft)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .insert_header(("X-Robots-Tag", "noindex"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="robots" content="noindex">
+<title>Preview: {}</title>
+</head>
+<body>
+{}
+</body>
+</html>"#,
+            escape_html(&draft.content.title),
+            draft.content.html
+        )))
+}
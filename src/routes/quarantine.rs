@@ -0,0 +1,280 @@
+use crate::EmailClient;
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::configuration::ConfirmationEmailSettings;
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriberRegion, SubscriptionToken,
+};
+use crate::encryption::{DecryptionError, FieldCipher};
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::routes::error_chain_fmt;
+use crate::routes::subscriptions::{
+    SubscriberLinks, claim_confirmation_email, outbox_relay_after, send_from_outbox, store_token,
+    write_confirmation_email_to_outbox,
+};
+use crate::signing::UrlSigner;
+use crate::startup::{ApplicationBaseUrl, LinkBaseUrl};
+use crate::templates::EmailTemplates;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum QuarantineError {
+    #[error("There is no quarantined subscription associated with the provided id.")]
+    UnknownSubscription,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for QuarantineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for QuarantineError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            QuarantineError::UnknownSubscription => StatusCode::NOT_FOUND,
+            QuarantineError::AuthError(e) => e.status_code(),
+            QuarantineError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            QuarantineError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct QuarantinedSubscription {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    signup_network: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct QuarantineList {
+    subscriptions: Vec<QuarantinedSubscription>,
+}
+
+/// Subscriptions held back as part of a signup burst, newest first.
+#[tracing::instrument(
+    name = "List quarantined subscriptions",
+    skip(pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/quarantine")]
+pub async fn list_quarantined_subscriptions(
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, QuarantineError> {
+    authenticate(credentials, &pg_pool).await?;
+    let subscriptions = sqlx::query!(
+        r#"
+        SELECT
+            id AS subscriber_id, email, name, subscribed_at, signup_network,
+            quarantine_reason AS reason
+        FROM subscriptions
+        WHERE status = 'quarantined'
+        ORDER BY subscribed_at DESC
+        "#,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to retrieve quarantined subscriptions")?
+    .into_iter()
+    .map(|r| {
+        Ok(QuarantinedSubscription {
+            subscriber_id: r.subscriber_id,
+            email: r.email,
+            name: cipher.decrypt(r.name)?,
+            subscribed_at: r.subscribed_at,
+            signup_network: r.signup_network,
+            reason: r.reason,
+        })
+    })
+    .collect::<Result<_, DecryptionError>>()
+    .context("Failed to decrypt quarantined subscriptions")?;
+    Ok(HttpResponse::Ok().json(QuarantineList { subscriptions }))
+}
+
+/// Treat a quarantined subscription as a regular signup: it goes back to
+/// `pending_confirmation` and the confirmation email is sent.
+#[tracing::instrument(
+    name = "Release a quarantined subscription",
+    skip(
+        pg_pool,
+        cipher,
+        email_client,
+        base_url,
+        link_base_url,
+        url_signer,
+        email_templates,
+        confirmation_email_settings,
+        feature_flags,
+        credentials
+    ),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/quarantine/{subscriber_id}/release")]
+#[allow(clippy::too_many_arguments)]
+pub async fn release_quarantined_subscription(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    url_signer: web::Data<UrlSigner>,
+    email_templates: web::Data<EmailTemplates>,
+    confirmation_email_settings: web::Data<ConfirmationEmailSettings>,
+    feature_flags: web::Data<FeatureFlags>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, QuarantineError> {
+    authenticate(credentials, &pg_pool).await?;
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = release_subscription(&mut transaction, &cipher, subscriber_id)
+        .await
+        .context("Failed to release a quarantined subscription")?
+        .ok_or(QuarantineError::UnknownSubscription)?;
+    if !claim_confirmation_email(
+        &mut transaction,
+        &confirmation_email_settings,
+        &subscriber.email,
+    )
+    .await
+    .context("Failed to check the confirmation emails sent to a released subscriber")?
+    {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to release a subscription")?;
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let subscriber_token = SubscriptionToken::generate();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        &subscriber_token,
+        confirmation_email_settings.token_ttl,
+    )
+    .await
+    .context("Failed to store the confirmation token for a released subscriber")?;
+    let flags = feature_flags
+        .load(&pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    let paused = flags.is_enabled(PAUSE_DELIVERIES);
+    write_confirmation_email_to_outbox(
+        &mut transaction,
+        subscriber_id,
+        &subscriber_token,
+        outbox_relay_after(paused),
+    )
+    .await
+    .context("Failed to write the confirmation email of a released subscriber to the outbox")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to release a subscription")?;
+
+    if !paused {
+        let links = SubscriberLinks {
+            base_url: &base_url.0,
+            link_base_url: &link_base_url.0,
+            url_signer: &url_signer,
+        };
+        send_from_outbox(
+            &pg_pool,
+            &email_client,
+            &*email_templates.current().await,
+            &links,
+            subscriber_id,
+            subscriber,
+            &subscriber_token,
+        )
+        .await?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Drop a quarantined subscription, no email is ever sent to it.
+#[tracing::instrument(
+    name = "Reject a quarantined subscription",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/quarantine/{subscriber_id}/reject")]
+pub async fn reject_quarantined_subscription(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, QuarantineError> {
+    authenticate(credentials, &pg_pool).await?;
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 AND status = 'quarantined'"#,
+        subscriber_id.into_inner(),
+    )
+    .execute(pg_pool.as_ref())
+    .await
+    .context("Failed to delete a quarantined subscription")?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(QuarantineError::UnknownSubscription);
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Returns `None` if the subscription is not quarantined.
+#[tracing::instrument(
+    name = "Move a subscription out of quarantine",
+    skip(transaction, cipher)
+)]
+async fn release_subscription(
+    transaction: &mut PgConnection,
+    cipher: &FieldCipher,
+    subscriber_id: Uuid,
+) -> Result<Option<NewSubscriber>, anyhow::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'pending_confirmation', quarantine_reason = NULL
+        WHERE id = $1 AND status = 'quarantined'
+        RETURNING email, name, region
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let subscriber = NewSubscriber {
+        email: SubscriberEmail::try_from(row.email).map_err(anyhow::Error::msg)?,
+        name: SubscriberName::try_from(cipher.decrypt(row.name)?).map_err(anyhow::Error::msg)?,
+        region: row
+            .region
+            .map(SubscriberRegion::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)?,
+        tags: Vec::new(),
+    };
+    Ok(Some(subscriber))
+}
@@ -0,0 +1,556 @@
+use crate::EmailClient;
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::branding::Branding;
+use crate::domain::{SubscriberEmail, SubscriberRegion, SubscriptionToken};
+use crate::email_client::EmailClientError;
+use crate::repositories::subscribers::unsubscribe_subscribers;
+use crate::routes::error_chain_fmt;
+use crate::routes::subscription_status::subscription_status_link;
+use crate::routes::subscriptions_unsubscribe::unsubscribe_link;
+use crate::signing::UrlSigner;
+use crate::startup::{ApplicationBaseUrl, LinkBaseUrl};
+use crate::templates::EmailTemplates;
+use actix_web::http::StatusCode;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+const DEFAULT_GRACE_PERIOD_DAYS: u32 = 30;
+
+#[derive(thiserror::Error)]
+pub enum ReengagementError {
+    #[error("There is no campaign associated with the provided id.")]
+    UnknownCampaign,
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The grace period of the campaign ends at {0}.")]
+    GracePeriodNotOver(DateTime<Utc>),
+    #[error("The campaign was already completed.")]
+    AlreadyCompleted,
+    #[error("Deliveries are paused, email the campaign once they resume.")]
+    DeliveriesPaused,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ReengagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ReengagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ReengagementError::UnknownCampaign => StatusCode::NOT_FOUND,
+            ReengagementError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ReengagementError::GracePeriodNotOver(_) | ReengagementError::AlreadyCompleted => {
+                StatusCode::CONFLICT
+            }
+            ReengagementError::DeliveriesPaused => StatusCode::SERVICE_UNAVAILABLE,
+            ReengagementError::AuthError(e) => e.status_code(),
+            ReengagementError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ReengagementError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct StartCampaignBody {
+    grace_period_days: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+struct CampaignStarted {
+    campaign_id: Uuid,
+    grace_period_ends_at: DateTime<Utc>,
+    recipients: usize,
+}
+
+/// Email every confirmed subscriber in the `inactive_90d` segment, asking
+/// them to confirm they still want the newsletter.
+///
+/// Subscribers are enrolled before being emailed: those a failure left out
+/// are emailed by [`resume_reengagement_campaign`].
+#[tracing::instrument(
+    name = "Start a re-engagement campaign",
+    skip(
+        body,
+        pg_pool,
+        email_client,
+        base_url,
+        link_base_url,
+        email_templates,
+        url_signer,
+        credentials
+    ),
+    fields(user_id=tracing::field::Empty, campaign_id=tracing::field::Empty)
+)]
+#[post("/admin/reengagement_campaigns")]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_reengagement_campaign(
+    body: web::Json<StartCampaignBody>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    email_templates: web::Data<EmailTemplates>,
+    url_signer: web::Data<UrlSigner>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ReengagementError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    if email_client
+        .is_paused()
+        .await
+        .context("Failed to check whether deliveries are paused")?
+    {
+        return Err(ReengagementError::DeliveriesPaused);
+    }
+    let grace_period_days = body.grace_period_days.unwrap_or(DEFAULT_GRACE_PERIOD_DAYS);
+    let grace_period_ends_at = Utc::now() + chrono::Duration::days(grace_period_days.into());
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let campaign_id = insert_campaign(&mut transaction, user_id, grace_period_ends_at)
+        .await
+        .context("Failed to store the re-engagement campaign")?;
+    tracing::Span::current().record("campaign_id", tracing::field::display(&campaign_id));
+    let recipients = enroll_inactive_subscribers(&mut transaction, campaign_id)
+        .await
+        .context("Failed to enroll inactive subscribers in the campaign")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a re-engagement campaign")?;
+
+    email_pending_recipients(
+        &pg_pool,
+        &email_client,
+        &base_url.0,
+        &link_base_url.0,
+        &email_templates,
+        &url_signer,
+        campaign_id,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(CampaignStarted {
+        campaign_id,
+        grace_period_ends_at,
+        recipients,
+    }))
+}
+
+#[derive(serde::Serialize)]
+struct CampaignResumed {
+    emailed: usize,
+}
+
+/// Email the recipients of a campaign who were enrolled but not emailed,
+/// after sending failed or was interrupted.
+#[tracing::instrument(
+    name = "Resume a re-engagement campaign",
+    skip(
+        pg_pool,
+        email_client,
+        base_url,
+        link_base_url,
+        email_templates,
+        url_signer,
+        credentials
+    ),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/reengagement_campaigns/{campaign_id}/resume")]
+#[allow(clippy::too_many_arguments)]
+pub async fn resume_reengagement_campaign(
+    campaign_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    email_templates: web::Data<EmailTemplates>,
+    url_signer: web::Data<UrlSigner>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ReengagementError> {
+    authenticate(credentials, &pg_pool).await?;
+    let campaign_id = campaign_id.into_inner();
+    let completed_at = sqlx::query_scalar!(
+        "SELECT completed_at FROM reengagement_campaigns WHERE campaign_id = $1",
+        campaign_id,
+    )
+    .fetch_optional(pg_pool.as_ref())
+    .await
+    .context("Failed to retrieve the re-engagement campaign")?
+    .ok_or(ReengagementError::UnknownCampaign)?;
+    if completed_at.is_some() {
+        return Err(ReengagementError::AlreadyCompleted);
+    }
+    if email_client
+        .is_paused()
+        .await
+        .context("Failed to check whether deliveries are paused")?
+    {
+        return Err(ReengagementError::DeliveriesPaused);
+    }
+
+    let emailed = email_pending_recipients(
+        &pg_pool,
+        &email_client,
+        &base_url.0,
+        &link_base_url.0,
+        &email_templates,
+        &url_signer,
+        campaign_id,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(CampaignResumed { emailed }))
+}
+
+/// Email the recipients of `campaign_id` not emailed yet, returning how many
+/// were. Recipients whose address is rejected are skipped and stay pending.
+async fn email_pending_recipients(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    link_base_url: &str,
+    email_templates: &EmailTemplates,
+    url_signer: &UrlSigner,
+    campaign_id: Uuid,
+) -> Result<usize, ReengagementError> {
+    let recipients = get_pending_recipients(pg_pool, campaign_id)
+        .await
+        .context("Failed to retrieve the recipients left to email")?;
+    let email_templates = email_templates.current().await;
+    let template = &email_templates.reengagement;
+    let mut emailed = 0;
+    for recipient in &recipients {
+        let contact = SubscriberEmail::try_from(recipient.email.clone()).and_then(|email| {
+            let region = recipient.region.clone().map(SubscriberRegion::parse);
+            Ok((email, region.transpose()?))
+        });
+        let (email, region) = match contact {
+            Ok(contact) => contact,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Skipping an inactive subscriber. Their stored contact details are invalid",
+                );
+                continue;
+            }
+        };
+        let link = create_reengagement_link(base_url, &recipient.reengagement_token)
+            .context("Failed to create a re-engagement link")?;
+        let variables = [("reengagement_link", link.as_str())];
+        let status_link =
+            subscription_status_link(link_base_url, url_signer, recipient.subscriber_id);
+        let unsubscribe_link = unsubscribe_link(link_base_url, url_signer, recipient.subscriber_id);
+        let (html, text) = email_templates.footer.append(
+            &template.render_html(&variables),
+            &template.render_text(&variables),
+            &status_link,
+            &unsubscribe_link,
+        );
+        let outcome = email_client
+            .send_email_in_region(region.as_ref(), &email, &template.subject, &html, &text)
+            .await;
+        let message_id = match outcome {
+            Ok(message_id) => message_id,
+            Err(e @ EmailClientError::InvalidRecipient(_)) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Skipping an inactive subscriber. The email provider rejected their address",
+                );
+                continue;
+            }
+            Err(EmailClientError::Paused) => return Err(ReengagementError::DeliveriesPaused),
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!(
+                        "Failed to send the re-engagement email to {}",
+                        email.masked()
+                    ))
+                    .into());
+            }
+        };
+        mark_emailed(
+            pg_pool,
+            campaign_id,
+            recipient.subscriber_id,
+            message_id.as_deref(),
+        )
+        .await
+        .context("Failed to record a re-engagement email")?;
+        emailed += 1;
+    }
+    Ok(emailed)
+}
+
+#[derive(serde::Serialize)]
+struct CampaignCompleted {
+    unsubscribed: u64,
+}
+
+/// Unsubscribe everyone who was emailed by the campaign and did not respond
+/// before its grace period ended.
+#[tracing::instrument(
+    name = "Complete a re-engagement campaign",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/reengagement_campaigns/{campaign_id}/complete")]
+pub async fn complete_reengagement_campaign(
+    campaign_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ReengagementError> {
+    authenticate(credentials, &pg_pool).await?;
+    let campaign_id = campaign_id.into_inner();
+
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let campaign = sqlx::query!(
+        r#"
+        SELECT grace_period_ends_at, completed_at
+        FROM reengagement_campaigns
+        WHERE campaign_id = $1
+        FOR UPDATE
+        "#,
+        campaign_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the re-engagement campaign")?
+    .ok_or(ReengagementError::UnknownCampaign)?;
+    if campaign.completed_at.is_some() {
+        return Err(ReengagementError::AlreadyCompleted);
+    }
+    if campaign.grace_period_ends_at > Utc::now() {
+        return Err(ReengagementError::GracePeriodNotOver(
+            campaign.grace_period_ends_at,
+        ));
+    }
+
+    let unsubscribed = unsubscribe_non_responders(&mut transaction, campaign_id)
+        .await
+        .context("Failed to unsubscribe non-responders")?;
+    sqlx::query!(
+        r#"UPDATE reengagement_campaigns SET completed_at = now() WHERE campaign_id = $1"#,
+        campaign_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the re-engagement campaign as completed")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to complete a re-engagement campaign")?;
+
+    Ok(HttpResponse::Ok().json(CampaignCompleted { unsubscribed }))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ReengageParameters {
+    reengagement_token: SubscriptionToken,
+}
+
+#[tracing::instrument(
+    name = "Record a re-engagement response",
+    skip(parameters, pg_pool, branding)
+)]
+#[get("/subscriptions/reengage")]
+pub async fn reengage(
+    parameters: web::Query<ReengageParameters>,
+    pg_pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, ReengagementError> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE reengagement_recipients
+        SET responded_at = COALESCE(responded_at, now())
+        WHERE reengagement_token = $1
+        "#,
+        parameters.reengagement_token.expose_secret(),
+    )
+    .execute(pg_pool.as_ref())
+    .await
+    .context("Failed to record the re-engagement response")?
+    .rows_affected();
+    if updated == 0 {
+        return Err(ReengagementError::UnknownToken);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(branding.page(
+            "You are still subscribed",
+            "<p>Thank you for letting us know, you will keep receiving the newsletter.</p>",
+        )))
+}
+
+#[tracing::instrument(name = "Store re-engagement campaign", skip(pg_connection))]
+async fn insert_campaign(
+    pg_connection: &mut PgConnection,
+    started_by: Uuid,
+    grace_period_ends_at: DateTime<Utc>,
+) -> Result<Uuid, sqlx::Error> {
+    let campaign_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO reengagement_campaigns (
+            campaign_id, started_by, started_at, grace_period_ends_at
+        )
+        VALUES ($1, $2, now(), $3)
+        "#,
+        campaign_id,
+        started_by,
+        grace_period_ends_at,
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(campaign_id)
+}
+
+struct Recipient {
+    subscriber_id: Uuid,
+    email: String,
+    region: Option<String>,
+    reengagement_token: SubscriptionToken,
+}
+
+/// Enroll the confirmed subscribers of the `inactive_90d` segment, returning
+/// how many were.
+#[tracing::instrument(name = "Enroll inactive subscribers", skip(pg_connection))]
+async fn enroll_inactive_subscribers(
+    pg_connection: &mut PgConnection,
+    campaign_id: Uuid,
+) -> Result<usize, sqlx::Error> {
+    let inactive_subscribers = sqlx::query_scalar!(
+        r#"
+        SELECT s.id
+        FROM subscriptions s
+        JOIN subscriber_engagement e ON e.subscriber_id = s.id
+        WHERE s.status = 'confirmed' AND e.inactive_90d
+        "#,
+    )
+    .fetch_all(&mut *pg_connection)
+    .await?;
+
+    for subscriber_id in &inactive_subscribers {
+        let reengagement_token = SubscriptionToken::generate();
+        sqlx::query!(
+            r#"
+            INSERT INTO reengagement_recipients (campaign_id, subscriber_id, reengagement_token)
+            VALUES ($1, $2, $3)
+            "#,
+            campaign_id,
+            subscriber_id,
+            reengagement_token.expose_secret(),
+        )
+        .execute(&mut *pg_connection)
+        .await?;
+    }
+    Ok(inactive_subscribers.len())
+}
+
+/// Recipients of `campaign_id` still confirmed and not emailed yet.
+#[tracing::instrument(name = "Get pending re-engagement recipients", skip(pg_pool))]
+async fn get_pending_recipients(
+    pg_pool: &PgPool,
+    campaign_id: Uuid,
+) -> Result<Vec<Recipient>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT r.subscriber_id, r.reengagement_token, s.email, s.region
+        FROM reengagement_recipients r
+        JOIN subscriptions s ON s.id = r.subscriber_id
+        WHERE r.campaign_id = $1 AND r.emailed_at IS NULL AND s.status = 'confirmed'
+        "#,
+        campaign_id,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Recipient {
+            subscriber_id: r.subscriber_id,
+            email: r.email,
+            region: r.region,
+            reengagement_token: SubscriptionToken::from(r.reengagement_token),
+        })
+        .collect())
+}
+
+#[tracing::instrument(name = "Mark re-engagement email as sent", skip(pg_pool))]
+async fn mark_emailed(
+    pg_pool: &PgPool,
+    campaign_id: Uuid,
+    subscriber_id: Uuid,
+    provider_message_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE reengagement_recipients
+        SET emailed_at = now(), provider_message_id = $3
+        WHERE campaign_id = $1 AND subscriber_id = $2
+        "#,
+        campaign_id,
+        subscriber_id,
+        provider_message_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Only recipients who actually received the email and are still confirmed
+/// are unsubscribed. The deliveries queued for them are dropped.
+#[tracing::instrument(name = "Unsubscribe non-responders", skip(pg_connection))]
+async fn unsubscribe_non_responders(
+    pg_connection: &mut PgConnection,
+    campaign_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let non_responders = sqlx::query_scalar!(
+        r#"
+        SELECT s.id
+        FROM subscriptions s
+        JOIN reengagement_recipients r ON r.subscriber_id = s.id
+        WHERE r.campaign_id = $1
+            AND r.emailed_at IS NOT NULL
+            AND r.responded_at IS NULL
+            AND s.status = 'confirmed'
+        FOR UPDATE OF s
+        "#,
+        campaign_id,
+    )
+    .fetch_all(&mut *pg_connection)
+    .await?;
+    let unsubscribed = unsubscribe_subscribers(pg_connection, &non_responders).await?;
+    Ok(unsubscribed.len() as u64)
+}
+
+fn create_reengagement_link(
+    base_url: &str,
+    reengagement_token: &SubscriptionToken,
+) -> Result<url::Url, url::ParseError> {
+    let base = url::Url::parse(base_url)?;
+    let mut url = base.join("subscriptions/reengage")?;
+    url.query_pairs_mut()
+        .append_pair("reengagement_token", reengagement_token.expose_secret());
+    Ok(url)
+}
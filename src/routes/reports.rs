@@ -0,0 +1,218 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::routes::error_chain_fmt;
+use crate::tracking::TrackingMode;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How many issues can be compared side by side.
+const MAX_COMPARED_ISSUES: usize = 20;
+
+#[derive(thiserror::Error)]
+pub enum ReportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no newsletter issue with id {0}.")]
+    UnknownIssue(Uuid),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ReportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ReportError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ReportError::UnknownIssue(_) => StatusCode::NOT_FOUND,
+            ReportError::AuthError(e) => e.status_code(),
+            ReportError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ReportError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CompareParameters {
+    /// Comma-separated ids of the issues to compare.
+    issues: String,
+}
+
+/// Parse the comma-separated issue ids of a comparison, dropping
+/// duplicates but keeping their order.
+fn parse_issue_ids(issues: &str) -> Result<Vec<Uuid>, String> {
+    let mut ids = Vec::new();
+    for id in issues.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let id = Uuid::parse_str(id).map_err(|_| format!("{} is not a valid issue id.", id))?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return Err("Pick at least one issue to compare.".into());
+    }
+    if ids.len() > MAX_COMPARED_ISSUES {
+        return Err(format!(
+            "At most {} issues can be compared at once.",
+            MAX_COMPARED_ISSUES
+        ));
+    }
+    Ok(ids)
+}
+
+/// The performance of an issue, as shares of its deliveries.
+///
+/// Open and click rates count unique subscribers, so they are only known
+/// for issues tracked in `detailed` mode. An unsubscribe is attributed to
+/// the last issue delivered to the subscriber before they unsubscribed.
+#[derive(serde::Serialize)]
+struct IssueComparison {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    tracking_mode: &'static str,
+    deliveries: i64,
+    open_rate: Option<f64>,
+    click_rate: Option<f64>,
+    bounce_rate: f64,
+    unsubscribe_rate: f64,
+    /// Time between the issue being published and its last delivery being
+    /// handed to the provider, unknown until something was delivered.
+    send_duration_seconds: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct Comparison {
+    issues: Vec<IssueComparison>,
+}
+
+/// Compare issues side by side, e.g. for end-of-month reporting. Issues are
+/// listed in the order they were asked for.
+#[tracing::instrument(
+    name = "Compare newsletter issues",
+    skip(parameters, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/reports/compare")]
+pub async fn compare_newsletter_issues(
+    parameters: web::Query<CompareParameters>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ReportError> {
+    authenticate(credentials, &pg_pool).await?;
+    let ids = parse_issue_ids(&parameters.issues).map_err(ReportError::ValidationError)?;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            i.tracking_mode,
+            (SELECT COUNT(*) FROM delivery_outcomes o
+             WHERE o.newsletter_issue_id = i.newsletter_issue_id) AS "deliveries!",
+            (SELECT COUNT(*) FROM delivery_outcomes o
+             WHERE o.newsletter_issue_id = i.newsletter_issue_id AND o.bounced) AS "bounces!",
+            (SELECT MAX(o.latency_seconds) FROM delivery_outcomes o
+             WHERE o.newsletter_issue_id = i.newsletter_issue_id) AS send_duration_seconds,
+            (SELECT COUNT(DISTINCT e.subscriber_id) FROM email_opens e
+             WHERE e.newsletter_issue_id = i.newsletter_issue_id
+                AND NOT e.is_bot) AS "unique_opens!",
+            (SELECT COUNT(DISTINCT c.subscriber_id) FROM short_link_clicks c
+             JOIN short_links l ON l.code = c.code
+             WHERE l.newsletter_issue_id = i.newsletter_issue_id
+                AND NOT c.is_bot) AS "unique_clicks!",
+            (SELECT COUNT(*) FROM newsletter_deliveries d
+             JOIN subscriptions s ON s.id = d.subscriber_id
+             WHERE d.newsletter_issue_id = i.newsletter_issue_id
+                AND s.unsubscribed_at >= d.delivered_at
+                AND NOT EXISTS (
+                    SELECT 1 FROM newsletter_deliveries later
+                    WHERE later.subscriber_id = d.subscriber_id
+                        AND later.delivered_at > d.delivered_at
+                        AND later.delivered_at <= s.unsubscribed_at
+                )) AS "unsubscribes!"
+        FROM newsletter_issues i
+        WHERE i.newsletter_issue_id = ANY($1)
+        "#,
+        &ids,
+    )
+    .fetch_all(pg_pool.as_ref())
+    .await
+    .context("Failed to compute the performance of the compared issues")?;
+
+    let mut issues = Vec::with_capacity(ids.len());
+    for id in ids {
+        let r = rows
+            .iter()
+            .find(|r| r.newsletter_issue_id == id)
+            .ok_or(ReportError::UnknownIssue(id))?;
+        let tracking_mode =
+            TrackingMode::try_from(r.tracking_mode.clone()).map_err(|e| anyhow::anyhow!(e))?;
+        let rate = |count: i64| {
+            if r.deliveries == 0 {
+                0.0
+            } else {
+                count as f64 / r.deliveries as f64
+            }
+        };
+        let detailed = tracking_mode == TrackingMode::Detailed;
+        issues.push(IssueComparison {
+            newsletter_issue_id: id,
+            title: r.title.clone(),
+            published_at: r.published_at,
+            tracking_mode: tracking_mode.as_str(),
+            deliveries: r.deliveries,
+            open_rate: detailed.then(|| rate(r.unique_opens)),
+            click_rate: detailed.then(|| rate(r.unique_clicks)),
+            bounce_rate: rate(r.bounces),
+            unsubscribe_rate: rate(r.unsubscribes),
+            send_duration_seconds: r.send_duration_seconds,
+        });
+    }
+    Ok(HttpResponse::Ok().json(Comparison { issues }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_COMPARED_ISSUES, parse_issue_ids};
+    use claims::assert_err;
+    use uuid::Uuid;
+
+    #[test]
+    fn ids_are_deduplicated_in_order() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let ids = parse_issue_ids(&format!("{b}, {a},{b},")).unwrap();
+
+        assert_eq!(ids, vec![b, a]);
+    }
+
+    #[test]
+    fn empty_invalid_or_too_long_lists_are_rejected() {
+        let too_many = (0..=MAX_COMPARED_ISSUES)
+            .map(|_| Uuid::new_v4().to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        assert_err!(parse_issue_ids(""));
+        assert_err!(parse_issue_ids(" , "));
+        assert_err!(parse_issue_ids("not-an-id"));
+        assert_err!(parse_issue_ids(&too_many));
+    }
+}
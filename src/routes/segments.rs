@@ -0,0 +1,282 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::delivery::count_recipients;
+use crate::domain::Segment;
+use crate::routes::error_chain_fmt;
+use crate::segments::{
+    SavedSegment, SegmentChange, StoreSegmentError, create_segment, delete_segment, get_segment,
+    get_segment_changes, list_segments, update_segment,
+};
+use crate::subscriber_search::{FilterError, SubscriberFilter};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, delete, get, post, put, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(thiserror::Error)]
+pub enum SegmentError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    InvalidFilter(#[from] FilterError),
+    #[error("There is no segment with the provided id.")]
+    UnknownSegment,
+    #[error("There is already a segment named `{0}`.")]
+    NameTaken(String),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<StoreSegmentError> for SegmentError {
+    fn from(e: StoreSegmentError) -> Self {
+        match e {
+            StoreSegmentError::NameTaken(name) => SegmentError::NameTaken(name),
+            StoreSegmentError::UnexpectedError(e) => SegmentError::UnexpectedError(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for SegmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SegmentError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SegmentError::ValidationError(_) | SegmentError::InvalidFilter(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SegmentError::UnknownSegment => StatusCode::NOT_FOUND,
+            SegmentError::NameTaken(_) => StatusCode::CONFLICT,
+            SegmentError::AuthError(e) => e.status_code(),
+            SegmentError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SegmentError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SegmentBody {
+    name: String,
+    #[serde(default)]
+    filter: SubscriberFilter,
+}
+
+impl SegmentBody {
+    fn validate(self) -> Result<(String, SubscriberFilter), SegmentError> {
+        let name = self.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(SegmentError::ValidationError(format!(
+                "Segment names are 1 to {} characters long.",
+                MAX_NAME_LENGTH
+            )));
+        }
+        self.filter.validate()?;
+        Ok((name.to_owned(), self.filter))
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PreviewBody {
+    #[serde(default)]
+    filter: SubscriberFilter,
+}
+
+#[derive(serde::Serialize)]
+struct SegmentList {
+    segments: Vec<SavedSegment>,
+}
+
+#[derive(serde::Serialize)]
+struct SegmentHistory {
+    changes: Vec<SegmentChange>,
+}
+
+#[derive(serde::Serialize)]
+struct RecipientCount {
+    /// Confirmed subscribers a newsletter sent to the segment would go to.
+    recipients: i64,
+}
+
+/// Every saved segment, by name.
+#[tracing::instrument(
+    name = "List segments",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/segments")]
+pub async fn list_saved_segments(
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let segments = list_segments(&pg_pool)
+        .await
+        .context("Failed to retrieve the segments")?;
+    Ok(HttpResponse::Ok().json(SegmentList { segments }))
+}
+
+/// Save a filter under a name, to send newsletters to with `segment_id`.
+#[tracing::instrument(
+    name = "Create a segment",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/segments")]
+pub async fn create_saved_segment(
+    body: web::Json<SegmentBody>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let (name, filter) = body.into_inner().validate()?;
+    let segment = create_segment(&pg_pool, user_id, name, filter).await?;
+    Ok(HttpResponse::Created().json(segment))
+}
+
+/// How many subscribers a filter would send a newsletter to, before saving
+/// it as a segment.
+#[tracing::instrument(
+    name = "Preview the recipients of a filter",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/segments/preview")]
+pub async fn preview_segment(
+    body: web::Json<PreviewBody>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let filter = body.into_inner().filter;
+    filter.validate()?;
+    recipient_count(&pg_pool, filter).await
+}
+
+#[tracing::instrument(
+    name = "Get a segment",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/segments/{segment_id}")]
+pub async fn get_saved_segment(
+    segment_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let segment = get_segment(&pg_pool, segment_id.into_inner())
+        .await
+        .context("Failed to retrieve a segment")?
+        .ok_or(SegmentError::UnknownSegment)?;
+    Ok(HttpResponse::Ok().json(segment))
+}
+
+/// Rename a segment or change its filter. Newsletters already queued keep
+/// their recipients.
+#[tracing::instrument(
+    name = "Update a segment",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[put("/admin/segments/{segment_id}")]
+pub async fn update_saved_segment(
+    segment_id: web::Path<Uuid>,
+    body: web::Json<SegmentBody>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    let (name, filter) = body.into_inner().validate()?;
+    let segment = update_segment(&pg_pool, user_id, segment_id.into_inner(), name, filter)
+        .await?
+        .ok_or(SegmentError::UnknownSegment)?;
+    Ok(HttpResponse::Ok().json(segment))
+}
+
+#[tracing::instrument(
+    name = "Delete a segment",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[delete("/admin/segments/{segment_id}")]
+pub async fn delete_saved_segment(
+    segment_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    if !delete_segment(&pg_pool, user_id, segment_id.into_inner()).await? {
+        return Err(SegmentError::UnknownSegment);
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// How many subscribers a newsletter sent to the segment now would go to.
+#[tracing::instrument(
+    name = "Count the recipients of a segment",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/segments/{segment_id}/recipients")]
+pub async fn count_segment_recipients(
+    segment_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let segment = get_segment(&pg_pool, segment_id.into_inner())
+        .await
+        .context("Failed to retrieve a segment")?
+        .ok_or(SegmentError::UnknownSegment)?;
+    recipient_count(&pg_pool, segment.filter).await
+}
+
+/// Who created, changed and deleted a segment, oldest change first. The
+/// history of a deleted segment stays available.
+#[tracing::instrument(
+    name = "Get the history of a segment",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/segments/{segment_id}/changes")]
+pub async fn get_segment_history(
+    segment_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SegmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let changes = get_segment_changes(&pg_pool, segment_id.into_inner())
+        .await
+        .context("Failed to retrieve the changes made to a segment")?;
+    if changes.is_empty() {
+        return Err(SegmentError::UnknownSegment);
+    }
+    Ok(HttpResponse::Ok().json(SegmentHistory { changes }))
+}
+
+async fn recipient_count(
+    pg_pool: &PgPool,
+    filter: SubscriberFilter,
+) -> Result<HttpResponse, SegmentError> {
+    let segment = Segment {
+        filter: Some(filter),
+        ..Default::default()
+    };
+    let recipients = count_recipients(pg_pool, &segment)
+        .await
+        .context("Failed to count the recipients of a segment")?;
+    Ok(HttpResponse::Ok().json(RecipientCount { recipients }))
+}
@@ -0,0 +1,131 @@
+use crate::EmailClient;
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::domain::SubscriptionToken;
+use crate::routes::error_chain_fmt;
+use crate::senders::{
+    Sender, SenderAddress, SenderRegistryError, add_sender, list_senders, verify_sender,
+};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum SenderError {
+    #[error(transparent)]
+    Registry(#[from] SenderRegistryError),
+    #[error("There is no sender waiting for this token.")]
+    UnknownToken,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SenderError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SenderError::Registry(SenderRegistryError::InvalidAddress(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            SenderError::Registry(SenderRegistryError::AlreadyVerified(_)) => StatusCode::CONFLICT,
+            SenderError::Registry(SenderRegistryError::DeliveriesPaused) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            SenderError::UnknownToken => StatusCode::NOT_FOUND,
+            SenderError::AuthError(e) => e.status_code(),
+            SenderError::Registry(SenderRegistryError::UnexpectedError(_))
+            | SenderError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SenderError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SenderList {
+    senders: Vec<Sender>,
+}
+
+#[tracing::instrument(
+    name = "List verified senders",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/senders")]
+pub async fn list_verified_senders(
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SenderError> {
+    authenticate(credentials, &pg_pool).await?;
+    let senders = list_senders(&pg_pool)
+        .await
+        .context("Failed to list the senders")?;
+    Ok(HttpResponse::Ok().json(SenderList { senders }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewSender {
+    /// An email address, or a domain to send from any address of.
+    address: String,
+}
+
+/// Add an address or a domain to the registry. Issues can be sent from it
+/// once the token emailed to it is verified.
+#[tracing::instrument(
+    name = "Add a verified sender",
+    skip(body, pg_pool, email_client, credentials),
+    fields(user_id=tracing::field::Empty, address=%body.address)
+)]
+#[post("/admin/senders")]
+pub async fn add_verified_sender(
+    body: web::Json<NewSender>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SenderError> {
+    authenticate(credentials, &pg_pool).await?;
+    let address = SenderAddress::parse(&body.address)?;
+    add_sender(&pg_pool, &email_client, &address).await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[derive(serde::Deserialize)]
+pub struct SenderVerification {
+    token: SubscriptionToken,
+}
+
+#[derive(serde::Serialize)]
+struct VerifiedSender {
+    address: String,
+}
+
+#[tracing::instrument(
+    name = "Verify a sender",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/senders/verify")]
+pub async fn verify_sender_token(
+    body: web::Json<SenderVerification>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SenderError> {
+    authenticate(credentials, &pg_pool).await?;
+    let address = verify_sender(&pg_pool, &body.token)
+        .await
+        .context("Failed to verify a sender")?
+        .ok_or(SenderError::UnknownToken)?;
+    Ok(HttpResponse::Ok().json(VerifiedSender { address }))
+}
@@ -0,0 +1,235 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::configuration::TrackingSettings;
+use crate::routes::error_chain_fmt;
+use crate::tracking::{BotReason, TrackingMode, detect_bot};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{LOCATION, USER_AGENT};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum ShortLinkError {
+    #[error("There is no link associated with the provided code.")]
+    UnknownCode,
+    #[error("The link has expired.")]
+    Expired,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ShortLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ShortLinkError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ShortLinkError::UnknownCode => StatusCode::NOT_FOUND,
+            ShortLinkError::Expired => StatusCode::GONE,
+            ShortLinkError::AuthError(e) => e.status_code(),
+            ShortLinkError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ShortLinkError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ClickParameters {
+    /// The recipient the link was sent to, if it was attributed.
+    s: Option<Uuid>,
+}
+
+#[tracing::instrument(
+    name = "Follow a short link",
+    skip(request, parameters, pg_pool, tracking_settings)
+)]
+#[get("/l/{code}")]
+pub async fn follow_short_link(
+    request: HttpRequest,
+    code: web::Path<String>,
+    parameters: web::Query<ClickParameters>,
+    pg_pool: web::Data<PgPool>,
+    tracking_settings: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, ShortLinkError> {
+    let short_link = get_short_link(&pg_pool, &code)
+        .await
+        .context("Failed to retrieve the short link")?
+        .ok_or(ShortLinkError::UnknownCode)?;
+    if short_link
+        .expires_at
+        .is_some_and(|expires_at| expires_at < Utc::now())
+    {
+        return Err(ShortLinkError::Expired);
+    }
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok());
+    let elapsed_since_send = (Utc::now() - short_link.published_at)
+        .to_std()
+        .unwrap_or_default();
+    let bot_reason = detect_bot(user_agent, elapsed_since_send, &tracking_settings);
+    if let Some(reason) = bot_reason {
+        tracing::info!(reason = reason.as_str(), "Flagging short link click");
+    }
+    let tracking_mode =
+        TrackingMode::try_from(short_link.tracking_mode).map_err(|e| anyhow::anyhow!(e))?;
+    match tracking_mode {
+        TrackingMode::Detailed => {
+            record_click(&pg_pool, &code, parameters.s, user_agent, bot_reason)
+                .await
+                .context("Failed to record a short link click")?
+        }
+        TrackingMode::Aggregate => increment_click_counter(&pg_pool, &code, bot_reason.is_some())
+            .await
+            .context("Failed to increment the short link click counter")?,
+    }
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, short_link.destination_url))
+        .finish())
+}
+
+struct ShortLink {
+    destination_url: String,
+    expires_at: Option<DateTime<Utc>>,
+    published_at: DateTime<Utc>,
+    tracking_mode: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkStats {
+    code: String,
+    destination_url: String,
+    clicks: i64,
+    /// Clicks attributed to bots and prefetchers, not included in `clicks`.
+    flagged_clicks: i64,
+}
+
+#[tracing::instrument(
+    name = "Get link stats for a newsletter issue",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/{newsletter_issue_id}/links")]
+pub async fn get_newsletter_link_stats(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ShortLinkError> {
+    authenticate(credentials, &pg_pool).await?;
+    let stats = get_link_stats(&pg_pool, *newsletter_issue_id)
+        .await
+        .context("Failed to compute link stats for the newsletter issue")?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[tracing::instrument(name = "Get short link", skip(pg_pool))]
+async fn get_short_link(pg_pool: &PgPool, code: &str) -> Result<Option<ShortLink>, sqlx::Error> {
+    let row = sqlx::query_as!(
+        ShortLink,
+        r#"
+        SELECT l.destination_url, l.expires_at, i.published_at, i.tracking_mode
+        FROM short_links l
+        JOIN newsletter_issues i ON i.newsletter_issue_id = l.newsletter_issue_id
+        WHERE l.code = $1
+        "#,
+        code,
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Record short link click", skip(pg_pool))]
+async fn record_click(
+    pg_pool: &PgPool,
+    code: &str,
+    subscriber_id: Option<Uuid>,
+    user_agent: Option<&str>,
+    bot_reason: Option<BotReason>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO short_link_clicks (
+            code, subscriber_id, user_agent, is_bot, bot_reason, clicked_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        code,
+        subscriber_id,
+        user_agent,
+        bot_reason.is_some(),
+        bot_reason.map(|r| r.as_str()),
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Increment short link click counter", skip(pg_pool))]
+async fn increment_click_counter(
+    pg_pool: &PgPool,
+    code: &str,
+    is_bot: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE short_links
+        SET aggregate_clicks = aggregate_clicks + (NOT $2)::int,
+            aggregate_flagged_clicks = aggregate_flagged_clicks + $2::int
+        WHERE code = $1
+        "#,
+        code,
+        is_bot,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Links of an issue, best performing first.
+#[tracing::instrument(name = "Get link stats", skip(pg_pool))]
+async fn get_link_stats(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<LinkStats>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            l.code,
+            l.destination_url,
+            COUNT(c.id) FILTER (WHERE NOT c.is_bot) + l.aggregate_clicks AS "clicks!",
+            COUNT(c.id) FILTER (WHERE c.is_bot) + l.aggregate_flagged_clicks AS "flagged_clicks!"
+        FROM short_links l
+        LEFT JOIN short_link_clicks c ON c.code = l.code
+        WHERE l.newsletter_issue_id = $1
+        GROUP BY l.code, l.destination_url, l.aggregate_clicks, l.aggregate_flagged_clicks
+        ORDER BY 3 DESC, l.destination_url
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pg_pool)
+    .await?
+    .into_iter()
+    .map(|r| LinkStats {
+        code: r.code,
+        destination_url: r.destination_url,
+        clicks: r.clicks,
+        flagged_clicks: r.flagged_clicks,
+    })
+    .collect();
+    Ok(rows)
+}
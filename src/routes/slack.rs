@@ -0,0 +1,219 @@
+use crate::configuration::SlackSettings;
+use crate::delivery::count_queued_emails;
+use crate::draft_scheduler::DRAFT_SCHEDULER_JOB;
+use crate::encryption::FieldCipher;
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::jobs::Jobs;
+use crate::publishing::{PublishDraftError, get_unpublished_drafts, schedule_draft};
+use crate::routes::error_chain_fmt;
+use crate::slack::{SlackCommand, verify_signature};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, post, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum SlackCommandError {
+    #[error("Slack commands are not enabled.")]
+    NotConfigured,
+    #[error("The request signature was missing or invalid.")]
+    InvalidSignature,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SlackCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SlackCommandError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SlackCommandError::NotConfigured => StatusCode::NOT_FOUND,
+            SlackCommandError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            SlackCommandError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Answer to a slash command, shown only to its user unless `in_channel`.
+#[derive(serde::Serialize)]
+struct SlackReply {
+    response_type: &'static str,
+    text: String,
+}
+
+impl SlackReply {
+    fn ephemeral(text: impl Into<String>) -> HttpResponse {
+        HttpResponse::Ok().json(Self {
+            response_type: "ephemeral",
+            text: text.into(),
+        })
+    }
+
+    fn in_channel(text: impl Into<String>) -> HttpResponse {
+        HttpResponse::Ok().json(Self {
+            response_type: "in_channel",
+            text: text.into(),
+        })
+    }
+}
+
+/// Run the `/newsletter` slash command sent by the Slack app.
+///
+/// Mistakes of the Slack user are answered with a `200 OK` and an
+/// explanation, Slack showing any other status as a failure of the app.
+/// Approved drafts are published in the background by the
+/// [`DraftScheduler`](crate::draft_scheduler::DraftScheduler), Slack giving
+/// up on answers taking more than three seconds.
+#[tracing::instrument(
+    name = "Run a Slack command",
+    skip_all,
+    fields(slack_user = tracing::field::Empty, command = tracing::field::Empty)
+)]
+#[post("/slack/commands")]
+pub async fn run_slack_command(
+    request: HttpRequest,
+    body: web::Bytes,
+    slack_settings: web::Data<Option<SlackSettings>>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    feature_flags: web::Data<FeatureFlags>,
+    jobs: web::Data<Jobs>,
+) -> Result<HttpResponse, SlackCommandError> {
+    let settings = slack_settings
+        .as_ref()
+        .as_ref()
+        .ok_or(SlackCommandError::NotConfigured)?;
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+    };
+    if !verify_signature(
+        &settings.signing_secret,
+        header("X-Slack-Request-Timestamp"),
+        &body,
+        header("X-Slack-Signature"),
+        Utc::now(),
+    ) {
+        return Err(SlackCommandError::InvalidSignature);
+    }
+
+    let mut user_id = String::new();
+    let mut text = String::new();
+    for (key, value) in url::form_urlencoded::parse(&body) {
+        match key.as_ref() {
+            "user_id" => user_id = value.into_owned(),
+            "text" => text = value.into_owned(),
+            _ => {}
+        }
+    }
+    let span = tracing::Span::current();
+    span.record("slack_user", tracing::field::display(&user_id));
+    span.record("command", tracing::field::display(&text));
+    if !settings.allowed_users.contains(&user_id) {
+        return Ok(SlackReply::ephemeral(
+            "You are not allowed to manage the newsletter.",
+        ));
+    }
+    let command = match SlackCommand::parse(&text) {
+        Ok(command) => command,
+        Err(usage) => return Ok(SlackReply::ephemeral(usage)),
+    };
+
+    match command {
+        SlackCommand::Status => Ok(SlackReply::ephemeral(
+            status(&pg_pool, &cipher, &feature_flags).await?,
+        )),
+        SlackCommand::Schedule {
+            newsletter_draft_id,
+            at,
+        } => {
+            if at <= Utc::now() {
+                return Ok(SlackReply::ephemeral(
+                    "Pick a time in the future, or approve the draft to publish it now.",
+                ));
+            }
+            if let Err(e) = schedule_draft(&pg_pool, newsletter_draft_id, at).await {
+                return draft_error_reply(e);
+            }
+            Ok(SlackReply::in_channel(format!(
+                "<@{}> scheduled draft `{}` to be published at {}.",
+                user_id,
+                newsletter_draft_id,
+                at.to_rfc3339()
+            )))
+        }
+        SlackCommand::Approve {
+            newsletter_draft_id,
+        } => {
+            if let Err(e) = schedule_draft(&pg_pool, newsletter_draft_id, Utc::now()).await {
+                return draft_error_reply(e);
+            }
+            if let Some(job) = jobs.get(DRAFT_SCHEDULER_JOB) {
+                job.trigger();
+            }
+            Ok(SlackReply::in_channel(format!(
+                "<@{}> approved draft `{}`, it is being published.",
+                user_id, newsletter_draft_id
+            )))
+        }
+    }
+}
+
+/// Unknown or already published drafts are the Slack user's mistake.
+fn draft_error_reply(e: PublishDraftError) -> Result<HttpResponse, SlackCommandError> {
+    match e {
+        PublishDraftError::UnexpectedError(e) => Err(e.into()),
+        e => Ok(SlackReply::ephemeral(e.to_string())),
+    }
+}
+
+async fn status(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    feature_flags: &FeatureFlags,
+) -> Result<String, anyhow::Error> {
+    let flags = feature_flags
+        .load(pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    let queued = count_queued_emails(pg_pool)
+        .await
+        .context("Failed to count the queued emails")?;
+    let drafts = get_unpublished_drafts(pg_pool, cipher)
+        .await
+        .context("Failed to retrieve newsletter drafts")?;
+
+    let mut status = format!(
+        "Deliveries are {}, {} deliveries and {} confirmation emails are queued.",
+        if flags.is_enabled(PAUSE_DELIVERIES) {
+            "paused"
+        } else {
+            "running"
+        },
+        queued.deliveries,
+        queued.confirmation_emails,
+    );
+    if drafts.is_empty() {
+        status.push_str("\nNo drafts are waiting.");
+    } else {
+        status.push_str("\nDrafts waiting:");
+    }
+    for draft in drafts {
+        status.push_str(&format!(
+            "\n• `{}` {}",
+            draft.newsletter_draft_id, draft.content.title
+        ));
+        if let Some(scheduled_at) = draft.scheduled_at {
+            status.push_str(&format!(", scheduled for {}", scheduled_at.to_rfc3339()));
+        }
+    }
+    Ok(status)
+}
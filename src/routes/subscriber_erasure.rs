@@ -0,0 +1,69 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::blob_store::BlobStore;
+use crate::repositories::subscribers::erase_subscriber;
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, delete, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum EraseSubscriberError {
+    #[error("There is no subscriber with this id.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EraseSubscriberError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EraseSubscriberError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EraseSubscriberError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            EraseSubscriberError::AuthError(e) => e.status_code(),
+            EraseSubscriberError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            EraseSubscriberError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+/// Delete everything about a subscriber, e.g. when they exercise their
+/// right to erasure by writing to us.
+#[tracing::instrument(
+    name = "Erase a subscriber on an admin's request",
+    skip(pg_pool, blob_store, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[delete("/admin/subscribers/{subscriber_id}")]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, EraseSubscriberError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    if !erase_subscriber(
+        &pg_pool,
+        blob_store.as_ref(),
+        subscriber_id.into_inner(),
+        Some(user_id),
+    )
+    .await?
+    {
+        return Err(EraseSubscriberError::UnknownSubscriber);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,135 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::encryption::FieldCipher;
+use crate::repositories::subscribers::{
+    Cursor, ListedSubscriber, ListingError, PageStart, SubscriberListing, count_subscribers,
+    list_subscribers,
+};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(thiserror::Error)]
+pub enum ListSubscribersError {
+    #[error(transparent)]
+    InvalidListing(#[from] ListingError),
+    #[error("Pages hold from 1 to {MAX_LIMIT} subscribers, and start at a cursor or an offset.")]
+    InvalidPage,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListSubscribersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ListSubscribersError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ListSubscribersError::InvalidListing(_) | ListSubscribersError::InvalidPage => {
+                StatusCode::BAD_REQUEST
+            }
+            ListSubscribersError::AuthError(e) => e.status_code(),
+            ListSubscribersError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ListSubscribersError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ListParameters {
+    status: Option<String>,
+    /// Part of the email address.
+    email: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+    offset: Option<i64>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+}
+
+fn default_limit() -> i64 {
+    DEFAULT_LIMIT
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberPage {
+    subscribers: Vec<ListedSubscriber>,
+    /// Where the next page starts, `None` on the last page.
+    next_cursor: Option<String>,
+    /// Subscribers matching the filters, across every page.
+    total: i64,
+}
+
+/// Subscribers, newest first, optionally filtered by status and part of
+/// their email address.
+///
+/// Pages start at the `cursor` returned with the previous one, or at an
+/// `offset`.
+#[tracing::instrument(
+    name = "List subscribers",
+    skip(pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/subscribers")]
+pub async fn list_all_subscribers(
+    parameters: web::Query<ListParameters>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, ListSubscribersError> {
+    authenticate(credentials, &pg_pool).await?;
+    let ListParameters {
+        status,
+        email,
+        limit,
+        offset,
+        cursor,
+    } = parameters.into_inner();
+    let listing = SubscriberListing {
+        status,
+        email_contains: email,
+    };
+    listing.validate()?;
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ListSubscribersError::InvalidPage);
+    }
+    let start = match (offset, cursor) {
+        (Some(offset), None) if offset >= 0 => PageStart::Offset(offset),
+        (None, Some(cursor)) => PageStart::After(Cursor::parse(&cursor)?),
+        (None, None) => PageStart::Offset(0),
+        _ => return Err(ListSubscribersError::InvalidPage),
+    };
+    // One more than asked, to tell whether there is a next page.
+    let mut subscribers = list_subscribers(&pg_pool, &cipher, &listing, &start, limit + 1)
+        .await
+        .context("Failed to list subscribers")?;
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|s| s.cursor().encode())
+    } else {
+        None
+    };
+    let total = count_subscribers(&pg_pool, &listing)
+        .await
+        .context("Failed to count the listed subscribers")?;
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        next_cursor,
+        total,
+    }))
+}
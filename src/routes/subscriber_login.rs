@@ -0,0 +1,190 @@
+use crate::EmailClient;
+use crate::branding::Branding;
+use crate::domain::{SubscriberEmail, SubscriberRegion};
+use crate::locale::{Locale, LocalizedError, Message};
+use crate::routes::error_chain_fmt;
+use crate::routes::subscription_status::magic_link;
+use crate::signing::UrlSigner;
+use crate::startup::ApplicationBaseUrl;
+use crate::telemetry::record_in_request_spans;
+use crate::templates::EmailTemplates;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CONTENT_LANGUAGE, ContentType};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Magic links replace a password, they must not outlive the email session
+/// they were requested in.
+const MAGIC_LINK_LIFETIME_MINUTES: i64 = 15;
+
+#[derive(thiserror::Error)]
+pub enum SubscriberLoginError {
+    #[error("{message}")]
+    ValidationError { message: String, locale: Locale },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberLoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberLoginError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberLoginError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            SubscriberLoginError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let SubscriberLoginError::ValidationError { locale, .. } = self {
+            response.insert_header((CONTENT_LANGUAGE, locale.tag()));
+        }
+        response.body(self.to_string())
+    }
+}
+
+#[get("/subscriptions/login")]
+async fn subscriber_login_form(branding: web::Data<Branding>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(branding.page(
+            "Manage your subscription",
+            r#"<form action="/subscriptions/login" method="post">
+<label>Email <input type="email" name="email" required></label>
+<button type="submit">Email me a link</button>
+</form>"#,
+        ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    email: String,
+}
+
+/// Email a short-lived link to the subscription of the submitted address.
+///
+/// The response is the same whether the address is subscribed or not, so
+/// that the form cannot be used to find out who reads the newsletter.
+#[tracing::instrument(
+    name = "Send a magic link to a subscriber",
+    skip(
+        request,
+        form,
+        pg_pool,
+        email_client,
+        base_url,
+        email_templates,
+        url_signer,
+        branding
+    ),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[post("/subscriptions/login")]
+#[allow(clippy::too_many_arguments)]
+async fn request_magic_link(
+    request: HttpRequest,
+    form: web::Form<LoginFormData>,
+    pg_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_templates: web::Data<EmailTemplates>,
+    url_signer: web::Data<UrlSigner>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, SubscriberLoginError> {
+    let email = SubscriberEmail::try_from(form.0.email.clone()).map_err(|_| {
+        let locale = Locale::from_request(&request);
+        SubscriberLoginError::ValidationError {
+            message: LocalizedError::new(Message::InvalidEmail, form.0.email).localize(locale),
+            locale,
+        }
+    })?;
+    let subscriber = get_subscriber(&pg_pool, &email)
+        .await
+        .context("Failed to retrieve the subscriber associated with an email")?;
+
+    // The link is emailed in the background: waiting for the provider only
+    // for subscribed addresses would tell them apart by the response time.
+    if let Some((subscriber_id, region)) = subscriber {
+        record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+        let magic_link = magic_link(
+            &base_url.0,
+            &url_signer,
+            subscriber_id,
+            Utc::now() + chrono::Duration::minutes(MAGIC_LINK_LIFETIME_MINUTES),
+        );
+        tokio::spawn(
+            send_magic_link(email_client, email_templates, email, region, magic_link)
+                .in_current_span(),
+        );
+    }
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(branding.page(
+        "Check your inbox",
+        "<p>If this address is subscribed to our newsletter, we sent it a link to manage your subscription.</p>",
+    )))
+}
+
+/// Email `magic_link` to `email`. A failure is only logged, the response
+/// being long gone.
+async fn send_magic_link(
+    email_client: web::Data<EmailClient>,
+    email_templates: web::Data<EmailTemplates>,
+    email: SubscriberEmail,
+    region: Option<SubscriberRegion>,
+    magic_link: String,
+) {
+    let lifetime = MAGIC_LINK_LIFETIME_MINUTES.to_string();
+    let email_templates = email_templates.current().await;
+    let template = &email_templates.magic_link;
+    let variables = [
+        ("magic_link", magic_link.as_str()),
+        ("expires_in_minutes", lifetime.as_str()),
+    ];
+    if let Err(e) = email_client
+        .send_email_in_region(
+            region.as_ref(),
+            &email,
+            &template.subject,
+            &template.render_html(&variables),
+            &template.render_text(&variables),
+        )
+        .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a magic link",
+        );
+    }
+}
+
+#[tracing::instrument(name = "Get subscriber by email", skip(pg_pool, email))]
+async fn get_subscriber(
+    pg_pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, Option<SubscriberRegion>)>, anyhow::Error> {
+    let Some(row) = sqlx::query!(
+        r#"SELECT id, region FROM subscriptions WHERE email = $1"#,
+        email.as_ref(),
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let region = row
+        .region
+        .map(SubscriberRegion::parse)
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
+    Ok(Some((row.id, region)))
+}
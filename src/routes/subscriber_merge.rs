@@ -0,0 +1,117 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::routes::error_chain_fmt;
+use crate::subscriber_merge::{DuplicateGroup, MergeError, find_duplicates, merge_subscribers};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum DeduplicationError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnknownSubscriber(MergeError),
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<MergeError> for DeduplicationError {
+    fn from(e: MergeError) -> Self {
+        match e {
+            MergeError::SameSubscriber => DeduplicationError::ValidationError(e.to_string()),
+            MergeError::UnknownSubscriber(_) => DeduplicationError::UnknownSubscriber(e),
+            MergeError::UnexpectedError(e) => DeduplicationError::UnexpectedError(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for DeduplicationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DeduplicationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DeduplicationError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            DeduplicationError::UnknownSubscriber(_) => StatusCode::NOT_FOUND,
+            DeduplicationError::AuthError(e) => e.status_code(),
+            DeduplicationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DeduplicationError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct DuplicatesQuery {
+    /// Also match the variants of an address delivered to the same mailbox.
+    #[serde(default)]
+    fuzzy: bool,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct MergeBody {
+    /// Subscriber to keep.
+    subscriber_id: Uuid,
+    /// Subscribers to merge into it.
+    duplicates: Vec<Uuid>,
+}
+
+#[derive(serde::Serialize)]
+struct DuplicateList {
+    groups: Vec<DuplicateGroup>,
+}
+
+/// Subscribers signed up more than once under the same address, to review
+/// before merging them.
+#[tracing::instrument(
+    name = "Find duplicate subscribers",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/subscribers/duplicates")]
+pub async fn list_duplicate_subscribers(
+    query: web::Query<DuplicatesQuery>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DeduplicationError> {
+    authenticate(credentials, &pg_pool).await?;
+    let groups = find_duplicates(&pg_pool, query.fuzzy)
+        .await
+        .context("Failed to look for duplicate subscribers")?;
+    Ok(HttpResponse::Ok().json(DuplicateList { groups }))
+}
+
+/// Merge duplicate subscribers into one of them, which keeps their tags,
+/// history and preferences.
+#[tracing::instrument(
+    name = "Merge duplicate subscribers",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/subscribers/merge")]
+pub async fn merge_duplicate_subscribers(
+    body: web::Json<MergeBody>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, DeduplicationError> {
+    let user_id = authenticate(credentials, &pg_pool).await?;
+    if body.duplicates.is_empty() {
+        return Err(DeduplicationError::ValidationError(
+            "There must be at least one subscriber to merge.".into(),
+        ));
+    }
+    let merged = merge_subscribers(&pg_pool, user_id, body.subscriber_id, &body.duplicates).await?;
+    Ok(HttpResponse::Ok().json(merged))
+}
@@ -0,0 +1,115 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::encryption::FieldCipher;
+use crate::routes::error_chain_fmt;
+use crate::subscriber_search::{
+    FilterError, FoundSubscriber, SubscriberFilter, count_subscribers, find_subscribers,
+};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, post, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(thiserror::Error)]
+pub enum SearchError {
+    #[error(transparent)]
+    InvalidFilter(#[from] FilterError),
+    #[error("Pages start at 1 and hold up to {MAX_PAGE_SIZE} subscribers.")]
+    InvalidPage,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SearchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SearchError::InvalidFilter(_) | SearchError::InvalidPage => StatusCode::BAD_REQUEST,
+            SearchError::AuthError(e) => e.status_code(),
+            SearchError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SearchError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SearchRequest {
+    #[serde(default)]
+    filter: SubscriberFilter,
+    #[serde(default = "first_page")]
+    page: i64,
+    #[serde(default = "default_page_size")]
+    page_size: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+fn default_page_size() -> i64 {
+    DEFAULT_PAGE_SIZE
+}
+
+#[derive(serde::Serialize)]
+struct SearchResults {
+    subscribers: Vec<FoundSubscriber>,
+    page: i64,
+    page_size: i64,
+    /// Subscribers matching the filter, across every page.
+    total: i64,
+}
+
+/// Subscribers matching a filter, newest first, a page at a time.
+#[tracing::instrument(
+    name = "Search subscribers",
+    skip(pg_pool, cipher, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/admin/subscribers/search")]
+pub async fn search_subscribers(
+    body: web::Json<SearchRequest>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, SearchError> {
+    authenticate(credentials, &pg_pool).await?;
+    let SearchRequest {
+        filter,
+        page,
+        page_size,
+    } = body.into_inner();
+    filter.validate()?;
+    if page < 1 || !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(SearchError::InvalidPage);
+    }
+    let offset = (page - 1)
+        .checked_mul(page_size)
+        .ok_or(SearchError::InvalidPage)?;
+    let subscribers = find_subscribers(&pg_pool, &cipher, &filter, page_size, offset)
+        .await
+        .context("Failed to search subscribers")?;
+    let total = count_subscribers(&pg_pool, &filter)
+        .await
+        .context("Failed to count the matching subscribers")?;
+    Ok(HttpResponse::Ok().json(SearchResults {
+        subscribers,
+        page,
+        page_size,
+        total,
+    }))
+}
@@ -0,0 +1,623 @@
+use crate::blob_store::BlobStore;
+use crate::branding::Branding;
+use crate::domain::{SubscriberEmail, SubscriptionToken};
+use crate::encryption::FieldCipher;
+use crate::publishing::escape_html;
+use crate::repositories::subscribers::erase_subscriber;
+use crate::routes::error_chain_fmt;
+use crate::signing::{SignatureError, UrlSigner};
+use crate::telemetry::record_in_request_spans;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{
+    ACCEPT, CacheControl, CacheDirective, ContentDisposition, ContentType, DispositionParam,
+    DispositionType, LOCATION,
+};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Signature purpose of the links in email footers, which only show the
+/// subscription: they are forwarded along with the emails.
+pub const SUBSCRIBER_LINK_PURPOSE: &str = "subscriber";
+/// Signature purpose of the magic links emailed on request, which also let
+/// the subscriber change, export or delete their subscription.
+pub const MAGIC_LINK_PURPOSE: &str = "subscriber-session";
+/// Signature purpose of the links returned to API clients signing someone
+/// up, which only tell whether the subscription got confirmed.
+pub const SIGNUP_LINK_PURPOSE: &str = "signup";
+/// Links embedded in emails stay valid long after the email was sent.
+const STATUS_LINK_LIFETIME_DAYS: i64 = 365;
+/// Confirmation links expire after a day as well.
+const SIGNUP_LINK_LIFETIME_HOURS: i64 = 24;
+
+/// What a link to the subscription status page lets its holder do.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum LinkAccess {
+    /// The status of the subscription, and the masked address.
+    Signup,
+    View,
+    Manage,
+}
+
+/// View-only link to the subscription status page of a subscriber, for
+/// email footers.
+pub fn subscription_status_link(
+    base_url: &str,
+    url_signer: &UrlSigner,
+    subscriber_id: Uuid,
+) -> String {
+    signed_subscriber_link(
+        base_url,
+        url_signer,
+        SUBSCRIBER_LINK_PURPOSE,
+        subscriber_id,
+        Utc::now() + chrono::Duration::days(STATUS_LINK_LIFETIME_DAYS),
+    )
+}
+
+/// Link to the status of a new subscription, telling whether it was
+/// confirmed without disclosing anything else about the subscriber.
+pub fn signup_status_link(base_url: &str, url_signer: &UrlSigner, subscriber_id: Uuid) -> String {
+    signed_subscriber_link(
+        base_url,
+        url_signer,
+        SIGNUP_LINK_PURPOSE,
+        subscriber_id,
+        Utc::now() + chrono::Duration::hours(SIGNUP_LINK_LIFETIME_HOURS),
+    )
+}
+
+/// Link letting a subscriber manage their subscription, valid until
+/// `expires_at`.
+pub fn magic_link(
+    base_url: &str,
+    url_signer: &UrlSigner,
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> String {
+    signed_subscriber_link(
+        base_url,
+        url_signer,
+        MAGIC_LINK_PURPOSE,
+        subscriber_id,
+        expires_at,
+    )
+}
+
+fn signed_subscriber_link(
+    base_url: &str,
+    url_signer: &UrlSigner,
+    purpose: &str,
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> String {
+    let token = url_signer.sign(purpose, &subscriber_id.to_string(), expires_at);
+    format!(
+        "{}/subscriptions/status?token={}",
+        base_url.trim_end_matches('/'),
+        token
+    )
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriptionStatusError {
+    #[error("The link is invalid.")]
+    InvalidToken,
+    #[error("The link has expired.")]
+    ExpiredToken,
+    #[error(
+        "The link only shows your subscription, request one from /subscriptions/login to manage it."
+    )]
+    ViewOnlyToken,
+    #[error("There is no subscriber associated with the provided link.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<SignatureError> for SubscriptionStatusError {
+    fn from(e: SignatureError) -> Self {
+        match e {
+            SignatureError::Invalid => SubscriptionStatusError::InvalidToken,
+            SignatureError::Expired(_) => SubscriptionStatusError::ExpiredToken,
+        }
+    }
+}
+
+impl std::fmt::Debug for SubscriptionStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriptionStatusError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriptionStatusError::InvalidToken => StatusCode::UNAUTHORIZED,
+            SubscriptionStatusError::ExpiredToken => StatusCode::GONE,
+            SubscriptionStatusError::ViewOnlyToken => StatusCode::FORBIDDEN,
+            SubscriptionStatusError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            SubscriptionStatusError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).body(self.to_string())
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: SubscriptionToken,
+}
+
+impl Parameters {
+    /// The subscriber the link was signed for, and what it lets them do.
+    fn verify(
+        &self,
+        url_signer: &UrlSigner,
+    ) -> Result<(Uuid, LinkAccess), SubscriptionStatusError> {
+        let purposes = [
+            (MAGIC_LINK_PURPOSE, LinkAccess::Manage),
+            (SUBSCRIBER_LINK_PURPOSE, LinkAccess::View),
+            (SIGNUP_LINK_PURPOSE, LinkAccess::Signup),
+        ];
+        for (purpose, access) in purposes {
+            match url_signer.verify(purpose, self.token.expose_secret(), Utc::now()) {
+                Ok(payload) => {
+                    let subscriber_id = payload
+                        .parse::<Uuid>()
+                        .map_err(|_| SubscriptionStatusError::InvalidToken)?;
+                    return Ok((subscriber_id, access));
+                }
+                // The signature matched, the purpose is the right one.
+                Err(e @ SignatureError::Expired(_)) => return Err(e.into()),
+                Err(SignatureError::Invalid) => {}
+            }
+        }
+        Err(SubscriptionStatusError::InvalidToken)
+    }
+
+    /// The subscriber the link was signed for, if it lets them manage their
+    /// subscription.
+    fn managed_subscriber_id(
+        &self,
+        url_signer: &UrlSigner,
+    ) -> Result<Uuid, SubscriptionStatusError> {
+        match self.verify(url_signer)? {
+            (subscriber_id, LinkAccess::Manage) => Ok(subscriber_id),
+            (_, LinkAccess::View | LinkAccess::Signup) => {
+                Err(SubscriptionStatusError::ViewOnlyToken)
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionStatus {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+    preferences: Preferences,
+}
+
+/// All a signup link shows.
+#[derive(serde::Serialize)]
+struct SignupStatus {
+    id: Uuid,
+    status: String,
+    email: String,
+}
+
+#[derive(serde::Serialize)]
+struct Preferences {
+    region: Option<String>,
+    /// Opted out of open and click tracking.
+    do_not_track: bool,
+}
+
+/// Show a subscriber their own subscription, as JSON when asked for it and
+/// as a web page otherwise.
+#[tracing::instrument(
+    name = "Show a subscriber their subscription status",
+    skip(request, parameters, pg_pool, cipher, url_signer, branding),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[get("/subscriptions/status")]
+async fn show_subscription_status(
+    request: HttpRequest,
+    parameters: web::Query<Parameters>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    url_signer: web::Data<UrlSigner>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, SubscriptionStatusError> {
+    let (subscriber_id, access) = parameters.verify(&url_signer)?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    let status = get_subscription_status(&pg_pool, &cipher, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscription status")?
+        .ok_or(SubscriptionStatusError::UnknownSubscriber)?;
+
+    let wants_json = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+    let mut response = HttpResponse::Ok();
+    response.insert_header(CacheControl(vec![CacheDirective::NoStore]));
+    if access == LinkAccess::Signup {
+        let email = SubscriberEmail::try_from(status.email)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("A stored subscriber email is invalid")?
+            .masked();
+        let status = SignupStatus {
+            id: subscriber_id,
+            status: status.status,
+            email,
+        };
+        if wants_json {
+            return Ok(response.json(status));
+        }
+        return Ok(response
+            .content_type(ContentType::html())
+            .body(render_signup_status_page(&branding, &status)));
+    }
+    if wants_json {
+        return Ok(response.json(status));
+    }
+    Ok(response
+        .content_type(ContentType::html())
+        .body(render_status_page(
+            &branding,
+            &status,
+            &parameters.token,
+            access,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    /// Sent by the checkbox only when it is ticked.
+    do_not_track: Option<String>,
+}
+
+/// Save the preferences a subscriber set on their status page, then show
+/// the page again.
+#[tracing::instrument(
+    name = "Update a subscriber's preferences",
+    skip(parameters, form, pg_pool, url_signer),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[post("/subscriptions/status")]
+async fn update_subscription_preferences(
+    parameters: web::Query<Parameters>,
+    form: web::Form<PreferencesFormData>,
+    pg_pool: web::Data<PgPool>,
+    url_signer: web::Data<UrlSigner>,
+) -> Result<HttpResponse, SubscriptionStatusError> {
+    let subscriber_id = parameters.managed_subscriber_id(&url_signer)?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    if !store_do_not_track(&pg_pool, subscriber_id, form.do_not_track.is_some())
+        .await
+        .context("Failed to store the subscriber's preferences")?
+    {
+        return Err(SubscriptionStatusError::UnknownSubscriber);
+    }
+    Ok(HttpResponse::SeeOther()
+        .insert_header((
+            LOCATION,
+            format!(
+                "/subscriptions/status?token={}",
+                parameters.token.expose_secret()
+            ),
+        ))
+        .finish())
+}
+
+/// Delete the subscription and everything about the subscriber, at their
+/// own request.
+#[tracing::instrument(
+    name = "Erase a subscriber on their own request",
+    skip(parameters, pg_pool, blob_store, url_signer, branding),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[post("/subscriptions/delete")]
+async fn delete_own_subscription(
+    parameters: web::Query<Parameters>,
+    pg_pool: web::Data<PgPool>,
+    blob_store: web::Data<dyn BlobStore>,
+    url_signer: web::Data<UrlSigner>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, SubscriptionStatusError> {
+    let subscriber_id = parameters.managed_subscriber_id(&url_signer)?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    if !erase_subscriber(&pg_pool, blob_store.as_ref(), subscriber_id, None).await? {
+        return Err(SubscriptionStatusError::UnknownSubscriber);
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .content_type(ContentType::html())
+        .body(branding.page(
+            "Your data is deleted",
+            "<p>We no longer hold anything about you, and you will not receive any more newsletters from us.</p>",
+        )))
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    #[serde(flatten)]
+    link: Parameters,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberData {
+    #[serde(flatten)]
+    subscription: SubscriptionStatus,
+    deliveries: Vec<Delivery>,
+}
+
+#[derive(serde::Serialize)]
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    delivered_at: DateTime<Utc>,
+}
+
+/// Everything stored about a subscriber, for them to download: as JSON, or
+/// as CSV with a row per newsletter delivered to them.
+#[tracing::instrument(
+    name = "Export a subscriber's data",
+    skip(parameters, pg_pool, cipher, url_signer),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[get("/subscriptions/export")]
+async fn export_subscriber_data(
+    parameters: web::Query<ExportParameters>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    url_signer: web::Data<UrlSigner>,
+) -> Result<HttpResponse, SubscriptionStatusError> {
+    let subscriber_id = parameters.link.managed_subscriber_id(&url_signer)?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    let subscription = get_subscription_status(&pg_pool, &cipher, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscription status")?
+        .ok_or(SubscriptionStatusError::UnknownSubscriber)?;
+    let deliveries = get_deliveries(&pg_pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the deliveries of a subscriber")?;
+    let data = SubscriberData {
+        subscription,
+        deliveries,
+    };
+
+    let extension = match parameters.format {
+        ExportFormat::Json => "json",
+        ExportFormat::Csv => "csv",
+    };
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscriber-data.{}",
+                extension
+            ))],
+        });
+    Ok(match parameters.format {
+        ExportFormat::Json => response.json(data),
+        ExportFormat::Csv => response
+            .content_type("text/csv; charset=utf-8")
+            .body(render_csv(&data)),
+    })
+}
+
+const CSV_HEADER: &str = "email,name,status,subscribed_at,region,do_not_track,tags,newsletter_issue_id,title,delivered_at";
+
+/// A row per delivery repeating the subscription, a single one without
+/// deliveries.
+fn render_csv(data: &SubscriberData) -> String {
+    let subscription = &data.subscription;
+    let columns = [
+        subscription.email.clone(),
+        subscription.name.clone(),
+        subscription.status.clone(),
+        subscription.subscribed_at.to_rfc3339(),
+        subscription.preferences.region.clone().unwrap_or_default(),
+        subscription.preferences.do_not_track.to_string(),
+        subscription.tags.join(" "),
+    ]
+    .iter()
+    .map(|value| csv_field(value))
+    .collect::<Vec<_>>()
+    .join(",");
+    let mut csv = format!("{}\r\n", CSV_HEADER);
+    if data.deliveries.is_empty() {
+        csv.push_str(&format!("{},,,\r\n", columns));
+    }
+    for delivery in &data.deliveries {
+        csv.push_str(&format!(
+            "{},{},{},{}\r\n",
+            columns,
+            delivery.newsletter_issue_id,
+            csv_field(&delivery.title),
+            delivery.delivered_at.to_rfc3339()
+        ));
+    }
+    csv
+}
+
+/// `value` quoted if need be, and kept from being read as a formula by
+/// spreadsheets.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_owned()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn render_signup_status_page(branding: &Branding, status: &SignupStatus) -> String {
+    let content = format!(
+        r#"<dl>
+<dt>Email</dt><dd>{}</dd>
+<dt>Status</dt><dd>{}</dd>
+</dl>"#,
+        escape_html(&status.email),
+        escape_html(&status.status.replace('_', " ")),
+    );
+    branding.page("Your subscription", &content)
+}
+
+fn render_status_page(
+    branding: &Branding,
+    status: &SubscriptionStatus,
+    token: &SubscriptionToken,
+    access: LinkAccess,
+) -> String {
+    let tags = if status.tags.is_empty() {
+        "none".to_owned()
+    } else {
+        escape_html(&status.tags.join(", "))
+    };
+    let actions = match access {
+        LinkAccess::Signup | LinkAccess::View => r#"<p><a href="/subscriptions/login">Email me a link</a> to change my preferences, download or delete my data.</p>"#.to_owned(),
+        LinkAccess::Manage => format!(
+            r#"<form action="/subscriptions/status?token={}" method="post">
+<label><input type="checkbox" name="do_not_track"{}> Do not track when I open emails or follow their links</label>
+<button type="submit">Save preferences</button>
+</form>
+<p><a href="/subscriptions/export?token={}">Download my data</a> (<a href="/subscriptions/export?token={}&amp;format=csv">as CSV</a>)</p>
+<form action="/subscriptions/delete?token={}" method="post">
+<button type="submit">Delete my subscription and data</button>
+</form>"#,
+            escape_html(token.expose_secret()),
+            if status.preferences.do_not_track {
+                " checked"
+            } else {
+                ""
+            },
+            escape_html(token.expose_secret()),
+            escape_html(token.expose_secret()),
+            escape_html(token.expose_secret()),
+        ),
+    };
+    let content = format!(
+        r#"<dl>
+<dt>Email</dt><dd>{}</dd>
+<dt>Name</dt><dd>{}</dd>
+<dt>Status</dt><dd>{}</dd>
+<dt>Subscribed on</dt><dd>{}</dd>
+<dt>Tags</dt><dd>{}</dd>
+<dt>Region</dt><dd>{}</dd>
+</dl>
+{}"#,
+        escape_html(&status.email),
+        escape_html(&status.name),
+        escape_html(&status.status.replace('_', " ")),
+        status.subscribed_at.format("%B %-d, %Y"),
+        tags,
+        escape_html(status.preferences.region.as_deref().unwrap_or("default")),
+        actions,
+    );
+    branding.page("Your subscription", &content)
+}
+
+#[tracing::instrument(name = "Get subscription status", skip(pg_pool, cipher))]
+async fn get_subscription_status(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriptionStatus>, anyhow::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT
+            s.email, s.name, s.status, s.subscribed_at, s.region, s.do_not_track,
+            COALESCE(
+                array_agg(t.tag ORDER BY t.tag) FILTER (WHERE t.tag IS NOT NULL),
+                '{}'
+            ) AS "tags!"
+        FROM subscriptions s
+        LEFT JOIN subscriber_tags t ON t.subscriber_id = s.id
+        WHERE s.id = $1
+        GROUP BY s.id
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(SubscriptionStatus {
+        email: r.email,
+        name: cipher.decrypt(r.name)?,
+        status: r.status,
+        subscribed_at: r.subscribed_at,
+        tags: r.tags,
+        preferences: Preferences {
+            region: r.region,
+            do_not_track: r.do_not_track,
+        },
+    }))
+}
+
+/// Newsletters delivered to a subscriber, oldest first.
+#[tracing::instrument(name = "Get the deliveries of a subscriber", skip(pg_pool))]
+async fn get_deliveries(
+    pg_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.delivered_at
+        FROM newsletter_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.subscriber_id = $1
+        ORDER BY d.delivered_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+/// Return whether the subscriber exists.
+#[tracing::instrument(name = "Store a subscriber's tracking preference", skip(pg_pool))]
+async fn store_do_not_track(
+    pg_pool: &PgPool,
+    subscriber_id: Uuid,
+    do_not_track: bool,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"UPDATE subscriptions SET do_not_track = $2 WHERE id = $1"#,
+        subscriber_id,
+        do_not_track,
+    )
+    .execute(pg_pool)
+    .await?
+    .rows_affected();
+    Ok(updated > 0)
+}
@@ -1,163 +1,686 @@
 use crate::EmailClient;
-use crate::domain::NewSubscriber;
-use crate::startup::ApplicationBaseUrl;
+use crate::complaints::is_suppressed;
+use crate::configuration::{
+    ConfirmationEmailSettings, EmailTemplatesSettings, InvitationSettings, SignupAnomalySettings,
+};
+use crate::consent::{RequestOrigin, record_confirmation_email, record_signup};
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberRules, SubscriberTag, SubscriptionToken,
+};
+use crate::email_client::EmailClientError;
+use crate::encryption::FieldCipher;
+use crate::extensions::{DomainEvents, Subscribed};
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::invitations::redeem_invitation;
+use crate::locale::Locale;
+use crate::publishing::escape_html;
+use crate::routes::subscription_status::{signup_status_link, subscription_status_link};
+use crate::routes::subscriptions_unsubscribe::unsubscribe_link;
+use crate::signing::UrlSigner;
+use crate::signup_anomalies::{SignupAnomaly, detect_signup_burst, signup_network};
+use crate::startup::{ApplicationBaseUrl, LinkBaseUrl};
+use crate::telemetry::record_in_request_spans;
+use crate::templates::EmailTemplates;
 use actix_web::http::StatusCode;
-use actix_web::{HttpResponse, ResponseError, post, web};
+use actix_web::http::header::{CONTENT_LANGUAGE, LOCATION};
+use actix_web::web::Either;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, post, web};
 use anyhow::Context;
-use chrono::Utc;
-use rand::Rng;
-use rand::distributions::Alphanumeric;
-use reqwest;
+use chrono::{DateTime, Utc};
 use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
 use uuid::Uuid;
 
+/// How long a confirmation email written to the outbox is left to the
+/// request sending it, before the delivery worker relays it.
+const OUTBOX_GRACE: Duration = Duration::from_secs(300);
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    pub region: Option<String>,
+    /// A list in JSON bodies, comma separated in forms.
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<String>,
+    /// Required when `invitations.required` is set.
+    pub invite_code: Option<String>,
 }
 
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<String>),
+        Separated(String),
+    }
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        Tags::List(tags) => tags,
+        Tags::Separated(tags) => tags
+            .split(',')
+            .filter(|tag| !tag.trim().is_empty())
+            .map(str::to_owned)
+            .collect(),
+    })
+}
+
+/// Returned to JSON requests, the subscription can then be followed through
+/// the status link in the `Location` header.
+#[derive(serde::Serialize)]
+struct CreatedSubscription {
+    id: Uuid,
+    status: &'static str,
+    email: String,
+}
+
+/// Subscribe from the HTML form, answered with an empty `200 OK`, or with a
+/// JSON body, answered with `201 Created` and the new subscription.
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pg_pool, email_client, base_url),
-    fields(subscriber_email = %form.email, subscriber_name = %form.name)
+    skip(
+        request,
+        body,
+        pg_pool,
+        email_client,
+        base_url,
+        link_base_url,
+        email_templates,
+        anomaly_settings,
+        subscriber_rules,
+        invitation_settings,
+        confirmation_email_settings,
+        cipher,
+        feature_flags,
+        url_signer,
+        events
+    ),
+    fields(subscriber_email = tracing::field::Empty, subscriber_id = tracing::field::Empty)
 )]
 #[post("/subscriptions")]
+#[allow(clippy::too_many_arguments)]
 async fn subscribe(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    body: Either<web::Json<FormData>, web::Form<FormData>>,
     pg_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    email_templates: web::Data<EmailTemplates>,
+    anomaly_settings: web::Data<SignupAnomalySettings>,
+    subscriber_rules: web::Data<SubscriberRules>,
+    invitation_settings: web::Data<InvitationSettings>,
+    confirmation_email_settings: web::Data<ConfirmationEmailSettings>,
+    cipher: web::Data<FieldCipher>,
+    feature_flags: web::Data<FeatureFlags>,
+    url_signer: web::Data<UrlSigner>,
+    events: web::Data<DomainEvents>,
 ) -> Result<HttpResponse, SubscribeError> {
+    let (form, wants_json) = match body {
+        Either::Left(json) => (json.into_inner(), true),
+        Either::Right(form) => (form.into_inner(), false),
+    };
+
     let mut transaction = pg_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber: NewSubscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let invite_code = form.invite_code.clone();
+    let subscriber = NewSubscriber::parse(form, &subscriber_rules).map_err(|e| {
+        let locale = Locale::from_request(&request);
+        SubscribeError::ValidationError {
+            message: e.localize(locale),
+            locale,
+        }
+    })?;
+    tracing::Span::current().record(
+        "subscriber_email",
+        tracing::field::display(subscriber.email.masked()),
+    );
 
-    let subscriber_id = insert_subscriber(&mut transaction, &subscriber)
+    // Redeemed along with storing the subscription, which gives the
+    // invitation back if it fails.
+    if invitation_settings.required {
+        let redeemed = match &invite_code {
+            Some(code) => redeem_invitation(&mut transaction, code)
+                .await
+                .context("Failed to redeem an invitation")?,
+            None => false,
+        };
+        if !redeemed {
+            return Err(SubscribeError::InvitationRequired);
+        }
+    }
+
+    let network = request.peer_addr().map(|a| signup_network(a.ip()));
+    let anomaly = detect_signup_burst(
+        &mut transaction,
+        &anomaly_settings,
+        network.as_deref(),
+        &subscriber.email,
+        Utc::now(),
+    )
+    .await
+    .context("Failed to look for a burst of signups")?;
+
+    let subscriber_id = insert_subscriber(
+        &mut transaction,
+        &cipher,
+        &subscriber,
+        network.as_deref(),
+        anomaly.as_ref(),
+    )
+    .await
+    .context("Failed to insert new subscriber in the database")?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    tag_subscriber(&mut transaction, subscriber_id, &subscriber.tags)
         .await
-        .context("Failed to insert new subscriber in the database")?;
+        .context("Failed to store the tags of a new subscriber")?;
+    let channel = if wants_json { "json" } else { "form" };
+    record_signup(
+        &mut transaction,
+        subscriber_id,
+        channel,
+        &RequestOrigin::from_request(&request),
+    )
+    .await
+    .context("Failed to record the signup of a new subscriber")?;
+
+    // Quarantined subscriptions are reported as pending as well.
+    let response = if wants_json {
+        HttpResponse::Created()
+            .insert_header((
+                LOCATION,
+                signup_status_link(&base_url.0, &url_signer, subscriber_id),
+            ))
+            .json(CreatedSubscription {
+                id: subscriber_id,
+                status: "pending_confirmation",
+                email: subscriber.email.masked(),
+            })
+    } else {
+        HttpResponse::Ok().finish()
+    };
+
+    if let Some(anomaly) = anomaly {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a new subscriber")?;
+        // The response must not tell an attacker their signups are held.
+        tracing::warn!(%anomaly, "Quarantining a subscription taking part in a signup burst");
+        return Ok(response);
+    }
+    let subscribed = Subscribed {
+        subscriber_id,
+        email: subscriber.email.as_ref().to_owned(),
+        name: subscriber.name.as_ref().to_owned(),
+    };
 
-    let subscriber_token = generate_subscription_token();
+    let subscriber_token = SubscriptionToken::generate();
 
-    store_token(&mut transaction, subscriber_id, &subscriber_token)
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        &subscriber_token,
+        confirmation_email_settings.token_ttl,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber")?;
+
+    if !claim_confirmation_email(
+        &mut transaction,
+        &confirmation_email_settings,
+        &subscriber.email,
+    )
+    .await
+    .context("Failed to check the confirmation emails sent to a new subscriber")?
+    {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a new subscriber")?;
+        events.subscribed(subscribed);
+        return Ok(response);
+    }
+    let flags = feature_flags
+        .load(&pg_pool)
         .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
+        .context("Failed to load the feature flags")?;
+    let paused = flags.is_enabled(PAUSE_DELIVERIES);
+    write_confirmation_email_to_outbox(
+        &mut transaction,
+        subscriber_id,
+        &subscriber_token,
+        outbox_relay_after(paused),
+    )
+    .await
+    .context("Failed to write the confirmation email of a new subscriber to the outbox")?;
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
+    events.subscribed(subscribed);
 
-    let confirmation_link = create_confirmation_link(&base_url.0, &subscriber_token)
-        .context("Failed to create a confirmation link for a new subscriber")?;
-
-    send_confirm_email(&email_client, subscriber, confirmation_link)
-        .await
-        .context("Failed to send the confirmation email")?;
-
-    Ok(HttpResponse::Ok().finish())
-}
-/// Generate a random 25-characters-long case-sensitive subscription token.
-fn generate_subscription_token() -> String {
-    let mut rng = rand::thread_rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
+    if !paused {
+        let links = SubscriberLinks {
+            base_url: &base_url.0,
+            link_base_url: &link_base_url.0,
+            url_signer: &url_signer,
+        };
+        send_from_outbox(
+            &pg_pool,
+            &email_client,
+            &*email_templates.current().await,
+            &links,
+            subscriber_id,
+            subscriber,
+            &subscriber_token,
+        )
+        .await?;
+    }
+    Ok(response)
 }
-
+/// Subscriptions taking part in an `anomaly` are stored as `quarantined`.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(pg_connection, subscriber)
+    skip(pg_connection, cipher, subscriber, anomaly)
 )]
 async fn insert_subscriber(
     pg_connection: &mut PgConnection,
+    cipher: &FieldCipher,
     subscriber: &NewSubscriber,
+    signup_network: Option<&str>,
+    anomaly: Option<&SignupAnomaly>,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
+    let status = match anomaly {
+        Some(_) => "quarantined",
+        None => "pending_confirmation",
+    };
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, region, signup_network, quarantine_reason
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         "#,
         subscriber_id,
         subscriber.email.as_ref(),
-        subscriber.name.as_ref(),
+        cipher.encrypt(subscriber.name.as_ref()),
         Utc::now(),
-        "pending_confirmation"
+        status,
+        subscriber.region.as_ref().map(|r| r.as_ref()),
+        signup_network,
+        anomaly.map(|a| a.to_string()),
     )
     .execute(pg_connection)
     .await?;
     Ok(subscriber_id)
 }
 
-#[tracing::instrument(
-    name = "Store subscription token in the database",
-    skip(subscription_token, pg_connection)
-)]
+#[tracing::instrument(name = "Store the tags of a new subscriber", skip(pg_connection, tags))]
+async fn tag_subscriber(
+    pg_connection: &mut PgConnection,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, UNNEST($2::text[])
+        "#,
+        subscriber_id,
+        &tags as &[&str],
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(())
+}
+
+/// Store a confirmation token, valid for `ttl`.
+#[tracing::instrument(name = "Store subscription token in the database", skip(pg_connection))]
 pub async fn store_token(
     pg_connection: &mut PgConnection,
     subscriber_id: Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
+    ttl: Duration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, expires_at)
+        VALUES ($1, $2, $3)"#,
+        subscription_token.expose_secret(),
+        subscriber_id,
+        token_expiry(ttl),
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(())
+}
+
+/// Make a token valid for `ttl` from now, for confirmation emails sent
+/// after being queued for a while.
+#[tracing::instrument(name = "Extend a subscription token", skip(pg_connection))]
+pub async fn extend_token(
+    pg_connection: &mut PgConnection,
+    subscription_token: &SubscriptionToken,
+    ttl: Duration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens SET expires_at = GREATEST(expires_at, $2)
+        WHERE subscription_token = $1
+        "#,
+        subscription_token.expose_secret(),
+        token_expiry(ttl),
+    )
+    .execute(pg_connection)
+    .await?;
+    Ok(())
+}
+
+/// When a token issued now for `ttl` expires.
+pub fn token_expiry(ttl: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(ttl)
+        .ok()
+        .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+/// Record a confirmation email about to be sent to `recipient`, unless it
+/// already received as many as allowed within the window or was suppressed.
+///
+/// The cap holds whoever signs the address up, so that the subscription form
+/// cannot be used to flood someone's inbox.
+///
+/// Meant to run in the transaction writing the email to the outbox, so that
+/// the claim is given back if it is rolled back.
+#[tracing::instrument(name = "Claim a confirmation email", skip(pg_connection, settings))]
+pub async fn claim_confirmation_email(
+    pg_connection: &mut PgConnection,
+    settings: &ConfirmationEmailSettings,
+    recipient: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let recipient = recipient.as_ref().to_lowercase();
+    let since = chrono::Duration::from_std(settings.window)
+        .ok()
+        .and_then(|window| Utc::now().checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    // Concurrent claims for the same recipient must not both see room left.
+    sqlx::query!(
+        "SELECT pg_advisory_xact_lock(hashtext($1))",
+        format!("confirmation_emails:{}", recipient),
+    )
+    .execute(&mut *pg_connection)
+    .await?;
+    if is_suppressed(pg_connection, &recipient).await? {
+        tracing::warn!("Not sending a confirmation email, the recipient reported us as spam");
+        return Ok(false);
+    }
+    let sent = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "sent!" FROM confirmation_emails
+        WHERE recipient = $1 AND sent_at > $2
+        "#,
+        recipient,
+        since,
+    )
+    .fetch_one(&mut *pg_connection)
+    .await?
+    .sent;
+    if sent >= settings.max_per_recipient.into() {
+        tracing::warn!(
+            sent,
+            "Not sending a confirmation email, the recipient received too many recently"
+        );
+        return Ok(false);
+    }
+    sqlx::query!(
+        "INSERT INTO confirmation_emails (recipient) VALUES ($1)",
+        recipient,
+    )
+    .execute(&mut *pg_connection)
+    .await?;
+    Ok(true)
+}
+
+/// Write a confirmation email to the outbox, in the transaction storing the
+/// subscription, for the [`DeliveryWorker`](crate::delivery::DeliveryWorker)
+/// to relay once `relay_after` is over unless it was sent and removed by
+/// then.
+#[tracing::instrument(name = "Write a confirmation email to the outbox", skip(pg_connection))]
+pub async fn write_confirmation_email_to_outbox(
+    pg_connection: &mut PgConnection,
+    subscriber_id: Uuid,
+    subscription_token: &SubscriptionToken,
+    relay_after: Duration,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
-        subscription_token,
-        subscriber_id
+        r#"
+        INSERT INTO email_outbox (subscriber_id, subscription_token, enqueued_at, next_attempt_at)
+        VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+        ON CONFLICT (subscriber_id) DO UPDATE
+        SET subscription_token = EXCLUDED.subscription_token,
+            next_attempt_at = EXCLUDED.next_attempt_at
+        "#,
+        subscriber_id,
+        subscription_token.expose_secret(),
+        relay_after.as_secs_f64(),
     )
     .execute(pg_connection)
     .await?;
     Ok(())
 }
 
+#[tracing::instrument(name = "Remove a confirmation email from the outbox", skip(pg_pool))]
+pub async fn remove_from_outbox(pg_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM email_outbox WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// How long the outbox holds a confirmation email back. It is sent straight
+/// away unless deliveries are paused, the outbox only relays it if the
+/// request does not get to it.
+pub fn outbox_relay_after(paused: bool) -> Duration {
+    if paused { Duration::ZERO } else { OUTBOX_GRACE }
+}
+
+/// Where the links of the emails sent to a subscriber point.
+pub struct SubscriberLinks<'a> {
+    /// Base of the confirmation link.
+    pub base_url: &'a str,
+    /// Base of the footer links, see [`LinkBaseUrl`].
+    pub link_base_url: &'a str,
+    pub url_signer: &'a UrlSigner,
+}
+
+/// Send a confirmation email once the transaction writing it to the outbox
+/// is committed, removing it from the outbox. If sending fails the
+/// [`DeliveryWorker`](crate::delivery::DeliveryWorker) retries it rather
+/// than having the subscriber ask again.
+#[tracing::instrument(
+    name = "Send a confirmation email from the outbox",
+    skip(
+        pg_pool,
+        email_client,
+        templates,
+        links,
+        subscriber,
+        subscription_token
+    )
+)]
+pub async fn send_from_outbox(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    templates: &EmailTemplatesSettings,
+    links: &SubscriberLinks<'_>,
+    subscriber_id: Uuid,
+    subscriber: NewSubscriber,
+    subscription_token: &SubscriptionToken,
+) -> Result<(), anyhow::Error> {
+    let confirmation_link = create_confirmation_link(links.base_url, subscription_token)
+        .context("Failed to create a confirmation link")?;
+    let outcome = send_confirm_email(
+        email_client,
+        templates,
+        links,
+        subscriber_id,
+        subscriber,
+        confirmation_link,
+    )
+    .await;
+    match outcome {
+        // The email is out: failing to tidy up is only logged, an error
+        // would fail a request that did its job. It leaves the outbox first,
+        // so that no failure gets it relayed a second time.
+        Ok(message_id) => {
+            if let Err(e) = remove_from_outbox(pg_pool, subscriber_id).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to remove a sent confirmation email from the outbox",
+                );
+            }
+            if let Err(e) =
+                record_confirmation_email(pg_pool, subscriber_id, message_id.as_deref()).await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to record a confirmation email",
+                );
+            }
+        }
+        // Left in the outbox as it is, for the worker to relay it once
+        // deliveries resume.
+        Err(EmailClientError::Paused | EmailClientError::PauseUnknown(_)) => {}
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to send the confirmation email, queueing it for a retry",
+            );
+            queue_failed_confirmation_email(
+                pg_pool,
+                subscriber_id,
+                subscription_token,
+                &e.to_string(),
+            )
+            .await
+            .context("Failed to queue a confirmation email for a retry")?;
+        }
+    }
+    Ok(())
+}
+
+/// Queue a confirmation email that failed to send, for the
+/// [`DeliveryWorker`](crate::delivery::DeliveryWorker) to retry.
+#[tracing::instrument(name = "Queue a failed confirmation email", skip(pg_pool))]
+pub async fn queue_failed_confirmation_email(
+    pg_pool: &PgPool,
+    subscriber_id: Uuid,
+    subscription_token: &SubscriptionToken,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (
+            subscriber_id, subscription_token, enqueued_at, attempts, last_error
+        )
+        VALUES ($1, $2, now(), 1, $3)
+        ON CONFLICT (subscriber_id) DO UPDATE
+        SET subscription_token = EXCLUDED.subscription_token,
+            attempts = email_outbox.attempts + 1,
+            last_error = EXCLUDED.last_error,
+            next_attempt_at = EXCLUDED.next_attempt_at
+        "#,
+        subscriber_id,
+        subscription_token.expose_secret(),
+        error,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Create new confirmation link for new subscriber",
     skip(base_url)
 )]
-fn create_confirmation_link(
+pub fn create_confirmation_link(
     base_url: &str,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
 ) -> Result<url::Url, url::ParseError> {
     let base = url::Url::parse(base_url)?;
     let mut url = base.join("subscriptions/confirm")?;
     url.query_pairs_mut()
-        .append_pair("subscription_token", subscription_token);
+        .append_pair("subscription_token", subscription_token.expose_secret());
     Ok(url)
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, subscriber, confirmation_link)
+    skip(email_client, templates, links, subscriber, confirmation_link),
+    fields(message_id = tracing::field::Empty)
 )]
-async fn send_confirm_email(
+pub async fn send_confirm_email(
     email_client: &EmailClient,
+    templates: &EmailTemplatesSettings,
+    links: &SubscriberLinks<'_>,
+    subscriber_id: Uuid,
     subscriber: NewSubscriber,
     confirmation_link: url::Url,
-) -> Result<(), reqwest::Error> {
-    let html = format!(
-        "Welcome to our newsletter!<br />\
-                Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-    let text = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
+) -> Result<Option<String>, EmailClientError> {
+    let template = &templates.confirmation;
+    let name = subscriber.name.as_ref();
+    let escaped_name = escape_html(name);
+    let variables = [
+        ("confirmation_link", confirmation_link.as_str()),
+        ("name", name),
+    ];
+    let html = template.render_html(&[
+        ("confirmation_link", confirmation_link.as_str()),
+        ("name", escaped_name.as_str()),
+    ]);
+    let text = template.render_text(&variables);
+    let status_link =
+        subscription_status_link(links.link_base_url, links.url_signer, subscriber_id);
+    let unsubscribe_link = unsubscribe_link(links.link_base_url, links.url_signer, subscriber_id);
+    let (html, text) = templates
+        .footer
+        .append(&html, &text, &status_link, &unsubscribe_link);
 
-    email_client
-        .send_email(&subscriber.email, "Welcome", &html, &text)
+    let message_id = email_client
+        .send_email_in_region(
+            subscriber.region.as_ref(),
+            &subscriber.email,
+            &template.render_subject(&variables),
+            &html,
+            &text,
+        )
         .await?;
-    Ok(())
+    tracing::Span::current().record("message_id", tracing::field::debug(&message_id));
+    Ok(message_id)
 }
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    #[error("{message}")]
+    ValidationError { message: String, locale: Locale },
+    #[error("A valid invite code is required to subscribe.")]
+    InvitationRequired,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -171,10 +694,19 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            SubscribeError::InvitationRequired => StatusCode::FORBIDDEN,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let SubscribeError::ValidationError { locale, .. } = self {
+            response.insert_header((CONTENT_LANGUAGE, locale.tag()));
+        }
+        response.body(self.to_string())
+    }
 }
 
 pub fn error_chain_fmt(
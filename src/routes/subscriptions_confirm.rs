@@ -1,14 +1,35 @@
+use crate::EmailClient;
+use crate::branding::Branding;
+use crate::configuration::ConfirmationEmailSettings;
+use crate::consent::{RequestOrigin, record_confirmation};
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriberRegion, SubscriptionToken,
+};
+use crate::encryption::FieldCipher;
+use crate::extensions::{Confirmed, DomainEvents};
+use crate::feature_flags::{FeatureFlags, PAUSE_DELIVERIES};
+use crate::locale::{Locale, LocalizedError, Message};
 use crate::routes::error_chain_fmt;
+use crate::routes::subscriptions::{
+    SubscriberLinks, claim_confirmation_email, outbox_relay_after, send_from_outbox, store_token,
+    write_confirmation_email_to_outbox,
+};
+use crate::signing::UrlSigner;
+use crate::startup::{ApplicationBaseUrl, LinkBaseUrl};
+use crate::telemetry::record_in_request_spans;
+use crate::templates::EmailTemplates;
 use actix_web::http::StatusCode;
-use actix_web::{HttpResponse, ResponseError, get, web};
+use actix_web::http::header::{CONTENT_LANGUAGE, ContentType};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, get, post, web};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use sqlx::PgPool;
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
 pub struct ConfirmRequest {
-    subscription_token: String,
+    subscription_token: SubscriptionToken,
 }
 
 #[derive(thiserror::Error)]
@@ -17,6 +38,8 @@ pub enum SubscriptionConfirmError {
     UnexpectedError(#[from] anyhow::Error),
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error("The confirmation link has expired, please ask for a new one.")]
+    ExpiredToken,
 }
 
 impl std::fmt::Debug for SubscriptionConfirmError {
@@ -30,33 +53,55 @@ impl ResponseError for SubscriptionConfirmError {
         match self {
             SubscriptionConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             SubscriptionConfirmError::UnknownToken => StatusCode::UNAUTHORIZED,
+            SubscriptionConfirmError::ExpiredToken => StatusCode::GONE,
         }
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(confirm_request, pg_pool))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(request, confirm_request, pg_pool, branding, events)
+)]
 #[get("/subscriptions/confirm")]
 pub async fn confirm(
+    request: HttpRequest,
     confirm_request: web::Query<ConfirmRequest>,
     pg_pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+    events: web::Data<DomainEvents>,
 ) -> Result<HttpResponse, SubscriptionConfirmError> {
-    let id = get_subscriber_id_from_token(&pg_pool, &confirm_request.subscription_token)
-        .await
-        .context(format!(
-            "Failed to retrieve the subscriber id associated with the provided token {}",
-            confirm_request.subscription_token
-        ))?
-        .ok_or(SubscriptionConfirmError::UnknownToken)?;
+    let (id, expires_at) =
+        get_subscriber_id_from_token(&pg_pool, &confirm_request.subscription_token)
+            .await
+            .context("Failed to retrieve the subscriber id associated with the provided token")?
+            .ok_or(SubscriptionConfirmError::UnknownToken)?;
+    if expires_at <= Utc::now() {
+        return Err(SubscriptionConfirmError::ExpiredToken);
+    }
     confirm_subscriber(&pg_pool, id)
         .await
         .context("Failed to update the subscriber status to `confirmed`.")?;
-    Ok(HttpResponse::Ok().finish())
+    record_confirmation(&pg_pool, id, &RequestOrigin::from_request(&request))
+        .await
+        .context("Failed to record the confirmation of a subscriber")?;
+    events.confirmed(Confirmed { subscriber_id: id });
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        branding.page(
+            "You are subscribed",
+            "<p>Thank you for confirming your subscription, the next issue will land in your inbox.</p>",
+        ),
+    ))
 }
 
+/// Only a pending subscriber is confirmed: following an old link must not
+/// bring back someone who unsubscribed or was quarantined since.
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pg_pool))]
 pub async fn confirm_subscriber(pg_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscriber_id,
     )
     .execute(pg_pool)
@@ -64,19 +109,209 @@ pub async fn confirm_subscriber(pg_pool: &PgPool, subscriber_id: Uuid) -> Result
     Ok(())
 }
 
-#[tracing::instrument(
-    name = "Get subscriber_id from token",
-    skip(subscription_token, pg_pool)
-)]
+/// The subscriber a token was issued to, and when the token expires.
+#[tracing::instrument(name = "Get subscriber_id from token", skip(pg_pool))]
 pub async fn get_subscriber_id_from_token(
     pg_pool: &PgPool,
-    subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<(Uuid, DateTime<Utc>)>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token,
+        r#"
+        SELECT subscriber_id, expires_at FROM subscription_tokens
+        WHERE subscription_token = $1
+        "#,
+        subscription_token.expose_secret(),
     )
     .fetch_optional(pg_pool)
     .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.expires_at)))
+}
+
+#[derive(thiserror::Error)]
+pub enum ResendConfirmationError {
+    #[error("{message}")]
+    ValidationError { message: String, locale: Locale },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResendConfirmationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ResendConfirmationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ResendConfirmationError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            ResendConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let ResendConfirmationError::ValidationError { locale, .. } = self {
+            response.insert_header((CONTENT_LANGUAGE, locale.tag()));
+        }
+        response.body(self.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ResendConfirmationFormData {
+    email: String,
+}
+
+/// Email a fresh confirmation link to a subscription still waiting to be
+/// confirmed, e.g. once the first link expired.
+///
+/// The response is the same whatever the state of the address, so that the
+/// form cannot be used to find out who signed up.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(
+        request,
+        form,
+        pg_pool,
+        cipher,
+        email_client,
+        base_url,
+        link_base_url,
+        url_signer,
+        email_templates,
+        confirmation_email_settings,
+        feature_flags,
+        branding
+    ),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[post("/subscriptions/resend_confirmation")]
+#[allow(clippy::too_many_arguments)]
+pub async fn resend_confirmation(
+    request: HttpRequest,
+    form: web::Form<ResendConfirmationFormData>,
+    pg_pool: web::Data<PgPool>,
+    cipher: web::Data<FieldCipher>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    link_base_url: web::Data<LinkBaseUrl>,
+    url_signer: web::Data<UrlSigner>,
+    email_templates: web::Data<EmailTemplates>,
+    confirmation_email_settings: web::Data<ConfirmationEmailSettings>,
+    feature_flags: web::Data<FeatureFlags>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, ResendConfirmationError> {
+    let email = SubscriberEmail::try_from(form.0.email.clone()).map_err(|_| {
+        let locale = Locale::from_request(&request);
+        ResendConfirmationError::ValidationError {
+            message: LocalizedError::new(Message::InvalidEmail, form.0.email).localize(locale),
+            locale,
+        }
+    })?;
+    let response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(branding.page(
+        "Check your inbox",
+        "<p>If this address is waiting to be confirmed, we sent it a new confirmation link.</p>",
+    ));
+
+    let Some((subscriber_id, subscriber)) = get_pending_subscriber(&pg_pool, &cipher, &email)
+        .await
+        .context("Failed to retrieve the pending subscriber associated with an email")?
+    else {
+        return Ok(response);
+    };
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+
+    let flags = feature_flags
+        .load(&pg_pool)
+        .await
+        .context("Failed to load the feature flags")?;
+    let paused = flags.is_enabled(PAUSE_DELIVERIES);
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if !claim_confirmation_email(
+        &mut transaction,
+        &confirmation_email_settings,
+        &subscriber.email,
+    )
+    .await
+    .context("Failed to check the confirmation emails sent to a pending subscriber")?
+    {
+        return Ok(response);
+    }
+    let subscriber_token = SubscriptionToken::generate();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        &subscriber_token,
+        confirmation_email_settings.token_ttl,
+    )
+    .await
+    .context("Failed to store a new confirmation token for a pending subscriber")?;
+    write_confirmation_email_to_outbox(
+        &mut transaction,
+        subscriber_id,
+        &subscriber_token,
+        outbox_relay_after(paused),
+    )
+    .await
+    .context("Failed to write the confirmation email of a pending subscriber to the outbox")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to resend a confirmation email")?;
+
+    if !paused {
+        let links = SubscriberLinks {
+            base_url: &base_url.0,
+            link_base_url: &link_base_url.0,
+            url_signer: &url_signer,
+        };
+        send_from_outbox(
+            &pg_pool,
+            &email_client,
+            &*email_templates.current().await,
+            &links,
+            subscriber_id,
+            subscriber,
+            &subscriber_token,
+        )
+        .await?;
+    }
+    Ok(response)
+}
+
+#[tracing::instrument(name = "Get pending subscriber by email", skip(pg_pool, cipher, email))]
+async fn get_pending_subscriber(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, NewSubscriber)>, anyhow::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT id, email, name, region FROM subscriptions
+        WHERE email = $1 AND status = 'pending_confirmation'
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let subscriber = NewSubscriber {
+        email: SubscriberEmail::try_from(row.email).map_err(anyhow::Error::msg)?,
+        name: SubscriberName::try_from(cipher.decrypt(row.name)?).map_err(anyhow::Error::msg)?,
+        region: row
+            .region
+            .map(SubscriberRegion::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)?,
+        tags: Vec::new(),
+    };
+    Ok(Some((row.id, subscriber)))
 }
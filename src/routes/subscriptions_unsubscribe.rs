@@ -0,0 +1,188 @@
+use crate::branding::Branding;
+use crate::domain::SubscriptionToken;
+use crate::publishing::escape_html;
+use crate::repositories::subscribers::unsubscribe_subscribers;
+use crate::routes::error_chain_fmt;
+use crate::signing::{SignatureError, UrlSigner};
+use crate::telemetry::record_in_request_spans;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{HttpResponse, ResponseError, get, post, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Signature purpose of the unsubscribe links, kept apart from the status
+/// links so that one cannot be turned into the other.
+pub const UNSUBSCRIBE_LINK_PURPOSE: &str = "unsubscribe";
+/// Links embedded in emails stay valid long after the email was sent.
+const UNSUBSCRIBE_LINK_LIFETIME_DAYS: i64 = 365;
+
+/// Link unsubscribing a subscriber, for email footers.
+pub fn unsubscribe_link(base_url: &str, url_signer: &UrlSigner, subscriber_id: Uuid) -> String {
+    let token = url_signer.sign(
+        UNSUBSCRIBE_LINK_PURPOSE,
+        &subscriber_id.to_string(),
+        Utc::now() + chrono::Duration::days(UNSUBSCRIBE_LINK_LIFETIME_DAYS),
+    );
+    format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        base_url.trim_end_matches('/'),
+        token
+    )
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The link is invalid.")]
+    InvalidToken,
+    #[error("The link has expired.")]
+    ExpiredToken,
+    #[error("There is no subscriber associated with the provided link.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<SignatureError> for UnsubscribeError {
+    fn from(e: SignatureError) -> Self {
+        match e {
+            SignatureError::Invalid => UnsubscribeError::InvalidToken,
+            SignatureError::Expired(_) => UnsubscribeError::ExpiredToken,
+        }
+    }
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::ExpiredToken => StatusCode::GONE,
+            UnsubscribeError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).body(self.to_string())
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: SubscriptionToken,
+}
+
+impl Parameters {
+    fn subscriber_id(&self, url_signer: &UrlSigner) -> Result<Uuid, UnsubscribeError> {
+        url_signer
+            .verify(
+                UNSUBSCRIBE_LINK_PURPOSE,
+                self.token.expose_secret(),
+                Utc::now(),
+            )?
+            .parse::<Uuid>()
+            .map_err(|_| UnsubscribeError::InvalidToken)
+    }
+}
+
+/// Ask the subscriber to confirm they want to unsubscribe.
+///
+/// Following the link changes nothing, as link scanners and prefetchers
+/// follow it too: the page posts back to the same URL.
+#[tracing::instrument(
+    name = "Show the unsubscribe page",
+    skip(parameters, pg_pool, url_signer, branding),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[get("/subscriptions/unsubscribe")]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    pg_pool: web::Data<PgPool>,
+    url_signer: web::Data<UrlSigner>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = parameters.subscriber_id(&url_signer)?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    if !subscriber_exists(&pg_pool, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber to unsubscribe")?
+    {
+        return Err(UnsubscribeError::UnknownSubscriber);
+    }
+    let form = format!(
+        r#"<p>You will not receive any more newsletters.</p>
+<form action="/subscriptions/unsubscribe?token={}" method="post"><button type="submit">Unsubscribe</button></form>"#,
+        escape_html(parameters.token.expose_secret())
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .content_type(ContentType::html())
+        .body(branding.page("Unsubscribe", &form)))
+}
+
+/// Unsubscribe the subscriber a link was signed for.
+///
+/// Also the target of one-click unsubscribe requests (RFC 8058), which post
+/// `List-Unsubscribe=One-Click` to the link: the body is ignored.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pg_pool, url_signer, branding),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[post("/subscriptions/unsubscribe")]
+pub async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pg_pool: web::Data<PgPool>,
+    url_signer: web::Data<UrlSigner>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = parameters.subscriber_id(&url_signer)?;
+    record_in_request_spans("subscriber_id", &tracing::field::display(&subscriber_id));
+    if !unsubscribe_subscriber(&pg_pool, subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to `unsubscribed`")?
+    {
+        return Err(UnsubscribeError::UnknownSubscriber);
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .content_type(ContentType::html())
+        .body(branding.page(
+            "You are unsubscribed",
+            "<p>You will not receive any more newsletters from us.</p>",
+        )))
+}
+
+#[tracing::instrument(name = "Check that a subscriber exists", skip(pg_pool))]
+async fn subscriber_exists(pg_pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let exists = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE id = $1) AS "exists!""#,
+        subscriber_id,
+    )
+    .fetch_one(pg_pool)
+    .await?
+    .exists;
+    Ok(exists)
+}
+
+/// Mark a subscriber as unsubscribed, excluding them from every later
+/// delivery and dropping the ones queued for them. Return whether they
+/// exist.
+#[tracing::instrument(name = "Mark a subscriber as unsubscribed", skip(pg_pool))]
+async fn unsubscribe_subscriber(
+    pg_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pg_pool.begin().await?;
+    let unsubscribed = unsubscribe_subscribers(&mut transaction, &[subscriber_id]).await?;
+    transaction.commit().await?;
+    Ok(!unsubscribed.is_empty())
+}
@@ -0,0 +1,144 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::routes::error_chain_fmt;
+use crate::template_fragments::{
+    FragmentPosition, TemplateFragment, TemplateFragments, store_fragment,
+};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError, delete, get, put, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(thiserror::Error)]
+pub enum TemplateFragmentError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no template fragment with the provided name.")]
+    UnknownFragment,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TemplateFragmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TemplateFragmentError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TemplateFragmentError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            TemplateFragmentError::UnknownFragment => StatusCode::NOT_FOUND,
+            TemplateFragmentError::AuthError(e) => e.status_code(),
+            TemplateFragmentError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            TemplateFragmentError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FragmentList {
+    fragments: Vec<TemplateFragment>,
+}
+
+/// Every template fragment, in the order they are added to issues.
+#[tracing::instrument(
+    name = "List template fragments",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/template_fragments")]
+pub async fn list_template_fragments(
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TemplateFragmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let mut connection = pg_pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let fragments = TemplateFragments::load(&mut connection)
+        .await
+        .context("Failed to load the template fragments")?;
+    Ok(HttpResponse::Ok().json(FragmentList {
+        fragments: fragments.0,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FragmentBody {
+    position: FragmentPosition,
+    html: String,
+    text: String,
+}
+
+/// Create or replace a fragment, added to every issue published from now
+/// on.
+///
+/// Required tokens are checked when an issue is published, so that a footer
+/// can be edited in several steps.
+#[tracing::instrument(
+    name = "Set a template fragment",
+    skip(body, pg_pool, credentials),
+    fields(user_id=tracing::field::Empty, position=body.position.as_str())
+)]
+#[put("/admin/template_fragments/{name}")]
+pub async fn set_template_fragment(
+    name: web::Path<String>,
+    body: web::Json<FragmentBody>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TemplateFragmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let body = body.into_inner();
+    if body.html.trim().is_empty() || body.text.trim().is_empty() {
+        return Err(TemplateFragmentError::ValidationError(
+            "Fragments need both an HTML and a text body.".into(),
+        ));
+    }
+    let fragment = TemplateFragment {
+        name: name.into_inner(),
+        position: body.position,
+        html: body.html,
+        text: body.text,
+    };
+    store_fragment(&pg_pool, &fragment)
+        .await
+        .context("Failed to store a template fragment")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Stop adding a fragment to the issues published from now on.
+#[tracing::instrument(
+    name = "Delete a template fragment",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[delete("/admin/template_fragments/{name}")]
+pub async fn delete_template_fragment(
+    name: web::Path<String>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TemplateFragmentError> {
+    authenticate(credentials, &pg_pool).await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM template_fragments WHERE name = $1",
+        name.as_str()
+    )
+    .execute(pg_pool.as_ref())
+    .await
+    .context("Failed to delete a template fragment")?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(TemplateFragmentError::UnknownFragment);
+    }
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,43 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::routes::error_chain_fmt;
+use crate::token_guard::TokenGuard;
+use actix_web::{HttpResponse, ResponseError, get, web};
+use sqlx::PgPool;
+use std::time::Instant;
+
+#[derive(thiserror::Error)]
+pub enum TokenGuardError {
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+}
+
+impl std::fmt::Debug for TokenGuardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TokenGuardError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            TokenGuardError::AuthError(e) => e.error_response(),
+        }
+    }
+}
+
+/// Failed token lookups, bans and rejected requests since this instance
+/// started, and the number of addresses currently banned.
+#[tracing::instrument(
+    name = "Get token guard metrics",
+    skip(pg_pool, token_guard, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/token_guard")]
+pub async fn get_token_guard_metrics(
+    pg_pool: web::Data<PgPool>,
+    token_guard: web::Data<TokenGuard>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TokenGuardError> {
+    authenticate(credentials, &pg_pool).await?;
+    Ok(HttpResponse::Ok().json(token_guard.metrics(Instant::now())))
+}
@@ -0,0 +1,394 @@
+use crate::authentication::{AdminCredentials, AuthError, authenticate};
+use crate::configuration::TrackingSettings;
+use crate::delivery::{get_delivery_report, get_issue_audience};
+use crate::routes::error_chain_fmt;
+use crate::tracking::{BotReason, TrackingMode, detect_bot};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType, USER_AGENT};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, get, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF.
+const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("There is no newsletter issue associated with the provided id.")]
+    UnknownIssue,
+    #[error("There is no subscriber associated with the provided id.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TrackingError::UnknownIssue | TrackingError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            TrackingError::AuthError(e) => e.status_code(),
+            TrackingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            TrackingError::AuthError(e) => e.error_response(),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Record an email open",
+    skip(request, pg_pool, tracking_settings)
+)]
+#[get("/t/o/{newsletter_issue_id}/{subscriber_id}")]
+pub async fn track_open(
+    request: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    pg_pool: web::Data<PgPool>,
+    tracking_settings: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, TrackingError> {
+    let (newsletter_issue_id, subscriber_id) = path.into_inner();
+    handle_open(
+        &request,
+        &pg_pool,
+        &tracking_settings,
+        newsletter_issue_id,
+        Some(subscriber_id),
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Record an anonymous email open",
+    skip(request, pg_pool, tracking_settings)
+)]
+#[get("/t/o/{newsletter_issue_id}")]
+pub async fn track_anonymous_open(
+    request: HttpRequest,
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    tracking_settings: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, TrackingError> {
+    handle_open(
+        &request,
+        &pg_pool,
+        &tracking_settings,
+        *newsletter_issue_id,
+        None,
+    )
+    .await
+}
+
+async fn handle_open(
+    request: &HttpRequest,
+    pg_pool: &PgPool,
+    tracking_settings: &TrackingSettings,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Option<Uuid>,
+) -> Result<HttpResponse, TrackingError> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT published_at, tracking_mode
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pg_pool)
+    .await
+    .context("Failed to retrieve the newsletter issue")?
+    .ok_or(TrackingError::UnknownIssue)?;
+    let tracking_mode =
+        TrackingMode::try_from(issue.tracking_mode).map_err(|e| anyhow::anyhow!(e))?;
+
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok());
+    let elapsed_since_send = (Utc::now() - issue.published_at)
+        .to_std()
+        .unwrap_or_default();
+    let bot_reason = detect_bot(user_agent, elapsed_since_send, tracking_settings);
+    match tracking_mode {
+        TrackingMode::Detailed => record_open(
+            pg_pool,
+            newsletter_issue_id,
+            subscriber_id,
+            user_agent,
+            bot_reason,
+        )
+        .await
+        .context("Failed to record an email open")?,
+        TrackingMode::Aggregate => {
+            increment_open_counter(pg_pool, newsletter_issue_id, bot_reason.is_some())
+                .await
+                .context("Failed to increment the open counter")?
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(actix_web::mime::IMAGE_GIF))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(TRACKING_PIXEL))
+}
+
+#[tracing::instrument(name = "Store email open", skip(pg_pool))]
+async fn record_open(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Option<Uuid>,
+    user_agent: Option<&str>,
+    bot_reason: Option<BotReason>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_opens (
+            newsletter_issue_id, subscriber_id, user_agent, is_bot, bot_reason, opened_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        user_agent,
+        bot_reason.is_some(),
+        bot_reason.map(|r| r.as_str()),
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Increment open counter", skip(pg_pool))]
+async fn increment_open_counter(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    is_bot: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET aggregate_opens = aggregate_opens + (NOT $2)::int,
+            aggregate_flagged_opens = aggregate_flagged_opens + $2::int
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        is_bot,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Engagement of a single issue. Events flagged as bots are reported
+/// separately and never included in the human counters.
+///
+/// Unique counts are only known for issues tracked in `detailed` mode.
+#[derive(serde::Serialize)]
+pub struct EngagementStats {
+    tracking_mode: &'static str,
+    opens: i64,
+    unique_opens: Option<i64>,
+    flagged_opens: i64,
+    clicks: i64,
+    unique_clicks: Option<i64>,
+    flagged_clicks: i64,
+}
+
+#[tracing::instrument(
+    name = "Get engagement stats for a newsletter issue",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/{newsletter_issue_id}/engagement")]
+pub async fn get_newsletter_engagement(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TrackingError> {
+    authenticate(credentials, &pg_pool).await?;
+    let stats = get_engagement_stats(&pg_pool, *newsletter_issue_id)
+        .await
+        .context("Failed to compute engagement stats for the newsletter issue")?
+        .ok_or(TrackingError::UnknownIssue)?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+/// Who an issue was sent to and how many of its deliveries went out. The
+/// recipients are those resolved when the issue was queued, whatever has
+/// happened to its segment and subscribers since.
+#[tracing::instrument(
+    name = "Get the audience of a newsletter issue",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/{newsletter_issue_id}/audience")]
+pub async fn get_newsletter_audience(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TrackingError> {
+    authenticate(credentials, &pg_pool).await?;
+    let audience = get_issue_audience(&pg_pool, *newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the audience of the newsletter issue")?
+        .ok_or(TrackingError::UnknownIssue)?;
+    Ok(HttpResponse::Ok().json(audience))
+}
+
+/// How many deliveries of an issue were sent, skipped or failed, with the
+/// recipients of the failed ones and the reason.
+#[tracing::instrument(
+    name = "Get the deliveries of a newsletter issue",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/newsletters/{newsletter_issue_id}/deliveries")]
+pub async fn get_newsletter_deliveries(
+    newsletter_issue_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TrackingError> {
+    authenticate(credentials, &pg_pool).await?;
+    let report = get_delivery_report(&pg_pool, *newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the deliveries of the newsletter issue")?
+        .ok_or(TrackingError::UnknownIssue)?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Engagement of a single subscriber over the last 90 days.
+///
+/// `score` is the share of the issues delivered to them they opened or
+/// clicked, from 0 to 100, and is unknown until they received at least one
+/// issue tracked in `detailed` mode.
+#[derive(serde::Serialize)]
+pub struct SubscriberEngagement {
+    last_engaged_at: Option<DateTime<Utc>>,
+    deliveries_90d: i64,
+    engaged_issues_90d: i64,
+    score: Option<i64>,
+    inactive_90d: bool,
+}
+
+#[tracing::instrument(
+    name = "Get engagement score of a subscriber",
+    skip(pg_pool, credentials),
+    fields(user_id=tracing::field::Empty)
+)]
+#[get("/admin/subscribers/{subscriber_id}/engagement")]
+pub async fn get_subscriber_engagement(
+    subscriber_id: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    credentials: AdminCredentials,
+) -> Result<HttpResponse, TrackingError> {
+    authenticate(credentials, &pg_pool).await?;
+    let engagement = sqlx::query_as!(
+        SubscriberEngagement,
+        r#"
+        SELECT
+            last_engaged_at,
+            deliveries_90d AS "deliveries_90d!",
+            engaged_issues_90d AS "engaged_issues_90d!",
+            score,
+            inactive_90d AS "inactive_90d!"
+        FROM subscriber_engagement
+        WHERE subscriber_id = $1
+        "#,
+        *subscriber_id,
+    )
+    .fetch_optional(pg_pool.as_ref())
+    .await
+    .context("Failed to compute the engagement of the subscriber")?
+    .ok_or(TrackingError::UnknownSubscriber)?;
+    Ok(HttpResponse::Ok().json(engagement))
+}
+
+#[tracing::instrument(name = "Get engagement stats", skip(pg_pool))]
+async fn get_engagement_stats(
+    pg_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<EngagementStats>, anyhow::Error> {
+    let Some(issue) = sqlx::query!(
+        r#"
+        SELECT tracking_mode, aggregate_opens, aggregate_flagged_opens
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let tracking_mode =
+        TrackingMode::try_from(issue.tracking_mode).map_err(|e| anyhow::anyhow!(e))?;
+
+    let opens = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE NOT is_bot) AS "opens!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE NOT is_bot) AS "unique_opens!",
+            COUNT(*) FILTER (WHERE is_bot) AS "flagged_opens!"
+        FROM email_opens
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    let clicks = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(c.id) FILTER (WHERE NOT c.is_bot) AS "clicks!",
+            COUNT(DISTINCT c.subscriber_id) FILTER (WHERE NOT c.is_bot) AS "unique_clicks!",
+            COUNT(c.id) FILTER (WHERE c.is_bot) AS "flagged_clicks!"
+        FROM short_link_clicks c
+        JOIN short_links l ON l.code = c.code
+        WHERE l.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+    let aggregate_clicks = sqlx::query!(
+        r#"
+        SELECT
+            COALESCE(SUM(aggregate_clicks), 0)::bigint AS "clicks!",
+            COALESCE(SUM(aggregate_flagged_clicks), 0)::bigint AS "flagged_clicks!"
+        FROM short_links
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(pg_pool)
+    .await?;
+
+    let detailed = tracking_mode == TrackingMode::Detailed;
+    Ok(Some(EngagementStats {
+        tracking_mode: tracking_mode.as_str(),
+        opens: opens.opens + issue.aggregate_opens,
+        unique_opens: detailed.then_some(opens.unique_opens),
+        flagged_opens: opens.flagged_opens + issue.aggregate_flagged_opens,
+        clicks: clicks.clicks + aggregate_clicks.clicks,
+        unique_clicks: detailed.then_some(clicks.unique_clicks),
+        flagged_clicks: clicks.flagged_clicks + aggregate_clicks.flagged_clicks,
+    }))
+}
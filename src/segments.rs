@@ -0,0 +1,310 @@
+//! Subscriber filters saved under a name, for newsletters to be sent to.
+use crate::subscriber_search::SubscriberFilter;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StoreSegmentError {
+    #[error("There is already a segment named `{0}`.")]
+    NameTaken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[derive(serde::Serialize)]
+pub struct SavedSegment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub filter: SubscriberFilter,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A segment as it was left by a change.
+#[derive(serde::Serialize)]
+pub struct SegmentChange {
+    /// `created`, `updated` or `deleted`.
+    pub change: String,
+    pub name: String,
+    pub filter: SubscriberFilter,
+    /// Username of the admin who made the change, `None` once they are
+    /// deleted.
+    pub changed_by: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Every segment, by name.
+#[tracing::instrument(name = "List segments", skip(pg_pool))]
+pub async fn list_segments(pg_pool: &PgPool) -> Result<Vec<SavedSegment>, anyhow::Error> {
+    sqlx::query!(
+        r#"
+        SELECT segment_id, name, filter, created_at, updated_at
+        FROM segments
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(SavedSegment {
+            segment_id: r.segment_id,
+            name: r.name,
+            filter: parse_filter(&r.filter)?,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+        })
+    })
+    .collect()
+}
+
+#[tracing::instrument(name = "Get a segment", skip(pg_pool))]
+pub async fn get_segment(
+    pg_pool: &PgPool,
+    segment_id: Uuid,
+) -> Result<Option<SavedSegment>, anyhow::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT name, filter, created_at, updated_at
+        FROM segments
+        WHERE segment_id = $1
+        "#,
+        segment_id,
+    )
+    .fetch_optional(pg_pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(SavedSegment {
+        segment_id,
+        name: r.name,
+        filter: parse_filter(&r.filter)?,
+        created_at: r.created_at,
+        updated_at: r.updated_at,
+    }))
+}
+
+#[tracing::instrument(name = "Create a segment", skip(pg_pool))]
+pub async fn create_segment(
+    pg_pool: &PgPool,
+    user_id: Uuid,
+    name: String,
+    filter: SubscriberFilter,
+) -> Result<SavedSegment, StoreSegmentError> {
+    let segment_id = Uuid::new_v4();
+    let now = Utc::now();
+    let serialized = serialize_filter(&filter)?;
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, filter, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $4)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        segment_id,
+        name,
+        serialized,
+        now,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to insert a segment")?
+    .rows_affected();
+    if inserted == 0 {
+        return Err(StoreSegmentError::NameTaken(name));
+    }
+    record_change(
+        &mut transaction,
+        segment_id,
+        "created",
+        &name,
+        &serialized,
+        user_id,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a segment")?;
+    Ok(SavedSegment {
+        segment_id,
+        name,
+        filter,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Rename a segment or change its filter, `None` if there is no such
+/// segment.
+#[tracing::instrument(name = "Update a segment", skip(pg_pool))]
+pub async fn update_segment(
+    pg_pool: &PgPool,
+    user_id: Uuid,
+    segment_id: Uuid,
+    name: String,
+    filter: SubscriberFilter,
+) -> Result<Option<SavedSegment>, StoreSegmentError> {
+    let now = Utc::now();
+    let serialized = serialize_filter(&filter)?;
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE segments
+        SET name = $2, filter = $3, updated_at = $4
+        WHERE segment_id = $1
+        RETURNING created_at
+        "#,
+        segment_id,
+        name,
+        serialized,
+        now,
+    )
+    .fetch_optional(&mut *transaction)
+    .await;
+    let created_at = match updated {
+        Ok(Some(r)) => r.created_at,
+        Ok(None) => return Ok(None),
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|e| e.is_unique_violation()) =>
+        {
+            return Err(StoreSegmentError::NameTaken(name));
+        }
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to update a segment")
+                .into());
+        }
+    };
+    record_change(
+        &mut transaction,
+        segment_id,
+        "updated",
+        &name,
+        &serialized,
+        user_id,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a segment")?;
+    Ok(Some(SavedSegment {
+        segment_id,
+        name,
+        filter,
+        created_at,
+        updated_at: now,
+    }))
+}
+
+/// Return whether the segment existed. Its history is kept.
+#[tracing::instrument(name = "Delete a segment", skip(pg_pool))]
+pub async fn delete_segment(
+    pg_pool: &PgPool,
+    user_id: Uuid,
+    segment_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(deleted) = sqlx::query!(
+        r#"DELETE FROM segments WHERE segment_id = $1 RETURNING name, filter"#,
+        segment_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete a segment")?
+    else {
+        return Ok(false);
+    };
+    record_change(
+        &mut transaction,
+        segment_id,
+        "deleted",
+        &deleted.name,
+        &deleted.filter,
+        user_id,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a segment")?;
+    Ok(true)
+}
+
+/// The changes made to a segment, oldest first.
+#[tracing::instrument(name = "Get the changes made to a segment", skip(pg_pool))]
+pub async fn get_segment_changes(
+    pg_pool: &PgPool,
+    segment_id: Uuid,
+) -> Result<Vec<SegmentChange>, anyhow::Error> {
+    sqlx::query!(
+        r#"
+        SELECT c.change, c.name, c.filter, u.username AS "changed_by?", c.changed_at
+        FROM segment_changes c
+        LEFT JOIN users u ON u.user_id = c.changed_by
+        WHERE c.segment_id = $1
+        ORDER BY c.id
+        "#,
+        segment_id,
+    )
+    .fetch_all(pg_pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        Ok(SegmentChange {
+            change: r.change,
+            name: r.name,
+            filter: parse_filter(&r.filter)?,
+            changed_by: r.changed_by,
+            changed_at: r.changed_at,
+        })
+    })
+    .collect()
+}
+
+async fn record_change(
+    connection: &mut PgConnection,
+    segment_id: Uuid,
+    change: &str,
+    name: &str,
+    filter: &str,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO segment_changes (segment_id, change, name, filter, changed_by, changed_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        segment_id,
+        change,
+        name,
+        filter,
+        user_id,
+    )
+    .execute(connection)
+    .await
+    .context("Failed to record a change made to a segment")?;
+    Ok(())
+}
+
+fn serialize_filter(filter: &SubscriberFilter) -> Result<String, anyhow::Error> {
+    serde_json::to_string(filter).context("Failed to serialize the filter of a segment")
+}
+
+fn parse_filter(filter: &str) -> Result<SubscriberFilter, anyhow::Error> {
+    serde_json::from_str(filter).context("Failed to parse the filter of a segment")
+}
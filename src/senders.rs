@@ -0,0 +1,248 @@
+//! Registry of the addresses newsletter issues may be sent from, besides the
+//! configured one.
+//!
+//! Entries are an address, e.g. `news@example.com`, or a whole domain, e.g.
+//! `example.com`. They are verified by a token emailed to the address, or to
+//! the `postmaster` of the domain.
+use crate::EmailClient;
+use crate::domain::{SubscriberEmail, SubscriptionToken};
+use crate::email_client::EmailClientError;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+
+/// The sender an issue is sent from, in place of the configured one.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct IssueSender {
+    pub email: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Issues cannot be sent from addresses missing from the registry, or not
+/// verified yet.
+#[derive(thiserror::Error, Debug)]
+#[error("{0} is not a verified sender.")]
+pub struct UnverifiedSender(pub String);
+
+#[derive(thiserror::Error, Debug)]
+pub enum SenderRegistryError {
+    #[error("`{0}` is neither an email address nor a domain.")]
+    InvalidAddress(String),
+    #[error("{0} is already verified.")]
+    AlreadyVerified(String),
+    #[error("Deliveries are paused, add the sender once they resume.")]
+    DeliveriesPaused,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// An entry of the registry.
+#[derive(Debug, PartialEq)]
+pub enum SenderAddress {
+    Email(String),
+    Domain(String),
+}
+
+impl SenderAddress {
+    pub fn parse(address: &str) -> Result<Self, SenderRegistryError> {
+        let address = address.trim().to_lowercase();
+        if address.contains('@') {
+            return SubscriberEmail::try_from(address.clone())
+                .map(|_| SenderAddress::Email(address.clone()))
+                .map_err(|_| SenderRegistryError::InvalidAddress(address));
+        }
+        let labels: Vec<&str> = address.split('.').collect();
+        let valid = labels.len() > 1
+            && labels.iter().all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if !valid {
+            return Err(SenderRegistryError::InvalidAddress(address));
+        }
+        Ok(SenderAddress::Domain(address))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            SenderAddress::Email(email) | SenderAddress::Domain(email) => email,
+        }
+    }
+
+    /// Where the verification token is emailed.
+    pub fn verification_recipient(&self) -> String {
+        match self {
+            SenderAddress::Email(email) => email.clone(),
+            SenderAddress::Domain(domain) => format!("postmaster@{}", domain),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct Sender {
+    pub address: String,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Add `address` to the registry, or renew its token if it is still
+/// unverified, and email the token to it.
+#[tracing::instrument(name = "Add a sender to the registry", skip(pg_pool, email_client))]
+pub async fn add_sender(
+    pg_pool: &PgPool,
+    email_client: &EmailClient,
+    address: &SenderAddress,
+) -> Result<(), SenderRegistryError> {
+    let recipient = SubscriberEmail::try_from(address.verification_recipient())
+        .map_err(|_| SenderRegistryError::InvalidAddress(address.as_str().to_owned()))?;
+    let token = SubscriptionToken::generate();
+    let stored = sqlx::query!(
+        r#"
+        INSERT INTO verified_senders (address, verification_token, added_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (address) DO UPDATE
+        SET verification_token = EXCLUDED.verification_token
+        WHERE verified_senders.verified_at IS NULL
+        "#,
+        address.as_str(),
+        token.expose_secret(),
+    )
+    .execute(pg_pool)
+    .await
+    .context("Failed to store a sender")?
+    .rows_affected();
+    if stored == 0 {
+        return Err(SenderRegistryError::AlreadyVerified(
+            address.as_str().to_owned(),
+        ));
+    }
+    let text = format!(
+        "Someone asked to send newsletters from {}.\n\
+         If it was you, verify the address with this token: {}\n\
+         Otherwise, you can ignore this email.",
+        address.as_str(),
+        token.expose_secret()
+    );
+    let html = format!(
+        "<p>Someone asked to send newsletters from {}.</p>\
+         <p>If it was you, verify the address with this token: <code>{}</code></p>\
+         <p>Otherwise, you can ignore this email.</p>",
+        address.as_str(),
+        token.expose_secret()
+    );
+    match email_client
+        .send_email(&recipient, "Verify your newsletter sender", &html, &text)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(EmailClientError::Paused) => Err(SenderRegistryError::DeliveriesPaused),
+        Err(e) => Err(anyhow::Error::new(e)
+            .context("Failed to send the verification email of a sender")
+            .into()),
+    }
+}
+
+/// Mark the sender `token` was emailed to as verified, returning its
+/// address. `None` if no sender is waiting for this token.
+#[tracing::instrument(name = "Verify a sender", skip(pg_pool, token))]
+pub async fn verify_sender(
+    pg_pool: &PgPool,
+    token: &SubscriptionToken,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE verified_senders
+        SET verified_at = now(), verification_token = NULL
+        WHERE verification_token = $1
+        RETURNING address
+        "#,
+        token.expose_secret(),
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+/// Every sender of the registry, verified or not, by address.
+#[tracing::instrument(name = "List senders", skip(pg_pool))]
+pub async fn list_senders(pg_pool: &PgPool) -> Result<Vec<Sender>, sqlx::Error> {
+    sqlx::query_as!(
+        Sender,
+        r#"
+        SELECT address, verified_at, added_at
+        FROM verified_senders
+        ORDER BY address
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+/// Whether issues may be sent from `email`, verified on its own or through
+/// its domain.
+#[tracing::instrument(name = "Check a sender", skip(connection))]
+pub async fn is_verified_sender(
+    connection: &mut PgConnection,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let Ok(SenderAddress::Email(email)) = SenderAddress::parse(email) else {
+        return Ok(false);
+    };
+    let domain = email.split_once('@').map_or("", |(_, domain)| domain);
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM verified_senders
+            WHERE address IN ($1, $2) AND verified_at IS NOT NULL
+        ) AS "verified!"
+        "#,
+        email,
+        domain,
+    )
+    .fetch_one(connection)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SenderAddress;
+    use claims::assert_err;
+
+    #[test]
+    fn addresses_and_domains_are_told_apart() {
+        assert_eq!(
+            SenderAddress::parse(" News@Example.com ").unwrap(),
+            SenderAddress::Email("news@example.com".into())
+        );
+        assert_eq!(
+            SenderAddress::parse("mail.example.com").unwrap(),
+            SenderAddress::Domain("mail.example.com".into())
+        );
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        for entry in [
+            "",
+            "example",
+            "-example.com",
+            "example..com",
+            "a b.com",
+            "@example.com",
+        ] {
+            assert_err!(SenderAddress::parse(entry), "{} was accepted", entry);
+        }
+    }
+
+    #[test]
+    fn domains_are_verified_through_their_postmaster() {
+        assert_eq!(
+            SenderAddress::parse("example.com")
+                .unwrap()
+                .verification_recipient(),
+            "postmaster@example.com"
+        );
+    }
+}
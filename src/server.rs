@@ -0,0 +1,108 @@
+use crate::EmailClient;
+use crate::configuration::Settings;
+use crate::extensions::Extensions;
+use crate::startup::{Application, Components};
+use sqlx::PgPool;
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// The newsletter service as a component of another binary.
+///
+/// Built from [`Settings`], possibly around components provided by the
+/// embedding binary, and run with [`Server::run`]. Unlike the binary, it
+/// does not stop on SIGTERM or SIGINT but through its [`ServerHandle`].
+pub struct Server {
+    application: Application,
+    stop: Arc<Notify>,
+}
+
+/// Stops a [`Server`] from another task.
+#[derive(Clone)]
+pub struct ServerHandle {
+    stop: Arc<Notify>,
+}
+
+impl ServerHandle {
+    /// Refuse new connections and let in-flight requests complete, as the
+    /// binary does on SIGTERM. [`Server::run`] returns once done.
+    pub fn stop(&self) {
+        self.stop.notify_one();
+    }
+}
+
+impl Server {
+    /// Build every component from `configuration`.
+    pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+        Self::builder(configuration).build().await
+    }
+
+    /// Provide some components, e.g. a connection pool shared with the
+    /// embedding binary, before building the others from `configuration`.
+    pub fn builder(configuration: Settings) -> ServerBuilder {
+        ServerBuilder {
+            configuration,
+            components: Components::default(),
+        }
+    }
+
+    /// Address the public routes are served on, e.g. to find the port
+    /// picked when `application.port` is 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.application.local_addr()
+    }
+
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            stop: self.stop.clone(),
+        }
+    }
+
+    /// Serve until stopped through a [`ServerHandle`]. The background
+    /// workers only start here, and are stopped before it returns: a server
+    /// dropped without running leaves nothing behind.
+    pub fn run(self) -> impl Future<Output = Result<(), std::io::Error>> {
+        let stop = self.stop;
+        self.application
+            .run_until(async move { stop.notified().await })
+    }
+}
+
+pub struct ServerBuilder {
+    configuration: Settings,
+    components: Components,
+}
+
+impl ServerBuilder {
+    /// Connections to an already migrated database.
+    pub fn pg_pool(mut self, pg_pool: PgPool) -> Self {
+        self.components = self.components.pg_pool(pg_pool);
+        self
+    }
+
+    pub fn email_client(mut self, email_client: EmailClient) -> Self {
+        self.components = self.components.email_client(email_client);
+        self
+    }
+
+    /// Serve the public routes on `listener` rather than on
+    /// `application.host` and `application.port`.
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.components = self.components.listener(listener);
+        self
+    }
+
+    /// Routes, middleware and event listeners added by the embedding binary.
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.components = self.components.extensions(extensions);
+        self
+    }
+
+    pub async fn build(self) -> Result<Server, std::io::Error> {
+        Ok(Server {
+            application: Application::build_with(self.configuration, self.components).await?,
+            stop: Arc::new(Notify::new()),
+        })
+    }
+}
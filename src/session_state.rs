@@ -0,0 +1,129 @@
+use actix_session::storage::{
+    CookieSessionStore, LoadError, RedisSessionStore, SaveError, SessionKey, SessionStore,
+    UpdateError,
+};
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::cookie::time::Duration;
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use std::collections::HashMap;
+use std::future::{Ready, ready};
+use uuid::Uuid;
+
+/// Session of an admin who logged in through `/login`.
+#[derive(Clone)]
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_VERSION_KEY: &'static str = "session_version";
+
+    /// Change the session key, so that a key handed out before logging in
+    /// cannot be used to hijack the session.
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    /// Store the admin, along with the session version of their account
+    /// the session is valid for.
+    pub fn insert_user(
+        &self,
+        user_id: Uuid,
+        session_version: i32,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)?;
+        self.0.insert(Self::SESSION_VERSION_KEY, session_version)
+    }
+
+    /// The admin and the session version stored, still to be checked
+    /// against their account.
+    pub fn get_user(&self) -> Result<Option<(Uuid, i32)>, SessionGetError> {
+        let user_id = self.0.get(Self::USER_ID_KEY)?;
+        let session_version = self.0.get(Self::SESSION_VERSION_KEY)?;
+        Ok(user_id.zip(session_version))
+    }
+
+    pub fn log_out(self) {
+        self.0.purge()
+    }
+}
+
+impl FromRequest for TypedSession {
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
+
+/// Where sessions are kept: Redis, shared by every instance, or the session
+/// cookie itself, encrypted, when no Redis is configured (e.g. locally).
+pub enum AdminSessionStore {
+    Redis(RedisSessionStore),
+    Cookie(CookieSessionStore),
+}
+
+impl Clone for AdminSessionStore {
+    fn clone(&self) -> Self {
+        match self {
+            AdminSessionStore::Redis(store) => AdminSessionStore::Redis(store.clone()),
+            AdminSessionStore::Cookie(_) => {
+                AdminSessionStore::Cookie(CookieSessionStore::default())
+            }
+        }
+    }
+}
+
+impl SessionStore for AdminSessionStore {
+    async fn load(
+        &self,
+        session_key: &SessionKey,
+    ) -> Result<Option<HashMap<String, String>>, LoadError> {
+        match self {
+            AdminSessionStore::Redis(store) => store.load(session_key).await,
+            AdminSessionStore::Cookie(store) => store.load(session_key).await,
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        match self {
+            AdminSessionStore::Redis(store) => store.save(session_state, ttl).await,
+            AdminSessionStore::Cookie(store) => store.save(session_state, ttl).await,
+        }
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        match self {
+            AdminSessionStore::Redis(store) => store.update(session_key, session_state, ttl).await,
+            AdminSessionStore::Cookie(store) => store.update(session_key, session_state, ttl).await,
+        }
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            AdminSessionStore::Redis(store) => store.update_ttl(session_key, ttl).await,
+            AdminSessionStore::Cookie(store) => store.update_ttl(session_key, ttl).await,
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        match self {
+            AdminSessionStore::Redis(store) => store.delete(session_key).await,
+            AdminSessionStore::Cookie(store) => store.delete(session_key).await,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+
+/// Signs the expiring tokens embedded in links that grant access without
+/// authentication, e.g. draft previews.
+///
+/// Tokens look like `{payload}.{expires_at}.{signature}`, `expires_at`
+/// being a Unix timestamp. The signature also covers a `purpose`, so that a
+/// token handed out for one kind of link is rejected by the others.
+#[derive(Clone)]
+pub struct UrlSigner {
+    secret: SecretString,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SignatureError {
+    #[error("The token is malformed or its signature is invalid.")]
+    Invalid,
+    #[error("The token expired at {0}.")]
+    Expired(DateTime<Utc>),
+}
+
+impl UrlSigner {
+    pub fn new(secret: SecretString) -> Self {
+        Self { secret }
+    }
+
+    pub fn sign(&self, purpose: &str, payload: &str, expires_at: DateTime<Utc>) -> String {
+        let signed = format!("{}.{}", payload, expires_at.timestamp());
+        let signature = hex::encode(self.mac(purpose, &signed).finalize().into_bytes());
+        format!("{}.{}", signed, signature)
+    }
+
+    /// Return the payload of a token that was signed for `purpose` and has
+    /// not expired by `now`.
+    pub fn verify(
+        &self,
+        purpose: &str,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, SignatureError> {
+        let (signed, signature) = token.rsplit_once('.').ok_or(SignatureError::Invalid)?;
+        let (payload, expires_at) = signed.rsplit_once('.').ok_or(SignatureError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| SignatureError::Invalid)?;
+        self.mac(purpose, signed)
+            .verify_slice(&signature)
+            .map_err(|_| SignatureError::Invalid)?;
+
+        let expires_at = expires_at
+            .parse()
+            .ok()
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .ok_or(SignatureError::Invalid)?;
+        if expires_at <= now {
+            return Err(SignatureError::Expired(expires_at));
+        }
+        Ok(payload.to_owned())
+    }
+
+    fn mac(&self, purpose: &str, signed: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(purpose.as_bytes());
+        mac.update(b"\0");
+        mac.update(signed.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SignatureError, UrlSigner};
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_ok_eq};
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new("a-secret".to_string().into())
+    }
+
+    #[test]
+    fn signed_tokens_are_verified() {
+        let now = Utc::now();
+        let token = signer().sign("preview", "some.payload", now + Duration::hours(1));
+        assert_ok_eq!(signer().verify("preview", &token, now), "some.payload");
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let now = Utc::now();
+        let token = signer().sign("preview", "payload", now - Duration::hours(1));
+        assert!(matches!(
+            signer().verify("preview", &token, now),
+            Err(SignatureError::Expired(_))
+        ));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let now = Utc::now();
+        let token = signer().sign("preview", "payload", now + Duration::hours(1));
+        let tampered = token.replacen("payload", "another", 1);
+        assert_eq!(
+            signer().verify("preview", &tampered, now),
+            Err(SignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn tokens_are_bound_to_their_purpose() {
+        let now = Utc::now();
+        let token = signer().sign("preview", "payload", now + Duration::hours(1));
+        assert_err!(signer().verify("login", &token, now));
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let now = Utc::now();
+        let token = UrlSigner::new("another-secret".to_string().into()).sign(
+            "preview",
+            "payload",
+            now + Duration::hours(1),
+        );
+        assert_err!(signer().verify("preview", &token, now));
+    }
+}
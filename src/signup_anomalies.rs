@@ -0,0 +1,132 @@
+use crate::configuration::SignupAnomalySettings;
+use crate::domain::SubscriberEmail;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use std::net::IpAddr;
+
+/// A burst of signups, subscriptions taking part in it are quarantined until
+/// an admin reviews them.
+#[derive(Debug, PartialEq)]
+pub enum SignupAnomaly {
+    NetworkBurst { network: String, signups: i64 },
+    DomainBurst { domain: String, signups: i64 },
+}
+
+impl std::fmt::Display for SignupAnomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignupAnomaly::NetworkBurst { network, signups } => {
+                write!(f, "{} recent signups from {}", signups, network)
+            }
+            SignupAnomaly::DomainBurst { domain, signups } => {
+                write!(f, "{} recent signups to {}", signups, domain)
+            }
+        }
+    }
+}
+
+/// The network an address belongs to for rate purposes: its /24 for IPv4,
+/// its /48 for IPv6, the size usually handed out to a single customer.
+pub fn signup_network(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => signup_network(IpAddr::V4(v4)),
+            None => {
+                let s = v6.segments();
+                format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+            }
+        },
+    }
+}
+
+/// Check whether a new signup from `network` for `email` would take part in
+/// a burst, given the subscriptions stored since `now - window`.
+#[tracing::instrument(name = "Detect signup burst", skip(connection, settings, email))]
+pub async fn detect_signup_burst(
+    connection: &mut PgConnection,
+    settings: &SignupAnomalySettings,
+    network: Option<&str>,
+    email: &SubscriberEmail,
+    now: DateTime<Utc>,
+) -> Result<Option<SignupAnomaly>, sqlx::Error> {
+    let since = chrono::Duration::from_std(settings.window)
+        .ok()
+        .and_then(|window| now.checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    if let Some(network) = network {
+        let signups = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "signups!" FROM subscriptions
+            WHERE signup_network = $1 AND subscribed_at > $2
+            "#,
+            network,
+            since,
+        )
+        .fetch_one(&mut *connection)
+        .await?
+        .signups;
+        if signups >= settings.max_signups_per_network.into() {
+            return Ok(Some(SignupAnomaly::NetworkBurst {
+                network: network.to_owned(),
+                signups,
+            }));
+        }
+    }
+
+    let domain = email.domain();
+    let signups = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "signups!" FROM subscriptions
+        WHERE split_part(email, '@', 2) = $1 AND subscribed_at > $2
+        "#,
+        domain,
+        since,
+    )
+    .fetch_one(&mut *connection)
+    .await?
+    .signups;
+    if signups >= settings.max_signups_per_domain.into() {
+        return Ok(Some(SignupAnomaly::DomainBurst {
+            domain: domain.to_owned(),
+            signups,
+        }));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::signup_network;
+
+    #[test]
+    fn ipv4_addresses_are_grouped_by_24() {
+        assert_eq!(
+            signup_network("203.0.113.7".parse().unwrap()),
+            "203.0.113.0/24"
+        );
+        assert_eq!(
+            signup_network("203.0.113.250".parse().unwrap()),
+            signup_network("203.0.113.7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn ipv6_addresses_are_grouped_by_48() {
+        assert_eq!(
+            signup_network("2001:db8:1234:5678::1".parse().unwrap()),
+            "2001:db8:1234::/48"
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_are_grouped_as_ipv4() {
+        assert_eq!(
+            signup_network("::ffff:203.0.113.7".parse().unwrap()),
+            "203.0.113.0/24"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Requests signed longer ago than this are rejected, so that a captured
+/// request cannot be replayed.
+const MAX_REQUEST_AGE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Whether a request was sent by the Slack app owning `signing_secret`.
+///
+/// Slack signs `v0:{timestamp}:{body}` with HMAC-SHA256, sending the
+/// timestamp in `X-Slack-Request-Timestamp` and `v0={hex digest}` in
+/// `X-Slack-Signature`.
+pub fn verify_signature(
+    signing_secret: &SecretString,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some(signed_at) = timestamp
+        .parse()
+        .ok()
+        .and_then(|t| DateTime::from_timestamp(t, 0))
+    else {
+        return false;
+    };
+    if (now - signed_at).abs() > MAX_REQUEST_AGE {
+        return false;
+    }
+    let Some(signature) = signature
+        .strip_prefix("v0=")
+        .and_then(|s| hex::decode(s).ok())
+    else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// What `/newsletter` was asked to do, from the text following the command.
+#[derive(Debug, PartialEq)]
+pub enum SlackCommand {
+    /// Whether deliveries are running, and the drafts waiting.
+    Status,
+    /// Publish a draft once `at` is reached.
+    Schedule {
+        newsletter_draft_id: Uuid,
+        at: DateTime<Utc>,
+    },
+    /// Publish a draft straight away.
+    Approve { newsletter_draft_id: Uuid },
+}
+
+pub const USAGE: &str = "Usage: `/newsletter status`, `/newsletter schedule <draft id> <time>` \
+    with an RFC 3339 time such as `2025-08-01T09:00:00Z`, or `/newsletter approve <draft id>`.";
+
+impl SlackCommand {
+    /// The error is meant for the Slack user.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["status"] => Ok(Self::Status),
+            ["schedule", draft, at] => Ok(Self::Schedule {
+                newsletter_draft_id: parse_draft_id(draft)?,
+                at: DateTime::parse_from_rfc3339(at)
+                    .map_err(|_| format!("`{}` is not an RFC 3339 time. {}", at, USAGE))?
+                    .with_timezone(&Utc),
+            }),
+            ["approve", draft] => Ok(Self::Approve {
+                newsletter_draft_id: parse_draft_id(draft)?,
+            }),
+            _ => Err(USAGE.to_owned()),
+        }
+    }
+}
+
+fn parse_draft_id(draft: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(draft).map_err(|_| format!("`{}` is not a draft id. {}", draft, USAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SlackCommand, verify_signature};
+    use chrono::{Duration, TimeZone, Utc};
+    use hmac::{Hmac, Mac};
+    use secrecy::SecretString;
+    use sha2::Sha256;
+
+    fn sign(timestamp: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"a-secret").unwrap();
+        mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+        format!("v0={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn secret() -> SecretString {
+        "a-secret".to_string().into()
+    }
+
+    #[test]
+    fn signed_requests_are_verified() {
+        let now = Utc::now();
+        let timestamp = now.timestamp().to_string();
+        let signature = sign(&timestamp, "text=status");
+        assert!(verify_signature(
+            &secret(),
+            &timestamp,
+            b"text=status",
+            &signature,
+            now
+        ));
+        assert!(!verify_signature(
+            &secret(),
+            &timestamp,
+            b"text=approve",
+            &signature,
+            now
+        ));
+    }
+
+    #[test]
+    fn old_requests_are_rejected() {
+        let now = Utc::now();
+        let timestamp = (now - Duration::minutes(10)).timestamp().to_string();
+        let signature = sign(&timestamp, "text=status");
+        assert!(!verify_signature(
+            &secret(),
+            &timestamp,
+            b"text=status",
+            &signature,
+            now
+        ));
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        let id = uuid::Uuid::new_v4();
+        assert_eq!(SlackCommand::parse(" status "), Ok(SlackCommand::Status));
+        assert_eq!(
+            SlackCommand::parse(&format!("schedule {} 2025-08-01T11:00:00+02:00", id)),
+            Ok(SlackCommand::Schedule {
+                newsletter_draft_id: id,
+                at: Utc.with_ymd_and_hms(2025, 8, 1, 9, 0, 0).unwrap(),
+            })
+        );
+        assert_eq!(
+            SlackCommand::parse(&format!("approve {}", id)),
+            Ok(SlackCommand::Approve {
+                newsletter_draft_id: id
+            })
+        );
+        assert!(SlackCommand::parse("approve not-a-draft").is_err());
+        assert!(SlackCommand::parse(&format!("schedule {} tomorrow", id)).is_err());
+        assert!(SlackCommand::parse("").is_err());
+    }
+}
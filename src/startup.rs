@@ -1,51 +1,469 @@
 use crate::EmailClient;
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::routes::{confirm, health_check, publish_newsletter, subscribe};
-use actix_web::dev::Server;
-use actix_web::{App, HttpServer, web::Data};
+use crate::admin_reports::AdminReportScheduler;
+use crate::alerts::OperationalAlerts;
+use crate::authentication::reject_anonymous_users;
+use crate::blob_store::BlobStore;
+use crate::branding::Branding;
+use crate::complaints::ComplaintAlerts;
+use crate::configuration::{
+    ConfirmationEmailSettings, DatabaseSettings, IdempotencySettings, InvitationSettings, Settings,
+    ShortLinkSettings, SignupAnomalySettings, SlackSettings, TlsSettings, TrackingSettings,
+};
+use crate::consent::ProofSigner;
+use crate::delivery::DeliveryWorker;
+use crate::digests::DigestScheduler;
+use crate::domain::SubscriberRules;
+use crate::draft_scheduler::DraftScheduler;
+use crate::encryption::FieldCipher;
+use crate::extensions::{DomainEvents, Extensions, run_extension_middleware};
+use crate::feature_flags::FeatureFlags;
+use crate::feed_watcher::FeedWatcher;
+use crate::imports::ImportWorker;
+use crate::issue_scheduler::IssueScheduler;
+use crate::jobs::Jobs;
+use crate::leader_election::LeaderElections;
+use crate::maintenance::{MaintenanceMode, reject_during_maintenance};
+use crate::postmaster::PostmasterIngester;
+use crate::publishing::SubscriberFooter;
+use crate::rate_limiting::{RateLimiter, rate_limit_signups};
+use crate::request_id::propagate_request_id;
+use crate::routes::{
+    add_verified_sender, admin_dashboard, cancel_newsletter_schedule, change_admin_password,
+    change_password_form, compare_newsletter_issues, complete_reengagement_campaign, confirm,
+    consent_proof_public_key, count_segment_recipients, create_draft_comment,
+    create_newsletter_draft, create_preview_link, create_saved_segment, delete_invitation_batch,
+    delete_own_subscription, delete_saved_segment, delete_subscriber, delete_template_fragment,
+    download_import_file, download_issue_event, export_consent_proofs,
+    export_subscriber_consent_proof, export_subscriber_data, follow_short_link, generate_api_key,
+    generate_invitation_batch, generate_subscriber_fixtures, get_deliverability,
+    get_delivery_status, get_email_endpoint_stats, get_leadership_metrics, get_maintenance_mode,
+    get_newsletter_audience, get_newsletter_deliveries, get_newsletter_engagement,
+    get_newsletter_issue, get_newsletter_link_stats, get_saved_segment, get_segment_history,
+    get_subscriber_engagement, get_subscriber_import, get_token_guard_metrics, health_check,
+    import_subscribers, list_all_subscribers, list_api_keys, list_draft_comments,
+    list_duplicate_subscribers, list_feature_flags, list_invitation_batches, list_jobs,
+    list_newsletter_drafts, list_newsletter_issues, list_quarantined_subscriptions,
+    list_saved_segments, list_template_fragments, list_verified_senders, log_in, log_out,
+    login_form, merge_duplicate_subscribers, preview_draft, preview_segment, publish_newsletter,
+    publish_newsletter_draft, publish_newsletter_form, receive_email_events, reengage,
+    reject_quarantined_subscription, release_quarantined_subscription, request_magic_link,
+    resend_confirmation, reset_feature_flag, resolve_draft_comment, resume_reengagement_campaign,
+    revoke_api_key, run_job, run_slack_command, search_subscribers, set_delivery_paused,
+    set_feature_flag, set_maintenance_mode, set_template_fragment, show_subscription_status,
+    start_reengagement_campaign, subscribe, subscriber_login_form, track_anonymous_open,
+    track_open, unsubscribe, unsubscribe_form, update_saved_segment,
+    update_subscription_preferences, verify_sender_token,
+};
+use crate::session_state::AdminSessionStore;
+use crate::signing::UrlSigner;
+use crate::telemetry::RequestRootSpan;
+use crate::templates::EmailTemplates;
+use crate::throttling::DeliveryThrottle;
+use crate::token_guard::{TokenGuard, guard_token_lookups};
+use crate::web_pages::PageFetcher;
+use actix_session::SessionMiddleware;
+use actix_session::storage::{CookieSessionStore, RedisSessionStore};
+use actix_web::cookie::Key;
+use actix_web::dev::{Server, ServerHandle};
+use actix_web::http::header::LOCATION;
+use actix_web::middleware::from_fn;
+use actix_web::web::{Data, ServiceConfig};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, guard, web};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha512};
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
-use std::net::TcpListener;
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
+/// How long to wait for the background workers to complete their current
+/// run on shutdown.
+const WORKERS_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for the database connections to close once the servers
+/// and workers stopped.
+const POOL_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parts of the application built from the [`Settings`] unless provided,
+/// e.g. by a binary embedding it through [`crate::Server`].
+#[derive(Default)]
+pub struct Components {
+    pg_pool: Option<PgPool>,
+    email_client: Option<EmailClient>,
+    listener: Option<TcpListener>,
+    extensions: Extensions,
+}
+
+impl Components {
+    /// Connections to the database, which must be migrated.
+    pub fn pg_pool(mut self, pg_pool: PgPool) -> Self {
+        self.pg_pool = Some(pg_pool);
+        self
+    }
+
+    pub fn email_client(mut self, email_client: EmailClient) -> Self {
+        self.email_client = Some(email_client);
+        self
+    }
+
+    /// Where the public routes are served, bound to `application.host` and
+    /// `application.port` unless provided.
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+}
+
 pub struct Application {
-    port: u16,
-    server: Server,
+    local_addr: SocketAddr,
+    admin_port: Option<u16>,
+    redirect_port: Option<u16>,
+    servers: Vec<Server>,
+    workers: Workers,
+    pg_pool: PgPool,
+}
+
+/// Built once, for the routes and the background workers alike.
+struct Shared {
+    pg_pool: PgPool,
+    email_client: Arc<EmailClient>,
+    throttle: Arc<DeliveryThrottle>,
+    cipher: FieldCipher,
+    email_templates: EmailTemplates,
+    page_fetcher: PageFetcher,
+}
+
+/// The background workers of an [`Application`], started when it runs and
+/// stopped along with its servers.
+#[derive(Default)]
+struct Workers {
+    shutdown: CancellationToken,
+    pending: Vec<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Workers {
+    fn add<F>(&mut self, worker: impl FnOnce(CancellationToken) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.pending.push(Box::pin(worker(self.shutdown.clone())));
+    }
+
+    fn start(&mut self) {
+        self.handles
+            .extend(self.pending.drain(..).map(tokio::spawn));
+    }
+
+    /// Let the workers complete their current run, aborting those still
+    /// running after [`WORKERS_STOP_TIMEOUT`].
+    async fn stop(&mut self) {
+        self.shutdown.cancel();
+        let handles = std::mem::take(&mut self.handles);
+        let aborts: Vec<_> = handles.iter().map(JoinHandle::abort_handle).collect();
+        if tokio::time::timeout(
+            WORKERS_STOP_TIMEOUT,
+            futures_util::future::join_all(handles),
+        )
+        .await
+        .is_err()
+        {
+            tracing::warn!("Timed out waiting for the background workers to stop");
+            aborts.iter().for_each(AbortHandle::abort);
+        }
+    }
+}
+
+/// Workers of an application dropped while running, e.g. along with the
+/// future of [`Application::run_until`], do not outlive it.
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+        self.handles.iter().for_each(JoinHandle::abort);
+    }
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
-        let pg_pool = get_connection_pool(&configuration.database);
+        Self::build_with(configuration, Components::default()).await
+    }
 
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            configuration.email_client.sender_email,
-            configuration.email_client.authorization_token,
-            configuration.email_client.timeout,
-        );
+    /// Build the application around the `components` provided, the others
+    /// being built from `configuration`.
+    pub async fn build_with(
+        configuration: Settings,
+        components: Components,
+    ) -> Result<Self, std::io::Error> {
+        configuration
+            .validate()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let pg_pool = components
+            .pg_pool
+            .unwrap_or_else(|| get_connection_pool(&configuration.database));
 
-        let address = format!(
-            "{}:{}",
-            configuration.application.host, configuration.application.port
+        let email_client = match components.email_client {
+            Some(email_client) => email_client,
+            None => configuration
+                .email_client
+                .client()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        };
+        let email_client = Arc::new(email_client.with_pause_switch(
+            FeatureFlags::new(configuration.feature_flags.clone()),
+            pg_pool.clone(),
+        ));
+        let throttle = Arc::new(DeliveryThrottle::new(&configuration.delivery_throttling));
+        let shared = Shared {
+            pg_pool: pg_pool.clone(),
+            email_client: email_client.clone(),
+            throttle: throttle.clone(),
+            cipher: FieldCipher::new(configuration.encryption.as_ref()),
+            email_templates: EmailTemplates::new(configuration.email_templates.clone()),
+            page_fetcher: PageFetcher::new(&configuration.web_pages),
+        };
+        let events = components.extensions.events();
+        let mut jobs = Jobs::default();
+        let mut workers = Workers::default();
+        let delivery_worker = DeliveryWorker::build(
+            &configuration,
+            pg_pool.clone(),
+            email_client.clone(),
+            throttle.clone(),
         );
-        let listener = TcpListener::bind(address).expect("Failed to bind port 8080");
-        let port = listener.local_addr()?.port();
-        let server = run(
-            listener,
-            pg_pool,
-            email_client,
-            ApplicationBaseUrl(configuration.application.base_url),
+        let delivery_worker_job = delivery_worker.job();
+        jobs.0.push(delivery_worker_job.clone());
+        workers.add(|shutdown| delivery_worker.run_until_stopped(shutdown));
+        let draft_scheduler = DraftScheduler::build(
+            &configuration,
+            pg_pool.clone(),
+            email_client.clone(),
+            throttle.clone(),
+            shared.email_templates.clone(),
+            shared.cipher.clone(),
+            shared.page_fetcher.clone(),
+        )
+        .with_events(events.clone());
+        jobs.0.push(draft_scheduler.job());
+        workers.add(|shutdown| draft_scheduler.run_until_stopped(shutdown));
+        let issue_scheduler = IssueScheduler::build(pg_pool.clone(), delivery_worker_job.clone())
+            .with_events(events.clone());
+        jobs.0.push(issue_scheduler.job());
+        workers.add(|shutdown| issue_scheduler.run_until_stopped(shutdown));
+        let import_worker = ImportWorker::build(pg_pool.clone(), configuration.storage.store());
+        jobs.0.push(import_worker.job());
+        workers.add(|shutdown| import_worker.run_until_stopped(shutdown));
+        let mut leader_elections = LeaderElections::default();
+        if let Some(feed_watcher) = FeedWatcher::build(
+            &configuration,
+            pg_pool.clone(),
+            email_client.clone(),
+            throttle.clone(),
+        ) {
+            let feed_watcher = feed_watcher.with_events(events.clone());
+            jobs.0.push(feed_watcher.job());
+            leader_elections.0.push(feed_watcher.leader_election());
+            workers.add(|shutdown| feed_watcher.run_until_stopped(shutdown));
+        }
+        if let Some(digest_scheduler) = DigestScheduler::build(
+            &configuration,
+            pg_pool.clone(),
+            email_client.clone(),
+            throttle.clone(),
+        ) {
+            let digest_scheduler = digest_scheduler.with_events(events.clone());
+            jobs.0.push(digest_scheduler.job());
+            leader_elections.0.push(digest_scheduler.leader_election());
+            workers.add(|shutdown| digest_scheduler.run_until_stopped(shutdown));
+        }
+        if let Some(admin_reports) =
+            AdminReportScheduler::build(&configuration, pg_pool.clone(), email_client.clone())
+        {
+            jobs.0.push(admin_reports.job());
+            leader_elections.0.push(admin_reports.leader_election());
+            workers.add(|shutdown| admin_reports.run_until_stopped(shutdown));
+        }
+        if let Some(alerts) =
+            OperationalAlerts::build(&configuration, pg_pool.clone(), email_client.clone())
+        {
+            jobs.0.push(alerts.job());
+            workers.add(|shutdown| alerts.run_until_stopped(shutdown));
+        }
+
+        if let Some(postmaster) = PostmasterIngester::build(&configuration, pg_pool.clone()) {
+            jobs.0.push(postmaster.job());
+            leader_elections.0.push(postmaster.leader_election());
+            workers.add(|shutdown| postmaster.run_until_stopped(shutdown));
+        }
+
+        let listener = match components.listener {
+            Some(listener) => listener,
+            None => TcpListener::bind(format!(
+                "{}:{}",
+                configuration.application.host, configuration.application.port
+            ))?,
+        };
+        let local_addr = listener.local_addr()?;
+        let admin_listener = match &configuration.application.admin_listener {
+            Some(admin) => Some(TcpListener::bind(format!("{}:{}", admin.host, admin.port))?),
+            None => None,
+        };
+        let admin_port = match &admin_listener {
+            Some(admin_listener) => Some(admin_listener.local_addr()?.port()),
+            None => None,
+        };
+        let redirect_listener = match &configuration.application.tls {
+            Some(TlsSettings {
+                redirect_listener: Some(redirect),
+                ..
+            }) => Some(TcpListener::bind(format!(
+                "{}:{}",
+                redirect.host, redirect.port
+            ))?),
+            _ => None,
+        };
+        let redirect_port = match &redirect_listener {
+            Some(redirect_listener) => Some(redirect_listener.local_addr()?.port()),
+            None => None,
+        };
+        let session_store = match &configuration.redis_uri {
+            Some(redis_uri) => AdminSessionStore::Redis(
+                RedisSessionStore::new(redis_uri.expose_secret())
+                    .await
+                    .map_err(std::io::Error::other)?,
+            ),
+            None => AdminSessionStore::Cookie(CookieSessionStore::default()),
+        };
+        let listeners = Listeners {
+            public: listener,
+            admin: admin_listener,
+            redirect: redirect_listener,
+        };
+        let servers = run(
+            listeners,
+            jobs,
+            leader_elections,
+            session_store,
+            shared,
+            components.extensions,
+            configuration,
         )?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            local_addr,
+            admin_port,
+            redirect_port,
+            servers,
+            workers,
+            pg_pool,
+        })
     }
 
     pub fn port(&self) -> u16 {
-        self.port
+        self.local_addr.port()
     }
 
+    /// Address the public routes are served on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Port of the admin listener, if it is separate from the public one.
+    pub fn admin_port(&self) -> Option<u16> {
+        self.admin_port
+    }
+
+    /// Port of the plain HTTP listener redirecting to HTTPS, if any.
+    pub fn redirect_port(&self) -> Option<u16> {
+        self.redirect_port
+    }
+
+    /// Serve until SIGTERM or SIGINT, see [`Application::run_until`].
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        self.run_until(shutdown_signal()).await
+    }
+
+    /// Serve until `shutdown` completes, then let the background workers
+    /// complete their current run. New connections are refused next, and
+    /// in-flight requests given `application.shutdown_timeout` to complete
+    /// before closing the connections to the database.
+    pub async fn run_until(
+        mut self,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), std::io::Error> {
+        self.workers.start();
+        let handles: Vec<ServerHandle> = self.servers.iter().map(Server::handle).collect();
+        let servers = async {
+            futures_util::future::try_join_all(std::mem::take(&mut self.servers))
+                .await
+                .map(|_| ())
+        };
+        tokio::pin!(servers);
+        let outcome = tokio::select! {
+            outcome = &mut servers => outcome,
+            _ = shutdown => {
+                // Workers share connections opened by the HTTP workers, which
+                // are bound to their runtime: they stop first.
+                tracing::info!("Shutting down, waiting for the background workers to stop");
+                self.workers.stop().await;
+                tracing::info!("Waiting for in-flight requests to complete");
+                // Servers carry out the stop while they are polled.
+                let stopped = futures_util::future::join_all(handles.iter().map(|h| h.stop(true)));
+                let (_, outcome) = tokio::join!(stopped, &mut servers);
+                outcome
+            }
+        };
+        self.workers.stop().await;
+        // Connections opened by the stopped HTTP workers are bound to their
+        // runtime and may never complete a graceful close, they are dropped.
+        if tokio::time::timeout(POOL_CLOSE_TIMEOUT, self.pg_pool.close())
+            .await
+            .is_err()
+        {
+            tracing::warn!("Timed out closing the connections to the database");
+        }
+        tracing::info!("Shut down");
+        outcome
+    }
+}
+
+/// Completes on SIGTERM, e.g. from Kubernetes during a rollout, or SIGINT.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error.cause_chain = ?e, "Failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => tracing::info!("Received SIGINT"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
     }
 }
 
@@ -57,28 +475,424 @@ pub fn get_connection_pool(db_configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+/// Where the links embedded in emails point to, see
+/// [`ApplicationSettings::link_base_url`](crate::configuration::ApplicationSettings::link_base_url).
+pub struct LinkBaseUrl(pub String);
+
+pub struct EmailWebhookToken(pub SecretString);
+
+/// State shared by the handlers of both listeners.
+#[derive(Clone)]
+struct AppState {
+    pg_pool: Data<PgPool>,
+    cipher: Data<FieldCipher>,
+    email_client: Data<EmailClient>,
+    throttle: Data<DeliveryThrottle>,
+    base_url: Data<ApplicationBaseUrl>,
+    link_base_url: Data<LinkBaseUrl>,
+    url_signer: Data<UrlSigner>,
+    proof_signer: Data<ProofSigner>,
+    subscriber_footer: Data<SubscriberFooter>,
+    email_webhook_token: Data<EmailWebhookToken>,
+    slack_settings: Data<Option<SlackSettings>>,
+    email_templates: Data<EmailTemplates>,
+    short_link_settings: Data<ShortLinkSettings>,
+    idempotency_settings: Data<IdempotencySettings>,
+    tracking_settings: Data<TrackingSettings>,
+    token_guard: Data<TokenGuard>,
+    rate_limiter: Data<RateLimiter>,
+    signup_anomaly_settings: Data<SignupAnomalySettings>,
+    subscriber_rules: Data<SubscriberRules>,
+    invitation_settings: Data<InvitationSettings>,
+    confirmation_email_settings: Data<ConfirmationEmailSettings>,
+    complaint_alerts: Data<ComplaintAlerts>,
+    maintenance: Data<MaintenanceMode>,
+    branding: Data<Branding>,
+    feature_flags: Data<FeatureFlags>,
+    jobs: Data<Jobs>,
+    leader_elections: Data<LeaderElections>,
+    blob_store: Data<dyn BlobStore>,
+    page_fetcher: Data<PageFetcher>,
+    extensions: Data<Extensions>,
+    domain_events: Data<DomainEvents>,
+}
+
+impl AppState {
+    fn register(&self, cfg: &mut ServiceConfig) {
+        cfg.app_data(self.pg_pool.clone())
+            .app_data(self.cipher.clone())
+            .app_data(self.email_client.clone())
+            .app_data(self.throttle.clone())
+            .app_data(self.base_url.clone())
+            .app_data(self.link_base_url.clone())
+            .app_data(self.url_signer.clone())
+            .app_data(self.proof_signer.clone())
+            .app_data(self.subscriber_footer.clone())
+            .app_data(self.email_webhook_token.clone())
+            .app_data(self.slack_settings.clone())
+            .app_data(self.email_templates.clone())
+            .app_data(self.short_link_settings.clone())
+            .app_data(self.idempotency_settings.clone())
+            .app_data(self.tracking_settings.clone())
+            .app_data(self.token_guard.clone())
+            .app_data(self.rate_limiter.clone())
+            .app_data(self.signup_anomaly_settings.clone())
+            .app_data(self.subscriber_rules.clone())
+            .app_data(self.invitation_settings.clone())
+            .app_data(self.confirmation_email_settings.clone())
+            .app_data(self.complaint_alerts.clone())
+            .app_data(self.maintenance.clone())
+            .app_data(self.branding.clone())
+            .app_data(self.feature_flags.clone())
+            .app_data(self.jobs.clone())
+            .app_data(self.leader_elections.clone())
+            .app_data(self.blob_store.clone())
+            .app_data(self.page_fetcher.clone())
+            .app_data(self.extensions.clone())
+            .app_data(self.domain_events.clone());
+    }
+}
+
+/// Routes reached by subscribers, their email client and the email provider.
+fn public_routes(cfg: &mut ServiceConfig) {
+    cfg.service(health_check)
+        .service(subscribe)
+        .service(confirm)
+        .service(resend_confirmation)
+        .service(show_subscription_status)
+        .service(update_subscription_preferences)
+        .service(delete_own_subscription)
+        .service(export_subscriber_data)
+        .service(unsubscribe_form)
+        .service(unsubscribe)
+        .service(subscriber_login_form)
+        .service(request_magic_link)
+        .service(reengage)
+        .service(preview_draft)
+        .service(consent_proof_public_key)
+        .service(follow_short_link)
+        .service(download_issue_event)
+        .service(track_open)
+        .service(track_anonymous_open)
+        .service(receive_email_events)
+        .service(run_slack_command);
+}
+
+/// Routes the links of emails point to, the only ones served on a custom
+/// link domain.
+fn link_routes(cfg: &mut ServiceConfig) {
+    cfg.service(show_subscription_status)
+        .service(update_subscription_preferences)
+        .service(delete_own_subscription)
+        .service(export_subscriber_data)
+        .service(unsubscribe_form)
+        .service(unsubscribe)
+        .service(follow_short_link)
+        .service(download_issue_event)
+        .service(track_open)
+        .service(track_anonymous_open);
+}
+
+/// Routes reached by the newsletter authors and operators.
+fn admin_routes(cfg: &mut ServiceConfig, enable_dev_routes: bool) {
+    cfg.service(login_form)
+        .service(log_in)
+        .service(log_out)
+        .service(change_admin_password)
+        .service(export_consent_proofs)
+        .service(export_subscriber_consent_proof)
+        .service(list_all_subscribers)
+        .service(delete_subscriber)
+        .service(search_subscribers)
+        .service(list_duplicate_subscribers)
+        .service(merge_duplicate_subscribers)
+        .service(import_subscribers)
+        .service(get_subscriber_import)
+        .service(download_import_file)
+        .service(list_saved_segments)
+        .service(create_saved_segment)
+        .service(preview_segment)
+        .service(get_saved_segment)
+        .service(update_saved_segment)
+        .service(delete_saved_segment)
+        .service(count_segment_recipients)
+        .service(get_segment_history)
+        .service(publish_newsletter)
+        .service(cancel_newsletter_schedule)
+        .service(create_newsletter_draft)
+        .service(list_newsletter_drafts)
+        .service(publish_newsletter_draft)
+        .service(create_preview_link)
+        .service(create_draft_comment)
+        .service(list_draft_comments)
+        .service(resolve_draft_comment)
+        .service(list_newsletter_issues)
+        .service(get_newsletter_issue)
+        .service(get_newsletter_link_stats)
+        .service(get_newsletter_engagement)
+        .service(get_newsletter_audience)
+        .service(get_newsletter_deliveries)
+        .service(get_subscriber_engagement)
+        .service(start_reengagement_campaign)
+        .service(complete_reengagement_campaign)
+        .service(resume_reengagement_campaign)
+        .service(get_deliverability)
+        .service(get_email_endpoint_stats)
+        .service(compare_newsletter_issues)
+        .service(get_delivery_status)
+        .service(set_delivery_paused)
+        .service(get_token_guard_metrics)
+        .service(list_quarantined_subscriptions)
+        .service(release_quarantined_subscription)
+        .service(reject_quarantined_subscription)
+        .service(get_maintenance_mode)
+        .service(set_maintenance_mode)
+        .service(list_api_keys)
+        .service(generate_api_key)
+        .service(revoke_api_key)
+        .service(list_invitation_batches)
+        .service(generate_invitation_batch)
+        .service(delete_invitation_batch)
+        .service(list_verified_senders)
+        .service(add_verified_sender)
+        .service(verify_sender_token)
+        .service(list_feature_flags)
+        .service(set_feature_flag)
+        .service(reset_feature_flag)
+        .service(list_template_fragments)
+        .service(set_template_fragment)
+        .service(delete_template_fragment)
+        .service(get_leadership_metrics)
+        .service(list_jobs)
+        .service(run_job);
+    if enable_dev_routes {
+        cfg.service(generate_subscriber_fixtures);
+    }
+    // Last, so that the routes above are matched first: the scope answers
+    // every other path under `/admin`.
+    cfg.service(
+        web::scope("/admin")
+            .wrap(from_fn(reject_anonymous_users))
+            .service(admin_dashboard)
+            .service(publish_newsletter_form)
+            .service(change_password_form),
+    );
+}
+
+/// The sockets an [`Application`] accepts connections on.
+struct Listeners {
+    public: TcpListener,
+    /// Serves the admin routes, then left out of the public listener.
+    admin: Option<TcpListener>,
+    /// Sends plain HTTP requests over to HTTPS.
+    redirect: Option<TcpListener>,
+}
+
+/// Serve the public routes on the public listener, and the admin ones on the
+/// admin listener if any, alongside the public routes otherwise.
 fn run(
-    listener: TcpListener,
-    pg_pool: PgPool,
-    email_client: EmailClient,
-    base_url: ApplicationBaseUrl,
-) -> Result<Server, std::io::Error> {
-    let pg_pool = Data::new(pg_pool);
-    let email_client = Data::new(email_client);
-    let base_url = Data::new(base_url);
+    listeners: Listeners,
+    jobs: Jobs,
+    leader_elections: LeaderElections,
+    session_store: AdminSessionStore,
+    shared: Shared,
+    extensions: Extensions,
+    configuration: Settings,
+) -> Result<Vec<Server>, std::io::Error> {
+    let tls_config = match &configuration.application.tls {
+        Some(tls) => Some(rustls_config(tls)?),
+        None => None,
+    };
+    let redirect_base_url = Data::new(ApplicationBaseUrl(
+        configuration.application.base_url.clone(),
+    ));
+    let link_base_url = configuration.application.link_base_url().to_owned();
+    let link_host = configuration.application.link_host();
+    // Cookie keys need 64 bytes, whatever the length of the secret.
+    let session_key = Key::from(&Sha512::digest(
+        configuration.application.hmac_secret.expose_secret(),
+    ));
+    let secure_cookies = configuration.application.base_url.starts_with("https://");
+    let url_signer = UrlSigner::new(configuration.application.hmac_secret);
+    let state = AppState {
+        pg_pool: Data::new(shared.pg_pool),
+        cipher: Data::new(shared.cipher),
+        email_client: Data::from(shared.email_client),
+        throttle: Data::from(shared.throttle),
+        link_base_url: Data::new(LinkBaseUrl(link_base_url)),
+        base_url: Data::new(ApplicationBaseUrl(configuration.application.base_url)),
+        subscriber_footer: Data::new(SubscriberFooter::new(
+            shared.email_templates.clone(),
+            url_signer.clone(),
+        )),
+        url_signer: Data::new(url_signer),
+        proof_signer: Data::new(ProofSigner::new(&configuration.consent_proofs)),
+        email_webhook_token: Data::new(EmailWebhookToken(configuration.email_client.webhook_token)),
+        slack_settings: Data::new(configuration.slack),
+        email_templates: Data::new(shared.email_templates),
+        short_link_settings: Data::new(configuration.short_links),
+        idempotency_settings: Data::new(configuration.idempotency),
+        tracking_settings: Data::new(configuration.tracking),
+        token_guard: Data::new(TokenGuard::new(configuration.token_guard)),
+        rate_limiter: Data::new(RateLimiter::new(configuration.application.rate_limit)),
+        signup_anomaly_settings: Data::new(configuration.signup_anomalies),
+        subscriber_rules: Data::new(SubscriberRules::new(
+            configuration.subscriber_validation,
+            extensions.validators(),
+        )),
+        invitation_settings: Data::new(configuration.invitations),
+        confirmation_email_settings: Data::new(configuration.confirmation_emails),
+        complaint_alerts: Data::new(ComplaintAlerts::new(configuration.complaints)),
+        maintenance: Data::new(MaintenanceMode::new(&configuration.maintenance)),
+        branding: Data::new(Branding::new(configuration.branding)),
+        feature_flags: Data::new(FeatureFlags::new(configuration.feature_flags)),
+        jobs: Data::new(jobs),
+        leader_elections: Data::new(leader_elections),
+        blob_store: Data::from(configuration.storage.store()),
+        page_fetcher: Data::new(shared.page_fetcher),
+        domain_events: Data::new(extensions.events()),
+        extensions: Data::new(extensions),
+    };
+    let enable_dev_routes = configuration.application.enable_dev_routes;
+    let serve_admin_routes = listeners.admin.is_none();
+    // Signals are handled by `Application::run_until_stopped`, which stops
+    // both servers together. Actix counts the timeout in whole seconds.
+    let shutdown_timeout =
+        (configuration.application.shutdown_timeout.as_millis() as u64).div_ceil(1000);
 
+    let public_state = state.clone();
+    let public_session_store = session_store.clone();
+    let public_session_key = session_key.clone();
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(TracingLogger::default())
-            .app_data(pg_pool.clone())
-            .app_data(email_client.clone())
-            .app_data(base_url.clone())
-            .service(health_check)
-            .service(subscribe)
-            .service(confirm)
-            .service(publish_newsletter)
+            .wrap(from_fn(guard_token_lookups))
+            .wrap(from_fn(rate_limit_signups))
+            .wrap(from_fn(reject_during_maintenance))
+            .wrap(session_middleware(
+                public_session_store.clone(),
+                public_session_key.clone(),
+                secure_cookies,
+            ))
+            .wrap(from_fn(run_extension_middleware))
+            .wrap(from_fn(propagate_request_id))
+            .wrap(TracingLogger::<RequestRootSpan>::new())
+            .configure(|cfg| public_state.register(cfg))
+            .configure(|cfg| {
+                if let Some(link_host) = &link_host {
+                    cfg.service(
+                        web::scope("")
+                            .guard(guard::Host(link_host))
+                            .configure(link_routes),
+                    );
+                }
+            })
+            .configure(public_routes)
+            .configure(|cfg| {
+                if serve_admin_routes {
+                    admin_routes(cfg, enable_dev_routes);
+                }
+            })
+            .configure(|cfg| public_state.extensions.configure(cfg))
     })
-    .listen(listener)?
-    .run();
-    Ok(server)
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout);
+    let server = match tls_config {
+        Some(tls_config) => server.listen_rustls_0_23(listeners.public, tls_config)?,
+        None => server.listen(listeners.public)?,
+    };
+    let admin_server = listeners
+        .admin
+        .map(|admin_listener| {
+            HttpServer::new(move || {
+                App::new()
+                    .wrap(from_fn(rate_limit_signups))
+                    .wrap(session_middleware(
+                        session_store.clone(),
+                        session_key.clone(),
+                        secure_cookies,
+                    ))
+                    .wrap(from_fn(run_extension_middleware))
+                    .wrap(from_fn(propagate_request_id))
+                    .wrap(TracingLogger::<RequestRootSpan>::new())
+                    .configure(|cfg| state.register(cfg))
+                    .configure(|cfg| admin_routes(cfg, enable_dev_routes))
+            })
+            .disable_signals()
+            .shutdown_timeout(shutdown_timeout)
+            .listen(admin_listener)
+        })
+        .transpose()?;
+    let redirect_server = listeners
+        .redirect
+        .map(|redirect_listener| {
+            HttpServer::new(move || {
+                App::new()
+                    .wrap(TracingLogger::<RequestRootSpan>::new())
+                    .app_data(redirect_base_url.clone())
+                    .default_service(web::to(redirect_to_https))
+            })
+            .disable_signals()
+            .shutdown_timeout(shutdown_timeout)
+            .listen(redirect_listener)
+        })
+        .transpose()?;
+
+    // Started once every listener is set up, none is left running when
+    // another fails.
+    let mut servers = vec![server.run()];
+    servers.extend(admin_server.map(HttpServer::run));
+    servers.extend(redirect_server.map(HttpServer::run));
+    Ok(servers)
+}
+
+/// Certificate chain and private key of `tls`, read from their PEM files.
+fn rustls_config(tls: &TlsSettings) -> Result<rustls::ServerConfig, std::io::Error> {
+    let invalid = |path: &Path, e: &dyn std::fmt::Display| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Failed to read {}: {}", path.display(), e),
+        )
+    };
+    let certificates = CertificateDer::pem_file_iter(&tls.cert_path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(&tls.cert_path, &e))?;
+    let key =
+        PrivateKeyDer::from_pem_file(&tls.key_path).map_err(|e| invalid(&tls.key_path, &e))?;
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(std::io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+/// Send the request over to the same path on `base_url`, keeping its
+/// method.
+async fn redirect_to_https(
+    request: HttpRequest,
+    base_url: Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    HttpResponse::PermanentRedirect()
+        .insert_header((
+            LOCATION,
+            format!("{}{}", base_url.0.trim_end_matches('/'), path),
+        ))
+        .finish()
+}
+
+/// Sessions of the admins logged in through `/login`, stored in
+/// `session_store`.
+fn session_middleware(
+    session_store: AdminSessionStore,
+    key: Key,
+    secure: bool,
+) -> SessionMiddleware<AdminSessionStore> {
+    SessionMiddleware::builder(session_store, key)
+        .cookie_secure(secure)
+        .build()
 }
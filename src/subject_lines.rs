@@ -0,0 +1,233 @@
+//! Subjects as mail clients will show them: encoded for providers that relay
+//! them as is over SMTP, and checked for what clients cut off.
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Bytes of UTF-8 an encoded word holds: its 75 characters, minus the
+/// `=?UTF-8?B?` and `?=` around it, are 60 characters of base64.
+const ENCODED_WORD_BYTES: usize = 45;
+
+/// Columns of the subject shown in the message list of common clients,
+/// from the narrowest.
+const CLIENT_WIDTHS: &[(&str, usize)] = &[
+    ("phones in portrait", 40),
+    ("Outlook on the desktop", 55),
+    ("Gmail on the web", 70),
+];
+
+/// `subject` as RFC 2047 encoded words when it is not plain ASCII, as SMTP
+/// headers cannot carry anything else.
+///
+/// Words are split between graphemes, so that clients decoding them one by
+/// one do not break an emoji apart.
+pub fn encode_subject(subject: &str) -> Cow<'_, str> {
+    if subject.is_ascii() && !subject.contains("=?") {
+        return Cow::Borrowed(subject);
+    }
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for grapheme in subject.graphemes(true) {
+        // A grapheme too long for a word of its own, e.g. a family emoji,
+        // is split between code points.
+        let pieces: Vec<&str> = if grapheme.len() > ENCODED_WORD_BYTES {
+            grapheme
+                .char_indices()
+                .map(|(i, c)| &grapheme[i..i + c.len_utf8()])
+                .collect()
+        } else {
+            vec![grapheme]
+        };
+        for piece in pieces {
+            if word.len() + piece.len() > ENCODED_WORD_BYTES {
+                words.push(std::mem::take(&mut word));
+            }
+            word.push_str(piece);
+        }
+    }
+    words.push(word);
+    Cow::Owned(
+        words
+            .iter()
+            .map(|word| format!("=?UTF-8?B?{}?=", BASE64_STANDARD.encode(word)))
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// A subject that will not look in every inbox as it does when written.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SubjectWarning {
+    /// The subject is cut off in the message list of `client`, which only
+    /// shows `shown`.
+    Truncated {
+        client: &'static str,
+        width: usize,
+        shown: String,
+    },
+    /// An emoji made of several, e.g. with a skin tone, which clients
+    /// without support for it show as its parts.
+    ComposedEmoji { emoji: String },
+}
+
+impl std::fmt::Display for SubjectWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubjectWarning::Truncated { client, shown, .. } => {
+                write!(f, "{} only show \"{}\"", client, shown)
+            }
+            SubjectWarning::ComposedEmoji { emoji } => {
+                write!(f, "{} may be shown as separate emoji", emoji)
+            }
+        }
+    }
+}
+
+/// Look for what common clients will cut off or render differently.
+///
+/// Emoji take two columns, as they do in most clients.
+pub fn check_subject(subject: &str) -> Vec<SubjectWarning> {
+    let mut warnings = Vec::new();
+    for &(client, width) in CLIENT_WIDTHS {
+        let mut columns = 0;
+        let mut shown = String::new();
+        for grapheme in subject.graphemes(true) {
+            columns += grapheme_width(grapheme);
+            if columns > width {
+                warnings.push(SubjectWarning::Truncated {
+                    client,
+                    width,
+                    shown: format!("{}…", shown.trim_end()),
+                });
+                break;
+            }
+            shown.push_str(grapheme);
+        }
+    }
+    let mut composed: Vec<&str> = Vec::new();
+    for grapheme in subject.graphemes(true) {
+        if is_emoji(grapheme)
+            && grapheme.chars().filter(|&c| !is_emoji_selector(c)).count() > 1
+            && !composed.contains(&grapheme)
+        {
+            composed.push(grapheme);
+        }
+    }
+    warnings.extend(
+        composed
+            .into_iter()
+            .map(|emoji| SubjectWarning::ComposedEmoji {
+                emoji: emoji.to_owned(),
+            }),
+    );
+    warnings
+}
+
+fn grapheme_width(grapheme: &str) -> usize {
+    if is_emoji(grapheme) { 2 } else { 1 }
+}
+
+fn is_emoji(grapheme: &str) -> bool {
+    grapheme.chars().any(|c| {
+        matches!(c as u32,
+            0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F)
+    })
+}
+
+/// Code points that change how the emoji before them is shown without
+/// being one: the emoji presentation selector.
+fn is_emoji_selector(c: char) -> bool {
+    c == '\u{FE0F}'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubjectWarning, check_subject, encode_subject};
+    use base64::Engine;
+    use base64::prelude::BASE64_STANDARD;
+
+    fn decode(encoded: &str) -> String {
+        encoded
+            .split(' ')
+            .map(|word| {
+                assert!(word.len() <= 75, "{} is too long for an encoded word", word);
+                let base64 = word
+                    .strip_prefix("=?UTF-8?B?")
+                    .and_then(|w| w.strip_suffix("?="))
+                    .unwrap();
+                String::from_utf8(BASE64_STANDARD.decode(base64).unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ascii_subjects_are_left_as_is() {
+        assert_eq!(
+            encode_subject("Issue #12: what's new"),
+            "Issue #12: what's new"
+        );
+    }
+
+    #[test]
+    fn other_subjects_are_encoded_words() {
+        let subject = "🎉 Ünïcödé launch party 🎉 with 👩‍👩‍👧‍👦 and 👍🏽, see you there!";
+
+        let encoded = encode_subject(subject);
+
+        assert!(encoded.is_ascii());
+        assert!(encoded.contains(' '));
+        assert_eq!(decode(&encoded), subject);
+    }
+
+    #[test]
+    fn ascii_looking_like_an_encoded_word_is_encoded() {
+        let subject = "Try =?UTF-8?B?aGk=?= yourself";
+
+        assert_eq!(decode(&encode_subject(subject)), subject);
+    }
+
+    #[test]
+    fn short_subjects_are_fine() {
+        assert_eq!(check_subject("🎉 Our first issue"), []);
+    }
+
+    #[test]
+    fn emoji_count_twice_towards_truncation() {
+        let subject = format!("{}{}", "🎉".repeat(15), "a".repeat(15));
+
+        assert_eq!(
+            check_subject(&subject),
+            [SubjectWarning::Truncated {
+                client: "phones in portrait",
+                width: 40,
+                shown: format!("{}{}…", "🎉".repeat(15), "a".repeat(10)),
+            }]
+        );
+    }
+
+    #[test]
+    fn long_subjects_are_truncated_by_every_client_they_overflow() {
+        let warnings = check_subject(&"word ".repeat(15));
+
+        let widths: Vec<_> = warnings
+            .iter()
+            .map(|w| match w {
+                SubjectWarning::Truncated { width, .. } => *width,
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(widths, [40, 55, 70]);
+    }
+
+    #[test]
+    fn composed_emoji_are_reported_once() {
+        assert_eq!(
+            check_subject("👍🏽 ❤️ 👍🏽 🎉"),
+            [SubjectWarning::ComposedEmoji {
+                emoji: "👍🏽".into()
+            }]
+        );
+    }
+}
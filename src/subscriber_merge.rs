@@ -0,0 +1,503 @@
+//! Duplicate subscribers, as left by importing lists from several sources,
+//! and their merge into a single record.
+use crate::repositories::subscribers::unsubscribe_subscribers;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Statuses of merged records, the first one held by any of them winning.
+/// An unsubscribe is always kept, the person having asked to stop receiving
+/// the newsletter from one of their addresses.
+const STATUS_PRECEDENCE: [&str; 4] = [
+    "unsubscribed",
+    "confirmed",
+    "pending_confirmation",
+    "quarantined",
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum MergeError {
+    #[error("A subscriber cannot be merged into itself.")]
+    SameSubscriber,
+    #[error("There is no subscriber with id {0}.")]
+    UnknownSubscriber(Uuid),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// Subscribers sharing an address, oldest signup first.
+#[derive(serde::Serialize)]
+pub struct DuplicateGroup {
+    /// `email` when the addresses only differ by case or surrounding spaces,
+    /// `fuzzy` when they are variants of the same mailbox.
+    pub matched_on: &'static str,
+    pub subscribers: Vec<DuplicateSubscriber>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DuplicateSubscriber {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// The record left by a merge.
+#[derive(serde::Serialize)]
+pub struct MergedSubscriber {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    /// Records merged into this one and deleted.
+    pub merged: usize,
+}
+
+/// Key of the subscribers considered the same person: the address, trimmed
+/// and lowercased.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Looser key, also matching the variants of an address most providers
+/// deliver to the same mailbox: `+` suffixes and, for Gmail, dots and the
+/// `googlemail.com` domain.
+pub fn fuzzy_email_key(email: &str) -> String {
+    let email = normalize_email(email);
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return email;
+    };
+    let local = local.split('+').next().unwrap_or(local);
+    match domain {
+        "gmail.com" | "googlemail.com" => format!("{}@gmail.com", local.replace('.', "")),
+        _ => format!("{}@{}", local, domain),
+    }
+}
+
+/// Status of the record left by merging records with `statuses`.
+fn merged_status<'a>(statuses: impl IntoIterator<Item = &'a str>) -> &'a str {
+    statuses
+        .into_iter()
+        .min_by_key(|status| {
+            STATUS_PRECEDENCE
+                .iter()
+                .position(|s| s == status)
+                .unwrap_or(STATUS_PRECEDENCE.len())
+        })
+        .unwrap_or("pending_confirmation")
+}
+
+/// Groups of subscribers with the same normalized address or, with `fuzzy`,
+/// the same [`fuzzy_email_key`], ordered by that key.
+#[tracing::instrument(name = "Find duplicate subscribers", skip(pg_pool))]
+pub async fn find_duplicates(
+    pg_pool: &PgPool,
+    fuzzy: bool,
+) -> Result<Vec<DuplicateGroup>, sqlx::Error> {
+    let subscribers = sqlx::query_as!(
+        DuplicateSubscriber,
+        r#"
+        SELECT id AS subscriber_id, email, status, subscribed_at
+        FROM subscriptions
+        ORDER BY subscribed_at, id
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+    let mut groups: BTreeMap<String, Vec<DuplicateSubscriber>> = BTreeMap::new();
+    for subscriber in subscribers {
+        let key = if fuzzy {
+            fuzzy_email_key(&subscriber.email)
+        } else {
+            normalize_email(&subscriber.email)
+        };
+        groups.entry(key).or_default().push(subscriber);
+    }
+    Ok(groups
+        .into_values()
+        .filter(|subscribers| subscribers.len() > 1)
+        .map(|subscribers| {
+            let email = normalize_email(&subscribers[0].email);
+            let matched_on = if subscribers
+                .iter()
+                .all(|s| normalize_email(&s.email) == email)
+            {
+                "email"
+            } else {
+                "fuzzy"
+            };
+            DuplicateGroup {
+                matched_on,
+                subscribers,
+            }
+        })
+        .collect())
+}
+
+/// Merge `duplicates` into the subscriber `subscriber_id`, which keeps its
+/// address and name, and delete them.
+///
+/// The tags, deliveries, engagement and consent of the duplicates move to
+/// the record kept, which signed up when the oldest of them did and stays
+/// out of tracking if any of them opted out. Its status is the first of
+/// [`STATUS_PRECEDENCE`] held by any of them; when that is `unsubscribed`, it
+/// unsubscribed when the first of them did.
+#[tracing::instrument(name = "Merge duplicate subscribers", skip(pg_pool))]
+pub async fn merge_subscribers(
+    pg_pool: &PgPool,
+    user_id: Uuid,
+    subscriber_id: Uuid,
+    duplicates: &[Uuid],
+) -> Result<MergedSubscriber, MergeError> {
+    let mut duplicates = duplicates.to_vec();
+    duplicates.sort();
+    duplicates.dedup();
+    if duplicates.contains(&subscriber_id) {
+        return Err(MergeError::SameSubscriber);
+    }
+    let mut transaction = pg_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let ids: Vec<Uuid> = std::iter::once(subscriber_id)
+        .chain(duplicates.iter().copied())
+        .collect();
+    let records = sqlx::query!(
+        r#"
+        SELECT id, email, status, subscribed_at, unsubscribed_at, region, do_not_track
+        FROM subscriptions
+        WHERE id = ANY($1)
+        FOR UPDATE
+        "#,
+        &ids,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to lock the subscribers to merge")?;
+    if let Some(missing) = ids.iter().find(|id| !records.iter().any(|r| r.id == **id)) {
+        return Err(MergeError::UnknownSubscriber(*missing));
+    }
+    let kept = records.iter().find(|r| r.id == subscriber_id).unwrap();
+    let status = merged_status(records.iter().map(|r| r.status.as_str())).to_owned();
+    let subscribed_at = records.iter().map(|r| r.subscribed_at).min().unwrap();
+    let unsubscribed_at = if status == "unsubscribed" {
+        records.iter().filter_map(|r| r.unsubscribed_at).min()
+    } else {
+        None
+    };
+    let region = kept
+        .region
+        .clone()
+        .or_else(|| records.iter().find_map(|r| r.region.clone()));
+    let do_not_track = records.iter().any(|r| r.do_not_track);
+    let email = kept.email.clone();
+
+    move_history(&mut transaction, subscriber_id, &duplicates).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_merges (
+            subscriber_id, merged_subscriber_id, merged_email, merged_by, merged_at
+        )
+        SELECT $1, id, email, $3, now()
+        FROM subscriptions
+        WHERE id = ANY($2)
+        "#,
+        subscriber_id,
+        &duplicates,
+        user_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record the merge of subscribers")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = ANY($1)"#,
+        &duplicates,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the merged subscribers")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2,
+            subscribed_at = $3,
+            unsubscribed_at = $4,
+            region = $5,
+            do_not_track = $6,
+            quarantine_reason = CASE WHEN $2 = 'quarantined' THEN quarantine_reason END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        status,
+        subscribed_at,
+        unsubscribed_at,
+        region,
+        do_not_track,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the merged subscriber")?;
+    if status == "unsubscribed" {
+        unsubscribe_subscribers(&mut transaction, &[subscriber_id])
+            .await
+            .context("Failed to unsubscribe the merged subscriber")?;
+    }
+    if status != "pending_confirmation" {
+        sqlx::query!(
+            r#"DELETE FROM email_outbox WHERE subscriber_id = $1"#,
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to drop the confirmation email of the merged subscriber")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to merge subscribers")?;
+    Ok(MergedSubscriber {
+        subscriber_id,
+        email,
+        status,
+        subscribed_at,
+        merged: duplicates.len(),
+    })
+}
+
+/// Point the records of `duplicates` at `subscriber_id`. Those it already
+/// has, such as a delivery of the same issue, are left to be deleted along
+/// with the duplicates.
+async fn move_history(
+    connection: &mut PgConnection,
+    subscriber_id: Uuid,
+    duplicates: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM subscriber_tags WHERE subscriber_id = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the tags of subscribers")?;
+
+    // Delivery events reference the delivery they are about, which is copied
+    // before the events move to it. The provider message id moves last, being
+    // unique.
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_id, delivered_at)
+        SELECT DISTINCT ON (newsletter_issue_id) newsletter_issue_id, $1, delivered_at
+        FROM newsletter_deliveries
+        WHERE subscriber_id = ANY($2)
+        ORDER BY newsletter_issue_id, delivered_at
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the deliveries of subscribers")?;
+    sqlx::query!(
+        r#"UPDATE delivery_events SET subscriber_id = $1 WHERE subscriber_id = ANY($2)"#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the delivery events of subscribers")?;
+    let message_ids = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_deliveries
+        WHERE subscriber_id = ANY($1) AND provider_message_id IS NOT NULL
+        RETURNING newsletter_issue_id, provider_message_id AS "provider_message_id!"
+        "#,
+        duplicates,
+    )
+    .fetch_all(&mut *connection)
+    .await
+    .context("Failed to merge the deliveries of subscribers")?;
+    for r in message_ids {
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_deliveries
+            SET provider_message_id = $3
+            WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+                AND provider_message_id IS NULL
+            "#,
+            r.newsletter_issue_id,
+            subscriber_id,
+            r.provider_message_id,
+        )
+        .execute(&mut *connection)
+        .await
+        .context("Failed to merge the deliveries of subscribers")?;
+    }
+
+    // Deliveries claimed by a worker are left to it.
+    sqlx::query!(
+        r#"
+        UPDATE delivery_tasks t
+        SET subscriber_id = $1
+        WHERE t.subscriber_id = ANY($2)
+            AND (t.lease_expires_at IS NULL OR t.lease_expires_at < now())
+            AND NOT EXISTS (
+                SELECT 1 FROM delivery_tasks k
+                WHERE k.newsletter_issue_id = t.newsletter_issue_id AND k.subscriber_id = $1
+            )
+        "#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the queued deliveries of subscribers")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_recipients (newsletter_issue_id, subscriber_id)
+        SELECT newsletter_issue_id, $1 FROM newsletter_issue_recipients
+        WHERE subscriber_id = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the audiences of subscribers")?;
+    sqlx::query!(
+        r#"
+        UPDATE reengagement_recipients r
+        SET subscriber_id = $1
+        WHERE r.subscriber_id = ANY($2)
+            AND NOT EXISTS (
+                SELECT 1 FROM reengagement_recipients k
+                WHERE k.campaign_id = r.campaign_id AND k.subscriber_id = $1
+            )
+        "#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the reengagement campaigns of subscribers")?;
+    sqlx::query!(
+        r#"UPDATE email_opens SET subscriber_id = $1 WHERE subscriber_id = ANY($2)"#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the opens of subscribers")?;
+    sqlx::query!(
+        r#"UPDATE short_link_clicks SET subscriber_id = $1 WHERE subscriber_id = ANY($2)"#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the clicks of subscribers")?;
+    sqlx::query!(
+        r#"UPDATE subscriber_merges SET subscriber_id = $1 WHERE subscriber_id = ANY($2)"#,
+        subscriber_id,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to merge the earlier merges of subscribers")?;
+    // Confirmation links sent to the duplicates stop working.
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)"#,
+        duplicates,
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to delete the tokens of the merged subscribers")?;
+    merge_consent(connection, subscriber_id, duplicates).await
+}
+
+/// Keep a single proof of consent, a confirmed one if any, that of
+/// `subscriber_id` first.
+async fn merge_consent(
+    connection: &mut PgConnection,
+    subscriber_id: Uuid,
+    duplicates: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    let kept = sqlx::query_scalar!(
+        r#"
+        SELECT subscriber_id
+        FROM consent_records
+        WHERE subscriber_id = $1 OR subscriber_id = ANY($2)
+        ORDER BY confirmed_at IS NULL, subscriber_id = $1 DESC, confirmed_at
+        LIMIT 1
+        "#,
+        subscriber_id,
+        duplicates,
+    )
+    .fetch_optional(&mut *connection)
+    .await
+    .context("Failed to look for the proofs of consent of subscribers")?;
+    if let Some(kept) = kept
+        && kept != subscriber_id
+    {
+        sqlx::query!(
+            r#"DELETE FROM consent_records WHERE subscriber_id = $1"#,
+            subscriber_id,
+        )
+        .execute(&mut *connection)
+        .await
+        .context("Failed to merge the proofs of consent of subscribers")?;
+        sqlx::query!(
+            r#"UPDATE consent_records SET subscriber_id = $1 WHERE subscriber_id = $2"#,
+            subscriber_id,
+            kept,
+        )
+        .execute(&mut *connection)
+        .await
+        .context("Failed to merge the proofs of consent of subscribers")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_normalized_to_lowercase_without_spaces() {
+        assert_eq!(normalize_email(" Ursula@GMail.com "), "ursula@gmail.com");
+    }
+
+    #[test]
+    fn fuzzy_keys_ignore_suffixes_and_gmail_dots() {
+        let cases = [
+            ("ursula.le.guin+news@gmail.com", "ursulaleguin@gmail.com"),
+            ("UrsulaLeGuin@googlemail.com", "ursulaleguin@gmail.com"),
+            ("le.guin+news@example.com", "le.guin@example.com"),
+            ("not-an-address", "not-an-address"),
+        ];
+        for (email, key) in cases {
+            assert_eq!(fuzzy_email_key(email), key);
+        }
+    }
+
+    #[test]
+    fn an_unsubscribe_wins_over_any_other_status() {
+        assert_eq!(
+            merged_status(["confirmed", "unsubscribed", "pending_confirmation"]),
+            "unsubscribed"
+        );
+        assert_eq!(
+            merged_status(["quarantined", "pending_confirmation", "confirmed"]),
+            "confirmed"
+        );
+        assert_eq!(merged_status(["quarantined"]), "quarantined");
+    }
+}
@@ -0,0 +1,400 @@
+//! Filters over subscribers, shared by the search of the dashboard and the
+//! segments newsletters are sent to.
+use crate::encryption::{DecryptionError, FieldCipher};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+pub const STATUSES: [&str; 4] = [
+    "pending_confirmation",
+    "confirmed",
+    "quarantined",
+    "unsubscribed",
+];
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    #[error("`{0}` is not a subscription status.")]
+    UnknownStatus(String),
+    #[error("Tags cannot be empty.")]
+    EmptyTag,
+    #[error("`{0}` is not an email domain.")]
+    InvalidDomain(String),
+    #[error("Engagement scores go from 0 to 100.")]
+    ScoreOutOfRange,
+    #[error("The lower bound of `{0}` is above its upper bound.")]
+    EmptyRange(&'static str),
+}
+
+/// Which subscribers to match. Every criterion given must hold, and an empty
+/// filter matches every subscriber.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SubscriberFilter {
+    /// Any of these statuses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<TagFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_up: Option<DateRange>,
+    /// Bounds of the engagement score over the last 90 days, as computed by
+    /// the `subscriber_engagement` view. Subscribers without a score, who were
+    /// sent no tracked issue lately, never match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engagement_score: Option<ScoreRange>,
+    /// Domain of the email address, e.g. `gmail.com`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TagFilter {
+    /// Subscribers must have every one of these tags...
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all: Vec<String>,
+    /// ...and at least one of these, when there are any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub any: Vec<String>,
+}
+
+impl TagFilter {
+    /// Append the filter as `AND` conditions to a query over
+    /// `subscriptions s`. Tags are matched whatever their case, like
+    /// [`SubscriberTag`](crate::domain::SubscriberTag)s are stored.
+    pub fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        let normalize = |tags: &[String]| -> Vec<String> {
+            tags.iter().map(|t| t.trim().to_lowercase()).collect()
+        };
+        if !self.all.is_empty() {
+            query.push(" AND ").push_bind(normalize(&self.all)).push(
+                "::text[] <@ ARRAY(SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id)",
+            );
+        }
+        if !self.any.is_empty() {
+            query
+                .push(" AND EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ANY(")
+                .push_bind(normalize(&self.any))
+                .push("))");
+        }
+    }
+}
+
+/// Signup dates, `after` included and `before` excluded.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DateRange {
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+}
+
+/// Both bounds included.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreRange {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl SubscriberFilter {
+    pub fn validate(&self) -> Result<(), FilterError> {
+        for status in self.status.iter().flatten() {
+            if !STATUSES.contains(&status.as_str()) {
+                return Err(FilterError::UnknownStatus(status.clone()));
+            }
+        }
+        if let Some(tags) = &self.tags
+            && tags
+                .all
+                .iter()
+                .chain(&tags.any)
+                .any(|t| t.trim().is_empty())
+        {
+            return Err(FilterError::EmptyTag);
+        }
+        if let Some(DateRange {
+            after: Some(after),
+            before: Some(before),
+        }) = &self.signed_up
+            && after >= before
+        {
+            return Err(FilterError::EmptyRange("signed_up"));
+        }
+        if let Some(score) = &self.engagement_score {
+            if [score.min, score.max]
+                .iter()
+                .flatten()
+                .any(|s| !(0..=100).contains(s))
+            {
+                return Err(FilterError::ScoreOutOfRange);
+            }
+            if let (Some(min), Some(max)) = (score.min, score.max)
+                && min > max
+            {
+                return Err(FilterError::EmptyRange("engagement_score"));
+            }
+        }
+        if let Some(domain) = &self.domain
+            && (domain.is_empty() || domain.contains(['@', ' ']))
+        {
+            return Err(FilterError::InvalidDomain(domain.clone()));
+        }
+        Ok(())
+    }
+
+    /// Append the filter as a `WHERE` clause to a query over `subscriptions s`
+    /// joined with `subscriber_engagement e`. Values are always bound, never
+    /// written into the query.
+    pub fn push_where(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        query.push(" WHERE TRUE");
+        if let Some(status) = &self.status {
+            query
+                .push(" AND s.status = ANY(")
+                .push_bind(status.clone())
+                .push(")");
+        }
+        if let Some(tags) = &self.tags {
+            tags.push_conditions(query);
+        }
+        if let Some(signed_up) = &self.signed_up {
+            if let Some(after) = signed_up.after {
+                query.push(" AND s.subscribed_at >= ").push_bind(after);
+            }
+            if let Some(before) = signed_up.before {
+                query.push(" AND s.subscribed_at < ").push_bind(before);
+            }
+        }
+        if let Some(score) = &self.engagement_score {
+            if let Some(min) = score.min {
+                query.push(" AND e.score >= ").push_bind(min);
+            }
+            if let Some(max) = score.max {
+                query.push(" AND e.score <= ").push_bind(max);
+            }
+        }
+        if let Some(domain) = &self.domain {
+            query
+                .push(" AND split_part(lower(s.email), '@', 2) = ")
+                .push_bind(domain.to_lowercase());
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct FoundSubscriber {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub engagement_score: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct FoundSubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+    score: Option<i64>,
+}
+
+/// A page of the subscribers matching `filter`, newest first.
+#[tracing::instrument(name = "Search subscribers", skip(pg_pool, cipher))]
+pub async fn find_subscribers(
+    pg_pool: &PgPool,
+    cipher: &FieldCipher,
+    filter: &SubscriberFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<FoundSubscriber>, anyhow::Error> {
+    let mut query = QueryBuilder::new(
+        r#"
+        SELECT
+            s.id, s.email, s.name, s.status, s.subscribed_at, e.score,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag
+            ) AS tags
+        FROM subscriptions s
+        JOIN subscriber_engagement e ON e.subscriber_id = s.id
+        "#,
+    );
+    filter.push_where(&mut query);
+    query
+        .push(" ORDER BY s.subscribed_at DESC, s.id LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let rows: Vec<FoundSubscriberRow> = query.build_query_as().fetch_all(pg_pool).await?;
+    let subscribers = rows
+        .into_iter()
+        .map(|r| {
+            Ok(FoundSubscriber {
+                subscriber_id: r.id,
+                email: r.email,
+                name: cipher.decrypt(r.name)?,
+                status: r.status,
+                subscribed_at: r.subscribed_at,
+                tags: r.tags,
+                engagement_score: r.score,
+            })
+        })
+        .collect::<Result<_, DecryptionError>>()?;
+    Ok(subscribers)
+}
+
+#[tracing::instrument(name = "Count matching subscribers", skip(pg_pool))]
+pub async fn count_subscribers(
+    pg_pool: &PgPool,
+    filter: &SubscriberFilter,
+) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        r#"
+        SELECT COUNT(*)
+        FROM subscriptions s
+        JOIN subscriber_engagement e ON e.subscriber_id = s.id
+        "#,
+    );
+    filter.push_where(&mut query);
+    query.build_query_scalar().fetch_one(pg_pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DateRange, FilterError, ScoreRange, SubscriberFilter, TagFilter};
+    use chrono::{Duration, Utc};
+    use sqlx::{Postgres, QueryBuilder};
+
+    fn compile(filter: &SubscriberFilter) -> String {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT s.id FROM subscriptions s");
+        filter.push_where(&mut query);
+        query.sql().to_owned()
+    }
+
+    #[test]
+    fn an_empty_filter_matches_everyone() {
+        assert_eq!(
+            compile(&SubscriberFilter::default()),
+            "SELECT s.id FROM subscriptions s WHERE TRUE"
+        );
+    }
+
+    #[test]
+    fn values_are_bound_instead_of_written_into_the_query() {
+        let injection = "x'); DROP TABLE subscriptions; --";
+        let filter = SubscriberFilter {
+            status: Some(vec![injection.to_owned()]),
+            tags: Some(TagFilter {
+                all: vec![injection.to_owned()],
+                any: vec![injection.to_owned()],
+            }),
+            signed_up: Some(DateRange {
+                after: Some(Utc::now()),
+                before: None,
+            }),
+            engagement_score: Some(ScoreRange {
+                min: Some(10),
+                max: Some(90),
+            }),
+            domain: Some(injection.to_owned()),
+        };
+
+        let sql = compile(&filter);
+
+        assert!(!sql.contains("DROP"));
+        for placeholder in ["$1", "$2", "$3", "$4", "$5", "$6", "$7"] {
+            assert!(
+                sql.contains(placeholder),
+                "{} is missing from {}",
+                placeholder,
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn filters_are_parsed_from_json() {
+        let filter: SubscriberFilter = serde_json::from_value(serde_json::json!({
+            "status": ["confirmed"],
+            "tags": { "all": ["rust"], "any": ["beta", "alpha"] },
+            "engagement_score": { "min": 50 },
+            "domain": "gmail.com"
+        }))
+        .unwrap();
+
+        assert_eq!(filter.validate(), Ok(()));
+        assert_eq!(filter.tags.unwrap().any, ["beta", "alpha"]);
+        assert!(
+            serde_json::from_value::<SubscriberFilter>(serde_json::json!({ "tag": ["rust"] }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        let now = Utc::now();
+        let cases = [
+            (
+                SubscriberFilter {
+                    status: Some(vec!["gone".to_owned()]),
+                    ..Default::default()
+                },
+                FilterError::UnknownStatus("gone".to_owned()),
+            ),
+            (
+                SubscriberFilter {
+                    tags: Some(TagFilter {
+                        all: vec![" ".to_owned()],
+                        any: vec![],
+                    }),
+                    ..Default::default()
+                },
+                FilterError::EmptyTag,
+            ),
+            (
+                SubscriberFilter {
+                    signed_up: Some(DateRange {
+                        after: Some(now),
+                        before: Some(now - Duration::days(1)),
+                    }),
+                    ..Default::default()
+                },
+                FilterError::EmptyRange("signed_up"),
+            ),
+            (
+                SubscriberFilter {
+                    engagement_score: Some(ScoreRange {
+                        min: None,
+                        max: Some(101),
+                    }),
+                    ..Default::default()
+                },
+                FilterError::ScoreOutOfRange,
+            ),
+            (
+                SubscriberFilter {
+                    engagement_score: Some(ScoreRange {
+                        min: Some(60),
+                        max: Some(40),
+                    }),
+                    ..Default::default()
+                },
+                FilterError::EmptyRange("engagement_score"),
+            ),
+            (
+                SubscriberFilter {
+                    domain: Some("ursula@gmail.com".to_owned()),
+                    ..Default::default()
+                },
+                FilterError::InvalidDomain("ursula@gmail.com".to_owned()),
+            ),
+        ];
+        for (filter, error) in cases {
+            assert_eq!(filter.validate(), Err(error));
+        }
+    }
+}
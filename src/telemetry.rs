@@ -1,23 +1,144 @@
+use crate::configuration::{LogFormat, TelemetrySettings};
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
 use tokio::task::JoinHandle;
-use tracing::{Subscriber, subscriber::set_global_default};
+use tracing::{Span, Subscriber, subscriber::set_global_default};
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder, root_span};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
-use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt::MakeWriter, layer::SubscriberExt};
 
+/// Exports the spans still buffered when dropped, to be kept until the
+/// application exits.
+#[must_use]
+pub struct TelemetryGuard(Option<SdkTracerProvider>);
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.0.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("Failed to export the last traces: {}", e);
+        }
+    }
+}
+
+/// Log spans to `sink`, as bunyan JSON or pretty-printed depending on
+/// `telemetry.format`, and export them to an OpenTelemetry collector as well
+/// given an `otlp_endpoint`.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
-) -> impl Subscriber + Send + Sync
+    telemetry: &TelemetrySettings,
+) -> Result<(impl Subscriber + Send + Sync, TelemetryGuard), ExporterBuildError>
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
-    Registry::default()
+    let provider = match &telemetry.otlp_endpoint {
+        Some(otlp_endpoint) => Some(tracer_provider(&name, otlp_endpoint, telemetry)?),
+        None => None,
+    };
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+    let (json_layer, pretty_layer) = match telemetry.format {
+        LogFormat::Json => (
+            Some(JsonStorageLayer.and_then(BunyanFormattingLayer::new(name, sink))),
+            None,
+        ),
+        LogFormat::Pretty => (
+            None,
+            Some(tracing_subscriber::fmt::layer().pretty().with_writer(sink)),
+        ),
+    };
+    let subscriber = Registry::default()
         .with(env_filter)
-        .with(JsonStorageLayer)
-        .with(formatting_layer)
+        .with(otel_layer)
+        .with(json_layer)
+        .with(pretty_layer);
+    Ok((subscriber, TelemetryGuard(provider)))
+}
+
+fn tracer_provider(
+    name: &str,
+    otlp_endpoint: &str,
+    telemetry: &TelemetrySettings,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(otlp_endpoint)
+        .build()?;
+    let service_name = telemetry.service_name.as_deref().unwrap_or(name);
+    // Requests carrying a `traceparent` header join the trace of the caller.
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            telemetry.sampling_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.to_owned())
+                .build(),
+        )
+        .build())
+}
+
+/// Root span of every request, declaring the fields recorded with
+/// [`record_in_request_spans`] once known, on top of the `request_id` and
+/// HTTP fields of [`DefaultRootSpanBuilder`].
+pub struct RequestRootSpan;
+
+impl RootSpanBuilder for RequestRootSpan {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        root_span!(
+            request,
+            user_id = tracing::field::Empty,
+            subscriber_id = tracing::field::Empty
+        )
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+/// Record `value` as `field` of the current span and of every span it is
+/// nested in declaring `field`, the root span of the request included.
+///
+/// `Span::record` only reaches the current span, leaving the admin or
+/// subscriber behind a request out of the logs of its other spans.
+pub fn record_in_request_spans(field: &str, value: &dyn tracing::Value) {
+    let Some(id) = Span::current().id() else {
+        return;
+    };
+    tracing::dispatcher::get_default(|dispatch| {
+        let Some(registry) = dispatch.downcast_ref::<Registry>() else {
+            return;
+        };
+        let Some(span) = registry.span(&id) else {
+            return;
+        };
+        for span in span.scope() {
+            let fields = span.metadata().fields();
+            if let Some(field) = fields.field(field) {
+                let values = [(&field, Some(value))];
+                dispatch.record(
+                    &span.id(),
+                    &tracing::span::Record::new(&fields.value_set(&values)),
+                );
+            }
+        }
+    });
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
@@ -32,3 +153,109 @@ where
     let current_span = tracing::Span::current();
     actix_web::rt::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn logs(format: LogFormat, f: impl FnOnce()) -> String {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let telemetry = TelemetrySettings {
+            format,
+            ..TelemetrySettings::default()
+        };
+        let (subscriber, _guard) = get_subscriber(
+            "test".into(),
+            "info".into(),
+            move || writer.clone(),
+            &telemetry,
+        )
+        .unwrap();
+        tracing::subscriber::with_default(subscriber, f);
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn fields_are_recorded_on_the_enclosing_spans_declaring_them() {
+        let logs = logs(LogFormat::Json, || {
+            let root = tracing::info_span!("Root", user_id = tracing::field::Empty);
+            let _root = root.enter();
+            let _handler = tracing::info_span!("Handler").entered();
+            record_in_request_spans("user_id", &"ursula");
+        });
+
+        let root_end: serde_json::Value = logs
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .find(|line: &serde_json::Value| line["msg"] == "[ROOT - END]")
+            .unwrap();
+        assert_eq!(root_end["user_id"], "ursula");
+    }
+
+    /// Export the spans of `f` to a mock collector, returning how many
+    /// export requests it received.
+    async fn exported(sampling_ratio: f64, f: impl FnOnce() + Send + 'static) -> usize {
+        let collector = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/traces"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&collector)
+            .await;
+        let telemetry = TelemetrySettings {
+            otlp_endpoint: Some(format!("{}/v1/traces", collector.uri())),
+            sampling_ratio,
+            ..TelemetrySettings::default()
+        };
+        // The exporter uses a blocking HTTP client, which must not run on
+        // the runtime of the test.
+        std::thread::spawn(move || {
+            let (subscriber, guard) =
+                get_subscriber("test".into(), "info".into(), std::io::sink, &telemetry).unwrap();
+            tracing::subscriber::with_default(subscriber, f);
+            // Flushes the spans.
+            drop(guard);
+        })
+        .join()
+        .unwrap();
+        collector.received_requests().await.unwrap().len()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_collector() {
+        let exports = exported(1.0, || tracing::info_span!("Exported").in_scope(|| {})).await;
+
+        assert_eq!(exports, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn traces_left_out_by_the_sampling_ratio_are_not_exported() {
+        let exports = exported(0.0, || tracing::info_span!("Dropped").in_scope(|| {})).await;
+
+        assert_eq!(exports, 0);
+    }
+
+    #[test]
+    fn pretty_logs_are_not_json() {
+        let logs = logs(LogFormat::Pretty, || tracing::info!("Hello"));
+
+        assert!(logs.contains("Hello"));
+        assert!(serde_json::from_str::<serde_json::Value>(logs.lines().next().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use sqlx::{PgConnection, PgPool};
+
+/// Tokens every footer fragment must carry, for the issues it is added to
+/// to comply with CAN-SPAM.
+const REQUIRED_FOOTER_TOKENS: &[&str] = &["{unsubscribe_link}", "{postal_address}"];
+
+/// Where a fragment goes in the issues it is added to.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FragmentPosition {
+    Header,
+    Footer,
+}
+
+impl FragmentPosition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FragmentPosition::Header => "header",
+            FragmentPosition::Footer => "footer",
+        }
+    }
+}
+
+impl TryFrom<String> for FragmentPosition {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "header" => Ok(FragmentPosition::Header),
+            "footer" => Ok(FragmentPosition::Footer),
+            other => Err(format!("{} is not a valid fragment position.", other)),
+        }
+    }
+}
+
+/// Reusable copy, e.g. a legal notice, added to every issue when it is
+/// published.
+///
+/// Fragments may use the placeholders of the configured footer, replaced
+/// for each recipient.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TemplateFragment {
+    pub name: String,
+    pub position: FragmentPosition,
+    pub html: String,
+    pub text: String,
+}
+
+/// Footer fragments missing the tokens an issue cannot be sent without.
+#[derive(thiserror::Error, Debug)]
+#[error("Some footer fragments are missing required tokens: {}.", .0.join(", "))]
+pub struct NonCompliantFooter(Vec<String>);
+
+/// Every fragment, in the order they are added to issues.
+pub struct TemplateFragments(pub Vec<TemplateFragment>);
+
+impl TemplateFragments {
+    #[tracing::instrument(name = "Load template fragments", skip(connection))]
+    pub async fn load(connection: &mut PgConnection) -> Result<Self, anyhow::Error> {
+        let fragments = sqlx::query!(
+            r#"
+            SELECT name, position, html, text
+            FROM template_fragments
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(connection)
+        .await?
+        .into_iter()
+        .map(|r| {
+            Ok(TemplateFragment {
+                name: r.name,
+                position: r.position.try_into().map_err(anyhow::Error::msg)?,
+                html: r.html,
+                text: r.text,
+            })
+        })
+        .collect::<Result<_, anyhow::Error>>()?;
+        Ok(Self(fragments))
+    }
+
+    /// Fail unless every footer fragment carries the unsubscribe link and
+    /// the postal address, in both bodies.
+    pub fn check_compliance(&self) -> Result<(), NonCompliantFooter> {
+        let mut missing = Vec::new();
+        for fragment in self.at(FragmentPosition::Footer) {
+            for token in REQUIRED_FOOTER_TOKENS {
+                if !fragment.html.contains(token) {
+                    missing.push(format!("{} in the HTML of `{}`", token, fragment.name));
+                }
+                if !fragment.text.contains(token) {
+                    missing.push(format!("{} in the text of `{}`", token, fragment.name));
+                }
+            }
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(NonCompliantFooter(missing))
+        }
+    }
+
+    /// Surround the bodies of an issue with the header and footer fragments.
+    pub fn wrap(&self, html: &str, text: &str) -> (String, String) {
+        let headers = self.at(FragmentPosition::Header);
+        let footers = self.at(FragmentPosition::Footer);
+        let html = headers
+            .clone()
+            .map(|f| f.html.as_str())
+            .chain(std::iter::once(html))
+            .chain(footers.clone().map(|f| f.html.as_str()))
+            .collect::<String>();
+        let text = headers
+            .map(|f| f.text.as_str())
+            .chain(std::iter::once(text))
+            .chain(footers.map(|f| f.text.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        (html, text)
+    }
+
+    fn at(&self, position: FragmentPosition) -> impl Iterator<Item = &TemplateFragment> + Clone {
+        self.0.iter().filter(move |f| f.position == position)
+    }
+}
+
+/// Create or replace the fragment named `fragment.name`.
+#[tracing::instrument(name = "Store a template fragment", skip(pg_pool, fragment), fields(name = %fragment.name))]
+pub async fn store_fragment(
+    pg_pool: &PgPool,
+    fragment: &TemplateFragment,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO template_fragments (name, position, html, text, updated_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (name) DO UPDATE
+        SET position = EXCLUDED.position,
+            html = EXCLUDED.html,
+            text = EXCLUDED.text,
+            updated_at = now()
+        "#,
+        fragment.name,
+        fragment.position.as_str(),
+        fragment.html,
+        fragment.text,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FragmentPosition, TemplateFragment, TemplateFragments};
+
+    fn fragment(
+        name: &str,
+        position: FragmentPosition,
+        html: &str,
+        text: &str,
+    ) -> TemplateFragment {
+        TemplateFragment {
+            name: name.into(),
+            position,
+            html: html.into(),
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn issues_are_wrapped_in_their_fragments() {
+        let fragments = TemplateFragments(vec![
+            fragment(
+                "banner",
+                FragmentPosition::Header,
+                "<p>Banner</p>",
+                "Banner",
+            ),
+            fragment("legal", FragmentPosition::Footer, "<p>Legal</p>", "Legal"),
+        ]);
+
+        let (html, text) = fragments.wrap("<p>Body</p>", "Body");
+
+        assert_eq!(html, "<p>Banner</p><p>Body</p><p>Legal</p>");
+        assert_eq!(text, "Banner\n\nBody\n\nLegal");
+    }
+
+    #[test]
+    fn footer_fragments_must_carry_the_required_tokens() {
+        let compliant = fragment(
+            "legal",
+            FragmentPosition::Footer,
+            r#"{postal_address} <a href="{unsubscribe_link}">Unsubscribe</a>"#,
+            "{postal_address}\nUnsubscribe: {unsubscribe_link}",
+        );
+        let header = fragment("banner", FragmentPosition::Header, "Banner", "Banner");
+        assert!(
+            TemplateFragments(vec![compliant.clone(), header])
+                .check_compliance()
+                .is_ok()
+        );
+
+        let forgetful = fragment(
+            "social",
+            FragmentPosition::Footer,
+            r#"<a href="{unsubscribe_link}">Unsubscribe</a>"#,
+            "Follow us",
+        );
+        let error = TemplateFragments(vec![compliant, forgetful])
+            .check_compliance()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("{postal_address} in the HTML of `social`"));
+        assert!(error.contains("{unsubscribe_link} in the text of `social`"));
+        assert!(!error.contains("`legal`"));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::configuration::EmailTemplatesSettings;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Templates with a subject, an HTML and a text file.
+const TEMPLATES: [&str; 6] = [
+    "confirmation",
+    "reengagement",
+    "feed_entry",
+    "digest",
+    "magic_link",
+    "admin_report",
+];
+
+/// The email templates emails are rendered with.
+///
+/// Debug builds pick up edits to the files of `email_templates.directory`
+/// without a restart: the files are read again whenever one of them was
+/// modified since the last read. Release builds stick to the files read
+/// along with the configuration.
+#[derive(Clone)]
+pub struct EmailTemplates {
+    configured: Arc<EmailTemplatesSettings>,
+    reloaded: Arc<Mutex<Option<ReloadedTemplates>>>,
+}
+
+/// Templates read from the directory, with the modification time of each of
+/// its files at the time.
+struct ReloadedTemplates {
+    modified: Vec<Option<SystemTime>>,
+    templates: Arc<EmailTemplatesSettings>,
+}
+
+impl EmailTemplates {
+    pub fn new(configured: EmailTemplatesSettings) -> Self {
+        Self {
+            configured: Arc::new(configured),
+            reloaded: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub async fn current(&self) -> Arc<EmailTemplatesSettings> {
+        let directory = match &self.configured.directory {
+            Some(directory) if cfg!(debug_assertions) => directory.clone(),
+            _ => return self.configured.clone(),
+        };
+        let templates = self.clone();
+        match tokio::task::spawn_blocking(move || templates.reload(&directory)).await {
+            Ok(templates) => templates,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to reload the email templates, keeping the configured ones",
+                );
+                self.configured.clone()
+            }
+        }
+    }
+
+    /// Read the files of `directory` again if any of them changed since the
+    /// last read. Blocks on file system calls.
+    fn reload(&self, directory: &Path) -> Arc<EmailTemplatesSettings> {
+        let modified: Vec<_> = template_files()
+            .map(|file| {
+                std::fs::metadata(directory.join(file))
+                    .and_then(|m| m.modified())
+                    .ok()
+            })
+            .collect();
+        let mut reloaded = self.reloaded.lock().unwrap();
+        if let Some(reloaded) = reloaded.as_ref()
+            && reloaded.modified == modified
+        {
+            return reloaded.templates.clone();
+        }
+        let mut templates = EmailTemplatesSettings::clone(&self.configured);
+        match read_template_files(&mut templates, directory) {
+            Ok(()) => {
+                let templates = Arc::new(templates);
+                *reloaded = Some(ReloadedTemplates {
+                    modified,
+                    templates: templates.clone(),
+                });
+                templates
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    directory = %directory.display(),
+                    "Failed to reload the email templates, keeping the previous ones",
+                );
+                reloaded
+                    .as_ref()
+                    .map(|r| r.templates.clone())
+                    .unwrap_or_else(|| self.configured.clone())
+            }
+        }
+    }
+}
+
+/// Names of the files [`read_template_files`] looks for.
+fn template_files() -> impl Iterator<Item = String> {
+    TEMPLATES
+        .into_iter()
+        .flat_map(|name| ["subject.txt", "html", "txt"].map(|ext| format!("{}.{}", name, ext)))
+        .chain(["footer.html".to_owned(), "footer.txt".to_owned()])
+}
+
+/// Replace the copy of `templates` with the files found in `directory`.
+///
+/// Files are named like the ones `zero2prod render` writes:
+/// `{template}.subject.txt`, `{template}.html` and `{template}.txt`, e.g.
+/// `confirmation.html`, the footer having no subject. Missing files leave
+/// the configured copy in place.
+pub fn read_template_files(
+    templates: &mut EmailTemplatesSettings,
+    directory: &Path,
+) -> std::io::Result<()> {
+    let [
+        confirmation,
+        reengagement,
+        feed_entry,
+        digest,
+        magic_link,
+        admin_report,
+    ] = TEMPLATES;
+    for (name, template) in [
+        (confirmation, &mut templates.confirmation),
+        (reengagement, &mut templates.reengagement),
+        (feed_entry, &mut templates.feed_entry),
+        (digest, &mut templates.digest),
+        (magic_link, &mut templates.magic_link),
+        (admin_report, &mut templates.admin_report),
+    ] {
+        if let Some(subject) = read_template_file(directory, name, "subject.txt")? {
+            // Editors end files with a newline, which has no place in a
+            // subject.
+            template.subject = subject.trim_end().to_owned();
+        }
+        if let Some(html) = read_template_file(directory, name, "html")? {
+            template.html = html;
+        }
+        if let Some(text) = read_template_file(directory, name, "txt")? {
+            template.text = text;
+        }
+    }
+    if let Some(html) = read_template_file(directory, "footer", "html")? {
+        templates.footer.html = html;
+    }
+    if let Some(text) = read_template_file(directory, "footer", "txt")? {
+        templates.footer.text = text;
+    }
+    Ok(())
+}
+
+fn read_template_file(
+    directory: &Path,
+    template: &str,
+    extension: &str,
+) -> std::io::Result<Option<String>> {
+    match std::fs::read_to_string(directory.join(format!("{}.{}", template, extension))) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailTemplates, read_template_files};
+    use crate::get_configuration;
+
+    #[test]
+    fn files_override_the_configured_copy() {
+        let mut templates = get_configuration().unwrap().email_templates;
+        let configured_text = templates.confirmation.text.clone();
+        let directory = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("confirmation.subject.txt"), "Hi {name}\n").unwrap();
+        std::fs::write(
+            directory.join("confirmation.html"),
+            "<p>{confirmation_link}</p>",
+        )
+        .unwrap();
+
+        read_template_files(&mut templates, &directory).unwrap();
+
+        assert_eq!(templates.confirmation.subject, "Hi {name}");
+        assert_eq!(templates.confirmation.html, "<p>{confirmation_link}</p>");
+        assert_eq!(templates.confirmation.text, configured_text);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn edits_are_picked_up_in_debug_builds() {
+        let mut configured = get_configuration().unwrap().email_templates;
+        let directory = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        configured.directory = Some(directory.clone());
+        let templates = EmailTemplates::new(configured);
+
+        let configured_text = templates.current().await.footer.text.clone();
+        std::fs::write(directory.join("footer.txt"), "Edited footer").unwrap();
+
+        assert_ne!(configured_text, "Edited footer");
+        assert_eq!(templates.current().await.footer.text, "Edited footer");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
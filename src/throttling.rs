@@ -0,0 +1,134 @@
+use crate::configuration::DeliveryThrottlingSettings;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Past this many tracked domains, the ones not sent to lately are forgotten.
+const MAX_TRACKED_DOMAINS: usize = 10_000;
+
+/// Spaces out the deliveries to each recipient domain, so that no domain
+/// receives more emails per second than its limit.
+///
+/// Every sender of an instance shares one throttle, state is kept in memory
+/// and is not coordinated between instances.
+pub struct DeliveryThrottle {
+    default_interval: Duration,
+    intervals: HashMap<String, Duration>,
+    /// When the next email to each domain may be sent.
+    next_slots: Mutex<HashMap<String, Instant>>,
+}
+
+impl DeliveryThrottle {
+    pub fn new(settings: &DeliveryThrottlingSettings) -> Self {
+        Self {
+            default_interval: interval(settings.default_per_second),
+            intervals: settings
+                .domains
+                .iter()
+                .map(|d| (d.domain.to_ascii_lowercase(), interval(d.per_second)))
+                .collect(),
+            next_slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Book the next slot to send an email to `domain`, returning when it
+    /// starts.
+    pub fn reserve(&self, domain: &str, now: Instant) -> Instant {
+        let domain = domain.to_ascii_lowercase();
+        let interval = self
+            .intervals
+            .get(&domain)
+            .copied()
+            .unwrap_or(self.default_interval);
+        let mut next_slots = self.next_slots.lock().unwrap();
+        if next_slots.len() >= MAX_TRACKED_DOMAINS {
+            next_slots.retain(|_, slot| *slot > now);
+        }
+        let slot = next_slots
+            .get(&domain)
+            .copied()
+            .filter(|slot| *slot > now)
+            .unwrap_or(now);
+        next_slots.insert(domain, slot + interval);
+        slot
+    }
+
+    /// Wait until an email can be sent to `domain`.
+    pub async fn wait_for(&self, domain: &str) {
+        let now = Instant::now();
+        let slot = self.reserve(domain, now);
+        if slot > now {
+            tokio::time::sleep(slot - now).await;
+        }
+    }
+}
+
+fn interval(per_second: NonZeroU32) -> Duration {
+    Duration::from_secs(1) / per_second.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryThrottle;
+    use crate::configuration::{DeliveryThrottlingSettings, DomainThrottlingSettings};
+    use std::num::NonZeroU32;
+    use std::time::{Duration, Instant};
+
+    fn throttle() -> DeliveryThrottle {
+        DeliveryThrottle::new(&DeliveryThrottlingSettings {
+            default_per_second: NonZeroU32::new(5).unwrap(),
+            domains: vec![DomainThrottlingSettings {
+                domain: "gmail.com".into(),
+                per_second: NonZeroU32::new(20).unwrap(),
+            }],
+        })
+    }
+
+    #[test]
+    fn sends_to_a_domain_are_spaced_by_its_limit() {
+        let throttle = throttle();
+        let now = Instant::now();
+        let slots: Vec<_> = (0..3).map(|_| throttle.reserve("gmail.com", now)).collect();
+        assert_eq!(
+            slots,
+            [
+                now,
+                now + Duration::from_millis(50),
+                now + Duration::from_millis(100)
+            ]
+        );
+    }
+
+    #[test]
+    fn unlisted_domains_get_the_default_limit() {
+        let throttle = throttle();
+        let now = Instant::now();
+        throttle.reserve("corp.example", now);
+        assert_eq!(
+            throttle.reserve("corp.example", now),
+            now + Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn domains_are_throttled_independently() {
+        let throttle = throttle();
+        let now = Instant::now();
+        throttle.reserve("gmail.com", now);
+        assert_eq!(throttle.reserve("corp.example", now), now);
+        assert_eq!(
+            throttle.reserve("GMAIL.com", now),
+            now + Duration::from_millis(50)
+        );
+    }
+
+    #[test]
+    fn idle_domains_can_be_sent_to_straight_away() {
+        let throttle = throttle();
+        let now = Instant::now();
+        throttle.reserve("corp.example", now);
+        let later = now + Duration::from_secs(1);
+        assert_eq!(throttle.reserve("corp.example", later), later);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::configuration::TokenGuardSettings;
+use crate::rate_limiting::limited_address;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Endpoints looking up a subscriber from a token in the URL. A `401`
+/// response from one of them counts as a failed guess.
+const GUARDED_PATHS: &[&str] = &[
+    "/subscriptions/confirm",
+    "/subscriptions/unsubscribe",
+    "/subscriptions/reengage",
+    "/subscriptions/status",
+    "/subscriptions/delete",
+    "/subscriptions/export",
+];
+
+/// Most addresses tracked at once. Reaching it forgets the tenth of them
+/// quiet for the longest.
+const MAX_TRACKED_ADDRESSES: usize = 10_000;
+
+/// Bans the addresses guessing subscriber tokens.
+///
+/// Too many failed lookups within a window get an address banned from every
+/// guarded endpoint, each new ban of the same address lasting twice as long
+/// as the previous one. IPv6 clients are tracked per /64, as the
+/// [`RateLimiter`](crate::rate_limiting::RateLimiter) does. State is kept in
+/// memory, per instance.
+pub struct TokenGuard {
+    settings: TokenGuardSettings,
+    offenders: Mutex<Offenders>,
+    failed_lookups: AtomicU64,
+    bans: AtomicU64,
+    rejected_requests: AtomicU64,
+}
+
+struct Offenders {
+    by_address: HashMap<IpAddr, Offender>,
+    swept_at: Option<Instant>,
+}
+
+struct Offender {
+    failures: u32,
+    window_started_at: Instant,
+    last_failure_at: Instant,
+    banned_until: Option<Instant>,
+    bans: u32,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct TokenGuardMetrics {
+    pub failed_lookups: u64,
+    pub bans: u64,
+    pub rejected_requests: u64,
+    pub banned_addresses: usize,
+}
+
+impl TokenGuard {
+    pub fn new(settings: TokenGuardSettings) -> Self {
+        Self {
+            settings,
+            offenders: Mutex::new(Offenders {
+                by_address: HashMap::new(),
+                swept_at: None,
+            }),
+            failed_lookups: AtomicU64::new(0),
+            bans: AtomicU64::new(0),
+            rejected_requests: AtomicU64::new(0),
+        }
+    }
+
+    /// How long `address` remains banned, if it is.
+    pub fn banned_for(&self, address: IpAddr, now: Instant) -> Option<Duration> {
+        let offenders = self.offenders.lock().unwrap();
+        let banned_until = offenders
+            .by_address
+            .get(&limited_address(address))?
+            .banned_until?;
+        banned_until
+            .checked_duration_since(now)
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Record a failed token lookup, returning the duration of the ban it
+    /// triggered, if any.
+    pub fn record_failure(&self, address: IpAddr, now: Instant) -> Option<Duration> {
+        self.failed_lookups.fetch_add(1, Ordering::Relaxed);
+        let address = limited_address(address);
+        let mut offenders = self.offenders.lock().unwrap();
+        // Stale offenders are swept once per window rather than per
+        // failure, the map being scanned with the lock held.
+        let swept_at = *offenders.swept_at.get_or_insert(now);
+        if now.saturating_duration_since(swept_at) >= self.settings.failure_window {
+            offenders
+                .by_address
+                .retain(|_, offender| !self.is_stale(offender, now));
+            offenders.swept_at = Some(now);
+        }
+        if offenders.by_address.len() >= MAX_TRACKED_ADDRESSES
+            && !offenders.by_address.contains_key(&address)
+        {
+            evict_quietest(&mut offenders.by_address, MAX_TRACKED_ADDRESSES / 10);
+        }
+        let offender = offenders.by_address.entry(address).or_insert(Offender {
+            failures: 0,
+            window_started_at: now,
+            last_failure_at: now,
+            banned_until: None,
+            bans: 0,
+        });
+        if now.duration_since(offender.window_started_at) > self.settings.failure_window {
+            offender.failures = 0;
+            offender.window_started_at = now;
+        }
+        offender.failures += 1;
+        offender.last_failure_at = now;
+        if offender.failures < self.settings.max_failures {
+            return None;
+        }
+
+        offender.failures = 0;
+        offender.window_started_at = now;
+        offender.bans += 1;
+        let ban = self
+            .settings
+            .ban_duration
+            .saturating_mul(2u32.saturating_pow(offender.bans - 1))
+            .min(self.settings.max_ban_duration);
+        offender.banned_until = Some(now + ban);
+        self.bans.fetch_add(1, Ordering::Relaxed);
+        Some(ban)
+    }
+
+    pub fn metrics(&self, now: Instant) -> TokenGuardMetrics {
+        let banned_addresses = self
+            .offenders
+            .lock()
+            .unwrap()
+            .by_address
+            .values()
+            .filter(|o| o.banned_until.is_some_and(|until| until > now))
+            .count();
+        TokenGuardMetrics {
+            failed_lookups: self.failed_lookups.load(Ordering::Relaxed),
+            bans: self.bans.load(Ordering::Relaxed),
+            rejected_requests: self.rejected_requests.load(Ordering::Relaxed),
+            banned_addresses,
+        }
+    }
+
+    /// An offender is forgotten, ban count included, once it has been quiet
+    /// for as long as the longest ban.
+    fn is_stale(&self, offender: &Offender, now: Instant) -> bool {
+        now.saturating_duration_since(offender.quiet_since()) > self.settings.max_ban_duration
+    }
+}
+
+impl Offender {
+    /// When the offender last failed a lookup or, if later, its ban ends.
+    fn quiet_since(&self) -> Instant {
+        self.banned_until.map_or(self.last_failure_at, |until| {
+            until.max(self.last_failure_at)
+        })
+    }
+}
+
+/// Forget the `count` offenders quiet for the longest.
+fn evict_quietest(offenders: &mut HashMap<IpAddr, Offender>, count: usize) {
+    let mut quiet_since: Vec<_> = offenders
+        .iter()
+        .map(|(address, offender)| (offender.quiet_since(), *address))
+        .collect();
+    let count = count.clamp(1, quiet_since.len());
+    quiet_since.select_nth_unstable(count - 1);
+    for (_, address) in &quiet_since[..count] {
+        offenders.remove(address);
+    }
+}
+
+/// Middleware rejecting banned addresses on the guarded endpoints and
+/// recording their failed token lookups.
+///
+/// Addresses are taken from the TCP connection: behind a reverse proxy they
+/// all belong to the proxy.
+pub async fn guard_token_lookups(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let guard = request.app_data::<web::Data<TokenGuard>>().cloned();
+    let address = request.peer_addr().map(|a| a.ip());
+    let (Some(guard), Some(address)) = (guard, address) else {
+        return Ok(next.call(request).await?.map_into_left_body());
+    };
+    if !GUARDED_PATHS.contains(&request.path()) {
+        return Ok(next.call(request).await?.map_into_left_body());
+    }
+
+    if let Some(remaining) = guard.banned_for(address, Instant::now()) {
+        guard.rejected_requests.fetch_add(1, Ordering::Relaxed);
+        let response = HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, remaining.as_secs().max(1).to_string()))
+            .body("Too many invalid links were opened from your address, try again later.");
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+
+    let response = next.call(request).await?;
+    if response.status() == StatusCode::UNAUTHORIZED
+        && let Some(ban) = guard.record_failure(address, Instant::now())
+    {
+        tracing::warn!(
+            %address,
+            ban_seconds = ban.as_secs(),
+            "Banning an address after too many failed token lookups",
+        );
+    }
+    Ok(response.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_TRACKED_ADDRESSES, TokenGuard};
+    use crate::configuration::TokenGuardSettings;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    fn guard() -> TokenGuard {
+        TokenGuard::new(TokenGuardSettings {
+            max_failures: 3,
+            failure_window: Duration::from_secs(60),
+            ban_duration: Duration::from_secs(600),
+            max_ban_duration: Duration::from_secs(1800),
+        })
+    }
+
+    fn address() -> IpAddr {
+        "203.0.113.7".parse().unwrap()
+    }
+
+    #[test]
+    fn addresses_are_banned_after_too_many_failures() {
+        let guard = guard();
+        let now = Instant::now();
+
+        assert_eq!(guard.record_failure(address(), now), None);
+        assert_eq!(guard.record_failure(address(), now), None);
+        assert_eq!(guard.banned_for(address(), now), None);
+        assert_eq!(
+            guard.record_failure(address(), now),
+            Some(Duration::from_secs(600))
+        );
+
+        assert_eq!(
+            guard.banned_for(address(), now + Duration::from_secs(60)),
+            Some(Duration::from_secs(540))
+        );
+        assert_eq!(
+            guard.banned_for(address(), now + Duration::from_secs(600)),
+            None
+        );
+        assert_eq!(guard.banned_for("203.0.113.8".parse().unwrap(), now), None);
+    }
+
+    #[test]
+    fn failures_outside_of_the_window_are_forgotten() {
+        let guard = guard();
+        let now = Instant::now();
+
+        guard.record_failure(address(), now);
+        guard.record_failure(address(), now);
+
+        assert_eq!(
+            guard.record_failure(address(), now + Duration::from_secs(61)),
+            None
+        );
+    }
+
+    #[test]
+    fn repeated_bans_escalate_up_to_the_maximum() {
+        let guard = guard();
+        let mut now = Instant::now();
+        let mut bans = Vec::new();
+
+        for _ in 0..3 {
+            for _ in 0..3 {
+                if let Some(ban) = guard.record_failure(address(), now) {
+                    bans.push(ban);
+                    now += ban;
+                }
+            }
+        }
+
+        assert_eq!(bans, [600, 1200, 1800].map(Duration::from_secs).to_vec());
+        let metrics = guard.metrics(now);
+        assert_eq!(metrics.failed_lookups, 9);
+        assert_eq!(metrics.bans, 3);
+    }
+
+    #[test]
+    fn ipv6_addresses_are_banned_per_64() {
+        let guard = guard();
+        let now = Instant::now();
+
+        for address in ["2001:db8:1:2::1", "2001:db8:1:2::2", "2001:db8:1:2:ffff::3"] {
+            guard.record_failure(address.parse().unwrap(), now);
+        }
+
+        assert!(
+            guard
+                .banned_for("2001:db8:1:2::4".parse().unwrap(), now)
+                .is_some()
+        );
+        assert_eq!(
+            guard.banned_for("2001:db8:1:3::1".parse().unwrap(), now),
+            None
+        );
+    }
+
+    #[test]
+    fn the_number_of_tracked_addresses_is_capped() {
+        let guard = guard();
+        let now = Instant::now();
+
+        for i in 0..MAX_TRACKED_ADDRESSES as u32 * 2 {
+            let address = IpAddr::V4(Ipv4Addr::from(i));
+            guard.record_failure(address, now + Duration::from_millis(i.into()));
+        }
+
+        let tracked = guard.offenders.lock().unwrap().by_address.len();
+        assert!(tracked <= MAX_TRACKED_ADDRESSES);
+        // The latest addresses are the ones kept.
+        let latest = IpAddr::V4(Ipv4Addr::from(MAX_TRACKED_ADDRESSES as u32 * 2 - 1));
+        assert!(
+            guard
+                .offenders
+                .lock()
+                .unwrap()
+                .by_address
+                .contains_key(&latest)
+        );
+    }
+}
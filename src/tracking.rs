@@ -0,0 +1,318 @@
+use crate::configuration::TrackingSettings;
+use crate::link_shortener::restore_links;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// User agent fragments of crawlers, link unfurlers and mail security
+/// scanners that fetch every URL in a message without a human involved.
+/// Matched case-insensitively.
+const BOT_USER_AGENT_FRAGMENTS: &[&str] = &[
+    "bot",
+    "crawler",
+    "spider",
+    "slurp",
+    "preview",
+    "facebookexternalhit",
+    "headlesschrome",
+    "phantomjs",
+    "python-requests",
+    "go-http-client",
+    "curl/",
+    "wget/",
+    "barracuda",
+    "mimecast",
+    "proofpoint",
+    "symantec",
+    "forcepoint",
+    "trendmicro",
+];
+
+/// How much we remember about opens and clicks.
+///
+/// `Detailed` stores one event per open or click, attributed to the
+/// recipient. `Aggregate` only bumps per-issue and per-link counters, nothing
+/// in the email identifies the recipient and no event rows are written.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackingMode {
+    #[default]
+    Detailed,
+    Aggregate,
+}
+
+impl TrackingMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackingMode::Detailed => "detailed",
+            TrackingMode::Aggregate => "aggregate",
+        }
+    }
+}
+
+impl TryFrom<String> for TrackingMode {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "detailed" => Ok(TrackingMode::Detailed),
+            "aggregate" => Ok(TrackingMode::Aggregate),
+            other => Err(format!("{} is not a valid tracking mode", other)),
+        }
+    }
+}
+
+/// Why an open or click was flagged instead of being counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotReason {
+    MissingUserAgent,
+    KnownBotUserAgent,
+    /// The event happened faster than a human could have read the email.
+    Prefetch,
+}
+
+impl BotReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotReason::MissingUserAgent => "missing_user_agent",
+            BotReason::KnownBotUserAgent => "known_bot_user_agent",
+            BotReason::Prefetch => "prefetch",
+        }
+    }
+}
+
+/// Classify a tracking event.
+///
+/// `elapsed_since_send` is measured from the moment the issue was published,
+/// which is an upper bound for the time the recipient had the email.
+pub fn detect_bot(
+    user_agent: Option<&str>,
+    elapsed_since_send: Duration,
+    settings: &TrackingSettings,
+) -> Option<BotReason> {
+    let Some(user_agent) = user_agent.filter(|ua| !ua.trim().is_empty()) else {
+        return Some(BotReason::MissingUserAgent);
+    };
+    let user_agent = user_agent.to_lowercase();
+    if BOT_USER_AGENT_FRAGMENTS
+        .iter()
+        .any(|fragment| user_agent.contains(fragment))
+    {
+        return Some(BotReason::KnownBotUserAgent);
+    }
+    if elapsed_since_send < settings.prefetch_window {
+        return Some(BotReason::Prefetch);
+    }
+    None
+}
+
+/// Attribute the short links of an issue to a single recipient and, for
+/// HTML bodies, append the open-tracking pixel if `open_pixel` is set.
+///
+/// Without a `subscriber_id` (aggregate tracking) links are left as they are
+/// and the pixel only identifies the issue.
+pub struct RecipientTracking<'a> {
+    pub base_url: &'a str,
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Option<Uuid>,
+    pub open_pixel: bool,
+    /// Destination of the short links by code, set to send them straight to
+    /// their destination instead.
+    pub link_destinations: Option<&'a HashMap<String, String>>,
+}
+
+impl<'a> RecipientTracking<'a> {
+    /// For recipients who opted out of tracking: no pixel, and links that
+    /// do not go through our redirects.
+    pub fn untracked(
+        base_url: &'a str,
+        newsletter_issue_id: Uuid,
+        link_destinations: &'a HashMap<String, String>,
+    ) -> Self {
+        Self {
+            base_url,
+            newsletter_issue_id,
+            subscriber_id: None,
+            open_pixel: false,
+            link_destinations: Some(link_destinations),
+        }
+    }
+
+    pub fn html(&self, html_content: &str) -> String {
+        let mut html = self.rewrite_short_links(html_content);
+        if self.open_pixel {
+            html.push_str(&format!(
+                r#"<img src="{}" width="1" height="1" alt="" />"#,
+                self.open_pixel_url()
+            ));
+        }
+        html
+    }
+
+    pub fn text(&self, text_content: &str) -> String {
+        self.rewrite_short_links(text_content)
+    }
+
+    fn rewrite_short_links(&self, content: &str) -> String {
+        match self.link_destinations {
+            Some(destinations) => restore_links(self.base_url, content, destinations),
+            None => self.attribute_short_links(content),
+        }
+    }
+
+    fn open_pixel_url(&self) -> String {
+        let base_url = self.base_url.trim_end_matches('/');
+        match self.subscriber_id {
+            Some(subscriber_id) => format!(
+                "{}/t/o/{}/{}",
+                base_url, self.newsletter_issue_id, subscriber_id
+            ),
+            None => format!("{}/t/o/{}", base_url, self.newsletter_issue_id),
+        }
+    }
+
+    fn attribute_short_links(&self, content: &str) -> String {
+        let Some(subscriber_id) = self.subscriber_id else {
+            return content.to_owned();
+        };
+        let short_link_prefix = format!("{}/l/", self.base_url.trim_end_matches('/'));
+        let mut output = String::with_capacity(content.len());
+        let mut last = 0;
+        for link in linkify::LinkFinder::new()
+            .kinds(&[linkify::LinkKind::Url])
+            .links(content)
+            .filter(|l| l.as_str().starts_with(&short_link_prefix))
+        {
+            output.push_str(&content[last..link.end()]);
+            output.push_str(&format!("?s={}", subscriber_id));
+            last = link.end();
+        }
+        output.push_str(&content[last..]);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BotReason, RecipientTracking, TrackingMode, detect_bot};
+    use crate::configuration::TrackingSettings;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    const FIREFOX: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:126.0) Gecko/20100101 Firefox/126.0";
+
+    fn settings() -> TrackingSettings {
+        TrackingSettings {
+            prefetch_window: Duration::from_secs(10),
+            mode: TrackingMode::Detailed,
+        }
+    }
+
+    #[test]
+    fn a_browser_well_after_send_is_counted() {
+        assert_eq!(
+            detect_bot(Some(FIREFOX), Duration::from_secs(600), &settings()),
+            None
+        );
+    }
+
+    #[test]
+    fn a_missing_user_agent_is_flagged() {
+        assert_eq!(
+            detect_bot(None, Duration::from_secs(600), &settings()),
+            Some(BotReason::MissingUserAgent)
+        );
+        assert_eq!(
+            detect_bot(Some(" "), Duration::from_secs(600), &settings()),
+            Some(BotReason::MissingUserAgent)
+        );
+    }
+
+    #[test]
+    fn known_scanners_are_flagged_regardless_of_case() {
+        for user_agent in [
+            "Googlebot/2.1",
+            "Barracuda Sentinel",
+            "python-requests/2.32",
+        ] {
+            assert_eq!(
+                detect_bot(Some(user_agent), Duration::from_secs(600), &settings()),
+                Some(BotReason::KnownBotUserAgent),
+                "{user_agent} was not flagged"
+            );
+        }
+    }
+
+    #[test]
+    fn events_inside_the_prefetch_window_are_flagged() {
+        assert_eq!(
+            detect_bot(Some(FIREFOX), Duration::from_secs(2), &settings()),
+            Some(BotReason::Prefetch)
+        );
+    }
+
+    #[test]
+    fn only_short_links_are_attributed_to_the_recipient() {
+        let subscriber_id = Uuid::new_v4();
+        let tracking = RecipientTracking {
+            base_url: "http://127.0.0.1",
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Some(subscriber_id),
+            open_pixel: true,
+            link_destinations: None,
+        };
+        assert_eq!(
+            tracking.text("http://127.0.0.1/l/abc1234 https://example.com"),
+            format!("http://127.0.0.1/l/abc1234?s={subscriber_id} https://example.com")
+        );
+    }
+
+    #[test]
+    fn aggregate_tracking_does_not_identify_the_recipient() {
+        let newsletter_issue_id = Uuid::new_v4();
+        let tracking = RecipientTracking {
+            base_url: "http://127.0.0.1/",
+            newsletter_issue_id,
+            subscriber_id: None,
+            open_pixel: true,
+            link_destinations: None,
+        };
+        let content = "http://127.0.0.1/l/abc1234";
+        assert_eq!(tracking.text(content), content);
+        assert_eq!(
+            tracking.html(content),
+            format!(
+                r#"{content}<img src="http://127.0.0.1/t/o/{newsletter_issue_id}" width="1" height="1" alt="" />"#
+            )
+        );
+    }
+
+    #[test]
+    fn the_open_pixel_can_be_left_out() {
+        let tracking = RecipientTracking {
+            base_url: "http://127.0.0.1",
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Some(Uuid::new_v4()),
+            open_pixel: false,
+            link_destinations: None,
+        };
+        assert_eq!(tracking.html("<p>Hello</p>"), "<p>Hello</p>");
+    }
+
+    #[test]
+    fn untracked_recipients_get_neither_pixel_nor_short_links() {
+        let destinations =
+            HashMap::from([("abc1234".to_owned(), "https://example.com".to_owned())]);
+        let tracking =
+            RecipientTracking::untracked("http://127.0.0.1", Uuid::new_v4(), &destinations);
+        let content = r#"<a href="http://127.0.0.1/l/abc1234">Read</a>"#;
+        assert_eq!(
+            tracking.html(content),
+            r#"<a href="https://example.com">Read</a>"#
+        );
+        assert_eq!(
+            tracking.text("http://127.0.0.1/l/abc1234"),
+            "https://example.com"
+        );
+    }
+}
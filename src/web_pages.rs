@@ -0,0 +1,650 @@
+//! Pages fetched from the web on behalf of the authors, e.g. a blog post to
+//! draft an issue from.
+use crate::accessibility::{attribute, tag_name};
+use crate::configuration::WebPageSettings;
+use anyhow::Context;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use url::{Host, Url};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Redirects followed at most, as browsers do.
+const MAX_REDIRECTS: usize = 10;
+
+/// Largest page read, about ten times a long blog post with its markup.
+const MAX_PAGE_SIZE: usize = 5 * 1024 * 1024;
+
+/// Elements dropped along with everything in them, never being part of an
+/// article.
+const NOISE_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "iframe", "svg", "nav", "header", "footer", "aside",
+    "form", "button",
+];
+
+/// Elements kept in the content of an article, with the attributes they keep.
+/// The others are dropped, their text being kept.
+const KEPT_ELEMENTS: &[(&str, &[&str])] = &[
+    ("p", &[]),
+    ("h1", &[]),
+    ("h2", &[]),
+    ("h3", &[]),
+    ("h4", &[]),
+    ("h5", &[]),
+    ("h6", &[]),
+    ("a", &["href"]),
+    ("img", &["src", "alt"]),
+    ("ul", &[]),
+    ("ol", &[]),
+    ("li", &[]),
+    ("blockquote", &[]),
+    ("pre", &[]),
+    ("code", &[]),
+    ("em", &[]),
+    ("strong", &[]),
+    ("b", &[]),
+    ("i", &[]),
+    ("br", &[]),
+    ("hr", &[]),
+    ("figure", &[]),
+    ("figcaption", &[]),
+];
+
+/// Elements followed by a line break in the content of an article, so that
+/// its plain text version keeps its paragraphs.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "figure",
+];
+
+/// Elements that cannot have content, thus never closed.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum FetchPageError {
+    #[error("'{0}' is not an http(s) URL.")]
+    InvalidUrl(String),
+    #[error("'{0}' is not on the public internet.")]
+    PrivateAddress(String),
+    #[error(transparent)]
+    Unreachable(#[from] anyhow::Error),
+}
+
+/// A page as served, after following redirects.
+pub struct FetchedPage {
+    pub url: Url,
+    pub html: String,
+}
+
+#[derive(Clone)]
+pub struct PageFetcher {
+    http_client: reqwest::Client,
+    allow_private_addresses: bool,
+}
+
+impl PageFetcher {
+    /// Unless `allow_private_addresses` is set, pages are only fetched from
+    /// public addresses, whatever the redirects they go through.
+    pub fn new(settings: &WebPageSettings) -> Self {
+        let mut builder = reqwest::Client::builder().timeout(FETCH_TIMEOUT);
+        if !settings.allow_private_addresses {
+            // IP literals are connected to without being resolved, the
+            // redirects to them are checked on their own.
+            builder = builder
+                .dns_resolver(Arc::new(PublicAddressResolver))
+                .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        return attempt.error("Too many redirects");
+                    }
+                    if is_private_host(attempt.url()) {
+                        let error = format!("{} is not on the public internet", attempt.url());
+                        return attempt.error(error);
+                    }
+                    attempt.follow()
+                }));
+        }
+        let http_client = builder
+            .build()
+            .expect("Failed to build the page HTTP client");
+        Self {
+            http_client,
+            allow_private_addresses: settings.allow_private_addresses,
+        }
+    }
+
+    #[tracing::instrument(name = "Fetch a web page", skip(self))]
+    pub async fn fetch(&self, url: &str) -> Result<FetchedPage, FetchPageError> {
+        let parsed = Url::parse(url)
+            .ok()
+            .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+            .ok_or_else(|| FetchPageError::InvalidUrl(url.to_owned()))?;
+        if !self.allow_private_addresses && is_private_host(&parsed) {
+            return Err(FetchPageError::PrivateAddress(url.to_owned()));
+        }
+        let mut response = self
+            .http_client
+            .get(parsed)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Failed to fetch {}", url))?;
+        let url = response.url().clone();
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .with_context(|| format!("Failed to read {}", url))?
+        {
+            if body.len() + chunk.len() > MAX_PAGE_SIZE {
+                return Err(
+                    anyhow::anyhow!("{} is larger than {} bytes", url, MAX_PAGE_SIZE).into(),
+                );
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(FetchedPage {
+            url,
+            html: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+/// Resolves host names to their public addresses only, failing when they
+/// have none: a page must not be fetched from the internal network, e.g. a
+/// cloud metadata endpoint, on behalf of an author.
+struct PublicAddressResolver;
+
+impl Resolve for PublicAddressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| is_public_address(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            let addresses: Addrs = Box::new(addresses.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+/// Whether `url` points at an IP address that is not public. Host names are
+/// checked once resolved.
+fn is_private_host(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => !is_public_address(ip.into()),
+        Some(Host::Ipv6(ip)) => !is_public_address(ip.into()),
+        Some(Host::Domain(_)) => false,
+        None => true,
+    }
+}
+
+/// Whether `ip` is reachable on the public internet, unlike loopback,
+/// private, link-local, unique local and the other special-purpose ranges.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // "This network", shared address space, IETF protocol
+                // assignments, benchmarking and reserved.
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64)
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (b & 0xfe) == 18)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(ip.into());
+            }
+            let segments = ip.segments();
+            // NAT64 addresses reach the IPv4 address they embed.
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., high, low] = segments;
+                let embedded = (u32::from(high) << 16) | u32::from(low);
+                return is_public_address(std::net::Ipv4Addr::from(embedded).into());
+            }
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local, link-local, site-local and documentation.
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80
+                || (segments[0] & 0xffc0) == 0xfec0
+                || (segments[0] == 0x2001 && segments[1] == 0xdb8))
+        }
+    }
+}
+
+/// The readable part of a page, as email-friendly HTML.
+#[derive(Debug, PartialEq)]
+pub struct Article {
+    pub title: Option<String>,
+    pub html: String,
+}
+
+/// Extract the article of a page the way reader modes do: the `<article>`
+/// element, `<main>` or the whole `<body>` otherwise, without navigation,
+/// scripts and the like.
+///
+/// Only the markup of the text is kept, links and images being made
+/// absolute against `base_url`. A heading repeating the title is dropped,
+/// as templates show the title. `None` when there is no text to be found.
+pub fn extract_article(html: &str, base_url: &Url) -> Option<Article> {
+    let tokens = tokenize(html);
+    let title = meta_content(&tokens, "property", "og:title")
+        .or_else(|| element_text(&tokens, "title"))
+        .or_else(|| element_text(&tokens, "h1"))
+        .filter(|title| !title.is_empty());
+    let tokens = without_noise(tokens);
+    let content = ["article", "main", "body"]
+        .iter()
+        .find_map(|name| element_content(&tokens, name))
+        .unwrap_or(&tokens);
+    let html = clean(content, base_url, title.as_deref());
+    let has_text = content
+        .iter()
+        .any(|token| matches!(token, Token::Text(text) if !text.trim().is_empty()));
+    has_text.then_some(Article { title, html })
+}
+
+/// Keep only the markup of the text of an HTML fragment, e.g. the summary
+/// of a feed entry, as for the content of an article: scripts, styles and
+/// the like are dropped, links and images made absolute against `base_url`.
+pub fn sanitize_fragment(html: &str, base_url: &Url) -> String {
+    clean(&without_noise(tokenize(html)), base_url, None)
+}
+
+/// What a page tells the apps unfurling links to it, from its OpenGraph
+/// tags or, failing those, its `<title>` and description.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PagePreview {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Absolute URL of the image illustrating the page.
+    pub image_url: Option<String>,
+}
+
+pub fn extract_preview(html: &str, base_url: &Url) -> PagePreview {
+    let tokens = tokenize(html);
+    let non_empty = |text: &String| !text.is_empty();
+    PagePreview {
+        title: meta_content(&tokens, "property", "og:title")
+            .or_else(|| element_text(&tokens, "title"))
+            .filter(non_empty),
+        description: meta_content(&tokens, "property", "og:description")
+            .or_else(|| meta_content(&tokens, "name", "description"))
+            .filter(non_empty),
+        image_url: meta_content(&tokens, "property", "og:image")
+            .and_then(|image| base_url.join(&image).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .map(String::from),
+    }
+}
+
+#[derive(Debug)]
+enum Token<'a> {
+    Text(&'a str),
+    Open {
+        name: String,
+        /// The tag without its angle brackets, attributes included.
+        tag: &'a str,
+        void: bool,
+    },
+    Close(String),
+}
+
+/// Split `html` into text and tags, dropping comments and doctypes.
+///
+/// Lenient as browsers are: a `<` that does not start a tag is text.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let starts_tag = rest[start + 1..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?');
+        if !starts_tag {
+            tokens.push(Token::Text(&rest[..start + 1]));
+            rest = &rest[start + 1..];
+            continue;
+        }
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.split_once("-->").map_or("", |(_, after)| after);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(tag_name(name)));
+        } else if !tag.starts_with('!') && !tag.starts_with('?') {
+            let name = tag_name(tag);
+            let void = tag.ends_with('/') || VOID_ELEMENTS.contains(&name.as_str());
+            tokens.push(Token::Open { name, tag, void });
+        }
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// The `content` of the first `<meta>` whose `key` attribute is `value`,
+/// e.g. `property="og:title"`.
+fn meta_content(tokens: &[Token], key: &str, value: &str) -> Option<String> {
+    tokens.iter().find_map(|token| match token {
+        Token::Open { name, tag, .. } if name == "meta" && attribute(tag, key) == Some(value) => {
+            attribute(tag, "content").map(|content| decode_entities(content.trim()))
+        }
+        _ => None,
+    })
+}
+
+/// The text of the first `name` element, tags stripped.
+fn element_text(tokens: &[Token], name: &str) -> Option<String> {
+    let content = element_content(tokens, name)?;
+    let text: String = content
+        .iter()
+        .filter_map(|token| match token {
+            Token::Text(text) => Some(*text),
+            _ => None,
+        })
+        .collect();
+    Some(decode_entities(
+        &text.split_whitespace().collect::<Vec<_>>().join(" "),
+    ))
+}
+
+/// What is within the first `name` element, up to the end of the page when
+/// it is not closed.
+fn element_content<'t, 'a>(tokens: &'t [Token<'a>], name: &str) -> Option<&'t [Token<'a>]> {
+    let start = tokens
+        .iter()
+        .position(|token| matches!(token, Token::Open { name: n, void: false, .. } if n == name))?
+        + 1;
+    let mut depth = 0;
+    for (i, token) in tokens[start..].iter().enumerate() {
+        match token {
+            Token::Open {
+                name: n,
+                void: false,
+                ..
+            } if n == name => depth += 1,
+            Token::Close(n) if n == name => {
+                if depth == 0 {
+                    return Some(&tokens[start..start + i]);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    Some(&tokens[start..])
+}
+
+fn without_noise(tokens: Vec<Token<'_>>) -> Vec<Token<'_>> {
+    let mut kept = Vec::with_capacity(tokens.len());
+    // The noise element being skipped, and how many of the same are nested.
+    let mut skipping: Option<(String, usize)> = None;
+    for token in tokens {
+        match (&mut skipping, &token) {
+            (
+                Some((name, depth)),
+                Token::Open {
+                    name: n,
+                    void: false,
+                    ..
+                },
+            ) if n == name => *depth += 1,
+            (Some((name, depth)), Token::Close(n)) if n == name => {
+                if *depth == 0 {
+                    skipping = None;
+                } else {
+                    *depth -= 1;
+                }
+            }
+            (Some(_), _) => {}
+            (None, Token::Open { name, void, .. }) if NOISE_ELEMENTS.contains(&name.as_str()) => {
+                if !void {
+                    skipping = Some((name.clone(), 0));
+                }
+            }
+            (None, _) => kept.push(token),
+        }
+    }
+    kept
+}
+
+fn clean(tokens: &[Token], base_url: &Url, title: Option<&str>) -> String {
+    let mut html = String::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        match &tokens[index] {
+            Token::Text(text) => {
+                // Source indentation is no content.
+                if !text.trim().is_empty() || html.ends_with(|c: char| !c.is_whitespace()) {
+                    html.push_str(text);
+                }
+            }
+            Token::Open { name, tag, .. } => {
+                if name == "h1" && title.is_some() {
+                    let rest = &tokens[index..];
+                    let heading = element_content(rest, "h1").unwrap_or_default();
+                    if element_text(rest, "h1").as_deref() == title {
+                        // Skip the heading and its closing tag.
+                        index += heading.len() + 2;
+                        continue;
+                    }
+                }
+                if let Some((_, attributes)) = KEPT_ELEMENTS.iter().find(|(n, _)| n == name) {
+                    html.push('<');
+                    html.push_str(name);
+                    for &attribute_name in *attributes {
+                        let Some(value) = attribute(tag, attribute_name)
+                            .and_then(|value| absolute_url(attribute_name, value, base_url))
+                        else {
+                            continue;
+                        };
+                        html.push_str(&format!(r#" {}="{}""#, attribute_name, value));
+                    }
+                    html.push('>');
+                }
+            }
+            Token::Close(name) => {
+                if KEPT_ELEMENTS.iter().any(|(n, _)| n == name) {
+                    html.push_str(&format!("</{}>", name));
+                    if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                        html.push('\n');
+                    }
+                }
+            }
+        }
+        index += 1;
+    }
+    html.trim().to_owned()
+}
+
+/// Links and images are followed from emails, far from the page they were
+/// on: URLs are made absolute, and the scripted ones dropped. Other
+/// attributes are kept as is.
+fn absolute_url(attribute_name: &str, value: &str, base_url: &Url) -> Option<String> {
+    if attribute_name != "href" && attribute_name != "src" {
+        return Some(value.replace('"', "&quot;"));
+    }
+    let url = base_url.join(&decode_entities(value)).ok()?;
+    matches!(url.scheme(), "http" | "https" | "mailto").then(|| url.as_str().replace('"', "%22"))
+}
+
+/// Decode the entities found in titles; markup is kept as written.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Article, PagePreview, extract_article, extract_preview, is_private_host, is_public_address,
+    };
+    use url::Url;
+
+    fn base_url() -> Url {
+        Url::parse("https://blog.example.com/posts/hello").unwrap()
+    }
+
+    #[test]
+    fn the_article_element_is_preferred() {
+        let html = r#"<!DOCTYPE html>
+<html><head><title>Hello &amp; welcome | My blog</title>
+<script>if (a < b) { document.write("<p>nope</p>") }</script></head>
+<body><nav><a href="/">Home</a></nav>
+<p>Not the article</p>
+<article><h1>Hello &amp; welcome | My blog</h1>
+<p>First <em>paragraph</em>.</p><!-- a comment -->
+<p class="lead" style="color: red">Second one.</p></article>
+<footer>Copyright</footer></body></html>"#;
+
+        let article = extract_article(html, &base_url()).unwrap();
+
+        assert_eq!(
+            article,
+            Article {
+                title: Some("Hello & welcome | My blog".into()),
+                html: "<p>First <em>paragraph</em>.</p>\n<p>Second one.</p>".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn the_body_is_used_without_noise_when_there_is_no_article() {
+        let html = r#"<html><head><meta property="og:title" content="Shared title">
+<title>Page title</title></head>
+<body><header><h1>My blog</h1></header>
+<div><p>Some <span>text</span>.</p><aside>Related posts</aside></div></body></html>"#;
+
+        let article = extract_article(html, &base_url()).unwrap();
+
+        assert_eq!(article.title.as_deref(), Some("Shared title"));
+        assert_eq!(article.html, "<p>Some text.</p>");
+    }
+
+    #[test]
+    fn links_and_images_are_made_absolute() {
+        let html = r#"<main><p><a href="../about" onclick="track()">About</a>
+<img src="/cat.png" alt="A cat" width="300"><a href="javascript:alert(1)">Evil</a></p></main>"#;
+
+        let article = extract_article(html, &base_url()).unwrap();
+
+        assert_eq!(
+            article.html,
+            r#"<p><a href="https://blog.example.com/about">About</a>
+<img src="https://blog.example.com/cat.png" alt="A cat"><a>Evil</a></p>"#
+        );
+    }
+
+    #[test]
+    fn pages_without_text_have_no_article() {
+        let html = "<html><body><script>app()</script><nav>Menu</nav></body></html>";
+
+        assert_eq!(extract_article(html, &base_url()), None);
+    }
+
+    #[test]
+    fn previews_prefer_opengraph_tags() {
+        let html = r#"<head><title>Page title</title>
+<meta name="description" content="Page description">
+<meta property="og:title" content="Tom &amp; Jerry">
+<meta property="og:image" content="/images/cover.png?w=600&amp;h=300"></head>"#;
+
+        assert_eq!(
+            extract_preview(html, &base_url()),
+            PagePreview {
+                title: Some("Tom & Jerry".into()),
+                description: Some("Page description".into()),
+                image_url: Some("https://blog.example.com/images/cover.png?w=600&h=300".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn pages_without_metadata_have_an_empty_preview() {
+        assert_eq!(
+            extract_preview("<p>Hello</p>", &base_url()),
+            PagePreview::default()
+        );
+    }
+
+    #[test]
+    fn only_public_addresses_can_be_fetched_from() {
+        for ip in ["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn urls_to_private_ip_addresses_are_recognized() {
+        let private = |url: &str| is_private_host(&Url::parse(url).unwrap());
+
+        assert!(private("http://127.0.0.1:8000/"));
+        assert!(private("http://[::1]/"));
+        assert!(private("http://169.254.169.254/latest/meta-data/"));
+        assert!(!private("https://93.184.215.14/"));
+        // Resolved before connecting.
+        assert!(!private("http://localhost/"));
+    }
+}
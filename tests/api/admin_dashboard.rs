@@ -0,0 +1,107 @@
+use crate::helpers::{browser, log_in, spawn_app};
+
+#[tokio::test]
+async fn anonymous_visitors_of_the_admin_pages_are_sent_to_the_login_form() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for page in ["/admin/dashboard", "/admin/newsletters", "/admin/password"] {
+        // Act
+        let response = browser()
+            .get(format!("{}{}", app.admin_address, page))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 303, "{} was served", page);
+        assert_eq!(response.headers()["Location"], "/login");
+    }
+}
+
+#[tokio::test]
+async fn unknown_admin_pages_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    let logged_in = browser();
+    log_in(&app, &logged_in, &app.test_user.password).await;
+
+    for client in [browser(), logged_in] {
+        // Act
+        let response = client
+            .get(format!("{}/admin/no-such-page", app.admin_address))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn the_dashboard_greets_the_admin_logged_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = browser();
+    log_in(&app, &client, &app.test_user.password).await;
+
+    // Act
+    let response = client
+        .get(format!("{}/admin/dashboard", app.admin_address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(&format!("Welcome {}!", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_admin_forms_are_served_to_the_admin_logged_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = browser();
+    log_in(&app, &client, &app.test_user.password).await;
+    let test_cases = [
+        ("/admin/newsletters", r#"action="/newsletters""#),
+        ("/admin/password", r#"action="/admin/password""#),
+    ];
+
+    for (page, form) in test_cases {
+        // Act
+        let response = client
+            .get(format!("{}{}", app.admin_address, page))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200, "{} was not served", page);
+        assert!(response.text().await.unwrap().contains(form));
+    }
+}
+
+#[tokio::test]
+async fn the_password_can_still_be_changed_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = browser();
+    log_in(&app, &client, &app.test_user.password).await;
+
+    // Act
+    let response = client
+        .post(format!("{}/admin/password", app.admin_address))
+        .json(&serde_json::json!({
+            "current_password": app.test_user.password,
+            "new_password": "correct horse battery staple and more",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
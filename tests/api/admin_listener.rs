@@ -0,0 +1,92 @@
+use crate::helpers::{TestApp, spawn_app_with_configuration};
+use zero2prod::configuration::ListenerSettings;
+
+async fn spawn_app_with_admin_listener() -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.application.admin_listener = Some(ListenerSettings {
+            host: "127.0.0.1".into(),
+            port: 0,
+        });
+    })
+    .await
+}
+
+async fn get_drafts(app: &TestApp, address: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/newsletters/drafts", address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn admin_routes_are_only_served_by_the_admin_listener() {
+    // Arrange
+    let app = spawn_app_with_admin_listener().await;
+    assert_ne!(app.admin_address, app.address);
+
+    // Act
+    let public = get_drafts(&app, &app.address).await;
+    let admin = get_drafts(&app, &app.admin_address).await;
+
+    // Assert
+    assert_eq!(public.status().as_u16(), 404);
+    assert_eq!(admin.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn public_routes_are_only_served_by_the_public_listener() {
+    // Arrange
+    let app = spawn_app_with_admin_listener().await;
+
+    for address in [&app.address, &app.admin_address] {
+        // Act
+        let response = reqwest::get(format!("{}/subscriptions/login", address))
+            .await
+            .unwrap();
+
+        // Assert
+        let expected = if address == &app.address { 200 } else { 404 };
+        assert_eq!(response.status().as_u16(), expected);
+    }
+}
+
+#[tokio::test]
+async fn dev_routes_are_served_by_the_admin_listener() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.application.enable_dev_routes = true;
+        c.application.admin_listener = Some(ListenerSettings {
+            host: "127.0.0.1".into(),
+            port: 0,
+        });
+    })
+    .await;
+
+    for address in [&app.address, &app.admin_address] {
+        // Act
+        let response = reqwest::Client::new()
+            .post(format!("{}/dev/fixtures/subscribers?count=1", address))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        let expected = if address == &app.address { 404 } else { 200 };
+        assert_eq!(response.status().as_u16(), expected);
+    }
+}
+
+#[tokio::test]
+async fn every_route_is_served_by_the_public_listener_without_an_admin_listener() {
+    // Arrange
+    let app = spawn_app_with_configuration(|_| {}).await;
+
+    // Act
+    let response = get_drafts(&app, &app.address).await;
+
+    // Assert
+    assert_eq!(app.admin_address, app.address);
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,99 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app_with_configuration};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::admin_reports::AdminReportScheduler;
+use zero2prod::configuration::AdminReportSettings;
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::SendEmailRequest;
+
+async fn spawn_app_with_weekly_report() -> (TestApp, AdminReportScheduler) {
+    let report = AdminReportSettings {
+        name: "Weekly report".into(),
+        schedule: "0 0 8 * * Mon".parse().unwrap(),
+        recipients: vec![
+            SubscriberEmail::try_from("admin@example.com".to_owned()).unwrap(),
+            SubscriberEmail::try_from("editor@example.com".to_owned()).unwrap(),
+        ],
+    };
+    let app = spawn_app_with_configuration(|c| c.admin_reports = vec![report]).await;
+    let scheduler = AdminReportScheduler::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        Arc::new(app.configuration.email_client.client().unwrap()),
+    )
+    .unwrap();
+    (app, scheduler)
+}
+
+#[tokio::test]
+async fn reports_summarise_the_period_since_the_last_run() {
+    // Arrange
+    let (app, scheduler) = spawn_app_with_weekly_report().await;
+    let now = Utc::now();
+    scheduler.run_due_reports(now).await.unwrap();
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Rust in production",
+        "content": {
+            "text": "Read https://example.com/rust",
+            "html": r#"<a href="https://example.com/rust">Read</a>"#,
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Act
+    let runs = scheduler
+        .run_due_reports(now + Duration::days(8))
+        .await
+        .unwrap();
+    let runs_again = scheduler
+        .run_due_reports(now + Duration::days(8))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].name, "Weekly report");
+    assert!(runs_again.is_empty());
+    let requests = app.email_server.received_requests().await.unwrap();
+    let reports: Vec<SendEmailRequest> = requests
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .filter(|e: &SendEmailRequest| e.subject.starts_with("Weekly report"))
+        .collect();
+    let recipients: Vec<_> = reports.iter().map(|e| e.to[0].email).collect();
+    assert_eq!(recipients, vec!["admin@example.com", "editor@example.com"]);
+    let text = &reports[0].text;
+    assert!(text.contains("1 new subscribers"), "{}", text);
+    assert!(text.contains("1 confirmed subscribers in total"));
+    assert!(text.contains("- Rust in production opened by 0 of 1 recipients"));
+    assert!(text.contains("1 emails delivered, 0.0% bounced"));
+}
+
+#[tokio::test]
+async fn reports_are_only_registered_the_first_time_they_are_seen() {
+    // Arrange
+    let (app, scheduler) = spawn_app_with_weekly_report().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let runs = scheduler.run_due_reports(Utc::now()).await.unwrap();
+
+    // Assert
+    assert!(runs.is_empty());
+}
@@ -0,0 +1,258 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, publish_issue, spawn_app_with_configuration,
+};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::alerts::{OperationalAlert, OperationalAlerts};
+use zero2prod::configuration::{AlertRule, AlertSettings};
+use zero2prod::domain::SubscriberEmail;
+
+fn alert_settings(rule: AlertRule) -> AlertSettings {
+    AlertSettings {
+        check_interval: std::time::Duration::from_secs(60),
+        cool_down: std::time::Duration::from_secs(3600),
+        email_recipients: vec![],
+        webhook_url: None,
+        slack_webhook_url: None,
+        webhook_timeout: std::time::Duration::from_secs(2),
+        rules: vec![rule],
+    }
+}
+
+fn build_alerts(app: &TestApp) -> OperationalAlerts {
+    OperationalAlerts::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        Arc::new(app.configuration.email_client.client().unwrap()),
+    )
+    .unwrap()
+}
+
+async fn pause_deliveries(app: &TestApp) {
+    reqwest::Client::new()
+        .post(format!("{}/admin/delivery", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "paused": true }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn alerts_are_sent_to_every_channel_once_per_cool_down() {
+    // Arrange
+    let hooks_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks_server)
+        .await;
+    let app = spawn_app_with_configuration(|c| {
+        c.alerts = Some(AlertSettings {
+            email_recipients: vec![
+                SubscriberEmail::try_from("oncall@example.com".to_owned()).unwrap(),
+            ],
+            webhook_url: Some(format!("{}/alerts", hooks_server.uri())),
+            slack_webhook_url: Some(format!("{}/slack", hooks_server.uri())),
+            ..alert_settings(AlertRule::QueueDepth { above: 0 })
+        });
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    pause_deliveries(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+    let alerts = build_alerts(&app);
+    let now = Utc::now();
+
+    // Act
+    let raised = alerts.check(now).await.unwrap();
+    let raised_again = alerts.check(now + Duration::minutes(5)).await.unwrap();
+    let raised_after_cool_down = alerts.check(now + Duration::hours(2)).await.unwrap();
+
+    // Assert
+    assert_eq!(raised.len(), 1);
+    assert_eq!(raised[0].rule, "queue_depth");
+    assert_eq!(raised[0].value, 1.0);
+    assert!(raised_again.is_empty());
+    assert_eq!(raised_after_cool_down.len(), 1);
+
+    let requests = hooks_server.received_requests().await.unwrap();
+    let webhook: OperationalAlert = requests
+        .iter()
+        .find(|r| r.url.path() == "/alerts")
+        .unwrap()
+        .body_json()
+        .unwrap();
+    assert_eq!(webhook, raised[0]);
+    let slack: serde_json::Value = requests
+        .iter()
+        .find(|r| r.url.path() == "/slack")
+        .unwrap()
+        .body_json()
+        .unwrap();
+    assert!(
+        slack["text"]
+            .as_str()
+            .unwrap()
+            .contains("1 deliveries are waiting to be sent")
+    );
+    let emails = app.email_server.received_requests().await.unwrap();
+    let email: serde_json::Value = emails.last().unwrap().body_json().unwrap();
+    assert_eq!(email["to"][0]["email"], "oncall@example.com");
+    assert_eq!(
+        email["subject"],
+        "[Alert] 1 deliveries are waiting to be sent"
+    );
+}
+
+#[tokio::test]
+async fn failing_deliveries_raise_an_alert() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.alerts = Some(alert_settings(AlertRule::DeliveryFailureRate {
+            above: 0.5,
+            min_attempts: 1,
+            window: std::time::Duration::from_secs(900),
+        }))
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+    app.wait_for_deliveries().await;
+
+    // Act
+    let raised = build_alerts(&app).check(Utc::now()).await.unwrap();
+
+    // Assert
+    assert_eq!(raised.len(), 1);
+    assert_eq!(raised[0].rule, "delivery_failure_rate");
+    assert_eq!(raised[0].value, 1.0);
+}
+
+#[tokio::test]
+async fn an_unavailable_provider_raises_an_alert() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.alerts = Some(alert_settings(AlertRule::ProviderUnavailable {
+            consecutive_failures: 2,
+        }))
+    })
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let email_client = Arc::new(app.configuration.email_client.client().unwrap());
+    let alerts = OperationalAlerts::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        email_client.clone(),
+    )
+    .unwrap();
+    let recipient = SubscriberEmail::try_from("ursula_le_guin@gmail.com".to_owned()).unwrap();
+    let send = || email_client.send_email(&recipient, "Subject", "<p>Body</p>", "Body");
+    send().await.unwrap_err();
+    assert!(alerts.check(Utc::now()).await.unwrap().is_empty());
+
+    // Act
+    send().await.unwrap_err();
+    let raised = alerts.check(Utc::now()).await.unwrap();
+
+    // Assert
+    assert_eq!(raised.len(), 1);
+    assert_eq!(raised[0].rule, "provider_unavailable");
+}
+
+#[tokio::test]
+async fn rules_of_one_kind_notify_independently() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.alerts = Some(AlertSettings {
+            rules: vec![
+                AlertRule::ProviderUnavailable {
+                    consecutive_failures: 0,
+                },
+                AlertRule::ProviderUnavailable {
+                    consecutive_failures: 1,
+                },
+            ],
+            ..alert_settings(AlertRule::QueueDepth { above: 0 })
+        });
+    })
+    .await;
+    let email_client = Arc::new(app.configuration.email_client.client().unwrap());
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let recipient = SubscriberEmail::try_from("ursula_le_guin@gmail.com".to_owned()).unwrap();
+    email_client
+        .send_email(&recipient, "Subject", "<p>Body</p>", "Body")
+        .await
+        .unwrap_err();
+    let alerts = OperationalAlerts::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        email_client,
+    )
+    .unwrap();
+
+    // Act
+    let raised = alerts.check(Utc::now()).await.unwrap();
+
+    // Assert
+    let subjects: Vec<_> = raised.iter().map(|a| a.subject.as_str()).collect();
+    assert_eq!(
+        subjects,
+        ["0 consecutive failures", "1 consecutive failures"]
+    );
+}
+
+#[tokio::test]
+async fn alerts_failing_on_every_channel_are_raised_again() {
+    // Arrange
+    let hooks_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&hooks_server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hooks_server)
+        .await;
+    let app = spawn_app_with_configuration(|c| {
+        c.alerts = Some(AlertSettings {
+            webhook_url: Some(format!("{}/alerts", hooks_server.uri())),
+            ..alert_settings(AlertRule::ProviderUnavailable {
+                consecutive_failures: 0,
+            })
+        });
+    })
+    .await;
+    let alerts = build_alerts(&app);
+    let now = Utc::now();
+
+    // Act
+    let raised = alerts.check(now).await.unwrap();
+    let raised_again = alerts.check(now + Duration::minutes(5)).await.unwrap();
+    let raised_once_notified = alerts.check(now + Duration::minutes(10)).await.unwrap();
+
+    // Assert
+    assert!(raised.is_empty());
+    assert_eq!(raised_again.len(), 1);
+    assert!(raised_once_notified.is_empty());
+    assert_eq!(hooks_server.received_requests().await.unwrap().len(), 2);
+}
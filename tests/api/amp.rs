@@ -0,0 +1,119 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, newsletter_body, spawn_app, spawn_app_with_configuration,
+};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+const AMP_BODY: &str = r#"<!doctype html>
+<html ⚡4email>
+<head>
+<meta charset="utf-8">
+<script async src="https://cdn.ampproject.org/v0.js"></script>
+<style amp4email-boilerplate>body{visibility:hidden}</style>
+</head>
+<body><p>Newsletter body as AMP</p></body>
+</html>"#;
+
+/// [`newsletter_body`] with `amp` as the AMP for Email body.
+fn newsletter_body_with_amp(amp: &str) -> serde_json::Value {
+    let mut body = newsletter_body();
+    body["content"]["amp"] = amp.into();
+    body
+}
+
+/// The body of the request sent to the email provider.
+async fn publish_and_get_sent_email(app: &TestApp) -> Vec<u8> {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(newsletter_body_with_amp(AMP_BODY))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    request.body
+}
+
+#[tokio::test]
+async fn amp_bodies_are_passed_to_providers_supporting_them() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.email_client.supports_amp = true).await;
+
+    // Act
+    let email = publish_and_get_sent_email(&app).await;
+
+    // Assert
+    let email: SendEmailRequest = serde_json::from_slice(&email).unwrap();
+    let amp = email.amp_html.unwrap();
+    assert!(amp.starts_with("<!doctype html>"));
+    assert!(amp.contains("<p>Newsletter body as AMP</p>"));
+    assert!(email.html.contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn amp_bodies_carry_the_postal_address_and_unsubscribe_link() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.email_client.supports_amp = true).await;
+
+    // Act
+    let email = publish_and_get_sent_email(&app).await;
+
+    // Assert
+    let email: SendEmailRequest = serde_json::from_slice(&email).unwrap();
+    let amp = email.amp_html.unwrap();
+    let postal_address = &app.configuration.email_templates.footer.postal_address;
+    let footer = amp.find(postal_address.as_str()).unwrap();
+    assert!(footer < amp.find("</body>").unwrap());
+    assert!(amp.contains("/subscriptions/unsubscribe?"));
+}
+
+#[tokio::test]
+async fn amp_bodies_are_left_out_for_other_providers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let email = publish_and_get_sent_email(&app).await;
+
+    // Assert
+    let email: serde_json::Value = serde_json::from_slice(&email).unwrap();
+    assert!(email.get("amp_html").is_none());
+}
+
+#[tokio::test]
+async fn malformed_amp_bodies_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.email_client.supports_amp = true).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let newsletter = app
+        .post_newsletters(newsletter_body_with_amp("<p>Not AMP</p>"))
+        .await;
+    let draft = app
+        .post_newsletter_draft(newsletter_body_with_amp(&AMP_BODY.replace("⚡4email", "")))
+        .await;
+
+    // Assert
+    assert_eq!(newsletter.status().as_u16(), 400);
+    assert_eq!(draft.status().as_u16(), 400);
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"issues!\" FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.issues, 0);
+}
@@ -0,0 +1,194 @@
+use crate::helpers::{TestApp, spawn_app};
+
+async fn generate_api_key(app: &TestApp, name: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/api_keys", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "name": name }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_api_keys(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/api_keys", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn revoke_api_key(app: &TestApp, api_key_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .delete(format!(
+            "{}/admin/api_keys/{}",
+            app.admin_address, api_key_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn publish_with_api_key(app: &TestApp, key: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/newsletters", app.address))
+        .header("X-Api-Key", key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn issues_can_be_published_with_an_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = generate_api_key(&app, "CI pipeline").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let generated: serde_json::Value = response.json().await.unwrap();
+    let key = generated["key"].as_str().unwrap();
+
+    // Act
+    let response = publish_with_api_key(&app, key).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let issue = sqlx::query!("SELECT author_id FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.author_id, Some(app.test_user.user_id));
+    let keys = get_api_keys(&app).await;
+    let listed = &keys["api_keys"][0];
+    assert_eq!(listed["name"], "CI pipeline");
+    assert_eq!(listed["username"], app.test_user.username);
+    assert!(key.starts_with(listed["prefix"].as_str().unwrap()));
+    assert!(listed["last_used_at"].is_string());
+    assert!(listed.get("key").is_none());
+    let stored = sqlx::query!("SELECT key_hash FROM api_keys")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.key_hash, key);
+}
+
+#[tokio::test]
+async fn revoked_or_unknown_api_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let generated: serde_json::Value = generate_api_key(&app, "CI pipeline")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let revoked = revoke_api_key(&app, generated["api_key_id"].as_str().unwrap()).await;
+    let with_revoked_key = publish_with_api_key(&app, generated["key"].as_str().unwrap()).await;
+    let with_unknown_key = publish_with_api_key(&app, "zp_notAKey").await;
+
+    // Assert
+    assert_eq!(revoked.status().as_u16(), 204);
+    assert_eq!(with_revoked_key.status().as_u16(), 401);
+    assert_eq!(with_unknown_key.status().as_u16(), 401);
+    let keys = get_api_keys(&app).await;
+    assert!(keys["api_keys"][0]["revoked_at"].is_string());
+}
+
+#[tokio::test]
+async fn api_keys_cannot_change_passwords() {
+    // Arrange
+    let app = spawn_app().await;
+    let generated: serde_json::Value = generate_api_key(&app, "CI pipeline")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/password", app.admin_address))
+        .header("X-Api-Key", generated["key"].as_str().unwrap())
+        .json(&serde_json::json!({
+            "current_password": app.test_user.password,
+            "new_password": "a-much-longer-new-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn invalid_names_and_unknown_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let unnamed = generate_api_key(&app, " ").await;
+    let unknown = revoke_api_key(&app, &uuid::Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(unnamed.status().as_u16(), 400);
+    assert_eq!(unknown.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn api_keys_cannot_generate_api_keys() {
+    // Arrange
+    let app = spawn_app().await;
+    let generated: serde_json::Value = generate_api_key(&app, "CI pipeline")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/api_keys", app.admin_address))
+        .header("X-Api-Key", generated["key"].as_str().unwrap())
+        .json(&serde_json::json!({ "name": "Another key" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admin_endpoints_accept_an_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let generated: serde_json::Value = generate_api_key(&app, "CI pipeline")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/subscribers", app.admin_address))
+        .header("X-Api-Key", generated["key"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,187 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with_configuration};
+use std::path::{Path, PathBuf};
+use zero2prod::archive::{Archive, ArchiveExport};
+use zero2prod::blob_store::LocalBlobStore;
+use zero2prod::encryption::FieldCipher;
+
+async fn publish(app: &TestApp, title: &str) -> String {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": title,
+            "content": {
+                "text": "Read https://example.com/article",
+                "html": r#"<p>Read <a href="https://example.com/article">this</a></p>"#,
+            }
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+async fn export(app: &TestApp, directory: &Path) -> ArchiveExport {
+    let cipher = FieldCipher::new(app.configuration.encryption.as_ref());
+    Archive::new(&app.configuration)
+        .export(
+            &app.connection_pool,
+            &cipher,
+            &LocalBlobStore::new(directory),
+            "",
+        )
+        .await
+        .unwrap()
+}
+
+fn archive_directory() -> PathBuf {
+    std::env::temp_dir().join(format!("archive-{}", uuid::Uuid::new_v4()))
+}
+
+#[tokio::test]
+async fn published_issues_are_exported_with_an_index() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = publish(&app, "First issue").await;
+    let directory = archive_directory();
+
+    // Act
+    let export = export(&app, &directory).await;
+
+    // Assert
+    assert_eq!(export.written.len(), 2);
+    let index = std::fs::read_to_string(directory.join("index.html")).unwrap();
+    assert!(index.contains(&format!(
+        r#"<a href="issues/{}.html">First issue</a>"#,
+        newsletter_issue_id
+    )));
+    let issue =
+        std::fs::read_to_string(directory.join(format!("issues/{}.html", newsletter_issue_id)))
+            .unwrap();
+    assert!(issue.contains("<title>First issue"));
+    // Links do not depend on the application being up.
+    assert!(issue.contains(r#"<a href="https://example.com/article">this</a>"#));
+    assert!(!issue.contains("/l/"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn issue_titles_are_escaped() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = publish(&app, "<script>alert(1)</script>").await;
+    let directory = archive_directory();
+
+    // Act
+    export(&app, &directory).await;
+
+    // Assert
+    let index = std::fs::read_to_string(directory.join("index.html")).unwrap();
+    let issue =
+        std::fs::read_to_string(directory.join(format!("issues/{}.html", newsletter_issue_id)))
+            .unwrap();
+    for page in [&index, &issue] {
+        assert!(!page.contains("<script>"));
+    }
+    assert!(issue.contains("<h1>&lt;script&gt;alert(1)&lt;/script&gt;</h1>"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn only_changed_pages_are_rewritten() {
+    // Arrange
+    let app = spawn_app().await;
+    let directory = archive_directory();
+    publish(&app, "First issue").await;
+    export(&app, &directory).await;
+
+    // Act
+    let unchanged = export(&app, &directory).await;
+    let second_issue_id = publish(&app, "Second issue").await;
+    let with_new_issue = export(&app, &directory).await;
+
+    // Assert
+    assert!(unchanged.written.is_empty());
+    assert_eq!(unchanged.unchanged, 2);
+    assert_eq!(
+        with_new_issue.written,
+        vec![
+            format!("issues/{}.html", second_issue_id),
+            "index.html".to_owned(),
+        ]
+    );
+    assert_eq!(with_new_issue.unchanged, 1);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn deleted_pages_are_written_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = publish(&app, "First issue").await;
+    let directory = archive_directory();
+    export(&app, &directory).await;
+    let issue_path = format!("issues/{}.html", newsletter_issue_id);
+    std::fs::remove_file(directory.join(&issue_path)).unwrap();
+
+    // Act
+    let export = export(&app, &directory).await;
+
+    // Assert
+    assert_eq!(export.written, vec![issue_path]);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn a_sitemap_lists_the_pages_of_an_archive_with_a_base_url() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.archive.base_url = Some("https://archive.example.com/".into());
+    })
+    .await;
+    let newsletter_issue_id = publish(&app, "First issue").await;
+    let directory = archive_directory();
+
+    // Act
+    let export = export(&app, &directory).await;
+
+    // Assert
+    assert_eq!(export.written.len(), 3);
+    let sitemap = std::fs::read_to_string(directory.join("sitemap.xml")).unwrap();
+    let issue_url = format!(
+        "https://archive.example.com/issues/{}.html",
+        newsletter_issue_id
+    );
+    assert!(sitemap.contains("<loc>https://archive.example.com/index.html</loc>"));
+    assert!(sitemap.contains(&format!("<loc>{}</loc>", issue_url)));
+    let issue =
+        std::fs::read_to_string(directory.join(format!("issues/{}.html", newsletter_issue_id)))
+            .unwrap();
+    assert!(issue.contains(&format!(
+        r#"<meta property="og:url" content="{}">"#,
+        issue_url
+    )));
+    assert!(issue.contains(r#"<meta property="og:title" content="First issue">"#));
+    // Described by its text, with the links subscribers would follow.
+    assert!(issue.contains(
+        r#"<meta property="og:description" content="Read https://example.com/article">"#
+    ));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn archives_without_a_base_url_have_no_sitemap() {
+    // Arrange
+    let app = spawn_app().await;
+    publish(&app, "First issue").await;
+    let directory = archive_directory();
+
+    // Act
+    export(&app, &directory).await;
+
+    // Assert
+    assert!(!directory.join("sitemap.xml").exists());
+    let index = std::fs::read_to_string(directory.join("index.html")).unwrap();
+    assert!(index.contains(r#"<meta property="og:type" content="website">"#));
+    assert!(!index.contains("og:url"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
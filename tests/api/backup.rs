@@ -0,0 +1,233 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, migrated_database, spawn_app, spawn_app_with_encryption,
+};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::backup::{BackupTarget, TABLES, backup, restore};
+use zero2prod::encryption::FieldCipher;
+
+/// A subscriber and an issue sent to them.
+async fn populate(app: &TestApp) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+}
+
+async fn take_backup(app: &TestApp) -> String {
+    let cipher = FieldCipher::new(app.configuration.encryption.as_ref());
+    let mut connection = app.connection_pool.acquire().await.unwrap();
+    backup(&mut connection, &cipher).await.unwrap()
+}
+
+#[tokio::test]
+async fn backups_are_restored_into_a_new_database() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    populate(&app).await;
+    let cipher = FieldCipher::new(app.configuration.encryption.as_ref());
+    let file = std::env::temp_dir().join(format!("{}.backup", Uuid::new_v4()));
+    let target = BackupTarget::parse(file.to_str().unwrap()).unwrap();
+    target
+        .write(app.blob_store().as_ref(), take_backup(&app).await)
+        .await
+        .unwrap();
+    let mut connection = migrated_database(&app.configuration.database).await;
+
+    // Act
+    restore(
+        &mut connection,
+        &cipher,
+        target.read(app.blob_store().as_ref()).await.unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let subscriber = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&mut connection)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.email, "ursula_le_guin@gmail.com");
+    assert_eq!(cipher.decrypt(subscriber.name).unwrap(), "le guin");
+    assert_eq!(subscriber.status, "confirmed");
+    let issue = sqlx::query!("SELECT title FROM newsletter_issues")
+        .fetch_one(&mut connection)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Newsletter title");
+    let user = sqlx::query!("SELECT username FROM users")
+        .fetch_one(&mut connection)
+        .await
+        .unwrap();
+    assert_eq!(user.username, app.test_user.username);
+    std::fs::remove_file(file).unwrap();
+}
+
+#[tokio::test]
+async fn backups_are_encrypted_with_the_configured_key() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    populate(&app).await;
+
+    // Act
+    let backup = take_backup(&app).await;
+
+    // Assert
+    assert!(backup.starts_with("enc:v1:"));
+    assert!(!backup.contains("ursula_le_guin@gmail.com"));
+}
+
+#[tokio::test]
+async fn backups_can_be_uploaded_to_a_url() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    populate(&app).await;
+    let bucket = MockServer::start().await;
+    Mock::given(path("/backups/latest"))
+        .and(method("PUT"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&bucket)
+        .await;
+    let target = BackupTarget::parse(&format!("{}/backups/latest", bucket.uri())).unwrap();
+    let backup = take_backup(&app).await;
+
+    // Act
+    target
+        .write(app.blob_store().as_ref(), backup.clone())
+        .await
+        .unwrap();
+
+    // Assert
+    let upload = bucket.received_requests().await.unwrap().pop().unwrap();
+    assert_eq!(upload.body, backup.into_bytes());
+}
+
+#[tokio::test]
+async fn backups_can_be_kept_in_the_storage() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    populate(&app).await;
+    let target = BackupTarget::parse("store:backups/latest").unwrap();
+    let backup = take_backup(&app).await;
+
+    // Act
+    target
+        .write(app.blob_store().as_ref(), backup.clone())
+        .await
+        .unwrap();
+
+    // Assert
+    let stored = target.read(app.blob_store().as_ref()).await.unwrap();
+    assert_eq!(stored, backup);
+}
+
+#[tokio::test]
+async fn backups_are_not_restored_over_existing_data() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    populate(&app).await;
+    let cipher = FieldCipher::new(app.configuration.encryption.as_ref());
+    let backup = take_backup(&app).await;
+    let mut connection = app.connection_pool.acquire().await.unwrap();
+
+    // Act
+    let outcome = restore(&mut connection, &cipher, backup).await;
+
+    // Assert
+    assert!(outcome.is_err());
+    let subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count, 1);
+}
+
+#[tokio::test]
+async fn restored_rows_keep_their_serial_ids_to_themselves() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    let record_change = "INSERT INTO segment_changes (segment_id, change, name, filter, changed_at) \
+                         VALUES (gen_random_uuid(), 'created', 'Readers', '{}', now())";
+    sqlx::query(record_change)
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    let cipher = FieldCipher::new(app.configuration.encryption.as_ref());
+    let backup = take_backup(&app).await;
+    let mut connection = migrated_database(&app.configuration.database).await;
+    restore(&mut connection, &cipher, backup).await.unwrap();
+
+    // Act
+    let outcome = sqlx::query(record_change).execute(&mut connection).await;
+
+    // Assert
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn backups_are_refused_without_an_encryption_key() {
+    // Arrange
+    let app = spawn_app().await;
+    populate(&app).await;
+    let mut connection = app.connection_pool.acquire().await.unwrap();
+
+    // Act
+    let outcome = backup(&mut connection, &FieldCipher::disabled()).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn backups_cover_every_table_after_the_tables_they_reference() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT table_name::text FROM information_schema.tables \
+         WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' \
+         AND table_name != '_sqlx_migrations' ORDER BY table_name",
+    )
+    .fetch_all(&app.connection_pool)
+    .await
+    .unwrap();
+    let references: Vec<(String, String)> = sqlx::query_as(
+        "SELECT DISTINCT child.relname::text, parent.relname::text FROM pg_constraint \
+         JOIN pg_class child ON child.oid = conrelid \
+         JOIN pg_class parent ON parent.oid = confrelid \
+         WHERE contype = 'f' AND connamespace = current_schema()::regnamespace",
+    )
+    .fetch_all(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // Assert
+    let mut backed_up: Vec<&str> = TABLES.to_vec();
+    backed_up.sort_unstable();
+    assert_eq!(backed_up, tables);
+    let position = |table: &str| TABLES.iter().position(|t| *t == table).unwrap();
+    for (table, referenced) in references {
+        assert!(
+            table == referenced || position(&referenced) < position(&table),
+            "{} is backed up before {}, which it references",
+            table,
+            referenced
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use secrecy::SecretString;
+use std::time::Duration;
+use wiremock::matchers::{header_exists, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::blob_store::{BlobStore, S3BlobStore};
+use zero2prod::configuration::S3Settings;
+
+fn s3_store(bucket: &MockServer) -> S3BlobStore {
+    S3BlobStore::new(S3Settings {
+        endpoint: bucket.uri(),
+        bucket: "zero2prod".into(),
+        region: "eu-west-1".into(),
+        access_key_id: "AKIDEXAMPLE".into(),
+        secret_access_key: SecretString::from("secret"),
+        connect_timeout: Duration::from_secs(1),
+        timeout: Duration::from_millis(500),
+    })
+}
+
+#[tokio::test]
+async fn objects_are_uploaded_to_the_bucket_with_a_signature() {
+    // Arrange
+    let bucket = MockServer::start().await;
+    Mock::given(path("/zero2prod/imports/1.csv"))
+        .and(method("PUT"))
+        .and(header_exists("x-amz-date"))
+        .and(header_exists("x-amz-content-sha256"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&bucket)
+        .await;
+
+    // Act
+    s3_store(&bucket)
+        .put("imports/1.csv", b"email,name\n".to_vec())
+        .await
+        .unwrap();
+
+    // Assert
+    let upload = bucket.received_requests().await.unwrap().pop().unwrap();
+    assert_eq!(upload.body, b"email,name\n");
+    let authorization = upload.headers["authorization"].to_str().unwrap();
+    assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+    assert!(authorization.contains("/eu-west-1/s3/aws4_request"));
+}
+
+#[tokio::test]
+async fn missing_objects_are_reported_as_absent() {
+    // Arrange
+    let bucket = MockServer::start().await;
+    Mock::given(path("/zero2prod/archive/index.html"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&bucket)
+        .await;
+    let store = s3_store(&bucket);
+
+    // Act
+    let content = store.get("archive/index.html").await.unwrap();
+    let exists = store.exists("archive/index.html").await.unwrap();
+
+    // Assert
+    assert!(content.is_none());
+    assert!(!exists);
+}
+
+#[tokio::test]
+async fn failed_uploads_are_errors() {
+    // Arrange
+    let bucket = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(403))
+        .mount(&bucket)
+        .await;
+
+    // Act
+    let outcome = s3_store(&bucket)
+        .put("imports/1.csv", b"email,name\n".to_vec())
+        .await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn a_bucket_that_does_not_answer_times_out() {
+    // Arrange
+    let bucket = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .mount(&bucket)
+        .await;
+
+    // Act
+    let outcome = tokio::time::timeout(
+        Duration::from_secs(5),
+        s3_store(&bucket).get("imports/1.csv"),
+    )
+    .await;
+
+    // Assert
+    assert!(outcome.expect("The request was not timed out").is_err());
+}
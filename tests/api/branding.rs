@@ -0,0 +1,50 @@
+use crate::helpers::{TestApp, create_unconfirmed_subscriber, spawn_app_with_configuration};
+
+async fn spawn_branded_app() -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.branding.product_name = "Rust Weekly".into();
+        c.branding.logo_url = Some("https://cdn.example.com/logo.png".into());
+        c.branding.primary_color = "#ce422b".into();
+        c.branding.footer_address = Some("1 Ferris Lane, Crabtown".into());
+    })
+    .await
+}
+
+fn assert_branded(page: &str) {
+    assert!(page.contains(r#"<img src="https://cdn.example.com/logo.png" alt="Rust Weekly""#));
+    assert!(page.contains("#ce422b"));
+    assert!(page.contains("1 Ferris Lane, Crabtown"));
+}
+
+#[tokio::test]
+async fn the_confirmation_page_carries_the_brand() {
+    // Arrange
+    let app = spawn_branded_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<title>You are subscribed - Rust Weekly</title>"));
+    assert_branded(&page);
+}
+
+#[tokio::test]
+async fn the_login_form_carries_the_brand() {
+    // Arrange
+    let app = spawn_branded_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/subscriptions/login", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<form action="/subscriptions/login" method="post">"#));
+    assert_branded(&page);
+}
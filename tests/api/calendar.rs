@@ -0,0 +1,146 @@
+use crate::helpers::{create_confirmed_subscriber, newsletter_body, spawn_app};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+/// [`newsletter_body`] with `event` attached.
+fn newsletter_body_with_event(event: serde_json::Value) -> serde_json::Value {
+    let mut body = newsletter_body();
+    body["event"] = event;
+    body
+}
+
+fn meetup() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Rust meetup",
+        "starts_at": "2025-07-10T18:00:00Z",
+        "ends_at": "2025-07-10T20:30:00Z",
+        "location": "Berlin",
+    })
+}
+
+#[tokio::test]
+async fn event_issues_carry_an_ics_attachment_and_calendar_links() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(newsletter_body_with_event(meetup()))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(email.attachments.len(), 1);
+    let attachment = &email.attachments[0];
+    assert_eq!(attachment.filename, "event.ics");
+    assert_eq!(attachment.content_type, "text/calendar");
+    let ics = String::from_utf8(BASE64_STANDARD.decode(&attachment.content).unwrap()).unwrap();
+    assert!(ics.contains("DTSTART:20250710T180000Z\r\n"));
+    assert!(ics.contains("SUMMARY:Rust meetup\r\n"));
+    assert!(email.html.contains("Add to calendar"));
+    assert!(email.text.contains("Add to calendar"));
+}
+
+#[tokio::test]
+async fn the_event_of_an_issue_can_be_downloaded() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_body_with_event(meetup()))
+        .await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}/event.ics",
+        app.address, issue.newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/calendar; charset=utf-8"
+    );
+    let ics = response.text().await.unwrap();
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(ics.contains("LOCATION:Berlin\r\n"));
+}
+
+#[tokio::test]
+async fn issues_without_an_event_have_no_ics_file() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_body_with_event(serde_json::Value::Null))
+        .await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}/event.ics",
+        app.address, issue.newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn events_ending_before_they_start_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut event = meetup();
+    event["ends_at"] = "2025-07-10T17:00:00Z".into();
+
+    // Act
+    let newsletter = app
+        .post_newsletters(newsletter_body_with_event(event.clone()))
+        .await;
+    let draft = app
+        .post_newsletter_draft(newsletter_body_with_event(event))
+        .await;
+
+    // Assert
+    assert_eq!(newsletter.status().as_u16(), 400);
+    assert_eq!(draft.status().as_u16(), 400);
+}
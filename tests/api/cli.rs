@@ -0,0 +1,126 @@
+use crate::helpers::spawn_app;
+use secrecy::SecretString;
+use zero2prod::cli::{check_config, check_migrations, create_admin, self_test};
+
+const PASSWORD: &str = "correct-Horse-battery-staple-42";
+
+#[tokio::test]
+async fn create_user_adds_an_admin_able_to_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let output = create_admin(&app.configuration, "ursula", SecretString::from(PASSWORD))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(output.details["username"], "ursula");
+    let response = reqwest::Client::new()
+        .get(format!("{}/newsletters/drafts", app.admin_address))
+        .basic_auth("ursula", Some(PASSWORD))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn create_user_is_a_conflict_when_the_username_is_taken() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let e = create_admin(
+        &app.configuration,
+        &app.test_user.username,
+        SecretString::from(PASSWORD),
+    )
+    .await
+    .unwrap_err();
+
+    // Assert
+    assert_eq!(e.exit_code(), 6);
+    assert_eq!(e.status(), "conflict");
+}
+
+#[tokio::test]
+async fn create_user_rejects_weak_passwords() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let e = create_admin(&app.configuration, "ursula", SecretString::from("password"))
+        .await
+        .unwrap_err();
+
+    // Assert
+    assert_eq!(e.exit_code(), 2);
+}
+
+#[tokio::test]
+async fn an_unreachable_database_has_its_own_exit_code() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = app.configuration.clone();
+    configuration.database.port = 1;
+
+    // Act
+    let e = check_migrations(&configuration).await.unwrap_err();
+
+    // Assert
+    assert_eq!(e.exit_code(), 4);
+    assert_eq!(e.status(), "database_unavailable");
+}
+
+#[tokio::test]
+async fn check_config_fails_without_a_postal_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = app.configuration.clone();
+    configuration.email_templates.footer.postal_address = "".into();
+
+    // Act
+    let e = check_config(&configuration).unwrap_err();
+
+    // Assert
+    assert_eq!(e.exit_code(), 3);
+}
+
+#[tokio::test]
+async fn self_test_passes_when_every_dependency_is_ready() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let output = self_test(&app.configuration).await.unwrap();
+
+    // Assert
+    let checks = output.details["checks"].as_array().unwrap();
+    assert_eq!(checks.len(), 3);
+    assert!(checks.iter().all(|check| check["error"].is_null()));
+}
+
+#[tokio::test]
+async fn self_test_reports_every_failed_check() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = app.configuration.clone();
+    configuration.database.port = 1;
+    configuration.email_client.base_url = "http://127.0.0.1:1".into();
+
+    // Act
+    let e = self_test(&configuration).await.unwrap_err();
+
+    // Assert
+    assert_eq!(e.exit_code(), 5);
+    let report = zero2prod::cli::json_report("self-test", &Err(e));
+    let failed: Vec<_> = report["details"]["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|check| !check["error"].is_null())
+        .map(|check| check["name"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(failed, ["database", "email_provider"]);
+}
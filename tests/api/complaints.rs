@@ -0,0 +1,148 @@
+use crate::helpers::{
+    create_confirmed_subscriber, deliver_issue, spawn_app, spawn_app_with_configuration,
+};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::complaints::ComplaintAlert;
+
+fn spam_complaint(event_id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "events": [{
+            "event": "spam",
+            "email": "ursula_le_guin@gmail.com",
+            "event_id": event_id,
+            "timestamp": chrono::Utc::now().timestamp() + 1,
+        }]
+    })
+}
+
+#[tokio::test]
+async fn complaining_subscribers_are_unsubscribed_and_suppressed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "Newsletter title", "message-1").await;
+
+    // Act
+    let response = app
+        .post_email_events("test-webhook-token", spam_complaint("event-1"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscription.status, "unsubscribed");
+    let suppression = sqlx::query!(
+        r#"
+        SELECT s.email, s.reason, i.title
+        FROM suppressions s
+        JOIN newsletter_issues i ON i.newsletter_issue_id = s.newsletter_issue_id
+        "#
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(suppression.email, "ursula_le_guin@gmail.com");
+    assert_eq!(suppression.reason, "complaint");
+    assert_eq!(suppression.title, "Newsletter title");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_emailed_when_signing_up_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "Newsletter title", "message-1").await;
+    app.post_email_events("test-webhook-token", spam_complaint("event-1"))
+        .await
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn admins_are_notified_once_an_issue_crosses_the_complaint_rate() {
+    // Arrange
+    let webhook_server = MockServer::start().await;
+    let webhook_url = format!("{}/alerts", webhook_server.uri());
+    let app = spawn_app_with_configuration(|c| {
+        c.complaints.alert_rate = 0.5;
+        c.complaints.min_deliveries = 1;
+        c.complaints.webhook_url = Some(webhook_url);
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "Newsletter title", "message-1").await;
+    Mock::given(path("/alerts"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&webhook_server)
+        .await;
+
+    // Act - a second report of the same issue raises no new alert
+    for event_id in ["event-1", "event-2"] {
+        app.post_email_events("test-webhook-token", spam_complaint(event_id))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert
+    let requests = webhook_server.received_requests().await.unwrap();
+    let alert: ComplaintAlert = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(alert.title, "Newsletter title");
+    assert_eq!(alert.deliveries, 1);
+    assert_eq!(alert.complaints, 1);
+    assert_eq!(alert.complaint_rate, 1.0);
+    let dashboard: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/admin/deliverability", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        dashboard["complaint_alerts"][0]["title"],
+        "Newsletter title"
+    );
+}
+
+#[tokio::test]
+async fn issues_below_the_complaint_rate_raise_no_alert() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "Newsletter title", "message-1").await;
+
+    // Act - a single delivery is below the default `min_deliveries`
+    app.post_email_events("test-webhook-token", spam_complaint("event-1"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let alerts = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM complaint_alerts"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(alerts.count, 0);
+}
@@ -0,0 +1,203 @@
+use crate::helpers::{TestApp, spawn_app};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use ring::signature::{ED25519, UnparsedPublicKey};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const MESSAGE_ID: &str = "0c7fd939-02cf-11ed-88c2-0a58a9feac02";
+const BROWSER_USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64; rv:126.0) Gecko/20100101 Firefox/126.0";
+
+/// Sign up and confirm a subscriber from a browser, the email provider returning
+/// [`MESSAGE_ID`] for the confirmation email.
+async fn confirm_from_a_browser(app: &TestApp) -> Uuid {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "message_ids": [MESSAGE_ID] })),
+        )
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::Client::new()
+        .get(confirmation_links.html)
+        .header("User-Agent", BROWSER_USER_AGENT)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn export(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}{}", app.address, path))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The published key the bundles are checked against, as a third party
+/// would fetch it.
+async fn public_key(app: &TestApp) -> Vec<u8> {
+    let response: serde_json::Value =
+        reqwest::get(format!("{}/consent_proofs/public_key", app.address))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+    assert_eq!(response["algorithm"], "Ed25519");
+    BASE64_STANDARD
+        .decode(response["public_key"].as_str().unwrap())
+        .unwrap()
+}
+
+fn verify(public_key: &[u8], export: &serde_json::Value) -> Result<(), ring::error::Unspecified> {
+    let signature = BASE64_STANDARD
+        .decode(export["signature"].as_str().unwrap())
+        .unwrap();
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(export["bundle"].to_string().as_bytes(), &signature)
+}
+
+async fn assert_signed(app: &TestApp, export: &serde_json::Value) {
+    let public_key = public_key(app).await;
+    assert_eq!(export["public_key"], BASE64_STANDARD.encode(&public_key));
+    verify(&public_key, export).expect("The proof bundle signature is invalid");
+}
+
+#[tokio::test]
+async fn the_proof_of_consent_of_a_subscriber_is_exported() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = confirm_from_a_browser(&app).await;
+
+    // Act
+    let response = export(
+        &app,
+        &format!("/admin/subscribers/{}/consent_proof", subscriber_id),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response.headers()["Content-Disposition"]
+            .to_str()
+            .unwrap()
+            .contains(&format!("consent-proof-{}.json", subscriber_id))
+    );
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_signed(&app, &export).await;
+    let subscribers = export["bundle"]["subscribers"].as_array().unwrap();
+    assert_eq!(subscribers.len(), 1);
+    let proof = &subscribers[0];
+    assert_eq!(proof["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(proof["status"], "confirmed");
+    assert_eq!(proof["consent"]["channel"], "form");
+    assert_eq!(proof["consent"]["ip"], "127.0.0.1");
+    assert!(proof["consent"]["subscribed_at"].is_string());
+    assert_eq!(proof["confirmation"]["email_message_id"], MESSAGE_ID);
+    assert!(proof["confirmation"]["email_sent_at"].is_string());
+    assert!(proof["confirmation"]["confirmed_at"].is_string());
+    assert_eq!(proof["confirmation"]["ip"], "127.0.0.1");
+    assert_eq!(proof["confirmation"]["user_agent"], BROWSER_USER_AGENT);
+    let token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    let token_hash = {
+        use sha2::Digest;
+        hex::encode(sha2::Sha256::digest(token.as_bytes()))
+    };
+    assert_eq!(proof["token_hashes"], serde_json::json!([token_hash]));
+}
+
+#[tokio::test]
+async fn the_proofs_of_consent_of_the_whole_list_are_exported() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = confirm_from_a_browser(&app).await;
+
+    // Act
+    let response = export(&app, "/admin/consent_proofs").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_signed(&app, &export).await;
+    let subscribers = export["bundle"]["subscribers"].as_array().unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["subscriber_id"], subscriber_id.to_string());
+}
+
+#[tokio::test]
+async fn an_altered_proof_bundle_fails_verification() {
+    // Arrange
+    let app = spawn_app().await;
+    confirm_from_a_browser(&app).await;
+    let mut export: serde_json::Value = export(&app, "/admin/consent_proofs")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    export["bundle"]["subscribers"][0]["consent"]["ip"] = "10.0.0.1".into();
+
+    // Assert
+    assert!(verify(&public_key(&app).await, &export).is_err());
+}
+
+#[tokio::test]
+async fn unknown_subscribers_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = export(
+        &app,
+        &format!("/admin/subscribers/{}/consent_proof", Uuid::new_v4()),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn exports_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/consent_proofs", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
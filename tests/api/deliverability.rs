@@ -0,0 +1,205 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, deliver_issue, spawn_app};
+
+async fn get_deliverability(app: &TestApp) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/deliverability", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn email_events_without_a_valid_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_email_events("not-the-token", serde_json::json!({"events": []}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_deliverability_dashboard_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/deliverability", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn bounces_and_complaints_are_aggregated_per_issue_and_per_domain() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "Newsletter title", "message-1").await;
+    let events = serde_json::json!({
+        "events": [
+            {
+                "event": "bounce",
+                "email": "ursula_le_guin@gmail.com",
+                "event_id": "event-1",
+                "timestamp": chrono::Utc::now().timestamp() + 1,
+            },
+            {
+                "event": "spam",
+                "email": "ursula_le_guin@gmail.com",
+                "event_id": "event-2",
+                "timestamp": chrono::Utc::now().timestamp() + 1,
+            },
+            {
+                "event": "delivery",
+                "email": "ursula_le_guin@gmail.com",
+                "event_id": "event-3",
+            },
+        ]
+    });
+
+    // Act - the provider retries, the events must only be counted once
+    for _ in 0..2 {
+        let response = app
+            .post_email_events("test-webhook-token", events.clone())
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert
+    let response = get_deliverability(&app).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let dashboard: serde_json::Value = response.json().await.unwrap();
+    let issue = &dashboard["issues"][0];
+    assert_eq!(issue["title"], "Newsletter title");
+    assert_eq!(issue["deliveries"], 1);
+    assert_eq!(issue["bounces"], 1);
+    assert_eq!(issue["bounce_rate"], 1.0);
+    assert_eq!(issue["complaints"], 1);
+    assert_eq!(issue["complaint_rate"], 1.0);
+    let domain = &dashboard["domains"][0];
+    assert_eq!(domain["domain"], "gmail.com");
+    assert_eq!(domain["deliveries"], 1);
+    assert_eq!(domain["bounces"], 1);
+    let events = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM delivery_events")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(events.count, 2);
+}
+
+#[tokio::test]
+async fn events_not_matching_a_newsletter_delivery_are_dropped() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_email_events(
+            "test-webhook-token",
+            serde_json::json!({
+                "events": [{"event": "bounce", "email": "nobody@outlook.com"}]
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let dashboard: serde_json::Value = get_deliverability(&app).await.json().await.unwrap();
+    assert_eq!(dashboard["issues"], serde_json::json!([]));
+    assert_eq!(dashboard["domains"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn events_are_matched_to_deliveries_using_the_provider_message_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "First issue", "message-1").await;
+    deliver_issue(&app, "Second issue", "message-2").await;
+
+    // Act - the bounce is about the first issue, not the latest one
+    let response = app
+        .post_email_events(
+            "test-webhook-token",
+            serde_json::json!({
+                "events": [{
+                    "event": "bounce",
+                    "email": "ursula_le_guin@gmail.com",
+                    "message_id": "message-1",
+                }]
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let dashboard: serde_json::Value = get_deliverability(&app).await.json().await.unwrap();
+    let issues = dashboard["issues"].as_array().unwrap();
+    let bounces = |title: &str| {
+        issues
+            .iter()
+            .find(|i| i["title"] == title)
+            .map(|i| i["bounces"].clone())
+            .unwrap()
+    };
+    assert_eq!(bounces("First issue"), 1);
+    assert_eq!(bounces("Second issue"), 0);
+}
+
+#[tokio::test]
+async fn events_with_an_unknown_message_id_are_dropped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_issue(&app, "Newsletter title", "message-1").await;
+
+    // Act
+    let response = app
+        .post_email_events(
+            "test-webhook-token",
+            serde_json::json!({
+                "events": [{
+                    "event": "bounce",
+                    "email": "ursula_le_guin@gmail.com",
+                    "message_id": "a-confirmation-email",
+                }]
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let dashboard: serde_json::Value = get_deliverability(&app).await.json().await.unwrap();
+    assert_eq!(dashboard["issues"][0]["bounces"], 0);
+}
+
+#[tokio::test]
+async fn email_endpoint_stats_count_sent_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/deliverability/endpoints", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let endpoints: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(endpoints[0]["base_url"], app.email_server.uri());
+    assert_eq!(endpoints[0]["routes"], serde_json::json!(["default"]));
+    assert_eq!(endpoints[0]["sent"], 1);
+    assert_eq!(endpoints[0]["failures"], 0);
+}
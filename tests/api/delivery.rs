@@ -0,0 +1,433 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with_configuration};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wiremock::matchers::{any, body_string_contains, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{DomainThrottlingSettings, FeatureFlagSettings};
+use zero2prod::delivery::DeliveryWorker;
+use zero2prod::feature_flags::{FeatureFlags, PAUSE_DELIVERIES, store_override};
+use zero2prod::throttling::DeliveryThrottle;
+
+fn delivery_worker(app: &TestApp) -> DeliveryWorker {
+    DeliveryWorker::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        Arc::new(app.configuration.email_client.client().unwrap()),
+        Arc::new(DeliveryThrottle::new(
+            &app.configuration.delivery_throttling,
+        )),
+    )
+}
+
+async fn insert_confirmed_subscribers(app: &TestApp, count: usize) {
+    for i in 0..count {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, $3, now(), 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            format!("reader{}@example.com", i),
+            format!("reader {}", i),
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+}
+
+/// Publish an issue while the email provider is down, leaving every
+/// delivery in the queue.
+async fn publish_while_provider_is_down(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+}
+
+async fn queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM delivery_tasks"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn delivered_issues_leave_nothing_in_the_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 3).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Assert
+    assert_eq!(queued_deliveries(&app).await, 0);
+    let deliveries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_deliveries"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, 3);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_by_the_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 2).await;
+    publish_while_provider_is_down(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let sent = delivery_worker(&app).deliver_pending().await.unwrap();
+
+    // Assert
+    assert_eq!(sent, 2);
+    assert_eq!(queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn deliveries_of_a_crashed_worker_are_reclaimed_once_their_lease_expires() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 1).await;
+    publish_while_provider_is_down(&app).await;
+    sqlx::query!(
+        r#"
+        UPDATE delivery_tasks
+        SET claimed_by = $1, lease_expires_at = now() - interval '1 second'
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let sent = delivery_worker(&app).deliver_pending().await.unwrap();
+
+    // Assert
+    assert_eq!(sent, 1);
+    assert_eq!(queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn deliveries_under_a_live_lease_are_left_alone() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 1).await;
+    publish_while_provider_is_down(&app).await;
+    sqlx::query!(
+        r#"
+        UPDATE delivery_tasks
+        SET claimed_by = $1, lease_expires_at = now() + interval '1 minute'
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let sent = delivery_worker(&app).deliver_pending().await.unwrap();
+
+    // Assert
+    assert_eq!(sent, 0);
+    assert_eq!(queued_deliveries(&app).await, 1);
+}
+
+#[tokio::test]
+async fn concurrent_workers_send_each_delivery_once() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 25).await;
+    publish_while_provider_is_down(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(25)
+        .mount(&app.email_server)
+        .await;
+    let workers = [delivery_worker(&app), delivery_worker(&app)];
+
+    // Act
+    let (first, second) = tokio::join!(workers[0].deliver_pending(), workers[1].deliver_pending());
+
+    // Assert
+    assert_eq!(first.unwrap() + second.unwrap(), 25);
+    assert_eq!(queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn deliveries_to_a_domain_are_spread_out_by_its_limit() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.delivery_throttling.domains = vec![DomainThrottlingSettings {
+            domain: "example.com".into(),
+            per_second: NonZeroU32::new(4).unwrap(),
+        }]
+    })
+    .await;
+    insert_confirmed_subscribers(&app, 5).await;
+    publish_while_provider_is_down(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let started_at = Instant::now();
+    let sent = delivery_worker(&app).deliver_pending().await.unwrap();
+
+    // Assert
+    assert_eq!(sent, 5);
+    assert!(started_at.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn deliveries_failing_too_often_are_given_up() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 1).await;
+    publish_while_provider_is_down(&app).await;
+    sqlx::query!("UPDATE delivery_tasks SET attempts = 5")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let sent = delivery_worker(&app).deliver_pending().await.unwrap();
+
+    // Assert
+    assert_eq!(sent, 0);
+    assert_eq!(queued_deliveries(&app).await, 1);
+}
+
+#[tokio::test]
+async fn deliveries_are_sent_concurrently() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.email_client.max_concurrent_sends = 10.try_into().unwrap();
+    })
+    .await;
+    insert_confirmed_subscribers(&app, 10).await;
+    publish_while_provider_is_down(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(10)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let started_at = Instant::now();
+    let sent = delivery_worker(&app).deliver_pending().await.unwrap();
+
+    // Assert
+    assert_eq!(sent, 10);
+    assert!(started_at.elapsed() < Duration::from_secs(2));
+    assert_eq!(queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn deliveries_paused_mid_batch_go_back_to_the_queue_without_an_attempt() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.email_client.max_concurrent_sends = 1.try_into().unwrap();
+    })
+    .await;
+    insert_confirmed_subscribers(&app, 3).await;
+    publish_while_provider_is_down(&app).await;
+    let email_client = Arc::new(
+        app.configuration
+            .email_client
+            .client()
+            .unwrap()
+            .with_pause_switch(
+                FeatureFlags::new(app.configuration.feature_flags.clone()),
+                app.connection_pool.clone(),
+            ),
+    );
+    let worker = DeliveryWorker::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        email_client.clone(),
+        Arc::new(DeliveryThrottle::new(
+            &app.configuration.delivery_throttling,
+        )),
+    );
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let run = tokio::spawn(async move { worker.deliver_pending().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let paused = FeatureFlagSettings {
+        enabled: true,
+        rollout_percentage: None,
+    };
+    store_override(&app.connection_pool, PAUSE_DELIVERIES, &paused)
+        .await
+        .unwrap();
+    email_client.forget_pause_state().await;
+    let sent = run.await.unwrap().unwrap();
+
+    // Assert
+    assert_eq!(sent, 1);
+    let queued = sqlx::query!("SELECT attempts, claimed_by FROM delivery_tasks")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 2);
+    for delivery in queued {
+        assert_eq!(delivery.attempts, 1);
+        assert!(delivery.claimed_by.is_none());
+    }
+}
+
+#[tokio::test]
+async fn a_failed_delivery_does_not_hold_up_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 3).await;
+    publish_while_provider_is_down(&app).await;
+    Mock::given(body_string_contains("reader0@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcome = delivery_worker(&app).deliver_pending().await;
+
+    // Assert
+    assert!(outcome.is_err());
+    let queued = sqlx::query!(
+        r#"
+        SELECT s.email, t.claimed_by
+        FROM delivery_tasks t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        "#
+    )
+    .fetch_all(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].email, "reader0@example.com");
+    assert!(queued[0].claimed_by.is_none());
+}
+
+#[tokio::test]
+async fn the_deliveries_of_an_issue_are_reported_with_why_they_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscribers(&app, 3).await;
+    Mock::given(body_string_contains("reader0@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["deliveries"]["queued"], 3);
+    assert_eq!(body["deliveries"]["sent"], 0);
+    app.wait_for_deliveries().await;
+
+    // Act - Part 2 - Follow up
+    let report: serde_json::Value = reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/deliveries",
+            app.address,
+            body["newsletter_issue_id"].as_str().unwrap()
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report["queued"], 0);
+    assert_eq!(report["sent"], 2);
+    assert_eq!(report["skipped"], 0);
+    assert_eq!(report["failed"], 1);
+    let failure = &report["failures"][0];
+    assert_eq!(failure["email"], "reader0@example.com");
+    assert_eq!(failure["attempts"], 1);
+    assert!(failure["error"].as_str().unwrap().contains("500"));
+}
@@ -0,0 +1,240 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, newsletter_body, sent_emails, spawn_app,
+    spawn_app_with_configuration,
+};
+use std::time::Duration;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::FeatureFlagSettings;
+
+async fn get_delivery_status(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/delivery", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn set_paused(app: &TestApp, paused: bool) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(format!("{}/admin/delivery", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "paused": paused }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Wait for the emails queued while paused to be sent.
+async fn wait_for_empty_queue(app: &TestApp) {
+    for _ in 0..50 {
+        let queued = &get_delivery_status(app).await["queued"];
+        if queued["deliveries"] == 0 && queued["confirmation_emails"] == 0 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The queued emails were not sent");
+}
+
+#[tokio::test]
+async fn issues_published_while_paused_are_sent_on_resume() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    set_paused(&app, true).await;
+    let sent_before = sent_emails(&app).await.len();
+
+    // Act
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(sent_emails(&app).await.len(), sent_before);
+    assert_eq!(get_delivery_status(&app).await["queued"]["deliveries"], 1);
+
+    let status = set_paused(&app, false).await;
+    assert_eq!(status["paused"], false);
+    wait_for_empty_queue(&app).await;
+    assert_eq!(sent_emails(&app).await.len(), sent_before + 1);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_queued_while_paused() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    set_paused(&app, true).await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(sent_emails(&app).await.len(), 0);
+    assert_eq!(
+        get_delivery_status(&app).await["queued"]["confirmation_emails"],
+        1
+    );
+
+    set_paused(&app, false).await;
+    wait_for_empty_queue(&app).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn deliveries_can_start_paused_from_the_configuration() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.feature_flags.insert(
+            "pause_deliveries".into(),
+            FeatureFlagSettings {
+                enabled: true,
+                rollout_percentage: None,
+            },
+        );
+    })
+    .await;
+
+    // Act
+    let status = get_delivery_status(&app).await;
+
+    // Assert
+    assert_eq!(status["paused"], true);
+}
+
+#[tokio::test]
+async fn the_deliverability_dashboard_shows_a_banner_while_paused() {
+    // Arrange
+    let app = spawn_app().await;
+    let get_dashboard = || async {
+        reqwest::Client::new()
+            .get(format!("{}/admin/deliverability", app.address))
+            .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+            .send()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap()
+    };
+    assert!(get_dashboard().await.get("banner").is_none());
+
+    // Act
+    set_paused(&app, true).await;
+
+    // Assert
+    let banner = get_dashboard().await["banner"].as_str().unwrap().to_owned();
+    assert!(banner.starts_with("Deliveries are paused"));
+}
+
+#[tokio::test]
+async fn pausing_deliveries_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let get = reqwest::get(format!("{}/admin/delivery", app.address))
+        .await
+        .unwrap();
+    let set = reqwest::Client::new()
+        .post(format!("{}/admin/delivery", app.address))
+        .json(&serde_json::json!({ "paused": true }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(get.status().as_u16(), 401);
+    assert_eq!(set.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn magic_links_are_held_back_while_paused() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    set_paused(&app, true).await;
+    let sent_before = sent_emails(&app).await.len();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/login", app.address))
+        .form(&[("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(sent_emails(&app).await.len(), sent_before);
+}
+
+#[tokio::test]
+async fn senders_and_campaigns_wait_for_deliveries_to_resume() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    set_paused(&app, true).await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let sender = client
+        .post(format!("{}/admin/senders", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "address": "editor@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    let campaign = client
+        .post(format!("{}/admin/reengagement_campaigns", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(sender.status().as_u16(), 503);
+    assert_eq!(campaign.status().as_u16(), 503);
+    assert_eq!(sent_emails(&app).await.len(), 0);
+}
@@ -0,0 +1,45 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
+
+#[tokio::test]
+async fn fixtures_insert_the_requested_number_of_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/dev/fixtures/subscribers?count=25000",
+            app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["inserted"], 25000);
+    let confirmed = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscriptions WHERE status = 'confirmed'"#
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(confirmed.count, 25000);
+}
+
+#[tokio::test]
+async fn fixtures_are_not_exposed_unless_dev_routes_are_enabled() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.application.enable_dev_routes = false).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/dev/fixtures/subscribers?count=10", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,141 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app_with_configuration};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::DigestSettings;
+use zero2prod::digests::DigestScheduler;
+use zero2prod::email_client::SendEmailRequest;
+use zero2prod::throttling::DeliveryThrottle;
+
+async fn spawn_app_with_weekly_digest(send_if_empty: bool) -> (TestApp, DigestScheduler) {
+    let digest = DigestSettings {
+        name: "Weekly digest".into(),
+        schedule: "0 0 9 * * Mon".parse().unwrap(),
+        send_if_empty,
+    };
+    let app = spawn_app_with_configuration(|c| c.digests = vec![digest]).await;
+    let scheduler = DigestScheduler::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        Arc::new(app.configuration.email_client.client().unwrap()),
+        Arc::new(DeliveryThrottle::new(
+            &app.configuration.delivery_throttling,
+        )),
+    )
+    .unwrap();
+    (app, scheduler)
+}
+
+#[tokio::test]
+async fn digests_list_the_issues_published_since_the_last_run() {
+    // Arrange
+    let (app, scheduler) = spawn_app_with_weekly_digest(false).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let now = Utc::now();
+    scheduler.run_due_digests(now).await.unwrap();
+    app.post_newsletters(serde_json::json!({
+        "title": "Rust in production",
+        "content": {
+            "text": "Read https://example.com/rust",
+            "html": r#"<a href="https://example.com/rust">Read</a>"#,
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Act
+    let runs = scheduler
+        .run_due_digests(now + Duration::days(8))
+        .await
+        .unwrap();
+    let runs_again = scheduler
+        .run_due_digests(now + Duration::days(8))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(runs.len(), 1);
+    assert!(runs[0].newsletter_issue_id.is_some());
+    assert!(runs_again.is_empty());
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(email.subject.ends_with("Weekly digest"));
+    assert!(email.text.contains("Rust in production"));
+    assert!(email.html.contains("<h2>Rust in production</h2>"));
+}
+
+#[tokio::test]
+async fn digests_are_skipped_when_nothing_was_published() {
+    // Arrange
+    let (app, scheduler) = spawn_app_with_weekly_digest(false).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let now = Utc::now();
+    scheduler.run_due_digests(now).await.unwrap();
+
+    // Act
+    let runs = scheduler
+        .run_due_digests(now + Duration::days(8))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(runs.len(), 1);
+    assert!(runs[0].newsletter_issue_id.is_none());
+}
+
+#[tokio::test]
+async fn previous_digests_are_not_listed_in_the_next_one() {
+    // Arrange
+    let (app, scheduler) = spawn_app_with_weekly_digest(true).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let now = Utc::now();
+    scheduler.run_due_digests(now).await.unwrap();
+    scheduler
+        .run_due_digests(now + Duration::days(8))
+        .await
+        .unwrap();
+
+    // Act
+    let runs = scheduler
+        .run_due_digests(now + Duration::days(15))
+        .await
+        .unwrap();
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(runs[0].newsletter_issue_id.is_some());
+    assert!(!email.html.contains("<h2>"));
+}
@@ -0,0 +1,179 @@
+use crate::helpers::{TestApp, create_draft, spawn_app};
+use uuid::Uuid;
+
+async fn post_comment(app: &TestApp, draft_id: Uuid, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/newsletters/drafts/{}/comments",
+            app.address, draft_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_comments(app: &TestApp, draft_id: Uuid, query: &str) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/drafts/{}/comments{}",
+            app.address, draft_id, query
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn resolve_comment(app: &TestApp, draft_id: Uuid, comment_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/newsletters/drafts/{}/comments/{}/resolve",
+            app.address, draft_id, comment_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn comments_are_listed_with_their_anchor_and_author() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let response = post_comment(
+        &app,
+        draft_id,
+        serde_json::json!({
+            "body": "Maybe a catchier title?",
+            "anchor": {"field": "title", "start": 0, "end": 10},
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let comments = get_comments(&app, draft_id, "").await;
+    let comments = comments["comments"].as_array().unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0]["body"], "Maybe a catchier title?");
+    assert_eq!(comments[0]["author"], app.test_user.username.as_str());
+    assert_eq!(
+        comments[0]["anchor"],
+        serde_json::json!({"field": "title", "start": 0, "end": 10})
+    );
+}
+
+#[tokio::test]
+async fn resolved_comments_are_hidden_unless_requested() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+    let anchor = serde_json::json!({"field": "text", "start": 0, "end": 4});
+    let response: serde_json::Value = post_comment(
+        &app,
+        draft_id,
+        serde_json::json!({"body": "Typo here", "anchor": anchor}),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let comment_id = response["comment_id"].as_str().unwrap();
+    post_comment(
+        &app,
+        draft_id,
+        serde_json::json!({"body": "Still open", "anchor": anchor}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let first = resolve_comment(&app, draft_id, comment_id).await;
+    let second = resolve_comment(&app, draft_id, comment_id).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 409);
+    let open = get_comments(&app, draft_id, "").await;
+    let open = open["comments"].as_array().unwrap();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0]["body"], "Still open");
+    let all = get_comments(&app, draft_id, "?include_resolved=true").await;
+    let resolved = all["comments"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["comment_id"] == comment_id)
+        .unwrap();
+    assert_eq!(resolved["resolved_by"], app.test_user.username.as_str());
+}
+
+#[tokio::test]
+async fn comments_with_an_invalid_anchor_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"field": "title", "start": 0, "end": 1000}),
+            "range past the end of the field",
+        ),
+        (
+            serde_json::json!({"field": "html", "start": 5, "end": 2}),
+            "reversed range",
+        ),
+    ];
+
+    for (anchor, description) in test_cases {
+        // Act
+        let response = post_comment(
+            &app,
+            draft_id,
+            serde_json::json!({"body": "A comment", "anchor": anchor}),
+        )
+        .await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject a comment with a {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_drafts_and_comments_return_404() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+    let unknown_id = Uuid::new_v4();
+
+    // Act
+    let comment_on_unknown_draft = post_comment(
+        &app,
+        unknown_id,
+        serde_json::json!({
+            "body": "A comment",
+            "anchor": {"field": "title", "start": 0, "end": 1},
+        }),
+    )
+    .await;
+    let resolve_unknown_comment = resolve_comment(&app, draft_id, &unknown_id.to_string()).await;
+
+    // Assert
+    assert_eq!(comment_on_unknown_draft.status().as_u16(), 404);
+    assert_eq!(resolve_unknown_comment.status().as_u16(), 404);
+}
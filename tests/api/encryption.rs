@@ -0,0 +1,85 @@
+use crate::helpers::{create_confirmed_subscriber, newsletter_body, spawn_app_with_encryption};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+#[tokio::test]
+async fn subscriber_names_are_stored_encrypted() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+
+    // Act
+    create_confirmed_subscriber(&app).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert!(saved.name.starts_with("enc:v1:"));
+    assert!(!saved.name.contains("le guin"));
+}
+
+#[tokio::test]
+async fn drafts_are_stored_encrypted_and_read_back_in_plain_text() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+
+    // Act
+    app.post_newsletter_draft(newsletter_body())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT title, text_content, html_content FROM newsletter_drafts")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.title, "Newsletter title");
+    assert!(saved.text_content.starts_with("enc:v1:"));
+    assert!(saved.html_content.starts_with("enc:v1:"));
+    let drafts = app.get_newsletter_drafts().await;
+    assert_eq!(drafts["drafts"][0]["text"], "Newsletter body as plain text");
+    assert_eq!(
+        drafts["drafts"][0]["html"],
+        "<p>Newsletter body as HTML</p>"
+    );
+}
+
+#[tokio::test]
+async fn issues_are_stored_encrypted_and_delivered_in_plain_text() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let saved = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(saved.text_content.starts_with("enc:v1:"));
+    assert!(saved.html_content.starts_with("enc:v1:"));
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(email.text.contains("Newsletter body as plain text"));
+    assert!(email.html.contains("<p>Newsletter body as HTML</p>"));
+}
@@ -0,0 +1,235 @@
+use crate::helpers::{TestApp, spawn_app};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{HttpResponse, body::BoxBody, web};
+use futures_util::future::BoxFuture;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::{NewSubscriber, SubscriberValidator};
+use zero2prod::extensions::{
+    Confirmed, EventListener, Extensions, Middleware, Published, Subscribed,
+};
+use zero2prod::locale::{LocalizedError, Message};
+use zero2prod::startup::get_connection_pool;
+use zero2prod::{Server, ServerHandle};
+
+#[derive(Clone, Default)]
+struct RecordingListener(Arc<Mutex<Vec<String>>>);
+
+impl RecordingListener {
+    /// Events are handed over in the background: wait for `count` of them.
+    async fn wait_for(&self, count: usize) -> Vec<String> {
+        for _ in 0..100 {
+            let events = self.0.lock().unwrap().clone();
+            if events.len() >= count {
+                return events;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!(
+            "Expected {} events, got {:?}",
+            count,
+            self.0.lock().unwrap()
+        );
+    }
+
+    fn record(&self, event: String) -> BoxFuture<'_, ()> {
+        self.0.lock().unwrap().push(event);
+        Box::pin(async {})
+    }
+}
+
+impl EventListener for RecordingListener {
+    fn on_subscribe<'a>(&'a self, event: &'a Subscribed) -> BoxFuture<'a, ()> {
+        self.record(format!("subscribed {}", event.email))
+    }
+
+    fn on_confirm<'a>(&'a self, event: &'a Confirmed) -> BoxFuture<'a, ()> {
+        self.record(format!("confirmed {}", event.subscriber_id))
+    }
+
+    fn on_publish<'a>(&'a self, event: &'a Published) -> BoxFuture<'a, ()> {
+        self.record(format!("published {}", event.title))
+    }
+}
+
+/// Blocks `/blocked` and tags every other response.
+struct TaggingMiddleware;
+
+impl Middleware for TaggingMiddleware {
+    fn on_request(&self, request: &ServiceRequest) -> Option<HttpResponse> {
+        (request.path() == "/blocked").then(|| HttpResponse::Forbidden().finish())
+    }
+
+    fn on_response(&self, response: &mut ServiceResponse<BoxBody>) {
+        response.headers_mut().insert(
+            HeaderName::from_static("x-extension"),
+            HeaderValue::from_static("tagged"),
+        );
+    }
+}
+
+/// Turns away role accounts.
+struct NoRoleAccounts;
+
+impl SubscriberValidator for NoRoleAccounts {
+    fn validate(&self, subscriber: &NewSubscriber) -> Result<(), LocalizedError> {
+        let email = subscriber.email.as_ref();
+        if email.starts_with("info@") {
+            return Err(LocalizedError::new(Message::NotAccepted, email));
+        }
+        Ok(())
+    }
+}
+
+async fn spawn_server(app: &TestApp, extensions: Extensions) -> (SocketAddr, ServerHandle) {
+    let server = Server::builder(app.configuration.clone())
+        .pg_pool(get_connection_pool(&app.configuration.database))
+        .listener(TcpListener::bind("127.0.0.1:0").unwrap())
+        .extensions(extensions)
+        .build()
+        .await
+        .unwrap();
+    let address = server.local_addr();
+    let handle = server.handle();
+    tokio::spawn(server.run());
+    (address, handle)
+}
+
+#[tokio::test]
+async fn listeners_are_told_about_subscriptions_and_confirmations() {
+    // Arrange
+    let app = spawn_app().await;
+    let listener = RecordingListener::default();
+    let (address, handle) =
+        spawn_server(&app, Extensions::default().listener(listener.clone())).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    reqwest::Client::new()
+        .post(format!("http://{}/subscriptions", address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let mut confirmation_link = app.get_confirmation_links(email_request).html;
+    confirmation_link.set_port(Some(address.port())).unwrap();
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let events = listener.wait_for(2).await;
+    assert_eq!(events[0], "subscribed ursula_le_guin@gmail.com");
+    assert!(events[1].starts_with("confirmed "));
+    handle.stop();
+}
+
+#[tokio::test]
+async fn listeners_are_told_about_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let listener = RecordingListener::default();
+    let (address, handle) =
+        spawn_server(&app, Extensions::default().listener(listener.clone())).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/newsletters", address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(listener.wait_for(1).await, ["published Newsletter title"]);
+    handle.stop();
+}
+
+#[tokio::test]
+async fn extension_routes_and_middleware_are_served() {
+    // Arrange
+    let app = spawn_app().await;
+    let extensions = Extensions::default()
+        .service(|cfg| {
+            cfg.route(
+                "/extension",
+                web::get().to(|| async { HttpResponse::Ok().body("from the extension") }),
+            );
+        })
+        .middleware(TaggingMiddleware);
+    let (address, handle) = spawn_server(&app, extensions).await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let extension = client
+        .get(format!("http://{}/extension", address))
+        .send()
+        .await
+        .unwrap();
+    let health_check = client
+        .get(format!("http://{}/health_check", address))
+        .send()
+        .await
+        .unwrap();
+    let blocked = client
+        .get(format!("http://{}/blocked", address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(extension.status().as_u16(), 200);
+    assert_eq!(extension.headers()["x-extension"], "tagged");
+    assert_eq!(extension.text().await.unwrap(), "from the extension");
+    assert!(health_check.status().is_success());
+    assert_eq!(health_check.headers()["x-extension"], "tagged");
+    assert_eq!(blocked.status().as_u16(), 403);
+    handle.stop();
+}
+
+#[tokio::test]
+async fn extension_validators_check_new_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let (address, handle) =
+        spawn_server(&app, Extensions::default().validator(NoRoleAccounts)).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/subscriptions", address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=info%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "info@gmail.com is not accepted by this newsletter"
+    );
+    handle.stop();
+}
@@ -0,0 +1,183 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, deliver_issue, spawn_app};
+use zero2prod::email_client::SendEmailRequest;
+
+async fn get_flags(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/feature_flags", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn set_flag(app: &TestApp, name: &str, value: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(format!(
+            "{}/admin/feature_flags/{}",
+            app.admin_address, name
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&value)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn reset_flag(app: &TestApp, name: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .delete(format!(
+            "{}/admin/feature_flags/{}",
+            app.admin_address, name
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Publish an issue to a single confirmed subscriber and return the HTML
+/// body they received.
+async fn delivered_html(app: &TestApp) -> String {
+    create_confirmed_subscriber(app).await;
+    deliver_issue(app, "Newsletter title", "message-1").await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    email.html.into_owned()
+}
+
+#[tokio::test]
+async fn configured_flags_are_listed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let flags = get_flags(&app).await;
+
+    // Assert
+    assert_eq!(
+        flags["flags"],
+        serde_json::json!([{
+            "name": "open_tracking",
+            "enabled": true,
+            "rollout_percentage": null,
+            "source": "configuration",
+        }])
+    );
+}
+
+#[tokio::test]
+async fn open_tracking_can_be_turned_off_at_runtime() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = set_flag(
+        &app,
+        "open_tracking",
+        serde_json::json!({ "enabled": false }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_flags(&app).await["flags"][0]["source"], "database");
+    let html = delivered_html(&app).await;
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+    assert!(!html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn open_tracking_is_on_for_no_one_in_an_empty_rollout() {
+    // Arrange
+    let app = spawn_app().await;
+    set_flag(
+        &app,
+        "open_tracking",
+        serde_json::json!({ "enabled": true, "rollout_percentage": 0 }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let html = delivered_html(&app).await;
+
+    // Assert
+    assert!(!html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn reset_flags_go_back_to_their_configured_value() {
+    // Arrange
+    let app = spawn_app().await;
+    set_flag(
+        &app,
+        "open_tracking",
+        serde_json::json!({ "enabled": false }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let response = reset_flag(&app, "open_tracking").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let flags = get_flags(&app).await;
+    assert_eq!(flags["flags"][0]["enabled"], true);
+    assert_eq!(flags["flags"][0]["source"], "configuration");
+    assert_eq!(
+        reset_flag(&app, "open_tracking").await.status().as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn rollouts_over_100_percent_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = set_flag(
+        &app,
+        "open_tracking",
+        serde_json::json!({ "enabled": true, "rollout_percentage": 101 }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn feature_flags_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list = reqwest::get(format!("{}/admin/feature_flags", app.address))
+        .await
+        .unwrap();
+    let set = reqwest::Client::new()
+        .put(format!("{}/admin/feature_flags/open_tracking", app.address))
+        .json(&serde_json::json!({ "enabled": false }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(list.status().as_u16(), 401);
+    assert_eq!(set.status().as_u16(), 401);
+}
@@ -0,0 +1,218 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app_with_configuration};
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::FeedSettings;
+use zero2prod::email_client::SendEmailRequest;
+use zero2prod::feed_watcher::FeedWatcher;
+use zero2prod::throttling::DeliveryThrottle;
+
+fn rss_feed(items: &[(&str, &str)]) -> String {
+    let items: String = items
+        .iter()
+        .map(|(guid, title)| {
+            format!(
+                "<item><guid>{guid}</guid><title>{title}</title>\
+                 <link>https://blog.example.com/{guid}</link>\
+                 <description>&lt;p&gt;All about {title}&lt;/p&gt;</description></item>"
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Blog</title>{items}</channel></rss>"#
+    )
+}
+
+async fn serve_feed(feed_server: &MockServer, feed: String) {
+    feed_server.reset().await;
+    Mock::given(path("/feed.xml"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(feed, "application/rss+xml"))
+        .mount(feed_server)
+        .await;
+}
+
+async fn spawn_app_watching(
+    feed_server: &MockServer,
+    auto_publish: bool,
+) -> (TestApp, FeedWatcher) {
+    let feed = FeedSettings {
+        url: format!("{}/feed.xml", feed_server.uri()),
+        // Polls are triggered by the test itself.
+        poll_interval: Duration::from_secs(3600),
+        timeout: Duration::from_secs(2),
+        auto_publish,
+    };
+    let app = spawn_app_with_configuration(|c| c.feed = Some(feed)).await;
+    let watcher = FeedWatcher::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        Arc::new(app.configuration.email_client.client().unwrap()),
+        Arc::new(DeliveryThrottle::new(
+            &app.configuration.delivery_throttling,
+        )),
+    )
+    .unwrap();
+    (app, watcher)
+}
+
+#[tokio::test]
+async fn entries_present_when_the_watcher_starts_are_not_turned_into_drafts() {
+    // Arrange
+    let feed_server = MockServer::start().await;
+    serve_feed(&feed_server, rss_feed(&[("first-post", "First post")])).await;
+    let (app, watcher) = spawn_app_watching(&feed_server, false).await;
+
+    // Act
+    let drafts = watcher.poll_once().await.unwrap();
+
+    // Assert
+    assert!(drafts.is_empty());
+    let drafts = app.get_newsletter_drafts().await;
+    assert!(drafts["drafts"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn new_feed_entries_become_drafts() {
+    // Arrange
+    let feed_server = MockServer::start().await;
+    serve_feed(&feed_server, rss_feed(&[("first-post", "First post")])).await;
+    let (app, watcher) = spawn_app_watching(&feed_server, false).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    watcher.poll_once().await.unwrap();
+
+    // Act
+    serve_feed(
+        &feed_server,
+        rss_feed(&[("second-post", "Second post"), ("first-post", "First post")]),
+    )
+    .await;
+    let created = watcher.poll_once().await.unwrap();
+    let created_again = watcher.poll_once().await.unwrap();
+
+    // Assert
+    assert_eq!(created.len(), 1);
+    assert!(created_again.is_empty());
+    let drafts = app.get_newsletter_drafts().await;
+    let drafts = drafts["drafts"].as_array().unwrap();
+    assert_eq!(drafts.len(), 1);
+    assert_eq!(drafts[0]["title"], "Second post");
+    let html = drafts[0]["html"].as_str().unwrap();
+    assert!(html.contains("<p>All about Second post</p>"));
+    assert!(html.contains(r#"href="https://blog.example.com/second-post""#));
+    let text = drafts[0]["text"].as_str().unwrap();
+    assert!(text.contains("All about Second post"));
+    assert!(!text.contains("<p>"));
+}
+
+#[tokio::test]
+async fn new_feed_entries_are_published_when_auto_publish_is_enabled() {
+    // Arrange
+    let feed_server = MockServer::start().await;
+    serve_feed(
+        &feed_server,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+        <feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title><id>urn:blog</id>
+        <updated>2025-06-27T12:00:00Z</updated></feed>"#
+            .into(),
+    )
+    .await;
+    let (app, watcher) = spawn_app_watching(&feed_server, true).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    watcher.poll_once().await.unwrap();
+
+    // Act
+    serve_feed(
+        &feed_server,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+        <feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title><id>urn:blog</id>
+        <updated>2025-06-27T12:00:00Z</updated>
+        <entry><id>urn:blog:atom-post</id><title>Atom post</title>
+        <link rel="alternate" href="https://blog.example.com/atom-post"/>
+        <updated>2025-06-27T12:00:00Z</updated><summary>Short summary</summary></entry>
+        </feed>"#
+            .into(),
+    )
+    .await;
+    watcher.poll_once().await.unwrap();
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(email.subject.ends_with("Atom post"));
+    assert!(email.text.contains("Short summary"));
+    let drafts = app.get_newsletter_drafts().await;
+    assert!(drafts["drafts"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn feed_entries_are_sanitized() {
+    // Arrange
+    let feed_server = MockServer::start().await;
+    serve_feed(&feed_server, rss_feed(&[])).await;
+    let (app, watcher) = spawn_app_watching(&feed_server, false).await;
+    watcher.poll_once().await.unwrap();
+
+    // Act
+    serve_feed(
+        &feed_server,
+        r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Blog</title>
+        <item><guid>evil-post</guid><title>Evil post</title>
+        <link>javascript:alert(1)</link>
+        <description>&lt;p onclick="steal()"&gt;Hello&lt;script&gt;steal()&lt;/script&gt; &lt;a href="javascript:steal()"&gt;there&lt;/a&gt;&lt;/p&gt;</description>
+        </item></channel></rss>"#
+            .into(),
+    )
+    .await;
+    watcher.poll_once().await.unwrap();
+
+    // Assert
+    let drafts = app.get_newsletter_drafts().await;
+    let html = drafts["drafts"][0]["html"].as_str().unwrap();
+    assert!(html.contains("<p>Hello <a>there</a></p>"));
+    assert!(!html.contains("steal()"));
+    assert!(!html.contains("javascript:"));
+}
+
+#[tokio::test]
+async fn a_failed_auto_publish_does_not_stop_the_poll() {
+    // Arrange
+    let feed_server = MockServer::start().await;
+    serve_feed(&feed_server, rss_feed(&[])).await;
+    let (app, watcher) = spawn_app_watching(&feed_server, true).await;
+    watcher.poll_once().await.unwrap();
+    sqlx::query!("ALTER TABLE newsletter_issues RENAME TO newsletter_issues_gone")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    serve_feed(
+        &feed_server,
+        rss_feed(&[("second-post", "Second post"), ("first-post", "First post")]),
+    )
+    .await;
+    let created = watcher.poll_once().await.unwrap();
+
+    // Assert
+    assert_eq!(created.len(), 2);
+    let drafts = app.get_newsletter_drafts().await;
+    assert_eq!(drafts["drafts"].as_array().unwrap().len(), 2);
+}
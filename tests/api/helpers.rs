@@ -1,10 +1,20 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use sqlx::migrate::Migrator;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::DatabaseSettings;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::blob_store::BlobStore;
+use zero2prod::configuration::{
+    DatabaseSettings, EncryptionSettings, Settings, StorageSettings, TelemetrySettings,
+};
+#[cfg(feature = "dev")]
+use zero2prod::dev::EphemeralPostgres;
 use zero2prod::email_client::SendEmailRequest;
 use zero2prod::get_configuration;
 use zero2prod::startup::{Application, get_connection_pool};
@@ -13,9 +23,18 @@ use zero2prod::telemetry::{get_subscriber, init_subscriber};
 pub struct TestApp {
     pub connection_pool: PgPool,
     pub address: String,
+    /// Where the admin routes are served, `address` unless the application
+    /// has a separate admin listener.
+    pub admin_address: String,
     pub email_server: MockServer,
     pub port: u16,
+    /// Port of the listener redirecting plain HTTP requests to HTTPS.
+    pub redirect_port: Option<u16>,
     pub test_user: TestUser,
+    pub configuration: Settings,
+    /// Stops the application as SIGTERM does, when sent or dropped.
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+    server: Option<tokio::task::JoinHandle<Result<(), std::io::Error>>>,
 }
 
 pub struct ConfirmationLinks {
@@ -24,6 +43,23 @@ pub struct ConfirmationLinks {
 }
 
 impl TestApp {
+    /// Shut the application down as on SIGTERM, returning once it stopped.
+    pub async fn shut_down(&mut self) -> Result<(), std::io::Error> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        self.server
+            .take()
+            .expect("The application was already shut down")
+            .await
+            .expect("The application panicked")
+    }
+
+    /// The storage of the application, in a directory of its own.
+    pub fn blob_store(&self) -> Arc<dyn BlobStore> {
+        self.configuration.storage.store()
+    }
+
     pub async fn post_subscriptions(&self, body: &'static str) -> reqwest::Response {
         let client = reqwest::Client::new();
         client
@@ -34,6 +70,20 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn post_email_events(
+        &self,
+        token: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/email_events", &self.address))
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
             .post(format!("{}/newsletters", &self.address))
@@ -47,6 +97,66 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// Wait for the delivery worker to be done with the queued deliveries,
+    /// as issues are emailed in the background once published: either every
+    /// delivery was attempted, or a failure made the worker stop early.
+    pub async fn wait_for_deliveries(&self) {
+        for _ in 0..200 {
+            let r = sqlx::query!(
+                r#"
+                SELECT
+                    EXISTS (SELECT 1 FROM delivery_tasks WHERE lease_expires_at > now()) AS "leased!",
+                    EXISTS (SELECT 1 FROM delivery_tasks WHERE attempts = 0) AS "unattempted!",
+                    EXISTS (SELECT 1 FROM delivery_tasks WHERE attempts > 0) AS "failed!"
+                "#
+            )
+            .fetch_one(&self.connection_pool)
+            .await
+            .unwrap();
+            if !r.leased && (!r.unattempted || r.failed) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("The queued deliveries were not attempted in time");
+    }
+
+    pub async fn post_newsletter_draft(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters/drafts", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_drafts(&self) -> serde_json::Value {
+        reqwest::Client::new()
+            .get(format!("{}/newsletters/drafts", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn publish_newsletter_draft(&self, newsletter_draft_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/drafts/{}/publish",
+                &self.address, newsletter_draft_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, request: &wiremock::Request) -> ConfirmationLinks {
         let body: SendEmailRequest =
             serde_json::from_slice(&request.body).expect("Invalid email request body");
@@ -74,28 +184,46 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     let subscriber_name = "test".to_string();
 
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let (subscriber, _) = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            &TelemetrySettings::default(),
+        )
+        .unwrap();
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let (subscriber, _) = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            &TelemetrySettings::default(),
+        )
+        .unwrap();
         init_subscriber(subscriber);
     }
 });
 
-async fn spawn_app_impl(base_url_override: Option<String>) -> TestApp {
+async fn spawn_app_impl(customise: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
 
     let configuration = {
         let mut c = get_configuration().expect("Failed to read configuration.");
+        #[cfg(feature = "dev")]
+        if let Some(postgres) = test_database_container(&c.database).await {
+            postgres.configure(&mut c.database);
+        }
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
-
-        if let Some(base_url) = base_url_override {
-            c.application.base_url = base_url;
-        }
+        // A failing provider fails at once, tests opt into retries.
+        c.email_client.retry.max_attempts = 1;
+        c.storage = StorageSettings::Local {
+            path: std::env::temp_dir().join(format!("storage-{}", Uuid::new_v4())),
+        };
+        customise(&mut c);
         c
     };
 
@@ -105,53 +233,220 @@ async fn spawn_app_impl(base_url_override: Option<String>) -> TestApp {
         .expect("Failed to build application.");
 
     let application_port = application.port();
-    let address = format!("http://127.0.0.1:{}", application_port);
-    tokio::spawn(application.run_until_stopped());
+    let scheme = match configuration.application.tls {
+        Some(_) => "https",
+        None => "http",
+    };
+    let address = format!("{}://127.0.0.1:{}", scheme, application_port);
+    let admin_address = match application.admin_port() {
+        Some(admin_port) => format!("http://127.0.0.1:{}", admin_port),
+        None => address.clone(),
+    };
+    let redirect_port = application.redirect_port();
+    let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel();
+    let server = tokio::spawn(application.run_until(async {
+        let _ = shutdown_signal.await;
+    }));
 
     let test_app = TestApp {
         address,
+        admin_address,
         email_server,
         connection_pool: get_connection_pool(&configuration.database),
         port: application_port,
+        redirect_port,
         test_user: TestUser::generate(),
+        configuration,
+        shutdown: Some(shutdown),
+        server: Some(server),
     };
     test_app.test_user.store(&test_app.connection_pool).await;
     test_app
 }
 
+/// A client keeping the session cookie, as a browser would.
+pub fn browser() -> reqwest::Client {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+pub async fn log_in(app: &TestApp, client: &reqwest::Client, password: &str) -> reqwest::Response {
+    client
+        .post(format!("{}/login", app.admin_address))
+        .form(&[
+            ("username", app.test_user.username.as_str()),
+            ("password", password),
+        ])
+        .send()
+        .await
+        .unwrap()
+}
+
+/// A request to an admin endpoint, authenticated as the test user.
+pub fn admin_request(
+    app: &TestApp,
+    method: reqwest::Method,
+    path: &str,
+) -> reqwest::RequestBuilder {
+    reqwest::Client::new()
+        .request(method, format!("{}{}", app.address, path))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+}
+
 pub async fn spawn_app() -> TestApp {
-    spawn_app_impl(None).await
+    spawn_app_impl(|_| {}).await
 }
 
 pub async fn spawn_app_with_base_url(base_url: String) -> TestApp {
-    spawn_app_impl(Some(base_url)).await
+    spawn_app_impl(|c| c.application.base_url = base_url).await
+}
+
+/// Spawn the application after tweaking its configuration, which already
+/// points at the test database and `email_server`.
+pub async fn spawn_app_with_configuration(customise: impl FnOnce(&mut Settings)) -> TestApp {
+    spawn_app_impl(customise).await
+}
+
+/// Spawn the application with field encryption turned on.
+pub async fn spawn_app_with_encryption() -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.encryption = Some(EncryptionSettings {
+            key: "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY="
+                .to_string()
+                .into(),
+        });
+    })
+    .await
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
-    //Create Database
+    let template = migrated_template_database(config).await;
+
+    // Cloning the migrated template is much faster than running every
+    // migration for each test.
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("Failed to connect to Postgres");
     connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .execute(
+            format!(
+                r#"CREATE DATABASE "{}" TEMPLATE "{}";"#,
+                config.database_name, template
+            )
+            .as_str(),
+        )
         .await
         .expect("Failed to create database.");
 
-    // create pgpool and migration
-    let connection_pool = PgPool::connect_with(config.with_db())
+    PgPool::connect_with(config.with_db())
+        .await
+        .expect("Failed to connect to Postgres")
+}
+
+#[cfg(feature = "dev")]
+static TEST_DATABASE_CONTAINER: tokio::sync::OnceCell<Option<EphemeralPostgres>> =
+    tokio::sync::OnceCell::const_new();
+
+/// With `TEST_DATABASE=container` (and the `dev` feature), tests run against
+/// a Postgres container instead of the database configured in `base.yaml`.
+/// The container is kept around and reused by later runs.
+#[cfg(feature = "dev")]
+async fn test_database_container(config: &DatabaseSettings) -> Option<&'static EphemeralPostgres> {
+    TEST_DATABASE_CONTAINER
+        .get_or_init(|| async {
+            if std::env::var("TEST_DATABASE").as_deref() != Ok("container") {
+                return None;
+            }
+            let postgres = EphemeralPostgres::reuse_or_start(config, "zero2prod-test-postgres")
+                .await
+                .expect("Failed to start the test Postgres container.");
+            Some(postgres)
+        })
+        .await
+        .as_ref()
+}
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+static TEMPLATE_DATABASE: tokio::sync::OnceCell<String> = tokio::sync::OnceCell::const_new();
+
+/// Name of a database with every migration applied, created on first use.
+///
+/// The name is derived from the migrations' checksums: test runs share the
+/// template until a migration is added or changed.
+pub async fn migrated_template_database(config: &DatabaseSettings) -> &'static str {
+    TEMPLATE_DATABASE
+        .get_or_init(|| create_template_database(config))
+        .await
+}
+
+async fn create_template_database(config: &DatabaseSettings) -> String {
+    // SHA-256 rather than `DefaultHasher`, whose output may change between
+    // Rust releases: the name has to be stable across test runs.
+    let mut hasher = Sha256::new();
+    for migration in MIGRATOR.iter() {
+        hasher.update(migration.version.to_be_bytes());
+        hasher.update(&migration.checksum);
+    }
+    let template = format!(
+        "newsletter_template_{}",
+        hex::encode(&hasher.finalize()[..8])
+    );
+
+    let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("Failed to connect to Postgres");
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM pg_database WHERE datname = $1)",
+    )
+    .bind(&template)
+    .fetch_one(&mut connection)
+    .await
+    .expect("Failed to look up the template database.");
+    if exists {
+        return template;
+    }
 
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
+    // Migrate under a temporary name and rename once done, so that a
+    // concurrent test run never clones a half-migrated template.
+    let staging = format!("{}_{}", template, Uuid::new_v4().simple());
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, staging).as_str())
+        .await
+        .expect("Failed to create the template database.");
+    let staging_config = DatabaseSettings {
+        database_name: staging.clone(),
+        ..config.clone()
+    };
+    let mut staging_connection = PgConnection::connect_with(&staging_config.with_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    MIGRATOR
+        .run(&mut staging_connection)
         .await
         .expect("Failed to run database migrations.");
+    staging_connection
+        .close()
+        .await
+        .expect("Failed to close the template database connection.");
 
-    connection_pool
+    let renamed = connection
+        .execute(format!(r#"ALTER DATABASE "{}" RENAME TO "{}";"#, staging, template).as_str())
+        .await;
+    if renamed.is_err() {
+        // Another test run got there first.
+        connection
+            .execute(format!(r#"DROP DATABASE "{}";"#, staging).as_str())
+            .await
+            .expect("Failed to drop the staging template database.");
+    }
+    template
 }
 
 pub struct TestUser {
-    user_id: Uuid,
+    pub user_id: Uuid,
     pub username: String,
     pub password: String,
 }
@@ -188,3 +483,182 @@ impl TestUser {
         .expect("Failed to store test users.");
     }
 }
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    // We can then reuse the same helper and just add
+    // an extra step to actually call the confirmation link!
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Store a draft and return its id.
+pub async fn create_draft(app: &TestApp) -> Uuid {
+    let response: serde_json::Value = app
+        .post_newsletter_draft(newsletter_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    response["newsletter_draft_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+/// A valid body for [`TestApp::post_newsletters`] and
+/// [`TestApp::post_newsletter_draft`].
+pub fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+/// Publish an issue titled `title` and return its id. It is emailed in the
+/// background, see [`TestApp::wait_for_deliveries`].
+pub async fn publish_issue(app: &TestApp, title: &str) -> Uuid {
+    let mut body = newsletter_body();
+    body["title"] = title.into();
+    let response: serde_json::Value = app
+        .post_newsletters(body)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    response["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+/// Publish an issue titled `title` and wait for it to be emailed to the one
+/// confirmed subscriber, the provider answering with `message_id`.
+pub async fn deliver_issue(app: &TestApp, title: &str, message_id: &str) {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true,
+            "message_ids": [message_id],
+        })))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_issue(app, title).await;
+    app.wait_for_deliveries().await;
+}
+
+/// The emails received by the email server so far.
+pub async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| request.body_json().unwrap())
+        .collect()
+}
+
+/// Store a subscriber directly, skipping the confirmation flow.
+pub async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    status: &str,
+    subscribed_at: DateTime<Utc>,
+    tags: &[&str],
+) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'a reader', $3, $4)
+        "#,
+        subscriber_id,
+        email,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    for tag in tags {
+        sqlx::query!(
+            "INSERT INTO subscriber_tags (subscriber_id, tag) VALUES ($1, $2)",
+            subscriber_id,
+            tag,
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+    subscriber_id
+}
+
+/// The id of the only subscriber.
+pub async fn get_subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+/// Connect to a new database without any migration applied.
+pub async fn empty_database(config: &DatabaseSettings) -> PgConnection {
+    let config = DatabaseSettings {
+        database_name: Uuid::new_v4().to_string(),
+        ..config.clone()
+    };
+    PgConnection::connect_with(&config.without_db())
+        .await
+        .unwrap()
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .unwrap();
+    PgConnection::connect_with(&config.with_db()).await.unwrap()
+}
+
+/// Connect to a new database with every migration applied.
+pub async fn migrated_database(config: &DatabaseSettings) -> PgConnection {
+    let config = DatabaseSettings {
+        database_name: Uuid::new_v4().to_string(),
+        ..config.clone()
+    };
+    configure_database(&config).await;
+    PgConnection::connect_with(&config.with_db()).await.unwrap()
+}
@@ -0,0 +1,214 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with_configuration};
+use uuid::Uuid;
+use zero2prod::imports::{ImportWorker, import_file_key};
+
+async fn upload(app: &TestApp, file: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/imports", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Content-Type", "text/csv")
+        .body(file.to_owned())
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_import(app: &TestApp, import_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/imports/{}", app.address, import_id))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Poll the import until the background job is done with it.
+async fn wait_for_import(app: &TestApp, import_id: &str) -> serde_json::Value {
+    for _ in 0..200 {
+        let import: serde_json::Value = get_import(app, import_id).await.json().await.unwrap();
+        if import["status"] == "completed" {
+            return import;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("The import was not completed in time");
+}
+
+#[tokio::test]
+async fn subscribers_are_imported_in_the_background_and_invalid_rows_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia@gmail.com', 'Octavia', now(), 'pending_confirmation')
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    let file = "email,name,tags\n\
+        ursula@gmail.com,\"Ursula Le Guin\",rust;go\n\
+        not-an-email,NK,\n\
+        octavia@gmail.com,Octavia,rust\n";
+
+    // Act
+    let response = upload(&app, file).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_rows"], 3);
+    assert_eq!(body["rejected"], 1);
+    let import = wait_for_import(&app, body["import_id"].as_str().unwrap()).await;
+    assert_eq!(import["processed_rows"], 3);
+    assert_eq!(import["imported"], 1);
+    assert_eq!(import["skipped"], 1);
+    assert_eq!(import["errors"][0]["line"], 3);
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.status, c.signup_channel,
+            ARRAY(SELECT tag FROM subscriber_tags WHERE subscriber_id = s.id ORDER BY tag)
+                AS "tags!"
+        FROM subscriptions s
+        JOIN consent_records c ON c.subscriber_id = s.id
+        WHERE email = 'ursula@gmail.com'
+        "#
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(saved.signup_channel.as_deref(), Some("import"));
+    assert_eq!(saved.tags, ["go", "rust"]);
+    let existing =
+        sqlx::query!("SELECT status FROM subscriptions WHERE email = 'octavia@gmail.com'")
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    assert_eq!(existing.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn imported_addresses_must_be_at_the_allowed_domains() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.subscriber_validation.allowed_email_domains = vec!["corp.com".into()];
+    })
+    .await;
+    let file = "email,name\n\
+        ursula@corp.com,Ursula\n\
+        octavia@gmail.com,Octavia\n";
+
+    // Act
+    let response = upload(&app, file).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["rejected"], 1);
+    let import = wait_for_import(&app, body["import_id"].as_str().unwrap()).await;
+    assert_eq!(import["imported"], 1);
+    assert_eq!(import["errors"][0]["line"], 3);
+    assert_eq!(
+        import["errors"][0]["error"],
+        "Only @corp.com addresses can subscribe to this newsletter"
+    );
+    let emails = sqlx::query_scalar!("SELECT email FROM subscriptions")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(emails, ["ursula@corp.com"]);
+}
+
+#[tokio::test]
+async fn unreadable_files_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("", "an empty file"),
+        ("name,address\nUrsula,ursula@gmail.com\n", "no email column"),
+        (
+            "email,name\nursula@gmail.com,\"Ursula\n",
+            "an unclosed quote",
+        ),
+    ];
+
+    for (file, description) in test_cases {
+        // Act
+        let response = upload(&app, file).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with a 400 when the file had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_progress_of_an_unknown_import_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_import(&app, &Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_uploaded_file_of_an_import_is_kept() {
+    // Arrange
+    let app = spawn_app().await;
+    let file = "email,name\nursula@gmail.com,Ursula\n";
+    let body: serde_json::Value = upload(&app, file).await.json().await.unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/imports/{}/file",
+            app.address,
+            body["import_id"].as_str().unwrap()
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), file);
+}
+
+#[tokio::test]
+async fn the_uploaded_file_is_deleted_a_while_after_the_import() {
+    // Arrange
+    let app = spawn_app().await;
+    let file = "email,name\nursula@gmail.com,Ursula\n";
+    let body: serde_json::Value = upload(&app, file).await.json().await.unwrap();
+    let import_id = body["import_id"].as_str().unwrap();
+    wait_for_import(&app, import_id).await;
+    let worker = ImportWorker::build(app.connection_pool.clone(), app.blob_store());
+    let key = import_file_key(import_id.parse().unwrap());
+
+    // Act
+    worker.delete_expired_files().await.unwrap();
+    let kept = app.blob_store().exists(&key).await.unwrap();
+    sqlx::query!("UPDATE subscriber_imports SET completed_at = now() - interval '31 days'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    worker.delete_expired_files().await.unwrap();
+
+    // Assert
+    assert!(kept);
+    assert!(!app.blob_store().exists(&key).await.unwrap());
+    let import: serde_json::Value = get_import(&app, import_id).await.json().await.unwrap();
+    assert_eq!(import["imported"], 1);
+}
@@ -0,0 +1,115 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, sent_emails, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn issue_with_copies(internal_copies: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Read https://example.com/post",
+            "html": r#"<p>Read <a href="https://example.com/post">the post</a></p>"#,
+        },
+        "internal_copies": internal_copies,
+    })
+}
+
+/// The emails received by the email server, by recipient.
+async fn sent_emails_by_recipient(app: &TestApp) -> Vec<(String, serde_json::Value)> {
+    sent_emails(app)
+        .await
+        .into_iter()
+        .map(|body| (body["to"][0]["email"].as_str().unwrap().to_owned(), body))
+        .collect()
+}
+
+#[tokio::test]
+async fn internal_copies_are_sent_untracked_and_left_out_of_the_stats() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(issue_with_copies(&[
+            "marketing@example.com",
+            "Archive@Example.com",
+        ]))
+        .await;
+    app.wait_for_deliveries().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let emails = sent_emails_by_recipient(&app).await;
+    let (_, copy) = emails
+        .iter()
+        .find(|(to, _)| to == "archive@example.com")
+        .unwrap();
+    assert_eq!(copy["subject"], "[Internal copy] Newsletter title");
+    let html = copy["html"].as_str().unwrap();
+    assert!(html.contains(r#"<a href="https://example.com/post">"#));
+    assert!(!html.contains("/t/o/"));
+    assert!(!html.contains("Unsubscribe"));
+    assert!(emails.iter().any(|(to, _)| to == "marketing@example.com"));
+    let outcomes = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM delivery_outcomes"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(outcomes.count, 1);
+}
+
+#[tokio::test]
+async fn internal_copies_are_sent_once_even_without_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response: serde_json::Value = app
+        .post_newsletter_draft(issue_with_copies(&["archive@example.com"]))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let draft_id = response["newsletter_draft_id"].as_str().unwrap();
+    let drafts = app.get_newsletter_drafts().await;
+    assert_eq!(
+        drafts["drafts"][0]["internal_copies"],
+        serde_json::json!(["archive@example.com"])
+    );
+
+    // Act
+    let response = app.publish_newsletter_draft(draft_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let emails = sent_emails_by_recipient(&app).await;
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].0, "archive@example.com");
+}
+
+#[tokio::test]
+async fn invalid_internal_copies_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let issue = app
+        .post_newsletters(issue_with_copies(&["not an address"]))
+        .await;
+    let draft = app
+        .post_newsletter_draft(issue_with_copies(&["not an address"]))
+        .await;
+
+    // Assert
+    assert_eq!(issue.status().as_u16(), 400);
+    assert_eq!(draft.status().as_u16(), 400);
+}
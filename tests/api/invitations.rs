@@ -0,0 +1,213 @@
+use crate::helpers::{TestApp, admin_request, spawn_app_with_configuration};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn spawn_invitation_only_app() -> TestApp {
+    let app = spawn_app_with_configuration(|c| c.invitations.required = true).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+async fn generate_batch(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    admin_request(app, reqwest::Method::POST, "/admin/invitations")
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// The codes of a new batch.
+async fn generate_codes(app: &TestApp, body: serde_json::Value) -> Vec<String> {
+    let batch: serde_json::Value = generate_batch(app, body)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    serde_json::from_value(batch["codes"].clone()).unwrap()
+}
+
+async fn subscribe(app: &TestApp, email: &str, invite_code: Option<&str>) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": email,
+            "invite_code": invite_code,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn subscription_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn subscribing_without_a_valid_invite_code_is_forbidden() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+    let test_cases = [
+        (None, "no invite code"),
+        (Some("ABCDEFGHJKLM"), "an unknown code"),
+    ];
+
+    for (invite_code, description) in test_cases {
+        // Act
+        let response = subscribe(&app, "ursula_le_guin@gmail.com", invite_code).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            403,
+            "The API did not fail with 403 Forbidden when the subscription had {}.",
+            description
+        );
+    }
+    assert_eq!(subscription_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn single_use_invite_codes_are_used_up() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+    let codes = generate_codes(&app, serde_json::json!({ "count": 2 })).await;
+    assert_eq!(codes.len(), 2);
+
+    // Act
+    let first = subscribe(&app, "ursula@gmail.com", Some(&codes[0].to_lowercase())).await;
+    let reused = subscribe(&app, "octavia@gmail.com", Some(&codes[0])).await;
+    let second = subscribe(&app, "octavia@gmail.com", Some(&codes[1])).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 201);
+    assert_eq!(reused.status().as_u16(), 403);
+    assert_eq!(second.status().as_u16(), 201);
+    assert_eq!(subscription_count(&app).await, 2);
+}
+
+#[tokio::test]
+async fn limited_use_invite_codes_are_good_for_max_uses_subscriptions() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+    let codes = generate_codes(&app, serde_json::json!({ "count": 1, "max_uses": 2 })).await;
+
+    // Act
+    let responses = [
+        subscribe(&app, "ursula@gmail.com", Some(&codes[0])).await,
+        subscribe(&app, "octavia@gmail.com", Some(&codes[0])).await,
+        subscribe(&app, "nk@gmail.com", Some(&codes[0])).await,
+    ];
+
+    // Assert
+    let statuses: Vec<u16> = responses.iter().map(|r| r.status().as_u16()).collect();
+    assert_eq!(statuses, [201, 201, 403]);
+    let batches: serde_json::Value =
+        admin_request(&app, reqwest::Method::GET, "/admin/invitations")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+    assert_eq!(batches["batches"][0]["codes"], 1);
+    assert_eq!(batches["batches"][0]["max_uses"], 2);
+    assert_eq!(batches["batches"][0]["uses"], 2);
+}
+
+#[tokio::test]
+async fn invalid_subscriptions_do_not_use_up_invite_codes() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+    let codes = generate_codes(&app, serde_json::json!({ "count": 1 })).await;
+
+    // Act
+    let invalid = subscribe(&app, "definitely-not-an-email", Some(&codes[0])).await;
+    let valid = subscribe(&app, "ursula@gmail.com", Some(&codes[0])).await;
+
+    // Assert
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(valid.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn the_codes_of_a_deleted_batch_are_rejected() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+    let batch: serde_json::Value = generate_batch(&app, serde_json::json!({ "count": 1 }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let path = format!("/admin/invitations/{}", batch["batch_id"].as_str().unwrap());
+
+    // Act
+    let response = admin_request(&app, reqwest::Method::DELETE, &path)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let code = batch["codes"][0].as_str().unwrap();
+    let response = subscribe(&app, "ursula@gmail.com", Some(code)).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = admin_request(&app, reqwest::Method::DELETE, &path)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn invalid_batches_are_rejected() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+    let test_cases = [
+        (serde_json::json!({ "count": 0 }), "no invitations"),
+        (serde_json::json!({ "count": 1001 }), "too many invitations"),
+        (
+            serde_json::json!({ "count": 1, "max_uses": 0 }),
+            "codes good for nothing",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = generate_batch(&app, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the batch had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn generating_invitations_requires_authentication() {
+    // Arrange
+    let app = spawn_invitation_only_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/invitations", app.address))
+        .json(&serde_json::json!({ "count": 1 }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,169 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use std::time::Duration;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn list_jobs(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/jobs", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn run_job(app: &TestApp, name: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/jobs/{}/run", app.address, name))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Wait for a run of the job `name` after the one reported at
+/// `previous_run_at` to be reported.
+async fn wait_for_run(
+    app: &TestApp,
+    name: &str,
+    previous_run_at: &serde_json::Value,
+) -> serde_json::Value {
+    for _ in 0..50 {
+        let jobs = list_jobs(app).await;
+        let job = jobs["jobs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|job| job["name"] == name)
+            .unwrap()
+            .clone();
+        if !job["last_run_at"].is_null() && &job["last_run_at"] != previous_run_at {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The {} job did not run", name);
+}
+
+#[tokio::test]
+async fn registered_jobs_are_listed_with_their_next_run() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let jobs = list_jobs(&app).await;
+
+    // Assert
+    let jobs = jobs["jobs"].as_array().unwrap();
+    let names: Vec<_> = jobs
+        .iter()
+        .map(|job| job["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "deliveries",
+            "draft_scheduler",
+            "issue_scheduler",
+            "imports"
+        ]
+    );
+    assert!(jobs[0]["last_run_at"].is_null());
+    assert!(jobs[0]["next_run_at"].is_string());
+    assert!(jobs[0]["last_error"].is_null());
+}
+
+#[tokio::test]
+async fn triggered_jobs_run_straight_away() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    {
+        let _mock_guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+        app.wait_for_deliveries().await;
+    }
+    let failed_run = wait_for_run(&app, "deliveries", &serde_json::Value::Null).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = run_job(&app, "deliveries").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let job = wait_for_run(&app, "deliveries", &failed_run["last_run_at"]).await;
+    assert!(job["last_error"].is_null());
+}
+
+#[tokio::test]
+async fn the_last_error_of_a_job_is_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!("ALTER TABLE delivery_tasks RENAME TO delivery_tasks_gone")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    run_job(&app, "deliveries").await;
+
+    // Assert
+    let job = wait_for_run(&app, "deliveries", &serde_json::Value::Null).await;
+    assert!(
+        job["last_error"]
+            .as_str()
+            .unwrap()
+            .contains("Failed to look for pending deliveries")
+    );
+}
+
+#[tokio::test]
+async fn unknown_jobs_cannot_be_run() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = run_job(&app, "cleanup").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn jobs_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list = reqwest::get(format!("{}/admin/jobs", app.address))
+        .await
+        .unwrap();
+    let run = reqwest::Client::new()
+        .post(format!("{}/admin/jobs/deliveries/run", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(list.status().as_u16(), 401);
+    assert_eq!(run.status().as_u16(), 401);
+}
@@ -0,0 +1,112 @@
+use crate::helpers::{spawn_app, spawn_app_with_configuration};
+use zero2prod::configuration::DigestSettings;
+use zero2prod::leader_election::{LeaderElection, LeadershipMetrics};
+
+#[tokio::test]
+async fn a_single_instance_leads_a_job() {
+    // Arrange
+    let app = spawn_app().await;
+    let first = LeaderElection::new("digests", app.connection_pool.clone());
+    let second = LeaderElection::new("digests", app.connection_pool.clone());
+
+    // Act
+    let first_leads = first.is_leader().await;
+    let second_leads = second.is_leader().await;
+
+    // Assert
+    assert!(first_leads);
+    assert!(!second_leads);
+    assert!(first.is_leader().await);
+    assert_eq!(first.metrics().elections_won, 1);
+}
+
+#[tokio::test]
+async fn jobs_are_led_independently() {
+    // Arrange
+    let app = spawn_app().await;
+    let digests = LeaderElection::new("digests", app.connection_pool.clone());
+    let feed_watcher = LeaderElection::new("feed_watcher", app.connection_pool.clone());
+
+    // Act
+    let digests_lead = digests.is_leader().await;
+    let feed_watcher_leads = feed_watcher.is_leader().await;
+
+    // Assert
+    assert!(digests_lead);
+    assert!(feed_watcher_leads);
+}
+
+#[tokio::test]
+async fn leadership_passes_on_when_the_leader_steps_down() {
+    // Arrange
+    let app = spawn_app().await;
+    let first = LeaderElection::new("digests", app.connection_pool.clone());
+    let second = LeaderElection::new("digests", app.connection_pool.clone());
+    assert!(first.is_leader().await);
+
+    // Act
+    first.resign().await;
+
+    // Assert
+    assert!(second.is_leader().await);
+    assert!(!first.is_leader().await);
+    assert_eq!(
+        first.metrics(),
+        LeadershipMetrics {
+            job: "digests".into(),
+            is_leader: false,
+            elections_won: 1,
+            leadership_lost: 1,
+        }
+    );
+}
+
+#[tokio::test]
+async fn leadership_is_reported_for_the_singleton_jobs() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.digests = vec![DigestSettings {
+            name: "Weekly digest".into(),
+            schedule: "0 0 9 * * Mon".parse().unwrap(),
+            send_if_empty: false,
+        }]
+    })
+    .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/leadership", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "jobs": [{
+                "job": "digests",
+                "is_leader": false,
+                "elections_won": 0,
+                "leadership_lost": 0,
+            }]
+        })
+    );
+}
+
+#[tokio::test]
+async fn leadership_metrics_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/leadership", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,102 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+fn issue_linking_to(url: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": format!("Worth a read:\n{{{{card url=\"{}\"}}}}", url),
+            "html": format!("<p>Worth a read:</p><p>{{{{card url=\"{}\"}}}}</p>", url),
+        }
+    })
+}
+
+/// The HTML and plain text of the last email sent.
+async fn last_email(app: &TestApp) -> (String, String) {
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&request.body).unwrap();
+    (email.html.into_owned(), email.text.into_owned())
+}
+
+#[tokio::test]
+async fn cards_preview_the_page_they_link_to_which_is_fetched_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let blog = MockServer::start().await;
+    Mock::given(path("/posts/hello"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"<html><head><meta property="og:title" content="Hello world">
+<meta property="og:description" content="My first post.">
+<meta property="og:image" content="/cover.png"></head><body></body></html>"#,
+            "text/html",
+        ))
+        .expect(1)
+        .mount(&blog)
+        .await;
+    let url = format!("{}/posts/hello", blog.uri());
+
+    for _ in 0..2 {
+        // Act
+        let response = app.post_newsletters(issue_linking_to(&url)).await;
+        assert_eq!(response.status().as_u16(), 202);
+        app.wait_for_deliveries().await;
+
+        // Assert
+        let (html, text) = last_email(&app).await;
+        assert!(!html.contains("{{card"));
+        assert!(html.contains("<strong>Hello world</strong>"));
+        assert!(html.contains("<p>My first post.</p>"));
+        assert!(html.contains("<img src="));
+        assert!(text.contains("Worth a read:\nHello world\n"));
+    }
+}
+
+#[tokio::test]
+async fn cards_fall_back_to_a_link_when_the_page_cannot_be_fetched() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let blog = MockServer::start().await;
+    Mock::given(path("/posts/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&blog)
+        .await;
+    let url = format!("{}/posts/missing", blog.uri());
+
+    // Act
+    let response = app.post_newsletters(issue_linking_to(&url)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let (html, _) = last_email(&app).await;
+    assert!(!html.contains("{{card"));
+    assert!(!html.contains("<table"));
+    assert!(html.contains("<p>Worth a read:</p><p><a href="));
+    let cached = sqlx::query!("SELECT url FROM link_previews")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(cached.is_empty());
+}
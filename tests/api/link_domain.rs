@@ -0,0 +1,114 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app_with_configuration};
+use reqwest::header::HOST;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+const LINK_HOST: &str = "links.example.com";
+
+async fn spawn_app_with_link_domain() -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.application.link_base_url = Some(format!("http://{}", LINK_HOST));
+    })
+    .await
+}
+
+/// Publish an issue linking to an article and return the text and HTML
+/// bodies of the email sent.
+async fn publish_issue_with_a_link(app: &TestApp) -> (String, String) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Read the post at https://example.com/post",
+            "html": r#"<p>Read the <a href="https://example.com/post">post</a></p>"#,
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&request.body).unwrap();
+    (email.text.into_owned(), email.html.into_owned())
+}
+
+/// Send a request for `path` to the application as if it came through the
+/// link domain.
+async fn get_on_link_domain(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}{}", app.address, path))
+        .header(HOST, LINK_HOST)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn links_in_emails_point_to_the_link_domain() {
+    // Arrange
+    let app = spawn_app_with_link_domain().await;
+
+    // Act
+    let (text, html) = publish_issue_with_a_link(&app).await;
+
+    // Assert
+    let short_link_prefix = format!("http://{}/l/", LINK_HOST);
+    let status_link_prefix = format!("http://{}/subscriptions/status?token=", LINK_HOST);
+    assert!(text.contains(&short_link_prefix));
+    assert!(text.contains(&status_link_prefix));
+    assert!(html.contains(&short_link_prefix));
+    assert!(!text.contains(&app.configuration.application.base_url));
+}
+
+#[tokio::test]
+async fn link_routes_are_served_on_the_link_domain() {
+    // Arrange
+    let app = spawn_app_with_link_domain().await;
+    let (text, _) = publish_issue_with_a_link(&app).await;
+    let short_link = linkify::LinkFinder::new()
+        .links(&text)
+        .map(|l| reqwest::Url::parse(l.as_str()).unwrap())
+        .find(|url| url.path().starts_with("/l/"))
+        .unwrap();
+
+    // Act
+    let response = get_on_link_domain(&app, short_link.path()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(response.headers()["Location"], "https://example.com/post");
+}
+
+#[tokio::test]
+async fn other_routes_are_not_served_on_the_link_domain() {
+    // Arrange
+    let app = spawn_app_with_link_domain().await;
+
+    // Act
+    let health_check = get_on_link_domain(&app, "/health_check").await;
+    let drafts = get_on_link_domain(&app, "/newsletters/drafts").await;
+
+    // Assert
+    assert_eq!(health_check.status().as_u16(), 404);
+    assert_eq!(drafts.status().as_u16(), 404);
+    let health_check = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap();
+    assert_eq!(health_check.status().as_u16(), 200);
+}
@@ -0,0 +1,125 @@
+use crate::helpers::{TestApp, browser, log_in, spawn_app};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_without_credentials(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .post(format!("{}/newsletters", app.admin_address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_login_form_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = browser()
+        .get(format!("{}/login", app.admin_address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<form action="/login" method="post">"#));
+}
+
+#[tokio::test]
+async fn logged_in_admins_can_publish_without_credentials() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let client = browser();
+
+    // Act
+    let login = log_in(&app, &client, &app.test_user.password).await;
+    let response = publish_without_credentials(&app, &client).await;
+
+    // Assert
+    assert_eq!(login.status().as_u16(), 303);
+    assert_eq!(login.headers()["Location"], "/login");
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+}
+
+#[tokio::test]
+async fn an_invalid_password_does_not_start_a_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = browser();
+
+    // Act
+    let login = log_in(&app, &client, "not-the-password").await;
+    let response = publish_without_credentials(&app, &client).await;
+
+    // Assert
+    assert_eq!(login.status().as_u16(), 401);
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = browser();
+    log_in(&app, &client, &app.test_user.password).await;
+
+    // Act
+    let logout = client
+        .post(format!("{}/logout", app.admin_address))
+        .send()
+        .await
+        .unwrap();
+    let response = publish_without_credentials(&app, &client).await;
+
+    // Assert
+    assert_eq!(logout.status().as_u16(), 303);
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn changing_the_password_ends_the_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let laptop = browser();
+    let phone = browser();
+    log_in(&app, &laptop, &app.test_user.password).await;
+    log_in(&app, &phone, &app.test_user.password).await;
+
+    // Act
+    let change = laptop
+        .post(format!("{}/admin/password", app.admin_address))
+        .json(&serde_json::json!({
+            "current_password": app.test_user.password,
+            "new_password": "correct horse battery staple and more",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(change.status().as_u16(), 200);
+    let dashboard = |client: &reqwest::Client| {
+        client
+            .get(format!("{}/admin/dashboard", app.admin_address))
+            .send()
+    };
+    assert_eq!(dashboard(&laptop).await.unwrap().status().as_u16(), 200);
+    let phone_dashboard = dashboard(&phone).await.unwrap();
+    assert_eq!(phone_dashboard.status().as_u16(), 303);
+    assert_eq!(phone_dashboard.headers()["Location"], "/login");
+}
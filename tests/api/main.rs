@@ -1,5 +1,67 @@
+mod admin_dashboard;
+mod admin_listener;
+mod admin_reports;
+mod alerts;
+mod amp;
+mod api_keys;
+mod archive;
+mod backup;
+mod blob_store;
+mod branding;
+mod calendar;
+mod cli;
+mod complaints;
+mod consent_proofs;
+mod deliverability;
+mod delivery;
+mod delivery_pause;
+mod dev_fixtures;
+mod digests;
+mod draft_comments;
+mod encryption;
+mod extensions;
+mod feature_flags;
+mod feed_watcher;
 mod health_check;
 mod helpers;
+mod imports;
+mod internal_copies;
+mod invitations;
+mod jobs;
+mod leader_election;
+mod link_cards;
+mod link_domain;
+mod login;
+mod maintenance;
+mod migrations;
 mod newsletter;
+mod newsletter_drafts;
+mod newsletter_scheduling;
+mod password;
+mod postmaster;
+mod previews;
+mod quarantine;
+mod rate_limiting;
+mod reengagement;
+mod reports;
+mod request_id;
+mod segments;
+mod senders;
+mod server;
+mod short_links;
+mod shutdown;
+mod slack;
+mod subscriber_erasure;
+mod subscriber_list;
+mod subscriber_login;
+mod subscriber_merge;
+mod subscriber_search;
+mod subscription_status;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod template_fragments;
+mod templates;
+mod tls;
+mod token_guard;
+mod tracking;
@@ -0,0 +1,107 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with_configuration};
+
+async fn set_maintenance(app: &TestApp, enabled: bool) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/maintenance", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "enabled": enabled }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn public_endpoints_are_down_during_maintenance() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = set_maintenance(&app, true).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(response.headers()["Retry-After"], "600");
+    let page = response.text().await.unwrap();
+    assert!(page.contains("come back in 10 minutes"));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn health_admin_and_webhook_endpoints_keep_working_during_maintenance() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.maintenance.enabled = true).await;
+
+    // Act
+    let health = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap();
+    let drafts = reqwest::Client::new()
+        .get(format!("{}/newsletters/drafts", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+    let login = reqwest::get(format!("{}/login", app.address))
+        .await
+        .unwrap();
+    let email_events = app
+        .post_email_events("test-webhook-token", serde_json::json!({ "events": [] }))
+        .await;
+
+    // Assert
+    assert_eq!(health.status().as_u16(), 200);
+    assert_eq!(drafts.status().as_u16(), 200);
+    assert_eq!(login.status().as_u16(), 200);
+    assert_eq!(email_events.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn public_endpoints_are_back_once_maintenance_is_over() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.maintenance.enabled = true).await;
+    let login_form = format!("{}/subscriptions/login", app.address);
+    assert_eq!(
+        reqwest::get(&login_form).await.unwrap().status().as_u16(),
+        503
+    );
+
+    // Act
+    let response = set_maintenance(&app, false).await;
+
+    // Assert
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status, serde_json::json!({ "enabled": false }));
+    assert_eq!(
+        reqwest::get(&login_form).await.unwrap().status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn toggling_maintenance_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/maintenance", app.address))
+        .json(&serde_json::json!({ "enabled": true }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let login_form = reqwest::get(format!("{}/subscriptions/login", app.address))
+        .await
+        .unwrap();
+    assert_eq!(login_form.status().as_u16(), 200);
+}
@@ -0,0 +1,70 @@
+use crate::helpers::{empty_database, spawn_app};
+use std::time::Duration;
+use zero2prod::configuration::MigrationSettings;
+use zero2prod::migrations::{ADVISORY_LOCK_KEY, MIGRATOR, check_pending_migrations, run_online};
+
+#[tokio::test]
+async fn pending_migrations_are_applied_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let settings = &app.configuration.database.migrations;
+    let mut connection = empty_database(&app.configuration.database).await;
+
+    // Act
+    let first = run_online(&mut connection, settings).await.unwrap();
+    let second = run_online(&mut connection, settings).await.unwrap();
+
+    // Assert
+    assert_eq!(first.len(), MIGRATOR.iter().count());
+    assert!(second.is_empty());
+}
+
+#[tokio::test]
+async fn a_fully_migrated_database_has_nothing_to_check() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut connection = app.connection_pool.acquire().await.unwrap();
+
+    // Act
+    let incompatibilities = check_pending_migrations(&mut connection).await.unwrap();
+
+    // Assert
+    assert!(incompatibilities.is_empty());
+}
+
+#[tokio::test]
+async fn a_fresh_database_has_no_running_version_to_break() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut connection = empty_database(&app.configuration.database).await;
+
+    // Act
+    let incompatibilities = check_pending_migrations(&mut connection).await.unwrap();
+
+    // Assert
+    assert!(incompatibilities.is_empty());
+}
+
+#[tokio::test]
+async fn only_one_instance_migrates_at_a_time() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut first = app.connection_pool.acquire().await.unwrap();
+    let mut second = app.connection_pool.acquire().await.unwrap();
+    let settings = MigrationSettings {
+        advisory_lock_wait: Duration::from_millis(100),
+        ..app.configuration.database.migrations.clone()
+    };
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(ADVISORY_LOCK_KEY)
+        .execute(&mut *first)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = run_online(&mut second, &settings).await;
+
+    // Assert
+    let error = outcome.unwrap_err();
+    assert!(error.to_string().contains("Another instance"));
+}
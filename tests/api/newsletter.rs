@@ -1,7 +1,14 @@
-use crate::helpers::{ConfirmationLinks, TestApp, spawn_app};
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    spawn_app_with_configuration,
+};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::get_configuration;
+use zero2prod::email_client::SendEmailRequest;
+use zero2prod::startup::Application;
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -26,7 +33,8 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let response = app.post_newsletters(newsletter_request_body).await;
 
     // Assert
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
 }
 
 #[tokio::test]
@@ -53,10 +61,81 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let response = app.post_newsletters(newsletter_request_body).await;
 
     // Assert
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
     // Mock verifies on Drop that we have sent the newsletter email
 }
 
+#[tokio::test]
+async fn newsletters_carry_the_postal_address_and_an_unsubscribe_link() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.email_templates.footer.html = "<p>Thanks for reading</p>".into();
+        c.email_templates.footer.text = "Thanks for reading".into();
+        c.email_templates.footer.postal_address = "1 Ferris Lane, Crabtown".into();
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+             "text": "Newsletter body as plain text",
+             "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&request.body).unwrap();
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?token=",
+        app.configuration.application.base_url
+    );
+    assert!(email.html.contains("1 Ferris Lane, Crabtown<br><a href="));
+    assert!(email.html.contains(&unsubscribe_link));
+    assert!(
+        email
+            .text
+            .contains("Thanks for reading\n1 Ferris Lane, Crabtown\nUnsubscribe: ")
+    );
+    assert!(email.text.contains(&unsubscribe_link));
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_without_a_postal_address() {
+    // Arrange
+    let mut configuration = get_configuration().expect("Failed to read configuration.");
+    configuration.application.port = 0;
+    configuration.email_templates.footer.postal_address = "".into();
+
+    // Act
+    let result = Application::build(configuration).await;
+
+    // Assert
+    let error = result
+        .err()
+        .expect("The application started without a postal address");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     // Arrange
@@ -91,7 +170,7 @@ async fn newsletters_returns_400_for_invalid_data() {
 }
 
 #[tokio::test]
-async fn newsletters_returns_500_if_sending_email_fails() {
+async fn newsletters_stay_queued_if_sending_email_fails() {
     // Arrange
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
@@ -114,7 +193,89 @@ async fn newsletters_returns_500_if_sending_email_fails() {
     let response = app.post_newsletters(newsletter_request_body).await;
 
     // Assert
-    assert_eq!(response.status().as_u16(), 500);
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let queued = sqlx::query!("SELECT attempts FROM delivery_tasks")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.attempts, 1);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_despite_a_transient_provider_error() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| {
+        c.email_client.retry.max_attempts = 3;
+        c.email_client.retry.base_delay = Duration::from_millis(10);
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let queued = sqlx::query!("SELECT count(*) AS \"count!\" FROM delivery_tasks")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_accepted_without_waiting_for_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(10)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let started_at = Instant::now();
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["queued_deliveries"], 1);
 }
 
 #[tokio::test]
@@ -155,7 +316,8 @@ async fn newsletters_are_delivered_to_confirmed_subscribers_while_skipping_inval
     let response = app.post_newsletters(newsletter_request_body).await;
 
     // Assert
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
     // Mock verifies on Drop that we have sent the newsletter email **once**.
 }
 
@@ -244,38 +406,227 @@ async fn invalid_password_is_rejected() {
     );
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+#[tokio::test]
+async fn newsletters_skip_subscribers_rejected_by_the_email_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-    let _mock_guard = Mock::given(path("/api/send"))
+    Mock::given(path("/api/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "success": false,
+            "errors": ["'to' address is invalid"]
+        })))
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
 
-    app.post_subscriptions(body)
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let deliveries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_deliveries"#)
+        .fetch_one(&app.connection_pool)
         .await
-        .error_for_status()
         .unwrap();
+    assert_eq!(deliveries.count, 0);
+}
 
-    let email_request = &app
-        .email_server
-        .received_requests()
+async fn post_newsletters_with_idempotency_key(
+    app: &TestApp,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Idempotency-Key", idempotency_key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act
+    let first = post_newsletters_with_idempotency_key(&app, &idempotency_key).await;
+    let second = post_newsletters_with_idempotency_key(&app, &idempotency_key).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(
+        first.headers()["Content-Type"],
+        second.headers()["Content-Type"]
+    );
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    app.wait_for_deliveries().await;
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.connection_pool)
         .await
-        .unwrap()
-        .pop()
         .unwrap();
-    app.get_confirmation_links(&email_request)
+    assert_eq!(issues.count, 1);
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_creation_is_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act
+    let (first, second) = tokio::join!(
+        post_newsletters_with_idempotency_key(&app, &idempotency_key),
+        post_newsletters_with_idempotency_key(&app, &idempotency_key),
+    );
+
+    // Assert
+    assert_eq!(first.status(), second.status());
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    app.wait_for_deliveries().await;
+}
+
+#[tokio::test]
+async fn invalid_idempotency_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_newsletters_with_idempotency_key(&app, &"a".repeat(51)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    // We can then reuse the same helper and just add
-    // an extra step to actually call the confirmation link!
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
+async fn get_archive(app: &TestApp, path: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}{}", &app.address, path))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn published_issues_can_be_listed_and_fetched() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut newsletter_issue_ids = Vec::new();
+    for title in ["First issue", "Second issue"] {
+        let body: serde_json::Value = app
+            .post_newsletters(serde_json::json!({
+                "title": title,
+                "content": {
+                    "text": format!("{} as plain text", title),
+                    "html": format!("<p>{} as HTML</p>", title),
+                }
+            }))
+            .await
+            .json()
+            .await
+            .unwrap();
+        newsletter_issue_ids.push(body["newsletter_issue_id"].as_str().unwrap().to_owned());
+    }
+
+    // Act - Part 1 - List
+    let list: serde_json::Value = get_archive(&app, "/newsletters?page_size=1")
         .await
-        .unwrap()
         .error_for_status()
+        .unwrap()
+        .json()
+        .await
         .unwrap();
+    assert_eq!(list["total"], 2);
+    assert_eq!(list["issues"].as_array().unwrap().len(), 1);
+    assert_eq!(list["issues"][0]["title"], "Second issue");
+    assert_eq!(list["issues"][0]["author"], app.test_user.username);
+
+    // Act - Part 2 - Fetch
+    let issue: serde_json::Value =
+        get_archive(&app, &format!("/newsletters/{}", newsletter_issue_ids[0]))
+            .await
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+    // Assert
+    assert_eq!(issue["newsletter_issue_id"], newsletter_issue_ids[0]);
+    assert_eq!(issue["title"], "First issue");
+    assert!(
+        issue["html_content"]
+            .as_str()
+            .unwrap()
+            .contains("<p>First issue as HTML</p>")
+    );
+    assert!(
+        issue["text_content"]
+            .as_str()
+            .unwrap()
+            .contains("First issue as plain text")
+    );
+    assert_eq!(issue["author"], app.test_user.username);
+}
+
+#[tokio::test]
+async fn fetching_an_unknown_issue_returns_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_archive(&app, &format!("/newsletters/{}", Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn listing_issues_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/newsletters", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
 }
@@ -0,0 +1,347 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, spawn_app_with_configuration};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered_until_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_newsletter_draft(draft_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let drafts = app.get_newsletter_drafts().await;
+    let drafts = drafts["drafts"].as_array().unwrap();
+    assert_eq!(drafts.len(), 1);
+    assert_eq!(drafts[0]["title"], "Newsletter title");
+    assert_eq!(drafts[0]["html"], "<p>Newsletter body as HTML</p>");
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response: serde_json::Value = app
+        .post_newsletter_draft(draft_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+    let draft_id = response["newsletter_draft_id"].as_str().unwrap();
+
+    // Act
+    let first = app.publish_newsletter_draft(draft_id).await;
+    let second = app.publish_newsletter_draft(draft_id).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 409);
+    let drafts = app.get_newsletter_drafts().await;
+    assert!(drafts["drafts"].as_array().unwrap().is_empty());
+    let issues = sqlx::query!("SELECT title FROM newsletter_issues")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].title, "Newsletter title");
+}
+
+#[tokio::test]
+async fn publishing_an_unknown_draft_is_rejected_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .publish_newsletter_draft(&Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters/drafts", &app.address))
+        .json(&draft_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn drafts_are_checked_for_accessibility() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": r#"<h1>Title</h1><h3>Section</h3><img src="chart.png"><p style="color: #aaa">Faint</p>"#,
+        }
+    });
+
+    // Act
+    let response: serde_json::Value = app.post_newsletter_draft(body).await.json().await.unwrap();
+
+    // Assert
+    assert_eq!(
+        response["accessibility_warnings"],
+        serde_json::json!([
+            {"kind": "skipped_heading_level", "from": 1, "to": 3},
+            {"kind": "missing_alt_text", "src": "chart.png"},
+            {"kind": "low_contrast", "color": "#aaa", "background": "#ffffff", "ratio": 2.32},
+        ])
+    );
+    let drafts = app.get_newsletter_drafts().await;
+    assert_eq!(drafts["drafts"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn drafts_are_checked_for_subjects_clients_cut_off() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({
+        "title": "🎉🎉🎉 Our biggest launch yet, with everything 👍🏽",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+
+    // Act
+    let response: serde_json::Value = app.post_newsletter_draft(body).await.json().await.unwrap();
+
+    // Assert
+    assert_eq!(
+        response["subject_warnings"],
+        serde_json::json!([
+            {
+                "kind": "truncated",
+                "client": "phones in portrait",
+                "width": 40,
+                "shown": "🎉🎉🎉 Our biggest launch yet, with ever…",
+            },
+            {"kind": "composed_emoji", "emoji": "👍🏽"},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn published_drafts_are_sent_with_a_language_and_an_article_landmark() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response: serde_json::Value = app
+        .post_newsletter_draft(draft_body())
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    app.publish_newsletter_draft(response["newsletter_draft_id"].as_str().unwrap())
+        .await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&request.body).unwrap();
+    let html = email.html;
+    assert!(html.starts_with(
+        r#"<div role="article" lang="en" aria-roledescription="email"><p>Newsletter body as HTML</p>"#
+    ));
+    assert!(html.contains("Manage your subscription</a></p></div>"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_from_a_web_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let blog = MockServer::start().await;
+    Mock::given(path("/posts/hello"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"<html><head><title>Hello world</title></head><body>
+<nav><a href="/">Home</a></nav>
+<article><h1>Hello world</h1><p>My <a href="/about">first</a> post.</p></article>
+</body></html>"#,
+            "text/html",
+        ))
+        .expect(1)
+        .mount(&blog)
+        .await;
+    let source_url = format!("{}/posts/hello", blog.uri());
+
+    // Act
+    let response = app
+        .post_newsletter_draft(serde_json::json!({ "source_url": source_url }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let drafts = app.get_newsletter_drafts().await;
+    let draft = &drafts["drafts"][0];
+    assert_eq!(draft["title"], "Hello world");
+    let html = draft["html"].as_str().unwrap();
+    assert!(html.contains(&format!(
+        r#"<p>My <a href="{}/about">first</a> post.</p>"#,
+        blog.uri()
+    )));
+    assert!(html.contains(&format!(
+        r#"<a href="{}">Read the full post</a>"#,
+        source_url
+    )));
+    assert!(!html.contains("Home"));
+    assert!(draft["text"].as_str().unwrap().contains("My first post."));
+}
+
+#[tokio::test]
+async fn drafts_from_unreachable_pages_are_rejected_with_a_502() {
+    // Arrange
+    let app = spawn_app().await;
+    let blog = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&blog)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletter_draft(serde_json::json!({
+            "source_url": format!("{}/posts/missing", blog.uri())
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 502);
+    let drafts = app.get_newsletter_drafts().await;
+    assert!(drafts["drafts"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn drafts_cannot_be_created_from_private_addresses() {
+    // Arrange
+    let app = spawn_app_with_configuration(|c| c.web_pages.allow_private_addresses = false).await;
+    let internal = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_body_raw("<p>Secret</p>", "text/html"))
+        .expect(0)
+        .mount(&internal)
+        .await;
+    let test_cases = [
+        (format!("{}/posts/hello", internal.uri()), 400),
+        ("http://169.254.169.254/latest/meta-data".to_owned(), 400),
+        // Host names are only resolved to their public addresses.
+        (
+            format!("http://localhost:{}/posts/hello", internal.address().port()),
+            502,
+        ),
+    ];
+
+    for (source_url, status) in test_cases {
+        // Act
+        let response = app
+            .post_newsletter_draft(serde_json::json!({ "source_url": source_url }))
+            .await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The API did not reject a draft from {}.",
+            source_url
+        );
+    }
+    let drafts = app.get_newsletter_drafts().await;
+    assert!(drafts["drafts"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn drafts_need_either_content_or_a_source_url() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "title": "Newsletter title" }),
+            "no content",
+        ),
+        (
+            serde_json::json!({
+                "content": { "text": "Body", "html": "<p>Body</p>" }
+            }),
+            "no title",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": { "text": "Body", "html": "<p>Body</p>" },
+                "source_url": "https://blog.example.com/posts/hello"
+            }),
+            "both content and a source URL",
+        ),
+        (
+            serde_json::json!({ "source_url": "file:///etc/passwd" }),
+            "a source URL that is not http(s)",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_newsletter_draft(body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with a 400 when the draft had {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::delivery::DELIVERY_JOB;
+use zero2prod::issue_scheduler::IssueScheduler;
+use zero2prod::jobs::Job;
+
+fn newsletter(scheduled_at: DateTime<Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "scheduled_at": scheduled_at,
+    })
+}
+
+fn issue_scheduler(app: &TestApp) -> IssueScheduler {
+    IssueScheduler::build(app.connection_pool.clone(), Job::new(DELIVERY_JOB))
+}
+
+async fn cancel_schedule(app: &TestApp, newsletter_issue_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .delete(format!(
+            "{}/newsletters/{}/schedule",
+            app.address, newsletter_issue_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let at = Utc::now() + Duration::hours(1);
+    let scheduler = issue_scheduler(&app);
+
+    // Act
+    let response = app.post_newsletters(newsletter(at)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["queued_deliveries"], 0);
+    assert!(body["scheduled_at"].is_string());
+    let newsletter_issue_id: Uuid = body["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(scheduler.enqueue_due(Utc::now()).await.unwrap().is_empty());
+    let queued = scheduler
+        .enqueue_due(at + Duration::seconds(1))
+        .await
+        .unwrap();
+    assert_eq!(queued, [newsletter_issue_id]);
+    assert!(
+        scheduler
+            .enqueue_due(at + Duration::seconds(1))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    reqwest::Client::new()
+        .post(format!("{}/admin/jobs/deliveries/run", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.wait_for_deliveries().await;
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_that_cannot_be_queued_does_not_hold_up_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let at = Utc::now() + Duration::hours(1);
+    let mut issues = Vec::new();
+    for scheduled_at in [at, at + Duration::minutes(1)] {
+        let response = app.post_newsletters(newsletter(scheduled_at)).await;
+        let body: serde_json::Value = response.json().await.unwrap();
+        let newsletter_issue_id: Uuid = body["newsletter_issue_id"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        issues.push(newsletter_issue_id);
+    }
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_filter = 'not json' WHERE newsletter_issue_id = $1",
+        issues[0],
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    let scheduler = issue_scheduler(&app);
+
+    // Act
+    let queued = scheduler
+        .enqueue_due(at + Duration::minutes(2))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(queued, [issues[1]]);
+    let schedule_error = sqlx::query_scalar!(
+        "SELECT schedule_error FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issues[0],
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert!(schedule_error.unwrap().contains("parse the filter"));
+    assert!(
+        scheduler
+            .enqueue_due(at + Duration::minutes(2))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_cancelled_before_they_are_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let at = Utc::now() + Duration::hours(1);
+    let response = app.post_newsletters(newsletter(at)).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap();
+
+    // Act
+    let response = cancel_schedule(&app, newsletter_issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(
+        issue_scheduler(&app)
+            .enqueue_due(at + Duration::seconds(1))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let response = cancel_schedule(&app, newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_sent_straight_away_cannot_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+        }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    // Act
+    let response = cancel_schedule(&app, body["newsletter_issue_id"].as_str().unwrap()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn issues_scheduled_in_the_past_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletters(newsletter(Utc::now() - Duration::minutes(1)))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "The issue must be scheduled in the future."
+    );
+}
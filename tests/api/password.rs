@@ -0,0 +1,141 @@
+use crate::helpers::{TestApp, spawn_app};
+
+const NEW_PASSWORD: &str = "correct horse battery staple";
+
+async fn change_password(
+    app: &TestApp,
+    password: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/password", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(password))
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn list_feature_flags(app: &TestApp, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/feature_flags", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(password))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_password_can_be_changed() {
+    // Arrange
+    let app = spawn_app().await;
+    let old_password = app.test_user.password.clone();
+
+    // Act
+    let response = change_password(
+        &app,
+        &old_password,
+        &serde_json::json!({
+            "current_password": old_password,
+            "new_password": NEW_PASSWORD,
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        list_feature_flags(&app, &old_password)
+            .await
+            .status()
+            .as_u16(),
+        401
+    );
+    assert_eq!(
+        list_feature_flags(&app, NEW_PASSWORD)
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn the_current_password_must_be_provided() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = change_password(
+        &app,
+        &app.test_user.password,
+        &serde_json::json!({
+            "current_password": "not-the-password",
+            "new_password": NEW_PASSWORD,
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        list_feature_flags(&app, &app.test_user.password)
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn weak_passwords_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let weak_passwords = [
+        ("short", "too short"),
+        ("aaaaaaaaaaaaaaaaaaaa", "too few distinct characters"),
+        ("abcdefghijkl", "too little entropy"),
+        (&*"a1".repeat(100), "too long"),
+    ];
+
+    for (new_password, description) in weak_passwords {
+        // Act
+        let response = change_password(
+            &app,
+            &app.test_user.password,
+            &serde_json::json!({
+                "current_password": app.test_user.password,
+                "new_password": new_password,
+            }),
+        )
+        .await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject a password that is {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn requests_without_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/password", app.admin_address))
+        .json(&serde_json::json!({
+            "current_password": app.test_user.password,
+            "new_password": NEW_PASSWORD,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
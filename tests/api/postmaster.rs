@@ -0,0 +1,160 @@
+use crate::helpers::{TestApp, spawn_app_with_configuration};
+use std::time::Duration;
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{GooglePostmasterSettings, PostmasterSettings};
+use zero2prod::postmaster::{IngestedReports, PostmasterIngester};
+
+fn dmarc_report(report_id: &str, begin: i64, passed: u32, failed: u32) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+<feedback>
+  <report_metadata>
+    <org_name>google.com</org_name>
+    <report_id>{report_id}</report_id>
+    <date_range><begin>{begin}</begin><end>{end}</end></date_range>
+  </report_metadata>
+  <policy_published><domain>example.com</domain><p>none</p></policy_published>
+  <record>
+    <row>
+      <source_ip>203.0.113.1</source_ip>
+      <count>{passed}</count>
+      <policy_evaluated><disposition>none</disposition><dkim>pass</dkim><spf>pass</spf></policy_evaluated>
+    </row>
+  </record>
+  <record>
+    <row>
+      <source_ip>198.51.100.7</source_ip>
+      <count>{failed}</count>
+      <policy_evaluated><disposition>none</disposition><dkim>fail</dkim><spf>fail</spf></policy_evaluated>
+    </row>
+  </record>
+</feedback>"#,
+        end = begin + 86399,
+    )
+}
+
+async fn spawn_app_with_postmaster(reports_server: &MockServer) -> (TestApp, PostmasterIngester) {
+    let postmaster = PostmasterSettings {
+        // Runs are triggered by the test itself.
+        poll_interval: Duration::from_secs(3600),
+        timeout: Duration::from_secs(2),
+        dmarc_reports_url: Some(format!("{}/dmarc", reports_server.uri())),
+        google: Some(GooglePostmasterSettings {
+            base_url: reports_server.uri(),
+            access_token: "postmaster-token".to_string().into(),
+            domains: vec!["example.com".into()],
+        }),
+    };
+    let app = spawn_app_with_configuration(|c| c.postmaster = Some(postmaster)).await;
+    let ingester =
+        PostmasterIngester::build(&app.configuration, app.connection_pool.clone()).unwrap();
+    (app, ingester)
+}
+
+async fn serve_reports(reports_server: &MockServer) {
+    let yesterday = chrono::Utc::now().timestamp() - 86400;
+    Mock::given(method("GET"))
+        .and(path("/dmarc"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(vec![
+            dmarc_report("report-1", yesterday, 90, 10),
+            dmarc_report("report-2", yesterday, 10, 0),
+            "<html>Not a report</html>".to_owned(),
+        ]))
+        .mount(reports_server)
+        .await;
+    let today = chrono::Utc::now().date_naive();
+    let day = |days_ago: u64| {
+        (today - chrono::Days::new(days_ago))
+            .format("%Y%m%d")
+            .to_string()
+    };
+    Mock::given(method("GET"))
+        .and(path("/v1/domains/example.com/trafficStats"))
+        .and(header("Authorization", "Bearer postmaster-token"))
+        .and(query_param("pageToken", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "trafficStats": [{
+                "name": format!("domains/example.com/trafficStats/{}", day(1)),
+                "domainReputation": "MEDIUM",
+                "userReportedSpamRatio": 0.002,
+            }]
+        })))
+        .mount(reports_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/domains/example.com/trafficStats"))
+        .and(header("Authorization", "Bearer postmaster-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "trafficStats": [{
+                "name": format!("domains/example.com/trafficStats/{}", day(2)),
+                "domainReputation": "HIGH",
+                "userReportedSpamRatio": 0.0005,
+                "dkimSuccessRatio": 1.0,
+            }],
+            "nextPageToken": "page-2",
+        })))
+        .mount(reports_server)
+        .await;
+}
+
+#[tokio::test]
+async fn reports_are_ingested_once() {
+    // Arrange
+    let reports_server = MockServer::start().await;
+    serve_reports(&reports_server).await;
+    let (_app, ingester) = spawn_app_with_postmaster(&reports_server).await;
+
+    // Act
+    let first = ingester.ingest_once().await.unwrap();
+    let second = ingester.ingest_once().await.unwrap();
+
+    // Assert
+    assert_eq!(
+        first,
+        IngestedReports {
+            dmarc_reports: 2,
+            reputation_days: 2,
+        }
+    );
+    // Postmaster Tools statistics are refreshed, DMARC reports are not.
+    assert_eq!(
+        second,
+        IngestedReports {
+            dmarc_reports: 0,
+            reputation_days: 2,
+        }
+    );
+}
+
+#[tokio::test]
+async fn reputation_trends_are_shown_on_the_deliverability_dashboard() {
+    // Arrange
+    let reports_server = MockServer::start().await;
+    serve_reports(&reports_server).await;
+    let (app, ingester) = spawn_app_with_postmaster(&reports_server).await;
+    ingester.ingest_once().await.unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/deliverability", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let dashboard: serde_json::Value = response.json().await.unwrap();
+    let reputation = dashboard["reputation"].as_array().unwrap();
+    assert_eq!(reputation.len(), 2);
+    assert_eq!(reputation[0]["domain"], "example.com");
+    assert_eq!(reputation[0]["reputation"], "HIGH");
+    assert_eq!(reputation[1]["reputation"], "MEDIUM");
+    assert_eq!(reputation[1]["user_reported_spam_ratio"], 0.002);
+    let dmarc = &dashboard["dmarc"][0];
+    assert_eq!(dmarc["domain"], "example.com");
+    assert_eq!(dmarc["messages"], 110);
+    let pass_rate = dmarc["pass_rate"].as_f64().unwrap();
+    assert!((pass_rate - 100.0 / 110.0).abs() < 1e-9);
+}
@@ -0,0 +1,127 @@
+use crate::helpers::{TestApp, create_draft, spawn_app};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use zero2prod::signing::UrlSigner;
+
+async fn create_preview_link(app: &TestApp, draft_id: Uuid, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/newsletters/drafts/{}/preview_links{}",
+            app.address, draft_id, query
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Preview links point at the configured base URL, we call the test server.
+fn local_preview_url(app: &TestApp, preview_url: &str) -> String {
+    let path = reqwest::Url::parse(preview_url).unwrap().path().to_owned();
+    format!("{}{}", app.address, path)
+}
+
+#[tokio::test]
+async fn preview_links_show_the_draft_without_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+    let response: serde_json::Value = app
+        .post_newsletter_draft(serde_json::json!({
+            "title": "Draft <title>",
+            "content": {
+                "text": "Draft body as plain text",
+                "html": "<p>Draft body as HTML</p>",
+            }
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let draft_id = response["newsletter_draft_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let response: serde_json::Value = create_preview_link(&app, draft_id, "")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let preview_url = local_preview_url(&app, response["preview_url"].as_str().unwrap());
+    let response = reqwest::get(preview_url).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["X-Robots-Tag"], "noindex");
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<p>Draft body as HTML</p>"));
+    assert!(page.contains("Draft &lt;title&gt;"));
+}
+
+#[tokio::test]
+async fn tampered_preview_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+    let other_draft_id = create_draft(&app).await;
+    let response: serde_json::Value = create_preview_link(&app, draft_id, "")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let preview_url = local_preview_url(&app, response["preview_url"].as_str().unwrap());
+
+    // Act
+    let response =
+        reqwest::get(preview_url.replace(&draft_id.to_string(), &other_draft_id.to_string()))
+            .await
+            .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn expired_preview_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+    let signer = UrlSigner::new(app.configuration.application.hmac_secret.clone());
+    let token = signer.sign(
+        "draft-preview",
+        &draft_id.to_string(),
+        Utc::now() - Duration::minutes(1),
+    );
+
+    // Act
+    let response = reqwest::get(format!("{}/preview/{}", app.address, token))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn preview_links_require_an_existing_draft_and_a_bounded_expiry() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let unknown = create_preview_link(&app, Uuid::new_v4(), "").await;
+    let no_expiry = create_preview_link(&app, draft_id, "?expires_in_hours=0").await;
+    let too_long = create_preview_link(&app, draft_id, "?expires_in_hours=721").await;
+    let overflowing =
+        create_preview_link(&app, draft_id, &format!("?expires_in_hours={}", u32::MAX)).await;
+
+    // Assert
+    assert_eq!(unknown.status().as_u16(), 404);
+    for response in [no_expiry, too_long, overflowing] {
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::helpers::{TestApp, spawn_app_with_configuration};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const SIGNUPS: [&str; 3] = [
+    "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    "name=tolkien&email=tolkien%40example.com",
+    "name=pratchett&email=pratchett%40example.org",
+];
+
+async fn spawn_app_quarantining_after(max_per_network: u32, max_per_domain: u32) -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.signup_anomalies.max_signups_per_network = max_per_network;
+        c.signup_anomalies.max_signups_per_domain = max_per_domain;
+    })
+    .await
+}
+
+async fn get_quarantine(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/quarantine", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn review(app: &TestApp, subscriber_id: &str, decision: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/admin/quarantine/{}/{}",
+            app.address, subscriber_id, decision
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Signs up every address of `SIGNUPS` and returns the id of the last one.
+async fn quarantine_last_signup(app: &TestApp) -> String {
+    for body in SIGNUPS {
+        app.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    let quarantine = get_quarantine(app).await;
+    quarantine["subscriptions"][0]["subscriber_id"]
+        .as_str()
+        .unwrap()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn signup_bursts_from_one_network_are_quarantined() {
+    // Arrange
+    let app = spawn_app_quarantining_after(2, 100).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for body in SIGNUPS {
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    let statuses: Vec<_> = saved.iter().map(|s| s.status.as_str()).collect();
+    assert_eq!(
+        statuses,
+        [
+            "pending_confirmation",
+            "pending_confirmation",
+            "quarantined"
+        ]
+    );
+
+    let quarantine = get_quarantine(&app).await;
+    let subscriptions = quarantine["subscriptions"].as_array().unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["email"], "pratchett@example.org");
+    assert_eq!(subscriptions[0]["signup_network"], "127.0.0.0/24");
+    assert!(
+        subscriptions[0]["reason"]
+            .as_str()
+            .unwrap()
+            .contains("127.0.0.0/24")
+    );
+}
+
+#[tokio::test]
+async fn signup_bursts_to_one_domain_are_quarantined() {
+    // Arrange
+    let app = spawn_app_quarantining_after(100, 1).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=tolkien&email=tolkien%40example.com")
+        .await;
+    app.post_subscriptions("name=lewis&email=lewis%40example.com")
+        .await;
+
+    // Assert
+    let quarantine = get_quarantine(&app).await;
+    let subscriptions = quarantine["subscriptions"].as_array().unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["email"], "lewis@example.com");
+    assert!(
+        subscriptions[0]["reason"]
+            .as_str()
+            .unwrap()
+            .contains("example.com")
+    );
+}
+
+#[tokio::test]
+async fn released_subscriptions_receive_their_confirmation_email() {
+    // Arrange
+    let app = spawn_app_quarantining_after(2, 100).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = quarantine_last_signup(&app).await;
+
+    // Act
+    let response = review(&app, &subscriber_id, "release").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved =
+        sqlx::query!("SELECT status FROM subscriptions WHERE email = 'pratchett@example.org'")
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(
+        get_quarantine(&app).await["subscriptions"],
+        serde_json::json!([])
+    );
+}
+
+#[tokio::test]
+async fn rejected_subscriptions_are_deleted() {
+    // Arrange
+    let app = spawn_app_quarantining_after(2, 100).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = quarantine_last_signup(&app).await;
+
+    // Act
+    let response = review(&app, &subscriber_id, "reject").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert!(saved.iter().all(|s| s.email != "pratchett@example.org"));
+}
+
+#[tokio::test]
+async fn only_quarantined_subscriptions_can_be_reviewed() {
+    // Arrange
+    let app = spawn_app_quarantining_after(100, 100).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(SIGNUPS[0]).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id
+        .to_string();
+
+    for decision in ["release", "reject"] {
+        for id in [subscriber_id.clone(), Uuid::new_v4().to_string()] {
+            // Act
+            let response = review(&app, &id, decision).await;
+
+            // Assert
+            assert_eq!(response.status().as_u16(), 404);
+        }
+    }
+}
+
+#[tokio::test]
+async fn quarantine_review_requires_authentication() {
+    // Arrange
+    let app = spawn_app_quarantining_after(2, 100).await;
+
+    // Act
+    let list = reqwest::get(format!("{}/admin/quarantine", app.address))
+        .await
+        .unwrap();
+    let release = reqwest::Client::new()
+        .post(format!(
+            "{}/admin/quarantine/{}/release",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(list.status().as_u16(), 401);
+    assert_eq!(release.status().as_u16(), 401);
+}
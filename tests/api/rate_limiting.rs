@@ -0,0 +1,117 @@
+use crate::helpers::{TestApp, spawn_app_with_configuration};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn spawn_app_limited_to(burst: u32) -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.application.rate_limit.burst = burst;
+        c.application.rate_limit.refill_interval = Duration::from_secs(60);
+    })
+    .await
+}
+
+#[tokio::test]
+async fn subscribing_too_often_is_rejected_with_a_429() {
+    // Arrange
+    let app = spawn_app_limited_to(2).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    for body in [
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "name=tolkien&email=tolkien%40gmail.com",
+    ] {
+        assert_eq!(app.post_subscriptions(body).await.status().as_u16(), 200);
+    }
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=victim%40gmail.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    let victims = sqlx::query!("SELECT id FROM subscriptions WHERE email = 'victim@gmail.com'")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(victims.is_empty());
+}
+
+#[tokio::test]
+async fn confirming_shares_the_limit_of_subscribing() {
+    // Arrange
+    let app = spawn_app_limited_to(1).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=someToken",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn guessing_admin_passwords_is_limited() {
+    // Arrange
+    let app = spawn_app_limited_to(1).await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let log_in = |password: &'static str| {
+        client
+            .post(format!("{}/login", app.admin_address))
+            .form(&[
+                ("username", app.test_user.username.as_str()),
+                ("password", password),
+            ])
+            .send()
+    };
+    assert_eq!(log_in("first-guess").await.unwrap().status().as_u16(), 401);
+
+    // Act
+    let response = log_in("second-guess").await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn other_endpoints_are_not_limited() {
+    // Arrange
+    let app = spawn_app_limited_to(1).await;
+    app.post_subscriptions("name=le%20guin&email=%40gmail.com")
+        .await;
+
+    // Act
+    let response = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,429 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+/// Deliver one issue to every confirmed subscriber, then pretend they all
+/// subscribed long enough ago to be considered inactive.
+async fn deliver_an_issue_to_long_time_subscribers(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = now() - interval '120 days'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+}
+
+async fn create_another_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia_butler@gmail.com', 'octavia butler', now(), 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn start_campaign(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/reengagement_campaigns", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn complete_campaign(app: &TestApp, campaign_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!(
+            "{}/admin/reengagement_campaigns/{}/complete",
+            app.address, campaign_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn get_reengagement_link(app: &TestApp, email_request: &wiremock::Request) -> reqwest::Url {
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    let link = linkify::LinkFinder::new()
+        .links(&email.text)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains("/subscriptions/reengage"))
+        .expect("No re-engagement link in the email body");
+    let mut link = reqwest::Url::parse(&link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+#[tokio::test]
+async fn subscribers_without_engagement_are_flagged_as_inactive() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Act
+    let engagement: serde_json::Value = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/subscribers/{}/engagement",
+            app.address, subscriber_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(engagement["deliveries_90d"], 1);
+    assert_eq!(engagement["engaged_issues_90d"], 0);
+    assert_eq!(engagement["score"], 0);
+    assert_eq!(engagement["inactive_90d"], true);
+}
+
+#[tokio::test]
+async fn engagement_of_an_unknown_subscriber_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/subscribers/{}/engagement",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletters_can_target_the_inactive_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+    create_another_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "We miss you",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment": { "engagement": "inactive_90d" },
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["to"][0]["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn a_reengagement_campaign_emails_inactive_subscribers_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+    create_another_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = start_campaign(&app, serde_json::json!({})).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["recipients"], 1);
+}
+
+#[tokio::test]
+async fn a_campaign_cannot_be_completed_before_the_grace_period_ends() {
+    // Arrange
+    let app = spawn_app().await;
+    let response = start_campaign(&app, serde_json::json!({"grace_period_days": 7})).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let campaign_id = body["campaign_id"].as_str().unwrap();
+
+    // Act
+    let response = complete_campaign(&app, campaign_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn completing_an_unknown_campaign_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = complete_campaign(&app, &Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn completing_a_campaign_unsubscribes_non_responders_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let other_subscriber_id = create_another_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = start_campaign(&app, serde_json::json!({"grace_period_days": 0})).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let campaign_id = body["campaign_id"].as_str().unwrap();
+    assert_eq!(body["recipients"], 2);
+
+    // The other subscriber clicks on the link they received.
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let other_email_request = email_requests
+        .iter()
+        .rev()
+        .find(|r| {
+            let email: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            email["to"][0]["email"] == "octavia_butler@gmail.com"
+        })
+        .unwrap();
+    let link = get_reengagement_link(&app, other_email_request);
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = complete_campaign(&app, campaign_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["unsubscribed"], 1);
+    let statuses = sqlx::query!("SELECT id, status FROM subscriptions")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    for subscriber in statuses {
+        if subscriber.id == other_subscriber_id {
+            assert_eq!(subscriber.status, "confirmed");
+        } else {
+            assert_eq!(subscriber.status, "unsubscribed");
+        }
+    }
+
+    // A completed campaign cannot be completed again.
+    let response = complete_campaign(&app, campaign_id).await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn completing_a_campaign_records_the_unsubscribe_and_drops_queued_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = start_campaign(&app, serde_json::json!({"grace_period_days": 0})).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let campaign_id = body["campaign_id"].as_str().unwrap();
+
+    // A delivery still queued for the subscriber, leased by a worker that
+    // went away.
+    sqlx::query!(
+        r#"
+        INSERT INTO delivery_tasks
+            (newsletter_issue_id, subscriber_id, enqueued_at, claimed_by, lease_expires_at)
+        SELECT i.newsletter_issue_id, s.id, now(), gen_random_uuid(), now() + interval '1 hour'
+        FROM newsletter_issues i, subscriptions s
+        "#,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = complete_campaign(&app, campaign_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "unsubscribed");
+    assert!(subscriber.unsubscribed_at.is_some());
+    let queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM delivery_tasks"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[tokio::test]
+async fn reengagement_with_an_unknown_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/reengage?reengagement_token=abcdef",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn recipients_left_out_by_a_failed_send_are_emailed_on_resume() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+    let failing_guard = Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = start_campaign(&app, serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 500);
+    drop(failing_guard);
+    let campaign_id = sqlx::query_scalar!("SELECT campaign_id FROM reengagement_campaigns")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/admin/reengagement_campaigns/{}/resume",
+            app.address, campaign_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["emailed"], 1);
+    let pending = sqlx::query_scalar!(
+        "SELECT count(*) FROM reengagement_recipients WHERE emailed_at IS NULL"
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(pending, Some(0));
+}
+
+#[tokio::test]
+async fn reengagement_emails_keep_the_provider_message_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    deliver_an_issue_to_long_time_subscribers(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true,
+            "message_ids": ["reengagement-message"],
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = start_campaign(&app, serde_json::json!({})).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let message_id = sqlx::query_scalar!("SELECT provider_message_id FROM reengagement_recipients")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(message_id.as_deref(), Some("reengagement-message"));
+}
@@ -0,0 +1,107 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, get_subscriber_id, publish_issue, spawn_app,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::routes::subscriptions_unsubscribe::unsubscribe_link;
+use zero2prod::signing::UrlSigner;
+
+async fn compare_issues(app: &TestApp, issues: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/reports/compare", app.admin_address))
+        .query(&[("issues", issues)])
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn issues_are_compared_side_by_side_in_the_requested_order() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let first = publish_issue(&app, "First issue").await;
+    app.wait_for_deliveries().await;
+    let events = serde_json::json!({
+        "events": [{
+            "event": "bounce",
+            "email": "ursula_le_guin@gmail.com",
+            "event_id": "event-1",
+            "timestamp": chrono::Utc::now().timestamp() + 1,
+        }]
+    });
+    app.post_email_events("test-webhook-token", events)
+        .await
+        .error_for_status()
+        .unwrap();
+    let second = publish_issue(&app, "Second issue").await;
+    app.wait_for_deliveries().await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let signer = UrlSigner::new(app.configuration.application.hmac_secret.clone());
+    reqwest::Client::new()
+        .post(unsubscribe_link(&app.address, &signer, subscriber_id))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = compare_issues(&app, &format!("{},{}", second, first)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    let (second, first) = (&report["issues"][0], &report["issues"][1]);
+    assert_eq!(second["title"], "Second issue");
+    assert_eq!(second["deliveries"], 1);
+    assert_eq!(second["bounce_rate"], 0.0);
+    assert_eq!(second["unsubscribe_rate"], 1.0);
+    assert_eq!(first["title"], "First issue");
+    assert_eq!(first["bounce_rate"], 1.0);
+    assert_eq!(first["unsubscribe_rate"], 0.0);
+    assert_eq!(first["open_rate"], 0.0);
+    assert_eq!(first["click_rate"], 0.0);
+    assert!(first["send_duration_seconds"].as_f64().unwrap() >= 0.0);
+}
+
+#[tokio::test]
+async fn unknown_or_invalid_issues_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let unknown = compare_issues(&app, &Uuid::new_v4().to_string()).await;
+    let invalid = compare_issues(&app, "not-an-id").await;
+    let empty = compare_issues(&app, "").await;
+
+    // Assert
+    assert_eq!(unknown.status().as_u16(), 404);
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(empty.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn comparing_issues_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/admin/reports/compare?issues={}",
+        app.admin_address,
+        Uuid::new_v4()
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,64 @@
+use crate::helpers::spawn_app;
+use uuid::Uuid;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn the_request_id_of_the_caller_is_echoed() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", app.address))
+        .header("X-Request-Id", "upstream-42")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.headers()["X-Request-Id"], "upstream-42");
+}
+
+#[tokio::test]
+async fn a_request_id_is_generated_when_missing() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let request_id = response.headers()["X-Request-Id"].to_str().unwrap();
+    assert!(Uuid::parse_str(request_id).is_ok());
+}
+
+#[tokio::test]
+async fn the_request_id_is_forwarded_to_the_email_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .and(header("X-Request-Id", "upstream-42"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "upstream-42")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    // Mock verifies on Drop that the request carried the id
+}
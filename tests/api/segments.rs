@@ -0,0 +1,418 @@
+use crate::helpers::{TestApp, admin_request, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+async fn insert_confirmed_subscriber(app: &TestApp, email: &str, tags: &[&str]) {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'a reader', now(), 'confirmed')
+        "#,
+        subscriber_id,
+        email,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    for tag in tags {
+        sqlx::query!(
+            "INSERT INTO subscriber_tags (subscriber_id, tag) VALUES ($1, $2)",
+            subscriber_id,
+            tag,
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+}
+
+async fn create_segment(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    admin_request(app, reqwest::Method::POST, "/admin/segments")
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_json(app: &TestApp, path: &str) -> serde_json::Value {
+    admin_request(app, reqwest::Method::GET, path)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn segments_can_be_created_listed_updated_and_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Create
+    let response = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rustaceans", "filter": { "tags": { "all": ["rust"] } } }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let segment: serde_json::Value = response.json().await.unwrap();
+    let segment_id = segment["segment_id"].as_str().unwrap().to_owned();
+
+    // Act - Part 2 - List
+    let list = get_json(&app, "/admin/segments").await;
+    assert_eq!(list["segments"][0]["name"], "Rustaceans");
+    assert_eq!(
+        list["segments"][0]["filter"],
+        serde_json::json!({ "tags": { "all": ["rust"] } })
+    );
+
+    // Act - Part 3 - Update
+    let response = admin_request(
+        &app,
+        reqwest::Method::PUT,
+        &format!("/admin/segments/{}", segment_id),
+    )
+    .json(&serde_json::json!({ "name": "Gmail users", "filter": { "domain": "gmail.com" } }))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let segment = get_json(&app, &format!("/admin/segments/{}", segment_id)).await;
+    assert_eq!(segment["name"], "Gmail users");
+    assert_eq!(
+        segment["filter"],
+        serde_json::json!({ "domain": "gmail.com" })
+    );
+
+    // Act - Part 4 - Delete
+    let response = admin_request(
+        &app,
+        reqwest::Method::DELETE,
+        &format!("/admin/segments/{}", segment_id),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let response = admin_request(
+        &app,
+        reqwest::Method::GET,
+        &format!("/admin/segments/{}", segment_id),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Assert
+    let history = get_json(&app, &format!("/admin/segments/{}/changes", segment_id)).await;
+    let changes: Vec<_> = history["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| (c["change"].as_str().unwrap(), c["name"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            ("created", "Rustaceans"),
+            ("updated", "Gmail users"),
+            ("deleted", "Gmail users")
+        ]
+    );
+    assert_eq!(history["changes"][0]["changed_by"], app.test_user.username);
+}
+
+#[tokio::test]
+async fn segment_names_are_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({ "name": "Rustaceans", "filter": {} });
+    create_segment(&app, body.clone())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = create_segment(&app, body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "name": " ", "filter": {} }),
+            "a blank name",
+        ),
+        (
+            serde_json::json!({ "name": "Lapsed", "filter": { "status": ["lapsed"] } }),
+            "an unknown status",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = create_segment(&app, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with a 400 when the segment had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_recipients_of_a_segment_can_be_counted_before_and_after_saving_it() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "ursula@gmail.com", &["rust"]).await;
+    insert_confirmed_subscriber(&app, "octavia@gmail.com", &["rust"]).await;
+    insert_confirmed_subscriber(&app, "nk@gmail.com", &["go"]).await;
+    let filter = serde_json::json!({ "tags": { "any": ["rust"] } });
+
+    // Act - Part 1 - Preview
+    let preview: serde_json::Value =
+        admin_request(&app, reqwest::Method::POST, "/admin/segments/preview")
+            .json(&serde_json::json!({ "filter": filter }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+    assert_eq!(preview["recipients"], 2);
+
+    // Act - Part 2 - Saved segment
+    let segment: serde_json::Value = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rustaceans", "filter": filter }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let count = get_json(
+        &app,
+        &format!(
+            "/admin/segments/{}/recipients",
+            segment["segment_id"].as_str().unwrap()
+        ),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(count["recipients"], 2);
+}
+
+#[tokio::test]
+async fn newsletters_can_be_sent_to_a_saved_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "ursula@gmail.com", &["rust"]).await;
+    insert_confirmed_subscriber(&app, "nk@gmail.com", &["go"]).await;
+    let segment: serde_json::Value = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rustaceans", "filter": { "tags": { "any": ["rust"] } } }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment_id": segment["segment_id"],
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["queued_deliveries"], 1);
+    app.wait_for_deliveries().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email.to[0].email, "ursula@gmail.com");
+}
+
+#[tokio::test]
+async fn newsletters_sent_to_an_unknown_segment_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment_id": Uuid::new_v4(),
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_audience_of_an_issue_is_kept_as_it_was_when_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "ursula@gmail.com", &["rust"]).await;
+    insert_confirmed_subscriber(&app, "octavia@gmail.com", &["rust"]).await;
+    let segment: serde_json::Value = create_segment(
+        &app,
+        serde_json::json!({ "name": "Rustaceans", "filter": { "tags": { "any": ["rust"] } } }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let segment_id = segment["segment_id"].as_str().unwrap();
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body: serde_json::Value = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment_id": segment_id,
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Act - Subscribers and the segment change after the issue is sent
+    sqlx::query!("DELETE FROM subscriber_tags")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    admin_request(
+        &app,
+        reqwest::Method::DELETE,
+        &format!("/admin/segments/{}", segment_id),
+    )
+    .send()
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let audience = get_json(
+        &app,
+        &format!(
+            "/newsletters/{}/audience",
+            body["newsletter_issue_id"].as_str().unwrap()
+        ),
+    )
+    .await;
+    assert_eq!(audience["segment_id"], segment_id);
+    assert_eq!(
+        audience["filter"],
+        serde_json::json!({ "tags": { "any": ["rust"] } })
+    );
+    assert_eq!(audience["recipients"], 2);
+    assert_eq!(audience["delivered"], 2);
+    assert_eq!(audience["pending"], 0);
+}
+
+#[tokio::test]
+async fn newsletters_can_be_sent_to_subscribers_with_a_tag() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "ursula@gmail.com", &["rust"]).await;
+    insert_confirmed_subscriber(&app, "nk@gmail.com", &["go"]).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment": { "tags": [" rust "] },
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["queued_deliveries"], 1);
+    app.wait_for_deliveries().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email.to[0].email, "ursula@gmail.com");
+    let audience = get_json(
+        &app,
+        &format!(
+            "/newsletters/{}/audience",
+            body["newsletter_issue_id"].as_str().unwrap()
+        ),
+    )
+    .await;
+    assert_eq!(audience["tags"], serde_json::json!(["rust"]));
+}
+
+#[tokio::test]
+async fn newsletters_sent_to_invalid_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "segment": { "tags": ["  "] },
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
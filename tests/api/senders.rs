@@ -0,0 +1,189 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, sent_emails, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn add_sender(app: &TestApp, address: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/senders", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "address": address }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn verify_sender(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/senders/verify", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "token": token }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_senders(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/senders", app.admin_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Add `address` to the registry and verify it with the token it was sent.
+async fn add_verified_sender(app: &TestApp, address: &str) {
+    assert_eq!(add_sender(app, address).await.status().as_u16(), 202);
+    let email = sent_emails(app).await.pop().unwrap();
+    let token = email["text"]
+        .as_str()
+        .unwrap()
+        .split("token: ")
+        .nth(1)
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_owned();
+    assert_eq!(verify_sender(app, &token).await.status().as_u16(), 200);
+}
+
+fn issue_from(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "sender": { "email": email, "name": "Our newsletter" },
+    })
+}
+
+#[tokio::test]
+async fn senders_are_verified_through_a_token_emailed_to_them() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    add_verified_sender(&app, "News@Example.com").await;
+
+    // Assert
+    let email = sent_emails(&app).await.pop().unwrap();
+    assert_eq!(email["to"][0]["email"], "news@example.com");
+    let senders = get_senders(&app).await;
+    assert_eq!(senders["senders"][0]["address"], "news@example.com");
+    assert!(senders["senders"][0]["verified_at"].is_string());
+}
+
+#[tokio::test]
+async fn domains_are_verified_through_their_postmaster() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    add_verified_sender(&app, "example.com").await;
+    let response = app.post_newsletters(issue_from("news@example.com")).await;
+    app.wait_for_deliveries().await;
+
+    // Assert
+    let emails = sent_emails(&app).await;
+    assert!(
+        emails
+            .iter()
+            .any(|email| email["to"][0]["email"] == "postmaster@example.com")
+    );
+    assert_eq!(response.status().as_u16(), 202);
+    let issue = emails.last().unwrap();
+    assert_eq!(issue["from"]["email"], "news@example.com");
+    assert_eq!(issue["from"]["name"], "Our newsletter");
+}
+
+#[tokio::test]
+async fn issues_from_unverified_senders_are_rejected_with_a_422() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    assert_eq!(
+        add_sender(&app, "news@example.com").await.status().as_u16(),
+        202
+    );
+
+    // Act
+    let pending = app.post_newsletters(issue_from("news@example.com")).await;
+    let unknown = app.post_newsletters(issue_from("other@example.org")).await;
+
+    // Assert
+    assert_eq!(pending.status().as_u16(), 422);
+    assert_eq!(unknown.status().as_u16(), 422);
+    let issues = sqlx::query!("SELECT title FROM newsletter_issues")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn drafts_from_unverified_senders_cannot_be_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let response: serde_json::Value = app
+        .post_newsletter_draft(issue_from("news@example.com"))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .publish_newsletter_draft(response["newsletter_draft_id"].as_str().unwrap())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let drafts = app.get_newsletter_drafts().await;
+    assert_eq!(drafts["drafts"][0]["sender"]["email"], "news@example.com");
+}
+
+#[tokio::test]
+async fn invalid_or_verified_senders_cannot_be_added() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    add_verified_sender(&app, "news@example.com").await;
+
+    // Act
+    let invalid = add_sender(&app, "not a domain").await;
+    let verified = add_sender(&app, "news@example.com").await;
+    let unknown_token = verify_sender(&app, "not-a-token").await;
+
+    // Assert
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(verified.status().as_u16(), 409);
+    assert_eq!(unknown_token.status().as_u16(), 404);
+}
@@ -0,0 +1,62 @@
+use crate::helpers::spawn_app;
+use std::net::TcpListener;
+use zero2prod::Server;
+use zero2prod::configuration::ListenerSettings;
+use zero2prod::startup::get_connection_pool;
+
+#[tokio::test]
+async fn an_embedded_server_serves_on_the_listener_it_is_given_until_stopped() {
+    // Arrange
+    let app = spawn_app().await;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = Server::builder(app.configuration.clone())
+        .pg_pool(get_connection_pool(&app.configuration.database))
+        .listener(listener)
+        .build()
+        .await
+        .unwrap();
+    let address = server.local_addr();
+    let handle = server.handle();
+    let running = tokio::spawn(server.run());
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/health_check", address))
+        .send()
+        .await
+        .unwrap();
+    handle.stop();
+
+    // Assert
+    assert!(response.status().is_success());
+    tokio::time::timeout(std::time::Duration::from_secs(10), running)
+        .await
+        .expect("The server did not stop")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn an_embedded_server_failing_to_bind_leaves_nothing_running() {
+    // Arrange
+    let app = spawn_app().await;
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut configuration = app.configuration.clone();
+    configuration.application.admin_listener = Some(ListenerSettings {
+        host: "127.0.0.1".into(),
+        port: taken.local_addr().unwrap().port(),
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    // Act
+    let outcome = Server::builder(configuration)
+        .pg_pool(get_connection_pool(&app.configuration.database))
+        .listener(listener)
+        .build()
+        .await;
+
+    // Assert
+    assert!(outcome.is_err());
+    assert!(TcpListener::bind(address).is_ok());
+}
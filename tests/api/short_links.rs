@@ -0,0 +1,239 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+const BROWSER_USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64; rv:126.0) Gecko/20100101 Firefox/126.0";
+
+fn no_redirect_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(BROWSER_USER_AGENT)
+        .build()
+        .unwrap()
+}
+
+/// Pretend the issue went out a while ago, so that clicks are not mistaken
+/// for link prefetching.
+async fn backdate_issues(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET published_at = now() - interval '1 hour'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+}
+
+/// Publish an issue linking to `destination` and return the issue id
+/// together with the short link found in the delivered plain text body.
+async fn publish_issue_with_link(app: &TestApp, destination: &str) -> (String, reqwest::Url) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": format!("Read the post at {}", destination),
+                "html": format!(r#"<p>Read the <a href="{}">post</a></p>"#, destination),
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(!email.text.contains(destination));
+    assert!(!email.html.contains(destination));
+    let short_link = linkify::LinkFinder::new()
+        .links(&email.text)
+        .next()
+        .expect("No short link in the newsletter body")
+        .as_str()
+        .to_owned();
+    let mut short_link = reqwest::Url::parse(&short_link).unwrap();
+    assert!(short_link.path().starts_with("/l/"));
+    short_link.set_port(Some(app.port)).unwrap();
+    (newsletter_issue_id, short_link)
+}
+
+#[tokio::test]
+async fn short_links_redirect_to_the_original_destination() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, short_link) = publish_issue_with_link(&app, "https://example.com/post").await;
+
+    // Act
+    let response = no_redirect_client().get(short_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(response.headers()["Location"], "https://example.com/post");
+}
+
+#[tokio::test]
+async fn clicks_are_reported_per_newsletter_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let (newsletter_issue_id, short_link) =
+        publish_issue_with_link(&app, "https://example.com/post").await;
+    backdate_issues(&app).await;
+    for _ in 0..2 {
+        no_redirect_client()
+            .get(short_link.clone())
+            .send()
+            .await
+            .unwrap();
+    }
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/links",
+            app.address, newsletter_issue_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats[0]["destination_url"], "https://example.com/post");
+    assert_eq!(stats[0]["clicks"], 2);
+    assert_eq!(stats[0]["flagged_clicks"], 0);
+}
+
+#[tokio::test]
+async fn clicks_from_known_bots_are_flagged_instead_of_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    let (newsletter_issue_id, short_link) =
+        publish_issue_with_link(&app, "https://example.com/post").await;
+    backdate_issues(&app).await;
+
+    // Act
+    let response = no_redirect_client()
+        .get(short_link)
+        .header("User-Agent", "Barracuda Sentinel (EE)")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    // Scanners still get redirected, they are just not counted.
+    assert_eq!(response.status().as_u16(), 302);
+    let stats: serde_json::Value = reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/links",
+            app.address, newsletter_issue_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats[0]["clicks"], 0);
+    assert_eq!(stats[0]["flagged_clicks"], 1);
+}
+
+#[tokio::test]
+async fn clicks_right_after_sending_are_flagged_as_prefetches() {
+    // Arrange
+    let app = spawn_app().await;
+    let (newsletter_issue_id, short_link) =
+        publish_issue_with_link(&app, "https://example.com/post").await;
+
+    // Act
+    no_redirect_client().get(short_link).send().await.unwrap();
+
+    // Assert
+    let bot_reason = sqlx::query_scalar!("SELECT bot_reason FROM short_link_clicks")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(bot_reason.as_deref(), Some("prefetch"));
+    let stats: serde_json::Value = reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/engagement",
+            app.address, newsletter_issue_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["clicks"], 0);
+    assert_eq!(stats["flagged_clicks"], 1);
+}
+
+#[tokio::test]
+async fn link_stats_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/links",
+            app.address,
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_short_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, short_link) = publish_issue_with_link(&app, "https://example.com/post").await;
+    sqlx::query!("UPDATE short_links SET expires_at = now() - interval '1 day'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = no_redirect_client().get(short_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn unknown_short_links_are_rejected_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = no_redirect_client()
+        .get(format!("{}/l/unknown", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
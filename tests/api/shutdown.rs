@@ -0,0 +1,110 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app_with_configuration};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Subscribe while the email provider takes `delay` to answer, returning
+/// once the request is being handled.
+async fn start_slow_subscription(
+    app: &TestApp,
+    delay: Duration,
+) -> tokio::task::JoinHandle<reqwest::Result<reqwest::Response>> {
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(delay))
+        .mount(&app.email_server)
+        .await;
+    let request = reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send();
+    let request = tokio::spawn(request);
+    while app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty()
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    request
+}
+
+#[tokio::test]
+async fn in_flight_requests_complete_during_a_shutdown() {
+    // Arrange
+    let mut app = spawn_app_with_configuration(|c| {
+        c.application.shutdown_timeout = Duration::from_secs(10);
+    })
+    .await;
+    let request = start_slow_subscription(&app, Duration::from_millis(500)).await;
+
+    // Act
+    app.shut_down().await.unwrap();
+
+    // Assert
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let new_request = reqwest::get(format!("{}/health_check", app.address)).await;
+    assert!(new_request.is_err());
+}
+
+#[tokio::test]
+async fn requests_outlasting_the_shutdown_timeout_are_cut() {
+    // Arrange
+    let mut app = spawn_app_with_configuration(|c| {
+        c.application.shutdown_timeout = Duration::from_secs(1);
+    })
+    .await;
+    let request = start_slow_subscription(&app, Duration::from_secs(30)).await;
+
+    // Act
+    let started_at = std::time::Instant::now();
+    app.shut_down().await.unwrap();
+
+    // Assert
+    assert!(started_at.elapsed() < Duration::from_secs(15));
+    assert!(request.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn background_runs_in_progress_complete_during_a_shutdown() {
+    // Arrange
+    let mut app = spawn_app_with_configuration(|c| {
+        c.application.shutdown_timeout = Duration::from_secs(1);
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    while app.email_server.received_requests().await.unwrap().len() == sent_before {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Act
+    app.shut_down().await.unwrap();
+
+    // Assert
+    let pending = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM delivery_tasks"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending, 0);
+}
@@ -0,0 +1,300 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, create_draft, spawn_app_with_configuration,
+};
+use chrono::{Duration, Timelike, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::SlackSettings;
+use zero2prod::draft_scheduler::DraftScheduler;
+use zero2prod::encryption::FieldCipher;
+use zero2prod::startup::Application;
+use zero2prod::templates::EmailTemplates;
+use zero2prod::throttling::DeliveryThrottle;
+use zero2prod::web_pages::PageFetcher;
+
+const SIGNING_SECRET: &str = "slack-signing-secret";
+
+/// Commands are sent as `U123`.
+async fn spawn_app_allowing(user_id: &str) -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.slack = Some(SlackSettings {
+            signing_secret: SIGNING_SECRET.to_string().into(),
+            allowed_users: vec![user_id.into()],
+        })
+    })
+    .await
+}
+
+async fn spawn_app() -> TestApp {
+    spawn_app_allowing("U123").await
+}
+
+fn draft_scheduler(app: &TestApp) -> DraftScheduler {
+    DraftScheduler::build(
+        &app.configuration,
+        app.connection_pool.clone(),
+        Arc::new(app.configuration.email_client.client().unwrap()),
+        Arc::new(DeliveryThrottle::new(
+            &app.configuration.delivery_throttling,
+        )),
+        EmailTemplates::new(app.configuration.email_templates.clone()),
+        FieldCipher::new(app.configuration.encryption.as_ref()),
+        PageFetcher::new(&app.configuration.web_pages),
+    )
+}
+
+async fn post_slack_command(app: &TestApp, text: &str, signing_secret: &str) -> reqwest::Response {
+    let body = format!(
+        "command=%2Fnewsletter&user_id=U123&user_name=ursula&text={}",
+        urlencoding(text)
+    );
+    let timestamp = Utc::now().timestamp().to_string();
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_secret.as_bytes()).unwrap();
+    mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+    let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+    reqwest::Client::new()
+        .post(format!("{}/slack/commands", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Slack-Request-Timestamp", timestamp)
+        .header("X-Slack-Signature", signature)
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn urlencoding(text: &str) -> String {
+    url::form_urlencoded::byte_serialize(text.as_bytes()).collect()
+}
+
+async fn get_issue_of_draft(app: &TestApp, draft_id: Uuid) -> Option<Uuid> {
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_drafts WHERE newsletter_draft_id = $1",
+        draft_id,
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn commands_with_an_invalid_signature_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_slack_command(&app, "status", "another-secret").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn status_lists_the_drafts_waiting() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let response = post_slack_command(&app, "status", SIGNING_SECRET).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let reply: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reply["response_type"], "ephemeral");
+    let text = reply["text"].as_str().unwrap();
+    assert!(text.starts_with("Deliveries are running"));
+    assert!(text.contains(&format!("`{}` Newsletter title", draft_id)));
+}
+
+#[tokio::test]
+async fn approved_drafts_are_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let response = post_slack_command(&app, &format!("approve {}", draft_id), SIGNING_SECRET).await;
+
+    // Assert
+    let reply: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reply["response_type"], "in_channel");
+    for _ in 0..50 {
+        if get_issue_of_draft(&app, draft_id).await.is_some() {
+            app.wait_for_deliveries().await;
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("The approved draft was not published");
+}
+
+#[tokio::test]
+async fn scheduled_drafts_are_published_once_due() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+    let at = Utc::now() + Duration::hours(1);
+    let scheduler = draft_scheduler(&app);
+
+    // Act
+    let response = post_slack_command(
+        &app,
+        &format!("schedule {} {}", draft_id, at.to_rfc3339()),
+        SIGNING_SECRET,
+    )
+    .await;
+
+    // Assert
+    let reply: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reply["response_type"], "in_channel");
+    assert!(scheduler.publish_due(Utc::now()).await.unwrap().is_empty());
+    let published = scheduler
+        .publish_due(at + Duration::seconds(1))
+        .await
+        .unwrap();
+    assert_eq!(published.len(), 1);
+    assert_eq!(get_issue_of_draft(&app, draft_id).await, Some(published[0]));
+}
+
+#[tokio::test]
+async fn unknown_drafts_are_reported_to_the_slack_user() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response =
+        post_slack_command(&app, &format!("approve {}", Uuid::new_v4()), SIGNING_SECRET).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let reply: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reply["response_type"], "ephemeral");
+    assert_eq!(
+        reply["text"],
+        "There is no draft associated with the provided id."
+    );
+}
+
+#[tokio::test]
+async fn failing_scheduled_drafts_are_retried_later_then_unscheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    let response: serde_json::Value = app
+        .post_newsletter_draft(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "sender": { "email": "news@example.com", "name": "Unverified" },
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let draft_id: Uuid = response["newsletter_draft_id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    // Whole seconds, Postgres keeping microseconds only.
+    let at = (Utc::now() + Duration::hours(1))
+        .with_nanosecond(0)
+        .unwrap();
+    post_slack_command(
+        &app,
+        &format!("schedule {} {}", draft_id, at.to_rfc3339()),
+        SIGNING_SECRET,
+    )
+    .await;
+    let scheduler = draft_scheduler(&app);
+
+    // Act
+    let mut now = at;
+    let mut delays = Vec::new();
+    for _ in 0..5 {
+        assert!(scheduler.publish_due(now).await.is_err());
+        let draft = sqlx::query!(
+            "SELECT scheduled_at, publish_error FROM newsletter_drafts WHERE newsletter_draft_id = $1",
+            draft_id,
+        )
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+        assert!(draft.publish_error.is_some());
+        let Some(retry_at) = draft.scheduled_at else {
+            break;
+        };
+        assert!(
+            scheduler
+                .publish_due(retry_at - Duration::seconds(1))
+                .await
+                .is_ok()
+        );
+        delays.push((retry_at - now).num_seconds());
+        now = retry_at;
+    }
+
+    // Assert
+    assert_eq!(delays, [60, 120, 240, 480]);
+    assert!(
+        scheduler
+            .publish_due(now + Duration::days(1))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(get_issue_of_draft(&app, draft_id).await, None);
+}
+
+#[tokio::test]
+async fn only_the_allowed_users_can_run_commands() {
+    // Arrange
+    let app = spawn_app_allowing("U999").await;
+    let draft_id = create_draft(&app).await;
+
+    // Act
+    let response = post_slack_command(&app, &format!("approve {}", draft_id), SIGNING_SECRET).await;
+
+    // Assert
+    let reply: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reply["response_type"], "ephemeral");
+    let scheduled_at = sqlx::query_scalar!(
+        "SELECT scheduled_at FROM newsletter_drafts WHERE newsletter_draft_id = $1",
+        draft_id,
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert!(scheduled_at.is_none());
+}
+
+#[tokio::test]
+async fn slack_commands_cannot_be_enabled_without_allowed_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = app.configuration.clone();
+    configuration.slack = Some(SlackSettings {
+        signing_secret: SIGNING_SECRET.to_string().into(),
+        allowed_users: vec![],
+    });
+
+    // Act
+    let outcome = Application::build(configuration).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
@@ -0,0 +1,183 @@
+use crate::helpers::{TestApp, create_unconfirmed_subscriber, get_subscriber_id, spawn_app};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use zero2prod::imports::import_file_key;
+use zero2prod::signing::UrlSigner;
+
+async fn delete_subscriber(app: &TestApp, subscriber_id: Uuid) -> reqwest::Response {
+    reqwest::Client::new()
+        .delete(format!(
+            "{}/admin/subscribers/{}",
+            app.address, subscriber_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn assert_nothing_is_left(app: &TestApp) {
+    let subscriptions = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    let tokens = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    let confirmation_emails =
+        sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM confirmation_emails"#)
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    assert_eq!((subscriptions, tokens, confirmation_emails), (0, 0, 0));
+}
+
+#[tokio::test]
+async fn admins_can_erase_a_subscriber_and_their_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    // Act
+    let response = delete_subscriber(&app, subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_nothing_is_left(&app).await;
+    let erasure = sqlx::query!("SELECT subscriber_id, erased_by FROM subscriber_erasures")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.subscriber_id, subscriber_id);
+    assert!(erasure.erased_by.is_some());
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_deletes_the_import_files_listing_them() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let blob_store = app.blob_store();
+    let (listing, other) = (Uuid::new_v4(), Uuid::new_v4());
+    for (import_id, file) in [
+        (listing, "email,name\nUrsula_Le_Guin@gmail.com,Ursula\n"),
+        (other, "email,name\nursula@example.com,Ursula\n"),
+    ] {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_imports (import_id, status, total_rows, created_at)
+            VALUES ($1, 'completed', 1, now())
+            "#,
+            import_id,
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+        blob_store
+            .put(&import_file_key(import_id), file.as_bytes().to_vec())
+            .await
+            .unwrap();
+    }
+
+    // Act
+    let response = delete_subscriber(&app, subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(!blob_store.exists(&import_file_key(listing)).await.unwrap());
+    assert!(blob_store.exists(&import_file_key(other)).await.unwrap());
+}
+
+#[tokio::test]
+async fn erasing_an_unknown_subscriber_returns_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = delete_subscriber(&app, Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let erasures = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriber_erasures"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(erasures, 0);
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .delete(format!(
+            "{}/admin/subscribers/{}",
+            app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscriber_id(&app).await, subscriber_id);
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_themselves_with_their_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let signer = UrlSigner::new(app.configuration.application.hmac_secret.clone());
+    let token = signer.sign(
+        "subscriber-session",
+        &subscriber_id.to_string(),
+        Utc::now() + Duration::hours(1),
+    );
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/delete?token={}",
+            app.address, token
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_nothing_is_left(&app).await;
+    let erasure = sqlx::query!("SELECT subscriber_id, erased_by FROM subscriber_erasures")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(erasure.subscriber_id, subscriber_id);
+    assert_eq!(erasure.erased_by, None);
+}
+
+#[tokio::test]
+async fn self_service_erasure_requires_a_valid_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/delete?token=forged", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    get_subscriber_id(&app).await;
+}
@@ -0,0 +1,161 @@
+use crate::helpers::{TestApp, insert_subscriber, spawn_app};
+use chrono::{Duration, Utc};
+
+async fn list(app: &TestApp, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/admin/subscribers?{}", app.address, query))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn list_ok(app: &TestApp, query: &str) -> serde_json::Value {
+    let response = list(app, query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn subscribers_are_filtered_by_status_and_part_of_their_email() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@gmail.com",
+        "confirmed",
+        Utc::now() - Duration::days(3),
+        &[],
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "octavia@gmail.com",
+        "pending_confirmation",
+        Utc::now() - Duration::days(2),
+        &[],
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "nk@proton.me",
+        "confirmed",
+        Utc::now() - Duration::days(1),
+        &[],
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "under_score@proton.me",
+        "confirmed",
+        Utc::now() - Duration::days(0),
+        &[],
+    )
+    .await;
+
+    // Act
+    let confirmed = list_ok(&app, "status=confirmed").await;
+    let gmail = list_ok(&app, "email=GMAIL").await;
+    let underscore = list_ok(&app, "email=r_s").await;
+    let percent = list_ok(&app, "email=%25").await;
+
+    // Assert
+    assert_eq!(
+        emails(&confirmed),
+        ["under_score@proton.me", "nk@proton.me", "ursula@gmail.com"]
+    );
+    assert_eq!(confirmed["total"], 3);
+    assert_eq!(emails(&gmail), ["octavia@gmail.com", "ursula@gmail.com"]);
+    assert_eq!(emails(&underscore), ["under_score@proton.me"]);
+    assert!(emails(&percent).is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_are_paged_through_with_a_cursor_or_an_offset() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..5 {
+        insert_subscriber(
+            &app,
+            &format!("reader{}@gmail.com", i),
+            "confirmed",
+            Utc::now() - Duration::days(i),
+            &[],
+        )
+        .await;
+    }
+
+    // Act
+    let first = list_ok(&app, "limit=2").await;
+    let second = list_ok(
+        &app,
+        &format!("limit=2&cursor={}", first["next_cursor"].as_str().unwrap()),
+    )
+    .await;
+    let last = list_ok(
+        &app,
+        &format!("limit=2&cursor={}", second["next_cursor"].as_str().unwrap()),
+    )
+    .await;
+    let by_offset = list_ok(&app, "limit=2&offset=2").await;
+
+    // Assert
+    assert_eq!(emails(&first), ["reader0@gmail.com", "reader1@gmail.com"]);
+    assert_eq!(emails(&second), ["reader2@gmail.com", "reader3@gmail.com"]);
+    assert_eq!(emails(&last), ["reader4@gmail.com"]);
+    assert!(last["next_cursor"].is_null());
+    assert_eq!(emails(&by_offset), emails(&second));
+    assert_eq!(first["total"], 5);
+}
+
+#[tokio::test]
+async fn invalid_listings_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        ("status=subscribed", "an unknown status"),
+        ("limit=0", "an empty page"),
+        ("limit=1000", "a page too large"),
+        ("offset=-1", "a negative offset"),
+        ("cursor=garbage", "a made up cursor"),
+        ("offset=2&cursor=garbage", "both an offset and a cursor"),
+    ];
+
+    for (query, description) in test_cases {
+        // Act
+        let response = list(&app, query).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with a 400 when the listing had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn listing_subscribers_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/subscribers", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,173 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use chrono::Utc;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+async fn post_login(app: &TestApp, body: &'static str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions/login", app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// The magic link is emailed in the background, once the response is sent.
+async fn wait_for_magic_link_email(app: &TestApp) -> wiremock::Request {
+    let subject = &app.configuration.email_templates.magic_link.subject;
+    for _ in 0..50 {
+        let magic_link_email = app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|r| {
+                let email: SendEmailRequest = serde_json::from_slice(&r.body).unwrap();
+                &email.subject == subject
+            });
+        if let Some(request) = magic_link_email {
+            return request;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("The magic link was not emailed");
+}
+
+#[tokio::test]
+async fn the_login_form_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/subscriptions/login", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<form action="/subscriptions/login" method="post">"#));
+}
+
+#[tokio::test]
+async fn subscribers_receive_a_short_lived_link_to_their_subscription() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_login(&app, "email=ursula_le_guin%40gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = wait_for_magic_link_email(&app).await;
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(&email.text)
+        .map(|l| l.as_str().to_owned())
+        .collect();
+    assert_eq!(links.len(), 1);
+    let mut magic_link = reqwest::Url::parse(&links[0]).unwrap();
+    assert_eq!(magic_link.path(), "/subscriptions/status");
+
+    let token = magic_link.query_pairs().next().unwrap().1.into_owned();
+    let expires_at: i64 = token.rsplit('.').nth(1).unwrap().parse().unwrap();
+    assert!(expires_at <= (Utc::now() + chrono::Duration::minutes(15)).timestamp());
+
+    magic_link.set_port(Some(app.port)).unwrap();
+    let response = reqwest::Client::new()
+        .get(magic_link)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_response_without_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let known = post_login(&app, "email=ursula_le_guin%40gmail.com")
+        .await
+        .text()
+        .await
+        .unwrap();
+    wait_for_magic_link_email(&app).await;
+
+    // Act
+    let response = post_login(&app, "email=someone_else%40gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), known);
+}
+
+#[tokio::test]
+async fn the_response_does_not_wait_for_the_email_to_be_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(3)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let started = std::time::Instant::now();
+    let response = post_login(&app, "email=ursula_le_guin%40gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    wait_for_magic_link_email(&app).await;
+}
+
+#[tokio::test]
+async fn failing_to_send_the_link_is_not_disclosed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_login(&app, "email=ursula_le_guin%40gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn invalid_addresses_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_login(&app, "email=definitely-not-an-email").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,358 @@
+use crate::helpers::{TestApp, insert_subscriber, spawn_app};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+async fn get_duplicates(app: &TestApp, query: &str) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!(
+            "{}/admin/subscribers/duplicates{}",
+            app.address, query
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn merge(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/merge", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn group_emails(duplicates: &serde_json::Value) -> Vec<(String, Vec<String>)> {
+    duplicates["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|g| {
+            (
+                g["matched_on"].as_str().unwrap().to_owned(),
+                g["subscribers"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|s| s["email"].as_str().unwrap().to_owned())
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn duplicates_are_found_by_normalized_and_fuzzy_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    insert_subscriber(
+        &app,
+        "ursula@gmail.com",
+        "confirmed",
+        now - Duration::days(2),
+        &[],
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "Ursula@GMail.com",
+        "pending_confirmation",
+        now - Duration::days(1),
+        &[],
+    )
+    .await;
+    insert_subscriber(&app, "ursu.la+news@gmail.com", "confirmed", now, &[]).await;
+    insert_subscriber(&app, "octavia@example.com", "confirmed", now, &[]).await;
+
+    // Act
+    let exact = get_duplicates(&app, "").await;
+    let fuzzy = get_duplicates(&app, "?fuzzy=true").await;
+
+    // Assert
+    assert_eq!(
+        group_emails(&exact),
+        [(
+            "email".to_owned(),
+            vec!["ursula@gmail.com".to_owned(), "Ursula@GMail.com".to_owned()]
+        )]
+    );
+    assert_eq!(
+        group_emails(&fuzzy),
+        [(
+            "fuzzy".to_owned(),
+            vec![
+                "ursula@gmail.com".to_owned(),
+                "Ursula@GMail.com".to_owned(),
+                "ursu.la+news@gmail.com".to_owned()
+            ]
+        )]
+    );
+}
+
+#[tokio::test]
+async fn merged_subscribers_keep_the_tags_history_and_preferences_of_their_duplicates() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    let kept = insert_subscriber(
+        &app,
+        "ursula@gmail.com",
+        "pending_confirmation",
+        now,
+        &["rust"],
+    )
+    .await;
+    let duplicate = insert_subscriber(
+        &app,
+        "Ursula@GMail.com",
+        "confirmed",
+        now - Duration::days(30),
+        &["beta"],
+    )
+    .await;
+    sqlx::query!(
+        "UPDATE subscriptions SET do_not_track = TRUE WHERE id = $1",
+        duplicate
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at
+        )
+        VALUES ($1, 'An issue', 'text', 'html', now())
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (
+            newsletter_issue_id, subscriber_id, delivered_at, provider_message_id
+        )
+        VALUES ($1, $2, now(), 'message-1')
+        "#,
+        newsletter_issue_id,
+        duplicate,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO delivery_events (
+            delivery_event_id, newsletter_issue_id, subscriber_id, event_type, occurred_at,
+            received_at
+        )
+        VALUES ($1, $2, $3, 'delivered', now(), now())
+        "#,
+        Uuid::new_v4(),
+        newsletter_issue_id,
+        duplicate,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = merge(
+        &app,
+        serde_json::json!({ "subscriber_id": kept, "duplicates": [duplicate] }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let merged: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(merged["email"], "ursula@gmail.com");
+    assert_eq!(merged["status"], "confirmed");
+    assert_eq!(merged["merged"], 1);
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT
+            s.status, s.subscribed_at, s.do_not_track,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag
+            ) AS "tags!",
+            (SELECT COUNT(*) FROM subscriptions) AS "subscribers!"
+        FROM subscriptions s
+        WHERE s.id = $1
+        "#,
+        kept,
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(subscriber.status, "confirmed");
+    assert!(subscriber.subscribed_at < now - Duration::days(29));
+    assert!(subscriber.do_not_track);
+    assert_eq!(subscriber.tags, ["beta", "rust"]);
+    assert_eq!(subscriber.subscribers, 1);
+    let delivery = sqlx::query!(
+        r#"
+        SELECT d.provider_message_id, (SELECT COUNT(*) FROM delivery_events) AS "events!"
+        FROM newsletter_deliveries d
+        WHERE d.subscriber_id = $1
+        "#,
+        kept,
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.provider_message_id.as_deref(), Some("message-1"));
+    assert_eq!(delivery.events, 1);
+    let merges = sqlx::query!("SELECT merged_subscriber_id, merged_email FROM subscriber_merges")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(merges.merged_subscriber_id, duplicate);
+    assert_eq!(merges.merged_email, "Ursula@GMail.com");
+}
+
+#[tokio::test]
+async fn an_unsubscribe_is_kept_when_merging() {
+    // Arrange
+    let app = spawn_app().await;
+    let kept = insert_subscriber(&app, "ursula@gmail.com", "confirmed", Utc::now(), &[]).await;
+    let duplicate =
+        insert_subscriber(&app, "URSULA@gmail.com", "unsubscribed", Utc::now(), &[]).await;
+    let unsubscribed_at = Utc::now() - Duration::days(3);
+    sqlx::query!(
+        "UPDATE subscriptions SET unsubscribed_at = $2 WHERE id = $1",
+        duplicate,
+        unsubscribed_at,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    // A delivery still queued for the kept subscriber, leased by a worker
+    // that went away.
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at
+        )
+        VALUES ($1, 'An issue', 'text', 'html', now())
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO delivery_tasks
+            (newsletter_issue_id, subscriber_id, enqueued_at, claimed_by, lease_expires_at)
+        VALUES ($1, $2, now(), $3, now() + interval '1 hour')
+        "#,
+        newsletter_issue_id,
+        kept,
+        Uuid::new_v4(),
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = merge(
+        &app,
+        serde_json::json!({ "subscriber_id": kept, "duplicates": [duplicate] }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let merged: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(merged["status"], "unsubscribed");
+    let queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM delivery_tasks"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+    let kept = sqlx::query!(
+        "SELECT status, unsubscribed_at FROM subscriptions WHERE id = $1",
+        kept,
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(kept.status, "unsubscribed");
+    assert_eq!(
+        kept.unsubscribed_at.map(|t| t.timestamp_micros()),
+        Some(unsubscribed_at.timestamp_micros())
+    );
+}
+
+#[tokio::test]
+async fn invalid_merges_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let kept = insert_subscriber(&app, "ursula@gmail.com", "confirmed", Utc::now(), &[]).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "subscriber_id": kept, "duplicates": [] }),
+            400,
+            "no duplicate",
+        ),
+        (
+            serde_json::json!({ "subscriber_id": kept, "duplicates": [kept] }),
+            400,
+            "the kept subscriber as a duplicate",
+        ),
+        (
+            serde_json::json!({ "subscriber_id": kept, "duplicates": [Uuid::new_v4()] }),
+            404,
+            "an unknown duplicate",
+        ),
+    ];
+
+    for (body, status, description) in test_cases {
+        // Act
+        let response = merge(&app, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The API did not fail with a {} when the merge had {}.",
+            status,
+            description
+        );
+    }
+    let subscribers = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count, 1);
+}
+
+#[tokio::test]
+async fn merging_subscribers_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/merge", app.address))
+        .json(&serde_json::json!({ "subscriber_id": Uuid::new_v4(), "duplicates": [] }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,198 @@
+use crate::helpers::{TestApp, insert_subscriber, spawn_app};
+use chrono::{Duration, Utc};
+
+async fn search(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/search", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn emails(results: &serde_json::Value) -> Vec<&str> {
+    results["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn subscribers_are_filtered_by_status_tags_date_and_domain() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    insert_subscriber(
+        &app,
+        "ursula@gmail.com",
+        "confirmed",
+        now - Duration::days(3),
+        &["rust", "beta"],
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "octavia@GMail.com",
+        "confirmed",
+        now - Duration::days(2),
+        &["rust", "alpha"],
+    )
+    .await;
+    insert_subscriber(&app, "nk@example.com", "confirmed", now, &["rust", "beta"]).await;
+    insert_subscriber(
+        &app,
+        "iain@gmail.com",
+        "unsubscribed",
+        now,
+        &["rust", "beta"],
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "ted@gmail.com",
+        "confirmed",
+        now - Duration::days(30),
+        &["rust", "beta"],
+    )
+    .await;
+
+    // Act
+    let response = search(
+        &app,
+        serde_json::json!({
+            "filter": {
+                "status": ["confirmed"],
+                "tags": { "all": ["rust"], "any": ["alpha", "beta"] },
+                "signed_up": { "after": now - Duration::days(7) },
+                "domain": "gmail.com"
+            }
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let results: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(emails(&results), ["octavia@GMail.com", "ursula@gmail.com"]);
+    assert_eq!(results["total"], 2);
+    assert_eq!(
+        results["subscribers"][1]["tags"],
+        serde_json::json!(["beta", "rust"])
+    );
+    assert_eq!(results["subscribers"][1]["name"], "a reader");
+}
+
+#[tokio::test]
+async fn results_are_paginated_newest_first() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    for i in 0..5 {
+        insert_subscriber(
+            &app,
+            &format!("reader{}@example.com", i),
+            "confirmed",
+            now - Duration::days(i),
+            &[],
+        )
+        .await;
+    }
+
+    // Act
+    let response = search(&app, serde_json::json!({ "page": 2, "page_size": 2 })).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let results: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        emails(&results),
+        ["reader2@example.com", "reader3@example.com"]
+    );
+    assert_eq!(results["page"], 2);
+    assert_eq!(results["page_size"], 2);
+    assert_eq!(results["total"], 5);
+}
+
+#[tokio::test]
+async fn filter_values_cannot_alter_the_query() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@gmail.com", "confirmed", Utc::now(), &["rust"]).await;
+
+    // Act
+    let response = search(
+        &app,
+        serde_json::json!({
+            "filter": {
+                "tags": { "any": ["rust') OR TRUE; DROP TABLE subscriber_tags; --"] },
+                "domain": "gmail.com'--"
+            }
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let results: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(results["total"], 0);
+    let tags = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriber_tags")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(tags.count, 1);
+}
+
+#[tokio::test]
+async fn invalid_searches_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "filter": { "status": ["deleted"] } }),
+            "an unknown status",
+        ),
+        (
+            serde_json::json!({ "filter": { "engagement_score": { "min": 80, "max": 20 } } }),
+            "an empty score range",
+        ),
+        (
+            serde_json::json!({ "filter": { "domains": ["gmail.com"] } }),
+            "an unknown criterion",
+        ),
+        (serde_json::json!({ "page": 0 }), "page 0"),
+        (serde_json::json!({ "page_size": 1000 }), "pages too large"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = search(&app, body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with a 400 when the search had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn searching_subscribers_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/search", app.address))
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,529 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, get_subscriber_id, spawn_app};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+use zero2prod::get_configuration;
+use zero2prod::signing::UrlSigner;
+use zero2prod::startup::Application;
+
+fn signed_status_url(
+    app: &TestApp,
+    purpose: &str,
+    subscriber_id: Uuid,
+    expires_in: Duration,
+) -> String {
+    let signer = UrlSigner::new(app.configuration.application.hmac_secret.clone());
+    let token = signer.sign(purpose, &subscriber_id.to_string(), Utc::now() + expires_in);
+    format!("{}/subscriptions/status?token={}", app.address, token)
+}
+
+/// A magic link, letting the subscriber manage their subscription.
+fn status_url(app: &TestApp, subscriber_id: Uuid, expires_in: Duration) -> String {
+    signed_status_url(app, "subscriber-session", subscriber_id, expires_in)
+}
+
+/// A link from an email footer, only showing the subscription.
+fn footer_status_url(app: &TestApp, subscriber_id: Uuid) -> String {
+    signed_status_url(app, "subscriber", subscriber_id, Duration::days(365))
+}
+
+#[tokio::test]
+async fn subscription_status_is_returned_as_json_when_asked_for() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    for tag in ["rust", "beta"] {
+        sqlx::query!(
+            "INSERT INTO subscriber_tags (subscriber_id, tag) VALUES ($1, $2)",
+            subscriber_id,
+            tag,
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(status_url(&app, subscriber_id, Duration::hours(1)))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(status["name"], "le guin");
+    assert_eq!(status["status"], "confirmed");
+    assert_eq!(status["tags"], serde_json::json!(["beta", "rust"]));
+    assert!(status["subscribed_at"].is_string());
+    assert!(status["preferences"].get("region").is_some());
+}
+
+#[tokio::test]
+async fn subscription_status_is_shown_as_a_page_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    // Act
+    let response = reqwest::get(status_url(&app, subscriber_id, Duration::hours(1)))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+    let page = response.text().await.unwrap();
+    assert!(page.contains("ursula_le_guin@gmail.com"));
+    assert!(page.contains("confirmed"));
+}
+
+#[tokio::test]
+async fn footer_links_only_show_the_subscription() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let url = footer_status_url(&app, subscriber_id);
+    let client = reqwest::Client::new();
+
+    // Act
+    let page = client.get(&url).send().await.unwrap();
+    let preferences = client
+        .post(&url)
+        .form(&[("do_not_track", "on")])
+        .send()
+        .await
+        .unwrap();
+    let export = client
+        .get(url.replace("/status?", "/export?"))
+        .send()
+        .await
+        .unwrap();
+    let deletion = client
+        .post(url.replace("/status?", "/delete?"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 200);
+    let page = page.text().await.unwrap();
+    assert!(page.contains("ursula_le_guin@gmail.com"));
+    assert!(page.contains(r#"<a href="/subscriptions/login">"#));
+    assert!(!page.contains("/subscriptions/delete"));
+    assert!(!page.contains("/subscriptions/export"));
+    for response in [preferences, export, deletion] {
+        assert_eq!(response.status().as_u16(), 403);
+    }
+    assert_eq!(
+        get_preferences(&app, subscriber_id).await["do_not_track"],
+        false
+    );
+    assert_eq!(get_subscriber_id(&app).await, subscriber_id);
+}
+
+#[tokio::test]
+async fn tampered_status_links_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let url = status_url(&app, subscriber_id, Duration::hours(1));
+
+    // Act
+    let response =
+        reqwest::get(url.replace(&subscriber_id.to_string(), &Uuid::new_v4().to_string()))
+            .await
+            .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_status_links_are_rejected_with_a_410() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    // Act
+    let response = reqwest::get(status_url(&app, subscriber_id, -Duration::minutes(1)))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn status_links_of_deleted_subscribers_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(status_url(&app, Uuid::new_v4(), Duration::hours(1)))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletter_emails_link_to_the_subscription_status_page() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Act
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    let status_links: Vec<_> = linkify::LinkFinder::new()
+        .links(&email.text)
+        .map(|l| l.as_str().to_owned())
+        .filter(|l| l.contains("/subscriptions/status"))
+        .collect();
+
+    // Assert
+    assert_eq!(status_links.len(), 1);
+    assert!(email.html.contains("/subscriptions/status?token="));
+    let mut status_link = reqwest::Url::parse(&status_links[0]).unwrap();
+    status_link.set_port(Some(app.port)).unwrap();
+    let response = reqwest::Client::new()
+        .get(status_link)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["email"], "ursula_le_guin@gmail.com");
+}
+
+async fn set_do_not_track(
+    app: &TestApp,
+    subscriber_id: Uuid,
+    do_not_track: bool,
+) -> reqwest::Response {
+    let form: &[(&str, &str)] = if do_not_track {
+        &[("do_not_track", "on")]
+    } else {
+        &[]
+    };
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(status_url(app, subscriber_id, Duration::hours(1)))
+        .form(form)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_preferences(app: &TestApp, subscriber_id: Uuid) -> serde_json::Value {
+    let status: serde_json::Value = reqwest::Client::new()
+        .get(status_url(app, subscriber_id, Duration::hours(1)))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    status["preferences"].clone()
+}
+
+#[tokio::test]
+async fn subscribers_can_opt_out_of_tracking_from_their_status_page() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    assert_eq!(
+        get_preferences(&app, subscriber_id).await["do_not_track"],
+        false
+    );
+
+    // Act
+    let response = set_do_not_track(&app, subscriber_id, true).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(
+        response.headers()["Location"]
+            .to_str()
+            .unwrap()
+            .starts_with("/subscriptions/status?token=")
+    );
+    assert_eq!(
+        get_preferences(&app, subscriber_id).await["do_not_track"],
+        true
+    );
+    let page = reqwest::get(status_url(&app, subscriber_id, Duration::hours(1)))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains(r#"<input type="checkbox" name="do_not_track" checked>"#));
+
+    // Unticking the box opts back in.
+    set_do_not_track(&app, subscriber_id, false).await;
+    assert_eq!(
+        get_preferences(&app, subscriber_id).await["do_not_track"],
+        false
+    );
+}
+
+#[tokio::test]
+async fn preferences_cannot_be_changed_with_a_tampered_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let url = status_url(&app, subscriber_id, Duration::hours(1));
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(url.replace(&subscriber_id.to_string(), &Uuid::new_v4().to_string()))
+        .form(&[("do_not_track", "on")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        get_preferences(&app, subscriber_id).await["do_not_track"],
+        false
+    );
+}
+
+#[tokio::test]
+async fn subscribers_who_opted_out_of_tracking_receive_untracked_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    set_do_not_track(&app, subscriber_id, true).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Read https://example.com/article",
+            "html": r#"<p>Read <a href="https://example.com/article">this</a></p>"#,
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    for body in [&email.html, &email.text] {
+        assert!(body.contains("https://example.com/article"));
+        assert!(!body.contains("/l/"));
+    }
+    assert!(!email.html.contains("/t/o/"));
+    // The delivery is still counted.
+    let engagement: serde_json::Value = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/subscribers/{}/engagement",
+            app.address, subscriber_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(engagement["deliveries_90d"], 1);
+}
+
+async fn insert_delivery(app: &TestApp, subscriber_id: Uuid, title: &str) -> Uuid {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at
+        )
+        VALUES ($1, $2, 'text', 'html', now())
+        "#,
+        newsletter_issue_id,
+        title,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_id, delivered_at)
+        VALUES ($1, $2, now())
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    newsletter_issue_id
+}
+
+fn export_url(app: &TestApp, subscriber_id: Uuid) -> String {
+    status_url(app, subscriber_id, Duration::hours(1)).replace("/status?", "/export?")
+}
+
+#[tokio::test]
+async fn subscribers_can_export_their_data_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let newsletter_issue_id = insert_delivery(&app, subscriber_id, "An issue").await;
+
+    // Act
+    let response = reqwest::get(export_url(&app, subscriber_id)).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    assert!(
+        response.headers()["Content-Disposition"]
+            .to_str()
+            .unwrap()
+            .contains("subscriber-data.json")
+    );
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(data["name"], "le guin");
+    assert_eq!(data["status"], "confirmed");
+    assert!(data["subscribed_at"].is_string());
+    let deliveries = data["deliveries"].as_array().unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(
+        deliveries[0]["newsletter_issue_id"],
+        newsletter_issue_id.to_string()
+    );
+    assert_eq!(deliveries[0]["title"], "An issue");
+}
+
+#[tokio::test]
+async fn subscribers_can_export_their_data_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    insert_delivery(&app, subscriber_id, "Issue #1, \"the first\"").await;
+    insert_delivery(&app, subscriber_id, "=HYPERLINK(\"x\")").await;
+
+    // Act
+    let response = reqwest::get(format!("{}&format=csv", export_url(&app, subscriber_id)))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/csv")
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("email,name,status,subscribed_at,"));
+    assert!(lines[1].starts_with("ursula_le_guin@gmail.com,le guin,confirmed,"));
+    assert!(lines[1].contains(r#","Issue #1, ""the first""","#));
+    assert!(lines[2].contains(r#","'=HYPERLINK(""x"")","#));
+}
+
+#[tokio::test]
+async fn data_cannot_be_exported_with_a_tampered_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    let url = export_url(&app, subscriber_id);
+
+    // Act
+    let response =
+        reqwest::get(url.replace(&subscriber_id.to_string(), &Uuid::new_v4().to_string()))
+            .await
+            .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_without_an_hmac_secret() {
+    // Arrange
+    let mut configuration = get_configuration().expect("Failed to read configuration.");
+    configuration.application.port = 0;
+    configuration.application.hmac_secret = "".into();
+
+    // Act
+    let result = Application::build(configuration).await;
+
+    // Assert
+    let error = result
+        .err()
+        .expect("The application started without an hmac secret");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,277 @@
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, create_unconfirmed_subscriber, get_subscriber_id,
+    spawn_app,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+use zero2prod::signing::UrlSigner;
+
+async fn get_subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+fn unsubscribe_url(app: &TestApp, purpose: &str, subscriber_id: Uuid) -> String {
+    let signer = UrlSigner::new(app.configuration.application.hmac_secret.clone());
+    let token = signer.sign(
+        purpose,
+        &subscriber_id.to_string(),
+        Utc::now() + Duration::hours(1),
+    );
+    format!("{}/subscriptions/unsubscribe?token={}", app.address, token)
+}
+
+/// Publish an issue to the confirmed subscriber and return the unsubscribe
+/// link of the email they received, pointing at the test application.
+async fn publish_issue_and_get_unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.wait_for_deliveries().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(email.html.contains("/subscriptions/unsubscribe?token="));
+    let unsubscribe_links: Vec<_> = linkify::LinkFinder::new()
+        .links(&email.text)
+        .map(|l| l.as_str().to_owned())
+        .filter(|l| l.contains("/subscriptions/unsubscribe"))
+        .collect();
+    assert_eq!(unsubscribe_links.len(), 1);
+    // Mailbox providers can unsubscribe the recipient without opening the
+    // email (RFC 8058).
+    let header = |name: &str| {
+        email
+            .headers
+            .iter()
+            .find(|header| header.name == name)
+            .map(|header| header.value.clone())
+    };
+    assert_eq!(
+        header("List-Unsubscribe"),
+        Some(format!("<{}>", unsubscribe_links[0]))
+    );
+    assert_eq!(
+        header("List-Unsubscribe-Post").as_deref(),
+        Some("List-Unsubscribe=One-Click")
+    );
+    let mut unsubscribe_link = reqwest::Url::parse(&unsubscribe_links[0]).unwrap();
+    unsubscribe_link.set_port(Some(app.port)).unwrap();
+    unsubscribe_link
+}
+
+async fn set_deliveries_paused(app: &TestApp, paused: bool) {
+    reqwest::Client::new()
+        .post(format!("{}/admin/delivery", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({ "paused": paused }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_of_a_newsletter_unsubscribes_its_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let unsubscribe_link = publish_issue_and_get_unsubscribe_link(&app).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_later_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_url(&app, "unsubscribe", subscriber_id))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    app.wait_for_deliveries().await;
+}
+
+#[tokio::test]
+async fn deliveries_queued_before_unsubscribing_are_dropped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    set_deliveries_paused(&app, true).await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    reqwest::Client::new()
+        .post(unsubscribe_url(&app, "unsubscribe", subscriber_id))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM delivery_tasks"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+    set_deliveries_paused(&app, false).await;
+    app.wait_for_deliveries().await;
+}
+
+#[tokio::test]
+async fn the_old_confirmation_link_does_not_subscribe_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = get_subscriber_id(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_url(&app, "unsubscribe", subscriber_id))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn following_the_unsubscribe_link_asks_for_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    // Act
+    let response = reqwest::get(unsubscribe_url(&app, "unsubscribe", subscriber_id))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"<form action="/subscriptions/unsubscribe?token="#));
+    assert_eq!(get_subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn status_links_cannot_be_used_to_unsubscribe() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = get_subscriber_id(&app).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(unsubscribe_url(&app, "subscriber", subscriber_id))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribe_links_of_deleted_subscribers_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(unsubscribe_url(&app, "unsubscribe", Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,208 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+async fn set_fragment(app: &TestApp, name: &str, value: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .put(format!(
+            "{}/admin/template_fragments/{}",
+            app.admin_address, name
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&value)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn post_issue(app: &TestApp) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn fragments_are_added_to_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    set_fragment(
+        &app,
+        "banner",
+        serde_json::json!({
+            "position": "header",
+            "html": "<p>Read it online</p>",
+            "text": "Read it online",
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    set_fragment(
+        &app,
+        "legal",
+        serde_json::json!({
+            "position": "footer",
+            "html": r#"<p>Sent by {postal_address}. <a href="{unsubscribe_link}">Opt out</a></p>"#,
+            "text": "Sent by {postal_address}. Opt out: {unsubscribe_link}",
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    post_issue(&app).await.error_for_status().unwrap();
+    app.wait_for_deliveries().await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&request.body).unwrap();
+    let postal_address = &app.configuration.email_templates.footer.postal_address;
+    assert!(
+        email
+            .text
+            .starts_with("Read it online\n\nNewsletter body as plain text")
+    );
+    assert!(email.text.contains(&format!(
+        "Sent by {}. Opt out: {}/subscriptions/unsubscribe?token=",
+        postal_address, app.configuration.application.base_url
+    )));
+    assert!(!email.text.contains("{unsubscribe_link}"));
+    assert!(
+        email
+            .html
+            .contains("<p>Read it online</p><p>Newsletter body as HTML</p>")
+    );
+    assert!(email.html.contains(r#"Opt out</a></p>"#));
+}
+
+#[tokio::test]
+async fn issues_are_not_published_while_a_footer_fragment_is_not_compliant() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    set_fragment(
+        &app,
+        "social",
+        serde_json::json!({
+            "position": "footer",
+            "html": "<p>Follow us</p>",
+            "text": "Follow us",
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let response = post_issue(&app).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("{unsubscribe_link} in the HTML of `social`"));
+    assert!(body.contains("{postal_address} in the text of `social`"));
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(issues, 0);
+}
+
+#[tokio::test]
+async fn fragments_can_be_listed_and_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    set_fragment(
+        &app,
+        "banner",
+        serde_json::json!({
+            "position": "header",
+            "html": "<p>Read it online</p>",
+            "text": "Read it online",
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let client = reqwest::Client::new();
+    let url = format!("{}/admin/template_fragments", app.admin_address);
+
+    // Act
+    let fragments: serde_json::Value = client
+        .get(&url)
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let deleted = client
+        .delete(format!("{}/banner", url))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+    let deleted_again = client
+        .delete(format!("{}/banner", url))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(fragments["fragments"][0]["name"], "banner");
+    assert_eq!(fragments["fragments"][0]["position"], "header");
+    assert_eq!(deleted.status().as_u16(), 200);
+    assert_eq!(deleted_again.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn fragments_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .put(format!(
+            "{}/admin/template_fragments/banner",
+            app.admin_address
+        ))
+        .json(&serde_json::json!({
+            "position": "header",
+            "html": "<p>Read it online</p>",
+            "text": "Read it online",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
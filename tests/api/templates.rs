@@ -0,0 +1,150 @@
+use crate::helpers::{TestApp, sent_emails, spawn_app, spawn_app_with_configuration};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::rendering::render_email_fixtures;
+
+async fn spawn_app_with_template_directory(directory: &Path) -> TestApp {
+    let directory = directory.to_owned();
+    let app = spawn_app_with_configuration(move |c| {
+        c.email_templates.directory = Some(directory);
+    })
+    .await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+fn template_directory() -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_rendered_from_the_template_directory() {
+    // Arrange
+    let directory = template_directory();
+    std::fs::write(
+        directory.join("confirmation.subject.txt"),
+        "Welcome, {name}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        directory.join("confirmation.html"),
+        r#"<p>Hi {name}, <a href="{confirmation_link}">confirm</a></p>"#,
+    )
+    .unwrap();
+    let app = spawn_app_with_template_directory(&directory).await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email = sent_emails(&app).await.pop().unwrap();
+    assert_eq!(email["subject"], "Welcome, le guin");
+    let html = email["html"].as_str().unwrap();
+    assert!(html.contains("<p>Hi le guin, <a href=\""));
+    assert!(html.contains("/subscriptions/confirm?subscription_token="));
+    // Missing files keep the configured copy.
+    let text = email["text"].as_str().unwrap();
+    assert!(text.contains("Welcome to our newsletter!"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn edited_templates_are_picked_up_without_a_restart() {
+    // Arrange
+    let directory = template_directory();
+    std::fs::write(directory.join("confirmation.txt"), "First copy").unwrap();
+    let app = spawn_app_with_template_directory(&directory).await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    std::fs::write(directory.join("confirmation.txt"), "Edited copy").unwrap();
+    app.post_subscriptions("name=tolkien&email=tolkien%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let emails = sent_emails(&app).await;
+    assert!(
+        emails[0]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("First copy\n")
+    );
+    assert!(
+        emails[1]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("Edited copy\n")
+    );
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn the_confirmation_fixture_matches_the_email_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let fixture = render_email_fixtures(&app.configuration)
+        .into_iter()
+        .find(|e| e.template == "confirmation")
+        .unwrap();
+
+    // Act
+    app.post_subscriptions("name=Ursula&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = email_request.body_json().unwrap();
+    let token = app
+        .get_confirmation_links(&email_request)
+        .html
+        .query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    // Footer links are signed per subscriber, the fixture uses placeholders.
+    let status_token = Regex::new(r#"status\?token=[^\s"<&]+"#).unwrap();
+    let unsubscribe_token = Regex::new(r#"unsubscribe\?token=[^\s"<&]+"#).unwrap();
+    let as_fixture = |field: &str| {
+        let rendered = email[field]
+            .as_str()
+            .unwrap()
+            .replace(&token, "fixtureSubscriptionToken0");
+        let rendered = status_token.replace_all(&rendered, "status?token=fixtureStatusToken0");
+        unsubscribe_token
+            .replace_all(&rendered, "unsubscribe?token=fixtureUnsubscribeToken0")
+            .into_owned()
+    };
+    assert_eq!(as_fixture("subject"), fixture.subject);
+    assert_eq!(as_fixture("html"), fixture.html);
+    assert_eq!(as_fixture("text"), fixture.text);
+}
@@ -0,0 +1,95 @@
+use crate::helpers::{TestApp, spawn_app_with_configuration};
+use zero2prod::configuration::{ListenerSettings, TlsSettings};
+
+/// Spawn the application serving HTTPS with a self-signed certificate for
+/// `127.0.0.1`, returned as PEM.
+async fn spawn_app_with_tls() -> (TestApp, String) {
+    let certified_key = rcgen::generate_simple_self_signed(vec!["127.0.0.1".into()]).unwrap();
+    let directory = tempfile::tempdir().unwrap();
+    let cert_path = directory.path().join("cert.pem");
+    let key_path = directory.path().join("key.pem");
+    let certificate = certified_key.cert.pem();
+    std::fs::write(&cert_path, &certificate).unwrap();
+    std::fs::write(&key_path, certified_key.key_pair.serialize_pem()).unwrap();
+
+    let app = spawn_app_with_configuration(|c| {
+        c.application.base_url = "https://127.0.0.1".into();
+        c.application.tls = Some(TlsSettings {
+            cert_path,
+            key_path,
+            redirect_listener: Some(ListenerSettings {
+                host: "127.0.0.1".into(),
+                port: 0,
+            }),
+        });
+    })
+    .await;
+    (app, certificate)
+}
+
+#[tokio::test]
+async fn https_is_served_with_the_configured_certificate() {
+    // Arrange
+    let (app, certificate) = spawn_app_with_tls().await;
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(certificate.as_bytes()).unwrap())
+        .build()
+        .unwrap();
+
+    // Act
+    let response = client
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(app.address.starts_with("https://"));
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn plain_http_requests_are_redirected_to_https() {
+    // Arrange
+    let (app, _) = spawn_app_with_tls().await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // Act
+    let response = client
+        .post(format!(
+            "http://127.0.0.1:{}/subscriptions?source=footer",
+            app.redirect_port.unwrap()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 308);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://127.0.0.1/subscriptions?source=footer"
+    );
+}
+
+#[tokio::test]
+async fn a_missing_certificate_fails_the_build() {
+    // Arrange
+    let mut configuration = zero2prod::get_configuration().unwrap();
+    configuration.application.port = 0;
+    configuration.application.tls = Some(TlsSettings {
+        cert_path: "/nonexistent/cert.pem".into(),
+        key_path: "/nonexistent/key.pem".into(),
+        redirect_listener: None,
+    });
+
+    // Act
+    let outcome = zero2prod::startup::Application::build(configuration).await;
+
+    // Assert
+    let error = outcome.err().expect("The application was built");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,160 @@
+use crate::helpers::{TestApp, create_unconfirmed_subscriber, spawn_app_with_configuration};
+use std::time::Duration;
+
+async fn spawn_app_banning_after(max_failures: u32) -> TestApp {
+    spawn_app_with_configuration(|c| {
+        c.token_guard.max_failures = max_failures;
+        c.token_guard.ban_duration = Duration::from_secs(60);
+    })
+    .await
+}
+
+async fn confirm_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        app.address, token
+    ))
+    .await
+    .unwrap()
+}
+
+async fn get_metrics(app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/admin/token_guard", app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn addresses_guessing_tokens_are_banned() {
+    // Arrange
+    let app = spawn_app_banning_after(3).await;
+    for _ in 0..3 {
+        let response = confirm_with_token(&app, "guessedToken").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Act
+    let response = confirm_with_token(&app, "guessedToken").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+}
+
+#[tokio::test]
+async fn bans_apply_to_every_token_endpoint_and_valid_tokens() {
+    // Arrange
+    let app = spawn_app_banning_after(2).await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    for _ in 0..2 {
+        confirm_with_token(&app, "guessedToken").await;
+    }
+
+    // Act
+    let confirm = reqwest::get(confirmation_links.html).await.unwrap();
+    let status = reqwest::get(format!("{}/subscriptions/status?token=a.b.c", app.address))
+        .await
+        .unwrap();
+    let reengage = reqwest::get(format!(
+        "{}/subscriptions/reengage?reengagement_token=guessedToken",
+        app.address
+    ))
+    .await
+    .unwrap();
+    let delete = reqwest::Client::new()
+        .post(format!("{}/subscriptions/delete?token=a.b.c", app.address))
+        .send()
+        .await
+        .unwrap();
+    let export = reqwest::get(format!("{}/subscriptions/export?token=a.b.c", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(confirm.status().as_u16(), 429);
+    assert_eq!(status.status().as_u16(), 429);
+    assert_eq!(reengage.status().as_u16(), 429);
+    assert_eq!(delete.status().as_u16(), 429);
+    assert_eq!(export.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn bans_do_not_apply_to_other_endpoints() {
+    // Arrange
+    let app = spawn_app_banning_after(1).await;
+    confirm_with_token(&app, "guessedToken").await;
+
+    // Act
+    let response = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn successful_lookups_are_not_counted_as_failures() {
+    // Arrange
+    let app = spawn_app_banning_after(1).await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    for _ in 0..3 {
+        let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert
+    assert_eq!(get_metrics(&app).await["failed_lookups"], 0);
+}
+
+#[tokio::test]
+async fn token_guard_metrics_are_reported() {
+    // Arrange
+    let app = spawn_app_banning_after(2).await;
+    for _ in 0..3 {
+        confirm_with_token(&app, "guessedToken").await;
+    }
+
+    // Act
+    let metrics = get_metrics(&app).await;
+
+    // Assert
+    assert_eq!(
+        metrics,
+        serde_json::json!({
+            "failed_lookups": 2,
+            "bans": 1,
+            "rejected_requests": 1,
+            "banned_addresses": 1,
+        })
+    );
+}
+
+#[tokio::test]
+async fn token_guard_metrics_require_authentication() {
+    // Arrange
+    let app = spawn_app_banning_after(2).await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/token_guard", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
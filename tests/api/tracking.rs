@@ -0,0 +1,169 @@
+use crate::helpers::{TestApp, create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_client::SendEmailRequest;
+
+const BROWSER_USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64; rv:126.0) Gecko/20100101 Firefox/126.0";
+
+/// Publish an issue to a single confirmed subscriber and return the issue id
+/// and the open-tracking pixel URL found in the delivered HTML body.
+async fn publish_tracked_issue(app: &TestApp) -> (String, reqwest::Url) {
+    publish_issue_with_tracking_mode(app, serde_json::Value::Null).await
+}
+
+async fn publish_issue_with_tracking_mode(
+    app: &TestApp,
+    tracking_mode: serde_json::Value,
+) -> (String, reqwest::Url) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/api/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "tracking_mode": tracking_mode,
+        }))
+        .await;
+    app.wait_for_deliveries().await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let newsletter_issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: SendEmailRequest = serde_json::from_slice(&email_request.body).unwrap();
+    let pixel = linkify::LinkFinder::new()
+        .links(&email.html)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains("/t/o/"))
+        .expect("No tracking pixel in the newsletter body");
+    let mut pixel = reqwest::Url::parse(&pixel).unwrap();
+    pixel.set_port(Some(app.port)).unwrap();
+    (newsletter_issue_id, pixel)
+}
+
+async fn get_engagement(app: &TestApp, newsletter_issue_id: &str) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/engagement",
+            app.address, newsletter_issue_id
+        ))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_tracking_pixel_is_a_gif_and_counts_opens() {
+    // Arrange
+    let app = spawn_app().await;
+    let (newsletter_issue_id, pixel) = publish_tracked_issue(&app).await;
+    sqlx::query!("UPDATE newsletter_issues SET published_at = now() - interval '1 hour'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(pixel)
+        .header("User-Agent", BROWSER_USER_AGENT)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    let stats = get_engagement(&app, &newsletter_issue_id).await;
+    assert_eq!(stats["opens"], 1);
+    assert_eq!(stats["unique_opens"], 1);
+    assert_eq!(stats["flagged_opens"], 0);
+}
+
+#[tokio::test]
+async fn opens_without_a_user_agent_are_flagged() {
+    // Arrange
+    let app = spawn_app().await;
+    let (newsletter_issue_id, pixel) = publish_tracked_issue(&app).await;
+
+    // Act
+    reqwest::get(pixel).await.unwrap();
+
+    // Assert
+    let stats = get_engagement(&app, &newsletter_issue_id).await;
+    assert_eq!(stats["opens"], 0);
+    assert_eq!(stats["flagged_opens"], 1);
+}
+
+#[tokio::test]
+async fn opens_for_unknown_issues_are_rejected_with_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/t/o/{}/{}",
+        app.address,
+        uuid::Uuid::new_v4(),
+        uuid::Uuid::new_v4()
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn aggregate_tracking_only_keeps_per_issue_counters() {
+    // Arrange
+    let app = spawn_app().await;
+    let (newsletter_issue_id, pixel) =
+        publish_issue_with_tracking_mode(&app, "aggregate".into()).await;
+    sqlx::query!("UPDATE newsletter_issues SET published_at = now() - interval '1 hour'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(pixel.clone())
+        .header("User-Agent", BROWSER_USER_AGENT)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        pixel.path(),
+        format!("/t/o/{}", newsletter_issue_id),
+        "The pixel must not identify the recipient"
+    );
+    let stored_events = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM email_opens"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored_events, 0);
+    let stats = get_engagement(&app, &newsletter_issue_id).await;
+    assert_eq!(stats["tracking_mode"], "aggregate");
+    assert_eq!(stats["opens"], 1);
+    assert_eq!(stats["unique_opens"], serde_json::Value::Null);
+}